@@ -0,0 +1,91 @@
+//! A kelvin backend storing nodes in a RocksDB column family
+#![warn(missing_docs)]
+
+use std::io::{self, Cursor, Read};
+use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::Arc;
+
+use kelvin::{Backend, ByteHash, PutResult, Store};
+use rocksdb::{Options, DB};
+
+fn to_io(e: rocksdb::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, e)
+}
+
+/// A backend storing nodes in a RocksDB column family, keyed by digest
+///
+/// The database can be shared with other data, the nodes are kept apart
+/// from it in their own column family.
+pub struct RocksBackend<H> {
+    db: Arc<DB>,
+    cf: String,
+    _marker: PhantomData<H>,
+}
+
+impl<H: ByteHash> RocksBackend<H> {
+    /// Opens the database at `path`, storing nodes in the column family
+    /// `cf`, created if necessary
+    pub fn open<P: AsRef<Path>>(path: P, cf: &str) -> io::Result<Self> {
+        let mut opts = Options::default();
+        opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+        let db = DB::open_cf(&opts, path, &[cf]).map_err(to_io)?;
+        Self::new(Arc::new(db), cf)
+    }
+
+    /// Creates a backend storing nodes in the column family `cf` of `db`
+    ///
+    /// Fails with `NotFound` if the column family does not exist.
+    pub fn new(db: Arc<DB>, cf: &str) -> io::Result<Self> {
+        if db.cf_handle(cf).is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "Column family not found",
+            ));
+        }
+        Ok(RocksBackend {
+            db,
+            cf: cf.into(),
+            _marker: PhantomData,
+        })
+    }
+
+    /// Creates a store on top of the column family
+    pub fn into_store(self) -> Store<H> {
+        Store::from_backend(self)
+    }
+
+    fn get_bytes(&self, hash: &H::Digest) -> io::Result<Option<Vec<u8>>> {
+        let cf = self.db.cf_handle(&self.cf).expect("checked on creation");
+        self.db.get_cf(cf, hash.as_ref()).map_err(to_io)
+    }
+}
+
+impl<H: ByteHash> Backend<H> for RocksBackend<H> {
+    fn get<'a>(&'a self, hash: &H::Digest) -> io::Result<Box<dyn Read + 'a>> {
+        match self.get_bytes(hash)? {
+            Some(bytes) => Ok(Box::new(Cursor::new(bytes))),
+            None => {
+                Err(io::Error::new(io::ErrorKind::NotFound, "Data not found"))
+            }
+        }
+    }
+
+    fn put(
+        &mut self,
+        hash: H::Digest,
+        bytes: Vec<u8>,
+    ) -> io::Result<PutResult> {
+        if self.get_bytes(&hash)?.is_some() {
+            return Ok(PutResult::AlreadyThere);
+        }
+        let cf = self.db.cf_handle(&self.cf).expect("checked on creation");
+        self.db.put_cf(cf, hash.as_ref(), bytes).map_err(to_io)?;
+        Ok(PutResult::Ok)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.db.flush().map_err(to_io)
+    }
+}
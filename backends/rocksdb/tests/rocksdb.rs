@@ -0,0 +1,38 @@
+use std::sync::Arc;
+
+use kelvin::tests::tempfile::tempdir;
+use kelvin::Blake2b;
+use kelvin_hamt::DefaultHAMTMap;
+use kelvin_rocksdb::RocksBackend;
+use rocksdb::{Options, DB};
+
+type Map = DefaultHAMTMap<u64, u64, Blake2b>;
+
+#[test]
+fn alongside_other_data() {
+    let dir = tempdir().unwrap();
+    let mut opts = Options::default();
+    opts.create_if_missing(true);
+    opts.create_missing_column_families(true);
+    let db = Arc::new(DB::open_cf(&opts, dir.path(), &["kelvin"]).unwrap());
+    db.put(b"height", b"42").unwrap();
+
+    let store = RocksBackend::<Blake2b>::new(db.clone(), "kelvin")
+        .unwrap()
+        .into_store();
+
+    let mut map = Map::new();
+    for i in 0..1000 {
+        map.insert(i, i).unwrap();
+    }
+    let snapshot = store.persist(&mut map).unwrap();
+    store.flush().unwrap();
+
+    store.verify(&snapshot).unwrap();
+    let restored = snapshot.restore().unwrap();
+    assert_eq!(*restored.get(&999).unwrap().unwrap(), 999);
+
+    // the other data is left as is
+    assert_eq!(db.get(b"height").unwrap().unwrap(), b"42");
+    assert!(RocksBackend::<Blake2b>::new(db, "missing").is_err());
+}
@@ -0,0 +1,66 @@
+//! A kelvin backend storing nodes in a sled tree
+#![warn(missing_docs)]
+
+use std::io::{self, Cursor, Read};
+use std::marker::PhantomData;
+use std::path::Path;
+
+use kelvin::{Backend, ByteHash, PutResult, Store};
+
+/// A backend storing nodes in a sled tree, keyed by digest
+///
+/// The tree can be one of many in a database also holding other data, the
+/// nodes are kept apart from it.
+pub struct SledBackend<H> {
+    tree: sled::Tree,
+    _marker: PhantomData<H>,
+}
+
+impl<H: ByteHash> SledBackend<H> {
+    /// Opens the sled database at `path`, storing nodes in its default tree
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let db = sled::open(path)?;
+        Ok(Self::new((*db).clone()))
+    }
+
+    /// Creates a backend storing nodes in `tree`
+    pub fn new(tree: sled::Tree) -> Self {
+        SledBackend {
+            tree,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Creates a store on top of the tree
+    pub fn into_store(self) -> Store<H> {
+        Store::from_backend(self)
+    }
+}
+
+impl<H: ByteHash> Backend<H> for SledBackend<H> {
+    fn get<'a>(&'a self, hash: &H::Digest) -> io::Result<Box<dyn Read + 'a>> {
+        match self.tree.get(hash.as_ref())? {
+            Some(bytes) => Ok(Box::new(Cursor::new(bytes))),
+            None => {
+                Err(io::Error::new(io::ErrorKind::NotFound, "Data not found"))
+            }
+        }
+    }
+
+    fn put(
+        &mut self,
+        hash: H::Digest,
+        bytes: Vec<u8>,
+    ) -> io::Result<PutResult> {
+        if self.tree.contains_key(hash.as_ref())? {
+            return Ok(PutResult::AlreadyThere);
+        }
+        self.tree.insert(hash.as_ref(), bytes)?;
+        Ok(PutResult::Ok)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.tree.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,31 @@
+use kelvin::tests::tempfile::tempdir;
+use kelvin::Blake2b;
+use kelvin_hamt::DefaultHAMTMap;
+use kelvin_sled::SledBackend;
+
+type Map = DefaultHAMTMap<u64, u64, Blake2b>;
+
+#[test]
+fn alongside_other_data() {
+    let dir = tempdir().unwrap();
+    let db = sled::open(dir.path()).unwrap();
+    db.insert(b"height", b"42").unwrap();
+
+    let tree = db.open_tree("kelvin").unwrap();
+    let store = SledBackend::<Blake2b>::new(tree).into_store();
+
+    let mut map = Map::new();
+    for i in 0..1000 {
+        map.insert(i, i).unwrap();
+    }
+    let snapshot = store.persist(&mut map).unwrap();
+    store.flush().unwrap();
+
+    store.verify(&snapshot).unwrap();
+    let restored = snapshot.restore().unwrap();
+    assert_eq!(*restored.get(&999).unwrap().unwrap(), 999);
+
+    // the other data is left as is
+    assert_eq!(&*db.get(b"height").unwrap().unwrap(), b"42");
+    assert_eq!(db.len(), 1);
+}
@@ -0,0 +1,330 @@
+//! Derive macros for kelvin `Content` and annotations
+#![warn(missing_docs)]
+
+extern crate proc_macro;
+
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::{
+    parse_macro_input, parse_quote, Data, DeriveInput, Field, Fields,
+    GenericParam, Ident, Index, Member,
+};
+
+/// Derives `Content<H>` for structs and enums whose fields are all
+/// `Content<H>`
+///
+/// Fields are encoded in declaration order. Enums are encoded as a one byte
+/// tag, the index of the variant, followed by its fields, so at most 256
+/// variants are supported. Type parameters are required to be `Content<H>`
+/// as well.
+#[proc_macro_derive(Content)]
+pub fn derive_content(
+    input: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match content(input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+/// Derives an annotation for structs whose fields are all annotations
+///
+/// Generates `From<&L>` for every leaf `L` all the fields can be made from,
+/// and `Associative`, combining field by field, which in turn provides
+/// `Combine`. Together with `Clone` and `Content` this makes the struct an
+/// `Annotation`. Fields marked `#[annotation(borrow)]` can also be borrowed
+/// from the struct, for methods such as `Count` that look for a specific
+/// annotation.
+#[proc_macro_derive(Annotation, attributes(annotation))]
+pub fn derive_annotation(
+    input: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match annotation(input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn content(input: DeriveInput) -> syn::Result<TokenStream> {
+    let name = &input.ident;
+    let hash = Ident::new("__H", Span::call_site());
+
+    let mut generics = input.generics.clone();
+    let params: Vec<_> = input.generics.type_params().cloned().collect();
+    {
+        let where_clause = generics.make_where_clause();
+        for param in params {
+            let ident = param.ident;
+            where_clause
+                .predicates
+                .push(parse_quote!(#ident: kelvin::Content<#hash>));
+        }
+    }
+    generics
+        .params
+        .push(GenericParam::Type(parse_quote!(#hash: kelvin::ByteHash)));
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
+    let (_, ty_generics, _) = input.generics.split_for_impl();
+
+    let (persist, restore, reach) = match &input.data {
+        Data::Struct(data) => {
+            let (pattern, persist, reach) = persist_fields(&data.fields);
+            let restore = restore_fields(quote!(#name), &data.fields);
+            (
+                quote! {
+                    let #name #pattern = self;
+                    #persist
+                },
+                quote!(Ok(#restore)),
+                quote! {
+                    let #name #pattern = self;
+                    #reach
+                },
+            )
+        }
+        Data::Enum(data) => {
+            if data.variants.len() > 256 {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "Content can not be derived for more than 256 variants",
+                ));
+            }
+            let mut persist_arms = vec![];
+            let mut restore_arms = vec![];
+            let mut reach_arms = vec![];
+            for (i, variant) in data.variants.iter().enumerate() {
+                let tag = i as u8;
+                let ident = &variant.ident;
+                let (pattern, persist, reach) = persist_fields(&variant.fields);
+                let restore =
+                    restore_fields(quote!(#name::#ident), &variant.fields);
+                persist_arms.push(quote! {
+                    #name::#ident #pattern => {
+                        <u8 as kelvin::Content<#hash>>::persist(
+                            &mut #tag,
+                            sink,
+                        )?;
+                        #persist
+                    }
+                });
+                restore_arms.push(quote!(#tag => Ok(#restore),));
+                reach_arms.push(quote! {
+                    #name::#ident #pattern => {
+                        #reach
+                    }
+                });
+            }
+            (
+                quote! {
+                    match self {
+                        #(#persist_arms)*
+                    }
+                },
+                quote! {
+                    let tag =
+                        <u8 as kelvin::Content<#hash>>::restore(source)?;
+                    match tag {
+                        #(#restore_arms)*
+                        _ => Err(std::io::Error::new(
+                            std::io::ErrorKind::InvalidData,
+                            "Invalid enum tag",
+                        )),
+                    }
+                },
+                quote! {
+                    match self {
+                        #(#reach_arms)*
+                    }
+                },
+            )
+        }
+        Data::Union(_) => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "Content can not be derived for unions",
+            ))
+        }
+    };
+
+    Ok(quote! {
+        impl #impl_generics kelvin::Content<#hash> for #name #ty_generics
+            #where_clause
+        {
+            fn persist(
+                &mut self,
+                sink: &mut kelvin::Sink<#hash>,
+            ) -> std::io::Result<()> {
+                #persist
+                Ok(())
+            }
+
+            fn restore(
+                source: &mut kelvin::Source<#hash>,
+            ) -> std::io::Result<Self> {
+                #restore
+            }
+
+            fn reach<__R: kelvin::Reach<#hash>>(
+                &self,
+                reach: &mut __R,
+            ) -> std::io::Result<()> {
+                #reach
+                Ok(())
+            }
+        }
+    })
+}
+
+fn annotation(input: DeriveInput) -> syn::Result<TokenStream> {
+    let name = &input.ident;
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "Annotation can only be derived for structs",
+            ))
+        }
+    };
+
+    let members: Vec<Member> = fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| match &field.ident {
+            Some(ident) => Member::Named(ident.clone()),
+            None => Member::Unnamed(Index::from(i)),
+        })
+        .collect();
+    let types: Vec<_> = fields.iter().map(|field| &field.ty).collect();
+    let (impl_generics, ty_generics, where_clause) =
+        input.generics.split_for_impl();
+
+    // From<&L>, for leaves every field can be made from
+    let leaf = Ident::new("__L", Span::call_site());
+    let mut from_generics = input.generics.clone();
+    from_generics.params.insert(0, parse_quote!('__l));
+    from_generics
+        .params
+        .push(GenericParam::Type(parse_quote!(#leaf)));
+    {
+        let where_clause = from_generics.make_where_clause();
+        for ty in &types {
+            where_clause
+                .predicates
+                .push(parse_quote!(#ty: From<&'__l #leaf>));
+        }
+    }
+    let (from_impl_generics, _, from_where_clause) =
+        from_generics.split_for_impl();
+
+    // Associative, combining field by field
+    let mut op_generics = input.generics.clone();
+    {
+        let where_clause = op_generics.make_where_clause();
+        for ty in &types {
+            where_clause
+                .predicates
+                .push(parse_quote!(#ty: kelvin::Associative));
+        }
+    }
+    let (_, _, op_where_clause) = op_generics.split_for_impl();
+
+    let mut borrows = vec![];
+    for (field, member) in fields.iter().zip(&members) {
+        if borrowed(field)? {
+            let ty = &field.ty;
+            borrows.push(quote! {
+                impl #impl_generics std::borrow::Borrow<#ty>
+                    for #name #ty_generics #where_clause
+                {
+                    fn borrow(&self) -> &#ty {
+                        &self.#member
+                    }
+                }
+            });
+        }
+    }
+
+    Ok(quote! {
+        impl #from_impl_generics From<&'__l #leaf> for #name #ty_generics
+            #from_where_clause
+        {
+            fn from(leaf: &'__l #leaf) -> Self {
+                #name { #(#members: From::from(leaf)),* }
+            }
+        }
+
+        impl #impl_generics kelvin::Associative for #name #ty_generics
+            #op_where_clause
+        {
+            fn op(&mut self, b: &Self) {
+                #(kelvin::Associative::op(&mut self.#members, &b.#members);)*
+            }
+        }
+
+        #(#borrows)*
+    })
+}
+
+// Returns true if the field is marked `#[annotation(borrow)]`
+fn borrowed(field: &Field) -> syn::Result<bool> {
+    let mut borrow = false;
+    for attr in &field.attrs {
+        if attr.path.is_ident("annotation") {
+            let arg: Ident = attr.parse_args()?;
+            if arg != "borrow" {
+                return Err(syn::Error::new_spanned(
+                    arg,
+                    "expected `#[annotation(borrow)]`",
+                ));
+            }
+            borrow = true;
+        }
+    }
+    Ok(borrow)
+}
+
+// Returns a pattern binding all fields, and the statements persisting and
+// reaching into them
+fn persist_fields(fields: &Fields) -> (TokenStream, TokenStream, TokenStream) {
+    let bindings: Vec<_> = (0..fields.len())
+        .map(|i| Ident::new(&format!("__field{}", i), Span::call_site()))
+        .collect();
+    let pattern = match fields {
+        Fields::Named(named) => {
+            let names = named.named.iter().map(|f| &f.ident);
+            quote!({ #(#names: #bindings),* })
+        }
+        Fields::Unnamed(_) => quote!(( #(#bindings),* )),
+        Fields::Unit => quote!(),
+    };
+    let persist = quote! {
+        #(kelvin::Content::persist(#bindings, sink)?;)*
+    };
+    let reach = quote! {
+        #(kelvin::Content::reach(#bindings, reach)?;)*
+    };
+    (pattern, persist, reach)
+}
+
+// Returns an expression restoring all fields into `path`
+fn restore_fields(path: TokenStream, fields: &Fields) -> TokenStream {
+    match fields {
+        Fields::Named(named) => {
+            let names = named.named.iter().map(|f| &f.ident);
+            quote! {
+                #path { #(#names: kelvin::Content::restore(source)?),* }
+            }
+        }
+        Fields::Unnamed(unnamed) => {
+            let restores = unnamed
+                .unnamed
+                .iter()
+                .map(|_| quote!(kelvin::Content::restore(source)?));
+            quote!(#path( #(#restores),* ))
+        }
+        Fields::Unit => quote!(#path),
+    }
+}
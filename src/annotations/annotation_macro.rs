@@ -1,7 +1,8 @@
 /// Conveniance macro for creating annotation types combining several annotations
 #[macro_export]
 macro_rules! annotation {
-    {  $pub:vis struct $struct_name:ident $( < $( $param:ident ),* > )*
+    {  $( #[$attr:meta] )*
+       $pub:vis struct $struct_name:ident $( < $( $param:ident ),* > )*
        {
            $( $ann_key:ident : $ann_type:ty ),* $( , )?
 
@@ -14,6 +15,7 @@ macro_rules! annotation {
         use $crate::annotations::ErasedAnnotation as __ErasedAnnotation;
         use $crate::annotations::Combine as __Combine;
 
+        $( #[$attr] )*
         $pub struct $struct_name $( < $( $param ),* > )* {
             $ ( $ann_key : $ann_type ),*
         }
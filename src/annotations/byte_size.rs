@@ -0,0 +1,61 @@
+use std::io;
+use std::marker::PhantomData;
+use std::ops::Deref;
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::{Associative, ByteHash, Content, Sink, Source, Store};
+
+/// Annotation used to keep track of the encoded size of the leaves in
+/// subtrees, in bytes
+///
+/// Every leaf is encoded once more when annotated, to be measured, so this
+/// is meant for structures whose changes are summarized with `diff_stats`.
+pub struct ByteSize<H>(u64, PhantomData<H>);
+
+impl<H> Clone for ByteSize<H> {
+    fn clone(&self) -> Self {
+        ByteSize(self.0, PhantomData)
+    }
+}
+
+impl<H> Deref for ByteSize<H> {
+    type Target = u64;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<H> Associative for ByteSize<H> {
+    fn op(&mut self, b: &Self) {
+        self.0 += b.0;
+    }
+}
+
+impl<L, H> From<&L> for ByteSize<H>
+where
+    L: Content<H>,
+    H: ByteHash,
+{
+    fn from(leaf: &L) -> Self {
+        let scratch = Store::ephemeral();
+        let mut sink = Sink::new(&scratch);
+        // encoding into memory does not fail
+        let size = match leaf.clone().persist(&mut sink) {
+            Ok(()) => sink.bytes().len() as u64,
+            Err(_) => 0,
+        };
+        ByteSize(size, PhantomData)
+    }
+}
+
+impl<H: ByteHash> Content<H> for ByteSize<H> {
+    fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
+        sink.write_u64::<BigEndian>(self.0)
+    }
+
+    fn restore(source: &mut Source<H>) -> io::Result<Self> {
+        Ok(ByteSize(source.read_u64::<BigEndian>()?, PhantomData))
+    }
+}
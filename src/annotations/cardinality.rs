@@ -1,12 +1,15 @@
 use std::borrow::Borrow;
 use std::io;
-use std::ops::AddAssign;
+use std::ops::{AddAssign, Deref, SubAssign};
 
 use bytehash::ByteHash;
 use num::{One, Zero};
 
 use super::Associative;
-use crate::{Compound, Content, Sink, Source};
+use crate::{
+    Branch, Compound, Content, HandleType, LeafIter, Method, SearchResult,
+    Sink, Source,
+};
 
 /// Trait group for Cardinality inner type
 pub trait Counter: AddAssign + Copy + Zero + One {}
@@ -16,6 +19,14 @@ impl<T> Counter for T where T: AddAssign + Copy + Zero + One {}
 #[derive(PartialEq, Eq, Clone)]
 pub struct Cardinality<T>(T);
 
+impl<T> Deref for Cardinality<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
 impl<T> Associative for Cardinality<T>
 where
     T: Counter,
@@ -67,3 +78,74 @@ where
             .unwrap_or_else(U::zero)
     }
 }
+
+/// Method searching for the leaf at a position, counting from zero
+///
+/// Whole subtrees before the position are skipped by their `Cardinality`,
+/// so the leaf is found in time proportional to the depth of the structure.
+/// Searching on from the leaf found visits the following leaves in order.
+pub struct Nth<U>(U);
+
+impl<U> Nth<U> {
+    /// Creates a search for the leaf at position `n`
+    pub fn new(n: U) -> Self {
+        Nth(n)
+    }
+}
+
+impl<U, C, H> Method<C, H> for Nth<U>
+where
+    U: Counter + PartialOrd + SubAssign,
+    H: ByteHash,
+    C: Compound<H>,
+    C::Annotation: Borrow<Cardinality<U>>,
+{
+    fn select(&mut self, compound: &C, offset: usize) -> SearchResult {
+        for (i, h) in compound.children()[offset..].iter().enumerate() {
+            if let Some(ann) = h.annotation() {
+                let count = (*ann).borrow().0;
+                if self.0 < count {
+                    return match h.handle_type() {
+                        HandleType::Leaf => SearchResult::Leaf(i),
+                        _ => SearchResult::Path(i),
+                    };
+                }
+                self.0 -= count;
+            }
+        }
+        SearchResult::None
+    }
+}
+
+/// Method for reaching leaves by their position, without visiting the ones
+/// before it
+///
+/// Meant for random sampling and pagination over structures annotated with
+/// `Cardinality`, in whatever order they keep their leaves.
+pub trait Select<U, H>
+where
+    Self: Compound<H>,
+    H: ByteHash,
+{
+    /// Returns a branch to the leaf at position `n`, counting from zero
+    fn nth(&self, n: U) -> io::Result<Option<Branch<'_, Self, H>>>;
+
+    /// Returns an iterator over the leaves from position `n` on
+    fn iter_from(&self, n: U) -> LeafIter<'_, Self, Nth<U>, H>;
+}
+
+impl<U, C, H> Select<U, H> for C
+where
+    U: Counter + PartialOrd + SubAssign,
+    H: ByteHash,
+    C: Compound<H>,
+    C::Annotation: Borrow<Cardinality<U>>,
+{
+    fn nth(&self, n: U) -> io::Result<Option<Branch<'_, Self, H>>> {
+        Branch::new(self, &mut Nth::new(n))
+    }
+
+    fn iter_from(&self, n: U) -> LeafIter<'_, Self, Nth<U>, H> {
+        LeafIter::new(self, Nth::new(n))
+    }
+}
@@ -0,0 +1,74 @@
+use std::io;
+use std::ops::Deref;
+
+use crate::{Associative, ByteHash, Content, Sink, Source};
+
+/// Annotation made up of a tuple of annotations, kept side by side
+///
+/// An alternative to the `annotation!` macro when the parts don't need
+/// names, such as `Compose<(Cardinality<u64>, Sum<u64>)>`. Tuples of two to
+/// four associative annotations are supported, and are reached through
+/// `Deref`.
+///
+/// Unlike the structs generated by `annotation!`, a `Compose` does not
+/// implement `Borrow` for its parts, as two parts may be of the same type,
+/// so it cannot be used where a structure needs, say, `Cardinality<u64>` to
+/// support `count` or `Nth`. Use `annotation!` to name the parts then.
+#[derive(Clone, Debug)]
+pub struct Compose<T>(T);
+
+impl<T> Compose<T> {
+    /// Returns the tuple of annotations
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for Compose<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+macro_rules! compose {
+    ( $( $t:ident : $i:tt ),* ) => {
+        impl< $( $t ),* > Associative for Compose<( $( $t, )* )>
+        where
+            $( $t: Associative ),*
+        {
+            fn op(&mut self, b: &Self) {
+                $( (self.0).$i.op(&(b.0).$i); )*
+            }
+        }
+
+        impl<__L, $( $t ),* > From<&__L> for Compose<( $( $t, )* )>
+        where
+            $( $t: for<'l> From<&'l __L> ),*
+        {
+            fn from(l: &__L) -> Self {
+                Compose(( $( $t::from(l), )* ))
+            }
+        }
+
+        impl<__H, $( $t ),* > Content<__H> for Compose<( $( $t, )* )>
+        where
+            __H: ByteHash,
+            $( $t: Content<__H> ),*
+        {
+            fn persist(&mut self, sink: &mut Sink<__H>) -> io::Result<()> {
+                $( (self.0).$i.persist(sink)?; )*
+                Ok(())
+            }
+
+            fn restore(source: &mut Source<__H>) -> io::Result<Self> {
+                Ok(Compose(( $( $t::restore(source)?, )* )))
+            }
+        }
+    };
+}
+
+compose!(A: 0, B: 1);
+compose!(A: 0, B: 1, C: 2);
+compose!(A: 0, B: 1, C: 2, D: 3);
@@ -0,0 +1,64 @@
+use std::borrow::Borrow;
+use std::io;
+
+use bytehash::ByteHash;
+
+use super::{Combine, ErasedAnnotation};
+use crate::{Compound, Content, Sink, Source};
+
+/// Annotation that keeps track of the depth of subtrees
+///
+/// Leaves have a depth of zero, nodes are one deeper than their deepest child.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Depth(u32);
+
+impl<A> Combine<A> for Depth {
+    fn combine<E>(elements: &[E]) -> Option<Self>
+    where
+        A: Borrow<Self> + Clone,
+        E: ErasedAnnotation<A>,
+    {
+        elements
+            .iter()
+            .filter_map(ErasedAnnotation::annotation)
+            .map(|ann| {
+                let depth: &Depth = (*ann).borrow();
+                depth.0
+            })
+            .max()
+            .map(|max| Depth(max + 1))
+    }
+}
+
+impl<Anything> From<&Anything> for Depth {
+    fn from(_: &Anything) -> Self {
+        Depth(0)
+    }
+}
+
+impl<H: ByteHash> Content<H> for Depth {
+    fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
+        self.0.persist(sink)
+    }
+
+    fn restore(source: &mut Source<H>) -> io::Result<Self> {
+        Ok(Depth(u32::restore(source)?))
+    }
+}
+
+/// Method for getting the depth of a collection
+pub trait MaxDepth<H> {
+    /// Returns the number of nodes on the longest path from the root to a leaf
+    fn depth(&self) -> u32;
+}
+
+impl<C, H> MaxDepth<H> for C
+where
+    H: ByteHash,
+    C: Compound<H>,
+    C::Annotation: Borrow<Depth>,
+{
+    fn depth(&self) -> u32 {
+        self.annotation().map(|ann| ann.borrow().0).unwrap_or(0)
+    }
+}
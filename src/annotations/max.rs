@@ -0,0 +1,51 @@
+use std::io;
+use std::ops::Deref;
+
+use super::MaxKeyType;
+use crate::{Associative, ByteHash, Content, Sink, Source};
+
+/// Annotation used to keep track of the largest leaf in subtrees
+#[derive(Clone, Debug)]
+pub struct Max<T>(T);
+
+impl<T> Deref for Max<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> Associative for Max<T>
+where
+    T: MaxKeyType,
+{
+    // Take the maximum leaf
+    fn op(&mut self, b: &Self) {
+        if b.0 > self.0 {
+            self.0 = b.0.clone()
+        }
+    }
+}
+
+impl<T> From<&T> for Max<T>
+where
+    T: MaxKeyType,
+{
+    fn from(t: &T) -> Self {
+        Max(t.clone())
+    }
+}
+
+impl<H: ByteHash, T: Content<H>> Content<H> for Max<T>
+where
+    T: MaxKeyType,
+{
+    fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
+        self.0.persist(sink)
+    }
+
+    fn restore(source: &mut Source<H>) -> io::Result<Self> {
+        Ok(Max(T::restore(source)?))
+    }
+}
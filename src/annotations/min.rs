@@ -0,0 +1,56 @@
+use std::borrow::Borrow;
+use std::io;
+use std::ops::Deref;
+
+use super::MaxKeyType;
+use crate::{Associative, ByteHash, Content, Sink, Source};
+
+/// Annotation used to keep track of the smallest value in subtrees
+///
+/// The value is borrowed from the leaf, so maps are annotated with the
+/// smallest of their values.
+#[derive(Clone, Debug)]
+pub struct Min<T>(T);
+
+impl<T> Deref for Min<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> Associative for Min<T>
+where
+    T: MaxKeyType,
+{
+    // Take the minimum value
+    fn op(&mut self, b: &Self) {
+        if b.0 < self.0 {
+            self.0 = b.0.clone()
+        }
+    }
+}
+
+impl<L, T> From<&L> for Min<T>
+where
+    L: Borrow<T>,
+    T: MaxKeyType,
+{
+    fn from(l: &L) -> Self {
+        Min(l.borrow().clone())
+    }
+}
+
+impl<H: ByteHash, T: Content<H>> Content<H> for Min<T>
+where
+    T: MaxKeyType,
+{
+    fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
+        self.0.persist(sink)
+    }
+
+    fn restore(source: &mut Source<H>) -> io::Result<Self> {
+        Ok(Min(T::restore(source)?))
+    }
+}
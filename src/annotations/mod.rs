@@ -3,16 +3,29 @@ use std::io;
 
 use bytehash::ByteHash;
 
-pub use cardinality::{Cardinality, Count, Counter};
+pub use byte_size::ByteSize;
+pub use cardinality::{Cardinality, Count, Counter, Nth, Select};
+pub use compose::Compose;
+pub use depth::{Depth, MaxDepth};
 
+pub use max::Max;
 pub use max_key::{MaxKey, MaxKeyType};
+pub use min::Min;
+pub use query::{FirstWhere, PrefixWhere};
+pub use sum::Sum;
 
 use crate::{Content, Sink, Source};
 
 mod annotation_macro;
+mod byte_size;
 mod cardinality;
-
+mod compose;
+mod depth;
+mod max;
 mod max_key;
+mod min;
+mod query;
+mod sum;
 
 /// Helper group-trait for annotations
 pub trait Annotation<L, H>:
@@ -0,0 +1,114 @@
+use std::borrow::Borrow;
+use std::marker::PhantomData;
+
+use super::Associative;
+use crate::{ByteHash, Compound, HandleType, Method, SearchResult};
+
+/// Method descending into the first subtree whose annotation matches a
+/// predicate
+///
+/// With a predicate such as `|max: &Max<u64>| **max > 100`, this finds the
+/// first leaf for which it holds, skipping every subtree where it cannot.
+pub struct FirstWhere<A, F> {
+    predicate: F,
+    _marker: PhantomData<A>,
+}
+
+impl<A, F> FirstWhere<A, F>
+where
+    F: FnMut(&A) -> bool,
+{
+    /// Creates a search for the first subtree where `predicate` holds
+    pub fn new(predicate: F) -> Self {
+        FirstWhere {
+            predicate,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<A, F, C, H> Method<C, H> for FirstWhere<A, F>
+where
+    F: FnMut(&A) -> bool,
+    H: ByteHash,
+    C: Compound<H>,
+    C::Annotation: Borrow<A>,
+{
+    fn select(&mut self, compound: &C, offset: usize) -> SearchResult {
+        for (i, h) in compound.children()[offset..].iter().enumerate() {
+            if let Some(ann) = h.annotation() {
+                if (self.predicate)((*ann).borrow()) {
+                    return match h.handle_type() {
+                        HandleType::Leaf => SearchResult::Leaf(i),
+                        _ => SearchResult::Path(i),
+                    };
+                }
+            }
+        }
+        SearchResult::None
+    }
+}
+
+/// Method descending to the first leaf where the annotation of all leaves up
+/// to and including it matches a predicate
+///
+/// The annotations of the subtrees passed over are combined as the search
+/// goes, so with `|sum: &Sum<u64>| **sum > 1000` this finds the leaf at which
+/// the running total first exceeds 1000. The predicate is expected to keep
+/// holding once it holds, as it does for running sums of unsigned values.
+pub struct PrefixWhere<A, F> {
+    prefix: Option<A>,
+    predicate: F,
+}
+
+impl<A, F> PrefixWhere<A, F>
+where
+    F: FnMut(&A) -> bool,
+{
+    /// Creates a search for the first leaf where `predicate` holds for the
+    /// combined annotation of the leaves up to it
+    pub fn new(predicate: F) -> Self {
+        PrefixWhere {
+            prefix: None,
+            predicate,
+        }
+    }
+
+    /// Returns the combined annotation of the subtrees passed over so far
+    pub fn prefix(&self) -> Option<&A> {
+        self.prefix.as_ref()
+    }
+}
+
+impl<A, F, C, H> Method<C, H> for PrefixWhere<A, F>
+where
+    A: Associative + Clone,
+    F: FnMut(&A) -> bool,
+    H: ByteHash,
+    C: Compound<H>,
+    C::Annotation: Borrow<A>,
+{
+    fn select(&mut self, compound: &C, offset: usize) -> SearchResult {
+        for (i, h) in compound.children()[offset..].iter().enumerate() {
+            if let Some(ann) = h.annotation() {
+                let ann: &A = (*ann).borrow();
+                let combined = match &self.prefix {
+                    Some(prefix) => {
+                        let mut combined = prefix.clone();
+                        combined.op(ann);
+                        combined
+                    }
+                    None => ann.clone(),
+                };
+                if (self.predicate)(&combined) {
+                    return match h.handle_type() {
+                        HandleType::Leaf => SearchResult::Leaf(i),
+                        _ => SearchResult::Path(i),
+                    };
+                }
+                self.prefix = Some(combined);
+            }
+        }
+        SearchResult::None
+    }
+}
@@ -0,0 +1,55 @@
+use std::borrow::Borrow;
+use std::io;
+use std::ops::{AddAssign, Deref};
+
+use num::traits::Saturating;
+
+use crate::{Associative, ByteHash, Content, Sink, Source};
+
+/// Annotation used to keep track of the sum of the values in subtrees
+///
+/// Sums saturate at the maximum value of `T` rather than overflow, so a
+/// subtree whose values add up past it only reports that maximum.
+#[derive(Clone, Debug)]
+pub struct Sum<T>(T);
+
+impl<T> Deref for Sum<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> Associative for Sum<T>
+where
+    T: AddAssign + Saturating + Clone,
+{
+    fn op(&mut self, b: &Self) {
+        self.0 = self.0.clone().saturating_add(b.0.clone());
+    }
+}
+
+impl<L, T> From<&L> for Sum<T>
+where
+    L: Borrow<T>,
+    T: AddAssign + Clone,
+{
+    fn from(l: &L) -> Self {
+        Sum(l.borrow().clone())
+    }
+}
+
+impl<H, T> Content<H> for Sum<T>
+where
+    H: ByteHash,
+    T: Content<H> + AddAssign,
+{
+    fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
+        self.0.persist(sink)
+    }
+
+    fn restore(source: &mut Source<H>) -> io::Result<Self> {
+        Ok(Sum(T::restore(source)?))
+    }
+}
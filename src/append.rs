@@ -0,0 +1,150 @@
+use std::io;
+use std::mem;
+
+use bytehash::ByteHash;
+
+use crate::compound::Compound;
+use crate::handle::{Handle, HandleMut, HandleRef, HandleType};
+
+/// A Compound filled from the left, such as a vector or a heap
+///
+/// Leaves are only added after the last one and removed from the end, so
+/// every node but the last one of each level is full. Implementors provide
+/// the bounded list of children of a node, the provided methods grow and
+/// shrink the tree.
+pub trait AppendOnly<H>
+where
+    Self: Compound<H> + Default,
+    H: ByteHash,
+{
+    /// Returns true if the node has no room for another child
+    fn is_full(&self) -> bool;
+
+    /// Adds a child after the last one
+    fn push_child(&mut self, child: Handle<Self, H>);
+
+    /// Removes the last child, returning it
+    fn pop_child(&mut self) -> Option<Handle<Self, H>>;
+
+    /// Adds a leaf after the last one, growing a new root if the tree is
+    /// full
+    fn append(&mut self, leaf: Self::Leaf) -> io::Result<()> {
+        if let Some(leaf) = append_below::<Self, H>(self, leaf)? {
+            let height = height::<Self, H>(self)?;
+            let old_root = mem::take(self);
+            self.push_child(Handle::new_node(old_root));
+            self.push_child(Handle::new_node(chain::<Self, H>(leaf, height)));
+        }
+        Ok(())
+    }
+
+    /// Removes the last leaf, returning it
+    ///
+    /// A root left with a single node child is replaced by it.
+    fn pop_last(&mut self) -> io::Result<Option<Self::Leaf>> {
+        let popped = pop_below::<Self, H>(self)?;
+        let children = self.children_mut();
+        if children.len() == 1 && children[0].handle_type() == HandleType::Node
+        {
+            children[0].inner_mut()?;
+            *self = mem::take(&mut children[0]).into_node();
+        }
+        Ok(popped)
+    }
+}
+
+// Adds a leaf to the subtree, returning it back if the subtree is full
+fn append_below<C, H>(
+    node: &mut C,
+    leaf: C::Leaf,
+) -> io::Result<Option<C::Leaf>>
+where
+    C: AppendOnly<H>,
+    H: ByteHash,
+{
+    let leaves = node
+        .children()
+        .first()
+        .map(|h| h.handle_type() == HandleType::Leaf)
+        .unwrap_or(true);
+
+    if leaves {
+        if node.is_full() {
+            return Ok(Some(leaf));
+        }
+        node.push_child(Handle::new_leaf(leaf));
+        return Ok(None);
+    }
+
+    let last = node.children().len() - 1;
+    let leaf = match &mut *node.children_mut()[last].inner_mut()? {
+        HandleMut::Node(n) => match append_below::<C, H>(*n, leaf)? {
+            None => return Ok(None),
+            Some(leaf) => leaf,
+        },
+        _ => unreachable!(),
+    };
+
+    if node.is_full() {
+        Ok(Some(leaf))
+    } else {
+        let height = height::<C, H>(node)? - 1;
+        node.push_child(Handle::new_node(chain::<C, H>(leaf, height)));
+        Ok(None)
+    }
+}
+
+// Removes the last leaf of the subtree, and the nodes it leaves empty
+fn pop_below<C, H>(node: &mut C) -> io::Result<Option<C::Leaf>>
+where
+    C: AppendOnly<H>,
+    H: ByteHash,
+{
+    let last = match node.children().len() {
+        0 => return Ok(None),
+        len => len - 1,
+    };
+    if node.children()[last].handle_type() == HandleType::Leaf {
+        return Ok(node.pop_child().map(Handle::into_leaf));
+    }
+    let (popped, empty) = match &mut *node.children_mut()[last].inner_mut()? {
+        HandleMut::Node(n) => (pop_below::<C, H>(*n)?, n.children().is_empty()),
+        _ => unreachable!(),
+    };
+    if empty {
+        node.pop_child();
+    }
+    Ok(popped)
+}
+
+// The number of levels of nodes, down to and including the one holding the
+// leaves
+fn height<C, H>(node: &C) -> io::Result<usize>
+where
+    C: AppendOnly<H>,
+    H: ByteHash,
+{
+    match node.children().first() {
+        Some(first) => match first.inner()? {
+            HandleRef::Node(n) => Ok(height::<C, H>(&*n)? + 1),
+            _ => Ok(1),
+        },
+        None => Ok(1),
+    }
+}
+
+// A subtree of `height` levels holding only `leaf`
+fn chain<C, H>(leaf: C::Leaf, height: usize) -> C
+where
+    C: AppendOnly<H>,
+    H: ByteHash,
+{
+    let mut node = C::default();
+    node.push_child(Handle::new_leaf(leaf));
+    for _ in 1..height {
+        let mut parent = C::default();
+        parent.push_child(Handle::new_node(node));
+        node = parent;
+    }
+    node
+}
@@ -0,0 +1,151 @@
+use std::collections::HashSet;
+use std::hash::Hash;
+use std::io::{self, Cursor, Read};
+use std::path::Path;
+use std::sync::Arc;
+
+use bytehash::ByteHash;
+
+use crate::backend::{Backend, PutResult};
+use crate::control::Control;
+use crate::eviction::EvictionPolicy;
+use crate::partition::Partition;
+
+/// A backend keeping the nodes read from another backend in memory
+///
+/// Nodes are cached by digest, up to a budget in bytes, so the hot paths of
+/// a large on-disk tree stay in memory across queries. The least recently
+/// used nodes are evicted by default. Hits and misses are counted, for
+/// tuning the budget through the `CacheControl` of the backend.
+pub struct CachedBackend<H: ByteHash, B> {
+    inner: B,
+    cache: Arc<Partition<H::Digest>>,
+}
+
+/// A handle to the cache of a `CachedBackend`
+///
+/// The backend is usually moved into a `Store`, the handle is kept to read
+/// the counters and tune the cache while the store is in use.
+pub struct CacheControl<D>(Arc<Partition<D>>);
+
+impl<D> Clone for CacheControl<D> {
+    fn clone(&self) -> Self {
+        CacheControl(self.0.clone())
+    }
+}
+
+impl<D: Hash + Eq + Copy + Send + 'static> CacheControl<D> {
+    /// Returns the budget of the cache, in bytes
+    pub fn budget(&self) -> usize {
+        self.0.budget()
+    }
+
+    /// Sets the budget of the cache, evicting nodes if now over it
+    pub fn set_budget(&self, budget: usize) {
+        self.0.set_budget(budget)
+    }
+
+    /// Replaces the eviction policy of the cache
+    ///
+    /// The nodes already cached are handed over to the new policy.
+    pub fn set_eviction_policy<P>(&self, policy: P)
+    where
+        P: EvictionPolicy<D> + 'static,
+    {
+        self.0.set_policy(Box::new(policy))
+    }
+
+    /// Returns the number of bytes cached
+    pub fn cached(&self) -> usize {
+        self.0.size()
+    }
+
+    /// Returns the number of reads served from the cache
+    pub fn hits(&self) -> u64 {
+        self.0.hits()
+    }
+
+    /// Returns the number of reads passed on to the wrapped backend
+    pub fn misses(&self) -> u64 {
+        self.0.misses()
+    }
+
+    /// Resets the hit and miss counters to zero
+    pub fn reset_counters(&self) {
+        self.0.reset_counters()
+    }
+}
+
+impl<H: ByteHash, B: Backend<H>> CachedBackend<H, B> {
+    /// Creates a backend reading from `inner`, caching up to `budget` bytes
+    /// of nodes
+    pub fn new(inner: B, budget: usize) -> Self {
+        CachedBackend {
+            inner,
+            cache: Arc::new(Partition::new("cached", budget)),
+        }
+    }
+
+    /// Returns the wrapped backend
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// Returns a handle to the cache, for tuning and reading its counters
+    pub fn control(&self) -> CacheControl<H::Digest> {
+        CacheControl(self.cache.clone())
+    }
+}
+
+impl<H: ByteHash, B: Backend<H>> Backend<H> for CachedBackend<H, B> {
+    fn get<'a>(&'a self, hash: &H::Digest) -> io::Result<Box<dyn Read + 'a>> {
+        if let Some(bytes) = self.cache.get(hash) {
+            return Ok(Box::new(Cursor::new(bytes)));
+        }
+        let mut bytes = vec![];
+        self.inner.get(hash)?.read_to_end(&mut bytes)?;
+        let bytes: Arc<[u8]> = bytes.into();
+        self.cache.insert(*hash, bytes.clone());
+        Ok(Box::new(Cursor::new(bytes)))
+    }
+
+    fn put(
+        &mut self,
+        hash: H::Digest,
+        bytes: Vec<u8>,
+    ) -> io::Result<PutResult> {
+        self.inner.put(hash, bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    fn slow(&self, hash: &H::Digest) -> bool {
+        !self.cache.contains(hash) && self.inner.slow(hash)
+    }
+
+    fn repair(&mut self, digest: H::Digest, bytes: Vec<u8>) -> io::Result<()> {
+        // the cached copy was read from the damaged value
+        self.cache.remove(&digest);
+        self.inner.repair(digest, bytes)
+    }
+
+    fn gc(
+        &mut self,
+        live: &HashSet<H::Digest>,
+        control: &mut Control<'_>,
+    ) -> io::Result<usize> {
+        let reclaimed = self.inner.gc(live, control)?;
+        self.cache.retain(|digest| live.contains(digest));
+        Ok(reclaimed)
+    }
+
+    fn size(&self) -> usize {
+        self.inner.size()
+    }
+
+    fn path(&self) -> Option<&Path> {
+        self.inner.path()
+    }
+}
@@ -0,0 +1,216 @@
+use std::collections::HashSet;
+use std::io::{self, Cursor, Read};
+use std::path::Path;
+
+use bytehash::ByteHash;
+
+use crate::backend::{Backend, PutResult};
+use crate::control::Control;
+use crate::error::Error;
+
+// Compression level used for zstd
+const LEVEL: i32 = 3;
+
+// Default limit on the size of a decompressed value
+const MAX_DECODED: usize = 64 << 20;
+
+// Flags leading every value, naming how the rest of it is compressed
+const RAW: u8 = 0;
+const LZ4: u8 = 1;
+const ZSTD: u8 = 2;
+
+/// The compression algorithm of a `CompressedBackend`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Codec {
+    /// lz4, fast to compress and decompress
+    Lz4,
+    /// zstd, compressing further at some cost in speed
+    Zstd,
+}
+
+/// When a `CompressedBackend` compresses the values written
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompressionPolicy {
+    /// Values are stored as they are
+    Never,
+    /// Values of at least this many bytes are compressed
+    Above(usize),
+    /// Every value is compressed
+    Always,
+}
+
+/// A backend compressing the nodes written to another backend
+///
+/// Every value is stored with a flag byte recording whether, and with which
+/// codec, it was compressed, so the policy and codec can change over the
+/// life of the store, and values are read back whichever way they were
+/// written. Values are stored uncompressed when compression does not make
+/// them smaller. All values of the wrapped backend must be written through a
+/// `CompressedBackend`, to carry the flag.
+///
+/// Digests are those of the uncompressed nodes, so compressing is invisible
+/// to the structures stored. Values decompressing to more than 64 MiB are
+/// rejected as invalid, see `set_max_decoded`.
+pub struct CompressedBackend<B> {
+    inner: B,
+    codec: Codec,
+    policy: CompressionPolicy,
+    max_decoded: usize,
+}
+
+impl<B> CompressedBackend<B> {
+    /// Creates a backend compressing the values it writes to `inner` with
+    /// `codec`, as decided by `policy`
+    pub fn new(inner: B, codec: Codec, policy: CompressionPolicy) -> Self {
+        CompressedBackend {
+            inner,
+            codec,
+            policy,
+            max_decoded: MAX_DECODED,
+        }
+    }
+
+    /// Returns the wrapped backend
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// Changes the compression of the values written from now on
+    pub fn set_policy(&mut self, codec: Codec, policy: CompressionPolicy) {
+        self.codec = codec;
+        self.policy = policy;
+    }
+
+    /// Sets the size in bytes above which decompressed values are rejected
+    pub fn set_max_decoded(&mut self, size: usize) {
+        self.max_decoded = size;
+    }
+
+    // Prepends the flag to `bytes`, compressing them if the policy says so
+    fn encode(&self, bytes: Vec<u8>) -> io::Result<Vec<u8>> {
+        let compress = match self.policy {
+            CompressionPolicy::Never => false,
+            CompressionPolicy::Above(size) => bytes.len() >= size,
+            CompressionPolicy::Always => true,
+        };
+        if compress {
+            let (flag, compressed) = match self.codec {
+                Codec::Lz4 => (LZ4, lz4_flex::compress_prepend_size(&bytes)),
+                Codec::Zstd => (ZSTD, zstd::encode_all(&bytes[..], LEVEL)?),
+            };
+            if compressed.len() < bytes.len() {
+                let mut encoded = Vec::with_capacity(compressed.len() + 1);
+                encoded.push(flag);
+                encoded.extend_from_slice(&compressed);
+                return Ok(encoded);
+            }
+        }
+        let mut encoded = Vec::with_capacity(bytes.len() + 1);
+        encoded.push(RAW);
+        encoded.extend_from_slice(&bytes);
+        Ok(encoded)
+    }
+
+    // Decodes a value as stored, flag included
+    fn decode(&self, mut stored: Vec<u8>) -> io::Result<Vec<u8>> {
+        let flag = match stored.first() {
+            Some(flag) => *flag,
+            None => {
+                return Err(
+                    Error::InvalidEncoding("Missing compression flag").into()
+                )
+            }
+        };
+        match flag {
+            RAW => {
+                stored.remove(0);
+                Ok(stored)
+            }
+            LZ4 => {
+                // the size is read from storage, and checked before
+                // allocating
+                if stored.len() < 5 {
+                    return Err(Error::InvalidEncoding(
+                        "Invalid lz4 compressed value",
+                    )
+                    .into());
+                }
+                let mut size = [0u8; 4];
+                size.copy_from_slice(&stored[1..5]);
+                let size = u32::from_le_bytes(size) as usize;
+                if size > self.max_decoded {
+                    return Err(too_large());
+                }
+                lz4_flex::decompress(&stored[5..], size).map_err(|_| {
+                    Error::InvalidEncoding("Invalid lz4 compressed value")
+                        .into()
+                })
+            }
+            ZSTD => {
+                let decoder = zstd::stream::read::Decoder::new(&stored[1..])?;
+                let mut decoded = vec![];
+                decoder
+                    .take(self.max_decoded as u64 + 1)
+                    .read_to_end(&mut decoded)?;
+                if decoded.len() > self.max_decoded {
+                    return Err(too_large());
+                }
+                Ok(decoded)
+            }
+            _ => Err(Error::InvalidEncoding("Invalid compression flag").into()),
+        }
+    }
+}
+
+fn too_large() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "Decompressed value exceeds the size limit",
+    )
+}
+
+impl<H: ByteHash, B: Backend<H>> Backend<H> for CompressedBackend<B> {
+    fn get<'a>(&'a self, hash: &H::Digest) -> io::Result<Box<dyn Read + 'a>> {
+        let mut stored = vec![];
+        self.inner.get(hash)?.read_to_end(&mut stored)?;
+        Ok(Box::new(Cursor::new(self.decode(stored)?)))
+    }
+
+    fn put(
+        &mut self,
+        hash: H::Digest,
+        bytes: Vec<u8>,
+    ) -> io::Result<PutResult> {
+        let encoded = self.encode(bytes)?;
+        self.inner.put(hash, encoded)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    fn slow(&self, hash: &H::Digest) -> bool {
+        self.inner.slow(hash)
+    }
+
+    fn repair(&mut self, digest: H::Digest, bytes: Vec<u8>) -> io::Result<()> {
+        let encoded = self.encode(bytes)?;
+        self.inner.repair(digest, encoded)
+    }
+
+    fn gc(
+        &mut self,
+        live: &HashSet<H::Digest>,
+        control: &mut Control<'_>,
+    ) -> io::Result<usize> {
+        self.inner.gc(live, control)
+    }
+
+    fn size(&self) -> usize {
+        self.inner.size()
+    }
+
+    fn path(&self) -> Option<&Path> {
+        self.inner.path()
+    }
+}
@@ -1,32 +1,268 @@
-use std::fs::{create_dir, File, OpenOptions};
-use std::io::{self, Read, Seek, SeekFrom, Write};
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::hash::{Hash, Hasher};
+use std::io::{
+    self, BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write,
+};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 
 use appendix::Index;
-use bytehash::ByteHash;
+use bytehash::{ByteHash, State};
+use byteorder::{BigEndian, ByteOrder, ReadBytesExt, WriteBytesExt};
+use memmap::Mmap;
+use parking_lot::RwLock;
 
 use crate::backend::{Backend, PutResult};
+use crate::control::Control;
+use crate::root::RootLock;
+
+// The version of the layout of the index and data file, recorded in the
+// `layout` file of the store
+//
+// The index holds digests and big endian entries, placed by a hash of the
+// bytes of the digests alone, so that stores can be moved across platforms.
+// Earlier versions were recorded as the platform they were written on,
+// such as `le64`, and cannot be read by this one. Stores written before the
+// layout was recorded are migrated, see `migrate_baseline`.
+const LAYOUT: &str = "3";
+
+// A digest as an index key, hashed the same on every platform
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Key<D>(D);
+
+impl<D: AsRef<[u8]>> Hash for Key<D> {
+    fn hash<S: Hasher>(&self, state: &mut S) {
+        // without the length prefix of slices, a `usize`
+        state.write(self.0.as_ref())
+    }
+}
+
+// The location of a node in the data file, its offset and length in big
+// endian
+#[derive(Clone, Copy)]
+struct Entry([u8; 16]);
+
+impl Entry {
+    fn new(offset: u64, len: u64) -> Self {
+        let mut entry = [0u8; 16];
+        BigEndian::write_u64(&mut entry[..8], offset);
+        BigEndian::write_u64(&mut entry[8..], len);
+        Entry(entry)
+    }
+
+    fn offset(&self) -> u64 {
+        BigEndian::read_u64(&self.0[..8])
+    }
+
+    fn len(&self) -> u64 {
+        BigEndian::read_u64(&self.0[8..])
+    }
+}
+
+fn check_layout<H: ByteHash>(dir: &Path) -> io::Result<()> {
+    let path = dir.join("layout");
+    match fs::read_to_string(&path) {
+        Ok(layout) if layout.trim() == LAYOUT => Ok(()),
+        Ok(_) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Store was written in an unsupported disk layout",
+        )),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+            let len = |file: &str| {
+                fs::metadata(dir.join(file)).map_or(0, |m| m.len())
+            };
+            // stores written before the layout was recorded had no log
+            if len("wal") > 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Store was written in an unrecorded disk layout",
+                ));
+            }
+            if len("data") > 0 {
+                migrate_baseline::<H>(dir)
+            } else {
+                fs::write(&path, LAYOUT)
+            }
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn hash<H: ByteHash>(bytes: &[u8]) -> H::Digest {
+    let mut hasher = H::state();
+    hasher.write(bytes);
+    hasher.fin()
+}
+
+// Finds the entries of the index of a store written before the layout was
+// recorded, an `Index<H::Digest, u64>` of the offsets of the nodes in the
+// data file
+//
+// The index can only be looked up, so the digests stored in its pages are
+// probed for, at the alignment of its entries. Any digest found with an
+// offset is one the store holds.
+fn baseline_entries<H: ByteHash>(
+    dir: &Path,
+) -> io::Result<Vec<(H::Digest, u64)>> {
+    let index_dir = dir.join("index");
+    let index = Index::<H::Digest, u64>::new(&index_dir)?;
+    let width = H::Digest::default().as_ref().len();
+    let mut found = HashMap::new();
+    for file in fs::read_dir(&index_dir)? {
+        let bytes = fs::read(file?.path())?;
+        for key in bytes.windows(width).step_by(8) {
+            if key.iter().all(|byte| *byte == 0) {
+                continue;
+            }
+            let mut digest = H::Digest::default();
+            digest.as_mut().copy_from_slice(key);
+            if found.contains_key(&digest) {
+                continue;
+            }
+            if let Some(offset) = index.get(&digest)? {
+                found.insert(digest, *offset);
+            }
+        }
+    }
+    Ok(found.into_iter().collect())
+}
+
+// Rewrites the index of a store written before the layout was recorded in
+// this one, leaving the data file as it is
+//
+// The length of each node runs up to the next offset in the data file, or
+// is zero for nodes sharing their offset with the next one, and is checked
+// against the digest of the node. The new index is built aside and swapped
+// in along with the `layout` file, as a compaction is, see
+// `finish_compaction`.
+fn migrate_baseline<H: ByteHash>(dir: &Path) -> io::Result<()> {
+    let mut entries = baseline_entries::<H>(dir)?;
+    if entries.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Store was written in an unrecorded disk layout",
+        ));
+    }
+    entries.sort_by_key(|(_, offset)| *offset);
+
+    let compact = dir.join("compact");
+    if compact.exists() {
+        fs::remove_dir_all(&compact)?;
+    }
+    fs::create_dir_all(compact.join("index"))?;
+    let mut index = Index::new(&compact.join("index"))?;
+    let mut data = File::open(dir.join("data"))?;
+    let end = data.metadata()?.len();
+    let empty = hash::<H>(&[]);
+
+    let mut bytes = vec![];
+    for (i, (digest, offset)) in entries.iter().enumerate() {
+        let next = entries[i + 1..]
+            .iter()
+            .map(|(_, next)| *next)
+            .find(|next| next > offset)
+            .unwrap_or(end)
+            .min(end);
+        let len = next.saturating_sub(*offset);
+        data.seek(SeekFrom::Start(*offset))?;
+        bytes.resize(len as usize, 0);
+        data.read_exact(&mut bytes)?;
+        let len = if hash::<H>(&bytes) == *digest {
+            len
+        } else if *digest == empty {
+            0
+        } else if next == end {
+            // indexed, but cut short in the data file by a crash
+            continue;
+        } else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Store does not match its index",
+            ));
+        };
+        index.insert(Key(*digest), Entry::new(*offset, len))?;
+    }
+    index.flush()?;
+    drop(index);
+    fs::write(compact.join("layout"), LAYOUT)?;
+    fs::write(compact.join("done"), [])?;
+    finish_compaction(dir)
+}
+
+// Moves a complete compacted copy of the data file and index in place of
+// the originals, or removes an incomplete one, see `DiskBackend::gc`
+fn finish_compaction(dir: &Path) -> io::Result<()> {
+    let compact = dir.join("compact");
+    if !compact.exists() {
+        return Ok(());
+    }
+    if compact.join("done").exists() {
+        // each step can be repeated when interrupted by a crash
+        if compact.join("index").exists() {
+            if dir.join("index").exists() {
+                fs::remove_dir_all(dir.join("index"))?;
+            }
+            fs::rename(compact.join("index"), dir.join("index"))?;
+        }
+        if compact.join("data").exists() {
+            fs::rename(compact.join("data"), dir.join("data"))?;
+        }
+        // the layout of a migrated index, see `migrate_baseline`
+        if compact.join("layout").exists() {
+            fs::rename(compact.join("layout"), dir.join("layout"))?;
+        }
+    }
+    fs::remove_dir_all(&compact)
+}
+
+// Tags of the entries in the write-ahead log
+const WAL_NODE: u8 = 0;
+const WAL_COMMIT: u8 = 1;
+
+// The file locked by the process writing to the store
+const LOCK: &str = ".lock";
 
 /// A backend that stores its data in an `appendix` index, and a flat file
+///
+/// Nodes written are kept in memory until flushed, when they are appended
+/// to a write-ahead log, and only moved into the data file and index once
+/// the log commits them. A crash at any point leaves either all or none of
+/// the nodes written since the last flush, see `recover`.
+///
+/// Several processes can share a store. Flushes are done under an advisory
+/// lock on the `.lock` file of the store, released should the process
+/// crash, and nodes flushed by other processes are found once they are, as
+/// the index is reopened when missing a node. Garbage collection replaces
+/// the files of the store, and needs it to itself.
 pub struct DiskBackend<H: ByteHash> {
-    index: Index<H::Digest, u64>,
+    dir: PathBuf,
+    index: RwLock<Index<Key<H::Digest>, Entry>>,
+    // the length of the data file when the index was last opened
+    indexed: AtomicU64,
     data: File,
     data_path: PathBuf,
     data_offset: u64,
+    wal: File,
+    wal_path: PathBuf,
+    // nodes written since the last flush, and their total length
+    pending: HashMap<H::Digest, Vec<u8>>,
+    pending_len: u64,
+    mmapped: bool,
+    // the data file as of the last flush, when reading through mmap
+    mmap: Option<Mmap>,
 }
 
 impl<H: ByteHash> DiskBackend<H> {
     /// Create a new DiskBackend at given path, creates a new directory if neccesary
     pub fn new<P: Into<PathBuf>>(path: P) -> io::Result<Self> {
         let dir = path.into();
-        if !dir.exists() {
-            create_dir(&dir)?;
-        }
-
         let index_dir = dir.join("index");
-        if !index_dir.exists() {
-            create_dir(&index_dir)?;
-        }
+        fs::create_dir_all(&dir)?;
+        let _lock = RootLock::acquire(dir.join(LOCK))?;
+        finish_compaction(&dir)?;
+        fs::create_dir_all(&index_dir)?;
+        check_layout::<H>(&dir)?;
 
         let index = Index::new(&index_dir)?;
         let data_path = dir.join("data");
@@ -39,22 +275,220 @@ impl<H: ByteHash> DiskBackend<H> {
         let data_offset = data.metadata()?.len();
         data.seek(SeekFrom::End(0))?;
 
-        Ok(DiskBackend {
-            index,
+        let wal_path = dir.join("wal");
+        let wal = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(&wal_path)?;
+
+        let mut backend = DiskBackend {
+            dir,
+            index: RwLock::new(index),
+            indexed: AtomicU64::new(data_offset),
             data_path,
             data,
             data_offset,
-        })
+            wal,
+            wal_path,
+            pending: HashMap::new(),
+            pending_len: 0,
+            mmapped: false,
+            mmap: None,
+        };
+        backend.replay()?;
+        Ok(backend)
+    }
+
+    /// Recovers from a crash, returning the number of nodes recovered
+    ///
+    /// Nodes of commits completed in the write-ahead log are replayed into
+    /// the data file and index, nodes of an incomplete commit and nodes not
+    /// flushed are dropped, and the log is truncated. Called when opening
+    /// the backend, so only needed to recover a backend kept open across a
+    /// failed flush.
+    pub fn recover(&mut self) -> io::Result<usize> {
+        let _lock = RootLock::acquire(self.dir.join(LOCK))?;
+        self.pending.clear();
+        self.pending_len = 0;
+        self.replay()
+    }
+
+    // Catches up with the nodes flushed by other processes sharing the
+    // store, the lock has to be held
+    fn refresh(&mut self) -> io::Result<()> {
+        // the data file is replaced by compaction
+        self.data = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .open(&self.data_path)?;
+        self.data_offset = self.data.seek(SeekFrom::End(0))?;
+        if self.data_offset != *self.indexed.get_mut() {
+            *self.index.get_mut() = Index::new(&self.dir.join("index"))?;
+            *self.indexed.get_mut() = self.data_offset;
+        }
+        Ok(())
+    }
+
+    // Reopens the index if other processes flushed nodes since it was
+    // opened, returning true if so
+    fn refresh_index(&self) -> io::Result<bool> {
+        let len = fs::metadata(&self.data_path)?.len();
+        if len == self.indexed.load(Ordering::Acquire) {
+            return Ok(false);
+        }
+        let _lock = RootLock::acquire(self.dir.join(LOCK))?;
+        let mut index = self.index.write();
+        *index = Index::new(&self.dir.join("index"))?;
+        let len = fs::metadata(&self.data_path)?.len();
+        self.indexed.store(len, Ordering::Release);
+        Ok(true)
+    }
+
+    // Appends the nodes written since the last flush to the write-ahead
+    // log, followed by the marker committing them, the lock has to be held
+    fn log(&mut self) -> io::Result<()> {
+        self.wal.seek(SeekFrom::End(0))?;
+        let mut wal = BufWriter::new(&self.wal);
+        for (hash, bytes) in &self.pending {
+            wal.write_u8(WAL_NODE)?;
+            wal.write_all(hash.as_ref())?;
+            wal.write_u32::<BigEndian>(bytes.len() as u32)?;
+            wal.write_all(bytes)?;
+        }
+        // the commit is complete once its marker is synced
+        wal.write_u8(WAL_COMMIT)?;
+        wal.into_inner()?.sync_data()
+    }
+
+    // Replays the commits complete in the write-ahead log, the lock has to
+    // be held
+    fn replay(&mut self) -> io::Result<usize> {
+        self.refresh()?;
+        let mut reader = BufReader::new(File::open(&self.wal_path)?);
+        let mut digest = H::Digest::default();
+        let mut segment_start = self.data_offset;
+        let mut segment = vec![];
+        let mut committed = vec![];
+
+        let mut bytes = vec![];
+        loop {
+            match Self::read_entry(&mut reader, &mut digest, &mut bytes) {
+                Ok(WAL_NODE) => {
+                    let index = self.index.get_mut();
+                    if index.get(&Key(digest))?.is_none() {
+                        self.data.write_all(&bytes)?;
+                        let len = bytes.len() as u64;
+                        let entry = Entry::new(self.data_offset, len);
+                        segment.push((digest, entry));
+                        self.data_offset += len;
+                    }
+                }
+                Ok(_) => {
+                    committed.append(&mut segment);
+                    segment_start = self.data_offset;
+                }
+                // an entry cut short by a crash
+                Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        // drop the nodes of the incomplete commit, if any
+        self.data.set_len(segment_start)?;
+        self.data.seek(SeekFrom::End(0))?;
+        self.data_offset = segment_start;
+        self.data.sync_data()?;
+
+        // the nodes are durable in the data file before being indexed
+        let recovered = committed.len();
+        let index = self.index.get_mut();
+        for (digest, entry) in committed {
+            index.insert(Key(digest), entry)?;
+        }
+        index.flush()?;
+        *self.indexed.get_mut() = self.data_offset;
+
+        self.wal.set_len(0)?;
+        self.wal.seek(SeekFrom::Start(0))?;
+        self.wal.sync_data()?;
+        Ok(recovered)
+    }
+
+    // Reads a log entry, returning its tag
+    fn read_entry<R: Read>(
+        reader: &mut R,
+        digest: &mut H::Digest,
+        bytes: &mut Vec<u8>,
+    ) -> io::Result<u8> {
+        match reader.read_u8()? {
+            WAL_NODE => {
+                reader.read_exact(digest.as_mut())?;
+                let len = reader.read_u32::<BigEndian>()?;
+                bytes.resize(len as usize, 0);
+                reader.read_exact(bytes)?;
+                Ok(WAL_NODE)
+            }
+            WAL_COMMIT => Ok(WAL_COMMIT),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Invalid write-ahead log entry",
+            )),
+        }
+    }
+
+    /// Opens a DiskBackend at given path, reading its data through a memory
+    /// map
+    ///
+    /// Nodes are then read straight from the mapped pages, instead of being
+    /// copied into intermediate buffers. The map is refreshed on flush,
+    /// nodes written since are read from the file.
+    pub fn open_mmap<P: Into<PathBuf>>(path: P) -> io::Result<Self> {
+        let mut backend = Self::new(path)?;
+        backend.mmapped = true;
+        backend.remap()?;
+        Ok(backend)
+    }
+
+    fn remap(&mut self) -> io::Result<()> {
+        // mapping an empty file fails
+        if self.data_offset > 0 {
+            let file = File::open(&self.data_path)?;
+            // the data file is only ever appended to, so the mapped bytes
+            // are never modified
+            self.mmap = Some(unsafe { Mmap::map(&file)? });
+        }
+        Ok(())
     }
 }
 
 impl<H: ByteHash> Backend<H> for DiskBackend<H> {
     fn get<'a>(&'a self, hash: &H::Digest) -> io::Result<Box<dyn Read + 'a>> {
-        match self.index.get(hash)? {
-            Some(offset) => {
+        if let Some(bytes) = self.pending.get(hash) {
+            return Ok(Box::new(&bytes[..]));
+        }
+        let found = self.index.read().get(&Key(*hash))?;
+        let found = match found {
+            // flushed by another process since the index was opened
+            None if self.refresh_index()? => {
+                self.index.read().get(&Key(*hash))?
+            }
+            found => found,
+        };
+        match found {
+            Some(entry) => {
+                let (offset, end) =
+                    (entry.offset(), entry.offset() + entry.len());
+                if let Some(ref mmap) = self.mmap {
+                    if end <= mmap.len() as u64 {
+                        let bytes = &mmap[offset as usize..end as usize];
+                        return Ok(Box::new(Cursor::new(bytes)));
+                    }
+                }
                 let mut file = File::open(&self.data_path)?;
-                file.seek(SeekFrom::Start(*offset))?;
-                Ok(Box::new(file))
+                file.seek(SeekFrom::Start(offset))?;
+                Ok(Box::new(file.take(entry.len())))
             }
             None => {
                 Err(io::Error::new(io::ErrorKind::NotFound, "Data not found"))
@@ -67,22 +501,372 @@ impl<H: ByteHash> Backend<H> for DiskBackend<H> {
         hash: H::Digest,
         bytes: Vec<u8>,
     ) -> io::Result<PutResult> {
-        if self.index.insert(hash, self.data_offset)? {
-            // value already present
-            Ok(PutResult::AlreadyThere)
-        } else {
-            self.data.write_all(&bytes)?;
-            self.data_offset += bytes.len() as u64;
-            Ok(PutResult::Ok)
+        if self.pending.contains_key(&hash)
+            || self.index.get_mut().get(&Key(hash))?.is_some()
+        {
+            return Ok(PutResult::AlreadyThere);
         }
+        self.pending_len += bytes.len() as u64;
+        self.pending.insert(hash, bytes);
+        Ok(PutResult::Ok)
     }
 
     fn flush(&mut self) -> io::Result<()> {
+        if !self.pending.is_empty() {
+            let _lock = RootLock::acquire(self.dir.join(LOCK))?;
+            // left by a process sharing the store that crashed flushing
+            if self.wal.metadata()?.len() > 0 {
+                self.replay()?;
+            }
+            self.log()?;
+            self.replay()?;
+            self.pending.clear();
+            self.pending_len = 0;
+        }
         self.data.flush()?;
-        self.index.flush()
+        self.index.get_mut().flush()?;
+        if self.mmapped {
+            self.remap()?;
+        }
+        Ok(())
+    }
+
+    fn repair(&mut self, hash: H::Digest, bytes: Vec<u8>) -> io::Result<()> {
+        match self.index.get_mut().get(&Key(hash))? {
+            // the intact node has the same length, and is written in place
+            Some(entry) => {
+                if bytes.len() as u64 != entry.len() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "Repaired node differs in length",
+                    ));
+                }
+                let mut file =
+                    OpenOptions::new().write(true).open(&self.data_path)?;
+                file.seek(SeekFrom::Start(entry.offset()))?;
+                file.write_all(&bytes)?;
+                file.sync_data()
+            }
+            None => self.put(hash, bytes).map(|_| ()),
+        }
+    }
+
+    fn gc(
+        &mut self,
+        live: &HashSet<H::Digest>,
+        control: &mut Control<'_>,
+    ) -> io::Result<usize> {
+        self.flush()?;
+        let _lock = RootLock::acquire(self.dir.join(LOCK))?;
+        self.refresh()?;
+        let before = self.size();
+
+        // the live nodes are copied aside, and swapped in once complete
+        let compact = self.dir.join("compact");
+        if compact.exists() {
+            fs::remove_dir_all(&compact)?;
+        }
+        fs::create_dir_all(compact.join("index"))?;
+        let mut index = Index::new(&compact.join("index"))?;
+        let mut from = File::open(&self.data_path)?;
+        let mut to = BufWriter::new(File::create(compact.join("data"))?);
+        // copied in the order they were written, keeping nodes written
+        // together close to each other
+        let mut entries = vec![];
+        for digest in live {
+            if let Some(entry) = self.index.get_mut().get(&Key(*digest))? {
+                entries.push((entry, *digest));
+            }
+        }
+        entries.sort_by_key(|(entry, _)| entry.offset());
+        control.set_total(control.done() + entries.len() as u64);
+
+        let mut offset = 0;
+        let mut bytes = vec![];
+        for (entry, digest) in entries {
+            from.seek(SeekFrom::Start(entry.offset()))?;
+            bytes.resize(entry.len() as usize, 0);
+            from.read_exact(&mut bytes)?;
+            to.write_all(&bytes)?;
+            index.insert(Key(digest), Entry::new(offset, entry.len()))?;
+            offset += entry.len();
+            control.advance(1)?;
+        }
+        to.into_inner()?.sync_all()?;
+        index.flush()?;
+        drop(index);
+        fs::write(compact.join("done"), [])?;
+
+        self.mmap = None;
+        finish_compaction(&self.dir)?;
+        *self.index.get_mut() = Index::new(&self.dir.join("index"))?;
+        *self.indexed.get_mut() = offset;
+        self.data = OpenOptions::new().write(true).open(&self.data_path)?;
+        self.data.seek(SeekFrom::End(0))?;
+        self.data_offset = offset;
+        if self.mmapped {
+            self.remap()?;
+        }
+        Ok(before.saturating_sub(self.size()))
     }
 
     fn size(&self) -> usize {
-        self.index.on_disk_size() + self.data_offset as usize
+        // saturate rather than wrap on 32-bit targets
+        let data = if self.data_offset > usize::MAX as u64 {
+            usize::MAX
+        } else {
+            self.data_offset as usize
+        };
+        let pending = if self.pending_len > usize::MAX as u64 {
+            usize::MAX
+        } else {
+            self.pending_len as usize
+        };
+        self.index
+            .read()
+            .on_disk_size()
+            .saturating_add(data)
+            .saturating_add(pending)
+    }
+
+    fn path(&self) -> Option<&Path> {
+        Some(&self.dir)
+    }
+
+    fn relocatable(&self) -> bool {
+        true
+    }
+}
+
+impl<H: ByteHash> Drop for DiskBackend<H> {
+    fn drop(&mut self) {
+        // a backend dropped cleanly commits what was written to it, errors
+        // leave the commit to be dropped on recovery
+        let _ = self.flush();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::mem;
+
+    use super::*;
+    use crate::tests::tempfile::tempdir;
+    use crate::Blake2b;
+
+    #[test]
+    fn nested_directories() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("a").join("b");
+        DiskBackend::<Blake2b>::new(&path).unwrap();
+        assert!(path.join("index").exists());
+    }
+
+    #[test]
+    fn mmap_reads() {
+        let dir = tempdir().unwrap();
+        let mut backend =
+            DiskBackend::<Blake2b>::open_mmap(dir.path()).unwrap();
+        let read = |backend: &DiskBackend<Blake2b>, hash| {
+            let mut bytes = [0u8; 4];
+            backend.get(&hash).unwrap().read_exact(&mut bytes).unwrap();
+            bytes
+        };
+
+        backend.put([1; 32], vec![1, 1, 1, 1]).unwrap();
+        // not mapped until flushed
+        assert!(backend.mmap.is_none());
+        assert_eq!(read(&backend, [1; 32]), [1, 1, 1, 1]);
+
+        backend.flush().unwrap();
+        backend.put([2; 32], vec![2, 2, 2, 2]).unwrap();
+        assert_eq!(backend.mmap.as_ref().unwrap().len(), 4);
+        assert_eq!(read(&backend, [1; 32]), [1, 1, 1, 1]);
+        assert_eq!(read(&backend, [2; 32]), [2, 2, 2, 2]);
+        backend.flush().unwrap();
+        drop(backend);
+
+        let backend = DiskBackend::<Blake2b>::open_mmap(dir.path()).unwrap();
+        assert_eq!(backend.mmap.as_ref().unwrap().len(), 8);
+        assert_eq!(read(&backend, [2; 32]), [2, 2, 2, 2]);
+        assert!(backend.get(&[3; 32]).is_err());
+    }
+
+    #[test]
+    fn wal_recovery() {
+        let dir = tempdir().unwrap();
+        let mut backend = DiskBackend::<Blake2b>::new(dir.path()).unwrap();
+        backend.put([1; 32], vec![1, 1, 1, 1]).unwrap();
+        backend.flush().unwrap();
+
+        // crash before the commit marker, the node is dropped
+        backend.put([2; 32], vec![2, 2, 2, 2]).unwrap();
+        mem::forget(backend);
+        let mut backend = DiskBackend::<Blake2b>::new(dir.path()).unwrap();
+        assert!(backend.get(&[2; 32]).is_err());
+        assert_eq!(backend.data_offset, 4);
+
+        // crash after the commit marker, the node is replayed
+        backend.put([3; 32], vec![3, 3, 3, 3]).unwrap();
+        backend.log().unwrap();
+        // followed by a torn write
+        backend.wal.write_all(&[WAL_NODE, 4, 4]).unwrap();
+        mem::forget(backend);
+        let mut backend = DiskBackend::<Blake2b>::new(dir.path()).unwrap();
+        let mut bytes = [0u8; 4];
+        backend
+            .get(&[3; 32])
+            .unwrap()
+            .read_exact(&mut bytes)
+            .unwrap();
+        assert_eq!(bytes, [3, 3, 3, 3]);
+        assert_eq!(backend.data_offset, 8);
+        assert_eq!(fs::metadata(dir.path().join("wal")).unwrap().len(), 0);
+        assert_eq!(backend.recover().unwrap(), 0);
+    }
+
+    #[test]
+    fn shared_between_backends() {
+        let dir = tempdir().unwrap();
+        let mut a = DiskBackend::<Blake2b>::new(dir.path()).unwrap();
+        let mut b = DiskBackend::<Blake2b>::new(dir.path()).unwrap();
+        let read = |backend: &DiskBackend<Blake2b>, hash| {
+            let mut bytes = [0u8; 4];
+            backend.get(&hash).unwrap().read_exact(&mut bytes).unwrap();
+            bytes
+        };
+
+        // written by each, in turns
+        a.put([1; 32], vec![1; 4]).unwrap();
+        a.flush().unwrap();
+        assert_eq!(read(&b, [1; 32]), [1; 4]);
+        b.put([2; 32], vec![2; 4]).unwrap();
+        a.put([3; 32], vec![3; 4]).unwrap();
+        b.flush().unwrap();
+        a.flush().unwrap();
+
+        for backend in &[&a, &b] {
+            for i in 1..4 {
+                assert_eq!(read(backend, [i; 32]), [i; 4]);
+            }
+        }
+        drop((a, b));
+        let backend = DiskBackend::<Blake2b>::new(dir.path()).unwrap();
+        assert_eq!(backend.data_offset, 12);
+        assert_eq!(read(&backend, [2; 32]), [2; 4]);
+    }
+
+    #[test]
+    fn gc_compaction() {
+        let dir = tempdir().unwrap();
+        let mut backend = DiskBackend::<Blake2b>::new(dir.path()).unwrap();
+        for i in 0..4 {
+            backend.put([i; 32], vec![i; 4]).unwrap();
+        }
+        let mut live = HashSet::new();
+        live.insert([1; 32]);
+        live.insert([3; 32]);
+        assert!(backend.gc(&live, &mut Control::none()).unwrap() > 0);
+        assert_eq!(backend.data_offset, 8);
+
+        let mut bytes = [0u8; 4];
+        for i in 0..4 {
+            match backend.get(&[i; 32]) {
+                Ok(mut read) => {
+                    read.read_exact(&mut bytes).unwrap();
+                    assert_eq!(bytes, [i; 4]);
+                    assert!(live.contains(&[i; 32]))
+                }
+                Err(_) => assert!(!live.contains(&[i; 32])),
+            }
+        }
+
+        // a compaction interrupted before completion is discarded
+        fs::create_dir_all(dir.path().join("compact").join("index")).unwrap();
+        drop(backend);
+        let backend = DiskBackend::<Blake2b>::new(dir.path()).unwrap();
+        assert!(!dir.path().join("compact").exists());
+        assert!(backend.get(&[3; 32]).is_ok());
+    }
+
+    #[test]
+    fn incompatible_layout() {
+        let dir = tempdir().unwrap();
+        let mut backend = DiskBackend::<Blake2b>::new(dir.path()).unwrap();
+        backend.put([1; 32], vec![1, 2, 3]).unwrap();
+        backend.flush().unwrap();
+        drop(backend);
+        assert_eq!(fs::read_to_string(dir.path().join("layout")).unwrap(), "3");
+        DiskBackend::<Blake2b>::new(dir.path()).unwrap();
+
+        // written in the platform dependent layout of earlier versions
+        fs::write(dir.path().join("layout"), "le64-2").unwrap();
+        let err = DiskBackend::<Blake2b>::new(dir.path()).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // or with its layout lost, not to be taken for a baseline store
+        fs::remove_file(dir.path().join("layout")).unwrap();
+        let err = DiskBackend::<Blake2b>::new(dir.path()).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(!dir.path().join("layout").exists());
+    }
+
+    // Writes `nodes` as the baseline backend did, indexing the offset of
+    // each node before appending it to the data file
+    fn write_baseline(dir: &Path, nodes: &[&[u8]]) -> Vec<[u8; 32]> {
+        fs::create_dir_all(dir.join("index")).unwrap();
+        let mut index =
+            Index::<[u8; 32], u64>::new(&dir.join("index")).unwrap();
+        let mut data = File::create(dir.join("data")).unwrap();
+        let mut offset = 0;
+        let mut digests = vec![];
+        for bytes in nodes {
+            let digest = hash::<Blake2b>(bytes);
+            if !index.insert(digest, offset).unwrap() {
+                data.write_all(bytes).unwrap();
+                offset += bytes.len() as u64;
+            }
+            digests.push(digest);
+        }
+        index.flush().unwrap();
+        digests
+    }
+
+    #[test]
+    fn baseline_stores_migrate() {
+        let dir = tempdir().unwrap();
+        let nodes: &[&[u8]] = &[
+            &42u64.to_be_bytes(),
+            &[],
+            &[1, 2, 3],
+            &[1, 2, 3],
+            &[0xce, 9, 9],
+        ];
+        let digests = write_baseline(dir.path(), nodes);
+
+        let backend = DiskBackend::<Blake2b>::new(dir.path()).unwrap();
+        assert_eq!(fs::read_to_string(dir.path().join("layout")).unwrap(), "3");
+        assert!(!dir.path().join("compact").exists());
+        for (digest, bytes) in digests.iter().zip(nodes) {
+            // bounded to the length of each node
+            let mut read = vec![];
+            backend.get(digest).unwrap().read_to_end(&mut read).unwrap();
+            assert_eq!(&read[..], *bytes);
+        }
+        drop(backend);
+
+        // headerless nodes are read as written before format versions
+        let store = crate::Store::<Blake2b>::new(dir.path()).unwrap();
+        store.set_legacy_reads(true);
+        let value = store.snapshot::<u64>(&digests[0]).restore().unwrap();
+        assert_eq!(value, 42);
+    }
+
+    #[test]
+    fn portable_index_entries() {
+        // the same bytes on every platform
+        let entry = Entry::new(0x0102, 3);
+        assert_eq!(entry.0, [0, 0, 0, 0, 0, 0, 1, 2, 0, 0, 0, 0, 0, 0, 0, 3]);
+        assert_eq!((entry.offset(), entry.len()), (0x0102, 3));
     }
 }
@@ -1,9 +1,10 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io::{self, Cursor, Read};
 
 use bytehash::ByteHash;
 
 use crate::backend::{Backend, PutResult};
+use crate::control::Control;
 
 type ByteMap<D> = HashMap<D, Vec<u8>>;
 
@@ -23,6 +24,12 @@ impl<H: ByteHash> MemBackend<H> {
     }
 }
 
+impl<H: ByteHash> Default for MemBackend<H> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<H: ByteHash> Backend<H> for MemBackend<H> {
     fn get<'a>(&'a self, hash: &H::Digest) -> io::Result<Box<dyn Read + 'a>> {
         if let Some(data) = self.data.get(hash) {
@@ -48,6 +55,25 @@ impl<H: ByteHash> Backend<H> for MemBackend<H> {
         Ok(())
     }
 
+    fn gc(
+        &mut self,
+        live: &HashSet<H::Digest>,
+        control: &mut Control<'_>,
+    ) -> io::Result<usize> {
+        control.check()?;
+        let before = self.size;
+        let mut size = 0;
+        self.data.retain(|digest, bytes| {
+            let keep = live.contains(digest);
+            if keep {
+                size += bytes.len()
+            }
+            keep
+        });
+        self.size = size;
+        Ok(before.saturating_sub(size))
+    }
+
     fn size(&self) -> usize {
         self.size
     }
@@ -1,8 +1,21 @@
+use std::collections::HashSet;
 use std::io::{self, Read};
+use std::path::Path;
 
 use bytehash::ByteHash;
 
+use crate::control::Control;
+
+mod cached;
+#[cfg(feature = "compression")]
+mod compressed;
 mod mem;
+#[cfg(feature = "async")]
+mod nonblocking;
+mod object;
+mod remote;
+mod roots;
+mod tiered;
 
 #[cfg(feature = "filesystem")]
 mod disk;
@@ -14,17 +27,43 @@ pub use self::localstorage::WebBackend as Persistant;
 
 #[cfg(feature = "filesystem")]
 pub use disk::DiskBackend as Persistant;
+#[cfg(feature = "filesystem")]
+pub use disk::DiskBackend;
 
+pub use self::cached::{CacheControl, CachedBackend};
+#[cfg(feature = "compression")]
+pub use self::compressed::{Codec, CompressedBackend, CompressionPolicy};
 pub use self::mem::MemBackend as Ephemeral;
+pub use self::mem::MemBackend;
+#[cfg(feature = "async")]
+pub use self::nonblocking::AsyncBackend;
+#[cfg(feature = "async")]
+pub(crate) use self::nonblocking::{Fetched, FetchedBackend};
+#[cfg(feature = "filesystem")]
+pub use self::object::DirObjectStore;
+pub use self::object::{ObjectBackend, ObjectStore};
+pub use self::remote::{Fetch, HttpFetch, RemoteBackend};
+pub use self::roots::{DirRoots, MemRoots, RootStore, RootUpdate};
+pub use self::tiered::TieredBackend;
+
+// Encodes `bytes` as lowercase hexadecimal, for keys and paths
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
 
+/// The outcome of putting a value in a backend
 pub enum PutResult {
+    /// The value was written
     Ok,
+    /// The value was already present, and left as is
     AlreadyThere,
 }
 
 /// Trait to implement custom backends
 pub trait Backend<H: ByteHash> {
     /// Get a reader from a hash
+    ///
+    /// The reader yields exactly the bytes put for the hash, and ends there.
     fn get<'a>(&'a self, digest: &H::Digest) -> io::Result<Box<dyn Read + 'a>>;
 
     /// Put the serialized value in the backend.
@@ -37,8 +76,63 @@ pub trait Backend<H: ByteHash> {
     /// Flush changes to underlying medium
     fn flush(&mut self) -> io::Result<()>;
 
+    /// Replaces the value of `digest`, found missing or corrupt, with the
+    /// intact `bytes`
+    ///
+    /// Puts the value again by default, backends that never overwrite
+    /// values override this.
+    fn repair(&mut self, digest: H::Digest, bytes: Vec<u8>) -> io::Result<()> {
+        self.put(digest, bytes).map(|_| ())
+    }
+
+    /// Deletes every node but the `live` ones, returning the number of bytes
+    /// reclaimed
+    ///
+    /// Live nodes missing from the backend are ignored. Backends copying
+    /// the live nodes report every node copied to `control`, and leave their
+    /// storage as it was when cancelled. Fails with an `Unsupported` error
+    /// by default, see `Store::gc`.
+    fn gc(
+        &mut self,
+        live: &HashSet<H::Digest>,
+        control: &mut Control<'_>,
+    ) -> io::Result<usize> {
+        let _ = (live, control);
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Backend does not support garbage collection",
+        ))
+    }
+
+    /// Returns true if reading `digest` may be slow, such as a read over
+    /// the network not served from a local copy
+    ///
+    /// Concurrent reads of a node that may be slow are coalesced into a
+    /// single read by the store, false by default.
+    fn slow(&self, digest: &H::Digest) -> bool {
+        let _ = digest;
+        false
+    }
+
     /// Return approximate size in bytes (optional)
     fn size(&self) -> usize {
         0
     }
+
+    /// Return the directory holding the data, if any
+    ///
+    /// The directory belongs to the store, which keeps its roots in it, and
+    /// removes it when relocated, so backends sharing their directory with
+    /// other data return `None`.
+    fn path(&self) -> Option<&Path> {
+        None
+    }
+
+    /// Returns true if the store can be moved by copying the directory of
+    /// the backend, and opening the copy as a `DiskBackend`
+    ///
+    /// Only true for `DiskBackend` itself, see `Store::relocate`.
+    fn relocatable(&self) -> bool {
+        false
+    }
 }
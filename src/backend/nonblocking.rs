@@ -0,0 +1,139 @@
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::io::{self, Cursor, Read};
+use std::sync::Arc;
+
+use bytehash::ByteHash;
+use futures::future::BoxFuture;
+use parking_lot::Mutex;
+
+use crate::backend::{Backend, PutResult};
+
+/// Trait to implement backends that are read and written asynchronously,
+/// such as remote or network backends
+///
+/// Used through an `AsyncStore`, which awaits the nodes missing from memory
+/// instead of blocking the thread of the runtime on them.
+pub trait AsyncBackend<H: ByteHash>: Send + Sync {
+    /// Gets the serialized value with digest `digest`
+    fn get<'a>(
+        &'a self,
+        digest: &'a H::Digest,
+    ) -> BoxFuture<'a, io::Result<Vec<u8>>>;
+
+    /// Puts the serialized value in the backend
+    fn put<'a>(
+        &'a self,
+        digest: H::Digest,
+        bytes: Vec<u8>,
+    ) -> BoxFuture<'a, io::Result<PutResult>>;
+
+    /// Flushes changes to the underlying medium
+    fn flush(&self) -> BoxFuture<'_, io::Result<()>>;
+}
+
+// The nodes fetched from an async backend, and written but not yet flushed
+// to it
+pub(crate) struct Fetched<D> {
+    nodes: HashMap<D, Arc<[u8]>>,
+    // the nodes that can be evicted, oldest first, all but the unflushed
+    evictable: VecDeque<D>,
+    capacity: usize,
+    unflushed: Vec<D>,
+    missing: Vec<D>,
+}
+
+// The synchronous side of an `AsyncStore`, serving the nodes already
+// fetched, and recording the ones missing so they can be awaited before
+// retrying
+pub(crate) struct FetchedBackend<H: ByteHash>(
+    pub(crate) Arc<Mutex<Fetched<H::Digest>>>,
+);
+
+impl<D: Copy + Eq + Hash> Fetched<D> {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Fetched {
+            nodes: HashMap::new(),
+            evictable: VecDeque::new(),
+            capacity,
+            unflushed: vec![],
+            missing: vec![],
+        }
+    }
+
+    pub(crate) fn insert(&mut self, digest: D, bytes: Vec<u8>) {
+        if self.nodes.insert(digest, bytes.into()).is_none() {
+            self.evictable.push_back(digest);
+            self.evict();
+        }
+    }
+
+    // Drops the oldest nodes over capacity, to be fetched again if needed
+    fn evict(&mut self) {
+        while self.nodes.len() > self.capacity {
+            match self.evictable.pop_front() {
+                Some(digest) => self.nodes.remove(&digest),
+                None => break,
+            };
+        }
+    }
+
+    pub(crate) fn take_missing(&mut self) -> Vec<D> {
+        let mut missing = std::mem::take(&mut self.missing);
+        missing.retain(|digest| !self.nodes.contains_key(digest));
+        missing
+    }
+
+    pub(crate) fn take_unflushed(&mut self) -> Vec<(D, Arc<[u8]>)> {
+        let nodes = &self.nodes;
+        let unflushed: Vec<_> = self
+            .unflushed
+            .drain(..)
+            .filter_map(|digest| {
+                nodes.get(&digest).map(|bytes| (digest, bytes.clone()))
+            })
+            .collect();
+        // written to the async backend, and read back from it if evicted
+        self.evictable
+            .extend(unflushed.iter().map(|(digest, _)| *digest));
+        self.evict();
+        unflushed
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.nodes.len()
+    }
+}
+
+impl<H: ByteHash> Backend<H> for FetchedBackend<H> {
+    fn get<'a>(&'a self, hash: &H::Digest) -> io::Result<Box<dyn Read + 'a>> {
+        let mut fetched = self.0.lock();
+        if let Some(bytes) = fetched.nodes.get(hash) {
+            return Ok(Box::new(Cursor::new(bytes.clone())));
+        }
+        fetched.missing.push(*hash);
+        Err(io::Error::new(
+            io::ErrorKind::WouldBlock,
+            "Node not fetched yet",
+        ))
+    }
+
+    fn put(
+        &mut self,
+        hash: H::Digest,
+        bytes: Vec<u8>,
+    ) -> io::Result<PutResult> {
+        let mut fetched = self.0.lock();
+        if fetched.nodes.contains_key(&hash) {
+            return Ok(PutResult::AlreadyThere);
+        }
+        fetched.nodes.insert(hash, bytes.into());
+        fetched.unflushed.push(hash);
+        Ok(PutResult::Ok)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        // written to the async backend by `AsyncStore::flush`
+        Ok(())
+    }
+}
@@ -0,0 +1,148 @@
+#[cfg(feature = "filesystem")]
+use std::io::Write;
+use std::io::{self, Cursor, Read};
+#[cfg(feature = "filesystem")]
+use std::path::PathBuf;
+use std::sync::Arc;
+
+#[cfg(feature = "filesystem")]
+use atomicwrites::{AllowOverwrite, AtomicFile};
+use bytehash::ByteHash;
+
+use crate::backend::{hex, Backend, PutResult};
+use crate::partition::Partition;
+
+/// An object store, such as S3, GCS or MinIO, holding objects by key
+///
+/// Implemented by clients of the actual services, for use with
+/// `ObjectBackend`.
+pub trait ObjectStore: Send {
+    /// Returns the object at `key`, if any
+    fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>>;
+
+    /// Writes the object at `key`, replacing any previous one
+    fn put(&self, key: &str, bytes: &[u8]) -> io::Result<()>;
+
+    /// Returns true if there is an object at `key`
+    ///
+    /// Gets the object by default, services with cheaper existence checks
+    /// override this.
+    fn contains(&self, key: &str) -> io::Result<bool> {
+        Ok(self.get(key)?.is_some())
+    }
+}
+
+/// An object store keeping its objects as files in a local directory
+///
+/// Useful for tests, and for buckets mounted as a filesystem.
+#[cfg(feature = "filesystem")]
+pub struct DirObjectStore(PathBuf);
+
+#[cfg(feature = "filesystem")]
+impl DirObjectStore {
+    /// Keeps objects in the directory at `path`, created if necessary
+    pub fn new<P: Into<PathBuf>>(path: P) -> io::Result<Self> {
+        let path = path.into();
+        std::fs::create_dir_all(&path)?;
+        Ok(DirObjectStore(path))
+    }
+}
+
+#[cfg(feature = "filesystem")]
+impl ObjectStore for DirObjectStore {
+    fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        match std::fs::read(self.0.join(key)) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn put(&self, key: &str, bytes: &[u8]) -> io::Result<()> {
+        let af = AtomicFile::new(self.0.join(key), AllowOverwrite);
+        af.write(|f| f.write_all(bytes))?;
+        Ok(())
+    }
+
+    fn contains(&self, key: &str) -> io::Result<bool> {
+        Ok(self.0.join(key).exists())
+    }
+}
+
+/// A backend storing every node as an object, keyed by its hex digest
+///
+/// Nodes read are kept in a local cache, limited to a budget in bytes, so
+/// that traversals do not fetch the upper levels of a tree over and over.
+pub struct ObjectBackend<H: ByteHash, O> {
+    objects: O,
+    prefix: String,
+    cache: Partition<H::Digest>,
+}
+
+impl<H: ByteHash, O: ObjectStore> ObjectBackend<H, O> {
+    /// Creates a backend storing nodes in `objects`, caching up to
+    /// `cache_budget` bytes of nodes locally
+    pub fn new(objects: O, cache_budget: usize) -> Self {
+        ObjectBackend {
+            objects,
+            prefix: String::new(),
+            cache: Partition::new("objects", cache_budget),
+        }
+    }
+
+    /// Prefixes the keys of all nodes with `prefix`, such as a folder in a
+    /// bucket shared with other data
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Returns the number of bytes cached locally
+    pub fn cached(&self) -> usize {
+        self.cache.size()
+    }
+
+    fn key(&self, digest: &H::Digest) -> String {
+        format!("{}{}", self.prefix, hex(digest.as_ref()))
+    }
+}
+
+impl<H: ByteHash, O: ObjectStore> Backend<H> for ObjectBackend<H, O> {
+    fn get<'a>(&'a self, hash: &H::Digest) -> io::Result<Box<dyn Read + 'a>> {
+        if let Some(bytes) = self.cache.get(hash) {
+            return Ok(Box::new(Cursor::new(bytes)));
+        }
+        match self.objects.get(&self.key(hash))? {
+            Some(bytes) => {
+                let bytes: Arc<[u8]> = bytes.into();
+                self.cache.insert(*hash, bytes.clone());
+                Ok(Box::new(Cursor::new(bytes)))
+            }
+            None => {
+                Err(io::Error::new(io::ErrorKind::NotFound, "Data not found"))
+            }
+        }
+    }
+
+    fn put(
+        &mut self,
+        hash: H::Digest,
+        bytes: Vec<u8>,
+    ) -> io::Result<PutResult> {
+        let key = self.key(&hash);
+        if self.cache.contains(&hash) || self.objects.contains(&key)? {
+            return Ok(PutResult::AlreadyThere);
+        }
+        self.objects.put(&key, &bytes)?;
+        Ok(PutResult::Ok)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        // every object is written as it is put
+        Ok(())
+    }
+
+    fn slow(&self, hash: &H::Digest) -> bool {
+        !self.cache.contains(hash)
+    }
+}
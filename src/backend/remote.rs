@@ -0,0 +1,438 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::Hasher;
+use std::io::{self, BufRead, BufReader, Cursor, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytehash::{ByteHash, State};
+use parking_lot::Mutex;
+
+use crate::backend::{hex, Backend, PutResult};
+use crate::control::Control;
+
+/// A source of nodes by digest, such as a remote peer
+pub trait Fetch: Send {
+    /// Fetches the encoded node with digest `digest`, if available
+    fn fetch(&self, digest: &[u8]) -> io::Result<Option<Vec<u8>>>;
+}
+
+// How long a server may take to accept a connection, or to send or receive
+// any part of a request, by default
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+// The longest node fetched by default
+const MAX_LEN: usize = 1 << 28;
+
+// The longest status or header line of a response
+const MAX_LINE: u64 = 8 * 1024;
+
+/// Fetches nodes over HTTP, as `GET <path>/<hex digest>`
+///
+/// A `200` response carries the encoded node, a `404` response means the
+/// node is not available. Bodies may be sent with a `Content-Length` or in
+/// chunks, and the connection is kept open for the next fetch unless the
+/// server closes it.
+pub struct HttpFetch {
+    addr: String,
+    path: String,
+    timeout: Duration,
+    max_len: usize,
+    // kept open between fetches
+    connection: Mutex<Option<BufReader<TcpStream>>>,
+}
+
+impl HttpFetch {
+    /// Fetches nodes from the server at `addr`, as `host:port`, below `path`
+    pub fn new(addr: &str, path: &str) -> Self {
+        HttpFetch {
+            addr: addr.into(),
+            path: path.trim_end_matches('/').into(),
+            timeout: TIMEOUT,
+            max_len: MAX_LEN,
+            connection: Mutex::new(None),
+        }
+    }
+
+    /// Sets how long the server may take to accept a connection, or to
+    /// send or receive any part of a request, 30 seconds by default
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the length in bytes of the longest node fetched, 256 MiB by
+    /// default
+    ///
+    /// Longer responses fail with an `InvalidData` error, before being read.
+    pub fn with_max_len(mut self, len: usize) -> Self {
+        self.max_len = len;
+        self
+    }
+
+    fn connect(&self) -> io::Result<BufReader<TcpStream>> {
+        let mut error = None;
+        for addr in self.addr.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, self.timeout) {
+                Ok(stream) => {
+                    stream.set_read_timeout(Some(self.timeout))?;
+                    stream.set_write_timeout(Some(self.timeout))?;
+                    return Ok(BufReader::new(stream));
+                }
+                Err(e) => error = Some(e),
+            }
+        }
+        Err(error.unwrap_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "No address to connect to")
+        }))
+    }
+
+    // Sends the request for `digest`, returning the status line of the
+    // response, or `None` if the connection was closed before it
+    fn request(
+        &self,
+        reader: &mut BufReader<TcpStream>,
+        digest: &[u8],
+    ) -> io::Result<Option<String>> {
+        let mut line = String::new();
+        let sent = write!(
+            reader.get_mut(),
+            "GET {}/{} HTTP/1.1\r\nHost: {}\r\n\r\n",
+            self.path,
+            hex(digest),
+            self.addr
+        );
+        match sent.and_then(|_| read_line(reader, &mut line)) {
+            Ok(0) => Ok(None),
+            Ok(_) => Ok(Some(line)),
+            Err(ref e) if closed(e) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    // Reads the rest of the response to a request, returning the node, and
+    // whether the connection can be used again
+    fn response(
+        &self,
+        reader: &mut BufReader<TcpStream>,
+        status: &str,
+    ) -> io::Result<(Option<Vec<u8>>, bool)> {
+        let mut status = status.split_whitespace();
+        let mut reusable = status.next() == Some("HTTP/1.1");
+        let found = match status.next() {
+            Some("200") => true,
+            Some("404") => false,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Unexpected HTTP response",
+                ))
+            }
+        };
+
+        let mut len = None;
+        let mut chunked = false;
+        let mut line = String::new();
+        loop {
+            line.clear();
+            read_line(reader, &mut line)?;
+            let header = line.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            let mut parts = header.splitn(2, ':');
+            let name = parts.next().unwrap_or("");
+            let value = parts.next().unwrap_or("").trim();
+            if name.eq_ignore_ascii_case("content-length") {
+                len = Some(value.parse::<u64>().map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "Invalid Content-Length",
+                    )
+                })?);
+            } else if name.eq_ignore_ascii_case("transfer-encoding") {
+                chunked = value.eq_ignore_ascii_case("chunked");
+            } else if name.eq_ignore_ascii_case("connection") {
+                reusable &= !value.eq_ignore_ascii_case("close");
+            }
+        }
+
+        let mut body = vec![];
+        if chunked {
+            self.read_chunks(reader, &mut body)?;
+        } else if let Some(len) = len {
+            self.read_body(reader, len, &mut body)?;
+        } else if found {
+            // the body ends with the connection
+            reusable = false;
+            let max = self.max_len as u64 + 1;
+            reader.take(max).read_to_end(&mut body)?;
+            if body.len() > self.max_len {
+                return Err(too_long());
+            }
+        } else {
+            reusable = false;
+        }
+        Ok((if found { Some(body) } else { None }, reusable))
+    }
+
+    // Reads `len` bytes of the body into `body`
+    fn read_body(
+        &self,
+        reader: &mut BufReader<TcpStream>,
+        len: u64,
+        body: &mut Vec<u8>,
+    ) -> io::Result<()> {
+        if body.len() as u64 + len > self.max_len as u64 {
+            return Err(too_long());
+        }
+        let read = reader.take(len).read_to_end(body)?;
+        if read as u64 != len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Truncated HTTP response",
+            ));
+        }
+        Ok(())
+    }
+
+    // Reads a body sent in chunks into `body`
+    fn read_chunks(
+        &self,
+        reader: &mut BufReader<TcpStream>,
+        body: &mut Vec<u8>,
+    ) -> io::Result<()> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            read_line(reader, &mut line)?;
+            // the size may be followed by extensions
+            let size = line.trim_end().split(';').next().unwrap_or("");
+            let size = u64::from_str_radix(size.trim(), 16).map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidData, "Invalid HTTP chunk")
+            })?;
+            if size == 0 {
+                break;
+            }
+            self.read_body(reader, size, body)?;
+            line.clear();
+            read_line(reader, &mut line)?;
+        }
+        // trailers, up to the empty line ending the response
+        loop {
+            line.clear();
+            if read_line(reader, &mut line)? == 0 || line.trim_end().is_empty()
+            {
+                return Ok(());
+            }
+        }
+    }
+}
+
+// Reads a line of a response, of bounded length
+fn read_line(
+    reader: &mut BufReader<TcpStream>,
+    line: &mut String,
+) -> io::Result<usize> {
+    let read = reader.take(MAX_LINE).read_line(line)?;
+    if read as u64 == MAX_LINE && !line.ends_with('\n') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "HTTP response line too long",
+        ));
+    }
+    Ok(read)
+}
+
+fn too_long() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "Fetched node too long")
+}
+
+// Returns true for the errors of a connection closed by the server
+fn closed(e: &io::Error) -> bool {
+    match e.kind() {
+        io::ErrorKind::BrokenPipe
+        | io::ErrorKind::ConnectionReset
+        | io::ErrorKind::ConnectionAborted
+        | io::ErrorKind::UnexpectedEof => true,
+        _ => false,
+    }
+}
+
+impl Fetch for HttpFetch {
+    fn fetch(&self, digest: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        let mut connection = self.connection.lock();
+        // the server may have closed a kept connection since, the request
+        // is then sent again on a new one
+        let mut reader = match connection.take() {
+            Some(mut reader) => match self.request(&mut reader, digest)? {
+                Some(status) => Some((reader, status)),
+                None => None,
+            },
+            None => None,
+        };
+        if reader.is_none() {
+            let mut fresh = self.connect()?;
+            match self.request(&mut fresh, digest)? {
+                Some(status) => reader = Some((fresh, status)),
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "Connection closed without a response",
+                    ))
+                }
+            }
+        }
+        let (mut reader, status) = reader.expect("connected above");
+        let (node, reusable) = self.response(&mut reader, &status)?;
+        if reusable {
+            *connection = Some(reader);
+        }
+        Ok(node)
+    }
+}
+
+/// A backend fetching the nodes missing locally from a remote source
+///
+/// Nodes are fetched lazily, as they are read, so a light node only pulls
+/// the subtrees it traverses. Every fetched node is checked against its
+/// digest, and written to the local backend on the next write or flush.
+/// Reads alone keep a bounded number of fetched nodes, the oldest ones are
+/// dropped and fetched again if read later.
+pub struct RemoteBackend<H: ByteHash, B, F> {
+    local: B,
+    remote: F,
+    fetched: Mutex<Fetched<H::Digest>>,
+}
+
+// The most fetched nodes kept between writes to the local backend
+const FETCHED: usize = 1 << 14;
+
+// Fetched nodes not yet written locally, in the order they were fetched
+struct Fetched<D> {
+    nodes: HashMap<D, Arc<[u8]>>,
+    order: VecDeque<D>,
+}
+
+impl<D: Eq + std::hash::Hash + Copy> Fetched<D> {
+    fn insert(&mut self, digest: D, bytes: Arc<[u8]>) {
+        if self.nodes.insert(digest, bytes).is_none() {
+            self.order.push_back(digest);
+        }
+        while self.order.len() > FETCHED {
+            if let Some(oldest) = self.order.pop_front() {
+                self.nodes.remove(&oldest);
+            }
+        }
+    }
+}
+
+impl<H, B, F> RemoteBackend<H, B, F>
+where
+    H: ByteHash,
+    B: Backend<H>,
+    F: Fetch,
+{
+    /// Creates a backend on top of `local`, fetching from `remote`
+    pub fn new(local: B, remote: F) -> Self {
+        RemoteBackend {
+            local,
+            remote,
+            fetched: Mutex::new(Fetched {
+                nodes: HashMap::new(),
+                order: VecDeque::new(),
+            }),
+        }
+    }
+
+    /// Returns the number of fetched nodes not yet written locally
+    pub fn fetched(&self) -> usize {
+        self.fetched.lock().nodes.len()
+    }
+
+    fn write_fetched(&mut self) -> io::Result<()> {
+        let fetched: Vec<_> = {
+            let mut fetched = self.fetched.lock();
+            fetched.order.clear();
+            fetched.nodes.drain().collect()
+        };
+        for (digest, bytes) in fetched {
+            self.local.put(digest, bytes.to_vec())?;
+        }
+        Ok(())
+    }
+}
+
+impl<H, B, F> Backend<H> for RemoteBackend<H, B, F>
+where
+    H: ByteHash,
+    B: Backend<H>,
+    F: Fetch,
+{
+    fn get<'a>(&'a self, hash: &H::Digest) -> io::Result<Box<dyn Read + 'a>> {
+        if let Ok(read) = self.local.get(hash) {
+            return Ok(read);
+        }
+        if let Some(bytes) = self.fetched.lock().nodes.get(hash) {
+            return Ok(Box::new(Cursor::new(bytes.clone())));
+        }
+        let bytes = match self.remote.fetch(hash.as_ref())? {
+            Some(bytes) => bytes,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "Data not found",
+                ))
+            }
+        };
+        let mut state = H::state();
+        state.write(&bytes);
+        if state.fin() != *hash {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Fetched node does not match its digest",
+            ));
+        }
+        let bytes: Arc<[u8]> = bytes.into();
+        self.fetched.lock().insert(*hash, bytes.clone());
+        Ok(Box::new(Cursor::new(bytes)))
+    }
+
+    fn put(
+        &mut self,
+        hash: H::Digest,
+        bytes: Vec<u8>,
+    ) -> io::Result<PutResult> {
+        self.write_fetched()?;
+        self.local.put(hash, bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_fetched()?;
+        self.local.flush()
+    }
+
+    fn slow(&self, hash: &H::Digest) -> bool {
+        // nodes in the local backend are not told apart without reading them
+        !self.fetched.lock().nodes.contains_key(hash)
+    }
+
+    fn gc(
+        &mut self,
+        live: &HashSet<H::Digest>,
+        control: &mut Control<'_>,
+    ) -> io::Result<usize> {
+        // the remote keeps its own nodes, only the local copies are deleted
+        self.write_fetched()?;
+        self.local.gc(live, control)
+    }
+
+    fn size(&self) -> usize {
+        self.local.size()
+    }
+
+    fn path(&self) -> Option<&Path> {
+        self.local.path()
+    }
+}
@@ -0,0 +1,472 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use atomicwrites::{AllowOverwrite, AtomicFile};
+use bytehash::ByteHash;
+use byteorder::{ReadBytesExt, WriteBytesExt};
+use parking_lot::{Mutex, RwLock};
+
+use crate::root::{RootConflict, RootLock};
+
+/// A registry of named roots, such as "state" or "mempool"
+///
+/// Keeps the digest each root is currently set to, along with the digests it
+/// was set to before. Updates are atomic, a root is either set to the new
+/// digest or left as is. The nodes of all current roots have to be marked
+/// live when collecting garbage, see `Store::gc`.
+pub trait RootStore<H: ByteHash> {
+    /// Returns the digest the root `name` is set to, if any
+    fn get(&self, name: &str) -> io::Result<Option<H::Digest>>;
+
+    /// Sets the root `name` to `digest`
+    fn set(&self, name: &str, digest: &H::Digest) -> io::Result<()>;
+
+    /// Sets the root `name` to `digest`, only if it is still set to
+    /// `expected` (`None` meaning the root is not set)
+    ///
+    /// Returns a `RootConflict` if another writer set the root first.
+    fn compare_and_set(
+        &self,
+        name: &str,
+        expected: Option<&H::Digest>,
+        digest: &H::Digest,
+    ) -> io::Result<Result<(), RootConflict<H>>>;
+
+    /// Applies all `updates` at once, only if every root is still set to
+    /// its expected digest
+    ///
+    /// Either all roots are set, or none is, and a `RootConflict` for the
+    /// first root found changed is returned.
+    fn commit(
+        &self,
+        updates: &[RootUpdate<H>],
+    ) -> io::Result<Result<(), RootConflict<H>>>;
+
+    /// Returns the digests the root `name` was set to, oldest first, ending
+    /// with the current one
+    fn history(&self, name: &str) -> io::Result<Vec<H::Digest>>;
+
+    /// Removes the root `name`, along with its history
+    fn remove(&self, name: &str) -> io::Result<()>;
+
+    /// Returns the names of all roots, in alphabetical order
+    fn names(&self) -> io::Result<Vec<String>>;
+}
+
+/// An update of a named root, see `RootStore::commit`
+#[derive(Clone)]
+pub struct RootUpdate<H: ByteHash> {
+    /// The name of the root
+    pub name: String,
+    /// The digest the root is expected to be set to, `None` if not set
+    pub expected: Option<H::Digest>,
+    /// The digest to set the root to
+    pub digest: H::Digest,
+}
+
+impl<H: ByteHash, R: RootStore<H>> RootStore<H> for &R {
+    fn get(&self, name: &str) -> io::Result<Option<H::Digest>> {
+        (**self).get(name)
+    }
+
+    fn set(&self, name: &str, digest: &H::Digest) -> io::Result<()> {
+        (**self).set(name, digest)
+    }
+
+    fn compare_and_set(
+        &self,
+        name: &str,
+        expected: Option<&H::Digest>,
+        digest: &H::Digest,
+    ) -> io::Result<Result<(), RootConflict<H>>> {
+        (**self).compare_and_set(name, expected, digest)
+    }
+
+    fn commit(
+        &self,
+        updates: &[RootUpdate<H>],
+    ) -> io::Result<Result<(), RootConflict<H>>> {
+        (**self).commit(updates)
+    }
+
+    fn history(&self, name: &str) -> io::Result<Vec<H::Digest>> {
+        (**self).history(name)
+    }
+
+    fn remove(&self, name: &str) -> io::Result<()> {
+        (**self).remove(name)
+    }
+
+    fn names(&self) -> io::Result<Vec<String>> {
+        (**self).names()
+    }
+}
+
+// Root names are used as file names, so they are kept to a safe subset
+fn check_name(name: &str) -> io::Result<()> {
+    let valid = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+    if name.is_empty() || name.len() > 255 || !name.chars().all(valid) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Root names may only contain up to 255 letters, digits, '-' and '_'",
+        ));
+    }
+    Ok(())
+}
+
+/// A registry of named roots kept in memory
+pub struct MemRoots<H: ByteHash>(Mutex<HashMap<String, Vec<H::Digest>>>);
+
+impl<H: ByteHash> MemRoots<H> {
+    /// Creates an empty registry
+    pub fn new() -> Self {
+        MemRoots(Mutex::new(HashMap::new()))
+    }
+}
+
+impl<H: ByteHash> Default for MemRoots<H> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<H: ByteHash> RootStore<H> for MemRoots<H> {
+    fn get(&self, name: &str) -> io::Result<Option<H::Digest>> {
+        Ok(self
+            .0
+            .lock()
+            .get(name)
+            .and_then(|history| history.last().cloned()))
+    }
+
+    fn set(&self, name: &str, digest: &H::Digest) -> io::Result<()> {
+        check_name(name)?;
+        self.0.lock().entry(name.into()).or_default().push(*digest);
+        Ok(())
+    }
+
+    fn compare_and_set(
+        &self,
+        name: &str,
+        expected: Option<&H::Digest>,
+        digest: &H::Digest,
+    ) -> io::Result<Result<(), RootConflict<H>>> {
+        check_name(name)?;
+        let mut roots = self.0.lock();
+        let history = roots.entry(name.into()).or_default();
+        let current = history.last().cloned();
+        if current.as_ref() != expected {
+            return Ok(Err(RootConflict::named(name, current)));
+        }
+        history.push(*digest);
+        Ok(Ok(()))
+    }
+
+    fn commit(
+        &self,
+        updates: &[RootUpdate<H>],
+    ) -> io::Result<Result<(), RootConflict<H>>> {
+        let mut roots = self.0.lock();
+        for update in updates {
+            check_name(&update.name)?;
+            let current = roots
+                .get(&update.name)
+                .and_then(|history| history.last().cloned());
+            if current != update.expected {
+                return Ok(Err(RootConflict::named(&update.name, current)));
+            }
+        }
+        for update in updates {
+            roots
+                .entry(update.name.clone())
+                .or_default()
+                .push(update.digest);
+        }
+        Ok(Ok(()))
+    }
+
+    fn history(&self, name: &str) -> io::Result<Vec<H::Digest>> {
+        Ok(self.0.lock().get(name).cloned().unwrap_or_default())
+    }
+
+    fn remove(&self, name: &str) -> io::Result<()> {
+        self.0.lock().remove(name);
+        Ok(())
+    }
+
+    fn names(&self) -> io::Result<Vec<String>> {
+        let mut names: Vec<_> = self
+            .0
+            .lock()
+            .iter()
+            .filter(|(_, history)| !history.is_empty())
+            .map(|(name, _)| name.clone())
+            .collect();
+        names.sort();
+        Ok(names)
+    }
+}
+
+/// A registry of named roots kept in a directory, one file per root
+///
+/// Each file is a log of the digests the root was set to, appended to and
+/// synced under an advisory lock, so that processes sharing the directory see
+/// every update whole. A digest cut short by a crash is ignored, and
+/// overwritten by the next update. Commits of several roots are first
+/// recorded in a file of their own, which is read over the logs until all
+/// of them are appended to, and replayed after a crash.
+///
+/// Digests have a fixed size, so the current digest of a root is read from
+/// the end of its log, and updates only read that far, however long the
+/// history.
+pub struct DirRoots<H> {
+    // shared with the store the registry belongs to, if any, which moves it
+    // when relocated, see `Store::relocate`
+    dir: Arc<RwLock<PathBuf>>,
+    _marker: PhantomData<H>,
+}
+
+impl<H: ByteHash> DirRoots<H> {
+    /// Keeps roots in the directory at `path`, created if necessary
+    pub fn open<P: Into<PathBuf>>(path: P) -> io::Result<Self> {
+        Self::shared(Arc::new(RwLock::new(path.into())))
+    }
+
+    // Keeps roots in the directory at `dir`, moved by whoever shares it
+    // only while holding it for writing
+    pub(crate) fn shared(dir: Arc<RwLock<PathBuf>>) -> io::Result<Self> {
+        fs::create_dir_all(&*dir.read())?;
+        let roots = DirRoots {
+            dir,
+            _marker: PhantomData,
+        };
+        let _lock = roots.lock()?;
+        Ok(roots)
+    }
+
+    // Returns the path of the file `name` in the directory
+    fn file(&self, name: &str) -> PathBuf {
+        self.dir.read_recursive().join(name)
+    }
+
+    // Locks the registry for writing, completing any interrupted commit
+    fn lock(&self) -> io::Result<RootLock> {
+        let lock = RootLock::acquire(self.file(".lock"))?;
+        let pending = self.pending()?;
+        if !pending.is_empty() {
+            for (name, digest) in pending {
+                let (complete, current) = self.last(&name)?;
+                if current != Some(digest) {
+                    self.append(&name, complete, &digest)?
+                }
+            }
+            fs::remove_file(self.file(".commit"))?;
+        }
+        Ok(lock)
+    }
+
+    // Reads the roots of the commit being applied, if any
+    fn pending(&self) -> io::Result<Vec<(String, H::Digest)>> {
+        let bytes = match fs::read(self.file(".commit")) {
+            Ok(bytes) => bytes,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                return Ok(vec![])
+            }
+            Err(e) => return Err(e),
+        };
+        let mut bytes = &bytes[..];
+        let mut pending = vec![];
+        while !bytes.is_empty() {
+            let len = bytes.read_u8()? as usize;
+            let mut name = vec![0; len];
+            bytes.read_exact(&mut name)?;
+            let name = String::from_utf8(name).map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidData, "Invalid root name")
+            })?;
+            let mut digest = H::Digest::default();
+            bytes.read_exact(digest.as_mut())?;
+            pending.push((name, digest));
+        }
+        Ok(pending)
+    }
+
+    // Reads the complete digests of the log of `name`
+    fn log(&self, name: &str) -> io::Result<Vec<H::Digest>> {
+        check_name(name)?;
+        let bytes = match fs::read(self.file(name)) {
+            Ok(bytes) => bytes,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e),
+        };
+        let len = H::Digest::default().as_ref().len();
+        Ok(bytes
+            .chunks_exact(len)
+            .map(|chunk| {
+                let mut digest = H::Digest::default();
+                digest.as_mut().copy_from_slice(chunk);
+                digest
+            })
+            .collect())
+    }
+
+    // Reads the number of complete digests in the log of `name`, and the
+    // last one, without reading the ones before
+    fn last(&self, name: &str) -> io::Result<(usize, Option<H::Digest>)> {
+        check_name(name)?;
+        let mut file = match File::open(self.file(name)) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                return Ok((0, None))
+            }
+            Err(e) => return Err(e),
+        };
+        let len = H::Digest::default().as_ref().len() as u64;
+        let complete = file.metadata()?.len() / len;
+        if complete == 0 {
+            return Ok((0, None));
+        }
+        file.seek(SeekFrom::Start((complete - 1) * len))?;
+        let mut digest = H::Digest::default();
+        file.read_exact(digest.as_mut())?;
+        Ok((complete as usize, Some(digest)))
+    }
+
+    // Reads the log of `name`, as of the commit being applied, if any
+    fn read(&self, name: &str) -> io::Result<Vec<H::Digest>> {
+        let mut history = self.log(name)?;
+        for (pending, digest) in self.pending()? {
+            if pending == name && history.last() != Some(&digest) {
+                history.push(digest)
+            }
+        }
+        Ok(history)
+    }
+
+    // Appends `digest` to the log of `name`, the lock has to be held
+    fn append(
+        &self,
+        name: &str,
+        complete: usize,
+        digest: &H::Digest,
+    ) -> io::Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.file(name))?;
+        // drops a digest cut short, if any
+        let len = digest.as_ref().len();
+        file.set_len((complete * len) as u64)?;
+        file.write_all(digest.as_ref())?;
+        file.sync_data()
+    }
+}
+
+impl<H: ByteHash> RootStore<H> for DirRoots<H> {
+    fn get(&self, name: &str) -> io::Result<Option<H::Digest>> {
+        let _dir = self.dir.read_recursive();
+        let (_, mut current) = self.last(name)?;
+        for (pending, digest) in self.pending()? {
+            if pending == name {
+                current = Some(digest)
+            }
+        }
+        Ok(current)
+    }
+
+    fn set(&self, name: &str, digest: &H::Digest) -> io::Result<()> {
+        let _dir = self.dir.read_recursive();
+        check_name(name)?;
+        let _lock = self.lock()?;
+        let (complete, _) = self.last(name)?;
+        self.append(name, complete, digest)
+    }
+
+    fn compare_and_set(
+        &self,
+        name: &str,
+        expected: Option<&H::Digest>,
+        digest: &H::Digest,
+    ) -> io::Result<Result<(), RootConflict<H>>> {
+        let _dir = self.dir.read_recursive();
+        check_name(name)?;
+        let _lock = self.lock()?;
+        let (complete, current) = self.last(name)?;
+        if current.as_ref() != expected {
+            return Ok(Err(RootConflict::named(name, current)));
+        }
+        self.append(name, complete, digest).map(Ok)
+    }
+
+    fn commit(
+        &self,
+        updates: &[RootUpdate<H>],
+    ) -> io::Result<Result<(), RootConflict<H>>> {
+        let _dir = self.dir.read_recursive();
+        for update in updates {
+            check_name(&update.name)?;
+        }
+        let _lock = self.lock()?;
+        for update in updates {
+            let (_, current) = self.last(&update.name)?;
+            if current != update.expected {
+                return Ok(Err(RootConflict::named(&update.name, current)));
+            }
+        }
+
+        // the commit is complete once recorded
+        let af = AtomicFile::new(self.file(".commit"), AllowOverwrite);
+        af.write(|f| {
+            for update in updates {
+                f.write_u8(update.name.len() as u8)?;
+                f.write_all(update.name.as_bytes())?;
+                f.write_all(update.digest.as_ref())?;
+            }
+            Ok::<_, io::Error>(())
+        })?;
+        for update in updates {
+            let (complete, _) = self.last(&update.name)?;
+            self.append(&update.name, complete, &update.digest)?;
+        }
+        fs::remove_file(self.file(".commit"))?;
+        Ok(Ok(()))
+    }
+
+    fn history(&self, name: &str) -> io::Result<Vec<H::Digest>> {
+        let _dir = self.dir.read_recursive();
+        self.read(name)
+    }
+
+    fn remove(&self, name: &str) -> io::Result<()> {
+        let _dir = self.dir.read_recursive();
+        check_name(name)?;
+        let _lock = self.lock()?;
+        match fs::remove_file(self.file(name)) {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+
+    fn names(&self) -> io::Result<Vec<String>> {
+        let dir = self.dir.read_recursive();
+        let mut names = vec![];
+        for entry in fs::read_dir(&*dir)? {
+            let name = entry?.file_name();
+            if let Some(name) = name.to_str() {
+                if check_name(name).is_ok() && self.get(name)?.is_some() {
+                    names.push(name.to_string())
+                }
+            }
+        }
+        for (name, _) in self.pending()? {
+            if !names.contains(&name) {
+                names.push(name)
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+}
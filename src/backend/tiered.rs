@@ -0,0 +1,151 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::Hasher;
+use std::io::{self, Cursor, Read};
+use std::path::Path;
+use std::sync::Arc;
+
+use bytehash::{ByteHash, State};
+use parking_lot::Mutex;
+
+use crate::backend::{Backend, PutResult};
+use crate::control::Control;
+
+type Promoted<D> = Mutex<HashMap<D, Arc<[u8]>>>;
+
+/// A backend reading from an upper layer, falling back to a lower one
+///
+/// Writes go through to both layers. Nodes read from the lower layer are
+/// promoted to the upper one, on the next write or flush, so layers can be
+/// stacked from the fastest to the most durable, such as memory over local
+/// disk over a remote store.
+pub struct TieredBackend<H: ByteHash, A, B> {
+    upper: A,
+    lower: B,
+    promoted: Promoted<H::Digest>,
+}
+
+impl<H, A, B> TieredBackend<H, A, B>
+where
+    H: ByteHash,
+    A: Backend<H>,
+    B: Backend<H>,
+{
+    /// Creates a backend reading from `upper`, then from `lower`
+    pub fn new(upper: A, lower: B) -> Self {
+        TieredBackend {
+            upper,
+            lower,
+            promoted: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the upper layer
+    pub fn upper(&self) -> &A {
+        &self.upper
+    }
+
+    /// Returns the lower layer
+    pub fn lower(&self) -> &B {
+        &self.lower
+    }
+
+    fn write_promoted(&mut self) -> io::Result<()> {
+        let promoted: Vec<_> = self.promoted.lock().drain().collect();
+        for (digest, bytes) in promoted {
+            self.upper.put(digest, bytes.to_vec())?;
+        }
+        Ok(())
+    }
+}
+
+// Records the bytes of a node read from the lower layer, and promotes them
+// once done if they hash to the digest of the node
+struct Promote<'a, H: ByteHash> {
+    read: Box<dyn Read + 'a>,
+    digest: H::Digest,
+    recorded: Vec<u8>,
+    promoted: &'a Promoted<H::Digest>,
+}
+
+impl<'a, H: ByteHash> Read for Promote<'a, H> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.read.read(buf)?;
+        self.recorded.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+}
+
+impl<'a, H: ByteHash> Drop for Promote<'a, H> {
+    fn drop(&mut self) {
+        let mut state = H::state();
+        state.write(&self.recorded);
+        if state.fin() == self.digest {
+            let bytes = std::mem::take(&mut self.recorded);
+            self.promoted.lock().insert(self.digest, bytes.into());
+        }
+    }
+}
+
+impl<H, A, B> Backend<H> for TieredBackend<H, A, B>
+where
+    H: ByteHash,
+    A: Backend<H>,
+    B: Backend<H>,
+{
+    fn get<'a>(&'a self, hash: &H::Digest) -> io::Result<Box<dyn Read + 'a>> {
+        if let Ok(read) = self.upper.get(hash) {
+            return Ok(read);
+        }
+        if let Some(bytes) = self.promoted.lock().get(hash) {
+            return Ok(Box::new(Cursor::new(bytes.clone())));
+        }
+        let read = self.lower.get(hash)?;
+        Ok(Box::new(Promote::<H> {
+            read,
+            digest: *hash,
+            recorded: vec![],
+            promoted: &self.promoted,
+        }))
+    }
+
+    fn put(
+        &mut self,
+        hash: H::Digest,
+        bytes: Vec<u8>,
+    ) -> io::Result<PutResult> {
+        self.write_promoted()?;
+        let upper = self.upper.put(hash, bytes.clone())?;
+        match self.lower.put(hash, bytes)? {
+            PutResult::AlreadyThere => Ok(upper),
+            PutResult::Ok => Ok(PutResult::Ok),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_promoted()?;
+        self.upper.flush()?;
+        self.lower.flush()
+    }
+
+    fn slow(&self, hash: &H::Digest) -> bool {
+        self.upper.slow(hash) || self.lower.slow(hash)
+    }
+
+    fn gc(
+        &mut self,
+        live: &HashSet<H::Digest>,
+        control: &mut Control<'_>,
+    ) -> io::Result<usize> {
+        self.flush()?;
+        let upper = self.upper.gc(live, control)?;
+        Ok(upper + self.lower.gc(live, control)?)
+    }
+
+    fn size(&self) -> usize {
+        self.lower.size()
+    }
+
+    fn path(&self) -> Option<&Path> {
+        self.lower.path().or_else(|| self.upper.path())
+    }
+}
@@ -0,0 +1,310 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+
+use bytehash::ByteHash;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::content::Content;
+use crate::gc::Reach;
+use crate::sink::Sink;
+use crate::source::Source;
+use crate::store::Store;
+
+// Size of the chunks large values are split into
+const CHUNK: usize = 16 * 1024;
+
+const INLINE: u8 = 0;
+const CHUNKED: u8 = 1;
+
+type Load<T> = Arc<dyn Fn() -> io::Result<T> + Send + Sync>;
+
+/// A leaf value stored in chunks of their own when it grows large
+///
+/// Values encoding to no more than the maximum leaf size of the store they
+/// are persisted to, see `Store::set_max_leaf_size`, are stored inline.
+/// Larger values are split into chunks written as separate nodes, and the
+/// leaf only keeps a link to them and their length, so that a huge value
+/// does not bloat the nodes and proofs of its neighbours.
+///
+/// Restoring a large value only reads its link, the chunks are read when
+/// the value is accessed, and are not written again when persisting a
+/// value that was not modified. The chunks are reached by garbage
+/// collection and export through the link, but a value left in the store is
+/// not read to reach into it, so values referring to other nodes are not
+/// meant to be kept in blobs.
+///
+/// The encoding, and thus the digest, of a large value depends on the
+/// threshold, so structures compared by digest must be persisted to stores
+/// with the same maximum leaf size.
+#[derive(Clone)]
+pub struct Blob<T> {
+    // the value, unless left in the store
+    value: Option<T>,
+    // the chunks holding the value, unless modified since written
+    stored: Option<Stored<T>>,
+}
+
+// A value in chunks, by its encoded length and the digest of its link
+#[derive(Clone)]
+struct Stored<T> {
+    len: u64,
+    link: Vec<u8>,
+    load: Load<T>,
+}
+
+impl<T> Blob<T> {
+    /// Wraps `value`
+    pub fn new(value: T) -> Self {
+        Blob {
+            value: Some(value),
+            stored: None,
+        }
+    }
+
+    /// Returns true if the value is in memory, rather than left in the store
+    pub fn is_loaded(&self) -> bool {
+        self.value.is_some()
+    }
+
+    /// Returns the value, reading it from the store unless in memory
+    ///
+    /// A value read from the store is not kept, see `get_mut`.
+    pub fn get(&self) -> io::Result<Cow<T>>
+    where
+        T: Clone,
+    {
+        match (&self.value, &self.stored) {
+            (Some(value), _) => Ok(Cow::Borrowed(value)),
+            (None, Some(stored)) => Ok(Cow::Owned((stored.load)()?)),
+            (None, None) => unreachable!("Blobs are either loaded or stored"),
+        }
+    }
+
+    /// Returns a mutable reference to the value, reading it from the store
+    /// unless in memory
+    ///
+    /// The value is kept in memory from then on, and written again when
+    /// persisted.
+    pub fn get_mut(&mut self) -> io::Result<&mut T> {
+        if let Some(stored) = self.stored.take() {
+            if self.value.is_none() {
+                self.value = Some((stored.load)()?);
+            }
+        }
+        Ok(self.value.as_mut().expect("loaded above"))
+    }
+
+    /// Returns the wrapped value, reading it from the store unless in memory
+    pub fn into_inner(self) -> io::Result<T> {
+        match (self.value, self.stored) {
+            (Some(value), _) => Ok(value),
+            (None, Some(stored)) => (stored.load)(),
+            (None, None) => unreachable!("Blobs are either loaded or stored"),
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Blob<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.value, &self.stored) {
+            (Some(value), _) => value.fmt(f),
+            (None, Some(stored)) => write!(f, "Blob({} bytes)", stored.len),
+            (None, None) => write!(f, "Blob"),
+        }
+    }
+}
+
+// A node holding raw bytes, read and written in one go
+#[derive(Clone)]
+struct Chunk(Vec<u8>);
+
+impl<H: ByteHash> Content<H> for Chunk {
+    fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
+        sink.write_u32::<BigEndian>(self.0.len() as u32)?;
+        sink.write_all(&self.0)
+    }
+
+    fn restore(source: &mut Source<H>) -> io::Result<Self> {
+        Ok(Chunk(source.read_len_prefixed(usize::MAX)?))
+    }
+}
+
+// The node listing the digests of the chunks of a value, encoded as a chunk
+#[derive(Clone)]
+struct Link(Chunk);
+
+impl<H: ByteHash> Content<H> for Link {
+    fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
+        self.0.persist(sink)
+    }
+
+    fn restore(source: &mut Source<H>) -> io::Result<Self> {
+        Ok(Link(Chunk::restore(source)?))
+    }
+
+    fn reach<R: Reach<H>>(&self, reach: &mut R) -> io::Result<()> {
+        for digest in self.digests::<H>()? {
+            reach.node::<Chunk>(&digest)?;
+        }
+        Ok(())
+    }
+}
+
+impl Link {
+    fn digests<H: ByteHash>(&self) -> io::Result<Vec<H::Digest>> {
+        let width = H::Digest::default().as_ref().len();
+        if (self.0).0.len() % width != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Invalid Blob link",
+            ));
+        }
+        Ok((self.0)
+            .0
+            .chunks(width)
+            .map(|bytes| {
+                let mut digest = H::Digest::default();
+                digest.as_mut().copy_from_slice(bytes);
+                digest
+            })
+            .collect())
+    }
+}
+
+fn digest<H: ByteHash>(bytes: &[u8]) -> io::Result<H::Digest> {
+    let mut digest = H::Digest::default();
+    if digest.as_ref().len() != bytes.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Blob link of another hash",
+        ));
+    }
+    digest.as_mut().copy_from_slice(bytes);
+    Ok(digest)
+}
+
+// Reads the value of `len` encoded bytes in the chunks listed by `link`
+fn load<T, H>(store: &Store<H>, len: u64, link: &[u8]) -> io::Result<T>
+where
+    T: Content<H>,
+    H: ByteHash,
+{
+    let link: Link = store.get_hash(&digest::<H>(link)?)?;
+    let digests = link.digests::<H>()?;
+    // every chunk but the last one is full
+    let max = digests.len() as u64 * CHUNK as u64;
+    if len > max || len + (CHUNK as u64) <= max {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Blob length does not match its chunks",
+        ));
+    }
+    let mut raw = Vec::with_capacity(len as usize);
+    for digest in digests {
+        let chunk: Chunk = store.get_hash(&digest)?;
+        if (raw.len() + chunk.0.len()) as u64 > len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Blob length does not match its chunks",
+            ));
+        }
+        raw.extend_from_slice(&chunk.0);
+    }
+    if raw.len() as u64 != len {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Blob length does not match its chunks",
+        ));
+    }
+    let mut source = Source::new(Box::new(io::Cursor::new(raw)), store);
+    T::restore(&mut source)
+}
+
+impl<T, H> Content<H> for Blob<T>
+where
+    T: Content<H>,
+    H: ByteHash,
+{
+    fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
+        if let Some(ref stored) = self.stored {
+            // not modified since written, the chunks are in the store
+            sink.write_all(&[CHUNKED])?;
+            sink.write_u64::<BigEndian>(stored.len)?;
+            return sink.write_all(&stored.link);
+        }
+
+        let store = sink.store().clone();
+        let mut inner = Sink::new(&store);
+        self.value
+            .as_mut()
+            .expect("Blobs are either loaded or stored")
+            .persist(&mut inner)?;
+        let raw = inner.bytes();
+
+        if raw.len() <= store.max_leaf_size() {
+            sink.write_all(&[INLINE])?;
+            return sink.write_all(raw);
+        }
+
+        // the link is a node of its own, listing the digests of the chunks
+        let mut link = vec![];
+        for chunk in raw.chunks(CHUNK) {
+            let snapshot = store.persist(&mut Chunk(chunk.to_vec()))?;
+            link.extend_from_slice(snapshot.hash().as_ref());
+        }
+        let link = store.persist(&mut Link(Chunk(link)))?;
+        let stored = stored(&store, raw.len() as u64, link.as_bytes());
+        sink.write_all(&[CHUNKED])?;
+        sink.write_u64::<BigEndian>(stored.len)?;
+        sink.write_all(&stored.link)?;
+        self.stored = Some(stored);
+        Ok(())
+    }
+
+    fn restore(source: &mut Source<H>) -> io::Result<Self> {
+        let mut tag = [0u8];
+        source.read_exact(&mut tag)?;
+        match tag[0] {
+            INLINE => Ok(Blob::new(T::restore(source)?)),
+            CHUNKED => {
+                let len = source.read_u64::<BigEndian>()?;
+                let mut digest = H::Digest::default();
+                source.read_exact(digest.as_mut())?;
+                Ok(Blob {
+                    value: None,
+                    stored: Some(stored(source.store(), len, digest.as_ref())),
+                })
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Invalid Blob encoding",
+            )),
+        }
+    }
+
+    fn reach<R: Reach<H>>(&self, reach: &mut R) -> io::Result<()> {
+        match (&self.value, &self.stored) {
+            (_, Some(stored)) => {
+                reach.node::<Link>(&digest::<H>(&stored.link)?)
+            }
+            (Some(value), None) => value.reach(reach),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+fn stored<T, H>(store: &Store<H>, len: u64, link: &[u8]) -> Stored<T>
+where
+    T: Content<H>,
+    H: ByteHash,
+{
+    let link = link.to_vec();
+    let store = store.clone();
+    Stored {
+        len,
+        link: link.clone(),
+        load: Arc::new(move || load(&store, len, &link)),
+    }
+}
@@ -1,3 +1,5 @@
+use std::cmp::Ordering;
+use std::fmt;
 use std::io;
 use std::ops::{Deref, DerefMut};
 
@@ -38,6 +40,14 @@ where
         self.0.exact()
     }
 
+    pub(crate) fn position(&self) -> Vec<usize> {
+        self.0.position()
+    }
+
+    pub(crate) fn cmp_position(&self, other: &Self) -> Ordering {
+        self.0.cmp_position(&other.0)
+    }
+
     /// Search for the next value in the branch, using `method`
     ///
     /// Takes self by value, and returns the updated branch or `None`
@@ -67,6 +77,20 @@ where
     }
 }
 
+impl<'a, C, H> fmt::Debug for Branch<'a, C, H>
+where
+    C: Compound<H>,
+    C::Leaf: fmt::Debug,
+    H: ByteHash,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Branch")
+            .field("depth", &self.0.depth())
+            .field("leaf", &self.0.leaf())
+            .finish()
+    }
+}
+
 /// A mutable branch into a `Compound<H>`
 /// The BranchMut is guaranteed to always point to a leaf
 pub struct BranchMut<'a, C, H>(RawBranch<'a, C, H>)
@@ -93,6 +117,24 @@ where
         })
     }
 
+    // Returns the branch to the leaf exactly matching `method`, or gives
+    // `node` back if there is none
+    pub(crate) fn exact_or_root<M>(
+        node: &'a mut C,
+        method: &mut M,
+    ) -> io::Result<Result<Self, &'a mut C>>
+    where
+        M: Method<C, H>,
+    {
+        let mut inner = RawBranch::new_mutable(node);
+        inner.search(method)?;
+        if inner.leaf().is_some() && inner.exact() {
+            Ok(Ok(BranchMut(inner)))
+        } else {
+            Ok(Err(inner.into_root().expect("created mutable")))
+        }
+    }
+
     pub(crate) fn exact(&self) -> bool {
         self.0.exact()
     }
@@ -114,6 +156,20 @@ where
     }
 }
 
+impl<'a, C, H> fmt::Debug for BranchMut<'a, C, H>
+where
+    C: Compound<H>,
+    C::Leaf: fmt::Debug,
+    H: ByteHash,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BranchMut")
+            .field("depth", &self.0.depth())
+            .field("leaf", &self.0.leaf())
+            .finish()
+    }
+}
+
 impl<'a, C, H> Drop for BranchMut<'a, C, H>
 where
     C: Compound<H>,
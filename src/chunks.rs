@@ -0,0 +1,314 @@
+use std::collections::HashMap;
+use std::hash::Hasher;
+use std::io::{self, Cursor, Read, Write};
+use std::marker::PhantomData;
+
+use bytehash::{ByteHash, State};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use parking_lot::RwLock;
+
+use crate::compound::Compound;
+use crate::error::Error;
+use crate::export::{export, import};
+use crate::store::{Snapshot, Store};
+
+/// Describes the chunks of the export of a root
+///
+/// Sent by the provider ahead of the chunks, so that the receiver can
+/// verify every chunk on its own as it arrives.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Manifest<H: ByteHash> {
+    /// The digest of the exported root
+    pub root: H::Digest,
+    /// The size of every chunk, but the last one
+    pub chunk_size: u32,
+    /// The digests of the chunks, in order
+    pub chunks: Vec<H::Digest>,
+}
+
+impl<H: ByteHash> Manifest<H> {
+    /// Writes the manifest to `writer`, to be sent to a receiver
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(self.root.as_ref())?;
+        writer.write_u32::<BigEndian>(self.chunk_size)?;
+        writer.write_u32::<BigEndian>(self.chunks.len() as u32)?;
+        for chunk in &self.chunks {
+            writer.write_all(chunk.as_ref())?;
+        }
+        Ok(())
+    }
+
+    /// Reads a manifest written with `write_to`
+    pub fn read_from<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let mut root = H::Digest::default();
+        reader.read_exact(root.as_mut())?;
+        let chunk_size = reader.read_u32::<BigEndian>()?;
+        let len = reader.read_u32::<BigEndian>()?;
+        let mut chunks = Vec::new();
+        for _ in 0..len {
+            let mut chunk = H::Digest::default();
+            reader.read_exact(chunk.as_mut())?;
+            chunks.push(chunk);
+        }
+        Ok(Manifest {
+            root,
+            chunk_size,
+            chunks,
+        })
+    }
+}
+
+fn digest<H: ByteHash>(bytes: &[u8]) -> H::Digest {
+    let mut state = H::state();
+    state.write(bytes);
+    state.fin()
+}
+
+// Splits the export of a root into chunks, kept in the store by digest
+struct Chunker<'a, H: ByteHash> {
+    store: &'a Store<H>,
+    size: usize,
+    buffer: Vec<u8>,
+    chunks: Vec<H::Digest>,
+}
+
+impl<'a, H: ByteHash> Chunker<'a, H> {
+    fn cut(&mut self) -> io::Result<()> {
+        let bytes = std::mem::take(&mut self.buffer);
+        let digest = digest::<H>(&bytes);
+        self.store.put(digest, bytes)?;
+        self.chunks.push(digest);
+        Ok(())
+    }
+}
+
+impl<'a, H: ByteHash> Write for Chunker<'a, H> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = buf.len().min(self.size - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..n]);
+        if self.buffer.len() == self.size {
+            self.cut()?;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Serves the exports of roots in fixed-size chunks, for state sync
+///
+/// Chunks are kept in the store of the provider, by digest, and addressed
+/// by the digest of their root and their index. Garbage collection retains
+/// them while their root is offered, and collects them once withdrawn, or
+/// once the provider is dropped.
+pub struct ChunkProvider<H: ByteHash> {
+    store: Store<H>,
+    chunk_size: u32,
+    manifests: RwLock<HashMap<H::Digest, Manifest<H>>>,
+}
+
+impl<H: ByteHash> ChunkProvider<H> {
+    /// Creates a provider keeping chunks of `chunk_size` bytes in `store`
+    pub fn new(store: &Store<H>, chunk_size: u32) -> Self {
+        assert!(chunk_size > 0, "Chunks can not be empty");
+        ChunkProvider {
+            store: store.clone(),
+            chunk_size,
+            manifests: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Exports `root` into chunks, returning its manifest
+    pub fn offer<C: Compound<H>>(
+        &self,
+        root: &Snapshot<C, H>,
+    ) -> io::Result<Manifest<H>> {
+        if let Some(manifest) = self.manifests.read().get(root.hash()) {
+            return Ok(manifest.clone());
+        }
+        let mut chunker = Chunker {
+            store: &self.store,
+            size: self.chunk_size as usize,
+            buffer: vec![],
+            chunks: vec![],
+        };
+        export(root, &mut chunker)?;
+        if !chunker.buffer.is_empty() {
+            chunker.cut()?;
+        }
+        let manifest = Manifest {
+            root: *root.hash(),
+            chunk_size: self.chunk_size,
+            chunks: chunker.chunks,
+        };
+        let mut manifests = self.manifests.write();
+        if !manifests.contains_key(root.hash()) {
+            for chunk in &manifest.chunks {
+                self.store.hold(chunk);
+            }
+            manifests.insert(*root.hash(), manifest.clone());
+        }
+        Ok(manifest)
+    }
+
+    /// Stops offering the root with digest `root`
+    pub fn withdraw(&self, root: &H::Digest) {
+        if let Some(manifest) = self.manifests.write().remove(root) {
+            for chunk in &manifest.chunks {
+                self.store.release(chunk);
+            }
+        }
+    }
+
+    /// Returns the manifest of the root with digest `root`, if offered
+    pub fn manifest(&self, root: &H::Digest) -> Option<Manifest<H>> {
+        self.manifests.read().get(root).cloned()
+    }
+
+    /// Returns the chunk at `index` of the root with digest `root`, if
+    /// offered
+    pub fn chunk(
+        &self,
+        root: &H::Digest,
+        index: usize,
+    ) -> io::Result<Option<Vec<u8>>> {
+        let chunk = match self.manifests.read().get(root) {
+            Some(manifest) => match manifest.chunks.get(index) {
+                Some(chunk) => *chunk,
+                None => return Ok(None),
+            },
+            None => return Ok(None),
+        };
+        self.store.read_bytes(&chunk).map(Some)
+    }
+}
+
+impl<H: ByteHash> Drop for ChunkProvider<H> {
+    fn drop(&mut self) {
+        for manifest in self.manifests.get_mut().values() {
+            for chunk in &manifest.chunks {
+                self.store.release(chunk);
+            }
+        }
+    }
+}
+
+/// Receives the chunks of a root described by a `Manifest`
+///
+/// Every chunk is verified against the manifest and kept in the store as
+/// it arrives, in any order. After an interruption, a receiver created
+/// anew with the same manifest and store only asks for the chunks still
+/// `missing`, as long as the store was flushed. Garbage collection retains
+/// the chunks received while a receiver is alive, and collects them once
+/// the root is imported.
+pub struct ChunkReceiver<C, H: ByteHash> {
+    manifest: Manifest<H>,
+    store: Store<H>,
+    _marker: PhantomData<C>,
+}
+
+impl<C, H> ChunkReceiver<C, H>
+where
+    C: Compound<H>,
+    H: ByteHash,
+{
+    /// Creates a receiver for the root of `manifest`, into `store`
+    pub fn new(manifest: Manifest<H>, store: &Store<H>) -> Self {
+        for chunk in &manifest.chunks {
+            store.hold(chunk);
+        }
+        ChunkReceiver {
+            manifest,
+            store: store.clone(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the manifest being received
+    pub fn manifest(&self) -> &Manifest<H> {
+        &self.manifest
+    }
+
+    /// Returns the indices of the chunks not received yet
+    pub fn missing(&self) -> Vec<usize> {
+        (0..self.manifest.chunks.len())
+            .filter(|i| !self.store.contains(&self.manifest.chunks[*i]))
+            .collect()
+    }
+
+    /// Applies the chunk at `index`
+    ///
+    /// Fails with an `InvalidData` error if the chunk does not match the
+    /// manifest, for the chunk to be requested again, possibly from another
+    /// provider.
+    pub fn apply(&self, index: usize, bytes: Vec<u8>) -> io::Result<()> {
+        let expected = match self.manifest.chunks.get(index) {
+            Some(expected) => expected,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Chunk index out of range",
+                ))
+            }
+        };
+        if digest::<H>(&bytes) != *expected {
+            return Err(Error::Corrupted.into());
+        }
+        self.store.put(*expected, bytes)?;
+        Ok(())
+    }
+
+    /// Imports the root from the chunks, once all are received
+    pub fn finish(self) -> io::Result<Snapshot<C, H>> {
+        if !self.missing().is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Chunks still missing",
+            ));
+        }
+        let reader = Chunks {
+            store: &self.store,
+            chunks: self.manifest.chunks.iter(),
+            current: Cursor::new(vec![]),
+        };
+        let snapshot = import(reader, &self.store)?;
+        if *snapshot.hash() != self.manifest.root {
+            return Err(Error::InvalidEncoding("Chunks of another root").into());
+        }
+        Ok(snapshot)
+    }
+}
+
+impl<C, H: ByteHash> Drop for ChunkReceiver<C, H> {
+    fn drop(&mut self) {
+        for chunk in &self.manifest.chunks {
+            self.store.release(chunk);
+        }
+    }
+}
+
+// Reads the chunks kept in the store back to back
+struct Chunks<'a, H: ByteHash> {
+    store: &'a Store<H>,
+    chunks: std::slice::Iter<'a, H::Digest>,
+    current: Cursor<Vec<u8>>,
+}
+
+impl<'a, H: ByteHash> Read for Chunks<'a, H> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.current.read(buf)?;
+            if n > 0 || buf.is_empty() {
+                return Ok(n);
+            }
+            match self.chunks.next() {
+                Some(chunk) => {
+                    self.current = Cursor::new(self.store.read_bytes(chunk)?)
+                }
+                None => return Ok(0),
+            }
+        }
+    }
+}
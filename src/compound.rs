@@ -1,10 +1,17 @@
+use std::io;
+
 use bytehash::ByteHash;
 
 use crate::annotations::Combine;
 use crate::content::Content;
 use crate::handle::Handle;
+use crate::store::{Snapshot, Store};
 
 /// A trait for tree-like structures containing leaves
+///
+/// Structures visit their children in `Content::reach`, through
+/// `reach_children`, for garbage collection and export to find them. Their
+/// nodes are hashed in the node domain, as they hold handles, see `Domain`.
 pub trait Compound<H>: Content<H> + Default
 where
     H: ByteHash,
@@ -27,4 +34,39 @@ where
     fn annotation(&self) -> Option<Self::Annotation> {
         Self::Annotation::combine(self.children())
     }
+
+    /// Returns the metadata of the node, if any
+    ///
+    /// Metadata is data about a node that search methods need before
+    /// descending into it, and that is not derived from the leaves like the
+    /// annotation is. It is persisted in the handles pointing at the node,
+    /// next to their annotation, and read back with `Handle::meta`. Nodes
+    /// deriving their metadata from their children's combine it here from
+    /// `Handle::meta` of each child. Handles to nodes without metadata carry
+    /// none, so structures not using it keep their encoding and digests.
+    fn meta(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// Returns a checkpoint of the current state, to `rollback` to
+    ///
+    /// Writes the nodes modified since the last checkpoint to `store`, and
+    /// leaves the structure referring to them by digest, so that it is copied
+    /// on write from then on. The checkpoint itself is just the digest of
+    /// the root.
+    fn checkpoint(
+        &mut self,
+        store: &Store<H>,
+    ) -> io::Result<Snapshot<Self, H>> {
+        store.persist(self)
+    }
+
+    /// Restores the state of `checkpoint`
+    ///
+    /// Only the root node is read back, the rest is read as it is accessed
+    /// again.
+    fn rollback(&mut self, checkpoint: &Snapshot<Self, H>) -> io::Result<()> {
+        *self = checkpoint.restore()?;
+        Ok(())
+    }
 }
@@ -0,0 +1,293 @@
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+
+use bytehash::{Blake2b, ByteHash};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use rand::Rng;
+use zstd::dict::{DecoderDictionary, EncoderDictionary};
+
+use crate::compound::Compound;
+use crate::content::Content;
+use crate::error::Error;
+use crate::gc::Reach;
+use crate::iter::LeafIterable;
+use crate::sink::{Sink, COMPRESSED, MAGIC};
+use crate::source::Source;
+use crate::store::Store;
+
+// Compression level used for nodes holding compressed values
+const LEVEL: i32 = 3;
+
+// Limit on the size of a decompressed node, or of a compressed value
+const MAX_DECODED: usize = 64 << 20;
+
+/// A zstd dictionary, trained on the leaves of a structure
+///
+/// Small values of the same shape, like account records, have little in
+/// common within themselves for generic compression to find. A dictionary
+/// trained on a sample of them captures what they have in common instead.
+///
+/// Dictionaries are prepared for compression and decompression once, and
+/// shared by their clones.
+#[derive(Clone)]
+pub struct Dictionary {
+    id: u32,
+    prepared: Arc<Prepared>,
+}
+
+struct Prepared {
+    bytes: Vec<u8>,
+    encoder: EncoderDictionary<'static>,
+    decoder: DecoderDictionary<'static>,
+}
+
+impl fmt::Debug for Dictionary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Dictionary({:08x}, {} bytes)",
+            self.id,
+            self.prepared.bytes.len()
+        )
+    }
+}
+
+impl Dictionary {
+    /// Creates a dictionary from its raw bytes
+    pub fn new(bytes: Vec<u8>) -> Self {
+        let digest = Blake2b::hash(&bytes[..]);
+        let mut id = [0u8; 4];
+        id.copy_from_slice(&digest.as_ref()[..4]);
+        Dictionary {
+            id: u32::from_be_bytes(id),
+            prepared: Arc::new(Prepared {
+                encoder: EncoderDictionary::copy(&bytes, LEVEL),
+                decoder: DecoderDictionary::copy(&bytes),
+                bytes,
+            }),
+        }
+    }
+
+    /// Trains a dictionary of at most `max_size` bytes on the encodings of
+    /// `samples`
+    pub fn from_samples<'a, T, H, I>(
+        samples: I,
+        max_size: usize,
+    ) -> io::Result<Self>
+    where
+        T: Content<H>,
+        H: ByteHash,
+        I: IntoIterator<Item = &'a T>,
+    {
+        let scratch = Store::ephemeral();
+        let samples = samples
+            .into_iter()
+            .map(|t| encode(t, &scratch))
+            .collect::<io::Result<Vec<_>>>()?;
+        Ok(Dictionary::new(zstd::dict::from_samples(
+            &samples, max_size,
+        )?))
+    }
+
+    /// Trains a dictionary of at most `max_size` bytes on up to `sample`
+    /// leaves of `root`, chosen at random
+    pub fn train<C, H>(
+        root: &C,
+        sample: usize,
+        max_size: usize,
+    ) -> io::Result<Self>
+    where
+        C: Compound<H>,
+        H: ByteHash,
+    {
+        let mut rng = rand::thread_rng();
+        let mut chosen: Vec<&C::Leaf> = Vec::with_capacity(sample);
+        for (seen, leaf) in root.iter().enumerate() {
+            let leaf = leaf?;
+            if chosen.len() < sample {
+                chosen.push(leaf);
+            } else {
+                let i = rng.gen_range(0, seen + 1);
+                if i < sample {
+                    chosen[i] = leaf
+                }
+            }
+        }
+        Dictionary::from_samples(chosen, max_size)
+    }
+
+    /// Returns the identifier of the dictionary, derived from its contents
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Returns the raw bytes of the dictionary
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.prepared.bytes
+    }
+}
+
+/// A value compressed with a dictionary when persisted
+///
+/// The node holding the value is stored compressed with the dictionary, but
+/// its digest is that of the uncompressed node, so that roots do not depend
+/// on the version or settings of zstd. Stores with domain separation turned
+/// off store the node uncompressed, see `Store::set_domain_separation`.
+///
+/// The dictionary must be known to the store the value is restored from,
+/// see `Store::add_dictionary`.
+#[derive(Clone)]
+pub struct Compressed<T> {
+    value: T,
+    dictionary: Dictionary,
+}
+
+impl<T> Compressed<T> {
+    /// Wraps `value`, to be compressed with `dictionary`
+    pub fn new(value: T, dictionary: &Dictionary) -> Self {
+        Compressed {
+            value,
+            dictionary: dictionary.clone(),
+        }
+    }
+
+    /// Returns the wrapped value
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<T> Deref for Compressed<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> DerefMut for Compressed<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Compressed<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.value.fmt(f)
+    }
+}
+
+impl<T: PartialEq> PartialEq for Compressed<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl<T: Eq> Eq for Compressed<T> {}
+
+impl<T, H> Content<H> for Compressed<T>
+where
+    T: Content<H>,
+    H: ByteHash,
+{
+    fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
+        let raw = encode(&self.value, sink.store())?;
+        sink.write_u32::<BigEndian>(self.dictionary.id)?;
+        sink.write_u32::<BigEndian>(raw.len() as u32)?;
+        sink.write_all(&raw)?;
+        sink.compress_with(&self.dictionary);
+        Ok(())
+    }
+
+    fn restore(source: &mut Source<H>) -> io::Result<Self> {
+        let id = source.read_u32::<BigEndian>()?;
+        let dictionary = dictionary(source.store(), id)?;
+        let raw = source.read_len_prefixed(MAX_DECODED)?;
+        let mut inner =
+            Source::new(Box::new(io::Cursor::new(raw)), source.store());
+        Ok(Compressed {
+            value: T::restore(&mut inner)?,
+            dictionary,
+        })
+    }
+
+    fn reach<R: Reach<H>>(&self, reach: &mut R) -> io::Result<()> {
+        self.value.reach(reach)
+    }
+}
+
+fn dictionary<H: ByteHash>(
+    store: &Store<H>,
+    id: u32,
+) -> io::Result<Dictionary> {
+    store.dictionary(id).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Unknown compression dictionary",
+        )
+    })
+}
+
+fn too_large() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "Decompressed value exceeds the size limit",
+    )
+}
+
+// Encodes a node for storage, compressed with `dictionary`
+//
+// Compressed nodes lead with `MAGIC` and `COMPRESSED`, which no node starts
+// with in stores with domain separation, followed by the identifier of the
+// dictionary and the zstd frame.
+pub(crate) fn compress(
+    bytes: &[u8],
+    dictionary: &Dictionary,
+) -> io::Result<Vec<u8>> {
+    let mut stored = vec![MAGIC, COMPRESSED];
+    stored.write_u32::<BigEndian>(dictionary.id)?;
+    let mut encoder = zstd::stream::write::Encoder::with_prepared_dictionary(
+        stored,
+        &dictionary.prepared.encoder,
+    )?;
+    encoder.write_all(bytes)?;
+    encoder.finish()
+}
+
+// Decodes a node as stored, returning it as hashed
+pub(crate) fn decompress<H: ByteHash>(
+    store: &Store<H>,
+    stored: Vec<u8>,
+) -> io::Result<Vec<u8>> {
+    if !store.domain_separation() || !stored.starts_with(&[MAGIC, COMPRESSED]) {
+        return Ok(stored);
+    }
+    let mut read = &stored[2..];
+    let id = read.read_u32::<BigEndian>().map_err(|_| {
+        io::Error::from(Error::InvalidEncoding("Invalid compressed node"))
+    })?;
+    let dictionary = dictionary(store, id)?;
+    let decoder = zstd::stream::read::Decoder::with_prepared_dictionary(
+        read,
+        &dictionary.prepared.decoder,
+    )?;
+    let mut bytes = vec![];
+    decoder
+        .take(MAX_DECODED as u64 + 1)
+        .read_to_end(&mut bytes)?;
+    if bytes.len() > MAX_DECODED {
+        return Err(too_large());
+    }
+    Ok(bytes)
+}
+
+fn encode<T: Content<H>, H: ByteHash>(
+    t: &T,
+    store: &Store<H>,
+) -> io::Result<Vec<u8>> {
+    let mut sink = Sink::new(store);
+    t.clone().persist(&mut sink)?;
+    Ok(sink.bytes().to_vec())
+}
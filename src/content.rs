@@ -1,12 +1,18 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryInto;
 use std::io::{self, Read, Write};
 use std::marker::PhantomData;
+use std::net::{
+    IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6,
+};
+use std::time::Duration;
 
 use bytehash::ByteHash;
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 
+use crate::gc::Reach;
 use crate::sink::Sink;
 use crate::source::Source;
-use arrayvec::ArrayVec;
 
 /// The main trait for content-adressable types, MUST assure a 1-1 mapping between
 /// values of the type and hash digests.
@@ -18,6 +24,18 @@ where
     fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()>;
     /// Restore the type from a `Source`
     fn restore(source: &mut Source<H>) -> io::Result<Self>;
+
+    /// Visits the nodes the value refers to by digest
+    ///
+    /// Garbage collection, export and staging only retain the nodes reached
+    /// from their roots, so every value referring to nodes must visit them
+    /// here. Structures visit their children through `reach_children`, and
+    /// values containing structures or other such values reach into them.
+    /// Nothing by default.
+    fn reach<R: Reach<H>>(&self, reach: &mut R) -> io::Result<()> {
+        let _ = reach;
+        Ok(())
+    }
 }
 
 impl<T: Content<H>, H: ByteHash> Content<H> for Option<T> {
@@ -43,6 +61,13 @@ impl<T: Content<H>, H: ByteHash> Content<H> for Option<T> {
             )),
         }
     }
+
+    fn reach<R: Reach<H>>(&self, reach: &mut R) -> io::Result<()> {
+        match *self {
+            Some(ref content) => content.reach(reach),
+            None => Ok(()),
+        }
+    }
 }
 
 impl<T: Content<H>, H: ByteHash> Content<H> for Box<T> {
@@ -53,6 +78,10 @@ impl<T: Content<H>, H: ByteHash> Content<H> for Box<T> {
     fn restore(source: &mut Source<H>) -> io::Result<Self> {
         Ok(Box::new(T::restore(source)?))
     }
+
+    fn reach<R: Reach<H>>(&self, reach: &mut R) -> io::Result<()> {
+        (**self).reach(reach)
+    }
 }
 
 impl<H: ByteHash> Content<H> for () {
@@ -145,6 +174,78 @@ impl<H: ByteHash, T: Content<H>> Content<H> for Vec<T> {
         }
         Ok(vec)
     }
+
+    fn reach<R: Reach<H>>(&self, reach: &mut R) -> io::Result<()> {
+        for t in self.iter() {
+            t.reach(reach)?
+        }
+        Ok(())
+    }
+}
+
+impl<K, V, H> Content<H> for BTreeMap<K, V>
+where
+    K: Content<H> + Ord,
+    V: Content<H>,
+    H: ByteHash,
+{
+    fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
+        sink.write_u64::<BigEndian>(self.len() as u64)?;
+        for (k, v) in self.iter_mut() {
+            // keys cannot be borrowed mutably
+            k.clone().persist(sink)?;
+            v.persist(sink)?;
+        }
+        Ok(())
+    }
+
+    fn restore(source: &mut Source<H>) -> io::Result<Self> {
+        let len = source.read_u64::<BigEndian>()?;
+        let mut map = BTreeMap::new();
+        for _ in 0..len {
+            let k = K::restore(source)?;
+            map.insert(k, V::restore(source)?);
+        }
+        Ok(map)
+    }
+
+    fn reach<R: Reach<H>>(&self, reach: &mut R) -> io::Result<()> {
+        for (k, v) in self.iter() {
+            k.reach(reach)?;
+            v.reach(reach)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T, H> Content<H> for BTreeSet<T>
+where
+    T: Content<H> + Ord,
+    H: ByteHash,
+{
+    fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
+        sink.write_u64::<BigEndian>(self.len() as u64)?;
+        for t in self.iter() {
+            t.clone().persist(sink)?;
+        }
+        Ok(())
+    }
+
+    fn restore(source: &mut Source<H>) -> io::Result<Self> {
+        let len = source.read_u64::<BigEndian>()?;
+        let mut set = BTreeSet::new();
+        for _ in 0..len {
+            set.insert(T::restore(source)?);
+        }
+        Ok(set)
+    }
+
+    fn reach<R: Reach<H>>(&self, reach: &mut R) -> io::Result<()> {
+        for t in self.iter() {
+            t.reach(reach)?;
+        }
+        Ok(())
+    }
 }
 
 // numbers
@@ -172,86 +273,240 @@ number!(i64: read_i64, write_i64);
 number!(i32: read_i32, write_i32);
 number!(i16: read_i16, write_i16);
 
-macro_rules! array {
-    ($number:expr) => {
-        impl<T, H: ByteHash> Content<H> for [T; $number]
+// Arrays of any length, with the elements one after the other
+impl<T, H, const N: usize> Content<H> for [T; N]
+where
+    T: Content<H>,
+    H: ByteHash,
+{
+    fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
+        for t in self.iter_mut() {
+            t.persist(sink)?;
+        }
+        Ok(())
+    }
+
+    fn restore(source: &mut Source<H>) -> io::Result<Self> {
+        let mut vec = Vec::with_capacity(N);
+        for _ in 0..N {
+            vec.push(T::restore(source)?);
+        }
+        match vec.try_into() {
+            Ok(arr) => Ok(arr),
+            Err(_) => unreachable!("Errors out earlier if not full"),
+        }
+    }
+
+    fn reach<R: Reach<H>>(&self, reach: &mut R) -> io::Result<()> {
+        for t in self.iter() {
+            t.reach(reach)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T, E, H> Content<H> for Result<T, E>
+where
+    T: Content<H>,
+    E: Content<H>,
+    H: ByteHash,
+{
+    fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
+        match self {
+            Ok(t) => {
+                sink.write_all(&[0])?;
+                t.persist(sink)
+            }
+            Err(e) => {
+                sink.write_all(&[1])?;
+                e.persist(sink)
+            }
+        }
+    }
+
+    fn restore(source: &mut Source<H>) -> io::Result<Self> {
+        let mut byte = [0u8];
+        source.read_exact(&mut byte)?;
+        match byte[0] {
+            0 => Ok(Ok(T::restore(source)?)),
+            1 => Ok(Err(E::restore(source)?)),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Invalid Result encoding",
+            )),
+        }
+    }
+
+    fn reach<R: Reach<H>>(&self, reach: &mut R) -> io::Result<()> {
+        match self {
+            Ok(t) => t.reach(reach),
+            Err(e) => e.reach(reach),
+        }
+    }
+}
+
+// tuples, with the fields in order
+macro_rules! tuple {
+    ( $( $t:ident : $i:tt ),* ) => {
+        impl<$( $t, )* H> Content<H> for ( $( $t, )* )
         where
-            T: Content<H>,
+            $( $t: Content<H>, )*
+            H: ByteHash,
         {
             fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
-                for i in 0..$number {
-                    self[i].persist(sink)?;
-                }
+                $( self.$i.persist(sink)?; )*
                 Ok(())
             }
 
             fn restore(source: &mut Source<H>) -> io::Result<Self> {
-                let mut arrayvec: ArrayVec<[T; $number]> = ArrayVec::new();
-                for _ in 0..$number {
-                    arrayvec.push(T::restore(source)?);
-                }
-                match arrayvec.into_inner() {
-                    Ok(arr) => Ok(arr),
-                    Err(_) => unreachable!("Errors out earlier if not full"),
-                }
+                Ok(( $( $t::restore(source)?, )* ))
+            }
+
+            fn reach<R: Reach<H>>(&self, reach: &mut R) -> io::Result<()> {
+                $( self.$i.reach(reach)?; )*
+                Ok(())
             }
         }
     };
 }
 
-// TODO, find a better way to do this
-array!(0);
-array!(1);
-array!(2);
-array!(3);
-array!(4);
-array!(5);
-array!(6);
-array!(7);
-array!(8);
-array!(9);
-array!(10);
-array!(11);
-array!(12);
-array!(13);
-array!(14);
-array!(15);
-array!(16);
-array!(17);
-array!(18);
-array!(19);
-array!(20);
-array!(21);
-array!(22);
-array!(23);
-array!(24);
-array!(25);
-array!(26);
-array!(27);
-array!(28);
-array!(29);
-array!(30);
-array!(31);
-array!(32);
-
-array!(64);
-array!(128);
-array!(256);
-array!(512);
-array!(1024);
-
-impl<A, B, H> Content<H> for (A, B)
-where
-    A: Content<H>,
-    B: Content<H>,
-    H: ByteHash,
-{
+tuple!(A: 0);
+tuple!(A: 0, B: 1);
+tuple!(A: 0, B: 1, C: 2);
+tuple!(A: 0, B: 1, C: 2, D: 3);
+tuple!(A: 0, B: 1, C: 2, D: 3, E: 4);
+tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5);
+tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6);
+tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, I: 7);
+
+impl<H: ByteHash> Content<H> for Duration {
+    fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
+        sink.write_u64::<BigEndian>(self.as_secs())?;
+        sink.write_u32::<BigEndian>(self.subsec_nanos())
+    }
+
+    fn restore(source: &mut Source<H>) -> io::Result<Self> {
+        let secs = source.read_u64::<BigEndian>()?;
+        let nanos = source.read_u32::<BigEndian>()?;
+        // carrying over nanoseconds would map two encodings to one value
+        if nanos >= 1_000_000_000 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Invalid Duration encoding",
+            ));
+        }
+        Ok(Duration::new(secs, nanos))
+    }
+}
+
+impl<H: ByteHash> Content<H> for Ipv4Addr {
     fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
-        self.0.persist(sink)?;
-        self.1.persist(sink)
+        sink.write_all(&self.octets())
     }
 
     fn restore(source: &mut Source<H>) -> io::Result<Self> {
-        Ok((A::restore(source)?, B::restore(source)?))
+        let mut octets = [0u8; 4];
+        source.read_exact(&mut octets)?;
+        Ok(octets.into())
+    }
+}
+
+impl<H: ByteHash> Content<H> for Ipv6Addr {
+    fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
+        sink.write_all(&self.octets())
+    }
+
+    fn restore(source: &mut Source<H>) -> io::Result<Self> {
+        let mut octets = [0u8; 16];
+        source.read_exact(&mut octets)?;
+        Ok(octets.into())
+    }
+}
+
+impl<H: ByteHash> Content<H> for IpAddr {
+    fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
+        match self {
+            IpAddr::V4(addr) => {
+                sink.write_all(&[4])?;
+                addr.persist(sink)
+            }
+            IpAddr::V6(addr) => {
+                sink.write_all(&[6])?;
+                addr.persist(sink)
+            }
+        }
+    }
+
+    fn restore(source: &mut Source<H>) -> io::Result<Self> {
+        let mut byte = [0u8];
+        source.read_exact(&mut byte)?;
+        match byte[0] {
+            4 => Ok(IpAddr::V4(Ipv4Addr::restore(source)?)),
+            6 => Ok(IpAddr::V6(Ipv6Addr::restore(source)?)),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Invalid IpAddr encoding",
+            )),
+        }
+    }
+}
+
+impl<H: ByteHash> Content<H> for SocketAddrV4 {
+    fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
+        let mut ip = *self.ip();
+        ip.persist(sink)?;
+        sink.write_u16::<BigEndian>(self.port())
+    }
+
+    fn restore(source: &mut Source<H>) -> io::Result<Self> {
+        let ip = Ipv4Addr::restore(source)?;
+        Ok(SocketAddrV4::new(ip, source.read_u16::<BigEndian>()?))
+    }
+}
+
+impl<H: ByteHash> Content<H> for SocketAddrV6 {
+    // the flow info and scope id are part of the value, so they are kept
+    fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
+        let mut ip = *self.ip();
+        ip.persist(sink)?;
+        sink.write_u16::<BigEndian>(self.port())?;
+        sink.write_u32::<BigEndian>(self.flowinfo())?;
+        sink.write_u32::<BigEndian>(self.scope_id())
+    }
+
+    fn restore(source: &mut Source<H>) -> io::Result<Self> {
+        let ip = Ipv6Addr::restore(source)?;
+        let port = source.read_u16::<BigEndian>()?;
+        let flowinfo = source.read_u32::<BigEndian>()?;
+        let scope_id = source.read_u32::<BigEndian>()?;
+        Ok(SocketAddrV6::new(ip, port, flowinfo, scope_id))
+    }
+}
+
+impl<H: ByteHash> Content<H> for SocketAddr {
+    fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
+        match self {
+            SocketAddr::V4(addr) => {
+                sink.write_all(&[4])?;
+                addr.persist(sink)
+            }
+            SocketAddr::V6(addr) => {
+                sink.write_all(&[6])?;
+                addr.persist(sink)
+            }
+        }
+    }
+
+    fn restore(source: &mut Source<H>) -> io::Result<Self> {
+        let mut byte = [0u8];
+        source.read_exact(&mut byte)?;
+        match byte[0] {
+            4 => Ok(SocketAddr::V4(SocketAddrV4::restore(source)?)),
+            6 => Ok(SocketAddr::V6(SocketAddrV6::restore(source)?)),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Invalid SocketAddr encoding",
+            )),
+        }
     }
 }
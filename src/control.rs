@@ -0,0 +1,204 @@
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Token for cooperative cancellation of long-running operations
+///
+/// Clones share their state, one clone can be handed to the operation while
+/// another is kept around to cancel it.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    /// Creates a new, not yet cancelled, token
+    pub fn new() -> Self {
+        CancelToken::default()
+    }
+
+    /// Requests cancellation of all operations holding this token
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst)
+    }
+
+    /// Returns true if cancellation has been requested
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+type ProgressFn<'a> = Box<dyn FnMut(u64, Option<u64>) + 'a>;
+
+/// Controls a long-running operation, through cancellation and progress
+/// reporting
+///
+/// Operations call `advance` for every unit of work done, which reports the
+/// progress and returns an `Interrupted` error if the operation was cancelled.
+#[derive(Default)]
+pub struct Control<'a> {
+    token: Option<CancelToken>,
+    progress: Option<ProgressFn<'a>>,
+    done: u64,
+    total: Option<u64>,
+}
+
+impl<'a> fmt::Debug for Control<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Control({}/{:?})", self.done, self.total)
+    }
+}
+
+impl<'a> Control<'a> {
+    /// A control that never cancels and reports nothing
+    pub fn none() -> Self {
+        Control::default()
+    }
+
+    /// A control that can be cancelled through `token`
+    pub fn cancellable(token: CancelToken) -> Self {
+        Control {
+            token: Some(token),
+            ..Control::default()
+        }
+    }
+
+    /// Sets a callback, called with the units of work done so far and the
+    /// total, if known
+    pub fn with_progress<F>(mut self, progress: F) -> Self
+    where
+        F: FnMut(u64, Option<u64>) + 'a,
+    {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    /// Sets the total amount of work, used in progress reports
+    pub fn set_total(&mut self, total: u64) {
+        self.total = Some(total)
+    }
+
+    /// Returns the units of work done so far
+    pub fn done(&self) -> u64 {
+        self.done
+    }
+
+    /// Returns an error if the operation has been cancelled
+    pub fn check(&self) -> io::Result<()> {
+        match self.token {
+            Some(ref token) if token.is_cancelled() => Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                "Operation cancelled",
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Marks `n` units of work as done, and checks for cancellation
+    pub fn advance(&mut self, n: u64) -> io::Result<()> {
+        self.report(n);
+        self.check()
+    }
+
+    fn report(&mut self, n: u64) {
+        self.done += n;
+        if let Some(ref mut progress) = self.progress {
+            progress(self.done, self.total)
+        }
+    }
+}
+
+/// Iterator adapter checking a `Control` for every item
+///
+/// When cancelled, yields an `Interrupted` error and then stops.
+pub struct Controlled<'a, I> {
+    iter: I,
+    control: Control<'a>,
+    stopped: bool,
+}
+
+impl<'a, I, T> Iterator for Controlled<'a, I>
+where
+    I: Iterator<Item = io::Result<T>>,
+{
+    type Item = io::Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.stopped {
+            return None;
+        }
+        if let Err(e) = self.control.check() {
+            self.stopped = true;
+            return Some(Err(e));
+        }
+        let next = self.iter.next();
+        if next.is_some() {
+            // cancellation is picked up before yielding the next item
+            self.control.report(1);
+        }
+        next
+    }
+}
+
+/// Extension trait for controlling fallible iterators, such as the leaf
+/// iterators of a Compound
+pub trait ControlledIterator: Sized {
+    /// Wraps the iterator, checking `control` for every item
+    fn controlled(self, control: Control) -> Controlled<Self>;
+}
+
+impl<I, T> ControlledIterator for I
+where
+    I: Iterator<Item = io::Result<T>>,
+{
+    fn controlled(self, control: Control) -> Controlled<Self> {
+        Controlled {
+            iter: self,
+            control,
+            stopped: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn items(n: u64) -> impl Iterator<Item = io::Result<u64>> {
+        (0..n).map(Ok)
+    }
+
+    #[test]
+    fn reports_progress() {
+        let mut reports = vec![];
+        {
+            let mut control = Control::none()
+                .with_progress(|done, total| reports.push((done, total)));
+            control.set_total(3);
+            let collected: Vec<_> = items(3)
+                .controlled(control)
+                .collect::<io::Result<_>>()
+                .unwrap();
+            assert_eq!(collected, vec![0, 1, 2]);
+        }
+        assert_eq!(reports, vec![(1, Some(3)), (2, Some(3)), (3, Some(3))]);
+    }
+
+    #[test]
+    fn cancel_iteration() {
+        let token = CancelToken::new();
+        let canceller = token.clone();
+
+        let control =
+            Control::cancellable(token).with_progress(move |done, _| {
+                if done == 10 {
+                    canceller.cancel()
+                }
+            });
+
+        let results: Vec<_> = items(100).controlled(control).collect();
+
+        assert_eq!(results.len(), 11);
+        let err = results.last().unwrap().as_ref().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+    }
+}
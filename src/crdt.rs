@@ -0,0 +1,310 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+
+use bytehash::ByteHash;
+
+use crate::content::Content;
+use crate::gc::Reach;
+use crate::sink::Sink;
+use crate::source::Source;
+
+/// Identifies a replica taking part in replication
+pub type ReplicaId = u64;
+
+/// Values that can be merged without conflicts
+///
+/// Merging must be commutative, associative and idempotent, so that replicas
+/// exchanging their states in any order converge to the same value.
+pub trait Merge {
+    /// Merges `other` into `self`
+    fn merge(&mut self, other: &Self);
+}
+
+/// A last-writer-wins register
+///
+/// The value with the highest timestamp wins, ties are broken by the value
+/// itself, so that all replicas pick the same one.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LwwRegister<T> {
+    timestamp: u64,
+    value: T,
+}
+
+impl<T> LwwRegister<T> {
+    /// Creates a new register holding `value` written at `timestamp`
+    pub fn new(timestamp: u64, value: T) -> Self {
+        LwwRegister { timestamp, value }
+    }
+
+    /// Returns the current value
+    pub fn get(&self) -> &T {
+        &self.value
+    }
+
+    /// Returns the timestamp of the current value
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+}
+
+impl<T: Ord> LwwRegister<T> {
+    /// Writes `value` at `timestamp`, unless a later write is already present
+    pub fn set(&mut self, timestamp: u64, value: T) {
+        if (timestamp, &value) > (self.timestamp, &self.value) {
+            self.timestamp = timestamp;
+            self.value = value;
+        }
+    }
+}
+
+impl<T: Ord + Clone> Merge for LwwRegister<T> {
+    fn merge(&mut self, other: &Self) {
+        self.set(other.timestamp, other.value.clone())
+    }
+}
+
+impl<T, H> Content<H> for LwwRegister<T>
+where
+    T: Content<H>,
+    H: ByteHash,
+{
+    fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
+        self.timestamp.persist(sink)?;
+        self.value.persist(sink)
+    }
+
+    fn restore(source: &mut Source<H>) -> io::Result<Self> {
+        Ok(LwwRegister {
+            timestamp: u64::restore(source)?,
+            value: T::restore(source)?,
+        })
+    }
+
+    fn reach<R: Reach<H>>(&self, reach: &mut R) -> io::Result<()> {
+        self.value.reach(reach)
+    }
+}
+
+/// A grow-only counter
+///
+/// Every replica only increments its own count, the value of the counter is
+/// the sum of all counts.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GCounter(BTreeMap<ReplicaId, u64>);
+
+impl GCounter {
+    /// Creates a new counter at zero
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Increments the count of `replica` by `n`
+    pub fn increment(&mut self, replica: ReplicaId, n: u64) {
+        *self.0.entry(replica).or_insert(0) += n
+    }
+
+    /// Returns the value of the counter
+    pub fn value(&self) -> u64 {
+        self.0.values().sum()
+    }
+}
+
+impl Merge for GCounter {
+    fn merge(&mut self, other: &Self) {
+        for (replica, count) in &other.0 {
+            let own = self.0.entry(*replica).or_insert(0);
+            if *count > *own {
+                *own = *count
+            }
+        }
+    }
+}
+
+impl<H: ByteHash> Content<H> for GCounter {
+    fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
+        self.0.persist(sink)
+    }
+
+    fn restore(source: &mut Source<H>) -> io::Result<Self> {
+        Ok(GCounter(BTreeMap::restore(source)?))
+    }
+}
+
+// A unique tag for an insertion, the replica and its insertion sequence
+type Tag = (ReplicaId, u64);
+
+/// An observed-remove set
+///
+/// Removing an element only removes the insertions of it that were observed
+/// by the replica, so an insertion concurrent with a removal wins.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ORSet<T: Ord> {
+    elements: BTreeMap<T, BTreeSet<Tag>>,
+    removed: BTreeSet<Tag>,
+    clock: BTreeMap<ReplicaId, u64>,
+}
+
+impl<T: Ord> Default for ORSet<T> {
+    fn default() -> Self {
+        ORSet {
+            elements: BTreeMap::new(),
+            removed: BTreeSet::new(),
+            clock: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T: Ord + Clone> ORSet<T> {
+    /// Creates a new, empty, set
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Inserts `value`, as seen by `replica`
+    pub fn insert(&mut self, replica: ReplicaId, value: T) {
+        let seq = self.clock.entry(replica).or_insert(0);
+        *seq += 1;
+        let tag = (replica, *seq);
+        self.elements.entry(value).or_default().insert(tag);
+    }
+
+    /// Removes all observed insertions of `value`
+    pub fn remove(&mut self, value: &T) {
+        if let Some(tags) = self.elements.remove(value) {
+            self.removed.extend(tags)
+        }
+    }
+
+    /// Returns true if the set contains `value`
+    pub fn contains(&self, value: &T) -> bool {
+        self.elements.contains_key(value)
+    }
+
+    /// Iterates over the elements of the set, in order
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.elements.keys()
+    }
+}
+
+impl<T: Ord + Clone> Merge for ORSet<T> {
+    fn merge(&mut self, other: &Self) {
+        self.removed.extend(other.removed.iter().cloned());
+        for (value, tags) in &other.elements {
+            self.elements
+                .entry(value.clone())
+                .or_default()
+                .extend(tags.iter().cloned());
+        }
+        let removed = &self.removed;
+        for tags in self.elements.values_mut() {
+            tags.retain(|tag| !removed.contains(tag));
+        }
+        self.elements.retain(|_, tags| !tags.is_empty());
+        for (replica, seq) in &other.clock {
+            let own = self.clock.entry(*replica).or_insert(0);
+            if *seq > *own {
+                *own = *seq
+            }
+        }
+    }
+}
+
+impl<T, H> Content<H> for ORSet<T>
+where
+    T: Content<H> + Ord,
+    H: ByteHash,
+{
+    fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
+        self.elements.persist(sink)?;
+        self.removed.persist(sink)?;
+        self.clock.persist(sink)
+    }
+
+    fn restore(source: &mut Source<H>) -> io::Result<Self> {
+        Ok(ORSet {
+            elements: BTreeMap::restore(source)?,
+            removed: BTreeSet::restore(source)?,
+            clock: BTreeMap::restore(source)?,
+        })
+    }
+
+    fn reach<R: Reach<H>>(&self, reach: &mut R) -> io::Result<()> {
+        self.elements.reach(reach)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Blake2b;
+
+    fn converges<T: Merge + Clone + PartialEq + std::fmt::Debug>(a: T, b: T) {
+        let mut ab = a.clone();
+        ab.merge(&b);
+        let mut ba = b.clone();
+        ba.merge(&a);
+        assert_eq!(ab, ba);
+
+        let mut abb = ab.clone();
+        abb.merge(&b);
+        assert_eq!(abb, ab);
+    }
+
+    #[test]
+    fn lww_register() {
+        let mut a = LwwRegister::new(1, 10u64);
+        let b = LwwRegister::new(2, 5u64);
+        converges(a.clone(), b.clone());
+        a.merge(&b);
+        assert_eq!(*a.get(), 5);
+
+        // ties are broken by value
+        converges(LwwRegister::new(3, 1u64), LwwRegister::new(3, 2u64));
+    }
+
+    #[test]
+    fn g_counter() {
+        let mut a = GCounter::new();
+        let mut b = GCounter::new();
+        a.increment(0, 3);
+        b.increment(1, 4);
+        b.increment(0, 1);
+        converges(a.clone(), b.clone());
+        a.merge(&b);
+        assert_eq!(a.value(), 7);
+    }
+
+    #[test]
+    fn or_set_add_wins() {
+        let mut a = ORSet::new();
+        a.insert(0, 1u64);
+        a.insert(0, 2u64);
+
+        let mut b = a.clone();
+        // concurrently, `a` removes 1 while `b` inserts it again
+        a.remove(&1);
+        b.insert(1, 1);
+        b.remove(&2);
+        converges(a.clone(), b.clone());
+
+        a.merge(&b);
+        assert!(a.contains(&1));
+        assert!(!a.contains(&2));
+        assert_eq!(a.iter().collect::<Vec<_>>(), vec![&1]);
+    }
+
+    #[test]
+    fn persist_and_restore() {
+        let mut set = ORSet::new();
+        set.insert(0, 1u64);
+        set.insert(1, 2u64);
+        set.remove(&1);
+        let mut counter = GCounter::new();
+        counter.increment(3, 4);
+
+        let store = crate::Store::<Blake2b>::ephemeral();
+        let mut state = (set, (counter, LwwRegister::new(1, 2u64)));
+        let snapshot = store.persist(&mut state).unwrap();
+        assert!(store.restore(&snapshot).unwrap() == state);
+    }
+}
@@ -0,0 +1,145 @@
+use std::io;
+
+use bytehash::ByteHash;
+use cache::Cached;
+
+use crate::compound::Compound;
+use crate::raw_branch::RawBranch;
+use crate::search::{First, Last, Method};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    // before the first leaf
+    Start,
+    At,
+    // past the last leaf
+    End,
+}
+
+/// A cursor for navigating a `Compound<H>`, keeping its path cached
+///
+/// Unlike a `Branch`, the cursor moves both ways, and seeking keeps the
+/// nodes of the current path that the new path goes through, so nearby
+/// lookups only restore the nodes that differ. A new cursor is placed
+/// before the first leaf.
+pub struct Cursor<'a, C, H> {
+    root: &'a C,
+    branch: RawBranch<'a, C, H>,
+    state: State,
+}
+
+impl<'a, C, H> Cursor<'a, C, H>
+where
+    C: Compound<H>,
+    H: ByteHash,
+{
+    /// Creates a cursor over `root`, placed before its first leaf
+    pub fn new(root: &'a C) -> Self {
+        Cursor {
+            root,
+            branch: RawBranch::new_cached(Cached::Borrowed(root)),
+            state: State::Start,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.branch = RawBranch::new_cached(Cached::Borrowed(self.root));
+    }
+
+    /// Moves to where `method` leads, returning the leaf found if it is an
+    /// exact match
+    ///
+    /// Following `next` and `prev` calls move from there, whether or not
+    /// the match was exact.
+    pub fn seek<M: Method<C, H>>(
+        &mut self,
+        method: &mut M,
+    ) -> io::Result<Option<&C::Leaf>> {
+        if self.state != State::At {
+            self.reset();
+        }
+        self.branch.reseek(method)?;
+        self.state = State::At;
+        Ok(if self.branch.exact() {
+            self.branch.leaf()
+        } else {
+            None
+        })
+    }
+
+    /// Moves to the next leaf, and returns it
+    ///
+    /// When pointing at a subtree, after `parent`, moves to the first leaf
+    /// after it.
+    pub fn next(&mut self) -> io::Result<Option<&C::Leaf>> {
+        match self.state {
+            State::End => return Ok(None),
+            State::Start => {
+                self.reset();
+                self.branch.search(&mut First)?;
+            }
+            State::At => {
+                self.branch.set_reverse(false);
+                self.branch.advance();
+                self.branch.search(&mut First)?;
+            }
+        }
+        self.settle(State::End)
+    }
+
+    /// Moves to the previous leaf, and returns it
+    ///
+    /// When pointing at a subtree, after `parent`, moves to the last leaf
+    /// before it.
+    pub fn prev(&mut self) -> io::Result<Option<&C::Leaf>> {
+        match self.state {
+            State::Start => return Ok(None),
+            State::End => {
+                self.reset();
+                self.branch.search(&mut Last)?;
+            }
+            State::At => {
+                self.branch.set_reverse(true);
+                self.branch.advance();
+                self.branch.search(&mut Last)?;
+            }
+        }
+        self.settle(State::Start)
+    }
+
+    // Points at the leaf found, or past the end in the direction searched
+    fn settle(&mut self, past: State) -> io::Result<Option<&C::Leaf>> {
+        if self.branch.leaf().is_some() {
+            self.state = State::At;
+        } else {
+            self.state = past;
+        }
+        Ok(self.branch.leaf())
+    }
+
+    /// Moves up to the subtree holding the current position, returning
+    /// false at the root
+    pub fn parent(&mut self) -> bool {
+        self.state == State::At && self.branch.ascend()
+    }
+
+    /// Returns the leaf pointed at, if any
+    pub fn value(&self) -> Option<&C::Leaf> {
+        match self.state {
+            State::At => self.branch.leaf(),
+            _ => None,
+        }
+    }
+
+    /// Returns the depth of the current position, zero for the children of
+    /// the root
+    pub fn depth(&self) -> usize {
+        self.branch.depth() - 1
+    }
+
+    /// Returns the indices of the children followed from the root down to
+    /// the current position
+    pub fn position(&self) -> Vec<usize> {
+        self.branch.position()
+    }
+}
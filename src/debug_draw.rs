@@ -1,3 +1,14 @@
+use std::fmt;
+use std::marker::PhantomData;
+
+use bytehash::ByteHash;
+
+use crate::compound::Compound;
+use crate::iter::LeafIterable;
+
+// Number of leaves shown by a `Summary`
+const SUMMARY_LEAVES: usize = 3;
+
 /// The state of drawing
 #[derive(Default)]
 pub struct DrawState {
@@ -25,3 +36,66 @@ pub trait DebugDraw<H> {
         self.draw_conf(&mut DrawState::default())
     }
 }
+
+/// A bounded `Debug` summary of a compound
+///
+/// Shows the direct children, with persisted nodes as digest prefixes, and
+/// the first few leaves. Only the nodes on the path to those leaves are
+/// loaded, so summarizing a large persisted structure is cheap.
+pub struct Summary<'a, C, H> {
+    name: &'static str,
+    compound: &'a C,
+    _marker: PhantomData<H>,
+}
+
+impl<'a, C, H> Summary<'a, C, H> {
+    /// Creates a summary of `compound`, displayed as `name`
+    pub fn new(name: &'static str, compound: &'a C) -> Self {
+        Summary {
+            name,
+            compound,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, C, H> fmt::Debug for Summary<'a, C, H>
+where
+    C: Compound<H>,
+    C::Leaf: fmt::Debug,
+    H: ByteHash,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut leaves = vec![];
+        let mut more = false;
+        for leaf in self.compound.iter() {
+            if leaves.len() == SUMMARY_LEAVES {
+                more = true;
+                break;
+            }
+            match leaf {
+                Ok(leaf) => leaves.push(format!("{:?}", leaf)),
+                Err(e) => {
+                    leaves.push(format!("<{}>", e));
+                    break;
+                }
+            }
+        }
+        if more {
+            leaves.push("..".into());
+        }
+        f.debug_struct(self.name)
+            .field("children", &self.compound.children())
+            .field("leaves", &Leaves(leaves))
+            .finish()
+    }
+}
+
+// Preformatted leaves, printed without quotes
+struct Leaves(Vec<String>);
+
+impl fmt::Debug for Leaves {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{}]", self.0.join(", "))
+    }
+}
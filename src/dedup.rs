@@ -0,0 +1,156 @@
+use std::collections::{HashSet, VecDeque};
+use std::hash::Hash;
+use std::io;
+
+use bytehash::ByteHash;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::content::Content;
+use crate::gc::Reach;
+use crate::sink::Sink;
+use crate::source::Source;
+
+/// A structure that applies each identified operation at most once
+///
+/// The ids of the last `capacity` applied operations are kept in a window,
+/// persisted together with the state, so that a committed root always
+/// records exactly which operations it includes. Retried or replayed
+/// operations whose id is still in the window are skipped.
+#[derive(Clone)]
+pub struct Dedup<I, T> {
+    state: T,
+    window: VecDeque<I>,
+    seen: HashSet<I>,
+    capacity: usize,
+}
+
+impl<I, T> Dedup<I, T>
+where
+    I: Clone + Eq + Hash,
+{
+    /// Wraps `state`, remembering the last `capacity` operation ids
+    pub fn new(state: T, capacity: usize) -> Self {
+        Dedup {
+            state,
+            window: VecDeque::with_capacity(capacity),
+            seen: HashSet::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Returns a reference to the state
+    pub fn state(&self) -> &T {
+        &self.state
+    }
+
+    /// Returns true if the operation `id` is in the window
+    pub fn contains(&self, id: &I) -> bool {
+        self.seen.contains(id)
+    }
+
+    /// Applies `f` to the state, unless `id` was already applied
+    ///
+    /// Returns `None` if the operation was skipped. If `f` fails, the id is
+    /// not recorded, and any partial changes it made are left in the state.
+    pub fn apply<F, R>(&mut self, id: I, f: F) -> io::Result<Option<R>>
+    where
+        F: FnOnce(&mut T) -> io::Result<R>,
+    {
+        if self.seen.contains(&id) {
+            return Ok(None);
+        }
+        let result = f(&mut self.state)?;
+        self.record(id);
+        Ok(Some(result))
+    }
+
+    fn record(&mut self, id: I) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.window.len() == self.capacity {
+            if let Some(oldest) = self.window.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+        self.seen.insert(id.clone());
+        self.window.push_back(id);
+    }
+}
+
+impl<I, T, H> Content<H> for Dedup<I, T>
+where
+    I: Content<H> + Eq + Hash,
+    T: Content<H>,
+    H: ByteHash,
+{
+    fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
+        sink.write_u64::<BigEndian>(self.capacity as u64)?;
+        sink.write_u64::<BigEndian>(self.window.len() as u64)?;
+        for id in self.window.iter_mut() {
+            id.persist(sink)?;
+        }
+        self.state.persist(sink)
+    }
+
+    fn restore(source: &mut Source<H>) -> io::Result<Self> {
+        let capacity = source.read_u64::<BigEndian>()? as usize;
+        let len = source.read_u64::<BigEndian>()? as usize;
+        let mut window = VecDeque::with_capacity(len);
+        let mut seen = HashSet::with_capacity(len);
+        for _ in 0..len {
+            let id = I::restore(source)?;
+            seen.insert(id.clone());
+            window.push_back(id);
+        }
+        Ok(Dedup {
+            state: T::restore(source)?,
+            window,
+            seen,
+            capacity,
+        })
+    }
+
+    fn reach<R: Reach<H>>(&self, reach: &mut R) -> io::Result<()> {
+        for id in self.window.iter() {
+            id.reach(reach)?;
+        }
+        self.state.reach(reach)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Blake2b, Store};
+
+    #[test]
+    fn skip_replayed() {
+        let store = Store::<Blake2b>::ephemeral();
+        let mut dedup = Dedup::<u64, u64>::new(0, 4);
+
+        for id in 0..4 {
+            dedup.apply(id, |s| Ok(*s += 1)).unwrap();
+        }
+
+        // the window survives a persist and restore
+        let snapshot = store.persist(&mut dedup).unwrap();
+        let mut dedup = store.restore(&snapshot).unwrap();
+
+        for id in 0..4 {
+            assert!(dedup.apply(id, |s| Ok(*s += 1)).unwrap().is_none());
+        }
+        assert_eq!(*dedup.state(), 4);
+
+        // id 0 falls out of the window
+        dedup.apply(4, |s| Ok(*s += 1)).unwrap();
+        assert!(!dedup.contains(&0));
+        assert!(dedup.contains(&4));
+
+        // failed operations are not recorded
+        let err: io::Result<Option<()>> = dedup
+            .apply(5, |_| Err(io::Error::new(io::ErrorKind::Other, "failed")));
+        assert!(err.is_err());
+        assert!(!dedup.contains(&5));
+    }
+}
@@ -0,0 +1,437 @@
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::io;
+
+use bytehash::ByteHash;
+
+use crate::annotations::{ByteSize, Cardinality};
+use crate::compound::Compound;
+use crate::content::Content;
+use crate::handle::{Handle, HandleRef};
+use crate::map::KV;
+use crate::sink::Sink;
+use crate::store::{Snapshot, Store};
+
+/// Summary of the changes between two versions of a map
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DiffStats {
+    /// Number of keys only present in the new version
+    pub added: u64,
+    /// Number of keys only present in the old version
+    pub removed: u64,
+    /// Number of keys present in both versions, with different values
+    pub modified: u64,
+    /// Change in the encoded size of all leaves, in bytes
+    pub bytes: i64,
+}
+
+/// A change to a key between two versions of a map
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change<K, V> {
+    /// The key is only present in the new version
+    Added(K, V),
+    /// The key is only present in the old version
+    Removed(K, V),
+    /// The key is present in both versions, with the old and the new value
+    Modified(K, V, V),
+}
+
+/// Returns the changes from `old` to `new`, one for each key that differs
+///
+/// Like `diff_stats`, identical subtrees are skipped by their digest. Values
+/// are compared by their encoding. The changes are in no particular order.
+///
+/// Both versions are walked lazily, as the changes are consumed. Modified
+/// values are yielded as soon as both versions are found, keys present in
+/// only one version once the walk is done, as they might have moved to
+/// another subtree of the other version.
+pub fn diff<C, K, V, H>(
+    old: &Snapshot<C, H>,
+    new: &Snapshot<C, H>,
+) -> io::Result<Diff<C, K, V, H>>
+where
+    C: Compound<H, Leaf = KV<K, V>>,
+    K: Content<H> + Eq + Hash,
+    V: Content<H>,
+    H: ByteHash,
+{
+    let mut stack = vec![];
+    if old.hash() != new.hash() {
+        stack.push(Pair {
+            old: old.restore()?,
+            new: new.restore()?,
+            next: 0,
+        })
+    }
+    Ok(Diff {
+        stack,
+        removed: HashMap::new(),
+        added: HashMap::new(),
+        ready: vec![],
+        scratch: Store::ephemeral(),
+    })
+}
+
+/// The changes between two versions of a map, see `diff`
+pub struct Diff<C, K, V, H: ByteHash> {
+    // nodes that differ, with the index of the next children to compare
+    stack: Vec<Pair<C>>,
+    // leaves not yet found in the other version, with their encoded values
+    removed: HashMap<K, (V, Vec<u8>)>,
+    added: HashMap<K, (V, Vec<u8>)>,
+    ready: Vec<Change<K, V>>,
+    // values are encoded into a scratch store, only to compare them
+    scratch: Store<H>,
+}
+
+struct Pair<C> {
+    old: C,
+    new: C,
+    next: usize,
+}
+
+impl<C, K, V, H> Diff<C, K, V, H>
+where
+    C: Compound<H, Leaf = KV<K, V>>,
+    K: Content<H> + Eq + Hash,
+    V: Content<H>,
+    H: ByteHash,
+{
+    // Compares the next children of the nodes on top of the stack, returning
+    // false once the walk is done
+    fn step(&mut self) -> io::Result<bool> {
+        let empty = Handle::new_empty();
+        let (mut olds, mut news) = (vec![], vec![]);
+        let push = match self.stack.last_mut() {
+            None => return Ok(false),
+            Some(pair) => {
+                let i = pair.next;
+                let (old, new) = (pair.old.children(), pair.new.children());
+                if i >= old.len().max(new.len()) {
+                    self.stack.pop();
+                    return Ok(true);
+                }
+                pair.next += 1;
+                let a = old.get(i).unwrap_or(&empty);
+                let b = new.get(i).unwrap_or(&empty);
+                if let (Some(x), Some(y)) = (a.digest(), b.digest()) {
+                    if x == y {
+                        return Ok(true);
+                    }
+                }
+                match (a.inner()?, b.inner()?) {
+                    (HandleRef::Node(x), HandleRef::Node(y)) => Some(Pair {
+                        old: (*x).clone(),
+                        new: (*y).clone(),
+                        next: 0,
+                    }),
+                    (x, y) => {
+                        leaves(x, &mut olds)?;
+                        leaves(y, &mut news)?;
+                        None
+                    }
+                }
+            }
+        };
+        if let Some(pair) = push {
+            self.stack.push(pair)
+        }
+
+        for mut leaf in olds {
+            let bytes = value_bytes(&mut leaf.val, &self.scratch)?;
+            match self.added.remove(&leaf.key) {
+                Some((new, new_bytes)) => {
+                    if new_bytes != bytes {
+                        self.ready
+                            .push(Change::Modified(leaf.key, leaf.val, new))
+                    }
+                }
+                None => {
+                    self.removed.insert(leaf.key, (leaf.val, bytes));
+                }
+            }
+        }
+        for mut leaf in news {
+            let bytes = value_bytes(&mut leaf.val, &self.scratch)?;
+            match self.removed.remove(&leaf.key) {
+                Some((old, old_bytes)) => {
+                    if old_bytes != bytes {
+                        self.ready
+                            .push(Change::Modified(leaf.key, old, leaf.val))
+                    }
+                }
+                None => {
+                    self.added.insert(leaf.key, (leaf.val, bytes));
+                }
+            }
+        }
+        Ok(true)
+    }
+}
+
+impl<C, K, V, H> Iterator for Diff<C, K, V, H>
+where
+    C: Compound<H, Leaf = KV<K, V>>,
+    K: Content<H> + Eq + Hash,
+    V: Content<H>,
+    H: ByteHash,
+{
+    type Item = io::Result<Change<K, V>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(change) = self.ready.pop() {
+                return Some(Ok(change));
+            }
+            match self.step() {
+                Ok(true) => (),
+                Ok(false) => break,
+                Err(e) => {
+                    self.stack.clear();
+                    return Some(Err(e));
+                }
+            }
+        }
+        // the walk is done, the keys left are in one version only
+        for (key, (val, _)) in self.removed.drain() {
+            self.ready.push(Change::Removed(key, val))
+        }
+        for (key, (val, _)) in self.added.drain() {
+            self.ready.push(Change::Added(key, val))
+        }
+        self.ready.pop().map(Ok)
+    }
+}
+
+/// Summarizes the changes from `old` to `new`
+///
+/// Subtrees that are identical in both versions are recognized by their
+/// digest and skipped, and subtrees present in only one version are counted
+/// from their `Cardinality` and `ByteSize` annotations, so the cost depends
+/// on the size of the change, not on the size of the maps. The leaves of the
+/// other subtrees that differ are compared by key.
+///
+/// Keys are only matched within subtrees differing at the same position.
+/// This is exact for structures placing keys by their hash, like HAMT, but
+/// in ordered structures a key moved to a subtree missing from the other
+/// version is counted as both removed and added.
+pub fn diff_stats<C, K, V, H>(
+    old: &Snapshot<C, H>,
+    new: &Snapshot<C, H>,
+) -> io::Result<DiffStats>
+where
+    C: Compound<H, Leaf = KV<K, V>>,
+    C::Annotation: Borrow<Cardinality<u64>> + Borrow<ByteSize<H>>,
+    K: Content<H> + Eq + Hash,
+    V: Content<H>,
+    H: ByteHash,
+{
+    let mut stats = DiffStats::default();
+    if old.hash() == new.hash() {
+        return Ok(stats);
+    }
+
+    let (mut olds, mut news) = (vec![], vec![]);
+    tally(
+        old.restore()?.children(),
+        new.restore()?.children(),
+        &mut olds,
+        &mut news,
+        &mut stats,
+    )?;
+
+    // leaves are encoded into a scratch store, only to compare and size them
+    let scratch = Store::ephemeral();
+    let mut removed = HashMap::with_capacity(olds.len());
+    for leaf in olds {
+        let (key, val, size) = encode(leaf, &scratch)?;
+        stats.bytes -= size as i64;
+        removed.insert(key, val);
+    }
+    for leaf in news {
+        let (key, val, size) = encode(leaf, &scratch)?;
+        stats.bytes += size as i64;
+        match removed.remove(&key) {
+            Some(old_val) => {
+                if old_val != val {
+                    stats.modified += 1
+                }
+            }
+            None => stats.added += 1,
+        }
+    }
+    stats.removed += removed.len() as u64;
+    Ok(stats)
+}
+
+// Like `changed`, counting the subtrees present in only one version into
+// `stats` from their annotations rather than collecting their leaves
+fn tally<C, H>(
+    old: &[Handle<C, H>],
+    new: &[Handle<C, H>],
+    olds: &mut Vec<C::Leaf>,
+    news: &mut Vec<C::Leaf>,
+    stats: &mut DiffStats,
+) -> io::Result<()>
+where
+    C: Compound<H>,
+    C::Annotation: Borrow<Cardinality<u64>> + Borrow<ByteSize<H>>,
+    H: ByteHash,
+{
+    let empty = Handle::new_empty();
+    for i in 0..old.len().max(new.len()) {
+        let a = old.get(i).unwrap_or(&empty);
+        let b = new.get(i).unwrap_or(&empty);
+        if let (Some(x), Some(y)) = (a.digest(), b.digest()) {
+            if x == y {
+                continue;
+            }
+        }
+        match (a.inner()?, b.inner()?) {
+            (HandleRef::Node(x), HandleRef::Node(y)) => {
+                tally(x.children(), y.children(), olds, news, stats)?
+            }
+            (_, HandleRef::None) => {
+                let (count, size) = counted(a);
+                stats.removed += count;
+                stats.bytes -= size as i64;
+            }
+            (HandleRef::None, _) => {
+                let (count, size) = counted(b);
+                stats.added += count;
+                stats.bytes += size as i64;
+            }
+            (x, y) => {
+                leaves(x, olds)?;
+                leaves(y, news)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Returns the number of leaves below `handle`, and their encoded size
+fn counted<C, H>(handle: &Handle<C, H>) -> (u64, u64)
+where
+    C: Compound<H>,
+    C::Annotation: Borrow<Cardinality<u64>> + Borrow<ByteSize<H>>,
+    H: ByteHash,
+{
+    match handle.annotation() {
+        Some(ann) => {
+            let count: &Cardinality<u64> = (*ann).borrow();
+            let size: &ByteSize<H> = (*ann).borrow();
+            (**count, **size)
+        }
+        None => (0, 0),
+    }
+}
+
+// Leaves of the subtrees that differ, in the old and in the new version
+type Changed<L> = (Vec<L>, Vec<L>);
+
+// Returns the leaves of all subtrees that differ, in `old` and in `new`
+pub(crate) fn changed_leaves<C, H>(
+    old: &Snapshot<C, H>,
+    new: &Snapshot<C, H>,
+) -> io::Result<Changed<C::Leaf>>
+where
+    C: Compound<H>,
+    H: ByteHash,
+{
+    let (mut olds, mut news) = (vec![], vec![]);
+    if old.hash() != new.hash() {
+        changed(
+            old.restore()?.children(),
+            new.restore()?.children(),
+            &mut olds,
+            &mut news,
+        )?;
+    }
+    Ok((olds, news))
+}
+
+// Collects the leaves of all subtrees that differ between `old` and `new`
+fn changed<C, H>(
+    old: &[Handle<C, H>],
+    new: &[Handle<C, H>],
+    olds: &mut Vec<C::Leaf>,
+    news: &mut Vec<C::Leaf>,
+) -> io::Result<()>
+where
+    C: Compound<H>,
+    H: ByteHash,
+{
+    let empty = Handle::new_empty();
+    for i in 0..old.len().max(new.len()) {
+        let a = old.get(i).unwrap_or(&empty);
+        let b = new.get(i).unwrap_or(&empty);
+        if let (Some(x), Some(y)) = (a.digest(), b.digest()) {
+            if x == y {
+                continue;
+            }
+        }
+        match (a.inner()?, b.inner()?) {
+            (HandleRef::Node(x), HandleRef::Node(y)) => {
+                changed(x.children(), y.children(), olds, news)?
+            }
+            (x, y) => {
+                leaves(x, olds)?;
+                leaves(y, news)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn leaves<C, H>(
+    handle: HandleRef<C, H>,
+    out: &mut Vec<C::Leaf>,
+) -> io::Result<()>
+where
+    C: Compound<H>,
+    H: ByteHash,
+{
+    match handle {
+        HandleRef::Leaf(l) => out.push(l.clone()),
+        HandleRef::Node(n) => {
+            for child in n.children() {
+                leaves(child.inner()?, out)?
+            }
+        }
+        HandleRef::None => (),
+    }
+    Ok(())
+}
+
+// Returns the key, the encoded value, and the encoded size of the leaf
+fn encode<K, V, H>(
+    mut leaf: KV<K, V>,
+    scratch: &Store<H>,
+) -> io::Result<(K, Vec<u8>, usize)>
+where
+    K: Content<H>,
+    V: Content<H>,
+    H: ByteHash,
+{
+    let mut sink = Sink::new(scratch);
+    leaf.key.persist(&mut sink)?;
+    let key_size = sink.bytes().len();
+    leaf.val.persist(&mut sink)?;
+    let size = sink.bytes().len();
+    Ok((leaf.key, sink.bytes()[key_size..].to_vec(), size))
+}
+
+pub(crate) fn value_bytes<V, H>(
+    val: &mut V,
+    scratch: &Store<H>,
+) -> io::Result<Vec<u8>>
+where
+    V: Content<H>,
+    H: ByteHash,
+{
+    let mut sink = Sink::new(scratch);
+    val.persist(&mut sink)?;
+    Ok(sink.bytes().to_vec())
+}
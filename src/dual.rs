@@ -0,0 +1,83 @@
+use std::io;
+use std::marker::PhantomData;
+
+use bytehash::ByteHash;
+
+use crate::compound::Compound;
+use crate::control::Control;
+use crate::map::{MapMut, KV};
+use crate::migrate::rehash;
+use crate::store::{Snapshot, Store};
+
+/// A map kept under two hashes, for the transition from `H1` to `H2`
+///
+/// Every write is applied to both the map using the old hash and the one
+/// using the new hash, and both are persisted together. Participants that
+/// have not switched yet keep reading, and verifying proofs against, the old
+/// roots, while the others already use the new ones. When the transition is
+/// over, `into_new` drops the old map.
+pub struct DualMap<C1, C2, H1, H2> {
+    old: C1,
+    new: C2,
+    _marker: PhantomData<(H1, H2)>,
+}
+
+impl<C1, C2, K, V, H1, H2> DualMap<C1, C2, H1, H2>
+where
+    C1: MapMut<K, V, H1> + Compound<H1, Leaf = KV<K, V>>,
+    C2: MapMut<K, V, H2> + Default,
+    K: Clone,
+    V: Clone,
+    H1: ByteHash,
+    H2: ByteHash,
+{
+    /// Starts the transition of `old`, building the map under the new hash
+    pub fn new(old: C1, control: &mut Control<'_>) -> io::Result<Self> {
+        let new = rehash(&old, control)?;
+        Ok(DualMap {
+            old,
+            new,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Insert key-value pair into both maps, returning the value replaced in
+    /// the old one
+    pub fn insert(&mut self, k: K, v: V) -> io::Result<Option<V>> {
+        self.new.insert(k.clone(), v.clone())?;
+        self.old.insert(k, v)
+    }
+
+    /// Remove the value at key from both maps, returning it
+    pub fn remove(&mut self, k: &K) -> io::Result<Option<V>> {
+        self.new.remove(k)?;
+        self.old.remove(k)
+    }
+
+    /// Returns the map under the old hash
+    pub fn old(&self) -> &C1 {
+        &self.old
+    }
+
+    /// Returns the map under the new hash
+    pub fn new_map(&self) -> &C2 {
+        &self.new
+    }
+
+    /// Persists both maps, into their respective stores
+    pub fn persist(
+        &mut self,
+        old: &Store<H1>,
+        new: &Store<H2>,
+    ) -> io::Result<(Snapshot<C1, H1>, Snapshot<C2, H2>)>
+    where
+        C2: Compound<H2>,
+    {
+        Ok((old.persist(&mut self.old)?, new.persist(&mut self.new)?))
+    }
+
+    /// Ends the transition, returning the map under the new hash
+    pub fn into_new(self) -> C2 {
+        self.new
+    }
+}
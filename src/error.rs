@@ -0,0 +1,122 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+use crate::schema::Mismatch;
+
+/// The errors of kelvin operations
+///
+/// The APIs of kelvin return `io::Result`, these errors are carried inside
+/// the `io::Error`, and recovered by converting it back with `Error::from`.
+#[derive(Debug)]
+pub enum Error {
+    /// An IO error, from the backend or from user code
+    Io(io::Error),
+    /// Data that could not be decoded
+    InvalidEncoding(&'static str),
+    /// A node whose contents do not match its digest
+    Corrupted,
+    /// A node missing from the store, with its digest
+    MissingHash(Vec<u8>),
+    /// Stored collections that do not match their registered schemas
+    Schema(Vec<Mismatch>),
+    /// A node written in a format version newer than this build reads
+    UnsupportedVersion(u8),
+}
+
+/// Result type using the kelvin `Error`
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Returns the `io::ErrorKind` the error is surfaced as
+    pub fn kind(&self) -> io::ErrorKind {
+        match self {
+            Error::Io(e) => e.kind(),
+            Error::InvalidEncoding(_)
+            | Error::Corrupted
+            | Error::Schema(_)
+            | Error::UnsupportedVersion(_) => io::ErrorKind::InvalidData,
+            Error::MissingHash(_) => io::ErrorKind::NotFound,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::InvalidEncoding(what) => write!(f, "{}", what),
+            Error::Corrupted => write!(f, "Digest mismatch"),
+            Error::MissingHash(digest) => {
+                write!(f, "Data not found: ")?;
+                for byte in digest {
+                    write!(f, "{:02x}", byte)?;
+                }
+                Ok(())
+            }
+            Error::Schema(mismatches) => {
+                write!(f, "Schema mismatch")?;
+                for (i, mismatch) in mismatches.iter().enumerate() {
+                    let sep = if i == 0 { ": " } else { "; " };
+                    write!(f, "{}{}", sep, mismatch)?;
+                }
+                Ok(())
+            }
+            Error::UnsupportedVersion(version) => {
+                write!(f, "Unsupported format version {}", version)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        match e.get_ref().map(|inner| inner.is::<Error>()) {
+            Some(true) => *e
+                .into_inner()
+                .and_then(|inner| inner.downcast().ok())
+                .expect("checked above"),
+            _ => Error::Io(e),
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Io(e) => e,
+            e => io::Error::new(e.kind(), e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let io: io::Error = Error::MissingHash(vec![0xab, 0xcd]).into();
+        assert_eq!(io.kind(), io::ErrorKind::NotFound);
+        assert_eq!(io.to_string(), "Data not found: abcd");
+        match Error::from(io) {
+            Error::MissingHash(digest) => assert_eq!(digest, vec![0xab, 0xcd]),
+            e => panic!("{:?}", e),
+        }
+
+        let io = io::Error::new(io::ErrorKind::InvalidInput, "Other");
+        match Error::from(io) {
+            Error::Io(e) => assert_eq!(e.kind(), io::ErrorKind::InvalidInput),
+            e => panic!("{:?}", e),
+        }
+    }
+}
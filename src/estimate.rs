@@ -0,0 +1,132 @@
+use std::borrow::Borrow;
+use std::io;
+
+use rand::Rng;
+
+use crate::annotations::{Cardinality, Nth};
+use crate::branch::Branch;
+use crate::compound::Compound;
+use crate::iter::LeafIterable;
+use crate::ByteHash;
+
+// Standard score of the default, 95%, confidence level
+const Z_95: f64 = 1.96;
+
+/// An estimate of the number of leaves matching a predicate
+#[derive(Clone, Debug, PartialEq)]
+pub struct Estimate {
+    /// Total number of leaves in the structure
+    pub total: u64,
+    /// Number of leaves sampled
+    pub sampled: u64,
+    /// Number of sampled leaves matching the predicate
+    pub matched: u64,
+    /// True if every leaf was visited, making the estimate exact
+    pub exact: bool,
+}
+
+impl Estimate {
+    /// Returns the estimated number of matching leaves
+    pub fn count(&self) -> f64 {
+        if self.sampled == 0 {
+            return 0.0;
+        }
+        self.total as f64 * self.matched as f64 / self.sampled as f64
+    }
+
+    /// Returns the bounds of the 95% confidence interval of the count
+    pub fn bounds(&self) -> (f64, f64) {
+        self.interval(Z_95)
+    }
+
+    /// Returns the bounds of the confidence interval of the count, for the
+    /// standard score `z`
+    ///
+    /// Computed as a Wilson score interval, which stays within bounds for
+    /// small samples and proportions close to zero or one.
+    pub fn interval(&self, z: f64) -> (f64, f64) {
+        if self.exact || self.sampled == 0 {
+            let count = self.count();
+            return (count, count);
+        }
+        let n = self.sampled as f64;
+        let p = self.matched as f64 / n;
+        let z2 = z * z;
+        let denom = 1.0 + z2 / n;
+        let center = (p + z2 / (2.0 * n)) / denom;
+        let margin =
+            z * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt() / denom;
+        let total = self.total as f64;
+        (
+            (total * (center - margin)).max(0.0),
+            (total * (center + margin)).min(total),
+        )
+    }
+}
+
+/// Estimates how many leaves of `root` match `predicate`, from up to
+/// `sample_size` leaves sampled uniformly
+///
+/// Every sample descends from the root to a random position, guided by the
+/// `Cardinality` annotations, so the cost does not depend on the size of the
+/// structure. Structures with no more leaves than `sample_size` are counted
+/// exactly.
+pub fn estimate_count<C, H, F>(
+    root: &C,
+    mut predicate: F,
+    sample_size: usize,
+) -> io::Result<Estimate>
+where
+    C: Compound<H>,
+    C::Annotation: Borrow<Cardinality<u64>>,
+    H: ByteHash,
+    F: FnMut(&C::Leaf) -> bool,
+{
+    let total = match root.annotation() {
+        Some(ann) => {
+            let count: &Cardinality<u64> = ann.borrow();
+            **count
+        }
+        None => 0,
+    };
+
+    if total <= sample_size as u64 {
+        let mut matched = 0;
+        for leaf in root.iter() {
+            if predicate(leaf?) {
+                matched += 1
+            }
+        }
+        return Ok(Estimate {
+            total,
+            sampled: total,
+            matched,
+            exact: true,
+        });
+    }
+
+    let mut rng = rand::thread_rng();
+    let mut matched = 0;
+    for _ in 0..sample_size {
+        let n = rng.gen_range(0, total);
+        match Branch::new(root, &mut Nth::new(n))? {
+            Some(branch) => {
+                if predicate(&*branch) {
+                    matched += 1
+                }
+            }
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Cardinality annotation out of sync with leaves",
+                ))
+            }
+        }
+    }
+    Ok(Estimate {
+        total,
+        sampled: sample_size as u64,
+        matched,
+        exact: false,
+    })
+}
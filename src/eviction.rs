@@ -0,0 +1,395 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+
+/// Decides which nodes a cache evicts when over budget
+///
+/// The cache reports every node it adds, and every hit on a cached node.
+/// When over budget, it evicts the nodes returned by `evict` until back
+/// within it.
+pub trait EvictionPolicy<D>: Send {
+    /// Records that `digest` was added to the cache
+    fn insert(&mut self, digest: D);
+
+    /// Records a hit on the cached `digest`
+    fn hit(&mut self, digest: &D);
+
+    /// Chooses a cached node to evict, and forgets about it
+    fn evict(&mut self) -> Option<D>;
+}
+
+/// Evicts the least recently used node
+pub struct Lru<D> {
+    ticks: HashMap<D, u64>,
+    order: BTreeMap<u64, D>,
+    tick: u64,
+}
+
+impl<D> Default for Lru<D> {
+    fn default() -> Self {
+        Lru {
+            ticks: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+}
+
+impl<D> Lru<D> {
+    /// Creates a new, empty, policy
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl<D: Hash + Eq + Copy> Lru<D> {
+    fn len(&self) -> usize {
+        self.ticks.len()
+    }
+
+    fn contains(&self, digest: &D) -> bool {
+        self.ticks.contains_key(digest)
+    }
+
+    fn oldest(&self) -> Option<D> {
+        self.order.values().next().copied()
+    }
+
+    fn remove(&mut self, digest: &D) {
+        if let Some(tick) = self.ticks.remove(digest) {
+            self.order.remove(&tick);
+        }
+    }
+}
+
+impl<D: Hash + Eq + Copy + Send> EvictionPolicy<D> for Lru<D> {
+    fn insert(&mut self, digest: D) {
+        self.tick += 1;
+        if let Some(old) = self.ticks.insert(digest, self.tick) {
+            self.order.remove(&old);
+        }
+        self.order.insert(self.tick, digest);
+    }
+
+    fn hit(&mut self, digest: &D) {
+        if self.ticks.contains_key(digest) {
+            self.insert(*digest)
+        }
+    }
+
+    fn evict(&mut self) -> Option<D> {
+        let oldest = self.oldest()?;
+        self.remove(&oldest);
+        Some(oldest)
+    }
+}
+
+/// Evicts the least frequently used node, the least recently used first
+/// among equally frequent ones
+pub struct Lfu<D> {
+    entries: HashMap<D, (u64, u64)>,
+    order: BTreeMap<(u64, u64), D>,
+    tick: u64,
+}
+
+impl<D> Default for Lfu<D> {
+    fn default() -> Self {
+        Lfu {
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+}
+
+impl<D> Lfu<D> {
+    /// Creates a new, empty, policy
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl<D: Hash + Eq + Copy + Send> EvictionPolicy<D> for Lfu<D> {
+    fn insert(&mut self, digest: D) {
+        self.tick += 1;
+        let key = (1, self.tick);
+        if let Some(old) = self.entries.insert(digest, key) {
+            self.order.remove(&old);
+        }
+        self.order.insert(key, digest);
+    }
+
+    fn hit(&mut self, digest: &D) {
+        self.tick += 1;
+        if let Some(entry) = self.entries.get_mut(digest) {
+            self.order.remove(entry);
+            *entry = (entry.0 + 1, self.tick);
+            self.order.insert(*entry, *digest);
+        }
+    }
+
+    fn evict(&mut self) -> Option<D> {
+        let key = *self.order.keys().next()?;
+        let digest = self.order.remove(&key)?;
+        self.entries.remove(&digest);
+        Some(digest)
+    }
+}
+
+/// Evicts nodes in insertion order, giving a second chance to the nodes hit
+/// since they were last considered
+///
+/// Approximates LRU, with cheaper bookkeeping on hits.
+pub struct Clock<D> {
+    queue: VecDeque<D>,
+    referenced: HashMap<D, bool>,
+}
+
+impl<D> Default for Clock<D> {
+    fn default() -> Self {
+        Clock {
+            queue: VecDeque::new(),
+            referenced: HashMap::new(),
+        }
+    }
+}
+
+impl<D> Clock<D> {
+    /// Creates a new, empty, policy
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl<D: Hash + Eq + Copy + Send> EvictionPolicy<D> for Clock<D> {
+    fn insert(&mut self, digest: D) {
+        if self.referenced.insert(digest, false).is_none() {
+            self.queue.push_back(digest)
+        }
+    }
+
+    fn hit(&mut self, digest: &D) {
+        if let Some(referenced) = self.referenced.get_mut(digest) {
+            *referenced = true
+        }
+    }
+
+    fn evict(&mut self) -> Option<D> {
+        while let Some(digest) = self.queue.pop_front() {
+            match self.referenced.get_mut(&digest) {
+                Some(referenced) if *referenced => {
+                    *referenced = false;
+                    self.queue.push_back(digest);
+                }
+                Some(_) => {
+                    self.referenced.remove(&digest);
+                    return Some(digest);
+                }
+                None => (),
+            }
+        }
+        None
+    }
+}
+
+// Counters per row of the frequency sketch
+const SKETCH_WIDTH: usize = 1 << 12;
+const SKETCH_ROWS: usize = 4;
+
+// Approximate access counts, halved periodically so that old popularity
+// fades
+struct Sketch {
+    counters: Vec<u8>,
+    increments: usize,
+}
+
+impl Sketch {
+    fn new() -> Self {
+        Sketch {
+            counters: vec![0; SKETCH_WIDTH * SKETCH_ROWS],
+            increments: 0,
+        }
+    }
+
+    fn slots<D: Hash>(digest: &D) -> [usize; SKETCH_ROWS] {
+        let mut slots = [0; SKETCH_ROWS];
+        for (row, slot) in slots.iter_mut().enumerate() {
+            let mut hasher = DefaultHasher::new();
+            row.hash(&mut hasher);
+            digest.hash(&mut hasher);
+            *slot =
+                row * SKETCH_WIDTH + (hasher.finish() as usize) % SKETCH_WIDTH;
+        }
+        slots
+    }
+
+    fn increment<D: Hash>(&mut self, digest: &D) {
+        for slot in Self::slots(digest).iter() {
+            let counter = &mut self.counters[*slot];
+            *counter = counter.saturating_add(1);
+        }
+        self.increments += 1;
+        if self.increments >= SKETCH_WIDTH * 10 {
+            for counter in self.counters.iter_mut() {
+                *counter /= 2
+            }
+            self.increments = 0;
+        }
+    }
+
+    fn frequency<D: Hash>(&self, digest: &D) -> u8 {
+        Self::slots(digest)
+            .iter()
+            .map(|slot| self.counters[*slot])
+            .min()
+            .unwrap_or(0)
+    }
+}
+
+/// Window TinyLFU
+///
+/// New nodes enter a small LRU window. Nodes leaving the window are only
+/// kept over the least recently used node of the main cache if they were
+/// accessed more often, as estimated by a frequency sketch. This keeps
+/// scans and one-off reads from flushing the hot set.
+pub struct TinyLfu<D> {
+    window: Lru<D>,
+    main: Lru<D>,
+    candidate: Option<D>,
+    sketch: Sketch,
+}
+
+impl<D> Default for TinyLfu<D> {
+    fn default() -> Self {
+        TinyLfu {
+            window: Lru::new(),
+            main: Lru::new(),
+            candidate: None,
+            sketch: Sketch::new(),
+        }
+    }
+}
+
+impl<D> TinyLfu<D> {
+    /// Creates a new, empty, policy
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl<D: Hash + Eq + Copy + Send> EvictionPolicy<D> for TinyLfu<D> {
+    fn insert(&mut self, digest: D) {
+        self.sketch.increment(&digest);
+        self.window.insert(digest);
+        // the window holds about 1% of the nodes
+        while self.window.len() * 100 > self.window.len() + self.main.len() {
+            match self.window.evict() {
+                Some(candidate) => {
+                    self.main.insert(candidate);
+                    self.candidate = Some(candidate);
+                }
+                None => break,
+            }
+        }
+    }
+
+    fn hit(&mut self, digest: &D) {
+        self.sketch.increment(digest);
+        if self.window.contains(digest) {
+            self.window.hit(digest)
+        } else {
+            self.main.hit(digest)
+        }
+    }
+
+    fn evict(&mut self) -> Option<D> {
+        // the node last admitted from the window is only kept over the least
+        // recently used node of the main cache if accessed more often
+        if let Some(candidate) = self.candidate.take() {
+            if let Some(victim) = self.main.oldest() {
+                if candidate != victim && self.main.contains(&candidate) {
+                    let evicted = if self.sketch.frequency(&candidate)
+                        > self.sketch.frequency(&victim)
+                    {
+                        victim
+                    } else {
+                        candidate
+                    };
+                    self.main.remove(&evicted);
+                    return Some(evicted);
+                }
+            }
+        }
+        self.main.evict().or_else(|| self.window.evict())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lru() {
+        let mut lru = Lru::new();
+        for i in 0..3u8 {
+            lru.insert(i)
+        }
+        lru.hit(&0);
+        assert_eq!(lru.evict(), Some(1));
+        assert_eq!(lru.evict(), Some(2));
+        assert_eq!(lru.evict(), Some(0));
+        assert_eq!(lru.evict(), None);
+    }
+
+    #[test]
+    fn lfu() {
+        let mut lfu = Lfu::new();
+        for i in 0..3u8 {
+            lfu.insert(i)
+        }
+        lfu.hit(&0);
+        lfu.hit(&0);
+        lfu.hit(&2);
+        assert_eq!(lfu.evict(), Some(1));
+        assert_eq!(lfu.evict(), Some(2));
+        assert_eq!(lfu.evict(), Some(0));
+    }
+
+    #[test]
+    fn clock() {
+        let mut clock = Clock::new();
+        for i in 0..3u8 {
+            clock.insert(i)
+        }
+        clock.hit(&0);
+        assert_eq!(clock.evict(), Some(1));
+        assert_eq!(clock.evict(), Some(2));
+        assert_eq!(clock.evict(), Some(0));
+        assert_eq!(clock.evict(), None);
+    }
+
+    #[test]
+    fn tiny_lfu_resists_scans() {
+        let mut policy = TinyLfu::new();
+        let mut cached = vec![];
+        // a hot set, accessed repeatedly
+        for i in 0..100u32 {
+            policy.insert(i);
+            cached.push(i);
+        }
+        for _ in 0..10 {
+            for i in 0..100u32 {
+                policy.hit(&i)
+            }
+        }
+        // a scan of cold nodes, keeping 100 nodes cached
+        for i in 1000..2000u32 {
+            policy.insert(i);
+            cached.push(i);
+            let evicted = policy.evict().unwrap();
+            cached.retain(|d| *d != evicted);
+        }
+        let hot = cached.iter().filter(|d| **d < 100).count();
+        assert!(hot >= 95, "only {} hot nodes kept", hot);
+    }
+}
@@ -0,0 +1,291 @@
+use std::collections::HashSet;
+use std::hash::Hasher;
+use std::io::{self, Read, Write};
+
+use bytehash::State;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::compound::Compound;
+use crate::content::Content;
+use crate::error::Error;
+use crate::gc::Reach;
+use crate::handle::{HandleRef, HandleType};
+use crate::source::read_len_prefixed;
+use crate::store::{Snapshot, Store};
+use crate::ByteHash;
+
+// Leading bytes of an export, followed by the format version
+const MAGIC: &[u8; 4] = b"KLVX";
+// Exports list the nodes as reached from the root since version 2
+const VERSION: u8 = 2;
+
+// The largest node imported or exported
+const MAX_NODE: u32 = 1 << 28;
+
+/// The annotation of a node, as exported for analytics
+#[derive(Clone, Debug)]
+pub struct AnnotationRecord<A> {
+    /// The slots followed from the root down to the node, empty for the root
+    pub path: Vec<usize>,
+    /// The annotation of the node, summarizing all leaves below it
+    pub annotation: A,
+}
+
+impl<A> AnnotationRecord<A> {
+    /// Returns the depth of the node, zero for the root
+    pub fn depth(&self) -> usize {
+        self.path.len()
+    }
+}
+
+/// Returns the annotations of the nodes of `root`, down to `depth` levels
+/// below it
+///
+/// Annotations are kept in the handles of their parents, so only the nodes
+/// above `depth` are loaded, and no leaf is ever read. This gives cheap
+/// approximate figures about structures too large to be traversed. Nodes
+/// are listed depth first, parents before their children in slot order.
+pub fn export_annotations<C, H>(
+    root: &C,
+    depth: usize,
+) -> io::Result<Vec<AnnotationRecord<C::Annotation>>>
+where
+    C: Compound<H>,
+    H: ByteHash,
+{
+    let mut records = vec![];
+    if let Some(annotation) = root.annotation() {
+        records.push(AnnotationRecord {
+            path: vec![],
+            annotation,
+        });
+        export_level(root, &mut vec![], depth, &mut records)?;
+    }
+    Ok(records)
+}
+
+fn export_level<C, H>(
+    node: &C,
+    path: &mut Vec<usize>,
+    depth: usize,
+    records: &mut Vec<AnnotationRecord<C::Annotation>>,
+) -> io::Result<()>
+where
+    C: Compound<H>,
+    H: ByteHash,
+{
+    if path.len() >= depth {
+        return Ok(());
+    }
+    for (i, child) in node.children().iter().enumerate() {
+        if child.handle_type() != HandleType::Node {
+            continue;
+        }
+        path.push(i);
+        if let Some(annotation) = child.annotation() {
+            records.push(AnnotationRecord {
+                path: path.clone(),
+                annotation: annotation.into_owned(),
+            });
+        }
+        if path.len() < depth {
+            if let HandleRef::Node(child) = child.inner()? {
+                export_level(&*child, path, depth, records)?;
+            }
+        }
+        path.pop();
+    }
+    Ok(())
+}
+
+/// Writes every node reachable from `root` to `writer`, returning the
+/// number of nodes written
+///
+/// Nodes are streamed as reached by `Content::reach`, depth first, parents
+/// before their children, so that the chunks of blobs and the nodes of
+/// structures kept in leaves are exported along with the root. The stream
+/// is a header with the root digest followed by length-prefixed encoded
+/// nodes. Meant for backups, and for syncing state over a socket with a
+/// peer not sharing the backend, see `import`.
+pub fn export<C, H, W>(
+    root: &Snapshot<C, H>,
+    mut writer: W,
+) -> io::Result<usize>
+where
+    C: Compound<H>,
+    H: ByteHash,
+    W: Write,
+{
+    writer.write_all(MAGIC)?;
+    writer.write_u8(VERSION)?;
+    writer.write_all(root.hash().as_ref())?;
+    let mut exporter = Exporter {
+        store: root.store(),
+        writer: &mut writer,
+        seen: HashSet::new(),
+        nodes: 0,
+    };
+    exporter.node::<C>(root.hash())?;
+    let nodes = exporter.nodes;
+    writer.write_u8(0)?;
+    writer.flush()?;
+    Ok(nodes)
+}
+
+// Writes the nodes reached, each once
+struct Exporter<'a, W, H: ByteHash> {
+    store: &'a Store<H>,
+    writer: W,
+    seen: HashSet<H::Digest>,
+    nodes: usize,
+}
+
+impl<'a, W: Write, H: ByteHash> Reach<H> for Exporter<'a, W, H> {
+    fn node<T: Content<H>>(&mut self, hash: &H::Digest) -> io::Result<()> {
+        if !self.seen.insert(*hash) {
+            return Ok(());
+        }
+        let (node, bytes) = self.store.read_raw::<T>(hash)?;
+        if bytes.len() > MAX_NODE as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Node too large to export",
+            ));
+        }
+        self.writer.write_u8(1)?;
+        self.writer.write_u32::<BigEndian>(bytes.len() as u32)?;
+        self.writer.write_all(&bytes)?;
+        self.nodes += 1;
+        node.reach(self)
+    }
+}
+
+/// Reads nodes written by `export` from `reader` into `store`, returning a
+/// snapshot of the root
+///
+/// Nodes are read in the order they were exported, the root decoded as
+/// `C` and every other node as the type its parent reaches it as. A node
+/// other than the one expected fails with an `InvalidData` error, as does
+/// an export ending with nodes still missing.
+pub fn import<C, H, R>(
+    mut reader: R,
+    store: &Store<H>,
+) -> io::Result<Snapshot<C, H>>
+where
+    C: Compound<H>,
+    H: ByteHash,
+    R: Read,
+{
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(Error::InvalidEncoding("Not a kelvin export").into());
+    }
+    let version = reader.read_u8()?;
+    if version != VERSION && version != 1 {
+        return Err(Error::InvalidEncoding("Unsupported export version").into());
+    }
+    let mut root = H::Digest::default();
+    reader.read_exact(root.as_mut())?;
+    if version == 1 {
+        return import_unordered::<C, H, R>(reader, store, root);
+    }
+
+    let mut importer = Importer {
+        reader: &mut reader,
+        store,
+        seen: HashSet::new(),
+    };
+    importer.node::<C>(&root)?;
+    if read_node(&mut reader)?.is_some() {
+        return Err(Error::InvalidEncoding("Unexpected node in export").into());
+    }
+    Ok(store.snapshot(&root))
+}
+
+// Reads the nodes reached off an export, in the order `Exporter` wrote them
+struct Importer<'a, R, H: ByteHash> {
+    reader: R,
+    store: &'a Store<H>,
+    seen: HashSet<H::Digest>,
+}
+
+impl<'a, R: Read, H: ByteHash> Reach<H> for Importer<'a, R, H> {
+    fn node<T: Content<H>>(&mut self, hash: &H::Digest) -> io::Result<()> {
+        if !self.seen.insert(*hash) {
+            return Ok(());
+        }
+        let bytes = match read_node(&mut self.reader)? {
+            Some(bytes) => bytes,
+            None => {
+                return Err(Error::InvalidEncoding("Incomplete export").into())
+            }
+        };
+        if digest::<H>(&bytes) != *hash {
+            return Err(
+                Error::InvalidEncoding("Unexpected node in export").into()
+            );
+        }
+        let node: T = self.store.restore_node(&bytes)?;
+        self.store.put(*hash, bytes)?;
+        node.reach(self)
+    }
+}
+
+// Imports an export of version 1, listing the nodes of a structure only,
+// in any order
+fn import_unordered<C, H, R>(
+    mut reader: R,
+    store: &Store<H>,
+    root: H::Digest,
+) -> io::Result<Snapshot<C, H>>
+where
+    C: Compound<H>,
+    H: ByteHash,
+    R: Read,
+{
+    // the nodes referenced but not read yet
+    let mut expected = HashSet::new();
+    let mut imported = HashSet::new();
+    expected.insert(root);
+    while let Some(bytes) = read_node(&mut reader)? {
+        let digest = digest::<H>(&bytes);
+        if !expected.remove(&digest) {
+            return Err(
+                Error::InvalidEncoding("Unexpected node in export").into()
+            );
+        }
+        let node: C = store.restore_node(&bytes)?;
+        for child in node.children() {
+            match child.digest() {
+                // shared subtrees are exported once
+                Some(child) if !imported.contains(child) => {
+                    expected.insert(*child);
+                }
+                _ => (),
+            }
+        }
+        imported.insert(digest);
+        store.put(digest, bytes)?;
+    }
+    if !expected.is_empty() {
+        return Err(Error::InvalidEncoding("Incomplete export").into());
+    }
+    Ok(store.snapshot(&root))
+}
+
+// Reads the next node of an export, `None` at its end
+fn read_node<R: Read>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    match reader.read_u8()? {
+        0 => return Ok(None),
+        1 => (),
+        _ => return Err(Error::InvalidEncoding("Invalid export").into()),
+    }
+    read_len_prefixed(reader, MAX_NODE as usize).map(Some)
+}
+
+fn digest<H: ByteHash>(bytes: &[u8]) -> H::Digest {
+    let mut state = H::state();
+    state.write(bytes);
+    state.fin()
+}
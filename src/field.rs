@@ -0,0 +1,100 @@
+use std::fmt::Debug;
+use std::hash::Hasher;
+use std::marker::PhantomData;
+
+use bytehash::{ByteHash, State};
+
+// Appended to every framed byte stream, so streams differing only in
+// trailing zero bytes frame to different elements
+const PADDING: u8 = 0x01;
+
+/// A hash over field elements, such as Poseidon
+///
+/// Arithmetic-friendly hashes are cheap to prove inside zk-SNARK circuits,
+/// but consume field elements rather than bytes. Wrapped in `Algebraic`,
+/// they hash kelvin nodes, packing the bytes written into elements as
+/// described by `frame`.
+pub trait FieldHash: 'static + Clone + Debug + Send + Sync {
+    /// The field elements hashed
+    type Element: Copy + Debug + Send + Sync + 'static;
+
+    /// The number of bytes packed into each element, at least one
+    ///
+    /// Every integer of this many bytes must be below the modulus of the
+    /// field, so that packing is injective.
+    const PACKED_BYTES: usize;
+
+    /// Packs up to `PACKED_BYTES` bytes, read as a little-endian integer,
+    /// into an element
+    fn pack(bytes: &[u8]) -> Self::Element;
+
+    /// Hashes a sequence of elements to a single element
+    fn hash(elements: &[Self::Element]) -> Self::Element;
+
+    /// The canonical encoding of `element`, used as digest
+    fn encode(element: &Self::Element) -> [u8; 32];
+}
+
+/// Returns the elements `F` hashes for `bytes`
+///
+/// The bytes, followed by a single `0x01` byte, are split into chunks of
+/// `F::PACKED_BYTES`, each packed into one element. Circuits proving a
+/// node recompute its digest from these elements.
+pub fn frame<F: FieldHash>(bytes: &[u8]) -> Vec<F::Element> {
+    let mut padded = Vec::with_capacity(bytes.len() + 1);
+    padded.extend_from_slice(bytes);
+    padded.push(PADDING);
+    padded.chunks(F::PACKED_BYTES).map(F::pack).collect()
+}
+
+/// A `ByteHash` hashing through the field hash `F`
+#[derive(Clone, Debug)]
+pub struct Algebraic<F>(PhantomData<F>);
+
+/// Hash state of `Algebraic`
+pub struct AlgebraicState<F: FieldHash> {
+    elements: Vec<F::Element>,
+    pending: Vec<u8>,
+}
+
+impl<F: FieldHash> AlgebraicState<F> {
+    fn digest(&self) -> [u8; 32] {
+        let mut elements = self.elements.clone();
+        elements.extend(frame::<F>(&self.pending));
+        F::encode(&F::hash(&elements))
+    }
+}
+
+impl<F: FieldHash> Hasher for AlgebraicState<F> {
+    fn write(&mut self, bytes: &[u8]) {
+        self.pending.extend_from_slice(bytes);
+        let full = self.pending.len() / F::PACKED_BYTES * F::PACKED_BYTES;
+        self.elements
+            .extend(self.pending[..full].chunks(F::PACKED_BYTES).map(F::pack));
+        self.pending.drain(..full);
+    }
+
+    fn finish(&self) -> u64 {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&self.digest()[..8]);
+        u64::from_le_bytes(bytes)
+    }
+}
+
+impl<F: FieldHash> State<[u8; 32]> for AlgebraicState<F> {
+    fn fin(self) -> [u8; 32] {
+        self.digest()
+    }
+}
+
+impl<F: FieldHash> ByteHash for Algebraic<F> {
+    type Digest = [u8; 32];
+    type State = AlgebraicState<F>;
+
+    fn state() -> Self::State {
+        AlgebraicState {
+            elements: vec![],
+            pending: Vec::with_capacity(F::PACKED_BYTES),
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use std::hash::Hash;
+use std::io::{self, Read, Write};
+use std::marker::PhantomData;
+
+use bytehash::ByteHash;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::compound::Compound;
+use crate::content::Content;
+use crate::diff::changed_leaves;
+use crate::iter::LeafIterable;
+use crate::map::KV;
+use crate::portable::portable_hash;
+use crate::sink::Sink;
+use crate::source::Source;
+use crate::store::Snapshot;
+
+/// An approximate membership filter over the keys of a map
+///
+/// A Bloom filter, answering whether a key may be present in the map without
+/// touching the tree. Negative answers are always correct, positive answers
+/// are wrong with a probability close to the rate the filter was created
+/// with, as long as it holds no more keys than its capacity.
+///
+/// Keys can not be removed from the filter, keys removed from the map remain
+/// as false positives until the filter is rebuilt.
+pub struct KeyFilter<H> {
+    bits: Vec<u8>,
+    hashes: u8,
+    capacity: u64,
+    len: u64,
+    _marker: PhantomData<H>,
+}
+
+impl<H> Clone for KeyFilter<H> {
+    fn clone(&self) -> Self {
+        KeyFilter {
+            bits: self.bits.clone(),
+            hashes: self.hashes,
+            capacity: self.capacity,
+            len: self.len,
+            _marker: PhantomData,
+        }
+    }
+}
+
+// Reads up to the first 8 bytes of a digest as a number
+fn fold<D: AsRef<[u8]>>(digest: D) -> u64 {
+    digest
+        .as_ref()
+        .iter()
+        .take(8)
+        .fold(0, |n, b| n << 8 | *b as u64)
+}
+
+impl<H: ByteHash> KeyFilter<H> {
+    /// Creates an empty filter sized for `capacity` keys, with a false
+    /// positive rate of `rate`
+    pub fn new(capacity: u64, rate: f64) -> Self {
+        let capacity = capacity.max(1);
+        let rate = rate.clamp(f64::MIN_POSITIVE, 0.5);
+        let ln2 = std::f64::consts::LN_2;
+        let bits = (-(capacity as f64) * rate.ln() / (ln2 * ln2)).ceil();
+        let bytes = (bits as u64).div_ceil(8).max(1);
+        let hashes = (bytes as f64 * 8.0 / capacity as f64 * ln2).round();
+        KeyFilter {
+            bits: vec![0; bytes as usize],
+            hashes: hashes.clamp(1.0, 32.0) as u8,
+            capacity,
+            len: 0,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Creates a filter over all keys of `map`
+    ///
+    /// The filter is sized for twice the current number of keys, leaving
+    /// room for updates.
+    pub fn from_map<C, K, V>(map: &C, rate: f64) -> io::Result<Self>
+    where
+        C: Compound<H, Leaf = KV<K, V>>,
+        K: Hash,
+    {
+        let mut count = 0;
+        for leaf in map.iter() {
+            leaf?;
+            count += 1;
+        }
+        let mut filter = Self::new(count * 2, rate);
+        for leaf in map.iter() {
+            filter.insert(&leaf?.key);
+        }
+        Ok(filter)
+    }
+
+    /// Returns the number of keys the filter is sized for
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    /// Returns the number of keys inserted into the filter
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns true if no keys were inserted into the filter
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns true if more keys were inserted than the filter is sized for
+    pub fn is_saturated(&self) -> bool {
+        self.len > self.capacity
+    }
+
+    fn indices<K: Hash + ?Sized>(&self, key: &K) -> impl Iterator<Item = u64> {
+        let m = self.bits.len() as u64 * 8;
+        let a = fold(portable_hash::<H, _>(key));
+        let b = fold(portable_hash::<H, _>(&(key, 1u8))) | 1;
+        (0..self.hashes as u64)
+            .map(move |i| a.wrapping_add(i.wrapping_mul(b)) % m)
+    }
+
+    /// Inserts `key` into the filter
+    pub fn insert<K: Hash + ?Sized>(&mut self, key: &K) {
+        let indices: Vec<_> = self.indices(key).collect();
+        for i in indices {
+            self.bits[(i / 8) as usize] |= 1 << (i % 8)
+        }
+        self.len += 1;
+    }
+
+    /// Returns false if `key` is definitely not in the map
+    pub fn may_contain<K: Hash + ?Sized>(&self, key: &K) -> bool {
+        self.indices(key)
+            .all(|i| self.bits[(i / 8) as usize] & (1 << (i % 8)) != 0)
+    }
+
+    /// Updates the filter of the map at `old` to cover the map at `new`
+    ///
+    /// Only the subtrees that differ between the two versions are visited.
+    pub fn update<C, K, V>(
+        &mut self,
+        old: &Snapshot<C, H>,
+        new: &Snapshot<C, H>,
+    ) -> io::Result<()>
+    where
+        C: Compound<H, Leaf = KV<K, V>>,
+        K: Hash,
+    {
+        let (_, added) = changed_leaves(old, new)?;
+        for leaf in added {
+            // modified leaves keep their key, and must not be counted twice
+            if !self.may_contain(&leaf.key) {
+                self.insert(&leaf.key)
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<H: ByteHash> Content<H> for KeyFilter<H> {
+    fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
+        sink.write_u8(self.hashes)?;
+        sink.write_u64::<BigEndian>(self.capacity)?;
+        sink.write_u64::<BigEndian>(self.len)?;
+        sink.write_u64::<BigEndian>(self.bits.len() as u64)?;
+        sink.write_all(&self.bits)
+    }
+
+    fn restore(source: &mut Source<H>) -> io::Result<Self> {
+        let hashes = source.read_u8()?;
+        let capacity = source.read_u64::<BigEndian>()?;
+        let len = source.read_u64::<BigEndian>()?;
+        let mut bits = vec![0; source.read_u64::<BigEndian>()? as usize];
+        source.read_exact(&mut bits)?;
+        if hashes == 0 || bits.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Invalid key filter",
+            ));
+        }
+        Ok(KeyFilter {
+            bits,
+            hashes,
+            capacity,
+            len,
+            _marker: PhantomData,
+        })
+    }
+}
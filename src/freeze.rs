@@ -0,0 +1,71 @@
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::sync::Arc;
+
+use bytehash::ByteHash;
+
+use crate::compound::Compound;
+
+/// A read-only structure, cheap to clone and share between threads
+///
+/// Only a shared reference to the structure is ever handed out, so methods
+/// taking `&mut self` are unavailable, and a published state cannot be
+/// modified by the threads reading it. Use `thaw` to get a mutable copy.
+pub struct Frozen<C, H>(Arc<C>, PhantomData<H>);
+
+impl<C, H> Frozen<C, H>
+where
+    C: Compound<H>,
+    H: ByteHash,
+{
+    /// Returns a mutable copy of the structure
+    pub fn thaw(&self) -> C {
+        (*self.0).clone()
+    }
+
+    /// Returns the structure, copying it if the frozen state is still shared
+    pub fn into_inner(self) -> C {
+        Arc::try_unwrap(self.0).unwrap_or_else(|arc| (*arc).clone())
+    }
+}
+
+impl<C, H> Clone for Frozen<C, H> {
+    fn clone(&self) -> Self {
+        Frozen(self.0.clone(), PhantomData)
+    }
+}
+
+impl<C, H> Deref for Frozen<C, H> {
+    type Target = C;
+
+    fn deref(&self) -> &C {
+        &self.0
+    }
+}
+
+impl<C: fmt::Debug, H> fmt::Debug for Frozen<C, H> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Frozen").field(&self.0).finish()
+    }
+}
+
+/// Trait for freezing a structure into its read-only form
+pub trait Freeze<H>
+where
+    Self: Compound<H>,
+    H: ByteHash,
+{
+    /// Converts the structure into its read-only form
+    fn freeze(self) -> Frozen<Self, H>;
+}
+
+impl<C, H> Freeze<H> for C
+where
+    C: Compound<H>,
+    H: ByteHash,
+{
+    fn freeze(self) -> Frozen<Self, H> {
+        Frozen(Arc::new(self), PhantomData)
+    }
+}
@@ -0,0 +1,102 @@
+use std::collections::HashSet;
+use std::io;
+
+use crate::compound::Compound;
+use crate::content::Content;
+use crate::control::Control;
+use crate::store::{Snapshot, Store};
+use crate::ByteHash;
+
+/// A traversal of the nodes reachable from a value, see `Content::reach`
+pub trait Reach<H: ByteHash> {
+    /// Visits the node with digest `hash`, holding a `T`
+    ///
+    /// Unless the node was visited before, implementations read it and go
+    /// on with `T::reach` on the value read.
+    fn node<T: Content<H>>(&mut self, hash: &H::Digest) -> io::Result<()>;
+}
+
+/// Visits the children of `node`, the `Content::reach` of structures
+///
+/// Persisted children are visited as nodes of their own, leaves and nodes
+/// in memory are reached into directly.
+pub fn reach_children<C, H, R>(node: &C, reach: &mut R) -> io::Result<()>
+where
+    C: Compound<H>,
+    H: ByteHash,
+    R: Reach<H>,
+{
+    for child in node.children() {
+        child.reach(reach)?;
+    }
+    Ok(())
+}
+
+/// The set of nodes retained by garbage collection, see `Store::gc`
+pub struct LiveSet<'a, 'c, H: ByteHash> {
+    store: &'a Store<H>,
+    control: &'a mut Control<'c>,
+    nodes: HashSet<H::Digest>,
+}
+
+impl<'a, 'c, H: ByteHash> LiveSet<'a, 'c, H> {
+    pub(crate) fn new(
+        store: &'a Store<H>,
+        control: &'a mut Control<'c>,
+    ) -> Self {
+        LiveSet {
+            store,
+            control,
+            nodes: HashSet::new(),
+        }
+    }
+
+    /// Marks every node reachable from `root` as live
+    ///
+    /// Roots may be structures, or any value reaching them, such as the
+    /// state of a program keeping several maps. Nodes shared with roots
+    /// already marked are not traversed again.
+    pub fn mark<T: Content<H>>(
+        &mut self,
+        root: &Snapshot<T, H>,
+    ) -> io::Result<()> {
+        self.node::<T>(root.hash())
+    }
+
+    /// Marks every node reachable from the root with digest `hash` as live
+    pub fn mark_hash<T: Content<H>>(
+        &mut self,
+        hash: &H::Digest,
+    ) -> io::Result<()> {
+        self.node::<T>(hash)
+    }
+
+    /// Returns true if the node with digest `hash` is marked live
+    pub fn contains(&self, hash: &H::Digest) -> bool {
+        self.nodes.contains(hash)
+    }
+
+    /// Returns the number of live nodes
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Returns true if no node is marked live
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub(crate) fn into_nodes(self) -> HashSet<H::Digest> {
+        self.nodes
+    }
+}
+
+impl<'a, 'c, H: ByteHash> Reach<H> for LiveSet<'a, 'c, H> {
+    fn node<T: Content<H>>(&mut self, hash: &H::Digest) -> io::Result<()> {
+        if !self.nodes.insert(*hash) {
+            return Ok(());
+        }
+        self.control.advance(1)?;
+        self.store.get_hash::<T>(hash)?.reach(self)
+    }
+}
@@ -3,18 +3,27 @@ use std::fmt;
 use std::io::{self, Read, Write};
 use std::mem;
 use std::ops::{Deref, DerefMut};
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock, Weak};
 
 use bytehash::ByteHash;
+use byteorder::{BigEndian, WriteBytesExt};
 use cache::Cached;
+use parking_lot::Mutex;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 
 use crate::annotations::ErasedAnnotation;
 use crate::compound::Compound;
 use crate::content::Content;
 use crate::debug_draw::{DebugDraw, DrawState};
+use crate::error::Error;
+use crate::gc::Reach;
 use crate::sink::Sink;
 use crate::source::Source;
-use crate::store::Snapshot;
+use crate::store::{Snapshot, Store};
+
+// Longest metadata of a node
+const MAX_META: usize = u16::MAX as usize;
 
 enum HandleInner<C, H>
 where
@@ -24,7 +33,17 @@ where
     Leaf(C::Leaf),
     Node(Box<C>, C::Annotation),
     SharedNode(Arc<C>, C::Annotation),
-    Persisted(Snapshot<C, H>, C::Annotation),
+    // persisted nodes keep their metadata next to the annotation, nodes in
+    // memory return it from `Compound::meta`
+    Persisted(Snapshot<C, H>, C::Annotation, Option<Vec<u8>>),
+    // a persisted node, kept in memory once restored, and shared by the
+    // clones of the handle and the threads reading it
+    ArcNode(
+        Snapshot<C, H>,
+        Arc<OnceLock<C>>,
+        C::Annotation,
+        Option<Vec<u8>>,
+    ),
     None,
 }
 
@@ -52,7 +71,7 @@ pub enum HandleType {
     None,
     /// Leaf handle
     Leaf,
-    /// Node handle
+    /// Node handle, also for nodes not yet read from the store
     Node,
 }
 
@@ -73,11 +92,56 @@ where
         match self.0 {
             HandleInner::None => write!(f, "None"),
             HandleInner::Leaf(ref l) => write!(f, "Leaf({:?})", l),
+            HandleInner::Persisted(ref snap, _, _)
+            | HandleInner::ArcNode(ref snap, _, _, _) => {
+                write!(f, "Node(")?;
+                for byte in snap.hash().as_ref().iter().take(4) {
+                    write!(f, "{:02x}", byte)?;
+                }
+                write!(f, "..)")
+            }
             _ => write!(f, "Node"),
         }
     }
 }
 
+/// A weak reference to a node, that does not keep it in memory
+///
+/// Meant for external caches and indexes, which should not prevent nodes
+/// from being dropped. Nodes that were persisted are restored from the store
+/// when no longer in memory.
+pub struct WeakHandle<C, H>
+where
+    C: Compound<H>,
+    H: ByteHash,
+{
+    node: Mutex<Weak<C>>,
+    snapshot: Option<Snapshot<C, H>>,
+}
+
+impl<C, H> WeakHandle<C, H>
+where
+    C: Compound<H>,
+    H: ByteHash,
+{
+    /// Returns the node, if still in memory or restorable from the store
+    pub fn upgrade(&self) -> io::Result<Option<Arc<C>>> {
+        let mut node = self.node.lock();
+        if let Some(arc) = node.upgrade() {
+            return Ok(Some(arc));
+        }
+        match self.snapshot {
+            Some(ref snap) => {
+                let arc = Arc::new(snap.restore()?);
+                // later upgrades share the node while it is alive
+                *node = Arc::downgrade(&arc);
+                Ok(Some(arc))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
 /// User facing reference to a handle
 pub enum HandleRef<'a, C, H>
 where
@@ -195,8 +259,16 @@ where
             HandleInner::SharedNode(ref arc, ref ann) => {
                 HandleInner::SharedNode(arc.clone(), ann.clone())
             }
-            HandleInner::Persisted(ref snap, ref ann) => {
-                HandleInner::Persisted(snap.clone(), ann.clone())
+            HandleInner::Persisted(ref snap, ref ann, ref meta) => {
+                HandleInner::Persisted(snap.clone(), ann.clone(), meta.clone())
+            }
+            HandleInner::ArcNode(ref snap, ref node, ref ann, ref meta) => {
+                HandleInner::ArcNode(
+                    snap.clone(),
+                    node.clone(),
+                    ann.clone(),
+                    meta.clone(),
+                )
             }
             HandleInner::None => HandleInner::None,
         }
@@ -209,44 +281,62 @@ where
     H: ByteHash,
 {
     fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
-        match self.0 {
-            HandleInner::None => sink.write_all(&[0]),
-            HandleInner::Leaf(ref mut leaf) => {
-                sink.write_all(&[1])?;
-                leaf.persist(sink)
-            }
-            HandleInner::Persisted(ref digest, ref mut ann) => {
-                sink.write_all(&[2])?;
-                sink.write_all((**digest).as_ref())?;
-                ann.persist(sink)
+        sink.mark_node();
+        let tag = match self.0 {
+            HandleInner::None => return sink.write_all(&[0]),
+            HandleInner::Leaf(_) => 1,
+            _ => {
+                self.persist_node(sink.store())?;
+                match self.meta() {
+                    Some(_) => 3,
+                    None => 2,
+                }
             }
-            HandleInner::Node(ref mut node, ref ann) => {
-                let snap = sink.store().persist(&mut **node)?;
-                self.0 = HandleInner::Persisted(snap, ann.clone());
-                self.persist(sink)
+        };
+        sink.write_all(&[tag])?;
+        self.write_untagged(sink)?;
+        match self.meta() {
+            Some(meta) => {
+                if meta.len() > MAX_META {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "Node metadata over the limit",
+                    ));
+                }
+                sink.write_u32::<BigEndian>(meta.len() as u32)?;
+                sink.write_all(&meta)
             }
-            HandleInner::SharedNode(_, _) => unimplemented!(),
+            None => Ok(()),
         }
     }
 
     fn restore(source: &mut Source<H>) -> io::Result<Self> {
+        source.mark_node();
         let mut tag = [0u8];
         source.read_exact(&mut tag)?;
         match tag {
             [0] => Ok(Handle(HandleInner::None)),
-            [1] => Ok(Handle(HandleInner::Leaf(C::Leaf::restore(source)?))),
-            [2] => {
-                let mut h = H::Digest::default();
-                source.read_exact(h.as_mut())?;
-                Ok(Handle(HandleInner::Persisted(
-                    Snapshot::new(h, source.store()),
-                    C::Annotation::restore(source)?,
-                )))
+            [1] => Self::restore_leaf(source),
+            [2] => Self::restore_node(source),
+            [3] => {
+                let mut handle = Self::restore_node(source)?;
+                if let HandleInner::Persisted(_, _, ref mut meta) = handle.0 {
+                    *meta = Some(source.read_len_prefixed(MAX_META)?);
+                }
+                Ok(handle)
             }
-            _ => Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Invalid Handle encoding",
-            )),
+            _ => Err(Error::InvalidEncoding("Invalid Handle encoding").into()),
+        }
+    }
+
+    fn reach<R: Reach<H>>(&self, reach: &mut R) -> io::Result<()> {
+        if let Some(digest) = self.stored() {
+            return reach.node::<C>(digest);
+        }
+        match self.inner()? {
+            HandleRef::Leaf(leaf) => leaf.reach(reach),
+            HandleRef::Node(node) => node.reach(reach),
+            HandleRef::None => Ok(()),
         }
     }
 }
@@ -273,6 +363,70 @@ where
         Handle(HandleInner::None)
     }
 
+    /// Persists the handle without the tag byte leading its encoding
+    ///
+    /// For compounds that record the kinds of their children themselves, and
+    /// restore them with `restore_leaf` and `restore_node`. Empty handles,
+    /// and handles to nodes with metadata, have no untagged encoding.
+    pub fn persist_untagged(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
+        self.persist_node(sink.store())?;
+        if self.meta().is_some() {
+            return Err(Error::InvalidEncoding(
+                "Node metadata has no untagged encoding",
+            )
+            .into());
+        }
+        self.write_untagged(sink)
+    }
+
+    // Persists the node of the handle, if only held in memory so far
+    fn persist_node(&mut self, store: &Store<H>) -> io::Result<()> {
+        if let HandleInner::Node(ref mut node, ref ann) = self.0 {
+            let meta = node.meta();
+            let snap = store.persist(&mut **node)?;
+            self.0 = HandleInner::Persisted(snap, ann.clone(), meta);
+        }
+        Ok(())
+    }
+
+    // Writes the handle without its tag and metadata, once its node is
+    // persisted
+    fn write_untagged(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
+        sink.mark_node();
+        match self.0 {
+            HandleInner::None => Err(Error::InvalidEncoding(
+                "Empty handles have no untagged encoding",
+            )
+            .into()),
+            HandleInner::Leaf(ref mut leaf) => leaf.persist(sink),
+            HandleInner::Persisted(ref digest, ref mut ann, _)
+            | HandleInner::ArcNode(ref digest, _, ref mut ann, _) => {
+                sink.write_all((**digest).as_ref())?;
+                ann.persist(sink)
+            }
+            HandleInner::Node(..) => unreachable!("persisted above"),
+            HandleInner::SharedNode(_, _) => unimplemented!(),
+        }
+    }
+
+    /// Restores a leaf handle persisted with `persist_untagged`
+    pub fn restore_leaf(source: &mut Source<H>) -> io::Result<Self> {
+        source.mark_node();
+        Ok(Handle(HandleInner::Leaf(C::Leaf::restore(source)?)))
+    }
+
+    /// Restores a node handle persisted with `persist_untagged`
+    pub fn restore_node(source: &mut Source<H>) -> io::Result<Self> {
+        source.mark_node();
+        let mut h = H::Digest::default();
+        source.read_exact(h.as_mut())?;
+        Ok(Handle(HandleInner::Persisted(
+            Snapshot::new(h, source.store()),
+            C::Annotation::restore(source)?,
+            None,
+        )))
+    }
+
     /// Converts handle into leaf, panics on mismatching type
     pub fn into_leaf(self) -> C::Leaf {
         self.try_into_leaf().expect("Not a leaf")
@@ -320,6 +474,70 @@ where
         }
     }
 
+    /// Returns the digest of a node not yet read from the store, if any
+    ///
+    /// Restored structures refer to their children by digest only, and
+    /// read them from the store as they are traversed, so subtrees can be
+    /// compared by digest without reading them.
+    pub fn digest(&self) -> Option<&H::Digest> {
+        match self.0 {
+            HandleInner::Persisted(ref snap, _, _) => Some(snap.hash()),
+            HandleInner::ArcNode(ref snap, ref node, _, _)
+                if node.get().is_none() =>
+            {
+                Some(snap.hash())
+            }
+            _ => None,
+        }
+    }
+
+    // Returns the digest of a persisted node, also once restored in memory
+    pub(crate) fn stored(&self) -> Option<&H::Digest> {
+        self.snapshot().map(Snapshot::hash)
+    }
+
+    // Returns the snapshot of a persisted node, also once restored in memory
+    pub(crate) fn snapshot(&self) -> Option<&Snapshot<C, H>> {
+        match self.0 {
+            HandleInner::Persisted(ref snap, _, _)
+            | HandleInner::ArcNode(ref snap, _, _, _) => Some(snap),
+            _ => None,
+        }
+    }
+
+    // Starts fetching a persisted node in the background, if enabled
+    pub(crate) fn prefetch(&self) {
+        match self.0 {
+            HandleInner::Persisted(ref snap, _, _) => snap.prefetch(),
+            HandleInner::ArcNode(ref snap, ref node, _, _)
+                if node.get().is_none() =>
+            {
+                snap.prefetch()
+            }
+            _ => (),
+        }
+    }
+
+    /// Turns a handle to a persisted node into one keeping the node in
+    /// memory once restored
+    ///
+    /// The restored node is shared by the clones of the handle, and its
+    /// children are turned into such handles as well, so several threads
+    /// traversing the same snapshot restore every node once, while a writer
+    /// builds the next version from a clone. Other handles are left as is.
+    pub fn make_arc(&mut self) {
+        if let HandleInner::Persisted(..) = self.0 {
+            if let HandleInner::Persisted(snap, ann, meta) =
+                mem::replace(&mut self.0, HandleInner::None)
+            {
+                let node = Arc::new(OnceLock::new());
+                self.0 = HandleInner::ArcNode(snap, node, ann, meta)
+            } else {
+                unreachable!()
+            }
+        }
+    }
+
     /// Returns the type of the Handle
     pub fn handle_type(&self) -> HandleType {
         match self.0 {
@@ -338,7 +556,26 @@ where
             }
             HandleInner::Node(_, ref ann)
             | HandleInner::SharedNode(_, ref ann)
-            | HandleInner::Persisted(_, ref ann) => Some(Cow::Borrowed(ann)),
+            | HandleInner::Persisted(_, ref ann, _)
+            | HandleInner::ArcNode(_, _, ref ann, _) => {
+                Some(Cow::Borrowed(ann))
+            }
+        }
+    }
+
+    /// Returns the metadata of the node, if any
+    ///
+    /// Read from the handle for persisted nodes, so search methods can use it
+    /// without restoring the node, see `Compound::meta`.
+    pub fn meta(&self) -> Option<Cow<[u8]>> {
+        match self.0 {
+            HandleInner::None | HandleInner::Leaf(_) => None,
+            HandleInner::Node(ref node, _) => node.meta().map(Cow::Owned),
+            HandleInner::SharedNode(ref node, _) => node.meta().map(Cow::Owned),
+            HandleInner::Persisted(_, _, ref meta)
+            | HandleInner::ArcNode(_, _, _, ref meta) => {
+                meta.as_deref().map(Cow::Borrowed)
+            }
         }
     }
 
@@ -353,10 +590,22 @@ where
             HandleInner::SharedNode(ref n, _) => {
                 HandleRef::Node(Cached::Borrowed(n.as_ref()))
             }
-            HandleInner::Persisted(ref snap, _) => {
+            HandleInner::Persisted(ref snap, _, _) => {
                 let restored = snap.restore()?;
                 HandleRef::Node(Cached::Spilled(Box::new(restored)))
             }
+            HandleInner::ArcNode(ref snap, ref node, _, _) => {
+                if node.get().is_none() {
+                    let mut restored = snap.restore()?;
+                    for child in restored.children_mut() {
+                        child.make_arc()
+                    }
+                    // another thread may have restored it meanwhile
+                    let _ = node.set(restored);
+                }
+                let node = node.get().expect("restored above");
+                HandleRef::Node(Cached::Borrowed(node))
+            }
         })
     }
 
@@ -385,8 +634,8 @@ where
                 annotation: Some(ann),
                 inner: HandleMut::Node(&mut **n),
             },
-            HandleInner::Persisted(_, _) => {
-                if let HandleInner::Persisted(snap, ann) =
+            HandleInner::Persisted(..) => {
+                if let HandleInner::Persisted(snap, ann, _) =
                     mem::replace(&mut self.0, HandleInner::None)
                 {
                     let restored = snap.restore()?;
@@ -396,10 +645,46 @@ where
                     unreachable!()
                 }
             }
+            HandleInner::ArcNode(..) => {
+                if let HandleInner::ArcNode(snap, node, ann, _) =
+                    mem::replace(&mut self.0, HandleInner::None)
+                {
+                    // readers of the shared node keep their copy
+                    let node = match Arc::try_unwrap(node) {
+                        Ok(cell) => cell.into_inner(),
+                        Err(shared) => shared.get().cloned(),
+                    };
+                    let node = match node {
+                        Some(node) => node,
+                        None => snap.restore()?,
+                    };
+                    *self = Handle(HandleInner::Node(Box::new(node), ann));
+                    return self.inner_mut();
+                } else {
+                    unreachable!()
+                }
+            }
             _ => unimplemented!(),
         })
     }
 
+    /// Returns a weak reference to the node, if the handle is a shared or
+    /// persisted node
+    pub fn downgrade(&self) -> Option<WeakHandle<C, H>> {
+        match self.0 {
+            HandleInner::SharedNode(ref arc, _) => Some(WeakHandle {
+                node: Mutex::new(Arc::downgrade(arc)),
+                snapshot: None,
+            }),
+            HandleInner::Persisted(ref snap, _, _)
+            | HandleInner::ArcNode(ref snap, _, _, _) => Some(WeakHandle {
+                node: Mutex::new(Weak::new()),
+                snapshot: Some(snap.clone()),
+            }),
+            _ => None,
+        }
+    }
+
     #[doc(hidden)]
     pub fn make_shared(&mut self) {
         if let HandleInner::Node(_, _) = self.0 {
@@ -416,6 +701,37 @@ where
     }
 }
 
+#[cfg(feature = "parallel")]
+impl<C, H> Handle<C, H>
+where
+    C: Compound<H> + Send,
+    C::Leaf: Send,
+    C::Annotation: Send,
+    H: ByteHash,
+    H::Digest: Send,
+{
+    // Persists the nodes not yet persisted below the handle, the subtrees of
+    // siblings being hashed in parallel
+    pub(crate) fn persist_parallel(
+        &mut self,
+        store: &Store<H>,
+    ) -> io::Result<()> {
+        if let HandleInner::Node(ref mut node, _) = self.0 {
+            Self::persist_children(&mut **node, store)?;
+        }
+        self.persist_node(store)
+    }
+
+    pub(crate) fn persist_children(
+        node: &mut C,
+        store: &Store<H>,
+    ) -> io::Result<()> {
+        node.children_mut()
+            .par_iter_mut()
+            .try_for_each(|child| child.persist_parallel(store))
+    }
+}
+
 impl<C, H> ErasedAnnotation<C::Annotation> for Handle<C, H>
 where
     C: Compound<H>,
@@ -0,0 +1,87 @@
+use std::hash::Hasher;
+
+use bytehash::{ByteHash, State};
+
+// `Hasher::finish` of the states, the leading 8 bytes of the digest so far
+fn leading_u64(digest: &[u8]) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&digest[..8]);
+    u64::from_le_bytes(bytes)
+}
+
+/// BLAKE3 with 32 byte digests
+///
+/// Digests equal those of the reference implementation over the bytes
+/// written, for BLAKE3 content addressing.
+#[cfg(feature = "blake3")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Blake3;
+
+/// Hash state of `Blake3`
+#[cfg(feature = "blake3")]
+pub struct Blake3State(blake3::Hasher);
+
+#[cfg(feature = "blake3")]
+impl Hasher for Blake3State {
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    fn finish(&self) -> u64 {
+        leading_u64(self.0.finalize().as_bytes())
+    }
+}
+
+#[cfg(feature = "blake3")]
+impl State<[u8; 32]> for Blake3State {
+    fn fin(self) -> [u8; 32] {
+        self.0.finalize().into()
+    }
+}
+
+#[cfg(feature = "blake3")]
+impl ByteHash for Blake3 {
+    type Digest = [u8; 32];
+    type State = Blake3State;
+
+    fn state() -> Self::State {
+        Blake3State(blake3::Hasher::new())
+    }
+}
+
+/// SHA-256, as used by Bitcoin-style Merkle trees
+#[cfg(feature = "sha256")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Sha256;
+
+/// Hash state of `Sha256`
+#[cfg(feature = "sha256")]
+pub struct Sha256State(sha2::Sha256);
+
+#[cfg(feature = "sha256")]
+impl Hasher for Sha256State {
+    fn write(&mut self, bytes: &[u8]) {
+        sha2::Digest::update(&mut self.0, bytes);
+    }
+
+    fn finish(&self) -> u64 {
+        leading_u64(&sha2::Digest::finalize(self.0.clone()))
+    }
+}
+
+#[cfg(feature = "sha256")]
+impl State<[u8; 32]> for Sha256State {
+    fn fin(self) -> [u8; 32] {
+        sha2::Digest::finalize(self.0).into()
+    }
+}
+
+#[cfg(feature = "sha256")]
+impl ByteHash for Sha256 {
+    type Digest = [u8; 32];
+    type State = Sha256State;
+
+    fn state() -> Self::State {
+        Sha256State(<sha2::Sha256 as sha2::Digest>::new())
+    }
+}
@@ -1,12 +1,13 @@
+use std::cmp::Ordering;
 use std::{io, mem};
 
 use crate::branch::{Branch, BranchMut};
 use crate::compound::Compound;
-use crate::search::{First, Method};
+use crate::search::{First, Last, Method};
 use crate::ByteHash;
 
-/// An iterator over the leaves of a Compound type
-pub enum LeafIter<'a, C, M, H>
+// A position among the leaves of a Compound, moved with the method `M`
+enum Cursor<'a, C, M, H>
 where
     C: Compound<H>,
     H: ByteHash,
@@ -16,48 +17,134 @@ where
     Exhausted,
 }
 
-impl<'a, C, M, H> Iterator for LeafIter<'a, C, M, H>
+impl<'a, C, M, H> Cursor<'a, C, M, H>
 where
     C: Compound<H>,
     M: 'a + Method<C, H>,
     H: ByteHash,
 {
-    type Item = io::Result<&'a C::Leaf>;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        let old = mem::replace(self, LeafIter::Exhausted);
+    // Moves to the next leaf, returns `None` when there are none left
+    fn step(&mut self) -> Option<io::Result<()>> {
+        let old = mem::replace(self, Cursor::Exhausted);
         match old {
-            LeafIter::Initial(node, mut method) => {
+            Cursor::Initial(node, mut method) => {
                 match Branch::new(node, &mut method) {
-                    Ok(Some(branch)) => {
-                        *self = LeafIter::Branch(branch, method);
-                    }
-                    Ok(None) => {
-                        *self = LeafIter::Exhausted;
-                    }
+                    Ok(Some(branch)) => *self = Cursor::Branch(branch, method),
+                    Ok(None) => return None,
                     Err(e) => return Some(Err(e)),
                 }
             }
-            LeafIter::Branch(branch, mut method) => {
+            Cursor::Branch(branch, mut method) => {
                 match branch.search(&mut method) {
-                    Ok(Some(branch)) => {
-                        *self = LeafIter::Branch(branch, method)
-                    }
+                    Ok(Some(branch)) => *self = Cursor::Branch(branch, method),
+                    Ok(None) => return None,
                     Err(e) => return Some(Err(e)),
-                    _ => (),
                 }
             }
-            LeafIter::Exhausted => return None,
+            Cursor::Exhausted => return None,
         }
+        Some(Ok(()))
+    }
 
-        let self_unsafe: &'a mut Self = unsafe { mem::transmute(self) };
+    // Compares the positions of two cursors, if both are on a leaf
+    fn cmp_position<N>(&self, other: &Cursor<'a, C, N, H>) -> Option<Ordering> {
+        match (self, other) {
+            (Cursor::Branch(a, _), Cursor::Branch(b, _)) => {
+                Some(a.cmp_position(b))
+            }
+            _ => None,
+        }
+    }
 
+    fn leaf(&mut self) -> Option<&'a C::Leaf> {
+        let self_unsafe: &'a mut Self = unsafe { mem::transmute(self) };
         match self_unsafe {
-            LeafIter::Branch(ref branch, _) => Some(Ok(&*branch)),
-            LeafIter::Initial(_, _) => unreachable!(),
-            LeafIter::Exhausted => None,
+            Cursor::Branch(ref branch, _) => Some(&*branch),
+            _ => None,
+        }
+    }
+}
+
+/// An iterator over the leaves of a Compound type
+///
+/// Iterators visiting all leaves in order are double-ended, the leaves
+/// then also being reachable from the last one.
+pub struct LeafIter<'a, C, M, H>
+where
+    C: Compound<H>,
+    H: ByteHash,
+{
+    front: Cursor<'a, C, M, H>,
+    back: Cursor<'a, C, Last, H>,
+}
+
+impl<'a, C, M, H> LeafIter<'a, C, M, H>
+where
+    C: Compound<H>,
+    H: ByteHash,
+{
+    /// Creates an iterator over the leaves of `node` found with `method`
+    pub fn new(node: &'a C, method: M) -> Self {
+        LeafIter {
+            front: Cursor::Initial(node, method),
+            back: Cursor::Initial(node, Last),
         }
     }
+
+    fn exhaust(&mut self) {
+        self.front = Cursor::Exhausted;
+        self.back = Cursor::Exhausted;
+    }
+}
+
+impl<'a, C, M, H> Iterator for LeafIter<'a, C, M, H>
+where
+    C: Compound<H>,
+    M: 'a + Method<C, H>,
+    H: ByteHash,
+{
+    type Item = io::Result<&'a C::Leaf>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.front.step() {
+            Some(Ok(())) => (),
+            Some(Err(e)) => return Some(Err(e)),
+            None => {
+                self.exhaust();
+                return None;
+            }
+        }
+        // stop where iteration from the back left off
+        let order = self.front.cmp_position(&self.back);
+        if order.map_or(false, |order| order != Ordering::Less) {
+            self.exhaust();
+            return None;
+        }
+        self.front.leaf().map(Ok)
+    }
+}
+
+impl<'a, C, H> DoubleEndedIterator for LeafIter<'a, C, First, H>
+where
+    C: Compound<H>,
+    H: ByteHash,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        match self.back.step() {
+            Some(Ok(())) => (),
+            Some(Err(e)) => return Some(Err(e)),
+            None => {
+                self.exhaust();
+                return None;
+            }
+        }
+        let order = self.front.cmp_position(&self.back);
+        if order.map_or(false, |order| order != Ordering::Less) {
+            self.exhaust();
+            return None;
+        }
+        self.back.leaf().map(Ok)
+    }
 }
 
 pub enum LeafIterMut<'a, C, M, H>
@@ -124,6 +211,9 @@ where
     fn iter(&self) -> LeafIter<Self, First, H>;
     /// Returns an iterator over the mutable leaves of the Compound
     fn iter_mut(&mut self) -> LeafIterMut<Self, First, H>;
+    /// Returns an iterator over the mutable leaves of the Compound, starting
+    /// from the last one
+    fn iter_mut_rev(&mut self) -> LeafIterMut<'_, Self, Last, H>;
 }
 
 impl<C, H> LeafIterable<H> for C
@@ -132,10 +222,14 @@ where
     H: ByteHash,
 {
     fn iter(&self) -> LeafIter<Self, First, H> {
-        LeafIter::Initial(self, First)
+        LeafIter::new(self, First)
     }
 
     fn iter_mut(&mut self) -> LeafIterMut<Self, First, H> {
         LeafIterMut::Initial(self, First)
     }
+
+    fn iter_mut_rev(&mut self) -> LeafIterMut<'_, Self, Last, H> {
+        LeafIterMut::Initial(self, Last)
+    }
 }
@@ -0,0 +1,213 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+use bytehash::ByteHash;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::oplog::Operation;
+use crate::sink::Sink;
+use crate::source::Source;
+use crate::store::Store;
+
+const OP: u8 = 0;
+const MARK: u8 = 1;
+
+enum Entry<O, D> {
+    Op(O),
+    Mark(D),
+}
+
+/// A journal of operations, kept in a file outside of the store
+///
+/// Every operation is appended as it is applied, and every committed root is
+/// marked with the number of operations it includes. A state restored from
+/// an older backup of the store can then be rolled forward to any position
+/// in the journal, not only to committed roots.
+///
+/// Operations are encoded on their own, so they must not contain handles to
+/// other nodes in the store.
+pub struct Journal<O, H> {
+    file: File,
+    path: PathBuf,
+    len: u64,
+    _marker: PhantomData<(O, H)>,
+}
+
+impl<O, H> Journal<O, H>
+where
+    O: Operation<H>,
+    H: ByteHash,
+{
+    /// Opens the journal at `path`, creating it if necessary
+    ///
+    /// An entry left incomplete by a crash is truncated.
+    pub fn open<P: Into<PathBuf>>(path: P) -> io::Result<Self> {
+        let path = path.into();
+        let mut file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(&path)?;
+
+        let mut len = 0;
+        let mut complete = 0;
+        let scratch = Store::ephemeral();
+        {
+            let mut reader = BufReader::new(&mut file);
+            loop {
+                match Self::read_entry(&mut reader, &scratch) {
+                    Ok(Entry::Op(_)) => len += 1,
+                    Ok(Entry::Mark(_)) => (),
+                    Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                        break
+                    }
+                    Err(e) => return Err(e),
+                }
+                complete = reader.stream_position()?;
+            }
+        }
+        file.set_len(complete)?;
+        file.seek(SeekFrom::End(0))?;
+
+        Ok(Journal {
+            file,
+            path,
+            len,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Returns the number of operations in the journal
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns true if the journal contains no operations
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the path of the journal file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends `op` to the journal, returning its position
+    pub fn append(&mut self, op: &mut O) -> io::Result<u64> {
+        let scratch = Store::ephemeral();
+        let mut sink = Sink::new(&scratch);
+        op.persist(&mut sink)?;
+        let bytes = sink.bytes();
+
+        let mut entry = Vec::with_capacity(bytes.len() + 5);
+        entry.push(OP);
+        entry.write_u32::<BigEndian>(bytes.len() as u32)?;
+        entry.extend_from_slice(bytes);
+        self.file.write_all(&entry)?;
+
+        self.len += 1;
+        Ok(self.len - 1)
+    }
+
+    /// Marks `root` as including all operations appended so far
+    ///
+    /// The journal is synced to disk, so the root can be rolled forward from
+    /// once it is committed.
+    pub fn mark(&mut self, root: &H::Digest) -> io::Result<()> {
+        let mut entry = Vec::with_capacity(root.as_ref().len() + 1);
+        entry.push(MARK);
+        entry.extend_from_slice(root.as_ref());
+        self.file.write_all(&entry)?;
+        self.file.sync_data()
+    }
+
+    /// Rolls `target`, the state at the committed `root`, forward to
+    /// position `to` in the journal
+    ///
+    /// Returns a `NotFound` error if `root` was never marked, and an
+    /// `InvalidInput` error if `to` is before the root, or past the end of
+    /// the journal.
+    pub fn roll_forward(
+        &self,
+        root: &H::Digest,
+        target: &mut O::Target,
+        to: u64,
+    ) -> io::Result<()> {
+        if to > self.len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Position past the end of the journal",
+            ));
+        }
+        let scratch = Store::ephemeral();
+        let mut reader = BufReader::new(File::open(&self.path)?);
+
+        // the latest mark of the root, and the operations following it
+        let mut from = None;
+        let mut pending = vec![];
+        let mut pos = 0;
+        while pos < to || from.is_none() {
+            match Self::read_entry(&mut reader, &scratch) {
+                Ok(Entry::Op(op)) => {
+                    if from.is_some() && pos < to {
+                        pending.push(op)
+                    }
+                    pos += 1;
+                }
+                Ok(Entry::Mark(digest)) => {
+                    if digest == *root {
+                        from = Some(pos);
+                        pending.clear();
+                    }
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        match from {
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "Root not found in journal",
+            )),
+            Some(from) if from > to => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Position before the root",
+            )),
+            Some(_) => {
+                for op in pending {
+                    op.apply(target)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn read_entry<R: Read>(
+        reader: &mut R,
+        scratch: &Store<H>,
+    ) -> io::Result<Entry<O, H::Digest>> {
+        match reader.read_u8()? {
+            OP => {
+                let len = reader.read_u32::<BigEndian>()? as usize;
+                let mut bytes = vec![0u8; len];
+                reader.read_exact(&mut bytes)?;
+                let mut source =
+                    Source::new(Box::new(io::Cursor::new(bytes)), scratch);
+                Ok(Entry::Op(O::restore(&mut source)?))
+            }
+            MARK => {
+                let mut digest = H::Digest::default();
+                reader.read_exact(digest.as_mut())?;
+                Ok(Entry::Mark(digest))
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Invalid journal entry",
+            )),
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use std::io;
+use std::mem;
+
+use bytehash::ByteHash;
+
+use crate::compound::Compound;
+use crate::handle::{Handle, HandleMut, HandleOwned, HandleRef, HandleType};
+use crate::map::{ValPath, ValPathMut, KV};
+use crate::search::{Method, SearchResult};
+
+/// A compound with one slot for each key in every node, usable as a map
+///
+/// Structures where searching for a key selects the one slot it belongs in
+/// at every level, such as tries and hash array mapped tries, get the whole
+/// map API by naming their key search, and iterate like any `KeyValIterable`.
+/// The search has to return `SearchResult::Leaf` for the slot holding the
+/// key, and `SearchResult::Path` for the slot the key belongs in otherwise.
+/// Structures passed where a `MapMut` is expected implement it by calling
+/// the methods of this trait.
+pub trait Keyed<K, V, H>: Compound<H, Leaf = KV<K, V>>
+where
+    H: ByteHash,
+{
+    /// The method used to search for keys in the structure
+    type KeySearch: Method<Self, H> + for<'k> From<&'k K>;
+
+    /// Returns a reference to a value in the map, if any
+    fn get(&self, k: &K) -> io::Result<Option<ValPath<'_, K, V, Self, H>>> {
+        ValPath::new(self, &mut Self::KeySearch::from(k))
+    }
+
+    /// Returns a reference to a mutable value in the map, if any
+    fn get_mut(
+        &mut self,
+        k: &K,
+    ) -> io::Result<Option<ValPathMut<'_, K, V, Self, H>>> {
+        ValPathMut::new(self, &mut Self::KeySearch::from(k))
+    }
+
+    /// Insert key-value pair, optionally returning the replaced value
+    ///
+    /// Fails with an `InvalidInput` error if the search can not tell the key
+    /// apart from one already in the map.
+    fn insert(&mut self, k: K, v: V) -> io::Result<Option<V>> {
+        let mut search = Self::KeySearch::from(&k);
+        match place(self, &mut search, KV::new(k, v))? {
+            Placed::Replaced(old) => Ok(Some(old)),
+            Placed::Inserted => Ok(None),
+            Placed::Collision(a, b) => {
+                let mut search_a = Self::KeySearch::from(&a.key);
+                let mut search_b = Self::KeySearch::from(&b.key);
+                resolve(self, a, &mut search_a, b, &mut search_b)?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Remove the value at key, returning it
+    fn remove(&mut self, k: &K) -> io::Result<Option<V>> {
+        remove_leaf(self, &mut Self::KeySearch::from(k))
+    }
+}
+
+// The outcome of placing a leaf in a node
+enum Placed<K, V> {
+    // the key was present, with the returned value
+    Replaced(V),
+    Inserted,
+    // the slot of the key holds the first leaf, which was taken out to be
+    // placed along with the second
+    Collision(KV<K, V>, KV<K, V>),
+}
+
+fn select<C, M, H>(node: &C, method: &mut M) -> io::Result<SearchResult>
+where
+    C: Compound<H>,
+    M: Method<C, H>,
+    H: ByteHash,
+{
+    match method.select(node, 0) {
+        SearchResult::None => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Key search found no slot for the key",
+        )),
+        result => Ok(result),
+    }
+}
+
+fn place<C, K, V, H>(
+    node: &mut C,
+    search: &mut C::KeySearch,
+    leaf: KV<K, V>,
+) -> io::Result<Placed<K, V>>
+where
+    C: Keyed<K, V, H>,
+    H: ByteHash,
+{
+    let i = match select(node, search)? {
+        SearchResult::Leaf(i) => {
+            let existing = node.children_mut()[i]
+                .leaf_mut()
+                .expect("search selected a leaf");
+            return Ok(Placed::Replaced(mem::replace(
+                &mut existing.val,
+                leaf.val,
+            )));
+        }
+        SearchResult::Path(i) => i,
+        SearchResult::None => unreachable!(),
+    };
+    let slot = &mut node.children_mut()[i];
+    match slot.handle_type() {
+        HandleType::Node => {
+            if let HandleMut::Node(child) = &mut *slot.inner_mut()? {
+                return place(*child, search, leaf);
+            }
+            unreachable!()
+        }
+        HandleType::Leaf => {
+            let other = slot.replace(HandleOwned::None);
+            if let HandleOwned::Leaf(other) = other {
+                return Ok(Placed::Collision(other, leaf));
+            }
+            unreachable!()
+        }
+        HandleType::None => (),
+    }
+    slot.replace(HandleOwned::Leaf(leaf));
+    Ok(Placed::Inserted)
+}
+
+// Places two leaves in a node of their own, split further while the
+// searches select the same slot, returns the first leaf if they never part
+fn split<C, K, V, H>(
+    a: KV<K, V>,
+    search_a: &mut C::KeySearch,
+    b: KV<K, V>,
+    search_b: &mut C::KeySearch,
+) -> Result<C, KV<K, V>>
+where
+    C: Keyed<K, V, H>,
+    H: ByteHash,
+{
+    let mut node = C::default();
+    match (search_a.select(&node, 0), search_b.select(&node, 0)) {
+        (SearchResult::Path(i), SearchResult::Path(j)) if i == j => {
+            let child = split::<C, K, V, H>(a, search_a, b, search_b)?;
+            node.children_mut()[i] = Handle::new_node(child);
+        }
+        (SearchResult::Path(i), SearchResult::Path(j)) => {
+            node.children_mut()[i] = Handle::new_leaf(a);
+            node.children_mut()[j] = Handle::new_leaf(b);
+        }
+        _ => return Err(a),
+    }
+    Ok(node)
+}
+
+// Follows both searches down to the empty slot left by a collision
+fn resolve<C, K, V, H>(
+    node: &mut C,
+    a: KV<K, V>,
+    search_a: &mut C::KeySearch,
+    b: KV<K, V>,
+    search_b: &mut C::KeySearch,
+) -> io::Result<()>
+where
+    C: Keyed<K, V, H>,
+    H: ByteHash,
+{
+    let i = match search_a.select(node, 0) {
+        SearchResult::Path(i) => i,
+        _ => unreachable!("the collision slot is empty"),
+    };
+    search_b.select(node, 0);
+    let slot = &mut node.children_mut()[i];
+    if let HandleMut::Node(child) = &mut *slot.inner_mut()? {
+        return resolve(*child, a, search_a, b, search_b);
+    }
+    match split::<C, K, V, H>(a, search_a, b, search_b) {
+        Ok(node) => {
+            *slot = Handle::new_node(node);
+            Ok(())
+        }
+        Err(a) => {
+            // the leaf taken out stays in place
+            *slot = Handle::new_leaf(a);
+            Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Key search can not tell keys apart",
+            ))
+        }
+    }
+}
+
+fn remove_leaf<C, K, V, H>(
+    node: &mut C,
+    search: &mut C::KeySearch,
+) -> io::Result<Option<V>>
+where
+    C: Keyed<K, V, H>,
+    H: ByteHash,
+{
+    let i = match select(node, search)? {
+        SearchResult::Leaf(i) => {
+            let slot = &mut node.children_mut()[i];
+            if let HandleOwned::Leaf(leaf) = slot.replace(HandleOwned::None) {
+                return Ok(Some(leaf.val));
+            }
+            unreachable!("search selected a leaf")
+        }
+        SearchResult::Path(i) => i,
+        SearchResult::None => unreachable!(),
+    };
+    let slot = &mut node.children_mut()[i];
+    let removed = match &mut *slot.inner_mut()? {
+        HandleMut::Node(child) => remove_leaf(*child, search)?,
+        _ => return Ok(None),
+    };
+    if removed.is_some() {
+        collapse(slot)?;
+    }
+    Ok(removed)
+}
+
+// Replaces a node left with a single leaf by the leaf, and an empty node by
+// an empty slot
+fn collapse<C, H>(slot: &mut Handle<C, H>) -> io::Result<()>
+where
+    C: Compound<H>,
+    H: ByteHash,
+{
+    let mut single = None;
+    if let HandleRef::Node(node) = slot.inner()? {
+        for (i, child) in node.children().iter().enumerate() {
+            match (child.inner()?, single) {
+                (HandleRef::None, _) => (),
+                (HandleRef::Leaf(_), None) => single = Some(i),
+                _ => return Ok(()),
+            }
+        }
+    }
+    let replacement = match (slot.replace(HandleOwned::None), single) {
+        (HandleOwned::Node(mut node), Some(i)) => HandleOwned::Leaf(
+            mem::take(&mut node.children_mut()[i]).into_leaf(),
+        ),
+        _ => HandleOwned::None,
+    };
+    slot.replace(replacement);
+    Ok(())
+}
@@ -7,39 +7,154 @@ pub mod tests;
 /// A collection of tree annotations
 pub mod annotations;
 
+/// Merkle inclusion proofs
+pub mod proof;
+
+mod append;
 mod backend;
+mod blob;
 mod branch;
+mod chunks;
 mod compound;
+#[cfg(feature = "compression")]
+mod compression;
 mod content;
+mod control;
+mod crdt;
+mod cursor;
 mod debug_draw;
+mod dedup;
+mod diff;
+mod dual;
+mod error;
+mod estimate;
+mod eviction;
+mod export;
+mod field;
+mod filter;
+mod freeze;
+mod gc;
 mod handle;
+#[cfg(any(feature = "blake3", feature = "sha256"))]
+mod hashes;
 mod iter;
+mod journal;
+mod keyed;
+mod maintenance;
 mod map;
+mod merge;
+mod migrate;
+mod namespace;
+#[cfg(feature = "async")]
+mod nonblocking;
+mod oplog;
+mod partition;
+mod portable;
+mod prefetch;
 mod raw_branch;
+mod rebalance;
+mod reclaim;
+mod records;
 mod root;
+mod schema;
 mod search;
+mod shard;
 mod sink;
 mod source;
+#[cfg(feature = "filesystem")]
+mod spill;
 mod store;
+mod trace;
+mod transaction;
+mod transfer;
+mod view;
 
 pub use crate::annotations::{
     Annotation, Associative, Combine, VoidAnnotation,
 };
-pub use crate::backend::Backend;
+pub use crate::append::AppendOnly;
+#[cfg(feature = "async")]
+pub use crate::backend::AsyncBackend;
+pub use crate::backend::{
+    Backend, CacheControl, CachedBackend, DirRoots, Fetch, HttpFetch,
+    MemBackend, MemRoots, ObjectBackend, ObjectStore, PutResult, RemoteBackend,
+    RootStore, RootUpdate, TieredBackend,
+};
+#[cfg(feature = "compression")]
+pub use crate::backend::{Codec, CompressedBackend, CompressionPolicy};
+#[cfg(feature = "filesystem")]
+pub use crate::backend::{DirObjectStore, DiskBackend};
+pub use crate::blob::Blob;
 pub use crate::branch::{Branch, BranchMut};
+pub use crate::chunks::{ChunkProvider, ChunkReceiver, Manifest};
 pub use crate::compound::Compound;
+#[cfg(feature = "compression")]
+pub use crate::compression::{Compressed, Dictionary};
 pub use crate::content::Content;
-pub use crate::debug_draw::{DebugDraw, DrawState};
+#[cfg(feature = "derive")]
+pub use kelvin_derive::{Annotation, Content};
+pub use crate::control::{CancelToken, Control, ControlledIterator};
+pub use crate::crdt::{GCounter, LwwRegister, Merge, ORSet, ReplicaId};
+pub use crate::cursor::Cursor;
+pub use crate::debug_draw::{DebugDraw, DrawState, Summary};
+pub use crate::dedup::Dedup;
+pub use crate::diff::{diff, diff_stats, Change, Diff, DiffStats};
+pub use crate::dual::DualMap;
+pub use crate::error::{Error, Result};
+pub use crate::estimate::{estimate_count, Estimate};
+pub use crate::eviction::{Clock, EvictionPolicy, Lfu, Lru, TinyLfu};
+pub use crate::export::{export, export_annotations, import, AnnotationRecord};
+pub use crate::field::{frame, Algebraic, AlgebraicState, FieldHash};
+pub use crate::filter::KeyFilter;
+pub use crate::freeze::{Freeze, Frozen};
+pub use crate::gc::{reach_children, LiveSet, Reach};
 pub use crate::handle::{
-    Handle, HandleMut, HandleOwned, HandleRef, HandleType,
+    Handle, HandleMut, HandleOwned, HandleRef, HandleType, WeakHandle,
+};
+#[cfg(feature = "blake3")]
+pub use crate::hashes::{Blake3, Blake3State};
+#[cfg(feature = "sha256")]
+pub use crate::hashes::{Sha256, Sha256State};
+pub use crate::iter::{LeafIter, LeafIterable};
+pub use crate::journal::Journal;
+pub use crate::keyed::Keyed;
+pub use crate::maintenance::{
+    integrity_scan, Limits, Maintenance, Priority, TaskReport, Throttle,
+};
+pub use crate::map::{
+    Entry, KeyOrdered, KeyValIterable, MapMut, OccupiedError, VacantEntry,
+    ValIterable, ValPath, ValPathMut, ValRef, ValRefMut, KV,
+};
+pub use crate::merge::{merge, Conflict};
+#[cfg(feature = "parallel")]
+pub use crate::migrate::map_values_par;
+pub use crate::migrate::{
+    map_keys, map_values, migrate, rehash, rehash_roots, Migration, Migrations,
+    Then,
 };
-pub use crate::iter::LeafIterable;
-pub use crate::map::{ValIterable, ValPath, ValPathMut, ValRef, ValRefMut, KV};
-pub use crate::root::Root;
-pub use crate::search::{Method, SearchResult};
-pub use crate::sink::Sink;
+pub use crate::namespace::{Namespace, Namespaces, ReadView};
+#[cfg(feature = "async")]
+pub use crate::nonblocking::AsyncStore;
+pub use crate::oplog::{EventSourced, OpLog, Operation};
+pub use crate::portable::{portable_hash, PortableHasher};
+pub use crate::rebalance::Rebalance;
+pub use crate::reclaim::{Deferred, Reclaimer};
+pub use crate::records::{NodeKind, NodeRecord, NodeRecords};
+pub use crate::root::{Root, RootConflict};
+pub use crate::schema::{Mismatch, Registry, Schema};
+pub use crate::search::{Method, RangeSearch, SearchResult};
+pub use crate::shard::{shard_of, Sharded};
+pub use crate::sink::{Domain, Sink, FORMAT_VERSION};
 pub use crate::source::Source;
-pub use crate::store::{Shared, Snapshot, Store};
+#[cfg(feature = "filesystem")]
+pub use crate::spill::Spill;
+#[cfg(feature = "filesystem")]
+pub use crate::store::Relocation;
+pub use crate::store::{Pinned, PreloadPolicy, Shared, Snapshot, Store};
+pub use crate::trace::{Recorder, ReplayReport, Trace, TraceEvent, TraceOp};
+pub use crate::transaction::Transaction;
+pub use crate::transfer::move_entry;
+pub use crate::view::{View, Viewed};
 
 // Re-export
 pub use bytehash::{Blake2b, ByteHash, State as ByteHashState};
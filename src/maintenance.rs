@@ -0,0 +1,336 @@
+use std::fmt;
+use std::hash::Hasher;
+use std::io;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use bytehash::State;
+use parking_lot::{Condvar, Mutex};
+
+use crate::compound::Compound;
+use crate::error::Error;
+use crate::store::{Snapshot, Store};
+use crate::ByteHash;
+
+/// The priority of a maintenance task, among the tasks that are due
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// Run when nothing else is due
+    Low,
+    /// The default priority
+    Normal,
+    /// Run before any other due task
+    High,
+}
+
+/// Limits on the resources used by maintenance
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    /// Bytes per second tasks may read or write, unlimited if `None`
+    pub io_bytes_per_sec: Option<u64>,
+    /// Share of the time the maintenance thread may be busy, in `(0, 1]`
+    pub cpu_share: f64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            io_bytes_per_sec: None,
+            cpu_share: 1.0,
+        }
+    }
+}
+
+/// Rate limiter handed to running tasks
+///
+/// Tasks report the bytes they read or write with `consume`, which sleeps for
+/// as long as needed to stay within the IO limit.
+#[derive(Debug)]
+pub struct Throttle {
+    bytes_per_sec: Option<u64>,
+    started: Instant,
+    consumed: u64,
+}
+
+impl Throttle {
+    fn new(bytes_per_sec: Option<u64>) -> Self {
+        Throttle {
+            bytes_per_sec,
+            started: Instant::now(),
+            consumed: 0,
+        }
+    }
+
+    /// Accounts for `bytes` of IO, sleeping if over the limit
+    pub fn consume(&mut self, bytes: usize) {
+        self.consumed += bytes as u64;
+        if let Some(rate) = self.bytes_per_sec {
+            let due =
+                Duration::from_secs_f64(self.consumed as f64 / rate as f64);
+            let elapsed = self.started.elapsed();
+            if due > elapsed {
+                thread::sleep(due - elapsed)
+            }
+        }
+    }
+
+    /// Returns the number of bytes consumed so far
+    pub fn consumed(&self) -> u64 {
+        self.consumed
+    }
+}
+
+type TaskFn = Box<dyn FnMut(&mut Throttle) -> io::Result<()> + Send>;
+
+struct Task {
+    name: String,
+    priority: Priority,
+    interval: Duration,
+    due: Instant,
+    run: TaskFn,
+    report: TaskReport,
+}
+
+/// The record of the runs of a maintenance task
+#[derive(Clone, Debug, Default)]
+pub struct TaskReport {
+    /// The name of the task
+    pub name: String,
+    /// Number of completed runs
+    pub runs: u64,
+    /// Number of runs that failed
+    pub failures: u64,
+    /// The error of the last failed run
+    pub last_error: Option<String>,
+    /// Bytes of IO accounted by the last run
+    pub last_io: u64,
+}
+
+struct Tasks {
+    tasks: Vec<Task>,
+    stopped: bool,
+}
+
+struct Shared {
+    limits: Limits,
+    tasks: Mutex<Tasks>,
+    wakeup: Condvar,
+}
+
+/// Scheduler running maintenance of stores in the background
+///
+/// Tasks like integrity scans or cache demotion are registered with an
+/// interval and a priority, and run one at a time, within the IO and CPU
+/// limits of the scheduler. Tasks can be run in the calling thread with
+/// `run_pending`, or on a background thread after `start`. The thread is
+/// stopped when the scheduler is dropped.
+pub struct Maintenance {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl fmt::Debug for Maintenance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Maintenance({} tasks)",
+            self.shared.tasks.lock().tasks.len()
+        )
+    }
+}
+
+impl Maintenance {
+    /// Creates a new scheduler, with no tasks
+    pub fn new(limits: Limits) -> Self {
+        assert!(
+            limits.cpu_share > 0.0 && limits.cpu_share <= 1.0,
+            "CPU share out of range"
+        );
+        Maintenance {
+            shared: Arc::new(Shared {
+                limits,
+                tasks: Mutex::new(Tasks {
+                    tasks: vec![],
+                    stopped: false,
+                }),
+                wakeup: Condvar::new(),
+            }),
+            thread: None,
+        }
+    }
+
+    /// Schedules `task` to run every `interval`, the first time right away
+    pub fn schedule<F>(
+        &self,
+        name: &str,
+        priority: Priority,
+        interval: Duration,
+        task: F,
+    ) where
+        F: FnMut(&mut Throttle) -> io::Result<()> + Send + 'static,
+    {
+        self.shared.tasks.lock().tasks.push(Task {
+            name: name.into(),
+            priority,
+            interval,
+            due: Instant::now(),
+            run: Box::new(task),
+            report: TaskReport {
+                name: name.into(),
+                ..TaskReport::default()
+            },
+        });
+        self.shared.wakeup.notify_one();
+    }
+
+    /// Removes the task with the given name, returning false if there is none
+    ///
+    /// A task that is running at the time is not removed.
+    pub fn unschedule(&self, name: &str) -> bool {
+        let mut tasks = self.shared.tasks.lock();
+        let len = tasks.tasks.len();
+        tasks.tasks.retain(|task| task.name != name);
+        tasks.tasks.len() < len
+    }
+
+    /// Runs the tasks that are due in the calling thread, returning how many
+    /// were run
+    pub fn run_pending(&self) -> usize {
+        let now = Instant::now();
+        let mut ran = 0;
+        while self.shared.run_next(now) {
+            ran += 1;
+        }
+        ran
+    }
+
+    /// Starts running the tasks on a background thread
+    pub fn start(&mut self) {
+        if self.thread.is_some() {
+            return;
+        }
+        let shared = self.shared.clone();
+        self.thread = Some(
+            thread::Builder::new()
+                .name("kelvin-maintenance".into())
+                .spawn(move || shared.run())
+                .expect("could not spawn maintenance thread"),
+        );
+    }
+
+    /// Returns the reports of all tasks
+    pub fn reports(&self) -> Vec<TaskReport> {
+        let tasks = self.shared.tasks.lock();
+        tasks.tasks.iter().map(|task| task.report.clone()).collect()
+    }
+}
+
+impl Drop for Maintenance {
+    fn drop(&mut self) {
+        self.shared.tasks.lock().stopped = true;
+        self.shared.wakeup.notify_one();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Shared {
+    fn run(&self) {
+        loop {
+            let now = Instant::now();
+            while self.run_next(now) {}
+            let mut tasks = self.tasks.lock();
+            if tasks.stopped {
+                return;
+            }
+            match tasks.tasks.iter().map(|task| task.due).min() {
+                Some(due) => {
+                    let now = Instant::now();
+                    if due > now {
+                        self.wakeup.wait_for(&mut tasks, due - now);
+                    }
+                }
+                None => self.wakeup.wait(&mut tasks),
+            }
+            if tasks.stopped {
+                return;
+            }
+        }
+    }
+
+    // Runs the task due at `now` with the highest priority, if any
+    fn run_next(&self, now: Instant) -> bool {
+        // the task is taken out while running, so that the lock is not held
+        let mut task = {
+            let mut tasks = self.tasks.lock();
+            if tasks.stopped {
+                return false;
+            }
+            let next = tasks
+                .tasks
+                .iter()
+                .enumerate()
+                .filter(|(_, task)| task.due <= now)
+                .max_by_key(|(_, task)| (task.priority, now - task.due))
+                .map(|(i, _)| i);
+            match next {
+                Some(i) => tasks.tasks.remove(i),
+                None => return false,
+            }
+        };
+
+        let started = Instant::now();
+        let mut throttle = Throttle::new(self.limits.io_bytes_per_sec);
+        let result = (task.run)(&mut throttle);
+        let busy = started.elapsed();
+
+        task.report.runs += 1;
+        task.report.last_io = throttle.consumed();
+        if let Err(e) = result {
+            task.report.failures += 1;
+            task.report.last_error = Some(e.to_string());
+        }
+        task.due = Instant::now() + task.interval;
+        self.tasks.lock().tasks.push(task);
+
+        // stay idle long enough to keep within the CPU share
+        let share = self.limits.cpu_share;
+        if share < 1.0 {
+            thread::sleep(busy.mul_f64((1.0 - share) / share));
+        }
+        true
+    }
+}
+
+/// Returns a task verifying every node reachable from the roots returned by
+/// `roots`, for use with `Maintenance::schedule`
+///
+/// Every node read is hashed again, and the task fails on the first one not
+/// matching its digest. The bytes of the nodes read are accounted against
+/// the IO limit.
+pub fn integrity_scan<C, H, F>(
+    store: Store<H>,
+    mut roots: F,
+) -> impl FnMut(&mut Throttle) -> io::Result<()> + Send
+where
+    C: Compound<H> + 'static,
+    H: ByteHash,
+    F: FnMut() -> Vec<Snapshot<C, H>> + Send,
+{
+    move |throttle| {
+        for root in roots() {
+            for record in store.node_records(&root) {
+                let record = record?;
+                throttle.consume(record.bytes.len());
+                let mut state = H::state();
+                state.write(&record.bytes);
+                if state.fin() != record.digest {
+                    return Err(Error::Corrupted.into());
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,16 +1,19 @@
 use std::borrow::{Borrow, BorrowMut};
 use std::io;
 use std::marker::PhantomData;
-use std::ops::{Deref, DerefMut};
+use std::mem;
+use std::ops::{Deref, DerefMut, RangeBounds};
 
 use bytehash::ByteHash;
 use owning_ref::{OwningRef, OwningRefMut, StableAddress};
 
+use crate::annotations::MaxKey;
 use crate::branch::{Branch, BranchMut};
 use crate::compound::Compound;
 use crate::content::Content;
+use crate::gc::Reach;
 use crate::iter::{LeafIter, LeafIterMut};
-use crate::search::{First, Method};
+use crate::search::{First, Method, RangeSearch};
 use crate::sink::Sink;
 use crate::source::Source;
 
@@ -53,6 +56,11 @@ where
             val: V::restore(source)?,
         })
     }
+
+    fn reach<R: Reach<H>>(&self, reach: &mut R) -> io::Result<()> {
+        self.key.reach(reach)?;
+        self.val.reach(reach)
+    }
 }
 
 impl<K, V> Borrow<V> for KV<K, V> {
@@ -93,6 +101,18 @@ where
     _marker: PhantomData<(K, V)>,
 }
 
+/// Error returned by `try_insert` when the key is already present
+pub struct OccupiedError<'a, K, V, C, H>
+where
+    C: Compound<H>,
+    H: ByteHash,
+{
+    /// Path to the value already in the map
+    pub existing: ValPathMut<'a, K, V, C, H>,
+    /// The value that was not inserted
+    pub value: V,
+}
+
 // The following
 unsafe impl<'a, K, V, C, H> StableAddress for ValPath<'a, K, V, C, H>
 where
@@ -145,6 +165,23 @@ where
                 _marker: PhantomData,
             }))
     }
+
+    /// Creates a new `ValPathMut` when leaf is found and key matches, or
+    /// gives `node` back otherwise
+    pub fn search<M>(
+        node: &'a mut C,
+        method: &mut M,
+    ) -> io::Result<Result<Self, &'a mut C>>
+    where
+        M: Method<C, H>,
+    {
+        Ok(
+            BranchMut::exact_or_root(node, method)?.map(|branch| ValPathMut {
+                branch,
+                _marker: PhantomData,
+            }),
+        )
+    }
 }
 
 impl<'a, K, V, C, H> Deref for ValPath<'a, K, V, C, H>
@@ -202,6 +239,22 @@ where
     C: Compound<H>,
     H: ByteHash;
 
+pub struct PairIter<'a, C, K, V, M, H>(
+    LeafIter<'a, C, M, H>,
+    PhantomData<(K, V)>,
+)
+where
+    C: Compound<H>,
+    H: ByteHash;
+
+pub struct PairIterMut<'a, C, K, V, M, H>(
+    LeafIterMut<'a, C, M, H>,
+    PhantomData<(K, V)>,
+)
+where
+    C: Compound<H>,
+    H: ByteHash;
+
 /// Compound can be iterated over like a map
 pub trait ValIterable<V, H>
 where
@@ -222,7 +275,7 @@ where
     H: ByteHash,
 {
     fn values(&self) -> ValIter<Self, V, First, H> {
-        ValIter(LeafIter::Initial(self, First), PhantomData)
+        ValIter(LeafIter::new(self, First), PhantomData)
     }
 
     fn values_mut(&mut self) -> ValIterMut<Self, V, First, H> {
@@ -230,6 +283,66 @@ where
     }
 }
 
+/// Marks maps keeping their leaves ordered by key
+///
+/// Searches relying on the order of keys, such as `KeyValIterable::range`,
+/// are only available for these maps. Maps placing leaves by hash, such as
+/// HAMTs, must not implement it.
+pub trait KeyOrdered<H: ByteHash>: Compound<H> {}
+
+/// Compound with key-value leaves can have its keys and pairs iterated over
+pub trait KeyValIterable<K, V, H>
+where
+    Self: Compound<H, Leaf = KV<K, V>>,
+    H: ByteHash,
+{
+    /// Iterator over the keys of the map
+    fn keys(&self) -> KeyIter<'_, Self, K, V, First, H>;
+
+    /// Iterator over the key-value pairs of the map
+    fn pairs(&self) -> PairIter<'_, Self, K, V, First, H>;
+
+    /// Iterator over the keys and mutable values of the map
+    fn pairs_mut(&mut self) -> PairIterMut<'_, Self, K, V, First, H>;
+
+    /// Iterator over the key-value pairs with keys in `range`, in key order
+    ///
+    /// Only available for maps keeping their leaves ordered by key, see
+    /// `KeyOrdered`, and annotating subtrees with their `MaxKey`, so that
+    /// subtrees outside the range are skipped.
+    fn range<O, R>(
+        &self,
+        range: R,
+    ) -> PairIter<'_, Self, K, V, RangeSearch<K, O, R>, H>
+    where
+        Self: KeyOrdered<H>,
+        Self::Annotation: Borrow<MaxKey<K>>,
+        K: Borrow<O>,
+        O: Ord + ?Sized,
+        R: RangeBounds<O>,
+    {
+        PairIter(LeafIter::new(self, RangeSearch::new(range)), PhantomData)
+    }
+}
+
+impl<C, K, V, H> KeyValIterable<K, V, H> for C
+where
+    C: Compound<H, Leaf = KV<K, V>>,
+    H: ByteHash,
+{
+    fn keys(&self) -> KeyIter<'_, Self, K, V, First, H> {
+        KeyIter(LeafIter::new(self, First), PhantomData)
+    }
+
+    fn pairs(&self) -> PairIter<'_, Self, K, V, First, H> {
+        PairIter(LeafIter::new(self, First), PhantomData)
+    }
+
+    fn pairs_mut(&mut self) -> PairIterMut<'_, Self, K, V, First, H> {
+        PairIterMut(LeafIterMut::Initial(self, First), PhantomData)
+    }
+}
+
 impl<'a, C, V, M, H> Iterator for ValIter<'a, C, V, M, H>
 where
     C: Compound<H>,
@@ -271,7 +384,7 @@ where
 impl<'a, C, K, V, M, H> Iterator for KeyIter<'a, C, K, V, M, H>
 where
     C: Compound<H>,
-    C::Leaf: Borrow<K>,
+    C::Leaf: AsRef<K>,
     M: 'a + Method<C, H>,
     K: 'a,
     V: 'a,
@@ -281,7 +394,45 @@ where
 
     fn next(&mut self) -> Option<Self::Item> {
         match self.0.next() {
-            Some(Ok(leaf)) => Some(Ok(leaf.borrow())),
+            Some(Ok(leaf)) => Some(Ok(leaf.as_ref())),
+            Some(Err(e)) => Some(Err(e)),
+            None => None,
+        }
+    }
+}
+
+impl<'a, C, K, V, M, H> Iterator for PairIter<'a, C, K, V, M, H>
+where
+    C: Compound<H, Leaf = KV<K, V>>,
+    M: 'a + Method<C, H>,
+    K: 'a,
+    V: 'a,
+    H: ByteHash,
+{
+    type Item = io::Result<(&'a K, &'a V)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.0.next() {
+            Some(Ok(leaf)) => Some(Ok((&leaf.key, &leaf.val))),
+            Some(Err(e)) => Some(Err(e)),
+            None => None,
+        }
+    }
+}
+
+impl<'a, C, K, V, M, H> Iterator for PairIterMut<'a, C, K, V, M, H>
+where
+    C: Compound<H, Leaf = KV<K, V>>,
+    M: 'a + Method<C, H>,
+    K: 'a,
+    V: 'a,
+    H: ByteHash,
+{
+    type Item = io::Result<(&'a K, &'a mut V)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.0.next() {
+            Some(Ok(leaf)) => Some(Ok((&leaf.key, &mut leaf.val))),
             Some(Err(e)) => Some(Err(e)),
             None => None,
         }
@@ -352,3 +503,218 @@ where
         ValPathMut::new(self, &mut Self::KeySearch::from(k.borrow()))
     }
 }
+
+/// Collection can be written to as a map
+pub trait MapMut<K, V, H>
+where
+    Self: Compound<H>,
+    H: ByteHash,
+{
+    /// Insert key-value pair, optionally returning the replaced value
+    fn insert(&mut self, k: K, v: V) -> io::Result<Option<V>>;
+
+    /// Insert key-value pair, unless the key is already present
+    fn try_insert(
+        &mut self,
+        k: K,
+        v: V,
+    ) -> io::Result<Result<(), OccupiedError<'_, K, V, Self, H>>>;
+
+    /// Remove the value at key, returning it
+    fn remove(&mut self, k: &K) -> io::Result<Option<V>>;
+
+    /// Insert all key-value pairs, later pairs replacing earlier ones with
+    /// the same key
+    ///
+    /// By default the pairs are inserted one by one, structures that can
+    /// build on several pairs at once override this.
+    fn insert_batch<I>(&mut self, pairs: I) -> io::Result<()>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        Self: Sized,
+    {
+        for (k, v) in pairs {
+            self.insert(k, v)?;
+        }
+        Ok(())
+    }
+
+    /// Returns a reference to a value in the map, if any
+    fn get(&self, k: &K) -> io::Result<Option<ValPath<'_, K, V, Self, H>>>;
+
+    /// Returns a reference to a mutable value in the map, if any
+    fn get_mut(
+        &mut self,
+        k: &K,
+    ) -> io::Result<Option<ValPathMut<'_, K, V, Self, H>>>;
+
+    /// Returns a reference to the mutable value at key, or the map itself if
+    /// the key is absent
+    ///
+    /// By default a present key is looked up twice, structures override
+    /// this to search once, see `ValPathMut::search`.
+    fn search_mut(
+        &mut self,
+        k: &K,
+    ) -> io::Result<Result<ValPathMut<'_, K, V, Self, H>, &mut Self>> {
+        if self.get_mut(k)?.is_none() {
+            return Ok(Err(self));
+        }
+        Ok(Ok(self.get_mut(k)?.expect("key present")))
+    }
+
+    /// Inserts a key-value pair, returning a reference to the mutable value
+    /// inserted
+    ///
+    /// By default the value is looked up after inserting it, structures
+    /// that can keep track of it while inserting override this.
+    fn insert_mut(
+        &mut self,
+        k: K,
+        v: V,
+    ) -> io::Result<ValPathMut<'_, K, V, Self, H>>
+    where
+        K: Clone,
+    {
+        self.insert(k.clone(), v)?;
+        Ok(self.get_mut(&k)?.expect("value just inserted"))
+    }
+
+    /// Replaces the value at key, returning the old value
+    ///
+    /// Nothing is inserted if the key is not present. The value is replaced
+    /// in place through `get_mut`, repairing the annotations along the path.
+    fn replace(&mut self, k: &K, v: V) -> io::Result<Option<V>>
+    where
+        Self::Leaf: BorrowMut<V>,
+    {
+        Ok(self.get_mut(k)?.map(|mut val| mem::replace(&mut *val, v)))
+    }
+
+    /// Swaps the values at two keys, returning false, with the map left
+    /// unchanged, if either is missing
+    ///
+    /// The values are moved rather than cloned. The value at `k1` is taken
+    /// out, leaving a default value in its place, moved into place at `k2`,
+    /// and the value found there moved back to `k1`.
+    fn swap_values(&mut self, k1: &K, k2: &K) -> io::Result<bool>
+    where
+        K: Eq,
+        V: Default,
+        Self::Leaf: BorrowMut<V>,
+    {
+        if k1 == k2 {
+            return Ok(self.get_mut(k1)?.is_some());
+        }
+        let v1 = match self.get_mut(k1)? {
+            Some(mut v1) => mem::take(&mut *v1),
+            None => return Ok(false),
+        };
+        let (back, swapped) = match self.get_mut(k2)? {
+            Some(mut v2) => (mem::replace(&mut *v2, v1), true),
+            None => (v1, false),
+        };
+        *self.get_mut(k1)?.expect("present above") = back;
+        Ok(swapped)
+    }
+
+    /// Returns the entry of the map at key, for in-place manipulation
+    fn entry(&mut self, k: K) -> io::Result<Entry<'_, K, V, Self, H>>
+    where
+        Self::Leaf: BorrowMut<V>,
+    {
+        Entry::new(self, k)
+    }
+}
+
+/// A view into a single entry of a map, which is either occupied or vacant
+pub enum Entry<'a, K, V, C, H>
+where
+    C: Compound<H>,
+    H: ByteHash,
+{
+    /// The key is present in the map
+    Occupied(ValPathMut<'a, K, V, C, H>),
+    /// The key is absent from the map
+    Vacant(VacantEntry<'a, K, V, C, H>),
+}
+
+/// The entry of a key absent from a map
+pub struct VacantEntry<'a, K, V, C, H> {
+    map: &'a mut C,
+    key: K,
+    _marker: PhantomData<(V, H)>,
+}
+
+impl<'a, K, V, C, H> Entry<'a, K, V, C, H>
+where
+    C: MapMut<K, V, H>,
+    C::Leaf: BorrowMut<V>,
+    H: ByteHash,
+{
+    fn new(map: &'a mut C, key: K) -> io::Result<Self> {
+        match map.search_mut(&key)? {
+            Ok(path) => Ok(Entry::Occupied(path)),
+            Err(map) => Ok(Entry::Vacant(VacantEntry {
+                map,
+                key,
+                _marker: PhantomData,
+            })),
+        }
+    }
+
+    /// Inserts `v` if the entry is vacant, returning the value of the entry
+    pub fn or_insert(self, v: V) -> io::Result<ValPathMut<'a, K, V, C, H>>
+    where
+        K: Clone,
+    {
+        self.or_insert_with(|| v)
+    }
+
+    /// Inserts the value returned by `f` if the entry is vacant, returning
+    /// the value of the entry
+    pub fn or_insert_with<F>(
+        self,
+        f: F,
+    ) -> io::Result<ValPathMut<'a, K, V, C, H>>
+    where
+        K: Clone,
+        F: FnOnce() -> V,
+    {
+        match self {
+            Entry::Occupied(path) => Ok(path),
+            Entry::Vacant(vacant) => vacant.insert(f()),
+        }
+    }
+
+    /// Modifies the value in place if the entry is occupied
+    pub fn and_modify<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(&mut V),
+    {
+        if let Entry::Occupied(ref mut path) = self {
+            f(&mut *path)
+        }
+        self
+    }
+}
+
+impl<'a, K, V, C, H> VacantEntry<'a, K, V, C, H>
+where
+    C: MapMut<K, V, H>,
+    C::Leaf: BorrowMut<V>,
+    H: ByteHash,
+{
+    /// Returns the key of the entry
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Inserts `v` at the key of the entry, returning a reference to it
+    pub fn insert(self, v: V) -> io::Result<ValPathMut<'a, K, V, C, H>>
+    where
+        K: Clone,
+    {
+        self.map.insert_mut(self.key, v)
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::io;
+
+use bytehash::ByteHash;
+
+use crate::compound::Compound;
+use crate::content::Content;
+use crate::diff::{diff, value_bytes, Change};
+use crate::map::{MapMut, KV};
+use crate::store::{Snapshot, Store};
+
+/// A key changed differently on both sides of a merge
+pub struct Conflict<'a, K, V> {
+    /// The key changed
+    pub key: &'a K,
+    /// The value in the common base, if any
+    pub base: Option<&'a V>,
+    /// Our value, if not removed
+    pub ours: Option<&'a V>,
+    /// Their value, if not removed
+    pub theirs: Option<&'a V>,
+}
+
+// The value before and after a change, `None` if absent
+fn sides<K, V>(change: Change<K, V>) -> (K, Option<V>, Option<V>) {
+    match change {
+        Change::Added(key, val) => (key, None, Some(val)),
+        Change::Removed(key, val) => (key, Some(val), None),
+        Change::Modified(key, old, new) => (key, Some(old), Some(new)),
+    }
+}
+
+/// Merges the changes from `base` to `ours` and to `theirs`, returning the
+/// merged map
+///
+/// Changes made on one side only are taken as is, as are identical changes
+/// made on both sides. For keys changed differently on both sides, the
+/// `resolver` is called with the conflict, and returns the merged value, or
+/// `None` to remove the key. Only subtrees that differ from the base are
+/// traversed, see `diff`.
+pub fn merge<C, K, V, H, F>(
+    base: &Snapshot<C, H>,
+    ours: &Snapshot<C, H>,
+    theirs: &Snapshot<C, H>,
+    mut resolver: F,
+) -> io::Result<C>
+where
+    C: MapMut<K, V, H> + Compound<H, Leaf = KV<K, V>>,
+    K: Content<H> + Eq + Hash,
+    V: Content<H>,
+    H: ByteHash,
+    F: FnMut(Conflict<K, V>) -> Option<V>,
+{
+    if ours.hash() == base.hash() {
+        return theirs.restore();
+    }
+    let mut merged = ours.restore()?;
+    if theirs.hash() == base.hash() || theirs.hash() == ours.hash() {
+        return Ok(merged);
+    }
+
+    let changes = diff(base, ours)?;
+    let mut ours = HashMap::new();
+    for change in changes {
+        let (key, _, new) = sides(change?);
+        ours.insert(key, new);
+    }
+
+    // values are compared by their encoding
+    let scratch = Store::ephemeral();
+    for change in diff(base, theirs)? {
+        let (key, old, mut theirs) = sides(change?);
+        let value = match ours.remove(&key) {
+            None => theirs,
+            Some(mut ours) => {
+                let same = match (ours.as_mut(), theirs.as_mut()) {
+                    (Some(a), Some(b)) => {
+                        value_bytes(a, &scratch)? == value_bytes(b, &scratch)?
+                    }
+                    (None, None) => true,
+                    _ => false,
+                };
+                if same {
+                    continue;
+                }
+                resolver(Conflict {
+                    key: &key,
+                    base: old.as_ref(),
+                    ours: ours.as_ref(),
+                    theirs: theirs.as_ref(),
+                })
+            }
+        };
+        match value {
+            Some(val) => {
+                merged.insert(key, val)?;
+            }
+            None => {
+                merged.remove(&key)?;
+            }
+        }
+    }
+    Ok(merged)
+}
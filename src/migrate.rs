@@ -0,0 +1,351 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::marker::PhantomData;
+
+use bytehash::ByteHash;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::compound::Compound;
+use crate::control::Control;
+use crate::iter::LeafIterable;
+use crate::map::{MapMut, KV};
+use crate::store::{Snapshot, Store};
+
+// Number of leaves transformed together by `map_values_par`
+#[cfg(feature = "parallel")]
+const BATCH: usize = 1024;
+
+// Number of leaves migrated between writes of the new structure to the store
+const FLUSH: usize = 4096;
+
+// Old root digests, with the corresponding re-keyed snapshots
+type Mapping<C, H1, H2> = Vec<(<H1 as ByteHash>::Digest, Snapshot<C, H2>)>;
+
+// A registered migration of a root, from its digest to the migrated one
+type Step<H> = Box<
+    dyn FnMut(
+            &Store<H>,
+            &<H as ByteHash>::Digest,
+            &mut Control<'_>,
+        ) -> io::Result<<H as ByteHash>::Digest>
+        + Send,
+>;
+
+/// A transformation of leaves of type `Old` into leaves of type `New`
+pub trait Migration<Old, New> {
+    /// Transforms a leaf, returning `None` to drop it
+    fn migrate(&mut self, old: Old) -> io::Result<Option<New>>;
+
+    /// Chains `next` after this migration, migrating `New` leaves on to a
+    /// later version
+    fn then<M, Newer>(self, next: M) -> Then<Self, M, New>
+    where
+        Self: Sized,
+        M: Migration<New, Newer>,
+    {
+        Then(self, next, PhantomData)
+    }
+}
+
+impl<Old, New, F> Migration<Old, New> for F
+where
+    F: FnMut(Old) -> io::Result<Option<New>>,
+{
+    fn migrate(&mut self, old: Old) -> io::Result<Option<New>> {
+        self(old)
+    }
+}
+
+/// Two migrations applied one after the other, see `Migration::then`
+pub struct Then<A, B, Mid>(A, B, PhantomData<Mid>);
+
+impl<A, B, Old, Mid, New> Migration<Old, New> for Then<A, B, Mid>
+where
+    A: Migration<Old, Mid>,
+    B: Migration<Mid, New>,
+{
+    fn migrate(&mut self, old: Old) -> io::Result<Option<New>> {
+        match self.0.migrate(old)? {
+            Some(mid) => self.1.migrate(mid),
+            None => Ok(None),
+        }
+    }
+}
+
+/// The migrations of stored collections, registered by the schema name and
+/// encoding version they migrate from, see `Schema`
+///
+/// Each step rewrites a collection from one version to the next, and
+/// `migrate` chains the steps from the version a root was stored in up to
+/// the version expected.
+pub struct Migrations<H: ByteHash> {
+    steps: BTreeMap<(String, u32), Step<H>>,
+}
+
+impl<H: ByteHash> Default for Migrations<H> {
+    fn default() -> Self {
+        Migrations {
+            steps: BTreeMap::new(),
+        }
+    }
+}
+
+impl<H: ByteHash> Migrations<H> {
+    /// Creates an empty registry
+    pub fn new() -> Self {
+        Migrations::default()
+    }
+
+    /// Registers `migration` as the step rewriting collections named `name`
+    /// from version `from` to the next, stored as a `C1` and rewritten as a
+    /// `C2`
+    pub fn register<C1, C2, K, V, M>(
+        &mut self,
+        name: &str,
+        from: u32,
+        mut migration: M,
+    ) -> &mut Self
+    where
+        C1: Compound<H> + 'static,
+        C2: MapMut<K, V, H> + 'static,
+        M: Migration<C1::Leaf, KV<K, V>> + Send + 'static,
+    {
+        let step: Step<H> = Box::new(move |store, digest, control| {
+            let old = store.snapshot::<C1>(digest).restore()?;
+            let mut new: C2 =
+                rewrite(&old, &mut migration, Some(store), control)?;
+            Ok(*store.persist(&mut new)?.hash())
+        });
+        self.steps.insert((name.into(), from), step);
+        self
+    }
+
+    /// Migrates the root with digest `root`, a collection named `name`
+    /// stored in version `from`, up to version `to`, returning the digest of
+    /// the migrated root
+    ///
+    /// Fails with an `InvalidInput` error, before migrating anything, if a
+    /// step is missing.
+    pub fn migrate(
+        &mut self,
+        store: &Store<H>,
+        name: &str,
+        from: u32,
+        to: u32,
+        root: &H::Digest,
+        control: &mut Control<'_>,
+    ) -> io::Result<H::Digest> {
+        for version in from..to {
+            if !self.steps.contains_key(&(name.into(), version)) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "No migration of {} from version {}",
+                        name, version
+                    ),
+                ));
+            }
+        }
+        let mut digest = *root;
+        for version in from..to {
+            let step = self
+                .steps
+                .get_mut(&(name.into(), version))
+                .expect("checked above");
+            digest = step(store, &digest, control)?;
+        }
+        Ok(digest)
+    }
+}
+
+/// Rewrites the structure at `root` into a map of migrated leaves, in the
+/// same store
+///
+/// Leaves are streamed from the old structure one at a time, reporting one
+/// unit of progress each, and the new map is written to the store as it
+/// grows, so only the paths being read and written are kept in memory.
+/// Returns an `InvalidData` error if two leaves are migrated to the same key.
+pub fn migrate<C1, C2, K, V, M, H>(
+    root: &Snapshot<C1, H>,
+    mut migration: M,
+    control: &mut Control<'_>,
+) -> io::Result<Snapshot<C2, H>>
+where
+    C1: Compound<H>,
+    C2: MapMut<K, V, H>,
+    M: Migration<C1::Leaf, KV<K, V>>,
+    H: ByteHash,
+{
+    let store = root.store();
+    let old = root.restore()?;
+    let mut new = rewrite(&old, &mut migration, Some(store), control)?;
+    store.persist(&mut new)
+}
+
+// Streams the leaves of `old` through `migration` into a new map, reporting
+// one unit of progress per leaf, and writing the new map to `store`, if any,
+// as it grows
+fn rewrite<C1, C2, K, V, M, H>(
+    old: &C1,
+    migration: &mut M,
+    store: Option<&Store<H>>,
+    control: &mut Control<'_>,
+) -> io::Result<C2>
+where
+    C1: Compound<H>,
+    C2: MapMut<K, V, H>,
+    M: Migration<C1::Leaf, KV<K, V>>,
+    H: ByteHash,
+{
+    let mut new = C2::default();
+    for (i, leaf) in old.iter().enumerate() {
+        if let Some(KV { key, val }) = migration.migrate(leaf?.clone())? {
+            if new.insert(key, val)?.is_some() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Migration maps two leaves to the same key",
+                ));
+            }
+        }
+        control.advance(1)?;
+        // persisting leaves the written nodes to be read back on demand
+        if let Some(store) = store {
+            if (i + 1) % FLUSH == 0 {
+                store.persist(&mut new)?;
+            }
+        }
+    }
+    Ok(new)
+}
+
+/// Rebuilds `old`, a map using the hash `H1`, as a map using the hash `H2`
+///
+/// The logical contents are preserved, leaves are streamed from `old` into
+/// the new map one at a time, reporting one unit of progress per leaf.
+pub fn rehash<C1, C2, K, V, H1, H2>(
+    old: &C1,
+    control: &mut Control<'_>,
+) -> io::Result<C2>
+where
+    C1: Compound<H1, Leaf = KV<K, V>>,
+    C2: MapMut<K, V, H2> + Default,
+    K: Clone,
+    V: Clone,
+    H1: ByteHash,
+    H2: ByteHash,
+{
+    let mut new = C2::default();
+    for leaf in old.iter() {
+        let KV { key, val } = leaf?.clone();
+        new.insert(key, val)?;
+        control.advance(1)?;
+    }
+    Ok(new)
+}
+
+/// Re-keys the roots `old` into the store `to`, using the hash `H2`
+///
+/// Returns the mapping from each old root digest to its new snapshot.
+pub fn rehash_roots<C1, C2, K, V, H1, H2>(
+    old: &[Snapshot<C1, H1>],
+    to: &Store<H2>,
+    mut control: Control<'_>,
+) -> io::Result<Mapping<C2, H1, H2>>
+where
+    C1: Compound<H1, Leaf = KV<K, V>>,
+    C2: MapMut<K, V, H2> + Default,
+    K: Clone,
+    V: Clone,
+    H1: ByteHash,
+    H2: ByteHash,
+{
+    let mut mapping = Vec::with_capacity(old.len());
+    for snapshot in old {
+        let root: C1 = snapshot.restore()?;
+        let mut new: C2 = rehash(&root, &mut control)?;
+        mapping.push((*snapshot.hash(), to.persist(&mut new)?));
+    }
+    Ok(mapping)
+}
+
+/// Rebuilds `old` with every value replaced by `f(key, value)`
+///
+/// Leaves are streamed into the new map one at a time, so the old and new
+/// maps are never both fully materialized in memory.
+pub fn map_values<C1, C2, K, V, W, H, F>(
+    old: &C1,
+    mut f: F,
+    control: &mut Control<'_>,
+) -> io::Result<C2>
+where
+    C1: Compound<H, Leaf = KV<K, V>>,
+    C2: MapMut<K, W, H>,
+    H: ByteHash,
+    F: FnMut(&K, &V) -> io::Result<W>,
+{
+    let mut migration = |KV { key, val }: KV<K, V>| {
+        let val = f(&key, &val)?;
+        Ok(Some(KV::new(key, val)))
+    };
+    rewrite(old, &mut migration, None, control)
+}
+
+/// Like `map_values`, calling `f` on the leaves in parallel
+///
+/// Leaves are transformed in batches, the new map is built in the same order
+/// as by `map_values`, so the results are identical.
+#[cfg(feature = "parallel")]
+pub fn map_values_par<C1, C2, K, V, W, H, F>(
+    old: &C1,
+    f: F,
+    control: &mut Control<'_>,
+) -> io::Result<C2>
+where
+    C1: Compound<H, Leaf = KV<K, V>>,
+    C2: MapMut<K, W, H> + Default,
+    K: Clone + Sync,
+    V: Clone + Sync,
+    W: Send,
+    H: ByteHash,
+    F: Fn(&K, &V) -> io::Result<W> + Sync,
+{
+    let mut new = C2::default();
+    let mut leaves = old.iter();
+    let mut batch = Vec::with_capacity(BATCH);
+    loop {
+        for leaf in leaves.by_ref().take(BATCH) {
+            batch.push(leaf?.clone());
+        }
+        if batch.is_empty() {
+            return Ok(new);
+        }
+        let vals: Vec<W> = batch
+            .par_iter()
+            .map(|leaf| f(&leaf.key, &leaf.val))
+            .collect::<io::Result<_>>()?;
+        for (KV { key, .. }, val) in batch.drain(..).zip(vals) {
+            new.insert(key, val)?;
+            control.advance(1)?;
+        }
+    }
+}
+
+/// Rebuilds `old` with every key replaced by `f(key)`
+///
+/// Returns an `InvalidData` error if two keys are mapped to the same key.
+pub fn map_keys<C1, C2, K, L, V, H, F>(
+    old: &C1,
+    mut f: F,
+    control: &mut Control<'_>,
+) -> io::Result<C2>
+where
+    C1: Compound<H, Leaf = KV<K, V>>,
+    C2: MapMut<L, V, H>,
+    H: ByteHash,
+    F: FnMut(&K) -> io::Result<L>,
+{
+    let mut migration =
+        |KV { key, val }: KV<K, V>| Ok(Some(KV::new(f(&key)?, val)));
+    rewrite(old, &mut migration, None, control)
+}
@@ -0,0 +1,717 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::hash::Hash;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use atomicwrites::{AllowOverwrite, AtomicFile};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use parking_lot::ReentrantMutex;
+
+use crate::backend::{DirRoots, RootStore, RootUpdate};
+use crate::compound::Compound;
+#[cfg(feature = "compression")]
+use crate::compression::Dictionary;
+use crate::error::Error;
+use crate::filter::KeyFilter;
+use crate::map::KV;
+use crate::schema::{Mismatch, Registry, Schema};
+use crate::sink::Sink;
+use crate::source::Source;
+use crate::store::Pinned;
+use crate::{content::Content, ByteHash, Snapshot, Store};
+
+// False positive rate of the key filters maintained for map roots
+const FILTER_RATE: f64 = 0.01;
+
+/// A store shared by several isolated namespaces
+///
+/// Every namespace has its own registry of named roots, and a quota on the
+/// bytes it has written. Nodes shared between namespaces are only stored,
+/// and accounted for, once. Dropping a namespace unregisters all its roots,
+/// leaving their nodes to be reclaimed by garbage collection.
+pub struct Namespaces<H: ByteHash> {
+    path: PathBuf,
+    store: Store<H>,
+    // serializes writes, so that store growth is attributed correctly, and
+    // read views never observe a commit half-way
+    write: Arc<ReentrantMutex<()>>,
+}
+
+/// The roots of a namespace as of a single commit
+///
+/// All roots restored from the view come from the same state of the
+/// namespace, even when written to concurrently. The roots are pinned in the
+/// store for as long as the view is alive.
+pub struct ReadView<H: ByteHash> {
+    store: Store<H>,
+    roots: Vec<(String, H::Digest)>,
+    _pins: Vec<Pinned<H>>,
+}
+
+impl<H: ByteHash> ReadView<H> {
+    /// Returns the digest of the root `root` in the view, if registered
+    pub fn digest(&self, root: &str) -> Option<&H::Digest> {
+        self.roots
+            .binary_search_by(|(name, _)| name.as_str().cmp(root))
+            .ok()
+            .map(|i| &self.roots[i].1)
+    }
+
+    /// Restores the root `root` as of the view, if registered
+    pub fn restore<T: Content<H>>(&self, root: &str) -> io::Result<Option<T>> {
+        match self.digest(root) {
+            Some(digest) => self.store.get_hash(digest).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the names and digests of all roots in the view, in order
+    pub fn roots(&self) -> &[(String, H::Digest)] {
+        &self.roots
+    }
+}
+
+/// A namespace in a `Namespaces` store
+///
+/// The roots of a namespace are kept in a `DirRoots` registry, in its
+/// `roots` directory.
+pub struct Namespace<H: ByteHash> {
+    name: String,
+    dir: PathBuf,
+    store: Store<H>,
+    roots: DirRoots<H>,
+    write: Arc<ReentrantMutex<()>>,
+    quota: u64,
+    usage: u64,
+    registry: Option<Arc<Registry>>,
+    // the roots set, or removed, by the commit under way, if any
+    staged: Option<BTreeMap<String, Option<H::Digest>>>,
+}
+
+fn check_name(name: &str) -> io::Result<()> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Names may only contain ascii alphanumerics, '-' and '_'",
+        ))
+    }
+}
+
+fn quota_exceeded() -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, "Namespace quota exceeded")
+}
+
+fn read_u64(path: &Path) -> io::Result<u64> {
+    File::open(path)?.read_u64::<BigEndian>()
+}
+
+fn write_u64(path: &Path, n: u64) -> io::Result<()> {
+    let af = AtomicFile::new(path, AllowOverwrite);
+    af.write(|f| f.write_u64::<BigEndian>(n))?;
+    Ok(())
+}
+
+impl<H: ByteHash> Namespaces<H> {
+    /// Opens the namespaced store at `path`, creating it if necessary
+    pub fn new<P: Into<PathBuf>>(path: P) -> io::Result<Self> {
+        let path = path.into();
+        let store = Store::new(&path)?;
+        fs::create_dir_all(path.join("namespaces"))?;
+        Ok(Namespaces {
+            path,
+            store,
+            write: Default::default(),
+        })
+    }
+
+    /// Returns the underlying store
+    pub fn store(&self) -> &Store<H> {
+        &self.store
+    }
+
+    fn dir(&self, name: &str) -> io::Result<PathBuf> {
+        check_name(name)?;
+        Ok(self.path.join("namespaces").join(name))
+    }
+
+    /// Creates the namespace `name`, limited to `quota` bytes
+    pub fn create(&self, name: &str, quota: u64) -> io::Result<Namespace<H>> {
+        let dir = self.dir(name)?;
+        if dir.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "Namespace already exists",
+            ));
+        }
+        fs::create_dir_all(dir.join("roots"))?;
+        write_u64(&dir.join("usage"), 0)?;
+        write_u64(&dir.join("quota"), quota)?;
+        self.open(name)
+    }
+
+    /// Opens the existing namespace `name`
+    ///
+    /// Only one handle to a namespace should be written through at a time.
+    pub fn open(&self, name: &str) -> io::Result<Namespace<H>> {
+        let dir = self.dir(name)?;
+        if !dir.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "Namespace not found",
+            ));
+        }
+        let namespace = Namespace {
+            name: name.into(),
+            quota: read_u64(&dir.join("quota"))?,
+            usage: read_u64(&dir.join("usage"))?,
+            roots: DirRoots::open(dir.join("roots"))?,
+            dir,
+            store: self.store.clone(),
+            write: self.write.clone(),
+            registry: None,
+            staged: None,
+        };
+        #[cfg(feature = "compression")]
+        namespace.load_dictionaries()?;
+        Ok(namespace)
+    }
+
+    /// Opens the existing namespace `name`, checking its roots against the
+    /// schemas in `registry`
+    ///
+    /// Fails with an `Error::Schema` listing every mismatch found, carried in
+    /// the returned `io::Error`. Roots set through the namespace are checked
+    /// against the registry as well, and recorded with the registered
+    /// encoding version.
+    pub fn open_validated(
+        &self,
+        name: &str,
+        registry: &Registry,
+    ) -> io::Result<Namespace<H>> {
+        let mut namespace = self.open(name)?;
+        let mismatches = namespace.validate(registry)?;
+        if !mismatches.is_empty() {
+            return Err(Error::Schema(mismatches).into());
+        }
+        namespace.set_registry(registry);
+        Ok(namespace)
+    }
+
+    /// Returns the names of all namespaces, in order
+    pub fn list(&self) -> io::Result<Vec<String>> {
+        let mut names = vec![];
+        for entry in fs::read_dir(self.path.join("namespaces"))? {
+            if let Some(name) = entry?.file_name().to_str() {
+                if check_name(name).is_ok() {
+                    names.push(name.into())
+                }
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// Drops the namespace `name`, with all its roots
+    pub fn remove(&self, name: &str) -> io::Result<()> {
+        fs::remove_dir_all(self.dir(name)?)
+    }
+
+    /// Returns the roots registered in all namespaces
+    ///
+    /// These are the roots garbage collection has to retain.
+    pub fn roots(&self) -> io::Result<Vec<H::Digest>> {
+        let mut roots = vec![];
+        for name in self.list()? {
+            for (_, digest) in self.open(&name)?.roots()? {
+                roots.push(digest)
+            }
+        }
+        Ok(roots)
+    }
+}
+
+impl<H: ByteHash> Namespace<H> {
+    /// Returns the name of the namespace
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the number of bytes the namespace may write
+    pub fn quota(&self) -> u64 {
+        self.quota
+    }
+
+    /// Returns the number of bytes written by the namespace
+    pub fn usage(&self) -> u64 {
+        self.usage
+    }
+
+    /// Sets the number of bytes the namespace may write
+    pub fn set_quota(&mut self, quota: u64) -> io::Result<()> {
+        write_u64(&self.dir.join("quota"), quota)?;
+        self.quota = quota;
+        Ok(())
+    }
+
+    /// Persists `t`, and registers it as the root `root`
+    ///
+    /// The bytes newly written to the store are added to the usage of the
+    /// namespace. If this exceeds the quota, an error is returned and the
+    /// root is left unchanged, but the bytes written are still charged, as
+    /// they stay in the store until collected. Namespaces over their quota
+    /// write nothing.
+    pub fn set_root<T: Content<H>>(
+        &mut self,
+        root: &str,
+        t: &mut T,
+    ) -> io::Result<Snapshot<T, H>> {
+        check_name(root)?;
+        let schema = self.schema_for::<T>(root)?;
+        let write = self.write.clone();
+        let _write = write.lock();
+
+        if self.usage > self.quota {
+            return Err(quota_exceeded());
+        }
+        let before = self.store.size() as u64;
+        let persisted = self.store.persist(t).and_then(|snapshot| {
+            self.store.flush()?;
+            Ok(snapshot)
+        });
+        let written = (self.store.size() as u64).saturating_sub(before);
+        self.usage = self.usage.saturating_add(written);
+        write_u64(&self.dir.join("usage"), self.usage)?;
+        let snapshot = persisted?;
+        if self.usage > self.quota {
+            return Err(quota_exceeded());
+        }
+
+        self.stage(root, Some(*snapshot.hash()))?;
+        self.remove_filter(root)?;
+        self.write_schema(root, schema)?;
+        Ok(snapshot)
+    }
+
+    /// Runs `f` as a single commit, setting any number of roots
+    ///
+    /// The roots set by `f` are journaled and set all at once when it
+    /// returns, so that read views, and the namespace after a crash, see
+    /// either none or all of them. Roots removed by `f` are removed right
+    /// after. If `f` fails, no root is changed.
+    pub fn commit<F, R>(&mut self, f: F) -> io::Result<R>
+    where
+        F: FnOnce(&mut Self) -> io::Result<R>,
+    {
+        let write = self.write.clone();
+        let _write = write.lock();
+        // nested commits are part of the outer one
+        if self.staged.is_some() {
+            return f(self);
+        }
+        self.staged = Some(BTreeMap::new());
+        let result = f(self);
+        let staged = self.staged.take().expect("staged above");
+        match result {
+            Ok(r) => {
+                self.apply(staged)?;
+                Ok(r)
+            }
+            Err(e) => {
+                for name in staged.keys() {
+                    self.remove_filter(name)?;
+                }
+                Err(e)
+            }
+        }
+    }
+
+    // Sets `root` to `digest`, or removes it if `None`, as part of the
+    // commit under way, if any
+    fn stage(
+        &mut self,
+        root: &str,
+        digest: Option<H::Digest>,
+    ) -> io::Result<()> {
+        if let Some(ref mut staged) = self.staged {
+            staged.insert(root.into(), digest);
+            return Ok(());
+        }
+        match digest {
+            Some(digest) => self.roots.set(root, &digest),
+            None => self.roots.remove(root),
+        }
+    }
+
+    // Sets the roots set by a commit at once, then removes those it removed
+    fn apply(
+        &self,
+        staged: BTreeMap<String, Option<H::Digest>>,
+    ) -> io::Result<()> {
+        let mut updates = vec![];
+        for (name, digest) in &staged {
+            if let Some(digest) = digest {
+                updates.push(RootUpdate {
+                    name: name.clone(),
+                    expected: self.roots.get(name)?,
+                    digest: *digest,
+                })
+            }
+        }
+        if self.roots.commit(&updates)?.is_err() {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "Roots were changed by another writer",
+            ));
+        }
+        for (name, digest) in staged {
+            if digest.is_none() {
+                self.roots.remove(&name)?
+            }
+        }
+        Ok(())
+    }
+
+    /// Captures the current roots of the namespace, as of the last commit
+    pub fn read_view(&self) -> io::Result<ReadView<H>> {
+        let _write = self.write.lock();
+        let roots: Vec<_> = self.registered()?.into_iter().collect();
+        let pins = roots
+            .iter()
+            .map(|(_, digest)| self.store.pin(digest))
+            .collect();
+        Ok(ReadView {
+            store: self.store.clone(),
+            roots,
+            _pins: pins,
+        })
+    }
+
+    /// Checks the roots set from now on against the schemas in `registry`,
+    /// and records them with the roots
+    ///
+    /// The roots already set are not validated, which lets applications
+    /// record the schemas of roots set before they had a registry.
+    pub fn set_registry(&mut self, registry: &Registry) {
+        self.registry = Some(Arc::new(registry.clone()));
+    }
+
+    /// Returns the schema recorded for the root `root`, if any
+    pub fn schema(&self, root: &str) -> io::Result<Option<Schema>> {
+        check_name(root)?;
+        let path = self.dir.join("schemas").join(root);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let bytes = fs::read(path)?;
+        let mut source =
+            Source::new(Box::new(io::Cursor::new(bytes)), &self.store);
+        Schema::restore(&mut source).map(Some)
+    }
+
+    /// Checks the registered roots against the schemas in `registry`,
+    /// returning all mismatches
+    ///
+    /// Roots in the registry that are not set are not mismatches.
+    pub fn validate(&self, registry: &Registry) -> io::Result<Vec<Mismatch>> {
+        let mut mismatches = vec![];
+        for (root, expected) in registry.iter() {
+            if self.digest(root)?.is_none() {
+                continue;
+            }
+            match self.schema(root)? {
+                Some(found) => {
+                    mismatches.extend(Mismatch::between(root, expected, &found))
+                }
+                None => {
+                    mismatches.push(Mismatch::Unrecorded { root: root.into() })
+                }
+            }
+        }
+        Ok(mismatches)
+    }
+
+    // The schema to record for `root` holding a `T`, if registered, failing
+    // if it does not match the registry
+    fn schema_for<T: Content<H>>(
+        &self,
+        root: &str,
+    ) -> io::Result<Option<Schema>> {
+        let registry = match self.registry {
+            Some(ref registry) => registry,
+            None => return Ok(None),
+        };
+        let mismatches = registry.check::<T, H>(root);
+        if !mismatches.is_empty() {
+            return Err(Error::Schema(mismatches).into());
+        }
+        Ok(registry.get(root).cloned())
+    }
+
+    // Records the schema of `root`, or removes the one recorded before when
+    // the root is set without a registered schema
+    fn write_schema(
+        &self,
+        root: &str,
+        schema: Option<Schema>,
+    ) -> io::Result<()> {
+        let path = self.dir.join("schemas").join(root);
+        let mut schema = match schema {
+            Some(schema) => schema,
+            None if path.exists() => return fs::remove_file(path),
+            None => return Ok(()),
+        };
+        let scratch = Store::<H>::ephemeral();
+        let mut sink = Sink::new(&scratch);
+        schema.persist(&mut sink)?;
+        fs::create_dir_all(self.dir.join("schemas"))?;
+        let af = AtomicFile::new(path, AllowOverwrite);
+        af.write(|f| f.write_all(sink.bytes()))?;
+        Ok(())
+    }
+
+    /// Persists the map `map`, and registers it as the root `root`,
+    /// maintaining a filter over its keys
+    ///
+    /// The filter is updated from the changes to the previous version of the
+    /// root, and rebuilt when it is missing or holds too many keys.
+    pub fn set_map_root<C, K, V>(
+        &mut self,
+        root: &str,
+        map: &mut C,
+    ) -> io::Result<Snapshot<C, H>>
+    where
+        C: Compound<H, Leaf = KV<K, V>>,
+        K: Hash,
+    {
+        let previous = match (self.digest(root)?, self.filter(root)?) {
+            (Some(digest), Some(filter)) => Some((digest, filter)),
+            _ => None,
+        };
+        let snapshot = self.set_root(root, map)?;
+
+        let filter = match previous {
+            Some((digest, mut filter)) => {
+                let old = Snapshot::new(digest, &self.store);
+                filter.update(&old, &snapshot)?;
+                if filter.is_saturated() {
+                    KeyFilter::from_map(map, FILTER_RATE)?
+                } else {
+                    filter
+                }
+            }
+            None => KeyFilter::from_map(map, FILTER_RATE)?,
+        };
+        self.write_filter(root, filter)?;
+        Ok(snapshot)
+    }
+
+    /// Returns the key filter of the root `root`, if registered as a map
+    pub fn filter(&self, root: &str) -> io::Result<Option<KeyFilter<H>>> {
+        check_name(root)?;
+        let path = self.dir.join("filters").join(root);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let bytes = fs::read(path)?;
+        let mut source =
+            Source::new(Box::new(io::Cursor::new(bytes)), &self.store);
+        KeyFilter::restore(&mut source).map(Some)
+    }
+
+    /// Returns false if `key` is definitely not in the map at root `root`
+    ///
+    /// Only the key filter is read, not the map itself. Roots registered
+    /// without a filter may contain any key.
+    pub fn may_contain<K: Hash + ?Sized>(
+        &self,
+        root: &str,
+        key: &K,
+    ) -> io::Result<bool> {
+        match self.filter(root)? {
+            Some(filter) => Ok(filter.may_contain(key)),
+            None => Ok(self.digest(root)?.is_some()),
+        }
+    }
+
+    fn write_filter(
+        &self,
+        root: &str,
+        mut filter: KeyFilter<H>,
+    ) -> io::Result<()> {
+        let scratch = Store::ephemeral();
+        let mut sink = Sink::new(&scratch);
+        filter.persist(&mut sink)?;
+        fs::create_dir_all(self.dir.join("filters"))?;
+        let af = AtomicFile::new(
+            self.dir.join("filters").join(root),
+            AllowOverwrite,
+        );
+        af.write(|f| f.write_all(sink.bytes()))?;
+        Ok(())
+    }
+
+    fn remove_filter(&self, root: &str) -> io::Result<()> {
+        let path = self.dir.join("filters").join(root);
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// Records `dictionary` as the compression dictionary of the root `root`
+    ///
+    /// The dictionaries of a namespace are added to the store when it is
+    /// opened, so that the values compressed with them can be restored.
+    #[cfg(feature = "compression")]
+    pub fn set_dictionary(
+        &self,
+        root: &str,
+        dictionary: &Dictionary,
+    ) -> io::Result<()> {
+        check_name(root)?;
+        fs::create_dir_all(self.dir.join("dictionaries"))?;
+        let af = AtomicFile::new(
+            self.dir.join("dictionaries").join(root),
+            AllowOverwrite,
+        );
+        af.write(|f| f.write_all(dictionary.as_bytes()))?;
+        self.store.add_dictionary(dictionary.clone());
+        Ok(())
+    }
+
+    /// Returns the compression dictionary of the root `root`, if recorded
+    #[cfg(feature = "compression")]
+    pub fn dictionary(&self, root: &str) -> io::Result<Option<Dictionary>> {
+        check_name(root)?;
+        let path = self.dir.join("dictionaries").join(root);
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(Dictionary::new(fs::read(path)?)))
+    }
+
+    #[cfg(feature = "compression")]
+    fn load_dictionaries(&self) -> io::Result<()> {
+        let dir = self.dir.join("dictionaries");
+        if !dir.exists() {
+            return Ok(());
+        }
+        for entry in fs::read_dir(dir)? {
+            let bytes = fs::read(entry?.path())?;
+            self.store.add_dictionary(Dictionary::new(bytes));
+        }
+        Ok(())
+    }
+
+    /// Restores the root `root`, if registered
+    pub fn restore<T: Content<H>>(&self, root: &str) -> io::Result<Option<T>> {
+        match self.digest(root)? {
+            Some(digest) => self.store.get_hash(&digest).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Unregisters the root `root`
+    ///
+    /// The bytes written for it stay accounted to the namespace, as they are
+    /// not reclaimed until garbage collection.
+    pub fn remove_root(&mut self, root: &str) -> io::Result<()> {
+        check_name(root)?;
+        self.stage(root, None)?;
+        let path = self.dir.join("schemas").join(root);
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        let path = self.dir.join("dictionaries").join(root);
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        self.remove_filter(root)
+    }
+
+    /// Returns the names and digests of all registered roots, in order
+    pub fn roots(&self) -> io::Result<Vec<(String, H::Digest)>> {
+        let mut roots = self.registered()?;
+        if let Some(ref staged) = self.staged {
+            for (name, digest) in staged {
+                match digest {
+                    Some(digest) => roots.insert(name.clone(), *digest),
+                    None => roots.remove(name),
+                };
+            }
+        }
+        Ok(roots.into_iter().collect())
+    }
+
+    // The roots as of the last commit
+    fn registered(&self) -> io::Result<BTreeMap<String, H::Digest>> {
+        let mut roots = BTreeMap::new();
+        for name in self.roots.names()? {
+            if let Some(digest) = self.roots.get(&name)? {
+                roots.insert(name, digest);
+            }
+        }
+        Ok(roots)
+    }
+
+    fn digest(&self, root: &str) -> io::Result<Option<H::Digest>> {
+        check_name(root)?;
+        if let Some(ref staged) = self.staged {
+            if let Some(digest) = staged.get(root) {
+                return Ok(*digest);
+            }
+        }
+        self.roots.get(root)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tests::tempfile::tempdir;
+    use crate::Blake2b;
+
+    #[test]
+    fn isolated_namespaces() {
+        let dir = tempdir().unwrap();
+        let namespaces = Namespaces::<Blake2b>::new(dir.path()).unwrap();
+
+        let mut a = namespaces.create("a", 1 << 20).unwrap();
+        let mut b = namespaces.create("b", 16).unwrap();
+        assert!(namespaces.create("a", 0).is_err());
+        assert!(namespaces.create("../a", 0).is_err());
+
+        a.set_root("state", &mut vec![1u64; 16]).unwrap();
+        assert!(a.usage() > 0);
+        assert!(b.restore::<Vec<u64>>("state").unwrap().is_none());
+
+        // over quota, the root is not registered, but what was written is
+        // charged, and nothing more is written
+        assert!(b.set_root("state", &mut vec![2u64; 16]).is_err());
+        assert!(b.roots().unwrap().is_empty());
+        let usage = b.usage();
+        assert!(usage > 16);
+        assert!(b.set_root("other", &mut vec![3u64; 16]).is_err());
+        assert_eq!(b.usage(), usage);
+        b.set_quota(1 << 20).unwrap();
+        b.set_root("state", &mut vec![2u64; 16]).unwrap();
+
+        let a = namespaces.open("a").unwrap();
+        assert_eq!(a.restore("state").unwrap(), Some(vec![1u64; 16]));
+        assert_eq!(namespaces.list().unwrap(), vec!["a", "b"]);
+        assert_eq!(namespaces.roots().unwrap().len(), 2);
+
+        namespaces.remove("b").unwrap();
+        assert_eq!(namespaces.list().unwrap(), vec!["a"]);
+        assert!(namespaces.open("b").is_err());
+        assert_eq!(namespaces.roots().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,281 @@
+use std::borrow::Borrow;
+use std::io;
+use std::sync::Arc;
+
+use bytehash::ByteHash;
+use futures::stream::{self, Stream, StreamExt};
+use parking_lot::Mutex;
+
+use crate::backend::{AsyncBackend, Fetched, FetchedBackend};
+use crate::branch::Branch;
+use crate::compound::Compound;
+use crate::content::Content;
+use crate::handle::HandleType;
+use crate::map::ValPath;
+use crate::search::{First, Method, SearchResult};
+use crate::store::{Snapshot, Store};
+
+// Number of nodes kept in memory by default
+const FETCHED: usize = 1 << 16;
+
+/// A store reading from an `AsyncBackend` without blocking
+///
+/// Nodes are kept in memory once fetched, up to a number of nodes past
+/// which the oldest ones are evicted, and fetched again when needed.
+/// Traversals run synchronously
+/// over the nodes in memory, and when they reach a node not yet fetched
+/// the node is awaited and the traversal retried, so a remote backend never
+/// blocks the thread of an async runtime. Content is persisted synchronously
+/// through `store`, and written to the async backend on `flush`.
+pub struct AsyncStore<H: ByteHash> {
+    store: Store<H>,
+    fetched: Arc<Mutex<Fetched<H::Digest>>>,
+    backend: Arc<dyn AsyncBackend<H>>,
+}
+
+impl<H: ByteHash> Clone for AsyncStore<H> {
+    fn clone(&self) -> Self {
+        AsyncStore {
+            store: self.store.clone(),
+            fetched: self.fetched.clone(),
+            backend: self.backend.clone(),
+        }
+    }
+}
+
+impl<H: ByteHash> AsyncStore<H> {
+    /// Creates a store reading from and writing to `backend`
+    pub fn new<B: AsyncBackend<H> + 'static>(backend: B) -> Self {
+        Self::with_capacity(backend, FETCHED)
+    }
+
+    /// Creates a store keeping at most `capacity` nodes fetched from
+    /// `backend` in memory
+    ///
+    /// Nodes written and not yet flushed are kept in addition to them.
+    pub fn with_capacity<B: AsyncBackend<H> + 'static>(
+        backend: B,
+        capacity: usize,
+    ) -> Self {
+        let fetched = Arc::new(Mutex::new(Fetched::new(capacity)));
+        AsyncStore {
+            store: Store::from_backend(FetchedBackend(fetched.clone())),
+            fetched,
+            backend: Arc::new(backend),
+        }
+    }
+
+    /// Returns the synchronous store over the nodes in memory
+    ///
+    /// Used to persist content, reading through it fails with a
+    /// `WouldBlock` error on the nodes not yet fetched.
+    pub fn store(&self) -> &Store<H> {
+        &self.store
+    }
+
+    /// Returns the number of nodes held in memory
+    pub fn fetched(&self) -> usize {
+        self.fetched.lock().len()
+    }
+
+    // Fetches the nodes found missing by the last attempt, returning false if
+    // there were none
+    async fn fetch_missing(&self) -> io::Result<bool> {
+        let missing = self.fetched.lock().take_missing();
+        if missing.is_empty() {
+            return Ok(false);
+        }
+        for digest in missing {
+            let bytes = self.backend.get(&digest).await?;
+            self.fetched.lock().insert(digest, bytes);
+        }
+        Ok(true)
+    }
+
+    // Runs `attempt` until it no longer fails on nodes not yet fetched,
+    // awaiting them in between
+    //
+    // The store reports nodes not fetched as missing, rather than with the
+    // `WouldBlock` error of the backend, so any failure with nodes recorded
+    // as missing is retried.
+    pub(crate) async fn retry<T, F>(&self, mut attempt: F) -> io::Result<T>
+    where
+        F: FnMut() -> io::Result<T>,
+    {
+        loop {
+            match attempt() {
+                Err(e) => {
+                    if !self.fetch_missing().await? {
+                        return Err(e);
+                    }
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Restores the root with digest `hash`, awaiting its node if needed
+    pub async fn restore<T: Content<H>>(
+        &self,
+        hash: &H::Digest,
+    ) -> io::Result<T> {
+        let snapshot: Snapshot<T, H> = self.store.snapshot(hash);
+        self.retry(|| snapshot.restore()).await
+    }
+
+    /// Writes the nodes persisted since the last flush to the async
+    /// backend, and flushes it
+    pub async fn flush(&self) -> io::Result<()> {
+        let unflushed = self.fetched.lock().take_unflushed();
+        for (digest, bytes) in unflushed {
+            self.backend.put(digest, bytes.to_vec()).await?;
+        }
+        self.backend.flush().await
+    }
+
+    /// Returns a stream over clones of the leaves of `node`, in order
+    ///
+    /// The stream resumes from the position of the last leaf, so a node
+    /// awaited midway does not restart the iteration.
+    pub fn leaves<'a, C>(
+        &'a self,
+        node: &'a C,
+    ) -> impl Stream<Item = io::Result<C::Leaf>> + 'a
+    where
+        C: Compound<H>,
+    {
+        stream::unfold(Some(None), move |state| async move {
+            let position: Option<Vec<usize>> = state?;
+            let next = self
+                .retry(|| {
+                    let branch = match position {
+                        None => Branch::new(node, &mut First)?,
+                        Some(ref position) => {
+                            let mut at = Position::new(position);
+                            match Branch::new(node, &mut at)? {
+                                Some(branch) => branch.search(&mut First)?,
+                                None => None,
+                            }
+                        }
+                    };
+                    Ok(branch
+                        .map(|branch| (branch.position(), (*branch).clone())))
+                })
+                .await;
+            match next {
+                Ok(Some((position, leaf))) => {
+                    Some((Ok(leaf), Some(Some(position))))
+                }
+                Ok(None) => None,
+                Err(e) => Some((Err(e), None)),
+            }
+        })
+    }
+
+    /// Returns a stream over clones of the values of `node`, in order
+    pub fn stream_values<'a, C, V>(
+        &'a self,
+        node: &'a C,
+    ) -> impl Stream<Item = io::Result<V>> + 'a
+    where
+        C: Compound<H>,
+        C::Leaf: Borrow<V>,
+        V: Clone + 'a,
+    {
+        self.leaves(node)
+            .map(|leaf| leaf.map(|leaf| leaf.borrow().clone()))
+    }
+
+    /// Returns a stream over clones of the key-value pairs of `node`, in
+    /// order
+    pub fn stream_kv<'a, C, K, V>(
+        &'a self,
+        node: &'a C,
+    ) -> impl Stream<Item = io::Result<(K, V)>> + 'a
+    where
+        C: Compound<H>,
+        C::Leaf: AsRef<K> + Borrow<V>,
+        K: Clone + 'a,
+        V: Clone + 'a,
+    {
+        self.leaves(node).map(|leaf| {
+            leaf.map(|leaf| (leaf.as_ref().clone(), leaf.borrow().clone()))
+        })
+    }
+}
+
+// Searches for the leaf at a position, as returned by `Branch::position`
+struct Position<'p> {
+    position: &'p [usize],
+    depth: usize,
+}
+
+impl<'p> Position<'p> {
+    fn new(position: &'p [usize]) -> Self {
+        Position { position, depth: 0 }
+    }
+}
+
+impl<'p, C, H> Method<C, H> for Position<'p>
+where
+    C: Compound<H>,
+    H: ByteHash,
+{
+    fn select(&mut self, compound: &C, offset: usize) -> SearchResult {
+        let idx = match self.position.get(self.depth) {
+            Some(idx) if *idx >= offset => *idx,
+            _ => return SearchResult::None,
+        };
+        self.depth += 1;
+        match compound.children().get(idx).map(|h| h.handle_type()) {
+            Some(HandleType::Leaf) => SearchResult::Leaf(idx - offset),
+            Some(HandleType::Node) => SearchResult::Path(idx - offset),
+            _ => SearchResult::None,
+        }
+    }
+}
+
+impl<'a, C, H> Branch<'a, C, H>
+where
+    C: Compound<H>,
+    H: ByteHash,
+{
+    /// Constructs a branch like `new`, awaiting the nodes not yet fetched
+    /// by `store`
+    ///
+    /// The search is retried from the root once a missing node is fetched,
+    /// with a fresh method from `method`.
+    pub async fn new_async<M, F>(
+        node: &'a C,
+        mut method: F,
+        store: &AsyncStore<H>,
+    ) -> io::Result<Option<Self>>
+    where
+        M: Method<C, H>,
+        F: FnMut() -> M,
+    {
+        store.retry(|| Branch::new(node, &mut method())).await
+    }
+}
+
+impl<'a, K, V, C, H> ValPath<'a, K, V, C, H>
+where
+    C: Compound<H>,
+    H: ByteHash,
+{
+    /// Creates a new `ValPath` like `new`, awaiting the nodes not yet
+    /// fetched by `store`
+    ///
+    /// Maps implement their async `get` on top of this.
+    pub async fn new_async<M, F>(
+        node: &'a C,
+        mut method: F,
+        store: &AsyncStore<H>,
+    ) -> io::Result<Option<Self>>
+    where
+        M: Method<C, H>,
+        F: FnMut() -> M,
+    {
+        store.retry(|| ValPath::new(node, &mut method())).await
+    }
+}
@@ -0,0 +1,378 @@
+use std::io::{self, Read, Write};
+use std::mem;
+
+use bytehash::ByteHash;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::content::Content;
+use crate::gc::Reach;
+use crate::sink::Sink;
+use crate::source::Source;
+use crate::store::Snapshot;
+
+// Number of operations per sealed segment of the log
+const SEGMENT: usize = 256;
+const DEFAULT_INTERVAL: u64 = 1024;
+
+/// A typed operation on a target structure
+pub trait Operation<H>
+where
+    Self: Content<H>,
+    H: ByteHash,
+{
+    /// The structure the operation applies to
+    type Target: Content<H> + Default;
+
+    /// Applies the operation to `target`
+    fn apply(&self, target: &mut Self::Target) -> io::Result<()>;
+}
+
+/// An append-only log of operations, with checkpoints of the target state
+///
+/// Operations are stored in fixed-size segments, each persisted as its own
+/// node, so only the segments covering a replayed range are read back.
+#[derive(Clone)]
+pub struct OpLog<O, H>
+where
+    O: Operation<H>,
+    H: ByteHash,
+{
+    segments: Vec<Snapshot<Vec<O>, H>>,
+    tail: Vec<O>,
+    checkpoints: Vec<(u64, Snapshot<O::Target, H>)>,
+}
+
+impl<O, H> Default for OpLog<O, H>
+where
+    O: Operation<H>,
+    H: ByteHash,
+{
+    fn default() -> Self {
+        OpLog {
+            segments: vec![],
+            tail: vec![],
+            checkpoints: vec![],
+        }
+    }
+}
+
+impl<O, H> OpLog<O, H>
+where
+    O: Operation<H>,
+    H: ByteHash,
+{
+    /// Creates a new, empty, log
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Returns the number of operations in the log
+    pub fn len(&self) -> u64 {
+        (self.segments.len() * SEGMENT + self.tail.len()) as u64
+    }
+
+    /// Returns true if the log is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Appends an operation to the log
+    pub fn push(&mut self, op: O) {
+        self.tail.push(op)
+    }
+
+    /// Returns the log positions of all checkpoints
+    pub fn checkpoints(&self) -> impl Iterator<Item = u64> + '_ {
+        self.checkpoints.iter().map(|(at, _)| *at)
+    }
+
+    /// Applies the operations in `from..to` to `target`
+    pub fn replay(
+        &self,
+        target: &mut O::Target,
+        from: u64,
+        to: u64,
+    ) -> io::Result<()> {
+        if from > to || to > self.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Invalid operation range",
+            ));
+        }
+        let sealed = (self.segments.len() * SEGMENT) as u64;
+        let mut loaded: Option<(usize, Vec<O>)> = None;
+
+        for i in from..to {
+            if i < sealed {
+                let segment = i as usize / SEGMENT;
+                match loaded {
+                    Some((loaded_segment, _)) if loaded_segment == segment => {}
+                    _ => {
+                        let ops = self.segments[segment].restore()?;
+                        loaded = Some((segment, ops));
+                    }
+                }
+                if let Some((_, ref ops)) = loaded {
+                    ops[i as usize % SEGMENT].apply(target)?;
+                }
+            } else {
+                self.tail[(i - sealed) as usize].apply(target)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Rebuilds the target state as of position `at` in the log, starting
+    /// from the latest checkpoint at or before it
+    pub fn rebuild(&self, at: u64) -> io::Result<O::Target> {
+        let (from, mut target) = match self
+            .checkpoints
+            .iter()
+            .rev()
+            .find(|(checkpoint, _)| *checkpoint <= at)
+        {
+            Some((checkpoint, snapshot)) => (*checkpoint, snapshot.restore()?),
+            None => (0, O::Target::default()),
+        };
+        self.replay(&mut target, from, at)?;
+        Ok(target)
+    }
+}
+
+impl<O, H> Content<H> for OpLog<O, H>
+where
+    O: Operation<H>,
+    H: ByteHash,
+{
+    fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
+        while self.tail.len() >= SEGMENT {
+            let rest = self.tail.split_off(SEGMENT);
+            let mut segment = mem::replace(&mut self.tail, rest);
+            self.segments.push(sink.store().persist(&mut segment)?);
+        }
+
+        sink.write_u64::<BigEndian>(self.segments.len() as u64)?;
+        for segment in &self.segments {
+            sink.write_all(segment.as_bytes())?;
+        }
+        self.tail.persist(sink)?;
+        sink.write_u64::<BigEndian>(self.checkpoints.len() as u64)?;
+        for (at, snapshot) in &self.checkpoints {
+            sink.write_u64::<BigEndian>(*at)?;
+            sink.write_all(snapshot.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn restore(source: &mut Source<H>) -> io::Result<Self> {
+        let mut log = OpLog::new();
+        for _ in 0..source.read_u64::<BigEndian>()? {
+            let mut digest = H::Digest::default();
+            source.read_exact(digest.as_mut())?;
+            log.segments.push(Snapshot::new(digest, source.store()));
+        }
+        log.tail = Vec::restore(source)?;
+        for _ in 0..source.read_u64::<BigEndian>()? {
+            let at = source.read_u64::<BigEndian>()?;
+            let mut digest = H::Digest::default();
+            source.read_exact(digest.as_mut())?;
+            log.checkpoints
+                .push((at, Snapshot::new(digest, source.store())));
+        }
+        Ok(log)
+    }
+
+    fn reach<R: Reach<H>>(&self, reach: &mut R) -> io::Result<()> {
+        for segment in &self.segments {
+            reach.node::<Vec<O>>(segment.hash())?;
+        }
+        self.tail.reach(reach)?;
+        for (_, snapshot) in &self.checkpoints {
+            reach.node::<O::Target>(snapshot.hash())?;
+        }
+        Ok(())
+    }
+}
+
+/// A structure kept together with the log of operations that built it
+///
+/// Every `interval` operations, a checkpoint of the state is recorded in the
+/// log when persisting, bounding the work needed to rebuild past states.
+#[derive(Clone)]
+pub struct EventSourced<O, H>
+where
+    O: Operation<H>,
+    H: ByteHash,
+{
+    log: OpLog<O, H>,
+    state: O::Target,
+    interval: u64,
+}
+
+impl<O, H> Default for EventSourced<O, H>
+where
+    O: Operation<H>,
+    H: ByteHash,
+{
+    fn default() -> Self {
+        EventSourced::with_interval(DEFAULT_INTERVAL)
+    }
+}
+
+impl<O, H> EventSourced<O, H>
+where
+    O: Operation<H>,
+    H: ByteHash,
+{
+    /// Creates a new, empty, structure checkpointing every `interval`
+    /// operations
+    pub fn with_interval(interval: u64) -> Self {
+        EventSourced {
+            log: OpLog::new(),
+            state: O::Target::default(),
+            interval,
+        }
+    }
+
+    /// Applies `op` to the state, and records it in the log
+    pub fn apply(&mut self, op: O) -> io::Result<()> {
+        op.apply(&mut self.state)?;
+        self.log.push(op);
+        Ok(())
+    }
+
+    /// Returns a reference to the current state
+    pub fn state(&self) -> &O::Target {
+        &self.state
+    }
+
+    /// Returns a reference to the operation log
+    pub fn log(&self) -> &OpLog<O, H> {
+        &self.log
+    }
+}
+
+impl<O, H> Content<H> for EventSourced<O, H>
+where
+    O: Operation<H>,
+    H: ByteHash,
+{
+    fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
+        let len = self.log.len();
+        let last = self.log.checkpoints().last().unwrap_or(0);
+        if self.interval > 0 && len >= last + self.interval {
+            let snapshot = sink.store().persist(&mut self.state)?;
+            self.log.checkpoints.push((len, snapshot));
+        }
+
+        self.interval.persist(sink)?;
+        self.log.persist(sink)?;
+        self.state.persist(sink)
+    }
+
+    fn restore(source: &mut Source<H>) -> io::Result<Self> {
+        Ok(EventSourced {
+            interval: u64::restore(source)?,
+            log: OpLog::restore(source)?,
+            state: O::Target::restore(source)?,
+        })
+    }
+
+    fn reach<R: Reach<H>>(&self, reach: &mut R) -> io::Result<()> {
+        self.log.reach(reach)?;
+        self.state.reach(reach)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Blake2b, Store};
+
+    /// Adds to, or multiplies, a number
+    #[derive(Clone)]
+    enum Arith {
+        Add(u64),
+        Mul(u64),
+    }
+
+    impl Content<Blake2b> for Arith {
+        fn persist(&mut self, sink: &mut Sink<Blake2b>) -> io::Result<()> {
+            match *self {
+                Arith::Add(ref mut n) => {
+                    sink.write_all(&[0])?;
+                    n.persist(sink)
+                }
+                Arith::Mul(ref mut n) => {
+                    sink.write_all(&[1])?;
+                    n.persist(sink)
+                }
+            }
+        }
+
+        fn restore(source: &mut Source<Blake2b>) -> io::Result<Self> {
+            match source.read_u8()? {
+                0 => Ok(Arith::Add(u64::restore(source)?)),
+                1 => Ok(Arith::Mul(u64::restore(source)?)),
+                _ => Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Invalid Arith encoding",
+                )),
+            }
+        }
+    }
+
+    impl Operation<Blake2b> for Arith {
+        type Target = u64;
+
+        fn apply(&self, target: &mut u64) -> io::Result<()> {
+            match *self {
+                Arith::Add(n) => *target = target.wrapping_add(n),
+                Arith::Mul(n) => *target = target.wrapping_mul(n),
+            }
+            Ok(())
+        }
+    }
+
+    fn op(i: u64) -> Arith {
+        if i % 3 == 0 {
+            Arith::Mul(i)
+        } else {
+            Arith::Add(i)
+        }
+    }
+
+    #[test]
+    fn replay_and_rebuild() {
+        let store = Store::<Blake2b>::ephemeral();
+        let mut sourced = EventSourced::<Arith, Blake2b>::with_interval(100);
+
+        let mut states = vec![0u64];
+        for i in 0..1000 {
+            sourced.apply(op(i)).unwrap();
+            states.push(*sourced.state());
+            if i % 37 == 0 {
+                let snapshot = store.persist(&mut sourced).unwrap();
+                sourced = store.restore(&snapshot).unwrap();
+            }
+        }
+        let snapshot = store.persist(&mut sourced).unwrap();
+        let restored = store.restore(&snapshot).unwrap();
+
+        assert_eq!(restored.log().len(), 1000);
+        assert_eq!(*restored.state(), states[1000]);
+        assert!(restored.log().checkpoints().count() >= 9);
+
+        for at in &[0, 1, 255, 256, 257, 500, 999, 1000] {
+            let rebuilt = restored.log().rebuild(*at).unwrap();
+            assert_eq!(rebuilt, states[*at as usize]);
+        }
+
+        // catch up from an older state
+        let mut target = states[300];
+        restored.log().replay(&mut target, 300, 1000).unwrap();
+        assert_eq!(target, states[1000]);
+
+        assert!(restored.log().replay(&mut target, 10, 1001).is_err());
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+
+use crate::eviction::{EvictionPolicy, Lru};
+
+// The cached nodes of a partition
+struct Nodes<D> {
+    nodes: HashMap<D, Arc<[u8]>>,
+    policy: Box<dyn EvictionPolicy<D>>,
+    size: usize,
+}
+
+/// A node cache with a budget of its own, shared by the store handles of a
+/// named partition
+pub(crate) struct Partition<D> {
+    name: String,
+    budget: AtomicUsize,
+    nodes: Mutex<Nodes<D>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<D: Hash + Eq + Copy + Send + 'static> Partition<D> {
+    pub(crate) fn new(name: &str, budget: usize) -> Self {
+        Partition {
+            name: name.into(),
+            budget: AtomicUsize::new(budget),
+            nodes: Mutex::new(Nodes {
+                nodes: HashMap::new(),
+                policy: Box::new(Lru::new()),
+                size: 0,
+            }),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn set_budget(&self, budget: usize) {
+        self.budget.store(budget, Ordering::SeqCst);
+        self.nodes.lock().evict(budget)
+    }
+
+    // Replaces the eviction policy, handing it the nodes already cached
+    pub(crate) fn set_policy(&self, mut policy: Box<dyn EvictionPolicy<D>>) {
+        let mut nodes = self.nodes.lock();
+        for digest in nodes.nodes.keys() {
+            policy.insert(*digest)
+        }
+        nodes.policy = policy;
+    }
+
+    pub(crate) fn get(&self, digest: &D) -> Option<Arc<[u8]>> {
+        let mut nodes = self.nodes.lock();
+        let bytes = match nodes.nodes.get(digest) {
+            Some(bytes) => bytes.clone(),
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                return None;
+            }
+        };
+        nodes.policy.hit(digest);
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        Some(bytes)
+    }
+
+    // Returns true if `digest` is cached, without counting a hit or miss
+    pub(crate) fn contains(&self, digest: &D) -> bool {
+        self.nodes.lock().nodes.contains_key(digest)
+    }
+
+    // Drops `digest` from the cache, the policy forgets about it once it
+    // chooses it for eviction
+    pub(crate) fn remove(&self, digest: &D) {
+        let mut nodes = self.nodes.lock();
+        if let Some(bytes) = nodes.nodes.remove(digest) {
+            nodes.size -= bytes.len();
+        }
+    }
+
+    // Drops every cached node not satisfying `keep`
+    pub(crate) fn retain<F: FnMut(&D) -> bool>(&self, mut keep: F) {
+        let mut nodes = self.nodes.lock();
+        let mut size = 0;
+        nodes.nodes.retain(|digest, bytes| {
+            let kept = keep(digest);
+            if kept {
+                size += bytes.len()
+            }
+            kept
+        });
+        nodes.size = size;
+    }
+
+    pub(crate) fn insert(&self, digest: D, bytes: Arc<[u8]>) {
+        let budget = self.budget.load(Ordering::Relaxed);
+        if bytes.len() > budget {
+            return;
+        }
+        let mut nodes = self.nodes.lock();
+        if nodes.nodes.contains_key(&digest) {
+            return;
+        }
+        nodes.size += bytes.len();
+        nodes.nodes.insert(digest, bytes);
+        nodes.policy.insert(digest);
+        nodes.evict(budget);
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        self.nodes.lock().size
+    }
+
+    pub(crate) fn budget(&self) -> usize {
+        self.budget.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn reset_counters(&self) {
+        self.hits.store(0, Ordering::Relaxed);
+        self.misses.store(0, Ordering::Relaxed);
+    }
+}
+
+impl<D: Hash + Eq> Nodes<D> {
+    // Evicts nodes until at most `size` bytes are cached
+    fn evict(&mut self, size: usize) {
+        while self.size > size {
+            let digest = match self.policy.evict() {
+                Some(digest) => digest,
+                None => return,
+            };
+            if let Some(bytes) = self.nodes.remove(&digest) {
+                self.size -= bytes.len();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::eviction::Lfu;
+
+    #[test]
+    fn least_recently_used_evicted() {
+        let partition = Partition::new("test", 30);
+        partition.insert(1u8, vec![0; 10].into());
+        partition.insert(2, vec![0; 10].into());
+        partition.insert(3, vec![0; 10].into());
+        assert!(partition.get(&1).is_some());
+
+        partition.insert(4, vec![0; 10].into());
+        assert!(partition.get(&2).is_none());
+        assert!(partition.get(&1).is_some());
+        assert_eq!(partition.size(), 30);
+
+        // larger than the budget, never cached
+        partition.insert(5, vec![0; 40].into());
+        assert!(partition.get(&5).is_none());
+
+        partition.set_budget(10);
+        assert_eq!(partition.size(), 10);
+        assert!(partition.get(&1).is_some());
+    }
+
+    #[test]
+    fn replaced_policy() {
+        let partition = Partition::new("test", 30);
+        partition.insert(1u8, vec![0; 10].into());
+        partition.insert(2, vec![0; 10].into());
+        partition.set_policy(Box::new(Lfu::new()));
+        partition.insert(3, vec![0; 10].into());
+        for _ in 0..3 {
+            partition.get(&3).unwrap();
+            partition.get(&1).unwrap();
+        }
+
+        // the least frequently used, not the least recently used
+        partition.insert(4, vec![0; 10].into());
+        assert!(partition.get(&2).is_none());
+        partition.insert(5, vec![0; 10].into());
+        assert!(partition.get(&4).is_none());
+        assert!(partition.get(&1).is_some());
+        assert!(partition.get(&3).is_some());
+    }
+
+    #[test]
+    fn counted_hits_and_misses() {
+        let partition = Partition::new("test", 30);
+        partition.insert(1u8, vec![0; 10].into());
+        assert!(partition.get(&1).is_some());
+        assert!(partition.get(&1).is_some());
+        assert!(partition.get(&2).is_none());
+        assert!(partition.contains(&1));
+        assert_eq!((partition.hits(), partition.misses()), (2, 1));
+
+        partition.remove(&1);
+        assert_eq!(partition.size(), 0);
+        assert!(partition.get(&1).is_none());
+        assert_eq!(partition.misses(), 2);
+
+        partition.reset_counters();
+        assert_eq!((partition.hits(), partition.misses()), (0, 0));
+    }
+}
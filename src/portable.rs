@@ -0,0 +1,92 @@
+use std::hash::{Hash, Hasher};
+
+use bytehash::{ByteHash, State};
+
+/// Hasher wrapper writing all integers as fixed-width little-endian bytes
+///
+/// `Hash` implementations of integers, and the length prefixes of slices and
+/// strings, write native-endian, pointer-width bytes. Wrapping the hash state
+/// makes the resulting digests equal on every platform, and equal to those
+/// computed by the unwrapped state on 64-bit little-endian hosts.
+pub struct PortableHasher<S>(S);
+
+impl<S> PortableHasher<S> {
+    /// Wraps the hash state `state`
+    pub fn new(state: S) -> Self {
+        PortableHasher(state)
+    }
+
+    /// Unwraps the hash state
+    pub fn into_inner(self) -> S {
+        self.0
+    }
+}
+
+macro_rules! write_le {
+    ($($name:ident: $t:ty),*) => {
+        $(
+            fn $name(&mut self, i: $t) {
+                self.0.write(&i.to_le_bytes())
+            }
+        )*
+    };
+}
+
+impl<S: Hasher> Hasher for PortableHasher<S> {
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.write(bytes)
+    }
+
+    write_le!(
+        write_u16: u16,
+        write_u32: u32,
+        write_u64: u64,
+        write_u128: u128,
+        write_i16: i16,
+        write_i32: i32,
+        write_i64: i64,
+        write_i128: i128
+    );
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64)
+    }
+
+    fn write_isize(&mut self, i: isize) {
+        self.write_i64(i as i64)
+    }
+
+    fn finish(&self) -> u64 {
+        self.0.finish()
+    }
+}
+
+/// Hashes `t` with `H`, producing the same digest on every platform
+pub fn portable_hash<H, T>(t: &T) -> H::Digest
+where
+    H: ByteHash,
+    T: Hash + ?Sized,
+{
+    let mut state = PortableHasher(H::state());
+    t.hash(&mut state);
+    state.0.fin()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Blake2b;
+
+    #[test]
+    fn matches_native_on_64_bit_little_endian() {
+        if cfg!(all(target_endian = "little", target_pointer_width = "64")) {
+            assert!(
+                portable_hash::<Blake2b, _>(&42u64) == Blake2b::hash(&42u64)
+            );
+            assert!(
+                portable_hash::<Blake2b, _>("kelvin")
+                    == Blake2b::hash("kelvin")
+            );
+        }
+    }
+}
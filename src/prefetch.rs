@@ -0,0 +1,199 @@
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::hash::Hash;
+use std::sync::Arc;
+use std::thread;
+
+use parking_lot::{Condvar, Mutex};
+
+// Maximum number of nodes waiting to be prefetched, past which the oldest
+// requests are dropped, the traversal having likely moved past them
+const QUEUED: usize = 256;
+
+// Maximum number of prefetched nodes waiting to be read, past which the least
+// recently requested ones are dropped
+const PREFETCHED: usize = 1024;
+
+// Reads the bytes of a node, `None` if it could not or need not be read
+pub(crate) type Fetch<D> = Box<dyn Fn(&D) -> Option<Arc<[u8]>> + Send>;
+
+// A bounded pool of threads reading nodes ahead of a traversal
+//
+// Workers are started on demand, up to the number given with the requests,
+// and stop when the prefetcher is dropped along with its store.
+pub(crate) struct Prefetcher<D> {
+    shared: Arc<Shared<D>>,
+}
+
+struct Shared<D> {
+    state: Mutex<State<D>>,
+    // signalled when a request is queued, or the prefetcher dropped
+    queued: Condvar,
+    // signalled when the workers run out of requests
+    idle: Condvar,
+}
+
+struct State<D> {
+    queue: VecDeque<D>,
+    // nodes being read by the workers
+    reading: HashSet<D>,
+    fetched: Lru<D>,
+    // workers started, at most `limit` of them
+    workers: usize,
+    limit: usize,
+    closed: bool,
+}
+
+impl<D> Default for Prefetcher<D> {
+    fn default() -> Self {
+        Prefetcher {
+            shared: Arc::new(Shared {
+                state: Mutex::new(State {
+                    queue: VecDeque::new(),
+                    reading: HashSet::new(),
+                    fetched: Lru::default(),
+                    workers: 0,
+                    limit: 0,
+                    closed: false,
+                }),
+                queued: Condvar::new(),
+                idle: Condvar::new(),
+            }),
+        }
+    }
+}
+
+impl<D: Hash + Eq + Copy + Send + 'static> Prefetcher<D> {
+    // Queues the node with digest `digest` to be read by one of at most
+    // `workers` threads, starting one with the reader returned by `start` if
+    // fewer are running
+    pub fn request<F>(&self, digest: D, workers: usize, start: F)
+    where
+        F: FnOnce() -> Fetch<D>,
+    {
+        let mut state = self.shared.state.lock();
+        state.limit = workers;
+        if workers == 0
+            || state.fetched.touch(&digest)
+            || state.reading.contains(&digest)
+            || state.queue.contains(&digest)
+        {
+            return;
+        }
+        if state.queue.len() >= QUEUED {
+            state.queue.pop_front();
+        }
+        state.queue.push_back(digest);
+        if state.workers < workers {
+            state.workers += 1;
+            let shared = self.shared.clone();
+            let fetch = start();
+            thread::spawn(move || work(shared, fetch));
+        }
+        self.shared.queued.notify_one();
+    }
+
+    // Takes the prefetched bytes of a node about to be read, dropping the
+    // request to prefetch it if still queued
+    pub fn take(&self, digest: &D) -> Option<Arc<[u8]>> {
+        let mut state = self.shared.state.lock();
+        if let Some(i) = state.queue.iter().position(|d| d == digest) {
+            state.queue.remove(i);
+        }
+        state.fetched.take(digest)
+    }
+
+    // Waits until every queued node has been read
+    pub fn wait(&self) {
+        let mut state = self.shared.state.lock();
+        while !state.queue.is_empty() || !state.reading.is_empty() {
+            self.shared.idle.wait(&mut state);
+        }
+    }
+}
+
+impl<D> Drop for Prefetcher<D> {
+    fn drop(&mut self) {
+        self.shared.state.lock().closed = true;
+        self.shared.queued.notify_all();
+    }
+}
+
+fn work<D: Hash + Eq + Copy>(shared: Arc<Shared<D>>, fetch: Fetch<D>) {
+    let mut state = shared.state.lock();
+    loop {
+        if state.closed || state.workers > state.limit {
+            state.workers -= 1;
+            return;
+        }
+        match state.queue.pop_front() {
+            Some(digest) => {
+                state.reading.insert(digest);
+                drop(state);
+                let bytes = fetch(&digest);
+                state = shared.state.lock();
+                state.reading.remove(&digest);
+                if let Some(bytes) = bytes {
+                    state.fetched.insert(digest, bytes);
+                }
+            }
+            None => {
+                shared.idle.notify_all();
+                shared.queued.wait(&mut state);
+            }
+        }
+    }
+}
+
+// Prefetched nodes, by the order they were last requested in
+struct Lru<D> {
+    nodes: HashMap<D, (u64, Arc<[u8]>)>,
+    order: BTreeMap<u64, D>,
+    tick: u64,
+}
+
+impl<D> Default for Lru<D> {
+    fn default() -> Self {
+        Lru {
+            nodes: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+}
+
+impl<D: Hash + Eq + Copy> Lru<D> {
+    // Marks a node as just requested, returning false if not prefetched
+    fn touch(&mut self, digest: &D) -> bool {
+        let tick = self.tick;
+        match self.nodes.get_mut(digest) {
+            Some(entry) => {
+                self.order.remove(&entry.0);
+                self.order.insert(tick, *digest);
+                entry.0 = tick;
+                self.tick += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn insert(&mut self, digest: D, bytes: Arc<[u8]>) {
+        if let Some((tick, _)) = self.nodes.remove(&digest) {
+            self.order.remove(&tick);
+        }
+        if self.nodes.len() >= PREFETCHED {
+            let oldest = *self.order.keys().next().expect("not empty");
+            let evicted = self.order.remove(&oldest).expect("present");
+            self.nodes.remove(&evicted);
+        }
+        self.order.insert(self.tick, digest);
+        self.nodes.insert(digest, (self.tick, bytes));
+        self.tick += 1;
+    }
+
+    fn take(&mut self, digest: &D) -> Option<Arc<[u8]>> {
+        let (tick, bytes) = self.nodes.remove(digest)?;
+        self.order.remove(&tick);
+        Some(bytes)
+    }
+}
@@ -0,0 +1,571 @@
+use std::borrow::{Borrow, Cow};
+use std::hash::Hasher;
+use std::io::{self, Write};
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds, RangeInclusive};
+use std::slice;
+
+use bytehash::{ByteHash, State};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::annotations::MaxKey;
+use crate::compound::Compound;
+use crate::content::Content;
+use crate::handle::HandleRef;
+use crate::search::{Method, SearchResult};
+use crate::sink::Sink;
+use crate::source::Source;
+use crate::store::{Snapshot, Store};
+
+/// A Merkle inclusion proof for a leaf in a Compound
+///
+/// Holds the encoding of every node on the path from the root to the leaf,
+/// and the index of the child followed in each. Sibling subtrees are only
+/// represented by their digests, so the proof grows with the depth of the
+/// tree, not with its size.
+pub struct Proof<C, H> {
+    nodes: Vec<Vec<u8>>,
+    path: Vec<u32>,
+    _marker: PhantomData<(C, H)>,
+}
+
+impl<C, H> Clone for Proof<C, H> {
+    fn clone(&self) -> Self {
+        Proof {
+            nodes: self.nodes.clone(),
+            path: self.path.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+fn hash<H: ByteHash>(bytes: &[u8]) -> H::Digest {
+    let mut hasher = H::state();
+    hasher.write(bytes);
+    hasher.fin()
+}
+
+fn encode<T: Content<H>, H: ByteHash>(
+    t: &T,
+    scratch: &Store<H>,
+) -> io::Result<Vec<u8>> {
+    let mut sink = Sink::new(scratch);
+    t.clone().persist(&mut sink)?;
+    Ok(sink.bytes().to_vec())
+}
+
+// A scratch store writing nodes as the store of the persisted children of
+// `node` does, if it has any
+fn scratch<C: Compound<H>, H: ByteHash>(node: &C) -> Store<H> {
+    let scratch = Store::ephemeral();
+    let children = node.children();
+    if let Some(snapshot) = children.iter().find_map(|c| c.snapshot()) {
+        let store = snapshot.store();
+        scratch.set_domain_separation(store.domain_separation());
+        scratch.set_max_leaf_size(store.max_leaf_size());
+    }
+    scratch
+}
+
+// Encodes a node as stored, format header included
+fn encode_node<C: Compound<H>, H: ByteHash>(
+    node: &C,
+    scratch: &Store<H>,
+) -> io::Result<Vec<u8>> {
+    let mut sink = Sink::node(scratch);
+    node.clone().persist(&mut sink)?;
+    Ok(sink.bytes().to_vec())
+}
+
+/// Proves the inclusion of the leaf found in `root` using `method`
+///
+/// Returns `None` if no leaf is found. Persisted nodes on the path are
+/// proven as stored, nodes that are not are encoded in full, including their
+/// subtrees, so proofs are cheapest to produce on a tree restored from the
+/// store. To prove against the root of a tree as stored, see `prove_stored`.
+pub fn prove<C, M, H>(
+    root: &C,
+    method: &mut M,
+) -> io::Result<Option<Proof<C, H>>>
+where
+    C: Compound<H>,
+    M: Method<C, H>,
+    H: ByteHash,
+{
+    let scratch = scratch(root);
+    let bytes = encode_node(root, &scratch)?;
+    prove_level(root, bytes, method, &scratch, &mut Proof::empty())
+}
+
+/// Proves the inclusion of the leaf found in the tree at `root` using
+/// `method`
+///
+/// The root is proven as stored, rather than encoded again in the current
+/// format, so that trees written by earlier versions prove against the
+/// digests they were stored under.
+pub fn prove_stored<C, M, H>(
+    root: &Snapshot<C, H>,
+    method: &mut M,
+) -> io::Result<Option<Proof<C, H>>>
+where
+    C: Compound<H>,
+    M: Method<C, H>,
+    H: ByteHash,
+{
+    let (node, bytes) = root.store().read_raw::<C>(root.hash())?;
+    let scratch = scratch(&node);
+    prove_level(&node, bytes, method, &scratch, &mut Proof::empty())
+}
+
+fn prove_level<C, M, H>(
+    node: &C,
+    bytes: Vec<u8>,
+    method: &mut M,
+    scratch: &Store<H>,
+    proof: &mut Proof<C, H>,
+) -> io::Result<Option<Proof<C, H>>>
+where
+    C: Compound<H>,
+    M: Method<C, H>,
+    H: ByteHash,
+{
+    let (i, leaf) = match method.select(node, 0) {
+        SearchResult::Leaf(i) => (i, true),
+        SearchResult::Path(i) => (i, false),
+        SearchResult::None => return Ok(None),
+    };
+    proof.nodes.push(bytes);
+    proof.path.push(i as u32);
+    let child = match node.children().get(i) {
+        Some(child) => child,
+        None => return Ok(None),
+    };
+    if !leaf {
+        // persisted nodes are proven as stored
+        if let Some(snapshot) = child.snapshot() {
+            let store = snapshot.store();
+            let (child, bytes) = store.read_raw::<C>(snapshot.hash())?;
+            return prove_level(&child, bytes, method, scratch, proof);
+        }
+    }
+    match child.inner()? {
+        HandleRef::Leaf(_) if leaf => Ok(Some(proof.clone())),
+        HandleRef::Node(child) if !leaf => {
+            let bytes = encode_node(&*child, scratch)?;
+            prove_level(&*child, bytes, method, scratch, proof)
+        }
+        _ => Ok(None),
+    }
+}
+
+impl<C, H> Proof<C, H>
+where
+    C: Compound<H>,
+    H: ByteHash,
+{
+    fn empty() -> Self {
+        Proof {
+            nodes: vec![],
+            path: vec![],
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the root digest the proof is against
+    pub fn root(&self) -> H::Digest {
+        hash::<H>(&self.nodes[0])
+    }
+
+    /// Returns the number of nodes on the proven path
+    pub fn depth(&self) -> usize {
+        self.nodes.len()
+    }
+}
+
+/// Verifies that `proof` proves the inclusion of `leaf` under `root`
+///
+/// Only the nodes in the proof are decoded, nothing is read from a store.
+/// Returns an `InvalidData` error if the proof is malformed, or if any of
+/// its nodes has no domain, see `verify_legacy`.
+pub fn verify<C, H>(
+    root: &H::Digest,
+    proof: &Proof<C, H>,
+    leaf: &C::Leaf,
+) -> io::Result<bool>
+where
+    C: Compound<H>,
+    H: ByteHash,
+{
+    verify_in(root, proof, leaf, &Store::ephemeral())
+}
+
+/// Verifies a proof like `verify`, accepting nodes without a domain
+///
+/// For proofs of roots written before format version 2, see
+/// `Store::set_legacy_reads`.
+pub fn verify_legacy<C, H>(
+    root: &H::Digest,
+    proof: &Proof<C, H>,
+    leaf: &C::Leaf,
+) -> io::Result<bool>
+where
+    C: Compound<H>,
+    H: ByteHash,
+{
+    let scratch = Store::ephemeral();
+    scratch.set_legacy_reads(true);
+    verify_in(root, proof, leaf, &scratch)
+}
+
+fn verify_in<C, H>(
+    root: &H::Digest,
+    proof: &Proof<C, H>,
+    leaf: &C::Leaf,
+    scratch: &Store<H>,
+) -> io::Result<bool>
+where
+    C: Compound<H>,
+    H: ByteHash,
+{
+    let mut expected = *root;
+    for (bytes, &i) in proof.nodes.iter().zip(&proof.path) {
+        if hash::<H>(bytes) != expected {
+            return Ok(false);
+        }
+        let node: C = scratch.restore_node(bytes)?;
+        let child = node.children().get(i as usize).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "Invalid proof path")
+        })?;
+        if let Some(digest) = child.digest() {
+            expected = *digest;
+            continue;
+        }
+        return match child.leaf() {
+            Some(found) => {
+                Ok(encode(found, scratch)? == encode(leaf, scratch)?)
+            }
+            None => Ok(false),
+        };
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "Proof does not end in a leaf",
+    ))
+}
+
+impl<C, H> Content<H> for Proof<C, H>
+where
+    C: 'static,
+    H: ByteHash,
+{
+    fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
+        sink.write_u32::<BigEndian>(self.nodes.len() as u32)?;
+        for (bytes, i) in self.nodes.iter().zip(&self.path) {
+            sink.write_u32::<BigEndian>(*i)?;
+            sink.write_u32::<BigEndian>(bytes.len() as u32)?;
+            sink.write_all(bytes)?;
+        }
+        Ok(())
+    }
+
+    fn restore(source: &mut Source<H>) -> io::Result<Self> {
+        let len = source.read_u32::<BigEndian>()?;
+        if len == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Empty proof",
+            ));
+        }
+        let mut proof = Proof {
+            nodes: vec![],
+            path: vec![],
+            _marker: PhantomData,
+        };
+        for _ in 0..len {
+            proof.path.push(source.read_u32::<BigEndian>()?);
+            proof.nodes.push(source.read_len_prefixed(usize::MAX)?);
+        }
+        Ok(proof)
+    }
+}
+
+/// A Merkle proof of all the keys within a range of an ordered Compound
+///
+/// Holds the encoding of every node whose subtree may contain keys in the
+/// range, as told by its `MaxKey` annotation. Verifying it yields all the
+/// leaves in the range, so it also proves that no other keys are present.
+pub struct RangeProof<C, H> {
+    nodes: Vec<Vec<u8>>,
+    _marker: PhantomData<(C, H)>,
+}
+
+impl<C, H> Clone for RangeProof<C, H> {
+    fn clone(&self) -> Self {
+        RangeProof {
+            nodes: self.nodes.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<C, H> RangeProof<C, H> {
+    /// Returns the number of nodes in the proof
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Returns true if the proof contains no nodes
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+}
+
+// Returns the indices of the children of `node` that may hold keys in
+// `range`, each child covering the keys above the max key of the one before
+fn overlapping<C, K, R, H>(node: &C, range: &R) -> Vec<usize>
+where
+    C: Compound<H>,
+    C::Annotation: Borrow<MaxKey<K>>,
+    K: Ord,
+    R: RangeBounds<K>,
+    H: ByteHash,
+{
+    let mut indices = vec![];
+    let mut below: Option<Cow<C::Annotation>> = None;
+    for (i, child) in node.children().iter().enumerate() {
+        let ann = match child.annotation() {
+            Some(ann) => ann,
+            None => continue,
+        };
+        let max: &MaxKey<K> = (*ann).borrow();
+        let max: &K = max;
+        let above_start = match range.start_bound() {
+            Bound::Included(start) => max >= start,
+            Bound::Excluded(start) => max > start,
+            Bound::Unbounded => true,
+        };
+        let below_end = match (range.end_bound(), &below) {
+            (_, None) | (Bound::Unbounded, _) => true,
+            (Bound::Included(end), Some(b))
+            | (Bound::Excluded(end), Some(b)) => {
+                let below: &MaxKey<K> = (**b).borrow();
+                **below < *end
+            }
+        };
+        if above_start && below_end {
+            indices.push(i)
+        }
+        below = Some(ann);
+    }
+    indices
+}
+
+/// Proves which keys of `root` are within `range`
+///
+/// As with `prove`, persisted nodes are proven as stored, and nodes that are
+/// not yet persisted are encoded in full.
+pub fn prove_range<C, K, R, H>(
+    root: &C,
+    range: &R,
+) -> io::Result<RangeProof<C, H>>
+where
+    C: Compound<H>,
+    C::Leaf: AsRef<K>,
+    C::Annotation: Borrow<MaxKey<K>>,
+    K: Ord,
+    R: RangeBounds<K>,
+    H: ByteHash,
+{
+    let scratch = scratch(root);
+    let mut proof = RangeProof {
+        nodes: vec![],
+        _marker: PhantomData,
+    };
+    let bytes = encode_node(root, &scratch)?;
+    prove_range_level(root, bytes, range, &scratch, &mut proof.nodes)?;
+    Ok(proof)
+}
+
+fn prove_range_level<C, K, R, H>(
+    node: &C,
+    bytes: Vec<u8>,
+    range: &R,
+    scratch: &Store<H>,
+    nodes: &mut Vec<Vec<u8>>,
+) -> io::Result<()>
+where
+    C: Compound<H>,
+    C::Annotation: Borrow<MaxKey<K>>,
+    K: Ord,
+    R: RangeBounds<K>,
+    H: ByteHash,
+{
+    nodes.push(bytes);
+    for i in overlapping(node, range) {
+        let child = &node.children()[i];
+        if let Some(snapshot) = child.snapshot() {
+            let store = snapshot.store();
+            let (child, bytes) = store.read_raw::<C>(snapshot.hash())?;
+            prove_range_level(&child, bytes, range, scratch, nodes)?
+        } else if let HandleRef::Node(child) = child.inner()? {
+            let bytes = encode_node(&*child, scratch)?;
+            prove_range_level(&*child, bytes, range, scratch, nodes)?
+        }
+    }
+    Ok(())
+}
+
+/// Proves that `key` is not in `root`
+///
+/// Returns `None` if the key is present.
+pub fn prove_absent<C, K, H>(
+    root: &C,
+    key: &K,
+) -> io::Result<Option<RangeProof<C, H>>>
+where
+    C: Compound<H>,
+    C::Leaf: AsRef<K>,
+    C::Annotation: Borrow<MaxKey<K>>,
+    K: Ord + Clone,
+    H: ByteHash,
+{
+    let range = key.clone()..=key.clone();
+    let proof = prove_range(root, &range)?;
+    let empty = verify_range(&proof.root(), &proof, &range)?
+        .map(|leaves| leaves.is_empty())
+        .unwrap_or(false);
+    Ok(if empty { Some(proof) } else { None })
+}
+
+impl<C, H> RangeProof<C, H>
+where
+    C: Compound<H>,
+    H: ByteHash,
+{
+    /// Returns the root digest the proof is against
+    pub fn root(&self) -> H::Digest {
+        self.nodes
+            .first()
+            .map(|bytes| hash::<H>(bytes))
+            .unwrap_or_default()
+    }
+}
+
+/// Verifies `proof` against `root`, returning all leaves within `range`
+///
+/// The range has to be the one the proof was made for. Returns `None` if
+/// the proof does not match the root, or does not cover the range. Only the
+/// nodes in the proof are decoded, nothing is read from a store.
+pub fn verify_range<C, K, R, H>(
+    root: &H::Digest,
+    proof: &RangeProof<C, H>,
+    range: &R,
+) -> io::Result<Option<Vec<C::Leaf>>>
+where
+    C: Compound<H>,
+    C::Leaf: AsRef<K>,
+    C::Annotation: Borrow<MaxKey<K>>,
+    K: Ord,
+    R: RangeBounds<K>,
+    H: ByteHash,
+{
+    let scratch = Store::<H>::ephemeral();
+    let mut nodes = proof.nodes.iter();
+    let mut leaves = vec![];
+    if verify_range_level::<C, K, R, H>(
+        root,
+        &mut nodes,
+        range,
+        &scratch,
+        &mut leaves,
+    )? && nodes.next().is_none()
+    {
+        Ok(Some(leaves))
+    } else {
+        Ok(None)
+    }
+}
+
+fn verify_range_level<C, K, R, H>(
+    expected: &H::Digest,
+    nodes: &mut slice::Iter<Vec<u8>>,
+    range: &R,
+    scratch: &Store<H>,
+    leaves: &mut Vec<C::Leaf>,
+) -> io::Result<bool>
+where
+    C: Compound<H>,
+    C::Leaf: AsRef<K>,
+    C::Annotation: Borrow<MaxKey<K>>,
+    K: Ord,
+    R: RangeBounds<K>,
+    H: ByteHash,
+{
+    let bytes = match nodes.next() {
+        Some(bytes) if hash::<H>(bytes) == *expected => bytes,
+        _ => return Ok(false),
+    };
+    let node: C = scratch.restore_node(bytes)?;
+    for i in overlapping(&node, range) {
+        let child = &node.children()[i];
+        if let Some(digest) = child.digest() {
+            if !verify_range_level::<C, K, R, H>(
+                digest, nodes, range, scratch, leaves,
+            )? {
+                return Ok(false);
+            }
+        } else if let Some(leaf) = child.leaf() {
+            if range.contains(leaf.as_ref()) {
+                leaves.push(C::Leaf::clone(leaf))
+            }
+        }
+    }
+    Ok(true)
+}
+
+/// Verifies that `proof` proves `key` is not in the tree with root `root`
+pub fn verify_absent<C, K, H>(
+    root: &H::Digest,
+    proof: &RangeProof<C, H>,
+    key: &K,
+) -> io::Result<bool>
+where
+    C: Compound<H>,
+    C::Leaf: AsRef<K>,
+    C::Annotation: Borrow<MaxKey<K>>,
+    K: Ord + Clone,
+    H: ByteHash,
+{
+    let range: RangeInclusive<K> = key.clone()..=key.clone();
+    Ok(verify_range(root, proof, &range)?
+        .map(|leaves| leaves.is_empty())
+        .unwrap_or(false))
+}
+
+impl<C, H> Content<H> for RangeProof<C, H>
+where
+    C: 'static,
+    H: ByteHash,
+{
+    fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
+        sink.write_u32::<BigEndian>(self.nodes.len() as u32)?;
+        for bytes in &self.nodes {
+            sink.write_u32::<BigEndian>(bytes.len() as u32)?;
+            sink.write_all(bytes)?;
+        }
+        Ok(())
+    }
+
+    fn restore(source: &mut Source<H>) -> io::Result<Self> {
+        let len = source.read_u32::<BigEndian>()?;
+        let mut nodes = vec![];
+        for _ in 0..len {
+            let bytes = read_node(source)?;
+            nodes.push(bytes);
+        }
+        Ok(RangeProof {
+            nodes,
+            _marker: PhantomData,
+        })
+    }
+}
@@ -1,3 +1,4 @@
+use std::cmp::Ordering;
 use std::io;
 use std::marker::PhantomData;
 use std::mem;
@@ -25,6 +26,8 @@ enum NodeRef<'a, C, H> {
 
 pub struct Level<'a, C, H> {
     ofs: usize,
+    // `ofs` counts from the last child when searching in reverse
+    rev: bool,
     node: NodeRef<'a, C, H>,
 }
 
@@ -163,22 +166,34 @@ where
     pub fn new_cached(cached: Cached<'a, C>) -> Self {
         Level {
             ofs: 0,
+            rev: false,
             node: NodeRef::new_cached(cached),
         }
     }
 
+    // The index of the child pointed to
+    fn index(&self) -> usize {
+        if self.rev {
+            // wraps to an invalid index past the first child
+            self.node.children().len().wrapping_sub(self.ofs + 1)
+        } else {
+            self.ofs
+        }
+    }
+
     pub fn insert_child(&mut self, node: C) {
+        let idx = self.index();
         match &mut self.node {
             NodeRef::Cached(c) => {
                 self.node = NodeRef::Owned(Box::new((*c).clone()));
                 self.insert_child(node)
             }
             NodeRef::Owned(o) => {
-                (**o).children_mut()[self.ofs] = Handle::new_node(node)
+                (**o).children_mut()[idx] = Handle::new_node(node)
             }
             NodeRef::Placeholder(_) => unreachable!(),
             NodeRef::Mutable(ref mut m) => {
-                m.children_mut()[self.ofs] = Handle::new_node(node)
+                m.children_mut()[idx] = Handle::new_node(node)
             }
         }
     }
@@ -186,6 +201,7 @@ where
     pub fn new_mutable(node: &'a mut C) -> Self {
         Level {
             ofs: 0,
+            rev: false,
             node: NodeRef::new_mutable(node),
         }
     }
@@ -197,22 +213,40 @@ where
     pub fn leaf(&self) -> Option<&C::Leaf> {
         self.node
             .children()
-            .get(self.ofs)
+            .get(self.index())
             .and_then(|handle| handle.leaf())
     }
 
     pub fn leaf_mut(&'a mut self) -> Option<&'a mut C::Leaf> {
+        let idx = self.index();
         self.node
             .children_mut()
-            .get_mut(self.ofs)
+            .get_mut(idx)
             .and_then(|handle| handle.leaf_mut())
     }
 
+    // Prefetches the children after the one pointed to, in the direction of
+    // the search, to be read next when iterating
+    fn prefetch_ahead(&self) {
+        let children = self.node.children();
+        let idx = self.index();
+        if self.rev {
+            for child in children[..idx.min(children.len())].iter().rev() {
+                child.prefetch()
+            }
+        } else {
+            for child in children.iter().skip(idx + 1) {
+                child.prefetch()
+            }
+        }
+    }
+
     pub fn referencing(&self) -> io::Result<HandleRef<C, H>> {
-        self.node.handle(self.ofs)
+        self.node.handle(self.index())
     }
 
     fn search<M: Method<C, H>>(&mut self, method: &mut M) -> io::Result<Found> {
+        self.rev = method.reverse();
         let node = self.inner_immutable();
         let children_len = node.children().len();
         if self.ofs + 1 > children_len {
@@ -269,6 +303,10 @@ where
         self.exact
     }
 
+    pub(crate) fn depth(&self) -> usize {
+        self.levels.len()
+    }
+
     pub fn search<M: Method<C, H>>(
         &mut self,
         method: &mut M,
@@ -283,6 +321,7 @@ where
                 }
                 Found::Path => match last.referencing()? {
                     HandleRef::Node(cached) => {
+                        last.prefetch_ahead();
                         let level: Level<'a, _, _> = unsafe {
                             mem::transmute(Level::new_cached(cached))
                         };
@@ -306,6 +345,102 @@ where
         Ok(())
     }
 
+    /// Searches from the root again, reusing the nodes of the current path
+    /// as long as the new path follows it, instead of restoring them
+    pub(crate) fn reseek<M: Method<C, H>>(
+        &mut self,
+        method: &mut M,
+    ) -> io::Result<()> {
+        let old = self.position();
+        self.exact = false;
+        let mut depth = 0;
+        loop {
+            let level = &mut self.levels[depth];
+            level.ofs = 0;
+            match level.search(method)? {
+                Found::Leaf => {
+                    self.truncate(depth + 1);
+                    self.exact = true;
+                    return Ok(());
+                }
+                Found::Path => {
+                    let followed = old.get(depth) == Some(&level.index());
+                    if followed && depth + 1 < self.levels.len() {
+                        depth += 1;
+                        continue;
+                    }
+                    self.truncate(depth + 1);
+                    let last = self.levels.last_mut().expect("not empty");
+                    let push = match last.referencing()? {
+                        HandleRef::Node(cached) => {
+                            let level: Level<'a, _, _> = unsafe {
+                                mem::transmute(Level::new_cached(cached))
+                            };
+                            level
+                        }
+                        _ => return Ok(()),
+                    };
+                    self.levels.push(push);
+                    return self.search(method);
+                }
+                Found::None => {
+                    // continue like `search` would from here
+                    self.truncate(depth + 1);
+                    if self.levels.len() > 1 {
+                        self.pop_level();
+                        self.advance();
+                        return self.search(method);
+                    }
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    fn truncate(&mut self, depth: usize) {
+        while self.levels.len() > depth {
+            self.pop_level();
+        }
+    }
+
+    /// Pops the deepest level, pointing at the subtree it was in, returns
+    /// false at the root
+    pub(crate) fn ascend(&mut self) -> bool {
+        if self.levels.len() > 1 {
+            self.pop_level();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Switches every level to counting from the last child, or from the
+    /// first, keeping the children pointed to
+    pub(crate) fn set_reverse(&mut self, rev: bool) {
+        for level in self.levels.iter_mut() {
+            if level.rev != rev {
+                let idx = level.index();
+                level.rev = rev;
+                level.ofs = if rev {
+                    level.node.children().len().wrapping_sub(idx + 1)
+                } else {
+                    idx
+                };
+            }
+        }
+    }
+
+    /// The indices of the children pointed to, from the root down
+    pub(crate) fn position(&self) -> Vec<usize> {
+        self.levels.iter().map(Level::index).collect()
+    }
+
+    /// Compares the positions of two branches into the same Compound
+    pub(crate) fn cmp_position(&self, other: &Self) -> Ordering {
+        let indices = self.levels.iter().map(Level::index);
+        indices.cmp(other.levels.iter().map(Level::index))
+    }
+
     pub fn advance(&mut self) {
         if let Some(level) = self.levels.last_mut() {
             level.ofs += 1;
@@ -349,4 +484,17 @@ where
     pub(crate) fn relink(&mut self) {
         while self.pop_level() {}
     }
+
+    /// Inserts the owned nodes into the tree and returns its root, if the
+    /// branch was created mutable
+    pub(crate) fn into_root(mut self) -> Option<&'a mut C> {
+        self.truncate(1);
+        match self.levels.pop() {
+            Some(Level {
+                node: NodeRef::Mutable(root),
+                ..
+            }) => Some(root),
+            _ => None,
+        }
+    }
 }
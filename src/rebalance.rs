@@ -0,0 +1,34 @@
+use std::borrow::Borrow;
+use std::io;
+
+use bytehash::ByteHash;
+
+use crate::annotations::{Depth, MaxDepth};
+use crate::compound::Compound;
+
+/// Structures that can be restructured when they have degraded
+///
+/// The depth of the structure is tracked by the `Depth` annotation, so
+/// checking whether a rebalance is needed does not require a traversal.
+/// Structures whose shape only depends on their keys, such as HAMTs, have
+/// nothing to rebalance and do not implement it.
+pub trait Rebalance<H>: Compound<H>
+where
+    H: ByteHash,
+    Self::Annotation: Borrow<Depth>,
+{
+    /// Rebuilds the structure into its balanced shape
+    fn rebalance(&mut self) -> io::Result<()>;
+
+    /// Rebalances the structure if it is deeper than `max_depth`
+    ///
+    /// Returns true if a rebalance was performed.
+    fn maybe_rebalance(&mut self, max_depth: u32) -> io::Result<bool> {
+        if self.depth() > max_depth {
+            self.rebalance()?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+use std::thread;
+
+enum Message {
+    Drop(Box<dyn Send>),
+    Sync(Sender<()>),
+}
+
+/// Background dropper for large node graphs
+///
+/// Values handed to the reclaimer are dropped on a dedicated thread, so that
+/// readers letting go of a restored tree never run its destructors inline.
+/// Clones share the same thread, which exits when the last clone is dropped.
+#[derive(Clone)]
+pub struct Reclaimer {
+    sender: Sender<Message>,
+    pending: Arc<AtomicUsize>,
+}
+
+impl fmt::Debug for Reclaimer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Reclaimer({} pending)", self.pending())
+    }
+}
+
+impl Default for Reclaimer {
+    fn default() -> Self {
+        Reclaimer::new()
+    }
+}
+
+impl Reclaimer {
+    /// Creates a new reclaimer, spawning its dropper thread
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let pending = Arc::new(AtomicUsize::new(0));
+        let thread_pending = pending.clone();
+
+        thread::Builder::new()
+            .name("kelvin-reclaim".into())
+            .spawn(move || {
+                for message in receiver {
+                    match message {
+                        Message::Drop(value) => {
+                            drop(value);
+                            thread_pending.fetch_sub(1, Ordering::SeqCst);
+                        }
+                        Message::Sync(done) => {
+                            let _ = done.send(());
+                        }
+                    }
+                }
+            })
+            .expect("could not spawn reclaimer thread");
+
+        Reclaimer { sender, pending }
+    }
+
+    /// Defers dropping `value` to the reclaimer thread
+    pub fn defer<T: Send + 'static>(&self, value: T) {
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        if let Err(mpsc::SendError(message)) =
+            self.sender.send(Message::Drop(Box::new(value)))
+        {
+            // The reclaimer thread is gone, drop inline
+            self.pending.fetch_sub(1, Ordering::SeqCst);
+            drop(message)
+        }
+    }
+
+    /// Returns the number of values waiting to be dropped
+    pub fn pending(&self) -> usize {
+        self.pending.load(Ordering::SeqCst)
+    }
+
+    /// Blocks until every value deferred so far has been dropped
+    pub fn sync(&self) {
+        let (done, wait) = mpsc::channel();
+        if self.sender.send(Message::Sync(done)).is_ok() {
+            let _ = wait.recv();
+        }
+    }
+}
+
+/// A value that is dropped by a `Reclaimer` instead of inline
+///
+/// Typically wrapped in an `Arc` and shared with reader threads, whichever
+/// thread drops the last reference only pays for a channel send.
+pub struct Deferred<T: Send + 'static> {
+    value: Option<T>,
+    reclaimer: Reclaimer,
+}
+
+impl<T: Send + 'static> Deferred<T> {
+    /// Wraps `value`, to be dropped by `reclaimer`
+    pub fn new(value: T, reclaimer: &Reclaimer) -> Self {
+        Deferred {
+            value: Some(value),
+            reclaimer: reclaimer.clone(),
+        }
+    }
+
+    /// Unwraps the value, it will then be dropped as usual
+    pub fn into_inner(mut self) -> T {
+        self.value.take().expect("value only taken on drop")
+    }
+}
+
+impl<T: Send + 'static> Deref for Deferred<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.value.as_ref().expect("value only taken on drop")
+    }
+}
+
+impl<T: Send + 'static> DerefMut for Deferred<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.value.as_mut().expect("value only taken on drop")
+    }
+}
+
+impl<T: Send + 'static> Drop for Deferred<T> {
+    fn drop(&mut self) {
+        if let Some(value) = self.value.take() {
+            self.reclaimer.defer(value)
+        }
+    }
+}
+
+impl<T: Send + 'static + fmt::Debug> fmt::Debug for Deferred<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Deferred({:?})", self.value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Mutex;
+    use std::thread::ThreadId;
+
+    struct Witness(Arc<Mutex<Option<ThreadId>>>);
+
+    impl Drop for Witness {
+        fn drop(&mut self) {
+            *self.0.lock().unwrap() = Some(thread::current().id())
+        }
+    }
+
+    #[test]
+    fn dropped_on_reclaimer_thread() {
+        let reclaimer = Reclaimer::new();
+        let dropped_on = Arc::new(Mutex::new(None));
+
+        let shared =
+            Arc::new(Deferred::new(Witness(dropped_on.clone()), &reclaimer));
+        let reader = shared.clone();
+        thread::spawn(move || drop(reader)).join().unwrap();
+        assert!(dropped_on.lock().unwrap().is_none());
+
+        drop(shared);
+        reclaimer.sync();
+
+        assert_eq!(reclaimer.pending(), 0);
+        let id = dropped_on.lock().unwrap().expect("value was dropped");
+        assert!(id != thread::current().id());
+    }
+
+    #[test]
+    fn into_inner() {
+        let reclaimer = Reclaimer::new();
+        let deferred = Deferred::new(vec![1, 2, 3], &reclaimer);
+        assert_eq!(deferred.len(), 3);
+        assert_eq!(deferred.into_inner(), vec![1, 2, 3]);
+        reclaimer.sync();
+        assert_eq!(reclaimer.pending(), 0);
+    }
+}
@@ -0,0 +1,102 @@
+use std::collections::HashSet;
+use std::io;
+use std::marker::PhantomData;
+
+use crate::compound::Compound;
+use crate::handle::HandleType;
+use crate::store::{Snapshot, Store};
+use crate::ByteHash;
+
+/// The kind of a persisted node
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NodeKind {
+    /// The root the iteration started from
+    Root,
+    /// A node linking to other persisted nodes
+    Branch,
+    /// A node with only leaves and empty slots
+    Bottom,
+}
+
+/// A persisted node, as stored in the backend
+#[derive(Clone, Debug)]
+pub struct NodeRecord<H: ByteHash> {
+    /// The digest of the node
+    pub digest: H::Digest,
+    /// The encoded node, hashing to `digest`
+    pub bytes: Vec<u8>,
+    /// The kind of node
+    pub kind: NodeKind,
+}
+
+/// An iterator over the persisted nodes reachable from a root
+///
+/// Nodes are visited depth first, parents before their children in slot
+/// order. Subtrees shared between several parents are only visited once.
+pub struct NodeRecords<C, H: ByteHash> {
+    store: Store<H>,
+    stack: Vec<H::Digest>,
+    seen: HashSet<H::Digest>,
+    root: bool,
+    _marker: PhantomData<C>,
+}
+
+impl<C, H> NodeRecords<C, H>
+where
+    C: Compound<H>,
+    H: ByteHash,
+{
+    pub(crate) fn new(store: &Store<H>, root: &Snapshot<C, H>) -> Self {
+        NodeRecords {
+            store: store.clone(),
+            stack: vec![*root.hash()],
+            seen: HashSet::new(),
+            root: true,
+            _marker: PhantomData,
+        }
+    }
+
+    fn record(&mut self, digest: H::Digest) -> io::Result<NodeRecord<H>> {
+        let (node, bytes) = self.store.read_raw::<C>(&digest)?;
+        let mut kind = NodeKind::Bottom;
+        for child in node.children().iter().rev() {
+            if child.handle_type() == HandleType::Node {
+                kind = NodeKind::Branch;
+            }
+            if let Some(child) = child.digest() {
+                if self.seen.insert(*child) {
+                    self.stack.push(*child)
+                }
+            }
+        }
+        if self.root {
+            self.root = false;
+            kind = NodeKind::Root;
+        }
+        Ok(NodeRecord {
+            digest,
+            bytes,
+            kind,
+        })
+    }
+}
+
+impl<C, H> Iterator for NodeRecords<C, H>
+where
+    C: Compound<H>,
+    H: ByteHash,
+{
+    type Item = io::Result<NodeRecord<H>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let digest = self.stack.pop()?;
+        match self.record(digest) {
+            Ok(record) => Some(Ok(record)),
+            Err(e) => {
+                // nothing more can be found below a failing node
+                self.stack.clear();
+                Some(Err(e))
+            }
+        }
+    }
+}
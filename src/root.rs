@@ -1,21 +1,128 @@
-use std::fs::File;
-use std::io::{self, Read, Write};
+use std::error;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read};
 use std::marker::PhantomData;
 use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use atomicwrites::{AllowOverwrite, AtomicFile};
+use fs2::FileExt;
 
+use crate::backend::{DirRoots, RootStore};
 use crate::{content::Content, ByteHash, Snapshot, Store};
 
+const LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+// The name of the root in the registry of the store
+const ROOT: &str = "root";
+
 /// Type to keep track of the root of a state tree.
 ///
-/// The latest snapshot is saved between program runs.
+/// The latest snapshot is saved between program runs, as the root named
+/// "root" in the registry of the store, see `Store::roots`.
 pub struct Root<T: Content<H>, H: ByteHash> {
-    path: PathBuf,
     store: Store<H>,
+    roots: DirRoots<H>,
     _marker: PhantomData<T>,
 }
 
+/// Error returned when the root was advanced by another writer
+pub struct RootConflict<H: ByteHash> {
+    current: Option<H::Digest>,
+    root: Option<String>,
+}
+
+impl<H: ByteHash> RootConflict<H> {
+    pub(crate) fn new(current: Option<H::Digest>) -> Self {
+        RootConflict {
+            current,
+            root: None,
+        }
+    }
+
+    pub(crate) fn named(root: &str, current: Option<H::Digest>) -> Self {
+        RootConflict {
+            current,
+            root: Some(root.into()),
+        }
+    }
+
+    /// The name of the root in conflict, for conflicts on named roots
+    pub fn root(&self) -> Option<&str> {
+        self.root.as_deref()
+    }
+
+    /// The digest of the root that is actually current, if any
+    pub fn current(&self) -> Option<&H::Digest> {
+        self.current.as_ref()
+    }
+}
+
+impl<H: ByteHash> fmt::Debug for RootConflict<H> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RootConflict(")?;
+        match self.current {
+            Some(ref digest) => {
+                for byte in digest.as_ref() {
+                    write!(f, "{:02x}", byte)?;
+                }
+            }
+            None => write!(f, "None")?,
+        }
+        write!(f, ")")
+    }
+}
+
+impl<H: ByteHash> fmt::Display for RootConflict<H> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Root was advanced by another writer")
+    }
+}
+
+impl<H: ByteHash> error::Error for RootConflict<H> {}
+
+/// Advisory lock on a file guarding the roots, or the files of a store,
+/// released on drop
+///
+/// The lock is held by the OS for as long as the file is open, so it is
+/// released when a process holding it crashes. The file itself is left in
+/// place.
+pub(crate) struct RootLock(File);
+
+impl RootLock {
+    pub(crate) fn acquire(path: PathBuf) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(&path)?;
+        let contended = fs2::lock_contended_error().kind();
+        let start = Instant::now();
+        loop {
+            match file.try_lock_exclusive() {
+                Ok(()) => return Ok(RootLock(file)),
+                Err(ref e) if e.kind() == contended => {
+                    if start.elapsed() > LOCK_TIMEOUT {
+                        return Err(io::Error::new(
+                            io::ErrorKind::TimedOut,
+                            "Timed out waiting for root lock",
+                        ));
+                    }
+                    thread::sleep(Duration::from_millis(1));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl Drop for RootLock {
+    fn drop(&mut self) {
+        let _ = self.0.unlock();
+    }
+}
+
 impl<T, H> Root<T, H>
 where
     T: Content<H> + Default,
@@ -25,24 +132,34 @@ where
     pub fn new<P: Into<PathBuf>>(path: P) -> io::Result<Self> {
         let path = path.into();
         let store = Store::new(&path)?;
+        let roots = DirRoots::open(path.join("roots"))?;
+
+        // roots used to be kept in a file of their own
+        let legacy = path.join("root");
+        if legacy.exists() {
+            let mut digest = H::Digest::default();
+            File::open(&legacy)?.read_exact(digest.as_mut())?;
+            let _ = roots.compare_and_set(ROOT, None, &digest)?;
+            fs::remove_file(legacy)?;
+        }
 
         Ok(Root {
-            path,
             store,
+            roots,
             _marker: PhantomData,
         })
     }
 
+    /// Returns the digest of the latest state of the Root, if any
+    pub fn current(&self) -> io::Result<Option<H::Digest>> {
+        self.roots.get(ROOT)
+    }
+
     /// Restore the latest state of the Root.
     pub fn restore(&self) -> io::Result<T> {
-        let root_file_path = self.path.join("root");
-        if root_file_path.exists() {
-            let mut file = File::open(root_file_path)?;
-            let mut hash = H::Digest::default();
-            file.read_exact(hash.as_mut())?;
-            self.store.get_hash(&hash)
-        } else {
-            Ok(T::default())
+        match self.current()? {
+            Some(hash) => self.store.get_hash(&hash),
+            None => Ok(T::default()),
         }
     }
 
@@ -51,11 +168,37 @@ where
     pub fn set_root(&mut self, t: &mut T) -> io::Result<Snapshot<T, H>> {
         let snapshot = self.store.persist(t)?;
         self.store.flush()?;
-        let root_file_path = self.path.join("root");
-        let af = AtomicFile::new(root_file_path, AllowOverwrite);
-        af.write(|f| f.write_all(snapshot.as_bytes()))?;
+        self.roots.set(ROOT, snapshot.hash())?;
         Ok(snapshot)
     }
+
+    /// Set the latest state of the Root, only if the current root is still
+    /// `expected` (`None` meaning no root has been set yet).
+    ///
+    /// Returns a `RootConflict` if another writer advanced the root first.
+    /// The root is set under the lock of the registry, and the nodes are
+    /// flushed under the lock of the store, both released should the writer
+    /// crash, so that several `Root`s, also in different processes, can
+    /// share a directory as long as all of them commit through this method.
+    pub fn compare_and_set_root(
+        &mut self,
+        expected: Option<&H::Digest>,
+        t: &mut T,
+    ) -> io::Result<Result<Snapshot<T, H>, RootConflict<H>>> {
+        // fails early, rather than persisting for nothing
+        let current = self.current()?;
+        if current.as_ref() != expected {
+            return Ok(Err(RootConflict::new(current)));
+        }
+
+        let snapshot = self.store.persist(t)?;
+        self.store.flush()?;
+        Ok(self
+            .roots
+            .compare_and_set(ROOT, expected, snapshot.hash())?
+            .map(|()| snapshot)
+            .map_err(|conflict| RootConflict::new(conflict.current)))
+    }
 }
 
 #[cfg(test)]
@@ -90,4 +233,70 @@ mod test {
             assert_eq!(restored, 42);
         }
     }
+
+    #[test]
+    fn compare_and_set() {
+        let dir = tempdir().unwrap();
+
+        let mut a = Root::<u64, Blake2b>::new(dir.path()).unwrap();
+        let first = a.compare_and_set_root(None, &mut 1).unwrap().unwrap();
+
+        // `b` is created after the first commit, and advances the root
+        let mut b = Root::<u64, Blake2b>::new(dir.path()).unwrap();
+        assert!(b.compare_and_set_root(None, &mut 2).unwrap().is_err());
+        let second = b
+            .compare_and_set_root(Some(first.hash()), &mut 2)
+            .unwrap()
+            .unwrap();
+
+        // `a` is now behind
+        match a.compare_and_set_root(Some(first.hash()), &mut 3).unwrap() {
+            Err(conflict) => assert!(conflict.current() == Some(second.hash())),
+            Ok(_) => panic!("stale root was overwritten"),
+        }
+
+        // retry on top of the current root
+        let current = a.current().unwrap();
+        a.compare_and_set_root(current.as_ref(), &mut 3)
+            .unwrap()
+            .unwrap();
+
+        let restored = Root::<u64, Blake2b>::new(dir.path())
+            .unwrap()
+            .restore()
+            .unwrap();
+        assert_eq!(restored, 3);
+    }
+
+    #[test]
+    fn legacy_root_file() {
+        let dir = tempdir().unwrap();
+        let digest = {
+            let store = Store::<Blake2b>::new(dir.path()).unwrap();
+            let snapshot = store.persist(&mut 7u64).unwrap();
+            store.flush().unwrap();
+            *snapshot.hash()
+        };
+        std::fs::write(dir.path().join("root"), digest).unwrap();
+
+        let root = Root::<u64, Blake2b>::new(dir.path()).unwrap();
+        assert_eq!(root.restore().unwrap(), 7);
+        assert!(!dir.path().join("root").exists());
+        let roots = DirRoots::<Blake2b>::open(dir.path().join("roots"));
+        assert_eq!(roots.unwrap().get(ROOT).unwrap(), Some(digest));
+    }
+
+    #[test]
+    fn stale_lock_files() {
+        let dir = tempdir().unwrap();
+
+        // left behind by a writer that crashed while committing
+        fs::create_dir_all(dir.path().join("roots")).unwrap();
+        File::create(dir.path().join("roots").join(".lock")).unwrap();
+        File::create(dir.path().join(".lock")).unwrap();
+
+        let mut root = Root::<u64, Blake2b>::new(dir.path()).unwrap();
+        root.compare_and_set_root(None, &mut 1).unwrap().unwrap();
+        assert_eq!(root.restore().unwrap(), 1);
+    }
 }
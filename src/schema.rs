@@ -0,0 +1,246 @@
+use std::any;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::hash::Hasher;
+use std::io;
+
+use crate::content::Content;
+use crate::sink::Sink;
+use crate::source::Source;
+use crate::ByteHash;
+
+// The input hashed to tell hashers apart
+const FINGERPRINT: &[u8] = b"kelvin schema";
+
+/// The type, hasher and encoding version of a collection
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Schema {
+    /// The name of the type of the collection, as chosen by the application
+    pub name: String,
+    /// The hasher, as the hex digest it computes of a fixed input
+    pub hasher: String,
+    /// The version of the encoding, as chosen by the application
+    pub version: u32,
+}
+
+impl Schema {
+    /// Returns the schema of collections named `name`, hashed with `H`
+    ///
+    /// The name is recorded with the roots, and must not change with the
+    /// Rust type of the collection being renamed or moved.
+    pub fn new<H: ByteHash>(name: &str, version: u32) -> Self {
+        let mut state = H::state();
+        state.write(FINGERPRINT);
+        let hasher = state
+            .fin()
+            .as_ref()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        Schema {
+            name: name.into(),
+            hasher,
+            version,
+        }
+    }
+}
+
+impl<H: ByteHash> Content<H> for Schema {
+    fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
+        self.name.persist(sink)?;
+        self.hasher.persist(sink)?;
+        self.version.persist(sink)
+    }
+
+    fn restore(source: &mut Source<H>) -> io::Result<Self> {
+        Ok(Schema {
+            name: String::restore(source)?,
+            hasher: String::restore(source)?,
+            version: u32::restore(source)?,
+        })
+    }
+}
+
+/// The collections an application expects, by root name
+#[derive(Clone, Debug, Default)]
+pub struct Registry {
+    schemas: BTreeMap<String, Schema>,
+    // the Rust types registered, only compared within a process
+    types: BTreeMap<String, &'static str>,
+}
+
+impl Registry {
+    /// Creates an empty registry
+    pub fn new() -> Self {
+        Registry::default()
+    }
+
+    /// Registers the root `root` as a collection of type `T` named `name`,
+    /// hashed with `H` and encoded in version `version`
+    ///
+    /// Stored roots are checked against the name, roots set through a
+    /// validated namespace are checked against `T` as well.
+    pub fn register<T: Content<H>, H: ByteHash>(
+        &mut self,
+        root: &str,
+        name: &str,
+        version: u32,
+    ) -> &mut Self {
+        self.schemas
+            .insert(root.into(), Schema::new::<H>(name, version));
+        self.types.insert(root.into(), any::type_name::<T>());
+        self
+    }
+
+    /// Returns the schema registered for `root`
+    pub fn get(&self, root: &str) -> Option<&Schema> {
+        self.schemas.get(root)
+    }
+
+    // Returns the mismatches of writing a `T` hashed with `H` as `root`
+    pub(crate) fn check<T: Content<H>, H: ByteHash>(
+        &self,
+        root: &str,
+    ) -> Vec<Mismatch> {
+        let expected = match self.schemas.get(root) {
+            Some(expected) => expected,
+            None => return vec![],
+        };
+        let mut mismatches = vec![];
+        let registered = self.types.get(root).copied().unwrap_or_default();
+        let found = any::type_name::<T>();
+        if registered != found {
+            mismatches.push(Mismatch::Type {
+                root: root.into(),
+                expected: registered.into(),
+                found: found.into(),
+            })
+        }
+        let schema = Schema::new::<H>(&expected.name, expected.version);
+        mismatches.extend(Mismatch::between(root, expected, &schema));
+        mismatches
+    }
+
+    /// Returns the registered roots and their schemas, in order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Schema)> {
+        self.schemas
+            .iter()
+            .map(|(root, schema)| (root.as_str(), schema))
+    }
+}
+
+/// A difference between a stored collection and its registered schema
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Mismatch {
+    /// The collection was stored with another type
+    Type {
+        /// The root of the collection
+        root: String,
+        /// The registered type
+        expected: String,
+        /// The stored type
+        found: String,
+    },
+    /// The collection was stored with another hasher
+    Hasher {
+        /// The root of the collection
+        root: String,
+        /// The registered hasher
+        expected: String,
+        /// The stored hasher
+        found: String,
+    },
+    /// The collection was stored in another encoding version
+    Version {
+        /// The root of the collection
+        root: String,
+        /// The registered version
+        expected: u32,
+        /// The stored version
+        found: u32,
+    },
+    /// The collection was stored without recording its schema
+    Unrecorded {
+        /// The root of the collection
+        root: String,
+    },
+}
+
+impl Mismatch {
+    /// Returns the differences between `found` and the `expected` schema
+    pub(crate) fn between(
+        root: &str,
+        expected: &Schema,
+        found: &Schema,
+    ) -> Vec<Mismatch> {
+        let mut mismatches = vec![];
+        if expected.name != found.name {
+            mismatches.push(Mismatch::Type {
+                root: root.into(),
+                expected: expected.name.clone(),
+                found: found.name.clone(),
+            })
+        }
+        if expected.hasher != found.hasher {
+            mismatches.push(Mismatch::Hasher {
+                root: root.into(),
+                expected: expected.hasher.clone(),
+                found: found.hasher.clone(),
+            })
+        }
+        if expected.version != found.version {
+            mismatches.push(Mismatch::Version {
+                root: root.into(),
+                expected: expected.version,
+                found: found.version,
+            })
+        }
+        mismatches
+    }
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Mismatch::Type {
+                root,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{}: expected type {}, found {}",
+                root, expected, found
+            ),
+            Mismatch::Hasher {
+                root,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{}: expected hasher {}, found {}",
+                root, expected, found
+            ),
+            Mismatch::Version {
+                root,
+                expected,
+                found,
+            } if found < expected => write!(
+                f,
+                "{}: outdated encoding version {}, expected {}",
+                root, found, expected
+            ),
+            Mismatch::Version {
+                root,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{}: unknown encoding version {}, expected {}",
+                root, found, expected
+            ),
+            Mismatch::Unrecorded { root } => {
+                write!(f, "{}: no schema recorded", root)
+            }
+        }
+    }
+}
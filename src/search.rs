@@ -1,3 +1,8 @@
+use std::borrow::Borrow;
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
+
+use crate::annotations::MaxKey;
 use crate::compound::Compound;
 use crate::handle::HandleType;
 use crate::ByteHash;
@@ -21,6 +26,12 @@ where
 {
     /// Select among the handles of the node, indexed from `offset`
     fn select(&mut self, compound: &C, offset: usize) -> SearchResult;
+
+    /// Returns true if the method searches from the last handle of each
+    /// node, `offset` and the selected index then counting from the end
+    fn reverse(&self) -> bool {
+        false
+    }
 }
 
 #[derive(Clone)]
@@ -46,3 +57,99 @@ where
         SearchResult::None
     }
 }
+
+/// Search method visiting the leaves from the last one
+#[derive(Clone)]
+pub struct Last;
+
+impl<C, H> Method<C, H> for Last
+where
+    H: ByteHash,
+    C: Compound<H>,
+{
+    fn select(&mut self, compound: &C, offset: usize) -> SearchResult {
+        let children = compound.children();
+        let end = children.len() - offset;
+        for (i, h) in children[..end].iter().rev().enumerate() {
+            match h.handle_type() {
+                HandleType::Leaf => return SearchResult::Leaf(i),
+                HandleType::Node => return SearchResult::Path(i),
+                HandleType::None => (),
+            }
+        }
+        SearchResult::None
+    }
+
+    fn reverse(&self) -> bool {
+        true
+    }
+}
+
+/// Search method visiting the leaves with keys in a range, in key order
+///
+/// Relies on the leaves being ordered by key, and on the `MaxKey`
+/// annotations of subtrees, to skip the subtrees below the range and stop at
+/// the first leaf above it. Searching a map that is not `KeyOrdered` misses
+/// leaves in the range.
+pub struct RangeSearch<K, O: ?Sized, R> {
+    range: R,
+    done: bool,
+    _marker: PhantomData<fn(&O) -> K>,
+}
+
+impl<K, O: ?Sized, R> RangeSearch<K, O, R> {
+    /// Creates a search for the leaves with keys in `range`
+    pub fn new(range: R) -> Self {
+        RangeSearch {
+            range,
+            done: false,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<C, K, O, R, H> Method<C, H> for RangeSearch<K, O, R>
+where
+    C: Compound<H>,
+    C::Annotation: Borrow<MaxKey<K>>,
+    K: Borrow<O>,
+    O: Ord + ?Sized,
+    R: RangeBounds<O>,
+    H: ByteHash,
+{
+    fn select(&mut self, compound: &C, offset: usize) -> SearchResult {
+        if self.done {
+            return SearchResult::None;
+        }
+        for (i, h) in compound.children()[offset..].iter().enumerate() {
+            if let Some(ann) = h.annotation() {
+                let max: &MaxKey<K> = (*ann).borrow();
+                let max: &O = (**max).borrow();
+                // the whole subtree is below the range
+                let below = match self.range.start_bound() {
+                    Bound::Included(start) => max < start,
+                    Bound::Excluded(start) => max <= start,
+                    Bound::Unbounded => false,
+                };
+                if below {
+                    continue;
+                }
+                if h.handle_type() == HandleType::Node {
+                    return SearchResult::Path(i);
+                }
+                let above = match self.range.end_bound() {
+                    Bound::Included(end) => max > end,
+                    Bound::Excluded(end) => max >= end,
+                    Bound::Unbounded => false,
+                };
+                if above {
+                    // leaves are ordered, nothing more to find
+                    self.done = true;
+                    return SearchResult::None;
+                }
+                return SearchResult::Leaf(i);
+            }
+        }
+        SearchResult::None
+    }
+}
@@ -0,0 +1,194 @@
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+
+use bytehash::State;
+
+use crate::compound::Compound;
+use crate::content::Content;
+use crate::iter::LeafIterable;
+use crate::portable::PortableHasher;
+use crate::root::Root;
+use crate::ByteHash;
+
+// Keeps shard routing independent of the key hashes used inside structures
+const SHARD_DOMAIN: &[u8] = b"kelvin-shard";
+
+/// Returns the shard `key` is routed to, out of `n` shards
+///
+/// Routing is by prefix of a domain-separated hash of the key, and is stable
+/// across runs and platforms.
+pub fn shard_of<K: Hash + ?Sized, H: ByteHash>(key: &K, n: usize) -> usize {
+    let mut state = PortableHasher::new(H::state());
+    state.write(SHARD_DOMAIN);
+    key.hash(&mut state);
+    let digest = state.into_inner().fin();
+
+    let mut prefix = [0u8; 8];
+    prefix.copy_from_slice(&digest.as_ref()[..8]);
+    (u64::from_be_bytes(prefix) % n as u64) as usize
+}
+
+/// A single logical structure split over a number of shards
+///
+/// Each shard lives in its own store, in a subdirectory of the sharded root,
+/// and keeps its own root. The combined root is the hash of all shard roots.
+pub struct Sharded<T: Content<H>, H: ByteHash> {
+    roots: Vec<Root<T, H>>,
+    states: Vec<T>,
+    dirty: Vec<bool>,
+}
+
+impl<T, H> Sharded<T, H>
+where
+    T: Content<H> + Default,
+    H: ByteHash,
+{
+    /// Opens, or creates, a structure split over `n` shards at `path`
+    ///
+    /// Returns an `InvalidInput` error if `path` holds a different number of
+    /// shards.
+    pub fn new<P: Into<PathBuf>>(path: P, n: usize) -> io::Result<Self> {
+        if n == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Number of shards must be non-zero",
+            ));
+        }
+        let path = path.into();
+        fs::create_dir_all(&path)?;
+
+        let count_path = path.join("shards");
+        if count_path.exists() {
+            let mut bytes = [0u8; 8];
+            File::open(&count_path)?.read_exact(&mut bytes)?;
+            if u64::from_be_bytes(bytes) != n as u64 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Number of shards does not match",
+                ));
+            }
+        } else {
+            let mut file = File::create(&count_path)?;
+            file.write_all(&(n as u64).to_be_bytes())?;
+            file.sync_all()?;
+        }
+
+        let mut roots = Vec::with_capacity(n);
+        let mut states = Vec::with_capacity(n);
+        for i in 0..n {
+            let root = Root::new(path.join(format!("shard-{}", i)))?;
+            states.push(root.restore()?);
+            roots.push(root);
+        }
+
+        Ok(Sharded {
+            roots,
+            states,
+            dirty: vec![false; n],
+        })
+    }
+
+    /// Returns the number of shards
+    pub fn shards(&self) -> usize {
+        self.states.len()
+    }
+
+    /// Returns the index of the shard `key` is routed to
+    pub fn shard_of<K: Hash + ?Sized>(&self, key: &K) -> usize {
+        shard_of::<K, H>(key, self.shards())
+    }
+
+    /// Returns a reference to the shard `key` is routed to
+    pub fn get<K: Hash + ?Sized>(&self, key: &K) -> &T {
+        &self.states[self.shard_of(key)]
+    }
+
+    /// Returns a mutable reference to the shard `key` is routed to
+    pub fn get_mut<K: Hash + ?Sized>(&mut self, key: &K) -> &mut T {
+        let i = self.shard_of(key);
+        self.dirty[i] = true;
+        &mut self.states[i]
+    }
+
+    /// Returns the states of all shards, in shard order
+    pub fn states(&self) -> &[T] {
+        &self.states
+    }
+
+    /// Sets the roots of all modified shards, and returns the combined root
+    pub fn commit(&mut self) -> io::Result<H::Digest> {
+        for (i, dirty) in self.dirty.iter_mut().enumerate() {
+            if *dirty {
+                self.roots[i].set_root(&mut self.states[i])?;
+                *dirty = false;
+            }
+        }
+        self.combined_root()
+    }
+
+    /// Returns the hash of the current roots of all shards
+    ///
+    /// Shards that were never committed contribute an all-zero digest.
+    pub fn combined_root(&self) -> io::Result<H::Digest> {
+        let mut state = H::state();
+        for root in &self.roots {
+            let digest = root.current()?.unwrap_or_default();
+            state.write(digest.as_ref());
+        }
+        Ok(state.fin())
+    }
+}
+
+impl<T, H> Sharded<T, H>
+where
+    T: Compound<H> + Default,
+    H: ByteHash,
+{
+    /// Iterates over the leaves of all shards, shard by shard
+    pub fn iter(&self) -> impl Iterator<Item = io::Result<&T::Leaf>> + '_ {
+        self.states.iter().flat_map(LeafIterable::iter)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tests::tempfile::tempdir;
+    use crate::Blake2b;
+
+    #[test]
+    fn routing_is_stable_and_spread() {
+        let mut counts = [0; 4];
+        for i in 0u64..1024 {
+            let shard = shard_of::<_, Blake2b>(&i, 4);
+            assert_eq!(shard, shard_of::<_, Blake2b>(&i, 4));
+            counts[shard] += 1;
+        }
+        for count in counts.iter() {
+            assert!(*count > 128);
+        }
+    }
+
+    #[test]
+    fn commit_and_reopen() {
+        let dir = tempdir().unwrap();
+
+        let combined = {
+            let mut sharded =
+                Sharded::<u64, Blake2b>::new(dir.path(), 4).unwrap();
+            for key in 0u64..16 {
+                *sharded.get_mut(&key) += key;
+            }
+            sharded.commit().unwrap()
+        };
+
+        let sharded = Sharded::<u64, Blake2b>::new(dir.path(), 4).unwrap();
+        assert!(sharded.combined_root().unwrap() == combined);
+        assert_eq!(sharded.states().iter().sum::<u64>(), (0..16).sum());
+
+        let err = Sharded::<u64, Blake2b>::new(dir.path(), 3).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
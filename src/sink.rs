@@ -3,6 +3,8 @@ use std::io;
 
 use bytehash::{ByteHash, State};
 
+#[cfg(feature = "compression")]
+use crate::compression::{self, Dictionary};
 use crate::store::Store;
 
 pub trait SinkTrait<H: ByteHash>
@@ -12,10 +14,69 @@ where
     fn recur(&self) -> Sink<H>;
 }
 
+/// The format version of the nodes written
+///
+/// Bumped whenever the encoding of a type in kelvin changes, so that nodes
+/// written earlier can still be read, see `Source::version`.
+pub const FORMAT_VERSION: u8 = 2;
+
+// Leads the format version at the start of every node. Nodes written before
+// format versions, version 0, have no header.
+pub(crate) const MAGIC: u8 = 0xce;
+
+// Follows `MAGIC` in place of the format version in nodes stored compressed
+#[cfg(feature = "compression")]
+pub(crate) const COMPRESSED: u8 = 0xff;
+
+// The last format version without a domain in the header
+pub(crate) const UNSEPARATED: u8 = 1;
+
+/// The kind of content a node holds
+///
+/// Since format version 2 the domain follows the version in the header of
+/// every node, and is hashed along with the rest of it, so that content
+/// persisted on its own can never hash to the same digest as a node of a
+/// structure, nor be restored as one. The domain follows from what the node
+/// holds rather than from its type: nodes holding handles, as those of every
+/// `Compound` do, are in the node domain, anything else is a leaf.
+///
+/// Annotations have no domain of their own, as they are never hashed on
+/// their own, only as part of the node holding the handles they annotate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Domain {
+    /// Leaves, and any other content persisted on its own
+    Leaf,
+    /// Internal nodes of structures
+    Node,
+}
+
+impl Domain {
+    pub(crate) fn tag(self) -> u8 {
+        match self {
+            Domain::Leaf => 0,
+            Domain::Node => 1,
+        }
+    }
+
+    pub(crate) fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            0 => Some(Domain::Leaf),
+            1 => Some(Domain::Node),
+            _ => None,
+        }
+    }
+}
+
 /// A sink for bytes, used in implementing `Content`
 pub struct Sink<'a, H: ByteHash> {
     bytes: Vec<u8>,
     store: &'a Store<H>,
+    version: u8,
+    // whether the node starts with a format header
+    tagged: bool,
+    // the dictionary to store the node compressed with, if any
+    #[cfg(feature = "compression")]
+    dictionary: Option<Dictionary>,
 }
 
 impl<'a, H: ByteHash> Sink<'a, H> {
@@ -23,23 +84,96 @@ impl<'a, H: ByteHash> Sink<'a, H> {
         Sink {
             bytes: vec![],
             store,
+            version: version(store),
+            tagged: false,
+            #[cfg(feature = "compression")]
+            dictionary: None,
+        }
+    }
+
+    // A sink for a node, starting with the format header
+    //
+    // The node is a leaf until a handle is written to it, see `mark_node`.
+    // Stores with domain separation turned off write nodes as they were
+    // before format versions, without a header, reproducing the digests of
+    // old roots.
+    pub(crate) fn node(store: &'a Store<H>) -> Self {
+        let version = version(store);
+        let tagged = version == FORMAT_VERSION;
+        let bytes = if tagged {
+            vec![MAGIC, FORMAT_VERSION, Domain::Leaf.tag()]
+        } else {
+            vec![]
+        };
+        Sink {
+            bytes,
+            store,
+            version,
+            tagged,
+            #[cfg(feature = "compression")]
+            dictionary: None,
         }
     }
 
+    /// Returns the format version of the node being written
+    ///
+    /// `Content` implementations that changed their encoding check this to
+    /// keep writing what earlier versions read, see `Source::version`.
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
     pub(crate) fn store(&self) -> &Store<H> {
         self.store
     }
 
+    // Puts the node in the node domain, as a handle is written to it
+    pub(crate) fn mark_node(&mut self) {
+        if self.tagged {
+            self.bytes[2] = Domain::Node.tag();
+        }
+    }
+
+    pub(crate) fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    // Stores the node compressed with `dictionary`, unless written without
+    // a header
+    #[cfg(feature = "compression")]
+    pub(crate) fn compress_with(&mut self, dictionary: &Dictionary) {
+        if self.version != 0 {
+            self.dictionary = Some(dictionary.clone());
+        }
+    }
+
+    // Stores the node, under the digest of its uncompressed bytes
     pub(crate) fn fin(self) -> io::Result<H::Digest> {
-        let Sink { bytes, .. } = self;
         let mut hasher = H::state();
-        hasher.write(&bytes);
+        hasher.write(&self.bytes);
         let hash = hasher.fin();
-        self.store.put(hash, bytes)?;
+        #[cfg(feature = "compression")]
+        {
+            if let Some(ref dictionary) = self.dictionary {
+                let stored = compression::compress(&self.bytes, dictionary)?;
+                self.store.put(hash, stored)?;
+                return Ok(hash);
+            }
+        }
+        self.store.put(hash, self.bytes)?;
         Ok(hash)
     }
 }
 
+// The format version of the nodes written to `store`
+fn version<H: ByteHash>(store: &Store<H>) -> u8 {
+    if store.domain_separation() {
+        FORMAT_VERSION
+    } else {
+        0
+    }
+}
+
 impl<'a, H> SinkTrait<H> for Sink<'a, H>
 where
     H: ByteHash,
@@ -1,27 +1,162 @@
 use std::io::{self, Read};
 
 use bytehash::ByteHash;
+use byteorder::{BigEndian, ReadBytesExt};
 
+use crate::error::Error;
+use crate::sink::{Domain, FORMAT_VERSION, MAGIC, UNSEPARATED};
 use crate::store::Store;
 
 /// A source of bytes, used in implementing `Content`
 pub struct Source<'a, H: ByteHash> {
     read: Box<dyn Read + 'a>,
     store: &'a Store<H>,
+    version: u8,
+    domain: Option<Domain>,
+    // whether a handle was read from the node
+    node: bool,
+    // a byte read ahead, to be read again
+    peeked: Option<u8>,
 }
 
 impl<'a, H: ByteHash> Source<'a, H> {
     pub(crate) fn new(read: Box<dyn Read + 'a>, store: &'a Store<H>) -> Self {
-        Source { read, store }
+        Source {
+            read,
+            store,
+            version: FORMAT_VERSION,
+            domain: None,
+            node: false,
+            peeked: None,
+        }
     }
 
     pub(crate) fn store(&self) -> &Store<H> {
         &self.store
     }
+
+    // Reads the format header at the start of a node
+    //
+    // Nodes without one were written before format versions, and are read
+    // as version 0, from their first byte on. Those and the nodes of format
+    // version 1 have no domain, and are only read by stores reading legacy
+    // nodes, see `Store::set_legacy_reads`.
+    pub(crate) fn read_header(&mut self) -> io::Result<()> {
+        let legacy = self.store.legacy_reads();
+        let mut byte = [0u8];
+        self.read_exact(&mut byte)?;
+        if byte[0] != MAGIC {
+            if !legacy {
+                return Err(Error::InvalidEncoding("Untagged node").into());
+            }
+            self.version = 0;
+            self.peeked = Some(byte[0]);
+            return Ok(());
+        }
+        self.read_exact(&mut byte)?;
+        match byte[0] {
+            0 => Err(Error::InvalidEncoding("Invalid format header").into()),
+            version if version > FORMAT_VERSION => {
+                Err(Error::UnsupportedVersion(version).into())
+            }
+            version if version <= UNSEPARATED && !legacy => {
+                Err(Error::InvalidEncoding("Untagged node").into())
+            }
+            version => {
+                self.version = version;
+                if version > UNSEPARATED {
+                    self.read_exact(&mut byte)?;
+                    self.domain = Some(Domain::from_tag(byte[0]).ok_or(
+                        Error::InvalidEncoding("Invalid node domain"),
+                    )?);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    // Reads the node as written before format versions, without a header
+    pub(crate) fn legacy(&mut self) {
+        self.version = 0;
+    }
+
+    // Records that a handle was read, which puts the node read in the node
+    // domain
+    pub(crate) fn mark_node(&mut self) {
+        self.node = true;
+    }
+
+    // Returns true if the node read is in the domain of what was read from
+    // it
+    //
+    // Nodes written before format version 2 have no domain, and are read as
+    // any.
+    pub(crate) fn in_domain(&self) -> bool {
+        let read = if self.node {
+            Domain::Node
+        } else {
+            Domain::Leaf
+        };
+        self.domain.map_or(true, |domain| domain == read)
+    }
+
+    /// Returns the format version of the node being read
+    ///
+    /// `Content` implementations changing their encoding check this to keep
+    /// reading what earlier versions wrote.
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// Returns the domain of the node being read, `None` for nodes written
+    /// before format version 2
+    pub fn domain(&self) -> Option<Domain> {
+        self.domain
+    }
+
+    /// Reads bytes prefixed with their length, as a big endian `u32`
+    ///
+    /// Lengths over `max` fail with an `InvalidData` error, and bytes cut
+    /// short with an `UnexpectedEof` error.
+    pub fn read_len_prefixed(&mut self, max: usize) -> io::Result<Vec<u8>> {
+        read_len_prefixed(self, max)
+    }
+}
+
+// Reads bytes prefixed with their length from `reader`, see
+// `Source::read_len_prefixed`
+pub(crate) fn read_len_prefixed<R: Read>(
+    reader: &mut R,
+    max: usize,
+) -> io::Result<Vec<u8>> {
+    let len = reader.read_u32::<BigEndian>()? as usize;
+    if len > max {
+        return Err(
+            Error::InvalidEncoding("Length prefix over the limit").into()
+        );
+    }
+    // the length is not trusted to allocate up front
+    let mut bytes = vec![];
+    Read::take(&mut *reader, len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "Truncated length prefixed bytes",
+        ));
+    }
+    Ok(bytes)
 }
 
 impl<'a, H: ByteHash> Read for Source<'a, H> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(byte) = self.peeked {
+            if buf.is_empty() {
+                return Ok(0);
+            }
+            buf[0] = byte;
+            self.peeked = None;
+            return Ok(1);
+        }
         self.read.read(buf)
     }
 }
@@ -0,0 +1,97 @@
+use std::collections::HashSet;
+use std::io;
+
+use tempfile::TempDir;
+
+use crate::backend::DiskBackend;
+use crate::compound::Compound;
+use crate::content::Content;
+use crate::gc::Reach;
+use crate::store::{Snapshot, Store};
+use crate::ByteHash;
+
+/// A temporary on-disk staging area for transactions too large to be kept
+/// in memory until committed
+///
+/// Bulk imports can `spill` the structure they are building every so often,
+/// moving its modified nodes out of memory into the staging area. Nothing
+/// reaches the target store until `commit`, which copies the staged nodes
+/// it needs in one go, so an import never leaves intermediate roots behind.
+/// The staging area is removed when dropped.
+pub struct Spill<H: ByteHash> {
+    store: Store<H>,
+    staging: Store<H>,
+    // dropped after the staging store, which lives in it
+    _dir: TempDir,
+}
+
+impl<H: ByteHash> Spill<H> {
+    /// Creates a staging area for a transaction against `store`
+    pub fn new(store: &Store<H>) -> io::Result<Self> {
+        let dir = TempDir::new()?;
+        Ok(Spill {
+            store: store.clone(),
+            staging: Store::layered(DiskBackend::new(dir.path())?, store),
+            _dir: dir,
+        })
+    }
+
+    /// Moves the nodes of `root` modified in memory to the staging area
+    ///
+    /// The nodes are read back from disk when accessed again, and only
+    /// nodes modified since are kept in memory. Nodes not staged are read
+    /// from the target store.
+    pub fn spill<C: Compound<H>>(&self, root: &mut C) -> io::Result<()> {
+        let snapshot = self.staging.persist(root)?;
+        self.staging.flush()?;
+        *root = snapshot.restore()?;
+        Ok(())
+    }
+
+    /// Returns the approximate number of bytes staged
+    pub fn staged(&self) -> usize {
+        self.staging.size()
+    }
+
+    /// Commits `root` to the target store, returning its snapshot there
+    ///
+    /// Copies the staged nodes reached from `root` into the target store,
+    /// and flushes it as a single commit, leaving `root` reading from it.
+    /// Nodes `root` shares with structures already in the target store are
+    /// not copied again.
+    pub fn commit<C: Compound<H>>(
+        self,
+        root: &mut C,
+    ) -> io::Result<Snapshot<C, H>> {
+        let snapshot = self.staging.persist(root)?;
+        Commit {
+            staging: &self.staging,
+            store: &self.store,
+            seen: HashSet::new(),
+        }
+        .node::<C>(snapshot.hash())?;
+        self.store.flush()?;
+        let snapshot = Snapshot::new(*snapshot.hash(), &self.store);
+        *root = snapshot.restore()?;
+        Ok(snapshot)
+    }
+}
+
+// Copies the staged nodes reached into the target store
+struct Commit<'a, H: ByteHash> {
+    staging: &'a Store<H>,
+    store: &'a Store<H>,
+    seen: HashSet<H::Digest>,
+}
+
+impl<'a, H: ByteHash> Reach<H> for Commit<'a, H> {
+    fn node<T: Content<H>>(&mut self, hash: &H::Digest) -> io::Result<()> {
+        // nodes in the target store have their whole subtree there
+        if !self.seen.insert(*hash) || self.store.contains(hash) {
+            return Ok(());
+        }
+        let (value, bytes) = self.staging.read_raw::<T>(hash)?;
+        value.reach(self)?;
+        self.store.put(*hash, bytes).map(|_| ())
+    }
+}
@@ -1,32 +1,134 @@
+use std::collections::HashMap;
+#[cfg(feature = "filesystem")]
+use std::fs;
+use std::hash::Hasher;
+use std::io::{Cursor, Read};
 use std::marker::PhantomData;
 use std::ops::Deref;
-use std::path::PathBuf;
-use std::sync::Arc;
+#[cfg(feature = "filesystem")]
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+use std::thread;
 use std::{fmt, io};
 
 use arrayvec::ArrayVec;
-use bytehash::ByteHash;
+use bytehash::{ByteHash, State};
 use cache::Cache;
-use parking_lot::RwLock;
+use parking_lot::{Condvar, Mutex, RwLock};
 
+#[cfg(feature = "filesystem")]
+use crate::backend::DirRoots;
 use crate::backend::{Backend, Ephemeral, Persistant, PutResult};
+use crate::compound::Compound;
+#[cfg(feature = "compression")]
+use crate::compression::{self, Dictionary};
 use crate::content::Content;
-use crate::sink::Sink;
+use crate::control::Control;
+use crate::error::Error;
+use crate::eviction::EvictionPolicy;
+use crate::freeze::{Freeze, Frozen};
+use crate::gc::LiveSet;
+#[cfg(feature = "parallel")]
+use crate::handle::Handle;
+use crate::partition::Partition;
+use crate::prefetch::Prefetcher;
+use crate::records::NodeRecords;
+#[cfg(feature = "filesystem")]
+use crate::root::RootLock;
+use crate::search::{Method, SearchResult};
+use crate::sink::{Sink, MAGIC};
 use crate::source::Source;
+use crate::transaction::Transaction;
 
 /// The main store type, wrapping backend and cache functionality
 #[derive(Clone)]
-pub struct Store<H: ByteHash>(Arc<StoreInner<H>>);
+pub struct Store<H: ByteHash>(
+    Arc<StoreInner<H>>,
+    Option<Arc<Partition<H::Digest>>>,
+);
 
 unsafe impl<H: ByteHash> Send for Store<H> {}
 unsafe impl<H: ByteHash> Sync for Store<H> {}
 
+type Pending<D> = HashMap<D, Arc<[u8]>>;
+
 const GENERATIONS: usize = 8;
 
 pub struct StoreInner<H: ByteHash> {
     generations: ArrayVec<[RwLock<Box<dyn Backend<H>>>; GENERATIONS]>,
     #[allow(unused)]
     cache: Cache<H::Digest>,
+    pins: Mutex<HashMap<H::Digest, usize>>,
+    // nodes retained by garbage collection without being reached, such as
+    // the chunks of state sync, with the number of holders of each
+    held: Mutex<HashMap<H::Digest, usize>>,
+    inflight: Mutex<HashMap<H::Digest, Arc<Flight>>>,
+    prefetcher: Prefetcher<H::Digest>,
+    preloaded: RwLock<HashMap<H::Digest, Arc<[u8]>>>,
+    partitions: Mutex<HashMap<String, Arc<Partition<H::Digest>>>>,
+    repairs: AtomicUsize,
+    max_leaf: AtomicUsize,
+    separated: AtomicBool,
+    // whether nodes without a domain are read
+    legacy: AtomicBool,
+    prefetch_budget: AtomicUsize,
+    // nodes written while the store is being relocated
+    relocating: RwLock<Option<Pending<H::Digest>>>,
+    // the directory of the registries of roots handed out, moved along with
+    // the store
+    #[cfg(feature = "filesystem")]
+    roots: Mutex<Option<Arc<RwLock<PathBuf>>>>,
+    // held for writing by garbage collection, and for reading by writers
+    collecting: RwLock<()>,
+    #[cfg(feature = "compression")]
+    dictionaries: RwLock<HashMap<u32, Dictionary>>,
+    archival: bool,
+    // store read from for nodes missing from this one
+    below: Option<Store<H>>,
+}
+
+// A fetch of a node from the backend, shared by all concurrent readers
+#[derive(Default)]
+struct Flight {
+    // whether the fetch finished, and the bytes of the node if it succeeded
+    result: Mutex<(bool, Option<Arc<[u8]>>)>,
+    done: Condvar,
+}
+
+impl Flight {
+    fn wait(&self) -> Option<Arc<[u8]>> {
+        let mut result = self.result.lock();
+        while !result.0 {
+            self.done.wait(&mut result);
+        }
+        result.1.clone()
+    }
+}
+
+/// Which nodes of a tree `Store::preload` loads
+pub enum PreloadPolicy<'a, C, H> {
+    /// The nodes in the upper `n` levels of the tree, `1` being the root
+    Depth(usize),
+    /// The nodes on the paths followed by the given searches
+    Hot(Vec<Box<dyn Method<C, H> + 'a>>),
+}
+
+// Completes the flight of the reader that started it, also on failure
+struct Landing<'a, H: ByteHash> {
+    inner: &'a StoreInner<H>,
+    hash: H::Digest,
+    flight: Arc<Flight>,
+    bytes: Option<Arc<[u8]>>,
+}
+
+impl<'a, H: ByteHash> Drop for Landing<'a, H> {
+    fn drop(&mut self) {
+        self.inner.inflight.lock().remove(&self.hash);
+        *self.flight.result.lock() = (true, self.bytes.take());
+        self.flight.done.notify_all();
+    }
 }
 
 impl<H: ByteHash> fmt::Debug for Store<H> {
@@ -35,6 +137,11 @@ impl<H: ByteHash> fmt::Debug for Store<H> {
     }
 }
 
+// A store referred to by the workers prefetching from it
+struct WeakStore<H: ByteHash>(Weak<StoreInner<H>>);
+
+unsafe impl<H: ByteHash> Send for WeakStore<H> {}
+
 #[doc(hidden)]
 pub struct Shared<T, H: ByteHash>(T, PhantomData<H>);
 
@@ -57,7 +164,8 @@ impl<T: Content<H>, H: ByteHash> Snapshot<T, H> {
         }
     }
 
-    pub(crate) fn restore(&self) -> io::Result<T> {
+    /// Restores the value from the store it was persisted to
+    pub fn restore(&self) -> io::Result<T> {
         self.store.restore(self)
     }
 
@@ -69,6 +177,50 @@ impl<T: Content<H>, H: ByteHash> Snapshot<T, H> {
     pub(crate) fn as_bytes(&self) -> &[u8] {
         self.hash.as_ref()
     }
+
+    pub(crate) fn store(&self) -> &Store<H> {
+        &self.store
+    }
+
+    pub(crate) fn prefetch(&self) {
+        self.store.prefetch(&self.hash)
+    }
+
+    /// Pins the snapshot, see `Store::pin`
+    pub fn pin(&self) -> Pinned<H> {
+        self.store.pin(&self.hash)
+    }
+}
+
+/// Guard keeping a root pinned in its store, unpinned on drop
+pub struct Pinned<H: ByteHash> {
+    hash: H::Digest,
+    store: Store<H>,
+}
+
+impl<H: ByteHash> Pinned<H> {
+    /// Returns the digest of the pinned root
+    pub fn hash(&self) -> &H::Digest {
+        &self.hash
+    }
+}
+
+impl<H: ByteHash> fmt::Debug for Pinned<H> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Pinned")
+    }
+}
+
+impl<H: ByteHash> Drop for Pinned<H> {
+    fn drop(&mut self) {
+        let mut pins = self.store.0.pins.lock();
+        if let Some(count) = pins.get_mut(&self.hash) {
+            *count -= 1;
+            if *count == 0 {
+                pins.remove(&self.hash);
+            }
+        }
+    }
 }
 
 impl<N, H: ByteHash> Deref for Snapshot<N, H> {
@@ -82,33 +234,170 @@ impl<H: ByteHash> Store<H> {
     /// Creates a new Store at `path`
     pub fn new<P: Into<PathBuf>>(path: P) -> io::Result<Self> {
         let pers = Persistant::new(path)?;
-        let mut generations = ArrayVec::new();
-        generations.push(RwLock::new(Box::new(pers) as Box<dyn Backend<H>>));
+        Ok(Self::with_backend(Box::new(pers), false))
+    }
 
-        Ok(Store(Arc::new(StoreInner {
-            generations,
-            cache: Cache::new(32, 4096),
-        })))
+    /// Opens the Store at `path` in write-once archival mode
+    ///
+    /// Nothing is ever removed from an archival store, and the digest of
+    /// every node read is verified against its contents.
+    pub fn archival<P: Into<PathBuf>>(path: P) -> io::Result<Self> {
+        let pers = Persistant::new(path)?;
+        Ok(Self::with_backend(Box::new(pers), true))
     }
 
     /// Creates a new ephemeral (in-memory only) Store
     pub fn ephemeral() -> Self {
-        let pers = Ephemeral::new();
+        Self::with_backend(Box::new(Ephemeral::new()), false)
+    }
+
+    /// Creates a new Store on top of a custom `backend`
+    pub fn from_backend<B: Backend<H> + 'static>(backend: B) -> Self {
+        Self::with_backend(Box::new(backend), false)
+    }
+
+    /// Creates a new Store reading from several tiers of backends
+    ///
+    /// Nodes are written to the first, primary, tier, and read from the
+    /// first tier holding them. Every node read is verified, and nodes found
+    /// missing or corrupt in the primary tier are repaired from a lower tier,
+    /// see `repairs`. At most 8 tiers are supported.
+    pub fn tiered(tiers: Vec<Box<dyn Backend<H>>>) -> io::Result<Self> {
+        if tiers.is_empty() || tiers.len() > GENERATIONS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Unsupported number of tiers",
+            ));
+        }
+        let mut tiers = tiers.into_iter();
+        let mut store =
+            Self::with_backend(tiers.next().expect("non-empty"), false);
+        let inner = Arc::get_mut(&mut store.0).expect("unshared");
+        for tier in tiers {
+            inner.generations.push(RwLock::new(tier));
+        }
+        Ok(store)
+    }
+
+    /// Sets the size in bytes above which `Blob` values persisted to the
+    /// store are split into chunks
+    pub fn set_max_leaf_size(&self, size: usize) {
+        self.0.max_leaf.store(size, Ordering::Relaxed)
+    }
+
+    /// Returns the size in bytes above which `Blob` values are split into
+    /// chunks, unlimited by default
+    pub fn max_leaf_size(&self) -> usize {
+        self.0.max_leaf.load(Ordering::Relaxed)
+    }
+
+    /// Turns the domain separation of the nodes persisted on or off
+    ///
+    /// With domain separation off, nodes are written as they were before
+    /// format versions, without a header, and structures in their earlier
+    /// layouts, so that structures persisted to the store keep the digests
+    /// they had then, and old roots are reproduced. Such stores read the
+    /// nodes they write, see `set_legacy_reads`, and proofs of trees read
+    /// from them are encoded as they write.
+    pub fn set_domain_separation(&self, enabled: bool) {
+        self.0.separated.store(enabled, Ordering::Relaxed)
+    }
+
+    /// Returns true if the nodes persisted are domain separated, the default
+    pub fn domain_separation(&self) -> bool {
+        self.0.separated.load(Ordering::Relaxed)
+    }
+
+    /// Turns the reading of nodes without a domain on or off
+    ///
+    /// Nodes written before format version 2 have no domain, and could be a
+    /// leaf passed off as a node of a structure, or the other way around.
+    /// They fail to restore unless read on purpose, such as to migrate the
+    /// roots of an old store. Off by default.
+    pub fn set_legacy_reads(&self, enabled: bool) {
+        self.0.legacy.store(enabled, Ordering::Relaxed)
+    }
+
+    /// Returns true if nodes without a domain are read, always the case in
+    /// stores with domain separation turned off
+    pub fn legacy_reads(&self) -> bool {
+        self.0.legacy.load(Ordering::Relaxed) || !self.domain_separation()
+    }
+
+    /// Returns the number of nodes repaired in the primary tier, since the
+    /// store was created
+    pub fn repairs(&self) -> usize {
+        self.0.repairs.load(Ordering::Relaxed)
+    }
+
+    // Creates a store on top of `below`, reading from it the nodes missing
+    // from `backend`
+    pub(crate) fn layered<B: Backend<H> + 'static>(
+        backend: B,
+        below: &Store<H>,
+    ) -> Self {
+        let mut store = Self::with_backend(Box::new(backend), false);
+        Arc::get_mut(&mut store.0).expect("unshared").below =
+            Some(below.clone());
+        // nodes are encoded as they would be in `below`
+        store.set_max_leaf_size(below.max_leaf_size());
+        store.set_domain_separation(below.domain_separation());
+        store.set_legacy_reads(below.legacy_reads());
+        store
+    }
+
+    fn with_backend(backend: Box<dyn Backend<H>>, archival: bool) -> Self {
         let mut generations = ArrayVec::new();
-        generations.push(RwLock::new(Box::new(pers) as Box<dyn Backend<H>>));
+        generations.push(RwLock::new(backend));
 
-        Store(Arc::new(StoreInner {
-            generations,
-            cache: Cache::new(32, 4096),
-        }))
+        Store(
+            Arc::new(StoreInner {
+                generations,
+                cache: Cache::new(32, 4096),
+                pins: Default::default(),
+                held: Default::default(),
+                inflight: Default::default(),
+                prefetcher: Default::default(),
+                preloaded: Default::default(),
+                partitions: Default::default(),
+                repairs: AtomicUsize::new(0),
+                max_leaf: AtomicUsize::new(usize::MAX),
+                separated: AtomicBool::new(true),
+                legacy: AtomicBool::new(false),
+                prefetch_budget: AtomicUsize::new(0),
+                relocating: Default::default(),
+                #[cfg(feature = "filesystem")]
+                roots: Default::default(),
+                collecting: Default::default(),
+                #[cfg(feature = "compression")]
+                dictionaries: Default::default(),
+                archival,
+                below: None,
+            }),
+            None,
+        )
+    }
+
+    /// Returns true if the store is in write-once archival mode
+    ///
+    /// Garbage collection, compaction and any other operation removing data
+    /// must refuse to run on archival stores.
+    pub fn is_archival(&self) -> bool {
+        self.0.archival
     }
 
     /// Persists Content to the store, returning a Snapshot
+    ///
+    /// Nodes are written depth-first in child order, so both the digest and
+    /// the sequence of nodes written only depend on the content, not on how
+    /// it was built.
     pub fn persist<T: Content<H>>(
         &self,
         content: &mut T,
     ) -> io::Result<Snapshot<T, H>> {
-        let mut sink = Sink::new(self);
+        // nested persists, such as of blobs, hold the lock already
+        let _writing = self.0.collecting.read_recursive();
+        let mut sink = Sink::node(self);
         content.persist(&mut sink)?;
         Ok(Snapshot {
             hash: sink.fin()?,
@@ -117,7 +406,31 @@ impl<H: ByteHash> Store<H> {
         })
     }
 
-    pub(crate) fn flush(&self) -> io::Result<()> {
+    /// Persists a structure like `persist`, hashing the subtrees of sibling
+    /// nodes in parallel
+    ///
+    /// Meant for committing large structures built in memory, where
+    /// persisting is bound by hashing. The digest is the same as with
+    /// `persist`, but nodes are written to the backend in no particular
+    /// order.
+    #[cfg(feature = "parallel")]
+    pub fn persist_parallel<C>(
+        &self,
+        content: &mut C,
+    ) -> io::Result<Snapshot<C, H>>
+    where
+        C: Compound<H> + Send,
+        C::Leaf: Send,
+        C::Annotation: Send,
+        H::Digest: Send,
+    {
+        let _writing = self.0.collecting.read_recursive();
+        Handle::persist_children(content, self)?;
+        self.persist(content)
+    }
+
+    /// Flushes the data written so far to the backend
+    pub fn flush(&self) -> io::Result<()> {
         // TODO, sync to disk
         for gen in &self.0.generations {
             gen.write().flush()?;
@@ -131,6 +444,17 @@ impl<H: ByteHash> Store<H> {
         hash: H::Digest,
         bytes: Vec<u8>,
     ) -> io::Result<PutResult> {
+        let _writing = self.0.collecting.read_recursive();
+        if let Some(pending) = self.0.relocating.write().as_mut() {
+            // the backend is being copied, and must not change meanwhile
+            if pending.contains_key(&hash)
+                || self.0.generations[0].read().get(&hash).is_ok()
+            {
+                return Ok(PutResult::AlreadyThere);
+            }
+            pending.insert(hash, bytes.into());
+            return Ok(PutResult::Ok);
+        }
         self.0.generations[0].write().put(hash, bytes)
     }
 
@@ -142,17 +466,601 @@ impl<H: ByteHash> Store<H> {
         self.get_hash(&snap.hash)
     }
 
+    /// Restores a snapshot as a read-only structure, to be traversed by
+    /// several threads at once
+    ///
+    /// Every node is kept in memory once restored, and shared by the
+    /// threads reading the structure and by the thawed copies built from it,
+    /// see `Handle::make_arc`.
+    pub fn restore_shared<C: Compound<H>>(
+        &self,
+        snap: &Snapshot<C, H>,
+    ) -> io::Result<Frozen<C, H>> {
+        let mut restored = self.restore(snap)?;
+        for child in restored.children_mut() {
+            child.make_arc()
+        }
+        Ok(restored.freeze())
+    }
+
+    /// Returns a snapshot of the root with digest `hash` in this store
+    ///
+    /// For roots learned of elsewhere, such as from a peer. Nothing is read
+    /// until the snapshot is restored.
+    pub fn snapshot<T: Content<H>>(&self, hash: &H::Digest) -> Snapshot<T, H> {
+        Snapshot::new(*hash, self)
+    }
+
     pub(crate) fn get_hash<T: Content<H>>(
         &self,
         hash: &H::Digest,
     ) -> io::Result<T> {
-        for gen in self.0.generations.as_ref() {
-            if let Ok(read) = gen.read().get(hash) {
-                let mut source = Source::new(read, self);
-                return T::restore(&mut source);
+        self.read(hash, self.0.archival)
+    }
+
+    fn read<T: Content<H>>(
+        &self,
+        hash: &H::Digest,
+        verify: bool,
+    ) -> io::Result<T> {
+        let prefetched = self.0.prefetcher.take(hash);
+        if let Some(bytes) = prefetched {
+            let read = Box::new(Cursor::new(bytes));
+            return Ok(self.restore_from(read, hash, verify, false)?.0);
+        }
+        let preloaded = self.0.preloaded.read().get(hash).cloned();
+        if let Some(bytes) = preloaded {
+            let read = Box::new(Cursor::new(bytes));
+            return Ok(self.restore_from(read, hash, verify, false)?.0);
+        }
+        match self.1 {
+            Some(ref partition) => {
+                if let Some(bytes) = partition.get(hash) {
+                    let read = Box::new(Cursor::new(bytes));
+                    return Ok(self.restore_from(read, hash, verify, false)?.0);
+                }
+                let (t, bytes) = self.coalesced(hash, verify, true)?;
+                if let Some(bytes) = bytes {
+                    partition.insert(*hash, bytes)
+                }
+                Ok(t)
+            }
+            None => Ok(self.coalesced(hash, verify, false)?.0),
+        }
+    }
+
+    /// Returns a handle to the store caching nodes in the partition `name`
+    ///
+    /// Every partition has a cache of its own, limited to `budget` bytes, so
+    /// that reads in one partition never evict the nodes of another. Content
+    /// restored through the handle reads its child nodes through it as well,
+    /// so restoring a root through a partition keeps the whole structure in
+    /// it. Asking for an existing partition sets its budget.
+    pub fn partition(&self, name: &str, budget: usize) -> Store<H> {
+        let partition = self
+            .0
+            .partitions
+            .lock()
+            .entry(name.into())
+            .or_insert_with(|| Arc::new(Partition::new(name, budget)))
+            .clone();
+        partition.set_budget(budget);
+        Store(self.0.clone(), Some(partition))
+    }
+
+    /// Returns the name of the partition of this handle, if any
+    pub fn partition_name(&self) -> Option<&str> {
+        self.1.as_ref().map(|partition| partition.name())
+    }
+
+    /// Returns the number of bytes cached in the partition of this handle
+    pub fn cached(&self) -> usize {
+        self.1
+            .as_ref()
+            .map(|partition| partition.size())
+            .unwrap_or(0)
+    }
+
+    /// Replaces the eviction policy of the partition of this handle
+    ///
+    /// Partitions evict the least recently used nodes by default. The nodes
+    /// already cached are handed over to the new policy.
+    pub fn set_eviction_policy<P>(&self, policy: P) -> io::Result<()>
+    where
+        P: EvictionPolicy<H::Digest> + 'static,
+    {
+        match self.1 {
+            Some(ref partition) => {
+                partition.set_policy(Box::new(policy));
+                Ok(())
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Store handle has no partition",
+            )),
+        }
+    }
+
+    // Concurrent reads of the same node are coalesced into a single fetch
+    // from the backend, whose bytes are shared with the waiting readers.
+    // Nodes the backend reads quickly are fetched right away, their bytes
+    // only returned if `record` is set.
+    fn coalesced<T: Content<H>>(
+        &self,
+        hash: &H::Digest,
+        verify: bool,
+        record: bool,
+    ) -> io::Result<(T, Option<Arc<[u8]>>)> {
+        if !self.slow(hash) {
+            let (t, bytes) = self.fetch(hash, verify, record)?;
+            return Ok((t, bytes.map(Into::into)));
+        }
+        let (flight, leader) = {
+            let mut inflight = self.0.inflight.lock();
+            match inflight.get(hash) {
+                Some(flight) => (flight.clone(), false),
+                None => {
+                    let flight = Arc::new(Flight::default());
+                    inflight.insert(*hash, flight.clone());
+                    (flight, true)
+                }
+            }
+        };
+
+        if !leader {
+            return match flight.wait() {
+                Some(bytes) => {
+                    let read = Box::new(Cursor::new(bytes.clone()));
+                    let (t, _) =
+                        self.restore_from(read, hash, verify, false)?;
+                    Ok((t, Some(bytes)))
+                }
+                // the other fetch failed, try on our own
+                None => Ok((self.fetch(hash, verify, false)?.0, None)),
+            };
+        }
+
+        let mut landing = Landing {
+            inner: &self.0,
+            hash: *hash,
+            flight,
+            bytes: None,
+        };
+        let (t, bytes) = self.fetch(hash, verify, true)?;
+        let bytes: Option<Arc<[u8]>> = bytes.map(Into::into);
+        landing.bytes = bytes.clone();
+        Ok((t, bytes))
+    }
+
+    /// Sets the number of nodes that may be fetched speculatively at once
+    ///
+    /// When descending into a node, the nodes next to it in the direction of
+    /// the search are then fetched from the backend in the background, by
+    /// at most `budget` threads, overlapping the latency of reading them when
+    /// iterating. Meant for high-latency backends, zero (the default)
+    /// disables prefetching.
+    pub fn set_prefetch(&self, budget: usize) {
+        self.0.prefetch_budget.store(budget, Ordering::SeqCst)
+    }
+
+    pub(crate) fn prefetch(&self, hash: &H::Digest) {
+        // tiered stores verify and repair the nodes they read
+        if self.0.generations.len() > 1 {
+            return;
+        }
+        let budget = self.0.prefetch_budget.load(Ordering::Relaxed);
+        let inner = WeakStore(Arc::downgrade(&self.0));
+        self.0.prefetcher.request(*hash, budget, move || {
+            Box::new(move |hash| {
+                // the workers do not keep the store alive
+                Store(inner.0.upgrade()?, None).read_ahead(hash)
+            })
+        });
+    }
+
+    // Reads the bytes of a node as stored, to be restored when read, unless
+    // it is being read already
+    fn read_ahead(&self, hash: &H::Digest) -> Option<Arc<[u8]>> {
+        let flight = {
+            let mut inflight = self.0.inflight.lock();
+            if inflight.contains_key(hash) {
+                return None;
+            }
+            let flight = Arc::new(Flight::default());
+            inflight.insert(*hash, flight.clone());
+            flight
+        };
+        // readers waiting on the flight restore the node from the bytes
+        let mut landing = Landing {
+            inner: &self.0,
+            hash: *hash,
+            flight,
+            bytes: None,
+        };
+        let mut bytes = vec![];
+        let gen = self.0.generations[0].read();
+        gen.get(hash).ok()?.read_to_end(&mut bytes).ok()?;
+        drop(gen);
+        let bytes: Arc<[u8]> = bytes.into();
+        landing.bytes = Some(bytes.clone());
+        Some(bytes)
+    }
+
+    /// Loads nodes of the tree at `root` into memory, as told by `policy`
+    ///
+    /// Preloaded nodes are read without touching the backend until
+    /// `clear_preloaded` is called, so that the first reads after a restart
+    /// do not all pay the latency of cold reads. Returns the number of nodes
+    /// newly loaded.
+    pub fn preload<C: Compound<H>>(
+        &self,
+        root: &Snapshot<C, H>,
+        policy: PreloadPolicy<'_, C, H>,
+    ) -> io::Result<usize> {
+        match policy {
+            PreloadPolicy::Depth(levels) => {
+                self.preload_levels::<C>(&root.hash, levels)
+            }
+            PreloadPolicy::Hot(methods) => {
+                let mut loaded = 0;
+                for mut method in methods {
+                    loaded += self.preload_path(&root.hash, &mut *method)?;
+                }
+                Ok(loaded)
+            }
+        }
+    }
+
+    fn preload_levels<C: Compound<H>>(
+        &self,
+        hash: &H::Digest,
+        levels: usize,
+    ) -> io::Result<usize> {
+        if levels == 0 {
+            return Ok(0);
+        }
+        let (node, mut loaded) = self.preload_node::<C>(hash)?;
+        for child in node.children() {
+            if let Some(digest) = child.digest() {
+                loaded += self.preload_levels::<C>(digest, levels - 1)?
             }
         }
-        Err(io::Error::new(io::ErrorKind::NotFound, "Data not found"))
+        Ok(loaded)
+    }
+
+    fn preload_path<C: Compound<H>>(
+        &self,
+        hash: &H::Digest,
+        method: &mut dyn Method<C, H>,
+    ) -> io::Result<usize> {
+        let (node, mut loaded) = self.preload_node::<C>(hash)?;
+        if let SearchResult::Path(i) = method.select(&node, 0) {
+            if let Some(digest) =
+                node.children().get(i).and_then(|c| c.digest())
+            {
+                loaded += self.preload_path(digest, method)?
+            }
+        }
+        Ok(loaded)
+    }
+
+    // Returns the node, and 1 if it was not already preloaded
+    fn preload_node<C: Compound<H>>(
+        &self,
+        hash: &H::Digest,
+    ) -> io::Result<(C, usize)> {
+        if self.0.preloaded.read().contains_key(hash) {
+            return Ok((self.get_hash(hash)?, 0));
+        }
+        let archival = self.0.archival;
+        let (node, bytes) = match self.coalesced(hash, archival, true)? {
+            (node, Some(bytes)) => (node, bytes),
+            (_, None) => {
+                let (node, bytes) = self.fetch(hash, self.0.archival, true)?;
+                (node, bytes.expect("recorded").into())
+            }
+        };
+        self.0.preloaded.write().insert(*hash, bytes);
+        Ok((node, 1))
+    }
+
+    /// Returns the number of preloaded nodes
+    pub fn preloaded(&self) -> usize {
+        self.0.preloaded.read().len()
+    }
+
+    /// Drops all preloaded nodes from memory
+    pub fn clear_preloaded(&self) {
+        self.0.preloaded.write().clear()
+    }
+
+    // Returns true if some generation may be slow to read the node from
+    fn slow(&self, hash: &H::Digest) -> bool {
+        self.0.generations.iter().any(|gen| gen.read().slow(hash))
+    }
+
+    fn fetch<T: Content<H>>(
+        &self,
+        hash: &H::Digest,
+        verify: bool,
+        record: bool,
+    ) -> io::Result<(T, Option<Vec<u8>>)> {
+        let pending = match self.0.relocating.read().as_ref() {
+            Some(pending) => pending.get(hash).cloned(),
+            None => None,
+        };
+        if let Some(bytes) = pending {
+            let read = Box::new(Cursor::new(bytes));
+            return self.restore_from(read, hash, verify, record);
+        }
+        if self.0.generations.len() > 1 {
+            return self.fetch_tiered(hash, record);
+        }
+        if let Ok(read) = self.0.generations[0].read().get(hash) {
+            return self.restore_from(read, hash, verify, record);
+        }
+        match self.0.below {
+            Some(ref below) => below.fetch(hash, verify, record),
+            None => Err(Error::MissingHash(hash.as_ref().to_vec()).into()),
+        }
+    }
+
+    // Reads through the tiers in order, verifying every node read, and
+    // writes the nodes found missing or corrupt in the primary tier back to
+    // it from the first lower tier holding an intact copy
+    fn fetch_tiered<T: Content<H>>(
+        &self,
+        hash: &H::Digest,
+        record: bool,
+    ) -> io::Result<(T, Option<Vec<u8>>)> {
+        let mut error = None;
+        for (i, gen) in self.0.generations.iter().enumerate() {
+            let gen = gen.read();
+            let read = match gen.get(hash) {
+                Ok(read) => self.restore_from(read, hash, true, true),
+                Err(_) => continue,
+            };
+            drop(gen);
+            match read {
+                Ok((t, bytes)) => {
+                    if i > 0 {
+                        let bytes = bytes.clone().expect("recorded");
+                        self.0.generations[0].write().repair(*hash, bytes)?;
+                        self.0.repairs.fetch_add(1, Ordering::Relaxed);
+                    }
+                    return Ok((t, if record { bytes } else { None }));
+                }
+                Err(e) => error = Some(e),
+            }
+        }
+        Err(error.unwrap_or_else(|| {
+            Error::MissingHash(hash.as_ref().to_vec()).into()
+        }))
+    }
+
+    fn restore_from<'a, T: Content<H>>(
+        &'a self,
+        mut read: Box<dyn Read + 'a>,
+        hash: &H::Digest,
+        verify: bool,
+        record: bool,
+    ) -> io::Result<(T, Option<Vec<u8>>)> {
+        let mut bytes = vec![];
+        read.read_to_end(&mut bytes)?;
+        #[cfg(feature = "compression")]
+        let bytes = compression::decompress(self, bytes)?;
+        if verify {
+            let mut state = H::state();
+            state.write(&bytes);
+            if state.fin() != *hash {
+                return Err(Error::Corrupted.into());
+            }
+        }
+        let t = self.restore_node(&bytes)?;
+        Ok((t, if record { Some(bytes) } else { None }))
+    }
+
+    // Restores a node from its bytes as stored
+    //
+    // Nodes written before format versions have no header, and may well
+    // start with the byte leading one. When reading legacy nodes, a node
+    // that fails to read as versioned, or is not read to its end, is read
+    // again as version 0, unless it is of another domain than the value
+    // read from it.
+    pub(crate) fn restore_node<T: Content<H>>(
+        &self,
+        bytes: &[u8],
+    ) -> io::Result<T> {
+        let versioned = self.restore_bytes(bytes, true);
+        let retry = match versioned {
+            Ok(Some((_, read))) => !read,
+            Ok(None) => false,
+            Err(_) => true,
+        };
+        if retry && self.legacy_reads() && bytes.first() == Some(&MAGIC) {
+            if let Ok(Some((t, true))) = self.restore_bytes(bytes, false) {
+                return Ok(t);
+            }
+        }
+        match versioned? {
+            Some((t, _)) => Ok(t),
+            None => {
+                Err(Error::InvalidEncoding("Node of unexpected domain").into())
+            }
+        }
+    }
+
+    // Restores a `T` from `bytes`, returning whether all of them were read,
+    // or `None` if the node is not in the domain of the value read
+    fn restore_bytes<T: Content<H>>(
+        &self,
+        bytes: &[u8],
+        header: bool,
+    ) -> io::Result<Option<(T, bool)>> {
+        let mut rest = bytes;
+        let (t, in_domain) = {
+            let mut source = Source::new(Box::new(&mut rest), self);
+            if header {
+                source.read_header()?;
+            } else {
+                source.legacy();
+            }
+            let t = T::restore(&mut source)?;
+            (t, source.in_domain())
+        };
+        Ok(if in_domain {
+            Some((t, rest.is_empty()))
+        } else {
+            None
+        })
+    }
+
+    /// Verifies every node of the tree at `root`
+    ///
+    /// Recomputes the digest of each node reachable from the root, and fails
+    /// with an `InvalidData` error if any of them does not match its contents,
+    /// or with a `NotFound` error if a node is missing. Meant to be run after
+    /// rewriting the store, to check that all retained roots are unchanged.
+    pub fn verify<C: Compound<H>>(
+        &self,
+        root: &Snapshot<C, H>,
+    ) -> io::Result<()> {
+        self.verify_node::<C>(&root.hash)
+    }
+
+    fn verify_node<C: Compound<H>>(&self, hash: &H::Digest) -> io::Result<()> {
+        let node: C = self.read(hash, true)?;
+        for child in node.children() {
+            if let Some(digest) = child.digest() {
+                self.verify_node::<C>(digest)?
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns an iterator over the records of all nodes reachable from
+    /// `root`
+    ///
+    /// Each record holds the digest and the encoded bytes of a node, for
+    /// tools that analyze, export or replicate stores without decoding the
+    /// structures themselves.
+    pub fn node_records<C: Compound<H>>(
+        &self,
+        root: &Snapshot<C, H>,
+    ) -> NodeRecords<C, H> {
+        NodeRecords::new(self, root)
+    }
+
+    // Returns a node along with its encoded bytes, read from the backend
+    pub(crate) fn read_raw<T: Content<H>>(
+        &self,
+        hash: &H::Digest,
+    ) -> io::Result<(T, Vec<u8>)> {
+        let (node, bytes) = self.fetch(hash, self.0.archival, true)?;
+        Ok((node, bytes.expect("recorded")))
+    }
+
+    // Returns true if the node with digest `hash` is in the store itself
+    pub(crate) fn contains(&self, hash: &H::Digest) -> bool {
+        self.0
+            .generations
+            .iter()
+            .any(|gen| gen.read().get(hash).is_ok())
+    }
+
+    // Returns the bytes stored under `hash`, as is
+    pub(crate) fn read_bytes(&self, hash: &H::Digest) -> io::Result<Vec<u8>> {
+        for gen in &self.0.generations {
+            if let Ok(mut read) = gen.read().get(hash) {
+                let mut bytes = vec![];
+                read.read_to_end(&mut bytes)?;
+                return Ok(bytes);
+            }
+        }
+        Err(Error::MissingHash(hash.as_ref().to_vec()).into())
+    }
+
+    /// Pins the root with digest `hash`, for as long as the guard is alive
+    ///
+    /// Readers pin the roots they are traversing, so that garbage collection
+    /// and compaction retain every node reachable from them, in addition to
+    /// the roots they are given.
+    pub fn pin(&self, hash: &H::Digest) -> Pinned<H> {
+        *self.0.pins.lock().entry(*hash).or_insert(0) += 1;
+        Pinned {
+            hash: *hash,
+            store: self.clone(),
+        }
+    }
+
+    // Retains the node with digest `hash` in garbage collection, until
+    // released as many times as held
+    pub(crate) fn hold(&self, hash: &H::Digest) {
+        *self.0.held.lock().entry(*hash).or_insert(0) += 1;
+    }
+
+    pub(crate) fn release(&self, hash: &H::Digest) {
+        let mut held = self.0.held.lock();
+        if let Some(count) = held.get_mut(hash) {
+            *count -= 1;
+            if *count == 0 {
+                held.remove(hash);
+            }
+        }
+    }
+
+    /// Returns the digests of all currently pinned roots
+    pub fn pinned(&self) -> Vec<H::Digest> {
+        self.0.pins.lock().keys().cloned().collect()
+    }
+
+    /// Deletes the nodes unreachable from the live roots, returning the
+    /// number of bytes reclaimed
+    ///
+    /// `mark` is called to mark the roots to retain in the `LiveSet`, by
+    /// their type, and every currently pinned root must be among them.
+    /// The chunks of roots being synced are retained as well, see
+    /// `ChunkProvider` and `ChunkReceiver`. Writers wait for the collection
+    /// to finish, so that no node is written after marking, and only the
+    /// marked roots are safe to persist again afterwards. Archival stores
+    /// are never collected, and fail with a `PermissionDenied` error.
+    ///
+    /// Every node marked, and then every node copied by backends compacting
+    /// their storage, is a unit of work of `control`. When cancelled, the
+    /// store is left as it was.
+    pub fn gc<F>(&self, control: &mut Control<'_>, mark: F) -> io::Result<usize>
+    where
+        F: FnOnce(&mut LiveSet<H>) -> io::Result<()>,
+    {
+        if self.0.archival {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "Archival stores are never garbage collected",
+            ));
+        }
+        if self.0.relocating.read().is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Store is being relocated",
+            ));
+        }
+        let _collecting = self.0.collecting.write();
+        self.flush()?;
+        let mut live = LiveSet::new(self, control);
+        mark(&mut live)?;
+        if self.pinned().iter().any(|pin| !live.contains(pin)) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Pinned root not marked live",
+            ));
+        }
+        let mut nodes = live.into_nodes();
+        nodes.extend(self.0.held.lock().keys().cloned());
+        let mut reclaimed = 0;
+        for gen in self.0.generations.as_ref() {
+            reclaimed += gen.write().gc(&nodes, control)?;
+        }
+        Ok(reclaimed)
     }
 
     /// Returns the approximate size of the store
@@ -163,11 +1071,198 @@ impl<H: ByteHash> Store<H> {
         }
         size
     }
+
+    /// Makes `dictionary` available for restoring compressed values
+    #[cfg(feature = "compression")]
+    pub fn add_dictionary(&self, dictionary: Dictionary) {
+        self.0
+            .dictionaries
+            .write()
+            .insert(dictionary.id(), dictionary);
+    }
+
+    /// Returns the dictionary with the identifier `id`, if added
+    #[cfg(feature = "compression")]
+    pub fn dictionary(&self, id: u32) -> Option<Dictionary> {
+        self.0.dictionaries.read().get(&id).cloned()
+    }
+
+    /// Returns the directory of the store, unless kept in memory
+    pub fn path(&self) -> Option<PathBuf> {
+        self.0.generations[0].read().path().map(Path::to_path_buf)
+    }
+
+    /// Returns the registry of named roots kept alongside the store
+    ///
+    /// The roots are kept in the `roots` directory of the store, which fails
+    /// with an `InvalidInput` error if kept in memory. The registry moves
+    /// along with the store, see `relocate`.
+    #[cfg(feature = "filesystem")]
+    pub fn roots(&self) -> io::Result<DirRoots<H>> {
+        let mut roots = self.0.roots.lock();
+        if let Some(ref dir) = *roots {
+            return DirRoots::shared(dir.clone());
+        }
+        let path = self.path().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Store has no directory to keep roots in",
+            )
+        })?;
+        let dir = Arc::new(RwLock::new(path.join("roots")));
+        *roots = Some(dir.clone());
+        DirRoots::shared(dir)
+    }
+
+    /// Starts a transaction setting roots in the registry of the store, see
+    /// `Store::roots`
+    #[cfg(feature = "filesystem")]
+    pub fn transaction(&self) -> io::Result<Transaction<H, DirRoots<H>>> {
+        Ok(Transaction::new(self, self.roots()?))
+    }
+
+    /// Moves the store to the directory at `path`, while it stays open
+    ///
+    /// The data is copied on a background thread. Nodes written meanwhile are
+    /// kept in memory, and written to the destination once the copy is done,
+    /// at which point the store switches to it, and the old directory is
+    /// removed. Roots set meanwhile through `roots` are copied again at the
+    /// switch, which they wait for, and the registries of the store follow
+    /// it. The destination must not exist, or be empty. If the copy
+    /// fails, the store keeps using the old directory. Only stores on a
+    /// single `DiskBackend`, without any backend wrapping it, can be
+    /// relocated, others fail with an `InvalidInput` error.
+    #[cfg(feature = "filesystem")]
+    pub fn relocate<P: Into<PathBuf>>(
+        &self,
+        path: P,
+    ) -> io::Result<Relocation> {
+        let to = path.into();
+        if to.exists() && fs::read_dir(&to)?.next().is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "Relocation destination is not empty",
+            ));
+        }
+        let from = {
+            let mut relocating = self.0.relocating.write();
+            if relocating.is_some() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Store is already being relocated",
+                ));
+            }
+            let mut gen = self.0.generations[0].write();
+            // wrapping backends, or other tiers, would be dropped by the copy
+            if self.0.generations.len() > 1 || !gen.relocatable() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Only stores on a single DiskBackend can be relocated",
+                ));
+            }
+            let from = gen.path().map(Path::to_path_buf).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Store has no directory to relocate",
+                )
+            })?;
+            gen.flush()?;
+            *relocating = Some(HashMap::new());
+            from
+        };
+        let store = self.clone();
+        Ok(Relocation(
+            thread::Builder::new()
+                .name("kelvin-relocation".into())
+                .spawn(move || store.finish_relocation(&from, &to))?,
+        ))
+    }
+
+    #[cfg(feature = "filesystem")]
+    fn finish_relocation(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let copied = copy_dir(from, to)
+            .and_then(|_| Persistant::<H>::new(to.to_path_buf()));
+
+        // the registries of roots handed out are moved at the switch
+        let roots = self.0.roots.lock();
+        let mut relocating = self.0.relocating.write();
+        let pending = relocating.take().unwrap_or_default();
+        let mut gen = self.0.generations[0].write();
+        let result = match copied {
+            Ok(new) => {
+                *gen = Box::new(new);
+                Ok(())
+            }
+            Err(e) => Err(e),
+        };
+        // whichever backend is kept receives the writes made meanwhile
+        for (hash, bytes) in pending {
+            gen.put(hash, bytes.to_vec())?;
+        }
+        gen.flush()?;
+        drop(gen);
+        drop(relocating);
+
+        result?;
+        if let Some(ref dir) = *roots {
+            let mut dir = dir.write();
+            let _lock = RootLock::acquire(dir.join(".lock"))?;
+            let moved = to.join("roots");
+            match fs::remove_dir_all(&moved) {
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => (),
+                result => result?,
+            }
+            copy_dir(&dir, &moved)?;
+            *dir = moved;
+        }
+        drop(roots);
+        fs::remove_dir_all(from)
+    }
+}
+
+/// A relocation of a store, running in the background
+#[cfg(feature = "filesystem")]
+#[derive(Debug)]
+pub struct Relocation(thread::JoinHandle<io::Result<()>>);
+
+#[cfg(feature = "filesystem")]
+impl Relocation {
+    /// Returns true if the relocation is over, successfully or not
+    pub fn is_finished(&self) -> bool {
+        self.0.is_finished()
+    }
+
+    /// Waits for the relocation to finish
+    pub fn wait(self) -> io::Result<()> {
+        match self.0.join() {
+            Ok(result) => result,
+            Err(panic) => panic::resume_unwind(panic),
+        }
+    }
+}
+
+// Copies the files of the directory `from`, recursively, into `to`
+#[cfg(feature = "filesystem")]
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use std::time::Duration;
+
+    use crate::backend::Ephemeral as MemBackend;
     use crate::tests::tempfile::tempdir;
     use crate::Blake2b;
 
@@ -192,4 +1287,138 @@ mod test {
         }
         let _store = Store::<Blake2b>::new(dir.path()).unwrap();
     }
+
+    #[test]
+    fn archival_verifies_reads() {
+        use std::fs::OpenOptions;
+        use std::io::{Seek, SeekFrom, Write};
+
+        let dir = tempdir().unwrap();
+        let snapshot = {
+            let store = Store::<Blake2b>::archival(dir.path()).unwrap();
+            assert!(store.is_archival());
+            let snapshot = store.persist(&mut vec![1u64, 2, 3]).unwrap();
+            assert_eq!(store.restore(&snapshot).unwrap(), vec![1, 2, 3]);
+            store.flush().unwrap();
+            *snapshot.hash()
+        };
+
+        // flip the last byte of the node
+        let mut data = OpenOptions::new()
+            .write(true)
+            .open(dir.path().join("data"))
+            .unwrap();
+        data.seek(SeekFrom::End(-1)).unwrap();
+        data.write_all(&[4]).unwrap();
+
+        let store = Store::<Blake2b>::new(dir.path()).unwrap();
+        let corrupted: Vec<u64> = store.get_hash(&snapshot).unwrap();
+        assert_eq!(corrupted, vec![1, 2, 4]);
+
+        let store = Store::<Blake2b>::archival(dir.path()).unwrap();
+        let err = store.get_hash::<Vec<u64>>(&snapshot).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(matches!(Error::from(err), Error::Corrupted));
+
+        let missing = store.get_hash::<Vec<u64>>(&[0; 32]).unwrap_err();
+        assert_eq!(missing.kind(), io::ErrorKind::NotFound);
+        assert!(matches!(Error::from(missing), Error::MissingHash(_)));
+    }
+
+    // A backend counting reads, and taking its time with them
+    struct Slow(MemBackend<Blake2b>, Arc<AtomicUsize>);
+
+    impl Backend<Blake2b> for Slow {
+        fn get<'a>(
+            &'a self,
+            digest: &[u8; 32],
+        ) -> io::Result<Box<dyn Read + 'a>> {
+            self.1.fetch_add(1, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(100));
+            self.0.get(digest)
+        }
+
+        fn put(
+            &mut self,
+            digest: [u8; 32],
+            bytes: Vec<u8>,
+        ) -> io::Result<PutResult> {
+            self.0.put(digest, bytes)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn slow(&self, _: &[u8; 32]) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn coalesced_reads() {
+        use std::sync::Barrier;
+
+        let gets = Arc::new(AtomicUsize::new(0));
+        let backend = Slow(MemBackend::new(), gets.clone());
+        let store = Store::with_backend(Box::new(backend), false);
+        let snapshot = store.persist(&mut vec![7u64; 100]).unwrap();
+
+        let barrier = Arc::new(Barrier::new(8));
+        let readers: Vec<_> = (0..8)
+            .map(|_| {
+                let store = store.clone();
+                let hash = *snapshot.hash();
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    barrier.wait();
+                    store.get_hash::<Vec<u64>>(&hash).unwrap()
+                })
+            })
+            .collect();
+        for reader in readers {
+            assert_eq!(reader.join().unwrap(), vec![7u64; 100]);
+        }
+        assert_eq!(gets.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn prefetch() {
+        let gets = Arc::new(AtomicUsize::new(0));
+        let backend = Slow(MemBackend::new(), gets.clone());
+        let store = Store::with_backend(Box::new(backend), false);
+        let snapshot = store.persist(&mut vec![7u64; 100]).unwrap();
+
+        // disabled by default
+        snapshot.prefetch();
+        store.0.prefetcher.wait();
+        assert_eq!(gets.load(Ordering::SeqCst), 0);
+
+        store.set_prefetch(4);
+        snapshot.prefetch();
+        snapshot.prefetch();
+        store.0.prefetcher.wait();
+        assert_eq!(gets.load(Ordering::SeqCst), 1);
+
+        // served from the prefetched node, which is read only once
+        assert_eq!(store.restore(&snapshot).unwrap(), vec![7u64; 100]);
+        assert_eq!(gets.load(Ordering::SeqCst), 1);
+        assert_eq!(store.restore(&snapshot).unwrap(), vec![7u64; 100]);
+        assert_eq!(gets.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn pins() {
+        let store = Store::<Blake2b>::ephemeral();
+        let snapshot = store.persist(&mut 42u64).unwrap();
+
+        let a = snapshot.pin();
+        let b = store.pin(snapshot.hash());
+        assert!(store.pinned() == vec![*snapshot.hash()]);
+
+        drop(a);
+        assert_eq!(store.pinned().len(), 1);
+        drop(b);
+        assert!(store.pinned().is_empty());
+    }
 }
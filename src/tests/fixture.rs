@@ -0,0 +1,91 @@
+//! Building structures with exactly specified shapes
+//!
+//! Structures normally decide the position of every leaf themselves, from
+//! hashes or from key order, so edge cases like collapsing singleton nodes
+//! or very deep paths only show up by chance. Fixtures place every leaf and
+//! node explicitly, and `shape` reads back the shape of a structure to check
+//! the result of operations on it.
+use std::io;
+
+use crate::{ByteHash, Compound, Handle, HandleRef};
+
+/// The shape of a subtree
+#[derive(Clone, Debug, PartialEq)]
+pub enum Shape<L> {
+    /// A leaf
+    Leaf(L),
+    /// A node, with its non-empty children and their slots
+    Node(Vec<(usize, Shape<L>)>),
+}
+
+impl<L> Shape<L> {
+    /// Creates a node with the children in consecutive slots
+    pub fn node<I: IntoIterator<Item = Shape<L>>>(children: I) -> Self {
+        Shape::Node(children.into_iter().enumerate().collect())
+    }
+
+    /// Returns the depth of the subtree, a leaf having depth 0
+    pub fn depth(&self) -> usize {
+        match self {
+            Shape::Leaf(_) => 0,
+            Shape::Node(children) => {
+                1 + children
+                    .iter()
+                    .map(|(_, child)| child.depth())
+                    .max()
+                    .unwrap_or(0)
+            }
+        }
+    }
+}
+
+/// Builds a structure of exactly the given shape
+///
+/// The slots of a node are the ones of its default value, so this works for
+/// structures with a fixed number of children per node. Annotations are
+/// computed as usual, but nothing checks that the leaves are where the
+/// structure would put them.
+///
+/// Panics if the shape is a leaf, or uses a slot the node does not have.
+pub fn build<C, H>(shape: Shape<C::Leaf>) -> C
+where
+    C: Compound<H>,
+    H: ByteHash,
+{
+    let mut node = C::default();
+    match shape {
+        Shape::Leaf(_) => panic!("The root of a fixture must be a node"),
+        Shape::Node(children) => {
+            let slots = node.children().len();
+            for (slot, child) in children {
+                assert!(slot < slots, "Slot {} of {} in fixture", slot, slots);
+                node.children_mut()[slot] = match child {
+                    Shape::Leaf(leaf) => Handle::new_leaf(leaf),
+                    shape => Handle::new_node(build::<C, H>(shape)),
+                };
+            }
+        }
+    }
+    node
+}
+
+/// Returns the shape of a structure
+pub fn shape<C, H>(node: &C) -> io::Result<Shape<C::Leaf>>
+where
+    C: Compound<H>,
+    H: ByteHash,
+{
+    let mut children = vec![];
+    for (slot, child) in node.children().iter().enumerate() {
+        match child.inner()? {
+            HandleRef::Leaf(leaf) => {
+                children.push((slot, Shape::Leaf(leaf.clone())))
+            }
+            HandleRef::Node(node) => {
+                children.push((slot, shape::<C, H>(&*node)?))
+            }
+            HandleRef::None => (),
+        }
+    }
+    Ok(Shape::Node(children))
+}
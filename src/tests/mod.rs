@@ -1,3 +1,4 @@
+pub mod fixture;
 mod fuzz;
 mod quickcheck_map;
 use crate::{ByteHash, Compound, HandleType};
@@ -0,0 +1,313 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+use bytehash::ByteHash;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use rand::Rng;
+
+use crate::content::Content;
+use crate::map::MapMut;
+use crate::portable::PortableHasher;
+use crate::sink::Sink;
+use crate::source::Source;
+use crate::store::{Snapshot, Store};
+
+const OPS: usize = 4;
+
+/// The kind of a traced operation
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TraceOp {
+    /// A lookup of a key
+    Get,
+    /// An insertion of a key
+    Insert,
+    /// A removal of a key
+    Remove,
+    /// The structure was persisted to the store
+    Persist,
+}
+
+impl TraceOp {
+    fn index(self) -> usize {
+        match self {
+            TraceOp::Get => 0,
+            TraceOp::Insert => 1,
+            TraceOp::Remove => 2,
+            TraceOp::Persist => 3,
+        }
+    }
+
+    fn from_index(i: u8) -> io::Result<Self> {
+        Ok(match i {
+            0 => TraceOp::Get,
+            1 => TraceOp::Insert,
+            2 => TraceOp::Remove,
+            3 => TraceOp::Persist,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Invalid trace operation",
+                ))
+            }
+        })
+    }
+}
+
+/// A single recorded operation
+///
+/// Keys are only kept as salted hashes, equal keys having equal hashes
+/// within a trace, so a trace can leave the machine it was recorded on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TraceEvent {
+    /// The kind of operation
+    pub op: TraceOp,
+    /// The salted hash of the key operated on, `0` for `Persist`
+    pub key: u64,
+    /// The time the operation took when recorded
+    pub elapsed: Duration,
+}
+
+/// A sequence of recorded operations
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Trace(Vec<TraceEvent>);
+
+/// Records the operations performed on a structure
+///
+/// Recording is opt-in, operations are only traced when performed through
+/// the recorder.
+#[derive(Debug)]
+pub struct Recorder {
+    salt: u64,
+    trace: Trace,
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Recorder {
+            salt: rand::thread_rng().gen(),
+            trace: Trace::default(),
+        }
+    }
+}
+
+impl Recorder {
+    /// Creates a new recorder, with a random salt for key hashes
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Runs `f`, recording it as an operation of kind `op` on `key`
+    pub fn record<K, T, F>(&mut self, op: TraceOp, key: &K, f: F) -> T
+    where
+        K: Hash + ?Sized,
+        F: FnOnce() -> T,
+    {
+        let key = self.anonymize(key);
+        self.timed(op, key, f)
+    }
+
+    /// Inserts into `map`, recording the operation
+    pub fn insert<M, K, V, H>(
+        &mut self,
+        map: &mut M,
+        k: K,
+        v: V,
+    ) -> io::Result<Option<V>>
+    where
+        M: MapMut<K, V, H>,
+        K: Hash,
+        H: ByteHash,
+    {
+        let key = self.anonymize(&k);
+        self.timed(TraceOp::Insert, key, || map.insert(k, v))
+    }
+
+    /// Removes from `map`, recording the operation
+    pub fn remove<M, K, V, H>(
+        &mut self,
+        map: &mut M,
+        k: &K,
+    ) -> io::Result<Option<V>>
+    where
+        M: MapMut<K, V, H>,
+        K: Hash,
+        H: ByteHash,
+    {
+        self.record(TraceOp::Remove, k, || map.remove(k))
+    }
+
+    /// Persists `content` to `store`, recording the operation
+    pub fn persist<T, H>(
+        &mut self,
+        store: &Store<H>,
+        content: &mut T,
+    ) -> io::Result<Snapshot<T, H>>
+    where
+        T: Content<H>,
+        H: ByteHash,
+    {
+        self.timed(TraceOp::Persist, 0, || store.persist(content))
+    }
+
+    /// Returns the operations recorded so far
+    pub fn trace(&self) -> &Trace {
+        &self.trace
+    }
+
+    /// Stops recording, returning the recorded operations
+    pub fn into_trace(self) -> Trace {
+        self.trace
+    }
+
+    fn anonymize<K: Hash + ?Sized>(&self, key: &K) -> u64 {
+        let mut hasher = PortableHasher::new(DefaultHasher::new());
+        self.salt.hash(&mut hasher);
+        key.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn timed<T, F: FnOnce() -> T>(&mut self, op: TraceOp, key: u64, f: F) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.trace.0.push(TraceEvent {
+            op,
+            key,
+            elapsed: start.elapsed(),
+        });
+        result
+    }
+}
+
+/// The outcome of replaying a trace
+#[derive(Clone, Debug, Default)]
+pub struct ReplayReport {
+    counts: [u64; OPS],
+    elapsed: [Duration; OPS],
+    recorded: Duration,
+    size: usize,
+}
+
+impl ReplayReport {
+    /// Returns the time taken by the replay
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed.iter().sum()
+    }
+
+    /// Returns the time taken by operations of kind `op` in the replay
+    pub fn elapsed_for(&self, op: TraceOp) -> Duration {
+        self.elapsed[op.index()]
+    }
+
+    /// Returns the number of operations of kind `op` replayed
+    pub fn count(&self, op: TraceOp) -> u64 {
+        self.counts[op.index()]
+    }
+
+    /// Returns the time the operations took when recorded
+    pub fn recorded(&self) -> Duration {
+        self.recorded
+    }
+
+    /// Returns the size of the store after the replay
+    pub fn store_size(&self) -> usize {
+        self.size
+    }
+}
+
+impl Trace {
+    /// Returns the recorded operations
+    pub fn events(&self) -> &[TraceEvent] {
+        &self.0
+    }
+
+    /// Returns the number of recorded operations
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns true if no operations were recorded
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Writes the trace to `writer`, to be moved off the recording machine
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_u64::<BigEndian>(self.0.len() as u64)?;
+        for event in &self.0 {
+            writer.write_u8(event.op.index() as u8)?;
+            writer.write_u64::<BigEndian>(event.key)?;
+            writer.write_u64::<BigEndian>(event.elapsed.as_nanos() as u64)?;
+        }
+        Ok(())
+    }
+
+    /// Reads a trace written with `write_to`
+    pub fn read_from<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let len = reader.read_u64::<BigEndian>()?;
+        let mut events = Vec::new();
+        for _ in 0..len {
+            let op = TraceOp::from_index(reader.read_u8()?)?;
+            let key = reader.read_u64::<BigEndian>()?;
+            let nanos = reader.read_u64::<BigEndian>()?;
+            events.push(TraceEvent {
+                op,
+                key,
+                elapsed: Duration::from_nanos(nanos),
+            });
+        }
+        Ok(Trace(events))
+    }
+
+    /// Re-runs the trace against a new map of type `M`, persisted into
+    /// `store`
+    ///
+    /// Key hashes stand in for the original keys and values. Candidate
+    /// configurations are compared by replaying the same trace with
+    /// different map types, or stores using different backends and cache
+    /// budgets.
+    pub fn replay<M, H>(&self, store: &Store<H>) -> io::Result<ReplayReport>
+    where
+        M: MapMut<u64, u64, H> + Content<H> + Default,
+        H: ByteHash,
+    {
+        let mut map = M::default();
+        let mut report = ReplayReport::default();
+
+        for event in &self.0 {
+            let start = Instant::now();
+            match event.op {
+                TraceOp::Get => {
+                    map.get(&event.key)?;
+                }
+                TraceOp::Insert => {
+                    map.insert(event.key, event.key)?;
+                }
+                TraceOp::Remove => {
+                    map.remove(&event.key)?;
+                }
+                TraceOp::Persist => {
+                    let snapshot = store.persist(&mut map)?;
+                    map = store.restore(&snapshot)?;
+                }
+            }
+            let i = event.op.index();
+            report.elapsed[i] += start.elapsed();
+            report.counts[i] += 1;
+            report.recorded += event.elapsed;
+        }
+        report.size = store.size();
+        Ok(report)
+    }
+}
+
+impl<H: ByteHash> Content<H> for Trace {
+    fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
+        self.write_to(sink)
+    }
+
+    fn restore(source: &mut Source<H>) -> io::Result<Self> {
+        Trace::read_from(source)
+    }
+}
@@ -0,0 +1,69 @@
+use std::io;
+
+use crate::backend::{RootStore, RootUpdate};
+use crate::content::Content;
+use crate::root::RootConflict;
+use crate::store::{Snapshot, Store};
+use crate::ByteHash;
+
+/// A set of new named roots, committed all at once
+///
+/// Structures staged in a transaction are written to the store right away,
+/// but none of their roots is set until `commit`, which sets them all or,
+/// if another writer changed any of them since it was first staged, none.
+/// Dropping a transaction without committing it rolls it back, leaving the
+/// nodes written to be reclaimed by garbage collection.
+pub struct Transaction<H: ByteHash, R: RootStore<H>> {
+    store: Store<H>,
+    roots: R,
+    updates: Vec<RootUpdate<H>>,
+}
+
+impl<H: ByteHash, R: RootStore<H>> Transaction<H, R> {
+    /// Starts a transaction writing to `store`, and setting roots in `roots`
+    pub fn new(store: &Store<H>, roots: R) -> Self {
+        Transaction {
+            store: store.clone(),
+            roots,
+            updates: vec![],
+        }
+    }
+
+    /// Persists `t`, to be set as the root `name` on commit
+    ///
+    /// The root is expected to be unchanged from when it is first staged,
+    /// staging it again replaces the digest it will be set to.
+    pub fn stage<T: Content<H>>(
+        &mut self,
+        name: &str,
+        t: &mut T,
+    ) -> io::Result<Snapshot<T, H>> {
+        let snapshot = self.store.persist(t)?;
+        match self.updates.iter_mut().find(|update| update.name == name) {
+            Some(update) => update.digest = *snapshot.hash(),
+            None => {
+                let expected = self.roots.get(name)?;
+                self.updates.push(RootUpdate {
+                    name: name.into(),
+                    expected,
+                    digest: *snapshot.hash(),
+                })
+            }
+        }
+        Ok(snapshot)
+    }
+
+    /// Returns the names of the roots staged
+    pub fn staged(&self) -> impl Iterator<Item = &str> {
+        self.updates.iter().map(|update| update.name.as_str())
+    }
+
+    /// Flushes the store, and sets all staged roots at once
+    ///
+    /// Returns a `RootConflict` naming the first root found changed by
+    /// another writer, in which case no root is set.
+    pub fn commit(self) -> io::Result<Result<(), RootConflict<H>>> {
+        self.store.flush()?;
+        self.roots.commit(&self.updates)
+    }
+}
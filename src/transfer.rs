@@ -0,0 +1,42 @@
+use std::io;
+
+use bytehash::ByteHash;
+
+use crate::map::MapMut;
+
+/// Moves the entry at `key` from `src` to `dst`, returning true if moved
+///
+/// The entry is removed from `src` and inserted into `dst` with
+/// `try_insert`, and put back into `src` if `dst` already has an entry at
+/// `key`, so that the value is never left in both maps, or in neither.
+/// Annotations of both maps are updated along their modified paths.
+///
+/// Returns false, leaving both maps untouched, if `src` has no entry at
+/// `key`. Fails with `AlreadyExists`, leaving both maps as they were, if
+/// `dst` already has one. Errors reading either map from its store may
+/// leave the move half done.
+pub fn move_entry<K, V, S, D, H>(
+    src: &mut S,
+    dst: &mut D,
+    key: &K,
+) -> io::Result<bool>
+where
+    K: Clone,
+    S: MapMut<K, V, H>,
+    D: MapMut<K, V, H>,
+    H: ByteHash,
+{
+    let val = match src.remove(key)? {
+        Some(val) => val,
+        None => return Ok(false),
+    };
+    let val = match dst.try_insert(key.clone(), val)? {
+        Ok(()) => return Ok(true),
+        Err(occupied) => occupied.value,
+    };
+    src.insert(key.clone(), val)?;
+    Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        "Key already present in destination",
+    ))
+}
@@ -0,0 +1,124 @@
+use std::io;
+
+use bytehash::ByteHash;
+
+use crate::compound::Compound;
+use crate::content::Content;
+use crate::gc::Reach;
+use crate::iter::LeafIterable;
+use crate::map::{MapMut, KV};
+use crate::sink::Sink;
+use crate::source::Source;
+
+/// A structure derived from a map, maintained incrementally on every write
+///
+/// `update` is called with the previous and the new value at `key`, where
+/// `None` means the key was absent, or is being removed.
+pub trait View<K, V, H>
+where
+    Self: Content<H>,
+    H: ByteHash,
+{
+    /// Applies a single change of the base map to the view
+    fn update(
+        &mut self,
+        key: &K,
+        old: Option<&V>,
+        new: Option<&V>,
+    ) -> io::Result<()>;
+}
+
+/// A map together with a view derived from it
+///
+/// All writes go through `Viewed`, which updates the view along with the map.
+/// Both are persisted together, so a single root always covers a map and a
+/// view that are consistent with each other.
+#[derive(Clone, Default)]
+pub struct Viewed<M, W> {
+    base: M,
+    view: W,
+}
+
+impl<M, W> Viewed<M, W> {
+    /// Returns a reference to the base map
+    pub fn base(&self) -> &M {
+        &self.base
+    }
+
+    /// Returns a reference to the view
+    pub fn view(&self) -> &W {
+        &self.view
+    }
+
+    /// Splits into the base map and the view
+    pub fn into_parts(self) -> (M, W) {
+        (self.base, self.view)
+    }
+
+    /// Builds the view from scratch, from all pairs in `base`
+    pub fn new<K, V, H>(base: M) -> io::Result<Self>
+    where
+        M: Compound<H, Leaf = KV<K, V>>,
+        W: View<K, V, H> + Default,
+        H: ByteHash,
+    {
+        let mut view = W::default();
+        for leaf in base.iter() {
+            let KV { ref key, ref val } = *leaf?;
+            view.update(key, None, Some(val))?;
+        }
+        Ok(Viewed { base, view })
+    }
+
+    /// Inserts into the base map, and updates the view accordingly
+    pub fn insert<K, V, H>(&mut self, k: K, v: V) -> io::Result<Option<V>>
+    where
+        M: MapMut<K, V, H>,
+        W: View<K, V, H>,
+        K: Clone,
+        V: Clone,
+        H: ByteHash,
+    {
+        let old = self.base.insert(k.clone(), v.clone())?;
+        self.view.update(&k, old.as_ref(), Some(&v))?;
+        Ok(old)
+    }
+
+    /// Removes from the base map, and updates the view accordingly
+    pub fn remove<K, V, H>(&mut self, k: &K) -> io::Result<Option<V>>
+    where
+        M: MapMut<K, V, H>,
+        W: View<K, V, H>,
+        H: ByteHash,
+    {
+        let old = self.base.remove(k)?;
+        if old.is_some() {
+            self.view.update(k, old.as_ref(), None)?;
+        }
+        Ok(old)
+    }
+}
+
+impl<M, W, H> Content<H> for Viewed<M, W>
+where
+    M: Content<H>,
+    W: Content<H>,
+    H: ByteHash,
+{
+    fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
+        self.base.persist(sink)?;
+        self.view.persist(sink)
+    }
+
+    fn restore(source: &mut Source<H>) -> io::Result<Self> {
+        Ok(Viewed {
+            base: M::restore(source)?,
+            view: W::restore(source)?,
+        })
+    }
+
+    fn reach<R: Reach<H>>(&self, reach: &mut R) -> io::Result<()> {
+        self.base.reach(reach)?;
+        self.view.reach(reach)
+    }
+}
@@ -0,0 +1,730 @@
+//! A B+ Tree implemented on kelvin
+//!
+//! All leaves are kept at the same depth, in key order, and every node is
+//! annotated with the largest key below it. This allows ordered iteration and
+//! range queries, like finding the smallest key greater than or equal to some
+//! key, which hashed structures like the HAMT can not answer.
+#![warn(missing_docs)]
+
+use std::borrow::Borrow;
+use std::fmt;
+use std::io;
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::{RangeBounds, RangeFull};
+
+use arrayvec::ArrayVec;
+
+use kelvin::proof::{self, Proof, RangeProof};
+use kelvin::{
+    annotation,
+    annotations::{Annotation, Cardinality, Counter, MaxKey, MaxKeyType},
+    reach_children, ByteHash, Compound, Content, Handle, HandleMut, HandleType,
+    KeyOrdered, LeafIter, MapMut, Method, OccupiedError, RangeSearch, Reach,
+    SearchResult, Sink, Source, Summary, ValPath, ValPathMut, KV,
+};
+
+/// The default B+ tree
+pub type DefaultBTreeMap<K, V, H> = BTree<K, V, MaxKey<K>, H>;
+
+// Minimum number of children of a node, except the root
+const B: usize = 8;
+// Maximum number of children of a node
+const M: usize = B * 2;
+
+/// A B+ tree
+#[derive(Clone)]
+pub struct BTree<K, V, A, H: ByteHash>(ArrayVec<[Handle<Self, H>; M]>)
+where
+    Self: Compound<H>;
+
+impl<K, V, A, H> Default for BTree<K, V, A, H>
+where
+    K: Content<H> + Ord,
+    V: Content<H>,
+    A: Annotation<KV<K, V>, H>,
+    H: ByteHash,
+{
+    fn default() -> Self {
+        BTree(Default::default())
+    }
+}
+
+annotation! {
+    struct BTreeAnnotation<K, U> {
+        key: MaxKey<K>,
+        count: Cardinality<U>,
+    }
+    where
+        K: MaxKeyType,
+        U: Counter
+}
+
+/// Struct used to search the B+ tree for a key
+pub struct BTreeSearch<'a, K, O: ?Sized>(&'a O, PhantomData<K>);
+
+impl<'a, K, O: ?Sized> From<&'a O> for BTreeSearch<'a, K, O> {
+    fn from(k: &'a O) -> Self {
+        BTreeSearch(k, PhantomData)
+    }
+}
+
+impl<'a, K, V, A, O, H> Method<BTree<K, V, A, H>, H> for BTreeSearch<'a, K, O>
+where
+    K: Ord + Borrow<O> + Content<H>,
+    V: Content<H>,
+    A: Annotation<KV<K, V>, H> + Borrow<MaxKey<K>>,
+    O: Ord + ?Sized,
+    H: ByteHash,
+{
+    fn select(
+        &mut self,
+        compound: &BTree<K, V, A, H>,
+        offset: usize,
+    ) -> SearchResult {
+        match compound.find(self.0, offset) {
+            Some((i, exact)) => match compound.0[offset + i].handle_type() {
+                HandleType::Leaf if exact => SearchResult::Leaf(i),
+                HandleType::Node => SearchResult::Path(i),
+                _ => SearchResult::None,
+            },
+            None => SearchResult::None,
+        }
+    }
+}
+
+type RangeIter<'a, K, V, A, O, R, H> =
+    LeafIter<'a, BTree<K, V, A, H>, RangeSearch<K, O, R>, H>;
+
+/// An iterator over the entries of a B+ tree in a range, in key order
+pub struct Range<'a, K, V, A, O: ?Sized, R, H>(RangeIter<'a, K, V, A, O, R, H>)
+where
+    K: Content<H> + Ord,
+    V: Content<H>,
+    A: Annotation<KV<K, V>, H>,
+    H: ByteHash;
+
+impl<'a, K, V, A, O, R, H> Iterator for Range<'a, K, V, A, O, R, H>
+where
+    K: Content<H> + Ord + Borrow<O>,
+    V: Content<H>,
+    A: Annotation<KV<K, V>, H> + Borrow<MaxKey<K>>,
+    O: Ord + ?Sized + 'a,
+    R: RangeBounds<O> + 'a,
+    H: ByteHash,
+{
+    type Item = io::Result<(&'a K, &'a V)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.0.next() {
+            Some(Ok(KV { key, val })) => Some(Ok((key, val))),
+            Some(Err(e)) => Some(Err(e)),
+            None => None,
+        }
+    }
+}
+
+enum InsertResult<C, H>
+where
+    C: Compound<H>,
+    H: ByteHash,
+{
+    Ok,
+    Replaced(C::Leaf),
+    // the key was present, and not replaced
+    Occupied(C::Leaf),
+    Split(Handle<C, H>),
+}
+
+impl<K, V, A, H> BTree<K, V, A, H>
+where
+    K: Content<H> + Ord,
+    V: Content<H>,
+    A: Annotation<KV<K, V>, H> + Borrow<MaxKey<K>>,
+    H: ByteHash,
+{
+    /// Creates a new BTree
+    pub fn new() -> Self {
+        BTree(Default::default())
+    }
+
+    /// Insert key-value pair into the BTree, optionally returning expelled
+    /// value
+    pub fn insert(&mut self, k: K, v: V) -> io::Result<Option<V>> {
+        match self._insert(KV::new(k, v), 0, true)? {
+            InsertResult::Ok => Ok(None),
+            InsertResult::Replaced(KV { key: _, val }) => Ok(Some(val)),
+            InsertResult::Occupied(_) | InsertResult::Split(_) => {
+                unreachable!()
+            }
+        }
+    }
+
+    /// Insert key-value pair into the BTree, unless the key is already
+    /// present
+    pub fn try_insert(
+        &mut self,
+        k: K,
+        v: V,
+    ) -> io::Result<Result<(), OccupiedError<'_, K, V, Self, H>>> {
+        match self._insert(KV::new(k, v), 0, false)? {
+            InsertResult::Ok => Ok(Ok(())),
+            InsertResult::Occupied(KV { key, val }) => {
+                let existing = self.get_mut(&key)?.expect("key present");
+                Ok(Err(OccupiedError {
+                    existing,
+                    value: val,
+                }))
+            }
+            InsertResult::Replaced(_) | InsertResult::Split(_) => {
+                unreachable!()
+            }
+        }
+    }
+
+    /// Get a reference to a value in the map
+    pub fn get<O>(
+        &self,
+        k: &O,
+    ) -> io::Result<Option<ValPath<'_, K, V, Self, H>>>
+    where
+        O: ?Sized + Ord + Eq,
+        K: Borrow<O>,
+    {
+        ValPath::new(self, &mut BTreeSearch::from(k))
+    }
+
+    /// Get a mutable reference to a value in the map
+    pub fn get_mut<O>(
+        &mut self,
+        k: &O,
+    ) -> io::Result<Option<ValPathMut<'_, K, V, Self, H>>>
+    where
+        O: ?Sized + Ord + Eq,
+        K: Borrow<O>,
+    {
+        ValPathMut::new(self, &mut BTreeSearch::from(k))
+    }
+
+    /// Returns an iterator over the entries with keys in `range`, in key
+    /// order
+    ///
+    /// The smallest key greater than or equal to `k` is the first entry of
+    /// `range(k..)`.
+    pub fn range<O, R>(&self, range: R) -> Range<'_, K, V, A, O, R, H>
+    where
+        O: ?Sized + Ord,
+        K: Borrow<O>,
+        R: RangeBounds<O>,
+    {
+        Range(LeafIter::new(self, RangeSearch::new(range)))
+    }
+
+    /// Returns an iterator over all entries, in key order
+    pub fn iter(&self) -> Range<'_, K, V, A, K, RangeFull, H> {
+        self.range(..)
+    }
+
+    /// Proves the inclusion of the value at key, if present
+    pub fn prove<O>(&self, k: &O) -> io::Result<Option<Proof<Self, H>>>
+    where
+        O: ?Sized + Ord + Eq,
+        K: Borrow<O>,
+    {
+        proof::prove(self, &mut BTreeSearch::from(k))
+    }
+
+    /// Proves which keys are within `range`
+    pub fn prove_range<R>(&self, range: &R) -> io::Result<RangeProof<Self, H>>
+    where
+        R: RangeBounds<K>,
+    {
+        proof::prove_range(self, range)
+    }
+
+    /// Proves that key is not in the tree, if absent
+    pub fn prove_absent(
+        &self,
+        k: &K,
+    ) -> io::Result<Option<RangeProof<Self, H>>> {
+        proof::prove_absent(self, k)
+    }
+
+    /// Remove element with given key, returning it.
+    pub fn remove<O>(&mut self, k: &O) -> io::Result<Option<V>>
+    where
+        O: ?Sized + Ord + Eq,
+        K: Borrow<O>,
+    {
+        let removed = self._remove(k)?;
+        // a root with a single node child is replaced by it
+        if self.0.len() == 1 && self.0[0].handle_type() == HandleType::Node {
+            *self = self.take_node(0)?;
+        }
+        Ok(removed.map(|KV { key: _, val }| val))
+    }
+
+    // Returns the index, counted from `offset`, of the first child whose
+    // largest key is not smaller than `k`, and whether it is equal
+    fn find<O>(&self, k: &O, offset: usize) -> Option<(usize, bool)>
+    where
+        O: ?Sized + Ord,
+        K: Borrow<O>,
+    {
+        for (i, h) in self.0[offset..].iter().enumerate() {
+            if let Some(ann) = h.annotation() {
+                let max: &MaxKey<K> = (*ann).borrow();
+                let max: &O = (**max).borrow();
+                if k <= max {
+                    return Some((i, k == max));
+                }
+            }
+        }
+        None
+    }
+
+    fn _insert(
+        &mut self,
+        leaf: KV<K, V>,
+        depth: usize,
+        replace: bool,
+    ) -> io::Result<InsertResult<Self, H>> {
+        let len = self.0.len();
+        let leaves = self
+            .0
+            .first()
+            .map(|h| h.handle_type() == HandleType::Leaf)
+            .unwrap_or(true);
+
+        let (i, handle) = if leaves {
+            match self.find(&leaf.key, 0) {
+                Some((i, true)) if replace => {
+                    let replaced =
+                        mem::replace(&mut self.0[i], Handle::new_leaf(leaf));
+                    return Ok(InsertResult::Replaced(replaced.into_leaf()));
+                }
+                Some((_, true)) => return Ok(InsertResult::Occupied(leaf)),
+                Some((i, false)) => (i, Handle::new_leaf(leaf)),
+                None => (len, Handle::new_leaf(leaf)),
+            }
+        } else {
+            // keys larger than all others go into the last child
+            let i = self.find(&leaf.key, 0).map(|(i, _)| i).unwrap_or(len - 1);
+            match &mut *self.0[i].inner_mut()? {
+                HandleMut::Node(n) => {
+                    match n._insert(leaf, depth + 1, replace)? {
+                        InsertResult::Split(handle) => (i + 1, handle),
+                        done => return Ok(done),
+                    }
+                }
+                _ => unreachable!(),
+            }
+        };
+
+        if !self.0.is_full() {
+            self.0.insert(i, handle);
+            return Ok(InsertResult::Ok);
+        }
+
+        // split the node in two halves, the second one is returned down the
+        // stack to be inserted next to this one
+        let mut split = Self::new();
+        split.0.extend(self.0.drain(B..));
+        if i <= B {
+            self.0.insert(i, handle);
+        } else {
+            split.0.insert(i - B, handle);
+        }
+
+        if depth == 0 {
+            // if we're on the top level, we create a new root.
+            let old_root = mem::replace(self, Self::new());
+            self.0.push(Handle::new_node(old_root));
+            self.0.push(Handle::new_node(split));
+            Ok(InsertResult::Ok)
+        } else {
+            Ok(InsertResult::Split(Handle::new_node(split)))
+        }
+    }
+
+    fn _remove<O>(&mut self, k: &O) -> io::Result<Option<KV<K, V>>>
+    where
+        O: ?Sized + Ord + Eq,
+        K: Borrow<O>,
+    {
+        let (i, exact) = match self.find(k, 0) {
+            Some(found) => found,
+            None => return Ok(None),
+        };
+
+        if self.0[i].handle_type() == HandleType::Leaf {
+            return Ok(if exact {
+                Some(self.0.remove(i).into_leaf())
+            } else {
+                None
+            });
+        }
+
+        let (removed, underfilled) = match &mut *self.0[i].inner_mut()? {
+            HandleMut::Node(n) => {
+                let removed = n._remove(k)?;
+                (removed, n.0.len() < B)
+            }
+            _ => unreachable!(),
+        };
+        if removed.is_some() && underfilled {
+            self.rebalance(i)?;
+        }
+        Ok(removed)
+    }
+
+    // Refills the underfilled child at `i` from a sibling, or merges the two
+    fn rebalance(&mut self, i: usize) -> io::Result<()> {
+        let l = if i > 0 { i - 1 } else { i };
+        let r = l + 1;
+        let mut left = self.take_node(l)?;
+        let mut right = self.take_node(r)?;
+
+        if left.0.len() + right.0.len() <= M {
+            left.0.extend(right.0.drain(..));
+            self.0[l] = Handle::new_node(left);
+            self.0.remove(r);
+        } else {
+            if left.0.len() < B {
+                left.0.push(right.0.remove(0));
+            } else {
+                let last = left.0.pop().expect("sibling is filled");
+                right.0.insert(0, last);
+            }
+            self.0[l] = Handle::new_node(left);
+            self.0[r] = Handle::new_node(right);
+        }
+        Ok(())
+    }
+
+    // Takes the node at `i`, leaving an empty handle in its place
+    fn take_node(&mut self, i: usize) -> io::Result<Self> {
+        // make sure the node is loaded
+        self.0[i].inner_mut()?;
+        Ok(mem::take(&mut self.0[i]).into_node())
+    }
+}
+
+impl<K, V, A, H> MapMut<K, V, H> for BTree<K, V, A, H>
+where
+    K: Content<H> + Ord,
+    V: Content<H>,
+    A: Annotation<KV<K, V>, H> + Borrow<MaxKey<K>>,
+    H: ByteHash,
+{
+    fn insert(&mut self, k: K, v: V) -> io::Result<Option<V>> {
+        BTree::insert(self, k, v)
+    }
+
+    fn try_insert(
+        &mut self,
+        k: K,
+        v: V,
+    ) -> io::Result<Result<(), OccupiedError<'_, K, V, Self, H>>> {
+        BTree::try_insert(self, k, v)
+    }
+
+    fn remove(&mut self, k: &K) -> io::Result<Option<V>> {
+        BTree::remove(self, k)
+    }
+
+    fn get(&self, k: &K) -> io::Result<Option<ValPath<'_, K, V, Self, H>>> {
+        BTree::get(self, k)
+    }
+
+    fn get_mut(
+        &mut self,
+        k: &K,
+    ) -> io::Result<Option<ValPathMut<'_, K, V, Self, H>>> {
+        BTree::get_mut(self, k)
+    }
+
+    fn search_mut(
+        &mut self,
+        k: &K,
+    ) -> io::Result<Result<ValPathMut<'_, K, V, Self, H>, &mut Self>> {
+        ValPathMut::search(self, &mut BTreeSearch::from(k))
+    }
+}
+
+impl<K, V, A, H> Content<H> for BTree<K, V, A, H>
+where
+    K: Content<H> + Ord,
+    V: Content<H>,
+    A: Annotation<KV<K, V>, H>,
+    H: ByteHash,
+{
+    fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
+        (self.0.len() as u8).persist(sink)?;
+        for h in &mut self.0 {
+            h.persist(sink)?
+        }
+        Ok(())
+    }
+
+    fn restore(source: &mut Source<H>) -> io::Result<Self> {
+        let mut b = BTree::default();
+        let len = u8::restore(source)?;
+        if len as usize > M {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Too many children in BTree node",
+            ));
+        }
+        for _ in 0..len {
+            b.0.push(Handle::restore(source)?);
+        }
+        Ok(b)
+    }
+
+    fn reach<R: Reach<H>>(&self, reach: &mut R) -> io::Result<()> {
+        reach_children(self, reach)
+    }
+}
+
+impl<K, V, A, H> fmt::Debug for BTree<K, V, A, H>
+where
+    K: Content<H> + Ord + fmt::Debug,
+    V: Content<H> + fmt::Debug,
+    A: Annotation<KV<K, V>, H>,
+    H: ByteHash,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Summary::new("BTree", self).fmt(f)
+    }
+}
+
+impl<K, V, A, H> KeyOrdered<H> for BTree<K, V, A, H>
+where
+    H: ByteHash,
+    K: Content<H> + Ord,
+    V: Content<H>,
+    A: Annotation<KV<K, V>, H>,
+{
+}
+
+impl<K, V, A, H> Compound<H> for BTree<K, V, A, H>
+where
+    H: ByteHash,
+    K: Content<H> + Ord,
+    V: Content<H>,
+    A: Annotation<KV<K, V>, H>,
+{
+    type Leaf = KV<K, V>;
+
+    type Annotation = A;
+
+    fn children_mut(&mut self) -> &mut [Handle<Self, H>] {
+        &mut self.0
+    }
+
+    fn children(&self) -> &[Handle<Self, H>] {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::ops::Bound;
+
+    use kelvin::quickcheck_map;
+    use kelvin::{Blake2b, HandleRef};
+
+    type Map<K, V> = DefaultBTreeMap<K, V, Blake2b>;
+
+    // Checks that all leaves are at the same depth, and that no node but the
+    // root is underfilled
+    fn check_shape<K, V>(tree: &Map<K, V>, depth: usize) -> usize
+    where
+        K: Content<Blake2b> + Ord,
+        V: Content<Blake2b>,
+    {
+        if depth > 0 {
+            assert!(tree.0.len() >= B);
+        }
+        let mut leaf_depth = None;
+        for child in tree.0.iter() {
+            let d = match child.inner().unwrap() {
+                HandleRef::Node(n) => check_shape(&*n, depth + 1),
+                _ => depth,
+            };
+            assert_eq!(*leaf_depth.get_or_insert(d), d);
+        }
+        leaf_depth.unwrap_or(depth)
+    }
+
+    #[test]
+    fn trivial_map() {
+        let mut h = Map::new();
+        h.insert(28, 28).unwrap();
+        assert_eq!(*h.get(&28).unwrap().unwrap(), 28);
+        assert!(h.get(&27).unwrap().is_none());
+    }
+
+    #[test]
+    fn bigger_map() {
+        let mut h = Map::new();
+        for i in 0..4096u64 {
+            h.insert(i * 7919 % 4096, i).unwrap();
+        }
+        check_shape(&h, 0);
+        for i in 0..4096u64 {
+            assert_eq!(*h.get(&(i * 7919 % 4096)).unwrap().unwrap(), i);
+        }
+        assert_eq!(h.insert(0, 0).unwrap(), Some(0));
+    }
+
+    #[test]
+    fn insert_remove() {
+        let mut h = Map::new();
+        for i in 0..4096u64 {
+            h.insert(i, i).unwrap();
+        }
+        for i in (0..4096u64).step_by(2) {
+            assert_eq!(h.remove(&i).unwrap(), Some(i));
+            assert_eq!(h.remove(&i).unwrap(), None);
+        }
+        check_shape(&h, 0);
+        for i in 0..4096u64 {
+            assert_eq!(
+                h.get(&i).unwrap().map(|v| *v),
+                Some(i).filter(|i| i % 2 == 1)
+            );
+        }
+        for i in (0..4096u64).rev() {
+            h.remove(&i).unwrap();
+            check_shape(&h, 0);
+        }
+        assert!(h.0.is_empty());
+    }
+
+    #[test]
+    fn ordered_iteration() {
+        let mut h = Map::new();
+        for i in 0..1000u64 {
+            h.insert(i * 7919 % 1000, i).unwrap();
+        }
+        let keys: Vec<u64> = h.iter().map(|e| *e.unwrap().0).collect();
+        assert_eq!(keys, (0..1000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn ranges() {
+        let mut h = Map::new();
+        for i in 0..1000u64 {
+            h.insert(i * 2, i).unwrap();
+        }
+        let keys = |r: Vec<io::Result<(&u64, &u64)>>| -> Vec<u64> {
+            r.into_iter().map(|e| *e.unwrap().0).collect()
+        };
+        assert_eq!(
+            keys(h.range(100..110).collect()),
+            vec![100, 102, 104, 106, 108]
+        );
+        assert_eq!(
+            keys(h.range(101..=110).collect()),
+            vec![102, 104, 106, 108, 110]
+        );
+        assert_eq!(keys(h.range(1995..).collect()), vec![1996, 1998]);
+        assert_eq!(keys(h.range(..3).collect()), vec![0, 2]);
+        assert!(h.range(2000..).next().is_none());
+
+        // the smallest key greater than or equal to k
+        for k in 0..1998u64 {
+            let (key, val) = h.range(k..).next().unwrap().unwrap();
+            assert_eq!(*key, k + k % 2);
+            assert_eq!(*val, (k + k % 2) / 2);
+        }
+    }
+
+    #[test]
+    fn try_insert() {
+        let mut h = Map::new();
+        for i in 0..1024 {
+            assert!(h.try_insert(i, i).unwrap().is_ok());
+        }
+        for i in 0..1024 {
+            let mut err = h.try_insert(i, i + 1).unwrap().unwrap_err();
+            assert_eq!(*err.existing, i);
+            assert_eq!(err.value, i + 1);
+            *err.existing = i + 2;
+        }
+        for i in 0..1024 {
+            assert_eq!(*h.get(&i).unwrap().unwrap(), i + 2);
+        }
+    }
+
+    #[test]
+    fn count() {
+        let mut h = BTree::<_, _, BTreeAnnotation<_, u64>, Blake2b>::new();
+        for i in 0..1000u64 {
+            h.insert(i, i).unwrap();
+        }
+        for i in 0..500u64 {
+            h.remove(&i).unwrap();
+        }
+        assert_eq!(h.count(), 500);
+    }
+
+    #[test]
+    fn persisted() {
+        let store = kelvin::Store::<Blake2b>::ephemeral();
+        let mut h = Map::new();
+        for i in 0..1000u64 {
+            h.insert(i, i).unwrap();
+        }
+        let snapshot = store.persist(&mut h).unwrap();
+        let mut restored: Map<u64, u64> = store.restore(&snapshot).unwrap();
+        assert_eq!(restored.range(500..).count(), 500);
+        for i in 0..1000u64 {
+            assert_eq!(restored.remove(&i).unwrap(), Some(i));
+        }
+    }
+
+    #[test]
+    fn range_proofs() {
+        let store = kelvin::Store::<Blake2b>::ephemeral();
+        let mut h = Map::new();
+        for i in 0..1024u64 {
+            h.insert(i * 2, i).unwrap();
+        }
+        let root = *store.persist(&mut h).unwrap().hash();
+
+        let proof = h.prove_range(&(100..200)).unwrap();
+        let leaves = proof::verify_range(&root, &proof, &(100..200))
+            .unwrap()
+            .unwrap();
+        assert_eq!(leaves.len(), 50);
+
+        let proof = h.prove(&100).unwrap().unwrap();
+        assert!(proof::verify(&root, &proof, &KV::new(100, 50)).unwrap());
+        let proof = h.prove_absent(&101).unwrap().unwrap();
+        assert!(proof::verify_absent(&root, &proof, &101).unwrap());
+    }
+
+    #[test]
+    fn borrowed_keys() {
+        let mut map = Map::<String, u8>::new();
+        map.insert("hello".into(), 8).unwrap();
+        map.insert("world".into(), 9).unwrap();
+        assert_eq!(*map.get("hello").unwrap().unwrap(), 8);
+        let (key, _) = map
+            .range::<str, _>((Bound::Included("i"), Bound::Unbounded))
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(key, "world");
+        assert_eq!(map.remove("hello").unwrap().unwrap(), 8);
+    }
+
+    quickcheck_map!(|| {
+        BTree::<_, _, BTreeAnnotation<_, u64>, Blake2b>::new()
+    });
+}
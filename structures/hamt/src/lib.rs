@@ -1,17 +1,25 @@
 //! A Hash-array mapped trie implemented on kelvin
 #![warn(missing_docs)]
 
+use std::array;
 use std::borrow::Borrow;
+use std::collections::HashSet;
+use std::fmt;
 use std::hash::Hash;
-use std::io;
+use std::io::{self, Read, Write};
 use std::iter::Iterator;
 use std::marker::PhantomData;
 use std::mem;
 
+use kelvin::proof::{self, Proof};
+#[cfg(feature = "async")]
+use kelvin::AsyncStore;
 use kelvin::{
     annotations::{Annotation, Cardinality, VoidAnnotation},
-    ByteHash, Compound, Content, Handle, HandleMut, HandleOwned, HandleRef,
-    HandleType, Method, SearchResult, Sink, Source, ValPath, ValPathMut, KV,
+    portable_hash, reach_children, ByteHash, Compound, Content, Handle,
+    HandleMut, HandleOwned, HandleRef, HandleType, MapMut, Method,
+    OccupiedError, Reach, SearchResult, Sink, Source, Summary, ValPath,
+    ValPathMut, KV,
 };
 
 /// Default HAMT-map without annotations
@@ -19,18 +27,32 @@ pub type DefaultHAMTMap<K, V, H> = HAMT<K, V, VoidAnnotation, H>;
 /// Default HAMT-map with Cardinality annotation (for `.count()`)
 pub type CountingHAMTMap<K, V, H> = HAMT<K, V, Cardinality<u64>, H>;
 
-const N_BUCKETS: usize = 16;
-
 /// A hash array mapped trie
+///
+/// Every node has `N` slots, selected by `log2(N)` bits of the hash of the
+/// key per level. `N` is a power of two from 4 to 256, and defaults to 16.
+/// Wider tries are shallower, so fewer nodes are read from disk per lookup,
+/// at the cost of larger nodes to write on every change.
+///
+/// Nodes are laid out in the manner of CHAMP: a bit mask of the occupied
+/// slots, and the children of those only, packed. They are persisted with a
+/// second bit mask of which children are nodes, followed by the leaves and
+/// then the nodes. Nodes of format versions before 2, with a tag per handle,
+/// are still restored.
 #[derive(Clone)]
-pub struct HAMT<K, V, A, H>([Handle<Self, H>; N_BUCKETS])
+pub struct HAMT<K, V, A, H, const N: usize = 16>
 where
     K: Content<H>,
     V: Content<H>,
     A: Annotation<KV<K, V>, H>,
-    H: ByteHash;
+    H: ByteHash,
+{
+    slots: Slots,
+    // the children of the occupied slots, in slot order
+    children: Vec<Handle<Self, H>>,
+}
 
-impl<K, V, A, H> Default for HAMT<K, V, A, H>
+impl<K, V, A, H, const N: usize> Default for HAMT<K, V, A, H, N>
 where
     K: Content<H>,
     V: Content<H>,
@@ -38,17 +60,81 @@ where
     H: ByteHash,
 {
     fn default() -> Self {
-        HAMT(Default::default())
+        #[allow(clippy::let_unit_value)]
+        let () = FanOut::<N>::VALID;
+        HAMT {
+            slots: Slots::default(),
+            children: vec![],
+        }
     }
 }
 
-fn select_slot(hash: &[u8], depth: usize) -> usize {
-    let ofs = depth / 2;
-    (if ofs % 2 == 0 {
-        (hash[ofs] & 0xF0) >> 4
+// The format version from which nodes are persisted in the compact layout,
+// earlier versions tag every handle
+const COMPACT: u8 = 2;
+
+struct FanOut<const N: usize>;
+
+impl<const N: usize> FanOut<N> {
+    const VALID: () = assert!(
+        N.is_power_of_two() && N >= 4 && N <= 256,
+        "HAMT fan-out must be a power of two from 4 to 256"
+    );
+
+    // Number of bytes in the mask of occupied slots
+    const MASK_LEN: usize = if N < 8 { 1 } else { N / 8 };
+}
+
+// The occupied slots of a node, as a bit mask
+#[derive(Clone, Copy, Default)]
+struct Slots([u64; 4]);
+
+impl Slots {
+    fn contains(&self, slot: usize) -> bool {
+        self.0[slot / 64] & (1 << (slot % 64)) != 0
+    }
+
+    fn insert(&mut self, slot: usize) {
+        self.0[slot / 64] |= 1 << (slot % 64)
+    }
+
+    fn remove(&mut self, slot: usize) {
+        self.0[slot / 64] &= !(1 << (slot % 64))
+    }
+
+    // The number of occupied slots before `slot`
+    fn rank(&self, slot: usize) -> usize {
+        let word = slot / 64;
+        let below: u32 = self.0[..word].iter().map(|w| w.count_ones()).sum();
+        let bits = self.0[word] & ((1 << (slot % 64)) - 1);
+        (below + bits.count_ones()) as usize
+    }
+}
+
+fn buckets<T: Default, const N: usize>() -> [T; N] {
+    #[allow(clippy::let_unit_value)]
+    let () = FanOut::<N>::VALID;
+    array::from_fn(|_| T::default())
+}
+
+fn select_slot<const N: usize>(hash: &[u8], depth: usize) -> usize {
+    if N == 16 {
+        // the 16-way trie keeps its original slot selection, so that the
+        // roots of existing maps stay the same
+        let ofs = depth / 2;
+        (if ofs % 2 == 0 {
+            (hash[ofs] & 0xF0) >> 4
+        } else {
+            hash[ofs] & 0x0F
+        }) as usize
     } else {
-        hash[ofs] & 0x0F
-    }) as usize
+        let bits = N.trailing_zeros() as usize;
+        let start = depth * bits;
+        let byte = start / 8;
+        let next = hash.get(byte + 1).copied().unwrap_or(0);
+        let window = u16::from_be_bytes([hash[byte], next]);
+        (window >> (16 - bits - start % 8)) as usize & (N - 1)
+    }
 }
 
 /// Type for searching for keys in the HAMT
@@ -69,7 +155,7 @@ where
     H: ByteHash,
 {
     fn from(key: &'a O) -> Self {
-        let hash = H::hash(&key);
+        let hash = portable_hash::<H, _>(&key);
         HAMTSearch {
             hash,
             key,
@@ -79,7 +165,7 @@ where
     }
 }
 
-impl<'a, K, V, A, O, H> Method<HAMT<K, V, A, H>, H>
+impl<'a, K, V, A, O, H, const N: usize> Method<HAMT<K, V, A, H, N>, H>
     for HAMTSearch<'a, K, V, O, H>
 where
     K: Borrow<O> + Content<H>,
@@ -90,16 +176,20 @@ where
 {
     fn select(
         &mut self,
-        compound: &HAMT<K, V, A, H>,
+        compound: &HAMT<K, V, A, H, N>,
         _: usize,
     ) -> SearchResult {
-        let slot = select_slot(self.hash.as_ref(), self.depth);
+        let slot = select_slot::<N>(self.hash.as_ref(), self.depth);
         self.depth += 1;
-        match compound.0[slot].leaf().map(Borrow::borrow) {
+        let i = match compound.index(slot) {
+            Some(i) => i,
+            None => return SearchResult::None,
+        };
+        match compound.children[i].leaf().map(Borrow::borrow) {
             Some(KV { key, val: _ }) if key.borrow() == self.key => {
-                SearchResult::Leaf(slot)
+                SearchResult::Leaf(i)
             }
-            _ => SearchResult::Path(slot),
+            _ => SearchResult::Path(i),
         }
     }
 }
@@ -110,7 +200,65 @@ enum Removed<L> {
     Collapse(L, L),
 }
 
-impl<K, V, A, H> HAMT<K, V, A, H>
+impl<K, V, A, H, const N: usize> HAMT<K, V, A, H, N>
+where
+    K: Content<H>,
+    V: Content<H>,
+    A: Annotation<KV<K, V>, H>,
+    H: ByteHash,
+{
+    // The index of the child in `slot`, if occupied
+    fn index(&self, slot: usize) -> Option<usize> {
+        if self.slots.contains(slot) {
+            Some(self.slots.rank(slot))
+        } else {
+            None
+        }
+    }
+
+    fn child_mut(&mut self, slot: usize) -> Option<&mut Handle<Self, H>> {
+        let i = self.index(slot)?;
+        Some(&mut self.children[i])
+    }
+
+    fn slot_type(&self, slot: usize) -> HandleType {
+        match self.index(slot) {
+            Some(i) => self.children[i].handle_type(),
+            None => HandleType::None,
+        }
+    }
+
+    // Puts `child` in `slot`, returning the child it replaces
+    fn set_child(
+        &mut self,
+        slot: usize,
+        child: Handle<Self, H>,
+    ) -> Option<Handle<Self, H>> {
+        match self.index(slot) {
+            Some(i) => Some(mem::replace(&mut self.children[i], child)),
+            None => {
+                self.children.insert(self.slots.rank(slot), child);
+                self.slots.insert(slot);
+                None
+            }
+        }
+    }
+
+    // Takes the child out of `slot`, leaving it unoccupied
+    fn take_child(&mut self, slot: usize) -> Option<Handle<Self, H>> {
+        let i = self.index(slot)?;
+        self.slots.remove(slot);
+        Some(self.children.remove(i))
+    }
+
+    // The occupied slots, in order
+    fn occupied(&self) -> impl Iterator<Item = usize> {
+        let slots = self.slots;
+        (0..N).filter(move |slot| slots.contains(*slot))
+    }
+}
+
+impl<K, V, A, H, const N: usize> HAMT<K, V, A, H, N>
 where
     K: Content<H> + Eq + Hash,
     V: Content<H>,
@@ -119,12 +267,62 @@ where
 {
     /// Creates a new HAMT
     pub fn new() -> Self {
-        HAMT(Default::default())
+        Self::default()
     }
 
     /// Insert key-value pair into the HAMT, optionally returning expelled value
     pub fn insert(&mut self, k: K, v: V) -> io::Result<Option<V>> {
-        self.sub_insert(0, H::hash(&k), k, v)
+        match self.sub_insert(0, portable_hash::<H, _>(&k), k, v, true)? {
+            Ok(replaced) => Ok(replaced),
+            Err(_) => unreachable!(),
+        }
+    }
+
+    /// Insert key-value pair into the HAMT, unless the key is already present
+    pub fn try_insert(
+        &mut self,
+        k: K,
+        v: V,
+    ) -> io::Result<Result<(), OccupiedError<'_, K, V, Self, H>>> {
+        match self.sub_insert(0, portable_hash::<H, _>(&k), k, v, false)? {
+            Ok(_) => Ok(Ok(())),
+            Err((k, value)) => {
+                let existing = self.get_mut(&k)?.expect("key present");
+                Ok(Err(OccupiedError { existing, value }))
+            }
+        }
+    }
+
+    /// Insert all key-value pairs, later pairs replacing earlier ones with
+    /// the same key
+    ///
+    /// The pairs are grouped by the slots their hashes select, so that every
+    /// node on their paths is visited, and re-annotated, only once.
+    pub fn insert_batch<I>(&mut self, pairs: I) -> io::Result<()>
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let batch: Vec<_> = pairs
+            .into_iter()
+            .map(|(k, v)| (portable_hash::<H, _>(&k), KV::new(k, v)))
+            .collect();
+
+        let mut keep = vec![true; batch.len()];
+        let mut seen = HashSet::new();
+        for (i, (_, kv)) in batch.iter().enumerate().rev() {
+            if !seen.insert(&kv.key) {
+                keep[i] = false
+            }
+        }
+        drop(seen);
+
+        let batch = batch
+            .into_iter()
+            .zip(keep)
+            .filter(|(_, keep)| *keep)
+            .map(|(pair, _)| pair)
+            .collect();
+        self.sub_insert_batch(0, batch)
     }
 
     /// Get a reference to a value in the map
@@ -148,14 +346,40 @@ where
         ValPathMut::new(self, &mut HAMTSearch::from(k.borrow()))
     }
 
+    /// Get a reference to a value in the map, awaiting the nodes not yet
+    /// fetched by `store`
+    #[cfg(feature = "async")]
+    pub async fn get_async<'a, O>(
+        &'a self,
+        k: &O,
+        store: &AsyncStore<H>,
+    ) -> io::Result<Option<ValPath<'a, K, V, Self, H>>>
+    where
+        O: ?Sized + Hash + Eq,
+        K: Borrow<O>,
+    {
+        ValPath::new_async(self, || HAMTSearch::from(k.borrow()), store).await
+    }
+
+    /// Proves the inclusion of the value at key, if present
+    pub fn prove<O>(&self, k: &O) -> io::Result<Option<Proof<Self, H>>>
+    where
+        O: ?Sized + Hash + Eq,
+        K: Borrow<O>,
+    {
+        proof::prove(self, &mut HAMTSearch::from(k.borrow()))
+    }
+
+    // Returns the pair back if the key is present, and `replace` is false
     fn sub_insert(
         &mut self,
         depth: usize,
         h: H::Digest,
         k: K,
         v: V,
-    ) -> io::Result<Option<V>> {
-        let s = select_slot(h.as_ref(), depth);
+        replace: bool,
+    ) -> io::Result<Result<Option<V>, (K, V)>> {
+        let s = select_slot::<N>(h.as_ref(), depth);
 
         enum Action {
             Split,
@@ -163,47 +387,98 @@ where
             Replace,
         }
 
-        let action = match &mut *self.0[s].inner_mut()? {
-            HandleMut::None => Action::Insert,
-            HandleMut::Leaf(KV { key, val: _ }) => {
-                if key == &k {
-                    Action::Replace
-                } else {
-                    Action::Split
+        let action = match self.child_mut(s) {
+            None => Action::Insert,
+            Some(child) => match &mut *child.inner_mut()? {
+                HandleMut::None => Action::Insert,
+                HandleMut::Leaf(KV { key, val: _ }) => {
+                    if key == &k {
+                        Action::Replace
+                    } else {
+                        Action::Split
+                    }
                 }
-            }
-            HandleMut::Node(node) => {
-                return node.sub_insert(depth + 1, h, k, v)
-            }
+                HandleMut::Node(node) => {
+                    return node.sub_insert(depth + 1, h, k, v, replace)
+                }
+            },
         };
 
-        Ok(match action {
+        Ok(Ok(match action {
             Action::Insert => {
-                self.0[s] = Handle::new_leaf(KV::new(k, v));
+                self.set_child(s, Handle::new_leaf(KV::new(k, v)));
                 None
             }
+            Action::Replace if !replace => return Ok(Err((k, v))),
             Action::Replace => {
-                let KV { key: _, val } = mem::replace(
-                    &mut self.0[s],
-                    Handle::new_leaf(KV::new(k, v)),
-                )
-                .into_leaf();
+                let KV { key: _, val } = self
+                    .set_child(s, Handle::new_leaf(KV::new(k, v)))
+                    .expect("occupied")
+                    .into_leaf();
                 Some(val)
             }
             Action::Split => {
-                let KV { key, val } =
-                    mem::replace(&mut self.0[s], Handle::new_empty())
-                        .into_leaf();
+                let KV { key, val } = self
+                    .set_child(s, Handle::new_empty())
+                    .expect("occupied")
+                    .into_leaf();
 
-                let old_h = H::hash(&key);
+                let old_h = portable_hash::<H, _>(&key);
 
-                let mut new_node = HAMT::new();
-                new_node.sub_insert(depth + 1, h, k, v)?;
-                new_node.sub_insert(depth + 1, old_h, key, val)?;
-                self.0[s] = Handle::new_node(new_node);
+                let mut new_node = Self::new();
+                let _ = new_node.sub_insert(depth + 1, h, k, v, true)?;
+                let _ =
+                    new_node.sub_insert(depth + 1, old_h, key, val, true)?;
+                self.set_child(s, Handle::new_node(new_node));
                 None
             }
-        })
+        }))
+    }
+
+    // Inserts pairs with distinct keys
+    fn sub_insert_batch(
+        &mut self,
+        depth: usize,
+        batch: Vec<(H::Digest, KV<K, V>)>,
+    ) -> io::Result<()> {
+        let mut buckets: [Vec<_>; N] = buckets();
+        for (h, kv) in batch {
+            buckets[select_slot::<N>(h.as_ref(), depth)].push((h, kv));
+        }
+
+        for (s, mut bucket) in buckets.iter_mut().map(mem::take).enumerate() {
+            if bucket.is_empty() {
+                continue;
+            }
+            match self.slot_type(s) {
+                HandleType::Node => {
+                    let child = self.child_mut(s).expect("occupied");
+                    if let HandleMut::Node(node) = &mut *child.inner_mut()? {
+                        node.sub_insert_batch(depth + 1, bucket)?;
+                    }
+                    continue;
+                }
+                HandleType::Leaf => {
+                    let kv = self
+                        .set_child(s, Handle::new_empty())
+                        .expect("occupied")
+                        .into_leaf();
+                    if !bucket.iter().any(|(_, new)| new.key == kv.key) {
+                        bucket.push((portable_hash::<H, _>(&kv.key), kv));
+                    }
+                }
+                HandleType::None => (),
+            }
+            let child = if bucket.len() == 1 {
+                Handle::new_leaf(bucket.pop().expect("one pair").1)
+            } else {
+                let mut node = Self::new();
+                node.sub_insert_batch(depth + 1, bucket)?;
+                Handle::new_node(node)
+            };
+            self.set_child(s, child);
+        }
+        Ok(())
     }
 
     /// Remove element with given key, returning it.
@@ -212,7 +487,7 @@ where
         O: ?Sized + Hash + Eq,
         K: Borrow<O>,
     {
-        match self.sub_remove(0, H::hash(k), k)? {
+        match self.sub_remove(0, portable_hash::<H, _>(k), k)? {
             Removed::None => Ok(None),
             Removed::Leaf(KV { key: _, val }) => Ok(Some(val)),
             _ => unreachable!(),
@@ -231,8 +506,11 @@ where
     {
         let removed_leaf;
         {
-            let s = select_slot(h.as_ref(), depth);
-            let slot = &mut self.0[s];
+            let s = select_slot::<N>(h.as_ref(), depth);
+            let slot = match self.child_mut(s) {
+                Some(slot) => slot,
+                None => return Ok(Removed::None),
+            };
 
             let mut collapse = None;
 
@@ -259,11 +537,8 @@ where
             if let Some((removed, reinsert)) = collapse {
                 removed_leaf = removed;
                 slot.replace(HandleOwned::Leaf(reinsert));
-            } else if let HandleOwned::Leaf(l) = slot.replace(HandleOwned::None)
-            {
-                removed_leaf = l
             } else {
-                unreachable!()
+                removed_leaf = self.take_child(s).expect("occupied").into_leaf()
             }
         }
         // we might have to collapse the branch
@@ -280,7 +555,7 @@ where
     fn remove_singleton(&mut self) -> io::Result<Option<KV<K, V>>> {
         let mut singleton = None;
 
-        for (i, child) in self.0.iter().enumerate() {
+        for (i, child) in self.children.iter().enumerate() {
             match (child.inner()?, singleton) {
                 (HandleRef::None, _) => (),
                 (HandleRef::Leaf(_), None) => singleton = Some(i),
@@ -289,53 +564,164 @@ where
             }
         }
         if let Some(idx) = singleton {
-            Ok(Some(mem::take(&mut self.0[idx]).into_leaf()))
+            let slot = self.occupied().nth(idx).expect("occupied");
+            Ok(Some(self.take_child(slot).expect("occupied").into_leaf()))
         } else {
             Ok(None)
         }
     }
 }
 
-impl<K, V, A, H> Content<H> for HAMT<K, V, A, H>
+impl<K, V, A, H, const N: usize> MapMut<K, V, H> for HAMT<K, V, A, H, N>
+where
+    K: Content<H> + Eq + Hash,
+    V: Content<H>,
+    A: Annotation<KV<K, V>, H>,
+    H: ByteHash,
+{
+    fn insert(&mut self, k: K, v: V) -> io::Result<Option<V>> {
+        HAMT::insert(self, k, v)
+    }
+
+    fn try_insert(
+        &mut self,
+        k: K,
+        v: V,
+    ) -> io::Result<Result<(), OccupiedError<'_, K, V, Self, H>>> {
+        HAMT::try_insert(self, k, v)
+    }
+
+    fn remove(&mut self, k: &K) -> io::Result<Option<V>> {
+        HAMT::remove(self, k)
+    }
+
+    fn insert_batch<I>(&mut self, pairs: I) -> io::Result<()>
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        HAMT::insert_batch(self, pairs)
+    }
+
+    fn get(&self, k: &K) -> io::Result<Option<ValPath<'_, K, V, Self, H>>> {
+        HAMT::get(self, k)
+    }
+
+    fn get_mut(
+        &mut self,
+        k: &K,
+    ) -> io::Result<Option<ValPathMut<'_, K, V, Self, H>>> {
+        HAMT::get_mut(self, k)
+    }
+
+    fn search_mut(
+        &mut self,
+        k: &K,
+    ) -> io::Result<Result<ValPathMut<'_, K, V, Self, H>, &mut Self>> {
+        ValPathMut::search(self, &mut HAMTSearch::from(k))
+    }
+}
+
+impl<K, V, A, H, const N: usize> Content<H> for HAMT<K, V, A, H, N>
 where
     K: Content<H>,
     V: Content<H>,
     A: Annotation<KV<K, V>, H>,
     H: ByteHash,
 {
+    // The occupied slots are written as a big endian bit mask. From format
+    // version `COMPACT` on, the mask is followed by a bit mask of which of
+    // the occupied slots hold nodes, the leaves without their tags, and then
+    // the nodes. Earlier versions follow the mask with the tagged handles,
+    // and are still written to stores reproducing old roots.
     fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
-        let mut mask = 0u16;
-        for i in 0..N_BUCKETS {
-            if let HandleType::None = self.0[i].handle_type() {
-                // no-op
-            } else {
-                mask |= 1 << i;
+        let len = FanOut::<N>::MASK_LEN;
+        let mut mask = [0u8; 32];
+        let mut nodes = [0u8; 32];
+        let mut occupied = 0;
+        for (i, child) in self.occupied().zip(&self.children) {
+            let node = match child.handle_type() {
+                HandleType::None => continue,
+                HandleType::Leaf => false,
+                HandleType::Node => true,
+            };
+            mask[len - 1 - i / 8] |= 1 << (i % 8);
+            if node {
+                nodes[occupied / 8] |= 1 << (occupied % 8);
             }
+            occupied += 1;
         }
 
-        <u16 as Content<H>>::persist(&mut mask, sink)?;
+        sink.write_all(&mask[..len])?;
+        if sink.version() < COMPACT {
+            for child in self.children.iter_mut() {
+                if child.handle_type() != HandleType::None {
+                    child.persist(sink)?
+                }
+            }
+            return Ok(());
+        }
+        sink.write_all(&nodes[..(occupied + 7) / 8])?;
 
-        for (i, handle) in self.0.iter_mut().enumerate() {
-            if mask & (1 << i) != 0 {
-                handle.persist(sink)?
+        for kind in &[HandleType::Leaf, HandleType::Node] {
+            for child in self.children.iter_mut() {
+                if child.handle_type() == *kind {
+                    child.persist_untagged(sink)?
+                }
             }
         }
         Ok(())
     }
 
     fn restore(source: &mut Source<H>) -> io::Result<Self> {
-        let mut bucket: [Handle<Self, H>; N_BUCKETS] = Default::default();
-        let mask = <u16 as Content<H>>::restore(source)?;
-        for (i, handle) in bucket.iter_mut().enumerate() {
-            if mask & (1 << i) != 0 {
-                *handle = Handle::restore(source)?
+        let len = FanOut::<N>::MASK_LEN;
+        let mut mask = [0u8; 32];
+        source.read_exact(&mut mask[..len])?;
+        let mut node = Self::default();
+        for i in 0..N {
+            if mask[len - 1 - i / 8] & (1 << (i % 8)) != 0 {
+                node.slots.insert(i);
+            }
+        }
+        let occupied = node.occupied().count();
+
+        if source.version() < COMPACT {
+            for _ in 0..occupied {
+                node.children.push(Handle::restore(source)?)
             }
+            return Ok(node);
         }
-        Ok(HAMT(bucket))
+
+        let mut nodes = [0u8; 32];
+        source.read_exact(&mut nodes[..(occupied + 7) / 8])?;
+        let is_node = |j: usize| nodes[j / 8] & (1 << (j % 8)) != 0;
+        node.children = (0..occupied).map(|_| Handle::new_empty()).collect();
+        for j in (0..occupied).filter(|j| !is_node(*j)) {
+            node.children[j] = Handle::restore_leaf(source)?
+        }
+        for j in (0..occupied).filter(|j| is_node(*j)) {
+            node.children[j] = Handle::restore_node(source)?
+        }
+        Ok(node)
+    }
+
+    fn reach<R: Reach<H>>(&self, reach: &mut R) -> io::Result<()> {
+        reach_children(self, reach)
+    }
+}
+
+impl<K, V, A, H, const N: usize> fmt::Debug for HAMT<K, V, A, H, N>
+where
+    K: Content<H> + fmt::Debug,
+    V: Content<H> + fmt::Debug,
+    A: Annotation<KV<K, V>, H>,
+    H: ByteHash,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Summary::new("HAMT", self).fmt(f)
     }
 }
 
-impl<K, V, A, H> Compound<H> for HAMT<K, V, A, H>
+impl<K, V, A, H, const N: usize> Compound<H> for HAMT<K, V, A, H, N>
 where
     K: Content<H>,
     V: Content<H>,
@@ -346,11 +732,11 @@ where
     type Annotation = A;
 
     fn children_mut(&mut self) -> &mut [Handle<Self, H>] {
-        &mut self.0
+        &mut self.children
     }
 
     fn children(&self) -> &[Handle<Self, H>] {
-        &self.0
+        &self.children
     }
 }
 
@@ -378,6 +764,46 @@ mod test {
         }
     }
 
+    #[test]
+    fn try_insert() {
+        let mut h = HAMT::<_, _, VoidAnnotation, Blake2b>::new();
+        for i in 0..1024 {
+            assert!(h.try_insert(i, i).unwrap().is_ok());
+        }
+        for i in 0..1024 {
+            let mut err = h.try_insert(i, i + 1).unwrap().unwrap_err();
+            assert_eq!(*err.existing, i);
+            assert_eq!(err.value, i + 1);
+            *err.existing = i + 2;
+        }
+        for i in 0..1024 {
+            assert_eq!(*h.get(&i).unwrap().unwrap(), i + 2);
+        }
+    }
+
+    #[test]
+    fn replace_and_swap() {
+        let store = kelvin::Store::<Blake2b>::ephemeral();
+        let mut h = CountingHAMTMap::new();
+        let mut expected = CountingHAMTMap::new();
+        for i in 0..1024u64 {
+            h.insert(i, i).unwrap();
+            expected.insert(i, 1023 - i).unwrap();
+        }
+        assert_eq!(h.replace(&2048, 0).unwrap(), None);
+        assert!(h.get(&2048).unwrap().is_none());
+        assert!(!h.swap_values(&0, &2048).unwrap());
+
+        for i in 0..512 {
+            assert!(h.swap_values(&i, &(1023 - i)).unwrap());
+        }
+        assert_eq!(h.replace(&0, 1023).unwrap(), Some(1023));
+        assert_eq!(
+            store.persist(&mut h).unwrap().hash(),
+            store.persist(&mut expected).unwrap().hash(),
+        );
+    }
+
     #[test]
     fn borrowed_keys() {
         let mut map = HAMT::<String, u8, VoidAnnotation, Blake2b>::new();
@@ -408,5 +834,208 @@ mod test {
         }
     }
 
+    #[test]
+    fn bounded_debug() {
+        let store = kelvin::Store::<Blake2b>::ephemeral();
+        let mut h = HAMT::<_, _, VoidAnnotation, Blake2b>::new();
+        for i in 0..10_000u64 {
+            h.insert(i, i).unwrap();
+        }
+        let snapshot = store.persist(&mut h).unwrap();
+        let h = store.restore(&snapshot).unwrap();
+
+        let debug = format!("{:?}", h);
+        assert!(debug.starts_with("HAMT { children: [Node("));
+        assert!(debug.ends_with(", ..] }"));
+        assert!(debug.len() < 1024);
+    }
+
+    #[test]
+    fn collapse_deep_collision() {
+        use kelvin::tests::fixture::{self, Shape};
+
+        let path = |k: &u64| -> Vec<usize> {
+            let hash = portable_hash::<Blake2b, _>(k);
+            (0..8)
+                .map(|d| select_slot::<16>(hash.as_ref(), d))
+                .collect()
+        };
+        // two keys sharing the slots of the first six levels
+        let (a, b) = (0u64, (1u64..).find(|k| path(k)[..6] == path(&0)[..6]));
+        let b = b.unwrap();
+        let (pa, pb) = (path(&a), path(&b));
+        assert_ne!(pa[6], pb[6]);
+
+        let mut h = HAMT::<u64, u64, VoidAnnotation, Blake2b>::new();
+        h.insert(a, a).unwrap();
+        h.insert(b, b).unwrap();
+
+        // children are packed, the shared levels hold a single one
+        let mut leaves = vec![(pa[6], KV::new(a, a)), (pb[6], KV::new(b, b))];
+        leaves.sort_by_key(|(slot, _)| *slot);
+        let mut bucket = Shape::Node(
+            leaves
+                .into_iter()
+                .enumerate()
+                .map(|(i, (_, kv))| (i, Shape::Leaf(kv)))
+                .collect(),
+        );
+        for _ in 0..6 {
+            bucket = Shape::Node(vec![(0, bucket)]);
+        }
+        assert_eq!(bucket.depth(), 7);
+        assert_eq!(fixture::shape(&h).unwrap(), bucket);
+        assert_eq!(*h.get(&a).unwrap().unwrap(), a);
+        assert_eq!(*h.get(&b).unwrap().unwrap(), b);
+
+        // the whole path collapses into a leaf at the root
+        assert_eq!(h.remove(&a).unwrap(), Some(a));
+        assert_eq!(
+            fixture::shape(&h).unwrap(),
+            Shape::Node(vec![(0, Shape::Leaf(KV::new(b, b)))])
+        );
+        assert!(h.get(&a).unwrap().is_none());
+        assert_eq!(*h.get(&b).unwrap().unwrap(), b);
+
+        assert_eq!(h.remove(&b).unwrap(), Some(b));
+        assert!(h.children().is_empty());
+    }
+
+    #[test]
+    fn insert_batch() {
+        let store = Store::<Blake2b>::ephemeral();
+
+        let mut one_by_one = CountingHAMTMap::<u64, u64, Blake2b>::new();
+        let mut batched = CountingHAMTMap::<u64, u64, Blake2b>::new();
+        for i in 0..500 {
+            one_by_one.insert(i, i).unwrap();
+            batched.insert(i, i).unwrap();
+        }
+        // replacing values, and with duplicate keys in the batch
+        let pairs: Vec<_> = (250..2000)
+            .map(|i| (i, i * 2))
+            .chain((1000..1100).map(|i| (i, 0)))
+            .collect();
+        for (k, v) in pairs.clone() {
+            one_by_one.insert(k, v).unwrap();
+        }
+        batched.insert_batch(pairs).unwrap();
+
+        assert_eq!(batched.count(), 2000);
+        assert_eq!(*batched.get(&100).unwrap().unwrap(), 100);
+        assert_eq!(*batched.get(&300).unwrap().unwrap(), 600);
+        assert_eq!(*batched.get(&1050).unwrap().unwrap(), 0);
+        assert!(
+            store.persist(&mut one_by_one).unwrap().hash()
+                == store.persist(&mut batched).unwrap().hash()
+        );
+
+        // into a restored map
+        let snapshot = store.persist(&mut batched).unwrap();
+        let mut restored = store.restore(&snapshot).unwrap();
+        restored.insert_batch((2000..3000).map(|i| (i, i))).unwrap();
+        for i in 2000..3000 {
+            one_by_one.insert(i, i).unwrap();
+        }
+        assert!(
+            store.persist(&mut one_by_one).unwrap().hash()
+                == store.persist(&mut restored).unwrap().hash()
+        );
+    }
+
+    #[test]
+    fn legacy_nodes_restore() {
+        use std::hash::Hasher;
+
+        use kelvin::{Backend, ByteHashState, MemBackend};
+
+        // a root of three leaves in distinct slots, with tagged handles
+        let mut keys: Vec<u64> = vec![0, 1, 3];
+        let slot = |k: &u64| {
+            select_slot::<16>(portable_hash::<Blake2b, _>(k).as_ref(), 0)
+        };
+        keys.sort_by_key(slot);
+        let mask = keys.iter().fold(0u16, |mask, k| mask | (1 << slot(k)));
+        let mut body = mask.to_be_bytes().to_vec();
+        for k in &keys {
+            body.push(1);
+            body.extend_from_slice(&k.to_be_bytes());
+            body.extend_from_slice(&(k * 2).to_be_bytes());
+        }
+
+        // without a header, and with the header of format version 1
+        let mut backend = MemBackend::new();
+        let mut digests = vec![];
+        for header in &[&[][..], &[0xce, 1][..]] {
+            let bytes = [*header, &body[..]].concat();
+            let mut state = Blake2b::state();
+            state.write(&bytes);
+            let digest = state.fin();
+            backend.put(digest, bytes).unwrap();
+            digests.push(digest);
+        }
+        let store = Store::from_backend(backend);
+        store.set_legacy_reads(true);
+
+        // and are persisted in the compact layout from then on
+        let mut fresh = DefaultHAMTMap::<u64, u64, Blake2b>::new();
+        for k in &keys {
+            fresh.insert(*k, k * 2).unwrap();
+        }
+        fresh.insert(7, 7).unwrap();
+        let compact = *store.persist(&mut fresh).unwrap().hash();
+
+        for digest in &digests {
+            let mut legacy: DefaultHAMTMap<u64, u64, Blake2b> =
+                store.snapshot(digest).restore().unwrap();
+            for k in &keys {
+                assert_eq!(*legacy.get(k).unwrap().unwrap(), k * 2);
+            }
+            legacy.insert(7, 7).unwrap();
+            assert!(*store.persist(&mut legacy).unwrap().hash() == compact);
+        }
+    }
+
+    fn fan_out<const N: usize>() {
+        let store = Store::<Blake2b>::ephemeral();
+        let mut h = HAMT::<u64, u64, Cardinality<u64>, Blake2b, N>::new();
+        for i in 0..2048 {
+            h.insert(i, i).unwrap();
+        }
+        for i in 0..1024 {
+            assert_eq!(h.remove(&i).unwrap(), Some(i));
+        }
+        let snapshot = store.persist(&mut h).unwrap();
+        let restored: HAMT<u64, u64, Cardinality<u64>, Blake2b, N> =
+            store.restore(&snapshot).unwrap();
+        assert_eq!(restored.count(), 1024);
+        for i in 0..2048 {
+            let found = restored.get(&i).unwrap().map(|v| *v);
+            assert_eq!(found, if i < 1024 { None } else { Some(i) });
+        }
+    }
+
+    #[test]
+    fn fan_outs() {
+        fan_out::<4>();
+        fan_out::<8>();
+        fan_out::<16>();
+        fan_out::<64>();
+        fan_out::<256>();
+    }
+
+    #[test]
+    fn wider_tries_are_shallower() {
+        use kelvin::annotations::{Depth, MaxDepth};
+
+        let mut narrow = HAMT::<u64, u64, Depth, Blake2b, 4>::new();
+        let mut wide = HAMT::<u64, u64, Depth, Blake2b, 256>::new();
+        for i in 0..4096 {
+            narrow.insert(i, i).unwrap();
+            wide.insert(i, i).unwrap();
+        }
+        assert!(wide.depth() < narrow.depth());
+    }
+
     quickcheck_map!(|| CountingHAMTMap::new());
 }
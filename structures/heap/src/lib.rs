@@ -0,0 +1,297 @@
+//! A persistent max-heap implemented on kelvin
+//!
+//! Values are kept in a tree that is filled from the left, like a vector, and
+//! every node is annotated with the largest value below it. The largest value
+//! of the heap is thus known from the annotations of the root alone, and is
+//! removed by following the annotations down to it, and replacing it with
+//! the last value of the tree.
+#![warn(missing_docs)]
+
+use std::borrow::Borrow;
+use std::fmt;
+use std::io;
+use std::mem;
+
+use arrayvec::ArrayVec;
+
+use kelvin::{
+    annotation,
+    annotations::{Annotation, Cardinality, Count, Counter, Max, MaxKeyType},
+    reach_children, AppendOnly, ByteHash, Compound, Content, Handle, HandleMut,
+    LeafIterable, Reach, Sink, Source, Summary,
+};
+
+annotation! {
+    /// The annotation of the heap, keeping track of its largest value and
+    /// length
+    pub struct HeapAnnotation<T, U> {
+        max: Max<T>,
+        count: Cardinality<U>,
+    }
+    where
+        T: MaxKeyType,
+        U: Counter
+}
+
+/// The default heap
+pub type DefaultHeap<T, H> = Heap<T, HeapAnnotation<T, u64>, H>;
+
+const N: usize = 4;
+
+/// A persistent max-heap
+#[derive(Clone)]
+pub struct Heap<T, A, H: ByteHash>(ArrayVec<[Handle<Self, H>; N]>)
+where
+    Self: Compound<H>;
+
+impl<T, A, H> Default for Heap<T, A, H>
+where
+    T: Content<H>,
+    A: Annotation<T, H>,
+    H: ByteHash,
+{
+    fn default() -> Self {
+        Heap(Default::default())
+    }
+}
+
+impl<T, A, H> Heap<T, A, H>
+where
+    T: Content<H> + MaxKeyType,
+    A: Annotation<T, H> + Borrow<Max<T>> + Borrow<Cardinality<u64>>,
+    H: ByteHash,
+{
+    /// Creates a new, empty, heap
+    pub fn new() -> Self {
+        Heap(Default::default())
+    }
+
+    /// Returns the number of values in the heap
+    pub fn len(&self) -> u64 {
+        self.count()
+    }
+
+    /// Returns true if the heap holds no values
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Adds a value to the heap
+    pub fn push(&mut self, t: T) -> io::Result<()> {
+        AppendOnly::append(self, t)
+    }
+
+    /// Returns the largest value of the heap
+    pub fn peek_max(&self) -> Option<T> {
+        self.annotation().map(|ann| {
+            let max: &Max<T> = ann.borrow();
+            (**max).clone()
+        })
+    }
+
+    /// Removes the largest value of the heap, returning it
+    pub fn pop_max(&mut self) -> io::Result<Option<T>> {
+        let last = match AppendOnly::pop_last(self)? {
+            Some(last) => last,
+            None => return Ok(None),
+        };
+        match self.peek_max() {
+            Some(max) if max > last => self.replace_max(last).map(Some),
+            _ => Ok(Some(last)),
+        }
+    }
+
+    /// Returns an iterator over the values, in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = io::Result<&T>> {
+        LeafIterable::iter(self)
+    }
+
+    // Replaces the largest value with `t`, returning it
+    fn replace_max(&mut self, t: T) -> io::Result<T> {
+        let mut found: Option<(usize, T)> = None;
+        for (i, handle) in self.0.iter().enumerate() {
+            if let Some(ann) = handle.annotation() {
+                let max: &Max<T> = (*ann).borrow();
+                match found {
+                    Some((_, ref best)) if best >= &**max => (),
+                    _ => found = Some((i, (**max).clone())),
+                }
+            }
+        }
+        let i = match found {
+            Some((i, _)) => i,
+            None => unreachable!("replace_max on empty heap"),
+        };
+        match &mut *self.0[i].inner_mut()? {
+            HandleMut::Leaf(leaf) => Ok(mem::replace(*leaf, t)),
+            HandleMut::Node(node) => node.replace_max(t),
+            HandleMut::None => unreachable!(),
+        }
+    }
+}
+
+impl<T, A, H> Content<H> for Heap<T, A, H>
+where
+    T: Content<H>,
+    A: Annotation<T, H>,
+    H: ByteHash,
+{
+    fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
+        (self.0.len() as u8).persist(sink)?;
+        for h in &mut self.0 {
+            h.persist(sink)?
+        }
+        Ok(())
+    }
+
+    fn restore(source: &mut Source<H>) -> io::Result<Self> {
+        let mut heap = Heap::default();
+        let len = u8::restore(source)?;
+        if len as usize > N {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Too many children in Heap node",
+            ));
+        }
+        for _ in 0..len {
+            heap.0.push(Handle::restore(source)?);
+        }
+        Ok(heap)
+    }
+
+    fn reach<R: Reach<H>>(&self, reach: &mut R) -> io::Result<()> {
+        reach_children(self, reach)
+    }
+}
+
+impl<T, A, H> fmt::Debug for Heap<T, A, H>
+where
+    T: Content<H> + fmt::Debug,
+    A: Annotation<T, H>,
+    H: ByteHash,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Summary::new("Heap", self).fmt(f)
+    }
+}
+
+impl<T, A, H> Compound<H> for Heap<T, A, H>
+where
+    T: Content<H>,
+    A: Annotation<T, H>,
+    H: ByteHash,
+{
+    type Leaf = T;
+
+    type Annotation = A;
+
+    fn children_mut(&mut self) -> &mut [Handle<Self, H>] {
+        &mut self.0
+    }
+
+    fn children(&self) -> &[Handle<Self, H>] {
+        &self.0
+    }
+}
+
+impl<T, A, H> AppendOnly<H> for Heap<T, A, H>
+where
+    T: Content<H>,
+    A: Annotation<T, H>,
+    H: ByteHash,
+{
+    fn is_full(&self) -> bool {
+        self.0.is_full()
+    }
+
+    fn push_child(&mut self, child: Handle<Self, H>) {
+        self.0.push(child)
+    }
+
+    fn pop_child(&mut self) -> Option<Handle<Self, H>> {
+        self.0.pop()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use kelvin::{tests::CorrectEmptyState, Blake2b, Store};
+    use kelvin_hamt::DefaultHAMTMap;
+
+    type Heap64 = DefaultHeap<u64, Blake2b>;
+
+    // deterministic, but scrambled, order of insertion
+    fn scrambled(n: u64) -> impl Iterator<Item = u64> {
+        (0..n).map(move |i| (i * 7919) % n)
+    }
+
+    #[test]
+    fn push_pop_max() {
+        let mut heap = Heap64::new();
+        assert_eq!(heap.peek_max(), None);
+        for (i, t) in scrambled(1000).enumerate() {
+            heap.push(t).unwrap();
+            assert_eq!(heap.len(), i as u64 + 1);
+        }
+        assert_eq!(heap.peek_max(), Some(999));
+        for i in (0..1000).rev() {
+            assert_eq!(heap.pop_max().unwrap(), Some(i));
+            assert_eq!(heap.len(), i);
+        }
+        assert_eq!(heap.pop_max().unwrap(), None);
+        heap.assert_correct_empty_state();
+    }
+
+    #[test]
+    fn duplicates() {
+        let mut heap = Heap64::new();
+        for t in scrambled(100) {
+            heap.push(t / 10).unwrap();
+        }
+        let mut popped = vec![];
+        while let Some(t) = heap.pop_max().unwrap() {
+            popped.push(t);
+        }
+        let mut expected: Vec<u64> = (0..100).map(|t| t / 10).collect();
+        expected.sort_by(|a, b| b.cmp(a));
+        assert_eq!(popped, expected);
+    }
+
+    #[test]
+    fn persisted() {
+        let store = Store::<Blake2b>::ephemeral();
+        let mut heap = Heap64::new();
+        for t in scrambled(1000) {
+            heap.push(t).unwrap();
+        }
+        let snapshot = store.persist(&mut heap).unwrap();
+        let mut restored = store.restore(&snapshot).unwrap();
+        assert_eq!(restored.peek_max(), Some(999));
+        for i in (900..1000).rev() {
+            assert_eq!(restored.pop_max().unwrap(), Some(i));
+        }
+        assert_eq!(restored.len(), 900);
+    }
+
+    #[test]
+    fn nested() {
+        let store = Store::<Blake2b>::ephemeral();
+        let mut map = DefaultHAMTMap::<u64, Heap64, Blake2b>::new();
+        for k in 0..10 {
+            let mut heap = Heap64::new();
+            for t in scrambled(100) {
+                heap.push(t * k).unwrap();
+            }
+            map.insert(k, heap).unwrap();
+        }
+        let snapshot = store.persist(&mut map).unwrap();
+        let mut restored = store.restore(&snapshot).unwrap();
+        for k in 0..10 {
+            let mut heap = restored.get_mut(&k).unwrap().unwrap();
+            assert_eq!(heap.pop_max().unwrap(), Some(99 * k));
+            assert_eq!(heap.len(), 99);
+        }
+    }
+}
@@ -1,6 +1,12 @@
 //! A Radix trie implemented on kelvin
+//!
+//! Keys are byte slices, read as nibbles. Each node branches on one nibble,
+//! and stores the nibbles shared by all keys below a branch as its prefix, so
+//! paths without branches take no nodes of their own. Keys may be prefixes of
+//! other keys, their values are kept in the leaf position of a node.
 #![warn(missing_docs)]
 
+use std::fmt;
 use std::io::{self};
 use std::mem;
 
@@ -8,10 +14,12 @@ mod nibbles;
 
 use nibbles::{AsNibbles, NibbleBuf, Nibbles};
 
+use kelvin::proof::{self, Proof};
 use kelvin::{
     annotations::{Annotation, VoidAnnotation},
-    ByteHash, Compound, Content, Handle, HandleMut, HandleType, Method,
-    SearchResult, Sink, Source, ValPath, ValPathMut,
+    reach_children, ByteHash, Compound, Content, Handle, HandleMut, HandleType,
+    MapMut, Method, OccupiedError, Reach, SearchResult, Sink, Source, Summary,
+    ValPath, ValPathMut,
 };
 
 const N_BUCKETS: usize = 17;
@@ -106,10 +114,11 @@ where
     }
 }
 
-enum Removed<L> {
+enum Removed<L, N> {
     None,
     Leaf(L),
-    Collapse(L, L, NibbleBuf, usize),
+    // the removed leaf, and the single handle left in the node
+    Collapse(L, N, NibbleBuf, usize),
 }
 
 impl<K, V, A, H> Radix<K, V, A, H>
@@ -143,26 +152,61 @@ where
         ValPathMut::new(self, &mut Nibbles::from(k.as_ref()))
     }
 
+    /// Proves the inclusion of the value at key, if present
+    pub fn prove<O>(&self, k: &O) -> io::Result<Option<Proof<Self, H>>>
+    where
+        O: ?Sized + AsRef<[u8]>,
+    {
+        proof::prove(self, &mut Nibbles::from(k.as_ref()))
+    }
+
     /// Insert key-value pair into the Radix, optionally returning expelled value
     pub fn insert(&mut self, k: K, v: V) -> io::Result<Option<V>> {
         debug_assert!(k.as_ref().len() <= MAX_KEY_LEN);
         let mut search = Nibbles::new(k.as_ref());
-        self._insert(&mut search, v)
+        match self._insert(&mut search, v, true)? {
+            Ok(replaced) => Ok(replaced),
+            Err(_) => unreachable!(),
+        }
+    }
+
+    /// Insert key-value pair into the Radix, unless the key is already present
+    pub fn try_insert(
+        &mut self,
+        k: K,
+        v: V,
+    ) -> io::Result<Result<(), OccupiedError<'_, K, V, Self, H>>> {
+        debug_assert!(k.as_ref().len() <= MAX_KEY_LEN);
+        let mut search = Nibbles::new(k.as_ref());
+        match self._insert(&mut search, v, false)? {
+            Ok(_) => Ok(Ok(())),
+            Err(value) => {
+                let existing = self.get_mut(&k)?.expect("key present");
+                Ok(Err(OccupiedError { existing, value }))
+            }
+        }
     }
 
-    fn _insert(&mut self, search: &mut Nibbles, v: V) -> io::Result<Option<V>> {
+    // Returns the value back if the key is present, and `replace` is false
+    fn _insert(
+        &mut self,
+        search: &mut Nibbles,
+        v: V,
+        replace: bool,
+    ) -> io::Result<Result<Option<V>, V>> {
         // Leaf case, for keys that are subsets of other keys
         if search.len() == 0 {
             match self.handles[0].handle_type() {
                 HandleType::None => {
                     self.handles[0] = Handle::new_leaf(v);
-                    return Ok(None);
+                    return Ok(Ok(None));
                 }
+                HandleType::Leaf if !replace => return Ok(Err(v)),
                 HandleType::Leaf => {
-                    return Ok(Some(
+                    return Ok(Ok(Some(
                         mem::replace(&mut self.handles[0], Handle::new_leaf(v))
                             .into_leaf(),
-                    ));
+                    )));
                 }
                 HandleType::Node => unreachable!("Invalid in Leaf position"),
             }
@@ -180,13 +224,32 @@ where
             let leaf = Handle::new_leaf(v);
             self.prefixes[i - 1] = (*search).into();
             self.handles[i] = leaf;
-            return Ok(None);
-        } else if common.len() == search.len() && common.len() == path_len {
+            return Ok(Ok(None));
+        } else if common.len() == search.len()
+            && common.len() == path_len
+            && self.handles[i].handle_type() == HandleType::Leaf
+        {
             // found the leaf
+            if !replace {
+                return Ok(Err(v));
+            }
             let leaf = Handle::new_leaf(v);
-            return Ok(Some(
+            return Ok(Ok(Some(
                 mem::replace(&mut self.handles[i], leaf).into_leaf(),
-            ));
+            )));
+        } else if common.len() == path_len
+            && self.handles[i].handle_type() == HandleType::Leaf
+        {
+            // the key of the leaf is a prefix of the new key, the leaf is
+            // pushed down into the leaf position of a new node
+            let mut new_node = Self::new();
+            new_node.handles[0] = mem::take(&mut self.handles[i]);
+
+            search.trim_front(common.len());
+            let _ = new_node._insert(search, v, replace)?;
+
+            self.handles[i] = Handle::new_node(new_node);
+            return Ok(Ok(None));
         } else if common.len() < path_len {
             // we need to split
             let mut old_path = mem::take(&mut self.prefixes[i - 1]);
@@ -204,19 +267,19 @@ where
 
             // insert into new node
             search.trim_front(common.len());
-            new_node._insert(search, v)?;
+            let _ = new_node._insert(search, v, replace)?;
 
             self.handles[i] = Handle::new_node(new_node);
             self.prefixes[i - 1] = common;
 
-            return Ok(None);
+            return Ok(Ok(None));
         } else {
             // recurse
             if let HandleMut::Node(ref mut node) =
                 *self.handles[i].inner_mut()?
             {
                 search.trim_front(common.len());
-                node._insert(search, v)
+                node._insert(search, v, replace)
             } else {
                 unreachable!()
             }
@@ -241,7 +304,7 @@ where
         &mut self,
         search: &mut Nibbles,
         depth: usize,
-    ) -> io::Result<Removed<V>> {
+    ) -> io::Result<Removed<V, Handle<Self, H>>> {
         let collapse = {
             if search.len() == 0 {
                 match self.handles[0].handle_type() {
@@ -249,9 +312,18 @@ where
                         return Ok(Removed::None);
                     }
                     HandleType::Leaf => {
-                        return Ok(Removed::Leaf(
-                            mem::take(&mut self.handles[0]).into_leaf(),
-                        ));
+                        let removed =
+                            mem::take(&mut self.handles[0]).into_leaf();
+                        if depth > 0 {
+                            if let Some((l, prefixes, inner_i)) =
+                                self.remove_singleton()
+                            {
+                                return Ok(Removed::Collapse(
+                                    removed, l, prefixes, inner_i,
+                                ));
+                            }
+                        }
+                        return Ok(Removed::Leaf(removed));
                     }
                     HandleType::Node => {
                         unreachable!("Invalid in Leaf position")
@@ -269,7 +341,10 @@ where
 
             if self.handles[i].handle_type() == HandleType::None {
                 return Ok(Removed::None);
-            } else if common.len() == search.len() && common.len() == path_len {
+            } else if common.len() == search.len()
+                && common.len() == path_len
+                && self.handles[i].handle_type() == HandleType::Leaf
+            {
                 // found the leaf
                 self.prefixes[i - 1] = Default::default();
                 let removed = mem::take(&mut self.handles[i]).into_leaf();
@@ -283,7 +358,9 @@ where
                     }
                 }
                 return Ok(Removed::Leaf(removed));
-            } else if common.len() < path_len {
+            } else if common.len() < path_len
+                || self.handles[i].handle_type() == HandleType::Leaf
+            {
                 // nothing here
                 return Ok(Removed::None);
             } else {
@@ -318,33 +395,80 @@ where
 
         self.prefixes[i - 1].append(&nibbles);
 
-        self.handles[i] = Handle::new_leaf(reinsert);
-
-        // recursion needed?
+        self.handles[i] = reinsert;
 
-        return Ok(Removed::Leaf(removed));
+        // the node might in turn be left with a single handle
+        if depth > 0 {
+            if let Some((h, prefixes, inner_i)) = self.remove_singleton() {
+                return Ok(Removed::Collapse(removed, h, prefixes, inner_i));
+            }
+        }
+        Ok(Removed::Leaf(removed))
     }
 
-    fn remove_singleton(&mut self) -> Option<(V, NibbleBuf, usize)> {
+    // Takes the handle out of a node with a single one left
+    fn remove_singleton(
+        &mut self,
+    ) -> Option<(Handle<Self, H>, NibbleBuf, usize)> {
         let mut singleton = None;
 
         for (i, child) in self.handles.iter().enumerate() {
             match (child.handle_type(), singleton) {
                 (HandleType::None, _) => (),
-                (HandleType::Leaf, None) => singleton = Some(i),
-                (HandleType::Leaf, Some(_)) => return None,
-                (HandleType::Node, _) => return None,
+                (_, None) => singleton = Some(i),
+                (_, Some(_)) => return None,
             }
         }
-        if let Some(idx) = singleton {
-            Some((
-                mem::take(&mut self.handles[idx]).into_leaf(),
-                mem::take(&mut self.prefixes[idx - 1]),
-                idx,
-            ))
-        } else {
-            None
-        }
+        singleton.map(|idx| {
+            // the leaf position has no prefix
+            let prefix = match idx {
+                0 => NibbleBuf::default(),
+                _ => mem::take(&mut self.prefixes[idx - 1]),
+            };
+            (mem::take(&mut self.handles[idx]), prefix, idx)
+        })
+    }
+}
+
+impl<K, V, A, H> MapMut<K, V, H> for Radix<K, V, A, H>
+where
+    K: AsRef<[u8]> + Eq + 'static,
+    V: Content<H>,
+    A: Annotation<V, H>,
+    H: ByteHash,
+{
+    fn insert(&mut self, k: K, v: V) -> io::Result<Option<V>> {
+        Radix::insert(self, k, v)
+    }
+
+    fn try_insert(
+        &mut self,
+        k: K,
+        v: V,
+    ) -> io::Result<Result<(), OccupiedError<'_, K, V, Self, H>>> {
+        Radix::try_insert(self, k, v)
+    }
+
+    fn remove(&mut self, k: &K) -> io::Result<Option<V>> {
+        Radix::remove(self, k)
+    }
+
+    fn get(&self, k: &K) -> io::Result<Option<ValPath<'_, K, V, Self, H>>> {
+        Radix::get(self, k)
+    }
+
+    fn get_mut(
+        &mut self,
+        k: &K,
+    ) -> io::Result<Option<ValPathMut<'_, K, V, Self, H>>> {
+        Radix::get_mut(self, k)
+    }
+
+    fn search_mut(
+        &mut self,
+        k: &K,
+    ) -> io::Result<Result<ValPathMut<'_, K, V, Self, H>, &mut Self>> {
+        ValPathMut::search(self, &mut Nibbles::from(k.as_ref()))
     }
 }
 
@@ -378,6 +502,22 @@ where
         }
         Ok(Radix { handles, prefixes })
     }
+
+    fn reach<R: Reach<H>>(&self, reach: &mut R) -> io::Result<()> {
+        reach_children(self, reach)
+    }
+}
+
+impl<K, V, A, H> fmt::Debug for Radix<K, V, A, H>
+where
+    K: 'static,
+    V: Content<H> + fmt::Debug,
+    A: Annotation<V, H>,
+    H: ByteHash,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Summary::new("Radix", self).fmt(f)
+    }
 }
 
 impl<K, V, A, H> Compound<H> for Radix<K, V, A, H>
@@ -431,6 +571,58 @@ mod test {
         }
     }
 
+    #[test]
+    fn try_insert() {
+        let mut h = Radix::<_, _, VoidAnnotation, Blake2b>::new();
+        for key in &["cat", "mouse", "m", "dog", ""] {
+            assert!(h.try_insert(*key, key.len() as u64).unwrap().is_ok());
+        }
+        for key in &["cat", "mouse", "m", "dog", ""] {
+            let mut err = h.try_insert(*key, 9).unwrap().unwrap_err();
+            assert_eq!(*err.existing, key.len() as u64);
+            assert_eq!(err.value, 9);
+            *err.existing = 7;
+        }
+        for key in &["cat", "mouse", "m", "dog", ""] {
+            assert_eq!(*h.get(key).unwrap().unwrap(), 7);
+        }
+    }
+
+    #[test]
+    fn replace_and_swap() {
+        let mut h = Radix::<_, _, Cardinality<u64>, Blake2b>::new();
+        for key in &["cat", "mouse", "m", "dog", ""] {
+            h.insert(*key, key.len() as u64).unwrap();
+        }
+        assert_eq!(h.replace(&"eel", 0).unwrap(), None);
+        assert!(h.get("eel").unwrap().is_none());
+        assert!(!h.swap_values(&"cat", &"eel").unwrap());
+
+        assert!(h.swap_values(&"mouse", &"").unwrap());
+        assert_eq!(h.replace(&"m", 7).unwrap(), Some(1));
+        assert_eq!(*h.get("mouse").unwrap().unwrap(), 0);
+        assert_eq!(*h.get("").unwrap().unwrap(), 5);
+        assert_eq!(*h.get("m").unwrap().unwrap(), 7);
+        assert_eq!(h.count(), 5);
+    }
+
+    #[test]
+    fn inclusion_proofs() {
+        let store = kelvin::Store::<Blake2b>::ephemeral();
+        let mut h = Radix::<_, _, VoidAnnotation, Blake2b>::new();
+        for key in &["cat", "mouse", "m", "dog", ""] {
+            h.insert(*key, key.len() as u64).unwrap();
+        }
+        let root = *store.persist(&mut h).unwrap().hash();
+        for key in &["cat", "mouse", "m", "dog", ""] {
+            let proof = h.prove(key).unwrap().unwrap();
+            let len = key.len() as u64;
+            assert!(proof::verify(&root, &proof, &len).unwrap());
+            assert!(!proof::verify(&root, &proof, &(len + 1)).unwrap());
+        }
+        assert!(h.prove("eel").unwrap().is_none());
+    }
+
     #[test]
     fn insert_remove() {
         let mut h = Radix::<_, _, VoidAnnotation, Blake2b>::new();
@@ -503,6 +695,70 @@ mod test {
         assert_eq!(*h.get(&vec![0x00, 0x10]).unwrap().unwrap(), 8);
     }
 
+    #[test]
+    fn prefix_keys() {
+        let keys: [&[u8]; 6] = [b"a", b"ab", b"abc", b"", b"abd", b"b"];
+
+        let mut h = Radix::<_, _, Cardinality<u64>, Blake2b>::new();
+        for (i, key) in keys.iter().enumerate() {
+            assert_eq!(h.insert(*key, i as u64).unwrap(), None);
+            for (j, key) in keys[..=i].iter().enumerate() {
+                assert_eq!(*h.get(key).unwrap().unwrap(), j as u64);
+            }
+        }
+        assert!(h.get(b"abcd").unwrap().is_none());
+        assert!(h.get(b"aa").unwrap().is_none());
+        assert_eq!(h.remove(b"abcd").unwrap(), None);
+        assert_eq!(h.insert(&b"ab"[..], 9).unwrap(), Some(1));
+        assert_eq!(h.count(), 6);
+
+        for (i, key) in keys.iter().enumerate().rev() {
+            let i = if i == 1 { 9 } else { i as u64 };
+            assert_eq!(h.remove(key).unwrap(), Some(i));
+            assert!(h.get(key).unwrap().is_none());
+        }
+        h.assert_correct_empty_state();
+
+        // keys removed from the front, collapsing the nodes they leave
+        for (i, key) in keys.iter().enumerate() {
+            h.insert(*key, i as u64).unwrap();
+        }
+        for (i, key) in keys.iter().enumerate() {
+            assert_eq!(h.remove(key).unwrap(), Some(i as u64));
+            for (j, key) in keys.iter().enumerate().skip(i + 1) {
+                assert_eq!(*h.get(key).unwrap().unwrap(), j as u64);
+            }
+        }
+        h.assert_correct_empty_state();
+    }
+
+    #[test]
+    fn all_short_keys() {
+        let bytes = [0x00, 0x01, 0x10, 0xff];
+        let mut keys = vec![vec![]];
+        for len in 1..=3 {
+            for i in 0..bytes.len().pow(len) {
+                let key = (0..len)
+                    .map(|d| bytes[i / bytes.len().pow(d) % bytes.len()])
+                    .collect::<Vec<u8>>();
+                keys.push(key);
+            }
+        }
+
+        let mut h = Radix::<_, _, Cardinality<u64>, Blake2b>::new();
+        for (i, key) in keys.iter().enumerate().rev() {
+            h.insert(key.clone(), i as u64).unwrap();
+        }
+        assert_eq!(h.count(), keys.len() as u64);
+        for (i, key) in keys.iter().enumerate() {
+            assert_eq!(h.remove(key).unwrap(), Some(i as u64));
+            for (j, key) in keys.iter().enumerate().skip(i + 1).step_by(7) {
+                assert_eq!(*h.get(key).unwrap().unwrap(), j as u64);
+            }
+        }
+        h.assert_correct_empty_state();
+    }
+
     #[test]
     fn borrowed_keys() {
         let mut map = Radix::<String, u8, VoidAnnotation, Blake2b>::new();
@@ -4,6 +4,10 @@ use std::io::{self, Write};
 use std::mem;
 
 use kelvin::{tests::arbitrary, ByteHash, Content, Sink, Source};
+use smallvec::SmallVec;
+
+// Most prefixes are short, keep them inline in the node
+type Bytes = SmallVec<[u8; 8]>;
 
 pub trait AsNibbles {
     fn as_nibbles(&self) -> Nibbles;
@@ -117,7 +121,7 @@ impl<'a> fmt::Debug for Nibbles<'a> {
 
 #[derive(Clone, Default)]
 pub struct NibbleBuf {
-    bytes: Vec<u8>,
+    bytes: Bytes,
     ofs_front: usize,
     ofs_back: usize,
 }
@@ -126,7 +130,7 @@ impl arbitrary::Arbitrary for NibbleBuf {
     fn arbitrary(
         u: &mut arbitrary::Unstructured<'_>,
     ) -> arbitrary::Result<Self> {
-        let bytes = Vec::arbitrary(u)?;
+        let bytes = Bytes::from_vec(Vec::arbitrary(u)?);
         let (mut ofs_front, mut ofs_back);
         if bytes.len() > 0 {
             ofs_front = u16::arbitrary(u)? % (bytes.len() * 2) as u16;
@@ -178,10 +182,8 @@ impl<'a> PartialEq for Nibbles<'a> {
 impl NibbleBuf {
     #[cfg(test)]
     fn new(bytes: &[u8]) -> Self {
-        let mut vec: Vec<u8> = Vec::with_capacity(bytes.len());
-        vec.extend_from_slice(bytes);
         NibbleBuf {
-            bytes: vec,
+            bytes: bytes.into(),
             ofs_front: 0,
             ofs_back: bytes.len() * 2,
         }
@@ -302,7 +304,7 @@ where
 
         let byte_len = (ofs_back + 1) / 2;
 
-        let mut vec = Vec::with_capacity(byte_len);
+        let mut vec = Bytes::with_capacity(byte_len);
         for _ in 0..byte_len {
             vec.push(u8::restore(source)?)
         }
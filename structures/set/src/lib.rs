@@ -0,0 +1,209 @@
+//! A persistent ordered set implemented on kelvin
+//!
+//! The set is a B+ tree with no values, so members are kept in order and can
+//! be iterated over, or queried by range, without storing dummy values or
+//! handling key-value leaves.
+#![warn(missing_docs)]
+
+use std::borrow::Borrow;
+use std::fmt;
+use std::io;
+use std::ops::RangeBounds;
+
+use kelvin::{ByteHash, Content, Reach, Sink, Source};
+use kelvin_btree::DefaultBTreeMap;
+
+/// A persistent ordered set
+#[derive(Clone)]
+pub struct Set<T, H>(DefaultBTreeMap<T, (), H>)
+where
+    T: Content<H> + Ord,
+    H: ByteHash;
+
+impl<T, H> Default for Set<T, H>
+where
+    T: Content<H> + Ord,
+    H: ByteHash,
+{
+    fn default() -> Self {
+        Set(Default::default())
+    }
+}
+
+impl<T, H> Set<T, H>
+where
+    T: Content<H> + Ord,
+    H: ByteHash,
+{
+    /// Creates a new, empty, set
+    pub fn new() -> Self {
+        Set::default()
+    }
+
+    /// Adds a member to the set, returning false if it was already present
+    pub fn insert(&mut self, t: T) -> io::Result<bool> {
+        Ok(self.0.insert(t, ())?.is_none())
+    }
+
+    /// Returns true if `t` is a member of the set
+    pub fn contains<O>(&self, t: &O) -> io::Result<bool>
+    where
+        O: ?Sized + Ord + Eq,
+        T: Borrow<O>,
+    {
+        Ok(self.0.get(t)?.is_some())
+    }
+
+    /// Removes a member from the set, returning false if it was not present
+    pub fn remove<O>(&mut self, t: &O) -> io::Result<bool>
+    where
+        O: ?Sized + Ord + Eq,
+        T: Borrow<O>,
+    {
+        Ok(self.0.remove(t)?.is_some())
+    }
+
+    /// Adds all members of `other` to the set
+    pub fn union(&mut self, other: &Self) -> io::Result<()>
+    where
+        T: Clone,
+    {
+        for member in other.iter() {
+            self.insert(member?.clone())?;
+        }
+        Ok(())
+    }
+
+    /// Returns an iterator over the members, in order
+    pub fn iter(&self) -> impl Iterator<Item = io::Result<&T>> {
+        self.0.iter().map(|res| res.map(|(t, _)| t))
+    }
+
+    /// Returns an iterator over the members within `range`, in order
+    pub fn range<'a, O, R>(
+        &'a self,
+        range: R,
+    ) -> impl Iterator<Item = io::Result<&'a T>>
+    where
+        O: ?Sized + Ord + 'a,
+        T: Borrow<O>,
+        R: RangeBounds<O> + 'a,
+    {
+        self.0.range(range).map(|res| res.map(|(t, _)| t))
+    }
+
+    /// Returns true if the set has no members
+    pub fn is_empty(&self) -> io::Result<bool> {
+        Ok(self.iter().next().transpose()?.is_none())
+    }
+}
+
+impl<T, H> Content<H> for Set<T, H>
+where
+    T: Content<H> + Ord,
+    H: ByteHash,
+{
+    fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
+        self.0.persist(sink)
+    }
+
+    fn restore(source: &mut Source<H>) -> io::Result<Self> {
+        Ok(Set(DefaultBTreeMap::restore(source)?))
+    }
+
+    fn reach<R: Reach<H>>(&self, reach: &mut R) -> io::Result<()> {
+        self.0.reach(reach)
+    }
+}
+
+impl<T, H> fmt::Debug for Set<T, H>
+where
+    T: Content<H> + Ord + fmt::Debug,
+    H: ByteHash,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut set = f.debug_set();
+        for member in self.iter() {
+            match member {
+                Ok(member) => set.entry(member),
+                Err(_) => set.entry(&"<io error>"),
+            };
+        }
+        set.finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use kelvin::{Blake2b, Store};
+
+    type Set64 = Set<u64, Blake2b>;
+
+    fn members(set: &Set64) -> Vec<u64> {
+        set.iter().map(|t| *t.unwrap()).collect()
+    }
+
+    #[test]
+    fn insert_contains_remove() {
+        let mut set = Set64::new();
+        assert!(set.is_empty().unwrap());
+        for i in (0..1000).rev() {
+            assert!(set.insert(i * 2).unwrap());
+        }
+        assert!(!set.insert(42).unwrap());
+        for i in 0..2000 {
+            assert_eq!(set.contains(&i).unwrap(), i % 2 == 0);
+        }
+        let evens: Vec<u64> = (0..1000).map(|i| i * 2).collect();
+        assert_eq!(members(&set), evens);
+
+        for i in 0..1000 {
+            assert!(set.remove(&(i * 2)).unwrap());
+            assert!(!set.remove(&(i * 2)).unwrap());
+        }
+        assert!(set.is_empty().unwrap());
+    }
+
+    #[test]
+    fn union() {
+        let mut a = Set64::new();
+        let mut b = Set64::new();
+        for i in 0..100 {
+            a.insert(i * 2).unwrap();
+            b.insert(i * 3).unwrap();
+        }
+        a.union(&b).unwrap();
+
+        let mut expected: Vec<u64> = (0..100)
+            .map(|i| i * 2)
+            .chain((0..100).map(|i| i * 3))
+            .collect();
+        expected.sort();
+        expected.dedup();
+        assert_eq!(members(&a), expected);
+    }
+
+    #[test]
+    fn range() {
+        let mut set = Set64::new();
+        for i in 0..100 {
+            set.insert(i).unwrap();
+        }
+        let found: Vec<u64> = set.range(10..20).map(|t| *t.unwrap()).collect();
+        assert_eq!(found, (10..20).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn persisted() {
+        let store = Store::<Blake2b>::ephemeral();
+        let mut set = Set64::new();
+        for i in 0..1000 {
+            set.insert(i).unwrap();
+        }
+        let snapshot = store.persist(&mut set).unwrap();
+        let restored = store.restore(&snapshot).unwrap();
+        assert_eq!(members(&restored), (0..1000).collect::<Vec<_>>());
+    }
+}
@@ -2,17 +2,21 @@
 #![warn(missing_docs)]
 
 use std::borrow::Borrow;
+use std::fmt;
 use std::io;
 use std::marker::PhantomData;
 use std::mem;
+use std::ops::RangeBounds;
 
 use arrayvec::ArrayVec;
 
+use kelvin::proof::{self, Proof, RangeProof};
 use kelvin::{
     annotation,
     annotations::{Annotation, Cardinality, Counter, MaxKey, MaxKeyType},
-    ByteHash, Compound, Content, Handle, HandleMut, HandleType, Method,
-    SearchResult, Sink, Source, ValPath, ValPathMut, KV,
+    reach_children, ByteHash, Compound, Content, Handle, HandleMut, HandleType,
+    KeyOrdered, MapMut, Method, OccupiedError, Reach, SearchResult, Sink,
+    Source, Summary, ValPath, ValPathMut, KV,
 };
 
 /// The default 2-3 tree
@@ -113,6 +117,8 @@ where
 {
     Ok,
     Replaced(C::Leaf),
+    // the key was present, and not replaced
+    Occupied(C::Leaf),
     Split(Handle<C, H>),
 }
 
@@ -140,10 +146,34 @@ where
 
     /// Insert key-value pair into the Two3Tree, optionally returning expelled value
     pub fn insert(&mut self, k: K, v: V) -> io::Result<Option<V>> {
-        match self._insert(Handle::new_leaf(KV::new(k, v)), 0)? {
+        match self._insert(Handle::new_leaf(KV::new(k, v)), 0, true)? {
             InsertResult::Ok => Ok(None),
             InsertResult::Replaced(KV { key: _, val }) => Ok(Some(val)),
-            InsertResult::Split(_) => unreachable!(),
+            InsertResult::Occupied(_) | InsertResult::Split(_) => {
+                unreachable!()
+            }
+        }
+    }
+
+    /// Insert key-value pair into the Two3Tree, unless the key is already
+    /// present
+    pub fn try_insert(
+        &mut self,
+        k: K,
+        v: V,
+    ) -> io::Result<Result<(), OccupiedError<'_, K, V, Self, H>>> {
+        match self._insert(Handle::new_leaf(KV::new(k, v)), 0, false)? {
+            InsertResult::Ok => Ok(Ok(())),
+            InsertResult::Occupied(KV { key, val }) => {
+                let existing = self.get_mut(&key)?.expect("key present");
+                Ok(Err(OccupiedError {
+                    existing,
+                    value: val,
+                }))
+            }
+            InsertResult::Replaced(_) | InsertResult::Split(_) => {
+                unreachable!()
+            }
         }
     }
 
@@ -168,10 +198,36 @@ where
         ValPathMut::new(self, &mut Two3TreeSearch::from(k.borrow()))
     }
 
+    /// Proves the inclusion of the value at key, if present
+    pub fn prove<O>(&self, k: &O) -> io::Result<Option<Proof<Self, H>>>
+    where
+        O: ?Sized + Ord + Eq,
+        K: Borrow<O>,
+    {
+        proof::prove(self, &mut Two3TreeSearch::from(k.borrow()))
+    }
+
+    /// Proves which keys are within `range`
+    pub fn prove_range<R>(&self, range: &R) -> io::Result<RangeProof<Self, H>>
+    where
+        R: RangeBounds<K>,
+    {
+        proof::prove_range(self, range)
+    }
+
+    /// Proves that key is not in the tree, if absent
+    pub fn prove_absent(
+        &self,
+        k: &K,
+    ) -> io::Result<Option<RangeProof<Self, H>>> {
+        proof::prove_absent(self, k)
+    }
+
     fn _insert(
         &mut self,
         mut handle: Handle<Self, H>,
         depth: usize,
+        replace: bool,
     ) -> io::Result<InsertResult<Self, H>> {
         /// Use an enum to get around borrow issues
         #[derive(Debug)]
@@ -210,10 +266,11 @@ where
 
                     // Recurse, also if it's the last node
                     if **node_key >= *ann_key || i + 1 == len {
-                        match n._insert(handle, depth + 1)? {
+                        match n._insert(handle, depth + 1, replace)? {
                             ok @ InsertResult::Ok => return Ok(ok),
-                            replace @ InsertResult::Replaced(_) => {
-                                return Ok(replace)
+                            done @ InsertResult::Replaced(_)
+                            | done @ InsertResult::Occupied(_) => {
+                                return Ok(done)
                             }
                             InsertResult::Split(new_handle) => {
                                 handle = new_handle;
@@ -229,6 +286,9 @@ where
         loop {
             match action {
                 Action::Placeholder => unreachable!("reached placeholder"),
+                Action::Replace(_) if !replace => {
+                    return Ok(InsertResult::Occupied(handle.into_leaf()));
+                }
                 Action::Replace(i) => {
                     let replaced =
                         mem::replace(&mut self.0[i], handle).into_leaf();
@@ -463,6 +523,48 @@ where
     }
 }
 
+impl<K, V, A, H> MapMut<K, V, H> for Two3Tree<K, V, A, H>
+where
+    K: Content<H> + Ord,
+    V: Content<H>,
+    A: Annotation<KV<K, V>, H> + Borrow<MaxKey<K>>,
+    H: ByteHash,
+{
+    fn insert(&mut self, k: K, v: V) -> io::Result<Option<V>> {
+        Two3Tree::insert(self, k, v)
+    }
+
+    fn try_insert(
+        &mut self,
+        k: K,
+        v: V,
+    ) -> io::Result<Result<(), OccupiedError<'_, K, V, Self, H>>> {
+        Two3Tree::try_insert(self, k, v)
+    }
+
+    fn remove(&mut self, k: &K) -> io::Result<Option<V>> {
+        Two3Tree::remove(self, k)
+    }
+
+    fn get(&self, k: &K) -> io::Result<Option<ValPath<'_, K, V, Self, H>>> {
+        Two3Tree::get(self, k)
+    }
+
+    fn get_mut(
+        &mut self,
+        k: &K,
+    ) -> io::Result<Option<ValPathMut<'_, K, V, Self, H>>> {
+        Two3Tree::get_mut(self, k)
+    }
+
+    fn search_mut(
+        &mut self,
+        k: &K,
+    ) -> io::Result<Result<ValPathMut<'_, K, V, Self, H>, &mut Self>> {
+        ValPathMut::search(self, &mut Two3TreeSearch::from(k))
+    }
+}
+
 impl<K, V, A, H> Content<H> for Two3Tree<K, V, A, H>
 where
     K: Content<H> + Ord,
@@ -486,6 +588,31 @@ where
         }
         Ok(b)
     }
+
+    fn reach<R: Reach<H>>(&self, reach: &mut R) -> io::Result<()> {
+        reach_children(self, reach)
+    }
+}
+
+impl<K, V, A, H> fmt::Debug for Two3Tree<K, V, A, H>
+where
+    K: Content<H> + Ord + fmt::Debug,
+    V: Content<H> + fmt::Debug,
+    A: Annotation<KV<K, V>, H>,
+    H: ByteHash,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Summary::new("Two3Tree", self).fmt(f)
+    }
+}
+
+impl<K, V, A, H> KeyOrdered<H> for Two3Tree<K, V, A, H>
+where
+    H: ByteHash,
+    K: Content<H> + Ord,
+    V: Content<H>,
+    A: Annotation<KV<K, V>, H>,
+{
 }
 
 impl<K, V, A, H> Compound<H> for Two3Tree<K, V, A, H>
@@ -534,6 +661,126 @@ mod test {
         }
     }
 
+    #[test]
+    fn range() {
+        use kelvin::{KeyValIterable, Store};
+
+        let mut h = Two3Tree::<_, _, MaxKey<_>, Blake2b>::new();
+        for i in 0..1024u64 {
+            h.insert(i * 2, i).unwrap();
+        }
+        let store = Store::<Blake2b>::ephemeral();
+        let snapshot = store.persist(&mut h).unwrap();
+        let restored: Two3Tree<_, _, MaxKey<_>, _> =
+            store.restore(&snapshot).unwrap();
+
+        for h in &[h, restored] {
+            let pairs: Vec<(u64, u64)> = h
+                .range(100..=110)
+                .map(|pair| {
+                    let (k, v) = pair.unwrap();
+                    (*k, *v)
+                })
+                .collect();
+            assert_eq!(
+                pairs,
+                vec![
+                    (100, 50),
+                    (102, 51),
+                    (104, 52),
+                    (106, 53),
+                    (108, 54),
+                    (110, 55)
+                ]
+            );
+            assert_eq!(h.range(2040..).count(), 4);
+            assert_eq!(h.range(..1).count(), 1);
+            assert_eq!(h.range(5000..).count(), 0);
+        }
+    }
+
+    #[test]
+    fn try_insert() {
+        let mut h = Two3Tree::<_, _, MaxKey<_>, Blake2b>::new();
+        for i in 0..1024 {
+            assert!(h.try_insert(i, i).unwrap().is_ok());
+        }
+        for i in 0..1024 {
+            let mut err = h.try_insert(i, i + 1).unwrap().unwrap_err();
+            assert_eq!(*err.existing, i);
+            assert_eq!(err.value, i + 1);
+            *err.existing = i + 2;
+        }
+        for i in 0..1024 {
+            assert_eq!(*h.get(&i).unwrap().unwrap(), i + 2);
+        }
+    }
+
+    #[test]
+    fn replace_and_swap() {
+        let mut h = Two3Tree::<_, _, MaxKey<_>, Blake2b>::new();
+        for i in 0..1024 {
+            h.insert(i, i).unwrap();
+        }
+        assert_eq!(h.replace(&2048, 0).unwrap(), None);
+        assert!(h.get(&2048).unwrap().is_none());
+        assert!(!h.swap_values(&0, &2048).unwrap());
+
+        for i in 0..512 {
+            assert!(h.swap_values(&i, &(1023 - i)).unwrap());
+        }
+        assert_eq!(h.replace(&0, 0).unwrap(), Some(1023));
+        assert_eq!(*h.get(&0).unwrap().unwrap(), 0);
+        for i in 1..1024 {
+            assert_eq!(*h.get(&i).unwrap().unwrap(), 1023 - i);
+        }
+    }
+
+    #[test]
+    fn inclusion_proofs() {
+        let store = kelvin::Store::<Blake2b>::ephemeral();
+        let mut h = Two3Tree::<_, _, MaxKey<_>, Blake2b>::new();
+        for i in 0..1024 {
+            h.insert(i, i).unwrap();
+        }
+        let root = *store.persist(&mut h).unwrap().hash();
+        for i in (0..1024).step_by(17) {
+            let proof = h.prove(&i).unwrap().unwrap();
+            assert!(proof::verify(&root, &proof, &KV::new(i, i)).unwrap());
+            assert!(!proof::verify(&root, &proof, &KV::new(i, i + 1)).unwrap());
+        }
+        assert!(h.prove(&1024).unwrap().is_none());
+    }
+
+    #[test]
+    fn range_proofs() {
+        let store = kelvin::Store::<Blake2b>::ephemeral();
+        let mut h = Two3Tree::<_, _, MaxKey<_>, Blake2b>::new();
+        for i in 0..1024u64 {
+            h.insert(i * 2, i).unwrap();
+        }
+        let root = *store.persist(&mut h).unwrap().hash();
+
+        let proof = h.prove_range(&(100..200)).unwrap();
+        let leaves = proof::verify_range(&root, &proof, &(100..200))
+            .unwrap()
+            .unwrap();
+        let keys: Vec<_> = leaves.iter().map(|kv| kv.key).collect();
+        assert_eq!(keys, (50..100).map(|i| i * 2).collect::<Vec<_>>());
+
+        // the proof does not cover a wider range
+        assert!(proof::verify_range(&root, &proof, &(0..400))
+            .unwrap()
+            .is_none());
+
+        assert!(h.prove_absent(&100).unwrap().is_none());
+        for k in (1..2048).step_by(90) {
+            let proof = h.prove_absent(&k).unwrap().unwrap();
+            assert!(proof::verify_absent(&root, &proof, &k).unwrap());
+            assert!(!proof::verify_absent(&root, &proof, &(k - 1)).unwrap());
+        }
+    }
+
     #[test]
     fn bigger_map_reverse() {
         let mut h = Two3Tree::<_, _, MaxKey<_>, Blake2b>::new();
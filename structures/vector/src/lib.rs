@@ -0,0 +1,284 @@
+//! A persistent vector implemented on kelvin
+//!
+//! Values are appended at the end and indexed by position. Every node is
+//! annotated with the number of values below it, so values are found by index
+//! in a number of steps logarithmic in the length of the vector.
+#![warn(missing_docs)]
+
+use std::borrow::Borrow;
+use std::fmt;
+use std::io;
+
+use arrayvec::ArrayVec;
+
+use kelvin::{
+    annotations::{Annotation, Cardinality, Count, Nth},
+    reach_children, AppendOnly, ByteHash, Compound, Content, Handle,
+    LeafIterable, Reach, Sink, Source, Summary, ValPath, ValPathMut,
+};
+
+/// The default vector, annotated with its length
+pub type DefaultVector<T, H> = Vector<T, Cardinality<u64>, H>;
+
+const N: usize = 4;
+
+/// A persistent vector
+#[derive(Clone)]
+pub struct Vector<T, A, H: ByteHash>(ArrayVec<[Handle<Self, H>; N]>)
+where
+    Self: Compound<H>;
+
+impl<T, A, H> Default for Vector<T, A, H>
+where
+    T: Content<H>,
+    A: Annotation<T, H>,
+    H: ByteHash,
+{
+    fn default() -> Self {
+        Vector(Default::default())
+    }
+}
+
+impl<T, A, H> Vector<T, A, H>
+where
+    T: Content<H>,
+    A: Annotation<T, H> + Borrow<Cardinality<u64>>,
+    H: ByteHash,
+{
+    /// Creates a new, empty, vector
+    pub fn new() -> Self {
+        Vector(Default::default())
+    }
+
+    /// Returns the number of values in the vector
+    pub fn len(&self) -> u64 {
+        self.count()
+    }
+
+    /// Returns true if the vector holds no values
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Appends a value to the end of the vector
+    pub fn push(&mut self, t: T) -> io::Result<()> {
+        AppendOnly::append(self, t)
+    }
+
+    /// Removes the last value of the vector, returning it
+    pub fn pop(&mut self) -> io::Result<Option<T>> {
+        AppendOnly::pop_last(self)
+    }
+
+    /// Get a reference to the value at index `i`
+    pub fn get(
+        &self,
+        i: u64,
+    ) -> io::Result<Option<ValPath<'_, u64, T, Self, H>>> {
+        ValPath::new(self, &mut Nth::new(i))
+    }
+
+    /// Get a mutable reference to the value at index `i`
+    pub fn get_mut(
+        &mut self,
+        i: u64,
+    ) -> io::Result<Option<ValPathMut<'_, u64, T, Self, H>>> {
+        ValPathMut::new(self, &mut Nth::new(i))
+    }
+
+    /// Returns an iterator over the values, in order
+    ///
+    /// The iterator is double-ended, so the latest values can be read with
+    /// `rev` without visiting the others.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = io::Result<&T>> {
+        LeafIterable::iter(self)
+    }
+}
+
+impl<T, A, H> Content<H> for Vector<T, A, H>
+where
+    T: Content<H>,
+    A: Annotation<T, H>,
+    H: ByteHash,
+{
+    fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
+        (self.0.len() as u8).persist(sink)?;
+        for h in &mut self.0 {
+            h.persist(sink)?
+        }
+        Ok(())
+    }
+
+    fn restore(source: &mut Source<H>) -> io::Result<Self> {
+        let mut v = Vector::default();
+        let len = u8::restore(source)?;
+        if len as usize > N {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Too many children in Vector node",
+            ));
+        }
+        for _ in 0..len {
+            v.0.push(Handle::restore(source)?);
+        }
+        Ok(v)
+    }
+
+    fn reach<R: Reach<H>>(&self, reach: &mut R) -> io::Result<()> {
+        reach_children(self, reach)
+    }
+}
+
+impl<T, A, H> fmt::Debug for Vector<T, A, H>
+where
+    T: Content<H> + fmt::Debug,
+    A: Annotation<T, H>,
+    H: ByteHash,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Summary::new("Vector", self).fmt(f)
+    }
+}
+
+impl<T, A, H> Compound<H> for Vector<T, A, H>
+where
+    T: Content<H>,
+    A: Annotation<T, H>,
+    H: ByteHash,
+{
+    type Leaf = T;
+
+    type Annotation = A;
+
+    fn children_mut(&mut self) -> &mut [Handle<Self, H>] {
+        &mut self.0
+    }
+
+    fn children(&self) -> &[Handle<Self, H>] {
+        &self.0
+    }
+}
+
+impl<T, A, H> AppendOnly<H> for Vector<T, A, H>
+where
+    T: Content<H>,
+    A: Annotation<T, H>,
+    H: ByteHash,
+{
+    fn is_full(&self) -> bool {
+        self.0.is_full()
+    }
+
+    fn push_child(&mut self, child: Handle<Self, H>) {
+        self.0.push(child)
+    }
+
+    fn pop_child(&mut self) -> Option<Handle<Self, H>> {
+        self.0.pop()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use kelvin::{tests::CorrectEmptyState, Blake2b, Store};
+
+    type Vec64 = DefaultVector<u64, Blake2b>;
+
+    #[test]
+    fn push_get() {
+        let mut v = Vec64::new();
+        assert!(v.is_empty());
+        for i in 0..1000 {
+            v.push(i).unwrap();
+            assert_eq!(v.len(), i + 1);
+        }
+        for i in 0..1000 {
+            assert_eq!(*v.get(i).unwrap().unwrap(), i);
+        }
+        assert!(v.get(1000).unwrap().is_none());
+    }
+
+    #[test]
+    fn push_pop() {
+        let mut v = Vec64::new();
+        for i in 0..1000 {
+            v.push(i).unwrap();
+        }
+        for i in (0..1000).rev() {
+            assert_eq!(v.pop().unwrap(), Some(i));
+            assert_eq!(v.len(), i);
+            if i > 0 {
+                assert_eq!(*v.get(i - 1).unwrap().unwrap(), i - 1);
+            }
+        }
+        assert_eq!(v.pop().unwrap(), None);
+        v.assert_correct_empty_state();
+
+        // interleaved, across the borders of full subtrees
+        for i in 0..100 {
+            v.push(i).unwrap();
+            v.push(i).unwrap();
+            v.pop().unwrap();
+        }
+        let values: Vec<u64> = v.iter().map(|t| *t.unwrap()).collect();
+        assert_eq!(values, (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn get_mut() {
+        let mut v = Vec64::new();
+        for i in 0..100 {
+            v.push(i).unwrap();
+        }
+        *v.get_mut(42).unwrap().unwrap() = 0;
+        assert_eq!(*v.get(42).unwrap().unwrap(), 0);
+        assert_eq!(v.len(), 100);
+    }
+
+    #[test]
+    fn latest() {
+        let store = Store::<Blake2b>::ephemeral();
+        let mut v = Vec64::new();
+        for i in 0..1000 {
+            v.push(i).unwrap();
+        }
+        let snapshot = store.persist(&mut v).unwrap();
+        let restored: Vec64 = store.restore(&snapshot).unwrap();
+
+        for v in &[v, restored] {
+            let latest: Vec<u64> =
+                v.iter().rev().take(3).map(|t| *t.unwrap()).collect();
+            assert_eq!(latest, vec![999, 998, 997]);
+
+            // both ends meet in the middle
+            let mut iter = v.iter();
+            let mut seen = vec![];
+            while let Some(t) = iter.next() {
+                seen.push(*t.unwrap());
+                if let Some(t) = iter.next_back() {
+                    seen.push(*t.unwrap());
+                }
+            }
+            seen.sort();
+            assert_eq!(seen, (0..1000).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn persisted() {
+        let store = Store::<Blake2b>::ephemeral();
+        let mut v = Vec64::new();
+        for i in 0..1000 {
+            v.push(i).unwrap();
+        }
+        let snapshot = store.persist(&mut v).unwrap();
+        let mut restored: Vec64 = store.restore(&snapshot).unwrap();
+        assert_eq!(restored.len(), 1000);
+        assert_eq!(*restored.get(777).unwrap().unwrap(), 777);
+        restored.push(1000).unwrap();
+        assert_eq!(restored.pop().unwrap(), Some(1000));
+        assert_eq!(restored.pop().unwrap(), Some(999));
+    }
+}
@@ -0,0 +1,105 @@
+use kelvin::tests::tempfile::tempdir;
+use kelvin::{Blake2b, Blob, Control, Spill, Store};
+use kelvin_hamt::DefaultHAMTMap;
+
+type Map = DefaultHAMTMap<u64, Blob<String>, Blake2b>;
+
+fn with_huge(huge: &str) -> Map {
+    let mut map = Map::new();
+    for i in 0..100 {
+        map.insert(i, Blob::new(i.to_string())).unwrap();
+    }
+    map.insert(1000, Blob::new(huge.to_string())).unwrap();
+    map
+}
+
+#[test]
+fn large_values_are_chunked() {
+    let store = Store::<Blake2b>::ephemeral();
+    store.set_max_leaf_size(1024);
+
+    let huge = "x".repeat(100_000);
+    let snapshot = store.persist(&mut with_huge(&huge)).unwrap();
+    let restored = store.restore(&snapshot).unwrap();
+    assert_eq!(*restored.get(&1000).unwrap().unwrap().get().unwrap(), huge);
+    assert_eq!(*restored.get(&42).unwrap().unwrap().get().unwrap(), "42");
+
+    // the nodes holding the small values stay small
+    let largest = store
+        .node_records(&snapshot)
+        .map(|record| record.unwrap().bytes.len())
+        .max()
+        .unwrap();
+    assert!(largest < 4096);
+
+    // large values are stored inline in stores without threshold
+    let inline = Store::<Blake2b>::ephemeral();
+    let mut map = Map::new();
+    map.insert(1000, Blob::new(huge)).unwrap();
+    let snapshot = inline.persist(&mut map).unwrap();
+    let largest = inline
+        .node_records(&snapshot)
+        .map(|record| record.unwrap().bytes.len())
+        .max()
+        .unwrap();
+    assert!(largest > 100_000);
+}
+
+#[test]
+fn large_values_are_read_lazily() {
+    let store = Store::<Blake2b>::ephemeral();
+    store.set_max_leaf_size(1024);
+
+    let huge = "x".repeat(100_000);
+    let snapshot = store.persist(&mut with_huge(&huge)).unwrap();
+    let mut map = store.restore(&snapshot).unwrap();
+    assert!(!map.get(&1000).unwrap().unwrap().is_loaded());
+
+    // persisting again leaves the unmodified value in the store
+    map.insert(42, Blob::new("changed".into())).unwrap();
+    let changed = store.persist(&mut map).unwrap();
+    let mut map = store.restore(&changed).unwrap();
+    let blob = map.get(&1000).unwrap().unwrap();
+    assert!(!blob.is_loaded());
+    assert_eq!(*blob.get().unwrap(), huge);
+    drop(blob);
+
+    // modified values are written again
+    map.get_mut(&1000)
+        .unwrap()
+        .unwrap()
+        .get_mut()
+        .unwrap()
+        .push('y');
+    let modified = store.persist(&mut map).unwrap();
+    let map = store.restore(&modified).unwrap();
+    let value = map.get(&1000).unwrap().unwrap().get().unwrap().into_owned();
+    assert_eq!(value.len(), 100_001);
+}
+
+#[test]
+fn chunks_are_reached() {
+    let store = Store::<Blake2b>::ephemeral();
+    store.set_max_leaf_size(1024);
+    let huge = "x".repeat(100_000);
+    let snapshot = store.persist(&mut with_huge(&huge)).unwrap();
+    store
+        .gc(&mut Control::none(), |live| live.mark(&snapshot))
+        .unwrap();
+    let map = store.restore(&snapshot).unwrap();
+    assert_eq!(*map.get(&1000).unwrap().unwrap().get().unwrap(), huge);
+
+    // and committed from the staging area
+    let dir = tempdir().unwrap();
+    let target = Store::<Blake2b>::new(dir.path()).unwrap();
+    target.set_max_leaf_size(1024);
+    let spill = Spill::new(&target).unwrap();
+    let mut map = with_huge(&huge);
+    spill.spill(&mut map).unwrap();
+    let committed = spill.commit(&mut map).unwrap();
+    drop(map);
+
+    let map = target.restore(&committed).unwrap();
+    assert_eq!(*map.get(&1000).unwrap().unwrap().get().unwrap(), huge);
+    assert_eq!(committed.hash(), snapshot.hash());
+}
@@ -0,0 +1,112 @@
+use std::hash::Hasher;
+use std::io::Read;
+
+use kelvin::tests::tempfile::tempdir;
+use kelvin::{
+    Backend, Blake2b, ByteHash, ByteHashState, CachedBackend, DiskBackend, Lfu,
+    MemBackend, Store,
+};
+use kelvin_hamt::DefaultHAMTMap;
+
+type Map = DefaultHAMTMap<u64, u64, Blake2b>;
+
+fn digest(bytes: &[u8]) -> <Blake2b as ByteHash>::Digest {
+    let mut state = Blake2b::state();
+    state.write(bytes);
+    state.fin()
+}
+
+fn read(backend: &CachedBackend<Blake2b, MemBackend<Blake2b>>, n: u8) {
+    let mut bytes = vec![];
+    backend
+        .get(&digest(&[n; 10]))
+        .unwrap()
+        .read_to_end(&mut bytes)
+        .unwrap();
+    assert_eq!(bytes, vec![n; 10]);
+}
+
+#[test]
+fn hits_and_misses() {
+    let mut backend = CachedBackend::new(MemBackend::new(), 30);
+    for n in 0..4u8 {
+        backend.put(digest(&[n; 10]), vec![n; 10]).unwrap();
+    }
+    let control = backend.control();
+    assert_eq!(control.cached(), 0);
+
+    for n in 0..3 {
+        read(&backend, n);
+    }
+    read(&backend, 0);
+    assert_eq!((control.hits(), control.misses()), (1, 3));
+    assert_eq!(control.cached(), 30);
+
+    // evicts the least recently used node
+    read(&backend, 3);
+    read(&backend, 1);
+    assert_eq!((control.hits(), control.misses()), (1, 5));
+
+    control.reset_counters();
+    control.set_budget(10);
+    assert_eq!(control.cached(), 10);
+    control.set_eviction_policy(Lfu::new());
+    read(&backend, 1);
+    assert_eq!((control.hits(), control.misses()), (1, 0));
+}
+
+#[test]
+fn hot_nodes_kept_across_queries() {
+    let backend = CachedBackend::new(MemBackend::new(), 1 << 20);
+    let control = backend.control();
+    let store = Store::<Blake2b>::from_backend(backend);
+
+    let mut map = Map::new();
+    for i in 0..1000 {
+        map.insert(i, i).unwrap();
+    }
+    let snapshot = store.persist(&mut map).unwrap();
+
+    store.verify(&snapshot).unwrap();
+    let misses = control.misses();
+    assert!(misses > 0);
+
+    store.verify(&snapshot).unwrap();
+    assert_eq!(control.misses(), misses);
+    assert!(control.hits() > 0);
+}
+
+#[test]
+fn disk_misses_read_single_nodes() {
+    let dir = tempdir().unwrap();
+    let mut backend =
+        CachedBackend::new(DiskBackend::new(dir.path()).unwrap(), 1 << 20);
+    for n in 0..4u8 {
+        backend.put(digest(&[n; 10]), vec![n; 10]).unwrap();
+    }
+    backend.flush().unwrap();
+    let control = backend.control();
+
+    for n in 0..4u8 {
+        let mut bytes = vec![];
+        backend
+            .get(&digest(&[n; 10]))
+            .unwrap()
+            .read_to_end(&mut bytes)
+            .unwrap();
+        assert_eq!(bytes, vec![n; 10]);
+    }
+    // only the nodes themselves are cached, not the rest of the data file
+    assert_eq!(control.cached(), 40);
+
+    let store = Store::<Blake2b>::from_backend(backend);
+    let mut map = Map::new();
+    for i in 0..1000 {
+        map.insert(i, i).unwrap();
+    }
+    let snapshot = store.persist(&mut map).unwrap();
+    store.flush().unwrap();
+    store.verify(&snapshot).unwrap();
+    store.verify(&snapshot).unwrap();
+    assert!(control.hits() > 0);
+}
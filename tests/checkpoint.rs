@@ -0,0 +1,33 @@
+use kelvin::{Blake2b, Compound, Store};
+use kelvin_hamt::DefaultHAMTMap;
+
+type Map = DefaultHAMTMap<u64, u64, Blake2b>;
+
+#[test]
+fn checkpoint_rollback() {
+    let store = Store::<Blake2b>::ephemeral();
+    let mut map = Map::new();
+    for i in 0..1000 {
+        map.insert(i, i).unwrap();
+    }
+    let first = map.checkpoint(&store).unwrap();
+
+    for i in 0..500 {
+        map.insert(i, 0).unwrap();
+    }
+    map.remove(&999).unwrap();
+    let second = map.checkpoint(&store).unwrap();
+
+    map.insert(1000, 1000).unwrap();
+    map.rollback(&second).unwrap();
+    assert!(map.get(&1000).unwrap().is_none());
+    assert!(map.get(&999).unwrap().is_none());
+    assert_eq!(*map.get(&1).unwrap().unwrap(), 0);
+
+    map.rollback(&first).unwrap();
+    assert_eq!(*map.get(&1).unwrap().unwrap(), 1);
+    assert_eq!(*map.get(&999).unwrap().unwrap(), 999);
+
+    // checkpoints of an unchanged state are identical
+    assert_eq!(map.checkpoint(&store).unwrap().hash(), first.hash());
+}
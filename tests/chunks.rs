@@ -0,0 +1,119 @@
+use std::io;
+
+use kelvin::tests::tempfile::tempdir;
+use kelvin::{Blake2b, ChunkProvider, ChunkReceiver, Control, Manifest, Store};
+use kelvin_hamt::DefaultHAMTMap;
+
+type Map = DefaultHAMTMap<u64, u64, Blake2b>;
+
+#[test]
+fn resumable_state_sync() {
+    let store = Store::<Blake2b>::ephemeral();
+    let mut map = Map::new();
+    for i in 0..2000 {
+        map.insert(i, i * 3).unwrap();
+    }
+    let snapshot = store.persist(&mut map).unwrap();
+
+    let provider = ChunkProvider::new(&store, 1024);
+    let manifest = provider.offer(&snapshot).unwrap();
+    assert!(manifest.chunks.len() > 4);
+    assert!(provider
+        .chunk(snapshot.hash(), manifest.chunks.len())
+        .unwrap()
+        .is_none());
+
+    // the manifest is sent over the wire
+    let mut bytes = vec![];
+    manifest.write_to(&mut bytes).unwrap();
+    let manifest = Manifest::<Blake2b>::read_from(&mut &bytes[..]).unwrap();
+    assert_eq!(manifest.root, *snapshot.hash());
+
+    let local = Store::<Blake2b>::ephemeral();
+    let receiver = ChunkReceiver::<Map, _>::new(manifest.clone(), &local);
+    let total = manifest.chunks.len();
+    assert_eq!(receiver.missing(), (0..total).collect::<Vec<_>>());
+
+    // damaged chunks are rejected
+    let mut chunk = provider.chunk(&manifest.root, 0).unwrap().unwrap();
+    chunk[0] ^= 1;
+    let err = receiver.apply(0, chunk).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    // interrupted halfway, chunks arriving out of order
+    for i in (0..total / 2).rev() {
+        let chunk = provider.chunk(&manifest.root, i).unwrap().unwrap();
+        receiver.apply(i, chunk).unwrap();
+    }
+    assert!(receiver.finish().is_err());
+
+    // resumed with the chunks still missing
+    let receiver = ChunkReceiver::<Map, _>::new(manifest.clone(), &local);
+    let missing = receiver.missing();
+    assert_eq!(missing, (total / 2..total).collect::<Vec<_>>());
+    for i in missing {
+        let chunk = provider.chunk(&manifest.root, i).unwrap().unwrap();
+        receiver.apply(i, chunk).unwrap();
+    }
+    let synced = receiver.finish().unwrap();
+    assert_eq!(synced.hash(), snapshot.hash());
+    local.verify(&synced).unwrap();
+    let map = synced.restore().unwrap();
+    assert_eq!(*map.get(&1234).unwrap().unwrap(), 1234 * 3);
+
+    provider.withdraw(snapshot.hash());
+    assert!(provider.manifest(snapshot.hash()).is_none());
+}
+
+#[test]
+fn disk_state_sync() {
+    let dir = tempdir().unwrap();
+    let store = Store::<Blake2b>::new(dir.path().join("provider")).unwrap();
+    let mut map = Map::new();
+    for i in 0..2000 {
+        map.insert(i, i * 3).unwrap();
+    }
+    let snapshot = store.persist(&mut map).unwrap();
+    store.flush().unwrap();
+
+    let provider = ChunkProvider::new(&store, 1024);
+    let manifest = provider.offer(&snapshot).unwrap();
+    let total = manifest.chunks.len();
+
+    // offered chunks survive garbage collection
+    store
+        .gc(&mut Control::none(), |live| live.mark(&snapshot))
+        .unwrap();
+
+    let local = Store::<Blake2b>::new(dir.path().join("receiver")).unwrap();
+    let receiver = ChunkReceiver::<Map, _>::new(manifest.clone(), &local);
+    for i in 0..total / 2 {
+        let chunk = provider.chunk(&manifest.root, i).unwrap().unwrap();
+        receiver.apply(i, chunk).unwrap();
+    }
+    // and so do the chunks received so far
+    local.gc(&mut Control::none(), |_| Ok(())).unwrap();
+    assert_eq!(receiver.missing(), (total / 2..total).collect::<Vec<_>>());
+    for i in total / 2..total {
+        let chunk = provider.chunk(&manifest.root, i).unwrap().unwrap();
+        receiver.apply(i, chunk).unwrap();
+    }
+    let synced = receiver.finish().unwrap();
+    assert_eq!(synced.hash(), snapshot.hash());
+    local.verify(&synced).unwrap();
+
+    // chunks are collected once imported, or withdrawn
+    local
+        .gc(&mut Control::none(), |live| live.mark(&synced))
+        .unwrap();
+    let receiver = ChunkReceiver::<Map, _>::new(manifest.clone(), &local);
+    assert_eq!(receiver.missing().len(), total);
+    local.verify(&synced).unwrap();
+
+    provider.withdraw(snapshot.hash());
+    store
+        .gc(&mut Control::none(), |live| live.mark(&snapshot))
+        .unwrap();
+    store.verify(&snapshot).unwrap();
+    assert!(provider.chunk(&manifest.root, 0).unwrap().is_none());
+}
@@ -0,0 +1,46 @@
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use kelvin::{Backend, Blake2b, ByteHash, ObjectStore, PutResult};
+
+// Counts the nodes read from the wrapped backend, or the objects fetched
+// from the wrapped object store
+pub struct Counting<B>(pub B, pub Arc<AtomicUsize>);
+
+impl<B: Backend<Blake2b>> Backend<Blake2b> for Counting<B> {
+    fn get<'a>(
+        &'a self,
+        hash: &<Blake2b as ByteHash>::Digest,
+    ) -> io::Result<Box<dyn Read + 'a>> {
+        self.1.fetch_add(1, Ordering::SeqCst);
+        self.0.get(hash)
+    }
+
+    fn put(
+        &mut self,
+        hash: <Blake2b as ByteHash>::Digest,
+        bytes: Vec<u8>,
+    ) -> io::Result<PutResult> {
+        self.0.put(hash, bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl<O: ObjectStore> ObjectStore for Counting<O> {
+    fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        self.1.fetch_add(1, Ordering::SeqCst);
+        self.0.get(key)
+    }
+
+    fn put(&self, key: &str, bytes: &[u8]) -> io::Result<()> {
+        self.0.put(key, bytes)
+    }
+
+    fn contains(&self, key: &str) -> io::Result<bool> {
+        self.0.contains(key)
+    }
+}
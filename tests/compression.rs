@@ -0,0 +1,220 @@
+#![cfg(feature = "compression")]
+
+use std::io::Read;
+
+use kelvin::tests::tempfile::tempdir;
+use kelvin::{
+    Backend, Blake2b, ByteHash, ByteHashState, Codec, Compressed,
+    CompressedBackend, CompressionPolicy, Content, Dictionary, DiskBackend,
+    MemBackend, Namespaces, Store,
+};
+use kelvin_hamt::DefaultHAMTMap;
+
+#[derive(Clone, Debug, PartialEq, Content)]
+struct Account {
+    owner: String,
+    currency: String,
+    balance: u64,
+    frozen: bool,
+}
+
+fn account(i: u64) -> Account {
+    Account {
+        owner: format!("account-holder-{:06}", i),
+        currency: "EUR".into(),
+        balance: i * 100,
+        frozen: i % 7 == 0,
+    }
+}
+
+type Plain = DefaultHAMTMap<u64, Account, Blake2b>;
+type Packed = DefaultHAMTMap<u64, Compressed<Account>, Blake2b>;
+
+#[test]
+fn trained_dictionary_compresses_leaves() {
+    let mut plain = Plain::new();
+    for i in 0..2000 {
+        plain.insert(i, account(i)).unwrap();
+    }
+    let dictionary = Dictionary::train(&plain, 500, 4096).unwrap();
+
+    let mut packed = Packed::new();
+    for i in 0..2000 {
+        packed
+            .insert(i, Compressed::new(account(i), &dictionary))
+            .unwrap();
+    }
+
+    let plain_store = Store::<Blake2b>::ephemeral();
+    plain_store.persist(&mut plain).unwrap();
+    let store = Store::<Blake2b>::ephemeral();
+    store.add_dictionary(dictionary.clone());
+    let snapshot = store.persist(&mut packed).unwrap();
+    assert!(store.size() < plain_store.size());
+
+    // nodes are stored compressed, but hashed uncompressed
+    store.verify(&snapshot).unwrap();
+    for record in store.node_records(&snapshot) {
+        let record = record.unwrap();
+        assert_eq!(digest(&record.bytes), record.digest);
+        assert!(!record.bytes.starts_with(&[0xce, 0xff]));
+    }
+
+    let restored = store.restore(&snapshot).unwrap();
+    for i in (0..2000).step_by(37) {
+        assert_eq!(**restored.get(&i).unwrap().unwrap(), account(i));
+    }
+
+    // without the dictionary, compressed values can not be restored
+    let other = Store::<Blake2b>::ephemeral();
+    let mut unknown = Packed::new();
+    unknown
+        .insert(0, Compressed::new(account(0), &dictionary))
+        .unwrap();
+    let snapshot = other.persist(&mut unknown).unwrap();
+    let err = other.restore(&snapshot).err().unwrap();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn dictionary_kept_in_namespace() {
+    let dir = tempdir().unwrap();
+    let samples: Vec<_> = (0..500).map(account).collect();
+    let dictionary =
+        Dictionary::from_samples::<_, Blake2b, _>(&samples, 4096).unwrap();
+
+    {
+        let namespaces = Namespaces::<Blake2b>::new(dir.path()).unwrap();
+        let mut ns = namespaces.create("bank", 1 << 30).unwrap();
+        ns.set_dictionary("accounts", &dictionary).unwrap();
+        let mut map = Packed::new();
+        for i in 0..100 {
+            map.insert(i, Compressed::new(account(i), &dictionary))
+                .unwrap();
+        }
+        ns.set_root("accounts", &mut map).unwrap();
+    }
+
+    let namespaces = Namespaces::<Blake2b>::new(dir.path()).unwrap();
+    let ns = namespaces.open("bank").unwrap();
+    assert_eq!(
+        ns.dictionary("accounts").unwrap().unwrap().id(),
+        dictionary.id()
+    );
+    let map: Packed = ns.restore("accounts").unwrap().unwrap();
+    assert_eq!(**map.get(&42).unwrap().unwrap(), account(42));
+}
+
+#[test]
+fn compressed_backends_round_trip() {
+    let mut map = Plain::new();
+    for i in 0..500 {
+        map.insert(i, account(i)).unwrap();
+    }
+    let plain = Store::<Blake2b>::ephemeral();
+    let plain_snapshot = plain.persist(&mut map).unwrap();
+
+    for codec in [Codec::Lz4, Codec::Zstd].iter() {
+        let backend = CompressedBackend::new(
+            MemBackend::new(),
+            *codec,
+            CompressionPolicy::Above(64),
+        );
+        let store = Store::<Blake2b>::from_backend(backend);
+        let snapshot = store.persist(&mut map).unwrap();
+        // digests are those of the uncompressed nodes
+        assert_eq!(snapshot.hash(), plain_snapshot.hash());
+        assert!(store.size() < plain.size());
+
+        let restored = store.restore(&snapshot).unwrap();
+        for i in (0..500).step_by(17) {
+            assert_eq!(*restored.get(&i).unwrap().unwrap(), account(i));
+        }
+    }
+}
+
+fn digest(bytes: &[u8]) -> <Blake2b as ByteHash>::Digest {
+    let mut state = Blake2b::state();
+    state.write(bytes);
+    state.fin()
+}
+
+#[test]
+fn compression_flagged_per_value() {
+    let mut backend = CompressedBackend::new(
+        MemBackend::new(),
+        Codec::Zstd,
+        CompressionPolicy::Always,
+    );
+    let squeezed = vec![7u8; 1024];
+    let short = b"too short to compress".to_vec();
+    let raw = vec![9u8; 1024];
+
+    backend.put(digest(&squeezed), squeezed.clone()).unwrap();
+    backend.put(digest(&short), short.clone()).unwrap();
+    backend.set_policy(Codec::Lz4, CompressionPolicy::Never);
+    backend.put(digest(&raw), raw.clone()).unwrap();
+
+    // only the repetitive value shrank, the others grew by their flag
+    let stored = Backend::<Blake2b>::size(&backend);
+    assert!(stored > raw.len() + short.len() + 2);
+    assert!(stored < raw.len() + short.len() + 2 + squeezed.len() / 2);
+
+    for bytes in [&squeezed, &short, &raw].iter() {
+        let mut read = vec![];
+        Backend::<Blake2b>::get(&backend, &digest(bytes))
+            .unwrap()
+            .read_to_end(&mut read)
+            .unwrap();
+        assert_eq!(&read, *bytes);
+    }
+}
+
+#[test]
+fn compressed_disk_backend() {
+    let dir = tempdir().unwrap();
+    let mut map = Plain::new();
+    for i in 0..500 {
+        map.insert(i, account(i)).unwrap();
+    }
+
+    for codec in [Codec::Lz4, Codec::Zstd].iter() {
+        let path = dir.path().join(format!("{:?}", codec));
+        let backend = CompressedBackend::new(
+            DiskBackend::new(&path).unwrap(),
+            *codec,
+            CompressionPolicy::Above(64),
+        );
+        let store = Store::<Blake2b>::from_backend(backend);
+        let snapshot = store.persist(&mut map).unwrap();
+        store.flush().unwrap();
+        store.verify(&snapshot).unwrap();
+
+        let restored = store.restore(&snapshot).unwrap();
+        for i in (0..500).step_by(17) {
+            assert_eq!(*restored.get(&i).unwrap().unwrap(), account(i));
+        }
+    }
+}
+
+#[test]
+fn decompression_is_bounded() {
+    let mut backend = CompressedBackend::new(
+        MemBackend::new(),
+        Codec::Zstd,
+        CompressionPolicy::Always,
+    );
+    let bytes = vec![0u8; 4096];
+    backend.put(digest(&bytes), bytes.clone()).unwrap();
+    backend.set_policy(Codec::Lz4, CompressionPolicy::Always);
+    let other = vec![1u8; 4096];
+    backend.put(digest(&other), other.clone()).unwrap();
+
+    backend.set_max_decoded(1024);
+    for bytes in [&bytes, &other].iter() {
+        let err = Backend::<Blake2b>::get(&backend, &digest(bytes))
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
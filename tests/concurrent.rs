@@ -0,0 +1,142 @@
+//! Stress tests for concurrent readers of a store with a single writer
+//!
+//! The writer keeps mutating a map and publishes a snapshot after every
+//! commit. Each committed state carries its commit number and the count and
+//! sum of the values in the map, readers restore whatever snapshot is
+//! currently published and check that the tree they observe is complete,
+//! matches its checksum, and is not older than the one observed before.
+//! Readers stop once they observe the last commit.
+use std::io;
+use std::sync::{Arc, Barrier, RwLock};
+use std::thread;
+
+use kelvin::tests::rand::{rngs::StdRng, Rng, SeedableRng};
+use kelvin::tests::tempfile::tempdir;
+use kelvin::{Blake2b, Deferred, LeafIterable, Reclaimer, Snapshot, Store};
+use kelvin_hamt::DefaultHAMTMap;
+
+type Map = DefaultHAMTMap<u64, u64, Blake2b>;
+/// The map together with its commit number and the expected (count, sum) of
+/// its values
+type State = (Map, (u64, u64, u64));
+
+type Published = Arc<RwLock<Snapshot<State, Blake2b>>>;
+
+fn writer(
+    store: Store<Blake2b>,
+    published: Published,
+    commits: u64,
+) -> io::Result<()> {
+    let mut rng = StdRng::seed_from_u64(commits);
+    let mut state = State::default();
+
+    for commit in 0..commits {
+        let (ref mut map, (ref mut number, ref mut count, ref mut sum)) = state;
+        *number = commit + 1;
+
+        let value = rng.gen_range(0, 1024);
+        map.insert(commit, value)?;
+        *count += 1;
+        *sum += value;
+
+        // overwrite and remove older entries
+        let key = rng.gen_range(0, commit + 1);
+        let value = rng.gen_range(0, 1024);
+        if let Some(old) = map.insert(key, value)? {
+            *sum -= old;
+        } else {
+            *count += 1;
+        }
+        *sum += value;
+
+        if rng.gen_range(0, 4) == 0 {
+            if let Some(old) = map.remove(&rng.gen_range(0, commit + 1))? {
+                *count -= 1;
+                *sum -= old;
+            }
+        }
+
+        let snapshot = store.persist(&mut state)?;
+        *published.write().unwrap() = snapshot;
+    }
+    Ok(())
+}
+
+fn reader(
+    store: Store<Blake2b>,
+    published: Published,
+    commits: u64,
+    reclaimer: Reclaimer,
+) -> io::Result<()> {
+    let mut last = 0;
+    while last < commits {
+        let snapshot = published.read().unwrap().clone();
+
+        // dropping the restored tree is left to the reclaimer
+        let state = Deferred::new(store.restore(&snapshot)?, &reclaimer);
+        let (ref map, (number, count, sum)) = *state;
+
+        let (mut seen_count, mut seen_sum) = (0, 0);
+        for leaf in map.iter() {
+            seen_count += 1;
+            seen_sum += leaf?.val;
+        }
+
+        assert_eq!(seen_count, count, "incomplete root observed");
+        assert_eq!(seen_sum, sum, "inconsistent root observed");
+        assert!(number >= last, "older root observed after a newer one");
+        last = number;
+    }
+    Ok(())
+}
+
+fn stress(store: Store<Blake2b>, commits: u64, readers: usize) {
+    // readers start from the empty state, committed before any of them runs
+    let snapshot = store.persist(&mut State::default()).unwrap();
+    let published: Published = Arc::new(RwLock::new(snapshot));
+    let started = Arc::new(Barrier::new(readers + 1));
+    let reclaimer = Reclaimer::new();
+
+    let handles: Vec<_> = (0..readers)
+        .map(|_| {
+            let store = store.clone();
+            let published = published.clone();
+            let started = started.clone();
+            let reclaimer = reclaimer.clone();
+            thread::spawn(move || {
+                started.wait();
+                reader(store, published, commits, reclaimer)
+            })
+        })
+        .collect();
+
+    // the writer commits once every reader is running
+    started.wait();
+    // readers wait for the last commit, so they are only joined once it is
+    writer(store, published, commits).unwrap();
+
+    for handle in handles {
+        handle.join().expect("reader panicked").unwrap();
+    }
+
+    reclaimer.sync();
+    assert_eq!(reclaimer.pending(), 0);
+}
+
+#[test]
+fn concurrent_readers_ephemeral() {
+    stress(Store::ephemeral(), 256, 4);
+}
+
+#[test]
+fn concurrent_readers_disk() {
+    let dir = tempdir().unwrap();
+    stress(Store::new(dir.path()).unwrap(), 256, 4);
+}
+
+#[test]
+#[ignore]
+fn concurrent_readers_long_running() {
+    let dir = tempdir().unwrap();
+    stress(Store::new(dir.path()).unwrap(), 16 * 1024, 8);
+}
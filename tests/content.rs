@@ -0,0 +1,58 @@
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::net::{Ipv6Addr, SocketAddr, SocketAddrV6};
+use std::time::Duration;
+
+use kelvin::{Blake2b, Content, Store};
+
+fn round_trip<T: Content<Blake2b> + Debug + PartialEq>(mut t: T) {
+    let store = Store::<Blake2b>::ephemeral();
+    let snapshot = store.persist(&mut t).unwrap();
+    assert_eq!(store.restore(&snapshot).unwrap(), t);
+}
+
+#[test]
+fn std_round_trips() {
+    round_trip(Some(3u64));
+    round_trip(None::<u64>);
+    round_trip(Ok::<u32, String>(7));
+    round_trip(Err::<u32, String>("failed".into()));
+    round_trip((1u8,));
+    round_trip((1u8, 2u16, 3u32, 4u64, 5i16, 6i32, 7i64, true));
+    round_trip([7u8; 48]);
+    round_trip([[1u16; 3]; 100]);
+    round_trip(u128::max_value());
+    round_trip(i128::min_value());
+    round_trip(Duration::new(u64::max_value(), 999_999_999));
+
+    let mut map = BTreeMap::new();
+    map.insert(String::from("a"), vec![Duration::from_millis(1)]);
+    round_trip(map);
+
+    round_trip("127.0.0.1:8080".parse::<SocketAddr>().unwrap());
+    round_trip("[::1]:443".parse::<SocketAddr>().unwrap());
+    round_trip(SocketAddr::V6(SocketAddrV6::new(
+        Ipv6Addr::LOCALHOST,
+        80,
+        7,
+        3,
+    )));
+}
+
+#[test]
+fn invalid_encodings() {
+    let store = Store::<Blake2b>::ephemeral();
+
+    // nanoseconds carrying over into seconds
+    let snapshot = store.persist(&mut (1u64, 1_000_000_000u32)).unwrap();
+    let snapshot = store.snapshot::<Duration>(snapshot.hash());
+    assert!(snapshot.restore().is_err());
+
+    let snapshot = store.persist(&mut (5u8, [0u8; 6])).unwrap();
+    let snapshot = store.snapshot::<SocketAddr>(snapshot.hash());
+    assert!(snapshot.restore().is_err());
+
+    let snapshot = store.persist(&mut 2u8).unwrap();
+    let snapshot = store.snapshot::<Result<u8, u8>>(snapshot.hash());
+    assert!(snapshot.restore().is_err());
+}
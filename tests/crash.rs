@@ -0,0 +1,140 @@
+//! Process-crash harness for the durability of `Root::set_root`
+//!
+//! The test binary re-executes itself as a child process which commits a
+//! seeded random workload in a loop, reporting every commit on stdout. The
+//! parent SIGKILLs the child at a random point, reopens the store and checks
+//! that the restored root is exactly one of the committed states, no older
+//! than the last commit the child acknowledged.
+use std::env;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use kelvin::tests::rand::{rngs::StdRng, Rng, SeedableRng};
+use kelvin::tests::tempfile::tempdir;
+use kelvin::{Blake2b, LeafIterable, Root, Store};
+use kelvin_hamt::DefaultHAMTMap;
+
+type Map = DefaultHAMTMap<u64, u64, Blake2b>;
+
+const ENV_DIR: &str = "KELVIN_CRASH_DIR";
+const ENV_SEED: &str = "KELVIN_CRASH_SEED";
+
+const ROUNDS: u64 = 8;
+const KEY_SPACE: u64 = 512;
+// How far the child may get ahead of the last acknowledged commit
+const MAX_UNACKED: usize = 4096;
+
+/// A deterministic sequence of mutations, driven by a seed
+struct Workload(StdRng);
+
+impl Workload {
+    fn new(seed: u64) -> Self {
+        Workload(StdRng::seed_from_u64(seed))
+    }
+
+    fn step(&mut self, map: &mut Map) -> io::Result<()> {
+        for _ in 0..self.0.gen_range(1, 16) {
+            let key = self.0.gen_range(0, KEY_SPACE);
+            map.insert(key, self.0.gen())?;
+        }
+        for _ in 0..self.0.gen_range(0, 4) {
+            map.remove(&self.0.gen_range(0, KEY_SPACE))?;
+        }
+        Ok(())
+    }
+}
+
+fn digest(map: &mut Map) -> io::Result<<Blake2b as kelvin::ByteHash>::Digest> {
+    Ok(*Store::<Blake2b>::ephemeral().persist(map)?.hash())
+}
+
+fn child(dir: &Path, seed: u64) -> io::Result<()> {
+    let mut root = Root::<Map, Blake2b>::new(dir)?;
+    let mut map = root.restore()?;
+    let mut workload = Workload::new(seed);
+    let stdout = io::stdout();
+
+    for commit in 0.. {
+        workload.step(&mut map)?;
+        root.set_root(&mut map)?;
+
+        let mut out = stdout.lock();
+        writeln!(out, "{}", commit)?;
+        out.flush()?;
+    }
+    Ok(())
+}
+
+fn crash_round(seed: u64) -> io::Result<()> {
+    let dir = tempdir()?;
+    let mut rng = StdRng::seed_from_u64(!seed);
+
+    let mut child = Command::new(env::current_exe()?)
+        .env(ENV_DIR, dir.path())
+        .env(ENV_SEED, seed.to_string())
+        .stdout(Stdio::piped())
+        .spawn()?;
+
+    // Wait for a random number of acknowledged commits, then kill the
+    // child with some jitter, to land in the middle of a commit.
+    let kill_after = rng.gen_range(1, 64);
+    let mut acked = 0;
+    let mut lines = BufReader::new(child.stdout.take().expect("piped")).lines();
+    while acked < kill_after {
+        match lines.next() {
+            Some(line) => {
+                line?;
+                acked += 1;
+            }
+            None => break,
+        }
+    }
+    thread::sleep(Duration::from_micros(rng.gen_range(0, 2000)));
+    child.kill()?;
+    child.wait()?;
+
+    assert_eq!(acked, kill_after, "child exited prematurely");
+
+    let root = Root::<Map, Blake2b>::new(dir.path())?;
+    let mut restored = root.restore()?;
+
+    // Force every node reachable from the root to be read from disk
+    for leaf in restored.iter() {
+        leaf?;
+    }
+    let restored_digest = digest(&mut restored)?;
+
+    // Replay the workload in memory, the restored root must match one of
+    // the states committed at, or after, the last acknowledged commit.
+    let mut model = Map::new();
+    let mut workload = Workload::new(seed);
+    for commit in 0..acked + MAX_UNACKED {
+        workload.step(&mut model)?;
+        if commit + 1 >= acked && digest(&mut model)? == restored_digest {
+            return Ok(());
+        }
+    }
+    panic!(
+        "seed {}: restored root is not a committed state at or after \
+         commit {}",
+        seed, acked
+    );
+}
+
+fn main() -> io::Result<()> {
+    if let (Ok(dir), Ok(seed)) = (env::var(ENV_DIR), env::var(ENV_SEED)) {
+        let seed = seed.parse().expect("invalid seed");
+        return child(Path::new(&dir), seed);
+    }
+
+    for seed in 0..ROUNDS {
+        print!("crash round {} ... ", seed);
+        io::stdout().flush()?;
+        crash_round(seed)?;
+        println!("ok");
+    }
+    Ok(())
+}
@@ -0,0 +1,94 @@
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use kelvin::{Blake2b, Cursor, LeafIterable, MemBackend, Store};
+use kelvin_hamt::{DefaultHAMTMap, HAMTSearch};
+
+use common::Counting;
+
+type Map = DefaultHAMTMap<u64, u64, Blake2b>;
+
+fn map(n: u64) -> Map {
+    let mut map = Map::new();
+    for i in 0..n {
+        map.insert(i, i).unwrap();
+    }
+    map
+}
+
+#[test]
+fn both_ways() {
+    let map = map(500);
+    let keys: Vec<u64> = map.iter().map(|leaf| leaf.unwrap().key).collect();
+
+    let mut cursor = Cursor::new(&map);
+    assert!(cursor.value().is_none());
+    assert!(cursor.prev().unwrap().is_none());
+    let mut forward = vec![];
+    while let Some(leaf) = cursor.next().unwrap() {
+        forward.push(leaf.key);
+    }
+    assert_eq!(forward, keys);
+    assert!(cursor.value().is_none());
+
+    let mut backward = vec![];
+    while let Some(leaf) = cursor.prev().unwrap() {
+        backward.push(leaf.key);
+    }
+    backward.reverse();
+    assert_eq!(backward, keys);
+
+    // turning around midway
+    let mut cursor = Cursor::new(&map);
+    for _ in 0..10 {
+        cursor.next().unwrap();
+    }
+    assert_eq!(cursor.value().unwrap().key, keys[9]);
+    assert_eq!(cursor.prev().unwrap().unwrap().key, keys[8]);
+    assert_eq!(cursor.next().unwrap().unwrap().key, keys[9]);
+}
+
+#[test]
+fn seek_and_parent() {
+    let map = map(500);
+    let keys: Vec<u64> = map.iter().map(|leaf| leaf.unwrap().key).collect();
+
+    let mut cursor = Cursor::new(&map);
+    let at = keys.iter().position(|k| *k == 250).unwrap();
+    let found = cursor.seek(&mut HAMTSearch::from(&250)).unwrap().unwrap();
+    assert_eq!(found.val, 250);
+    assert_eq!(cursor.next().unwrap().unwrap().key, keys[at + 1]);
+    assert!(cursor.seek(&mut HAMTSearch::from(&1000)).unwrap().is_none());
+
+    cursor.seek(&mut HAMTSearch::from(&250)).unwrap().unwrap();
+    let depth = cursor.depth();
+    assert!(depth > 0);
+    assert!(cursor.parent());
+    assert_eq!(cursor.depth(), depth - 1);
+    assert!(cursor.value().is_none());
+
+    // moves past the subtree
+    let next = cursor.next().unwrap().unwrap().key;
+    let skipped = keys.iter().position(|k| *k == next).unwrap();
+    assert!(skipped > at);
+}
+
+#[test]
+fn nearby_seeks_reuse_path() {
+    let reads = Arc::new(AtomicUsize::new(0));
+    let store = Store::from_backend(Counting(MemBackend::new(), reads.clone()));
+    let snapshot = store.persist(&mut map(5000)).unwrap();
+    let map = snapshot.restore().unwrap();
+
+    let mut cursor = Cursor::new(&map);
+    cursor.seek(&mut HAMTSearch::from(&42)).unwrap().unwrap();
+    let read = reads.load(Ordering::SeqCst);
+    assert!(read > 1);
+
+    for _ in 0..10 {
+        cursor.seek(&mut HAMTSearch::from(&42)).unwrap().unwrap();
+    }
+    assert_eq!(reads.load(Ordering::SeqCst), read);
+}
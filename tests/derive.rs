@@ -0,0 +1,131 @@
+use std::fmt::Debug;
+
+use kelvin::annotations::{Cardinality, Count, FirstWhere, Sum};
+use kelvin::{
+    Annotation, Associative, Blake2b, Branch, Compound, Content, Store, KV,
+};
+use kelvin_hamt::HAMT;
+
+#[derive(Clone, Debug, PartialEq, Content)]
+struct Account {
+    balance: u64,
+    nonce: u32,
+    frozen: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Content)]
+struct Pair(u8, Vec<u16>);
+
+#[derive(Clone, Debug, PartialEq, Content)]
+struct Marker;
+
+#[derive(Clone, Debug, PartialEq, Content)]
+struct Tagged<T> {
+    tag: u8,
+    inner: T,
+}
+
+#[derive(Clone, Debug, PartialEq, Content)]
+enum Event {
+    Created,
+    Moved(u64, u64),
+    Renamed { from: String, to: String },
+    Nested(Tagged<Account>),
+}
+
+fn round_trip<T: Content<Blake2b> + Debug + PartialEq>(mut t: T) {
+    let store = Store::<Blake2b>::ephemeral();
+    let snapshot = store.persist(&mut t).unwrap();
+    assert_eq!(store.restore(&snapshot).unwrap(), t);
+}
+
+#[test]
+fn derived_round_trips() {
+    round_trip(Account {
+        balance: 100,
+        nonce: 7,
+        frozen: true,
+    });
+    round_trip(Pair(3, vec![1, 2, 3]));
+    round_trip(Marker);
+    round_trip(Tagged {
+        tag: 1,
+        inner: Pair(0, vec![]),
+    });
+    round_trip(Event::Created);
+    round_trip(Event::Moved(1, 2));
+    round_trip(Event::Renamed {
+        from: "a".into(),
+        to: "b".into(),
+    });
+    round_trip(Event::Nested(Tagged {
+        tag: 2,
+        inner: Account {
+            balance: 0,
+            nonce: 0,
+            frozen: false,
+        },
+    }));
+}
+
+#[test]
+fn fields_in_declaration_order() {
+    let store = Store::<Blake2b>::ephemeral();
+    let a = store.persist(&mut Pair(1, vec![2])).unwrap();
+    let b = store.persist(&mut (1u8, vec![2u16])).unwrap();
+    assert_eq!(a.hash(), b.hash());
+}
+
+// Bloom filter over the keys of a subtree
+#[derive(Clone, Content)]
+struct Bloom(u64);
+
+impl Bloom {
+    fn may_contain(&self, key: u64) -> bool {
+        self.0 & (1 << (key % 64)) != 0
+    }
+}
+
+impl Associative for Bloom {
+    fn op(&mut self, b: &Self) {
+        self.0 |= b.0
+    }
+}
+
+impl From<&KV<u64, u64>> for Bloom {
+    fn from(kv: &KV<u64, u64>) -> Self {
+        Bloom(1 << (kv.key % 64))
+    }
+}
+
+#[derive(Clone, Content, Annotation)]
+struct Stats {
+    #[annotation(borrow)]
+    count: Cardinality<u64>,
+    sum: Sum<u64>,
+    bloom: Bloom,
+}
+
+#[test]
+fn derived_annotations() {
+    let mut map = HAMT::<u64, u64, Stats, Blake2b>::new();
+    for i in 0..100 {
+        map.insert(i * 128, i).unwrap();
+    }
+    let store = Store::<Blake2b>::ephemeral();
+    let map: HAMT<_, _, Stats, _> =
+        store.persist(&mut map).unwrap().restore().unwrap();
+
+    let count: u64 = map.count();
+    assert_eq!(count, 100);
+    let stats = map.annotation().unwrap();
+    assert_eq!(*stats.sum, (0..100).sum::<u64>());
+    // every key is a multiple of 64
+    assert!(stats.bloom.may_contain(0));
+    assert!(!stats.bloom.may_contain(1));
+
+    // subtrees are skipped by their filters
+    let mut method =
+        FirstWhere::new(|stats: &Stats| stats.bloom.may_contain(1));
+    assert!(Branch::new(&map, &mut method).unwrap().is_none());
+}
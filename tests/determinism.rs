@@ -0,0 +1,108 @@
+//! Determinism of persisted output
+//!
+//! Replicas must end up with identical roots and identical store contents,
+//! independently of the order in which they were built, and of how many
+//! threads were persisting into the same store at the time.
+use std::fs;
+use std::path::Path;
+use std::thread;
+
+use kelvin::tests::rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use kelvin::tests::tempfile::tempdir;
+use kelvin::{Blake2b, ByteHash, LeafIterable, Root, Store};
+use kelvin_hamt::DefaultHAMTMap;
+
+type Map = DefaultHAMTMap<u64, u64, Blake2b>;
+type Digest = <Blake2b as ByteHash>::Digest;
+
+const ENTRIES: u64 = 1024;
+
+fn shuffled(seed: u64) -> Vec<u64> {
+    let mut keys: Vec<u64> = (0..ENTRIES).collect();
+    keys.shuffle(&mut StdRng::seed_from_u64(seed));
+    keys
+}
+
+fn build(keys: &[u64]) -> Map {
+    let mut map = Map::new();
+    for key in keys {
+        map.insert(*key, key.wrapping_mul(31)).unwrap();
+    }
+    map
+}
+
+fn digest(map: &mut Map) -> Digest {
+    *Store::<Blake2b>::ephemeral().persist(map).unwrap().hash()
+}
+
+fn read(path: &Path) -> Vec<u8> {
+    fs::read(path.join("data")).unwrap()
+}
+
+#[test]
+fn insertion_order_independent() {
+    let expected = digest(&mut build(&shuffled(0)));
+    for seed in 1..8 {
+        assert!(digest(&mut build(&shuffled(seed))) == expected);
+    }
+}
+
+#[test]
+fn byte_identical_replicas() {
+    let a = tempdir().unwrap();
+    let b = tempdir().unwrap();
+
+    let mut root_a = Root::<Map, Blake2b>::new(a.path()).unwrap();
+    let mut root_b = Root::<Map, Blake2b>::new(b.path()).unwrap();
+
+    let snap_a = root_a.set_root(&mut build(&shuffled(1))).unwrap();
+    // build the second replica on another thread
+    let mut map_b = thread::spawn(|| build(&shuffled(2))).join().unwrap();
+    let snap_b = root_b.set_root(&mut map_b).unwrap();
+
+    assert!(snap_a.hash() == snap_b.hash());
+    assert_eq!(read(a.path()), read(b.path()));
+    assert_eq!(
+        fs::read(a.path().join("roots").join("root")).unwrap(),
+        fs::read(b.path().join("roots").join("root")).unwrap()
+    );
+}
+
+#[test]
+fn thread_count_independent() {
+    let expected: Vec<Digest> = (0..8)
+        .map(|seed| {
+            let mut keys = shuffled(seed);
+            keys.truncate(512);
+            digest(&mut build(&keys))
+        })
+        .collect();
+
+    for &threads in &[1u64, 2, 8] {
+        let store = Store::<Blake2b>::ephemeral();
+        let mut handles = vec![];
+        for t in 0..threads {
+            let store = store.clone();
+            handles.push(thread::spawn(move || {
+                (0..8)
+                    .filter(|seed| seed % threads == t)
+                    .map(|seed| {
+                        let mut keys = shuffled(seed);
+                        keys.truncate(512);
+                        let snap = store.persist(&mut build(&keys)).unwrap();
+                        (seed, *snap.hash(), snap)
+                    })
+                    .collect::<Vec<_>>()
+            }))
+        }
+
+        for handle in handles {
+            for (seed, hash, snap) in handle.join().unwrap() {
+                assert!(hash == expected[seed as usize]);
+                // every interleaving must leave a complete tree behind
+                let restored = store.restore(&snap).unwrap();
+                assert_eq!(restored.iter().map(|l| l.unwrap()).count(), 512);
+            }
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use std::io;
+
+use kelvin::annotation;
+use kelvin::annotations::{ByteSize, Cardinality};
+use kelvin::{
+    diff, diff_stats, Blake2b, ByteHash, Change, Content, DiffStats, Sink,
+    Source, Store,
+};
+use kelvin_hamt::{DefaultHAMTMap, HAMT};
+
+annotation! {
+    struct Sizes {
+        count: Cardinality<u64>,
+        size: ByteSize<Blake2b>,
+    }
+}
+
+type Map = DefaultHAMTMap<u64, u64, Blake2b>;
+type SizedMap = HAMT<u64, u64, Sizes, Blake2b>;
+
+#[test]
+fn diff_summary() {
+    let store = Store::<Blake2b>::ephemeral();
+
+    let mut map = SizedMap::new();
+    for i in 0..1024 {
+        map.insert(i, i).unwrap();
+    }
+    let old = store.persist(&mut map).unwrap();
+    let mut map = store.restore(&old).unwrap();
+
+    assert_eq!(diff_stats(&old, &old).unwrap(), DiffStats::default());
+
+    // add 10, remove 20, modify 30
+    for i in 1024..1034 {
+        map.insert(i, i).unwrap();
+    }
+    for i in 0..20 {
+        map.remove(&i).unwrap();
+    }
+    for i in 100..130 {
+        map.insert(i, i + 1).unwrap();
+    }
+    // rewriting a value unchanged is not a modification
+    map.insert(500, 500).unwrap();
+    let new = store.persist(&mut map).unwrap();
+
+    let stats = diff_stats(&old, &new).unwrap();
+    assert_eq!(stats.added, 10);
+    assert_eq!(stats.removed, 20);
+    assert_eq!(stats.modified, 30);
+    // each leaf encodes two u64
+    assert_eq!(stats.bytes, -10 * 16);
+
+    let back = diff_stats(&new, &old).unwrap();
+    assert_eq!(back.added, 20);
+    assert_eq!(back.removed, 10);
+    assert_eq!(back.bytes, 10 * 16);
+
+    let mut grown = store.restore(&new).unwrap();
+    for i in 10_000..11_000 {
+        grown.insert(i, i).unwrap();
+    }
+    let grown = store.persist(&mut grown).unwrap();
+    let expected = DiffStats {
+        added: 1000,
+        removed: 0,
+        modified: 0,
+        bytes: 1000 * 16,
+    };
+    assert_eq!(diff_stats(&new, &grown).unwrap(), expected);
+}
+
+#[test]
+fn diff_changes() {
+    let store = Store::<Blake2b>::ephemeral();
+
+    let mut map = Map::new();
+    for i in 0..1024 {
+        map.insert(i, i).unwrap();
+    }
+    let old = store.persist(&mut map).unwrap();
+    let mut map = store.restore(&old).unwrap();
+    assert_eq!(diff(&old, &old).unwrap().count(), 0);
+
+    map.insert(2000, 1).unwrap();
+    map.remove(&7).unwrap();
+    map.insert(42, 43).unwrap();
+    map.insert(500, 500).unwrap();
+    let new = store.persist(&mut map).unwrap();
+
+    let mut changes: Vec<_> =
+        diff(&old, &new).unwrap().map(Result::unwrap).collect();
+    changes.sort_by_key(|change| match change {
+        Change::Added(k, _) | Change::Removed(k, _) => *k,
+        Change::Modified(k, _, _) => *k,
+    });
+    assert_eq!(
+        changes,
+        vec![
+            Change::Removed(7, 7),
+            Change::Modified(42, 42, 43),
+            Change::Added(2000, 1),
+        ]
+    );
+
+    let back: Vec<_> = diff(&new, &old).unwrap().map(Result::unwrap).collect();
+    assert_eq!(back.len(), 3);
+    assert!(back.contains(&Change::Added(7, 7)));
+    assert!(back.contains(&Change::Modified(42, 43, 42)));
+    assert!(back.contains(&Change::Removed(2000, 1)));
+}
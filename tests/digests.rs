@@ -0,0 +1,83 @@
+//! Digest fixtures, which must hold on every architecture
+//!
+//! The expected values were computed on x86_64, any platform computing a
+//! different root for the same content breaks consensus with it.
+use kelvin::{portable_hash, Blake2b, ByteHash, Content, Store};
+use kelvin_hamt::DefaultHAMTMap;
+
+fn hex(digest: &<Blake2b as ByteHash>::Digest) -> String {
+    digest.as_ref()[..16]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn root<C: Content<Blake2b>>(mut content: C) -> String {
+    hex(Store::<Blake2b>::ephemeral()
+        .persist(&mut content)
+        .unwrap()
+        .hash())
+}
+
+#[test]
+fn content_digests() {
+    assert_eq!(root(42u64), "2435c0556492aa127a991d10219c4e69");
+    assert_eq!(root(-7i32), "6a59ce0f7ba884d3f31a2ab92baa39e8");
+    assert_eq!(
+        root(String::from("kelvin")),
+        "3453423a1734d9b66a20dce7b4abf373"
+    );
+    assert_eq!(root(vec![1u16, 2, 3]), "74fba79433dd57ad0aed1ad1cc1c1a10");
+    assert_eq!(
+        root((u128::max_value(), true)),
+        "8be500c53386b67db2a8cab6b2cc11d4"
+    );
+}
+
+#[test]
+fn key_hashes() {
+    assert_eq!(
+        hex(&portable_hash::<Blake2b, _>(&42u64)),
+        "5a1f0afdd3d6ee56b265373709961343"
+    );
+    assert_eq!(
+        hex(&portable_hash::<Blake2b, _>("kelvin")),
+        "e205c3f9bd4870be9d525154ad356ffa"
+    );
+    assert_eq!(
+        hex(&portable_hash::<Blake2b, _>(&[1u32, 2, 3][..])),
+        "fbc2393a0c919b695cab4759a29c75bf"
+    );
+}
+
+#[test]
+fn hamt_digests() {
+    let mut map = DefaultHAMTMap::<u64, u64, Blake2b>::new();
+    for i in 0..256 {
+        map.insert(i, i * 3).unwrap();
+    }
+    assert_eq!(root(map), "07a12370c08873b85608ca36923a97c3");
+
+    let mut map = DefaultHAMTMap::<String, u32, Blake2b>::new();
+    for i in 0..64 {
+        map.insert(format!("key-{}", i), i).unwrap();
+    }
+    assert_eq!(root(map), "b190051c77db36c24f6628e8e06c03f2");
+}
+
+#[test]
+fn digests_without_domain_separation() {
+    // as persisted before format versions, the fixtures above back then
+    fn legacy<C: Content<Blake2b>>(mut content: C) -> String {
+        let store = Store::<Blake2b>::ephemeral();
+        store.set_domain_separation(false);
+        hex(store.persist(&mut content).unwrap().hash())
+    }
+
+    assert_eq!(legacy(42u64), "f3b5159bfc1a0af693fd21af8c8f3ffb");
+    let mut map = DefaultHAMTMap::<u64, u64, Blake2b>::new();
+    for i in 0..256 {
+        map.insert(i, i * 3).unwrap();
+    }
+    assert_eq!(legacy(map), "02b44c008eb6c597461ec517fcfdba9c");
+}
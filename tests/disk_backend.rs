@@ -0,0 +1,54 @@
+use kelvin::tests::tempfile::tempdir;
+use kelvin::{Blake2b, DiskBackend, MemBackend, Store};
+use kelvin_hamt::DefaultHAMTMap;
+
+type Map = DefaultHAMTMap<u64, u64, Blake2b>;
+
+#[test]
+fn durable_roundtrip() {
+    let dir = tempdir().unwrap();
+    let backend = DiskBackend::<Blake2b>::new(dir.path()).unwrap();
+    let store = Store::from_backend(backend);
+
+    let mut map = Map::new();
+    for i in 0..1000 {
+        map.insert(i, i).unwrap();
+    }
+    let snapshot = store.persist(&mut map).unwrap();
+    store.flush().unwrap();
+
+    let restored = snapshot.restore().unwrap();
+    assert_eq!(*restored.get(&999).unwrap().unwrap(), 999);
+
+    // the same data is found when the directory is opened again
+    let reopened = Store::<Blake2b>::new(dir.path()).unwrap();
+    reopened.verify(&snapshot).unwrap();
+}
+
+#[test]
+fn custom_backend() {
+    let store = Store::<Blake2b>::from_backend(MemBackend::new());
+    let snapshot = store.persist(&mut vec![1u64, 2, 3]).unwrap();
+    assert_eq!(snapshot.restore().unwrap(), vec![1, 2, 3]);
+}
+
+#[test]
+fn mmap_backend() {
+    let dir = tempdir().unwrap();
+    let mut map = Map::new();
+    for i in 0..1000 {
+        map.insert(i, i).unwrap();
+    }
+    let snapshot = {
+        let store = Store::<Blake2b>::new(dir.path()).unwrap();
+        let snapshot = store.persist(&mut map).unwrap();
+        store.flush().unwrap();
+        snapshot
+    };
+
+    let backend = DiskBackend::<Blake2b>::open_mmap(dir.path()).unwrap();
+    let store = Store::from_backend(backend);
+    store.verify(&snapshot).unwrap();
+    let restored: Map = store.restore(&snapshot).unwrap();
+    assert_eq!(*restored.get(&500).unwrap().unwrap(), 500);
+}
@@ -0,0 +1,47 @@
+use std::collections::hash_map::DefaultHasher;
+
+use bytehash::Wrapped;
+use kelvin::proof;
+use kelvin::{Blake2b, Control, DualMap, Store, KV};
+use kelvin_hamt::DefaultHAMTMap;
+
+type Old = DefaultHAMTMap<u64, u64, Blake2b>;
+type New = DefaultHAMTMap<u64, u64, Wrapped<DefaultHasher>>;
+
+#[test]
+fn writes_and_proofs_under_both_hashes() {
+    let old_store = Store::<Blake2b>::ephemeral();
+    let new_store = Store::<Wrapped<DefaultHasher>>::ephemeral();
+
+    let mut map = Old::new();
+    for i in 0..100 {
+        map.insert(i, i).unwrap();
+    }
+
+    let mut dual: DualMap<Old, New, _, _> =
+        DualMap::new(map, &mut Control::none()).unwrap();
+    for i in 100..200 {
+        assert_eq!(dual.insert(i, i).unwrap(), None);
+    }
+    assert_eq!(dual.insert(0, 1000).unwrap(), Some(0));
+    assert_eq!(dual.remove(&1).unwrap(), Some(1));
+
+    let (old_root, new_root) = dual.persist(&old_store, &new_store).unwrap();
+
+    for i in (2..200).step_by(13) {
+        let proof = dual.old().prove(&i).unwrap().unwrap();
+        assert!(proof::verify(old_root.hash(), &proof, &KV::new(i, i)).unwrap());
+        let proof = dual.new_map().prove(&i).unwrap().unwrap();
+        assert!(proof::verify(new_root.hash(), &proof, &KV::new(i, i)).unwrap());
+    }
+
+    let old = old_store.restore(&old_root).unwrap();
+    let new = new_store.restore(&new_root).unwrap();
+    assert_eq!(*old.get(&0).unwrap().unwrap(), 1000);
+    assert!(old.get(&1).unwrap().is_none());
+    assert_eq!(*new.get(&0).unwrap().unwrap(), 1000);
+    assert!(new.get(&1).unwrap().is_none());
+
+    let new = dual.into_new();
+    assert_eq!(*new.get(&199).unwrap().unwrap(), 199);
+}
@@ -0,0 +1,49 @@
+use kelvin::{Blake2b, Entry, MapMut, Store};
+use kelvin_hamt::DefaultHAMTMap;
+
+type Map = DefaultHAMTMap<String, u64, Blake2b>;
+
+#[test]
+fn count_words() {
+    let text = "the quick brown fox jumps over the lazy dog the end";
+    let mut map = Map::new();
+    for word in text.split(' ') {
+        *map.entry(word.into()).unwrap().or_insert(0).unwrap() += 1;
+    }
+    assert_eq!(*map.get("the").unwrap().unwrap(), 3);
+    assert_eq!(*map.get("fox").unwrap().unwrap(), 1);
+
+    // entries are found in restored maps as well
+    let store = Store::<Blake2b>::ephemeral();
+    let snapshot = store.persist(&mut map).unwrap();
+    let mut map = store.restore(&snapshot).unwrap();
+    map.entry("fox".into())
+        .unwrap()
+        .and_modify(|n| *n += 10)
+        .or_insert_with(|| unreachable!())
+        .unwrap();
+    assert_eq!(*map.get("fox").unwrap().unwrap(), 11);
+}
+
+#[test]
+fn vacant_and_occupied() {
+    let mut map = Map::new();
+    match map.entry("a".into()).unwrap() {
+        Entry::Vacant(vacant) => {
+            assert_eq!(vacant.key(), "a");
+            assert_eq!(*vacant.insert(1).unwrap(), 1);
+        }
+        Entry::Occupied(_) => panic!("entry should be vacant"),
+    }
+    match map.entry("a".into()).unwrap() {
+        Entry::Occupied(mut value) => *value = 2,
+        Entry::Vacant(_) => panic!("entry should be occupied"),
+    }
+    // nothing is modified or inserted in vacant entries
+    assert!(matches!(
+        map.entry("b".into()).unwrap().and_modify(|_| panic!()),
+        Entry::Vacant(_)
+    ));
+    assert_eq!(*map.get("a").unwrap().unwrap(), 2);
+    assert!(map.get("b").unwrap().is_none());
+}
@@ -0,0 +1,43 @@
+use kelvin::{estimate_count, Blake2b, Store};
+use kelvin_hamt::CountingHAMTMap;
+
+type Map = CountingHAMTMap<u64, u64, Blake2b>;
+
+#[test]
+fn estimate_matching() {
+    let mut map = Map::new();
+    for i in 0..20_000 {
+        map.insert(i, i).unwrap();
+    }
+    let store = Store::<Blake2b>::ephemeral();
+    let snapshot = store.persist(&mut map).unwrap();
+    let map = store.restore(&snapshot).unwrap();
+
+    let estimate =
+        estimate_count(&map, |leaf| leaf.val % 4 == 0, 2000).unwrap();
+    assert_eq!(estimate.total, 20_000);
+    assert_eq!(estimate.sampled, 2000);
+    assert!(!estimate.exact);
+
+    // wide enough for the test to practically never fail
+    let (low, high) = estimate.interval(5.0);
+    assert!(low <= 5000.0 && 5000.0 <= high);
+    let (low95, high95) = estimate.bounds();
+    assert!(low <= low95 && low95 <= estimate.count());
+    assert!(estimate.count() <= high95 && high95 <= high);
+}
+
+#[test]
+fn small_maps_are_counted() {
+    let mut map = Map::new();
+    let empty = estimate_count(&map, |_| true, 10).unwrap();
+    assert_eq!(empty.count(), 0.0);
+
+    for i in 0..100 {
+        map.insert(i, i).unwrap();
+    }
+    let estimate = estimate_count(&map, |leaf| leaf.key < 30, 100).unwrap();
+    assert!(estimate.exact);
+    assert_eq!(estimate.count(), 30.0);
+    assert_eq!(estimate.bounds(), (30.0, 30.0));
+}
@@ -0,0 +1,86 @@
+use std::io;
+
+use kelvin::{export, import, Blake2b, Blob, ByteHash, Store};
+use kelvin_hamt::DefaultHAMTMap;
+
+type Map = DefaultHAMTMap<u64, u64, Blake2b>;
+type Digest = <Blake2b as ByteHash>::Digest;
+
+fn exported() -> (Vec<u8>, usize, Digest) {
+    let store = Store::<Blake2b>::ephemeral();
+    let mut map = Map::new();
+    for i in 0..1000 {
+        map.insert(i, i + 1).unwrap();
+    }
+    let snapshot = store.persist(&mut map).unwrap();
+    let mut bytes = vec![];
+    let nodes = export(&snapshot, &mut bytes).unwrap();
+    assert_eq!(nodes, store.node_records(&snapshot).count());
+    (bytes, nodes, *snapshot.hash())
+}
+
+#[test]
+fn round_trip() {
+    let (bytes, nodes, root) = exported();
+
+    let store = Store::<Blake2b>::ephemeral();
+    let snapshot = import::<Map, _, _>(&bytes[..], &store).unwrap();
+    assert_eq!(*snapshot.hash(), root);
+    assert_eq!(store.node_records(&snapshot).count(), nodes);
+    store.verify(&snapshot).unwrap();
+
+    let map = snapshot.restore().unwrap();
+    for i in 0..1000 {
+        assert_eq!(*map.get(&i).unwrap().unwrap(), i + 1);
+    }
+}
+
+#[test]
+fn damaged_exports_rejected() {
+    let (bytes, _, _) = exported();
+    let store = Store::<Blake2b>::ephemeral();
+
+    let mut flipped = bytes.clone();
+    let last = flipped.len() - 2;
+    flipped[last] ^= 1;
+    let err = import::<Map, _, _>(&flipped[..], &store).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    let truncated = &bytes[..bytes.len() / 2];
+    assert!(import::<Map, _, _>(truncated, &store).is_err());
+
+    let err = import::<Map, _, _>(&b"nope"[..], &store).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn blob_chunks_exported() {
+    type Blobs = DefaultHAMTMap<u64, Blob<String>, Blake2b>;
+
+    let store = Store::<Blake2b>::ephemeral();
+    store.set_max_leaf_size(1024);
+    let huge = "x".repeat(100_000);
+    let mut map = Blobs::new();
+    map.insert(1, Blob::new(huge.clone())).unwrap();
+    let snapshot = store.persist(&mut map).unwrap();
+    let mut bytes = vec![];
+    let nodes = export(&snapshot, &mut bytes).unwrap();
+    // the link and the chunks, on top of the nodes of the map
+    assert!(nodes > store.node_records(&snapshot).count() + 1);
+
+    let other = Store::<Blake2b>::ephemeral();
+    let snapshot = import::<Blobs, _, _>(&bytes[..], &other).unwrap();
+    let map = snapshot.restore().unwrap();
+    assert_eq!(*map.get(&1).unwrap().unwrap().get().unwrap(), huge);
+}
+
+#[test]
+fn oversized_nodes_rejected() {
+    let (bytes, _, _) = exported();
+    // the length of the first node, claiming 4 GiB
+    let mut oversized = bytes[..4 + 1 + 32 + 1].to_vec();
+    oversized.extend_from_slice(&[0xff; 4]);
+    let store = Store::<Blake2b>::ephemeral();
+    let err = import::<Map, _, _>(&oversized[..], &store).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
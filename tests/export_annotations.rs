@@ -0,0 +1,34 @@
+use kelvin::{export_annotations, Blake2b, Store};
+use kelvin_hamt::CountingHAMTMap;
+
+type Map = CountingHAMTMap<u64, u64, Blake2b>;
+
+#[test]
+fn export_to_depth() {
+    let store = Store::<Blake2b>::ephemeral();
+    let mut map = Map::new();
+    for i in 0..10_000 {
+        map.insert(i, i).unwrap();
+    }
+    let snapshot = store.persist(&mut map).unwrap();
+    let restored = store.restore(&snapshot).unwrap();
+
+    let root = export_annotations(&restored, 0).unwrap();
+    assert_eq!(root.len(), 1);
+    assert_eq!(root[0].depth(), 0);
+    assert_eq!(*root[0].annotation, 10_000);
+
+    let shallow = export_annotations(&restored, 1).unwrap();
+    assert!(shallow.len() > 1);
+    assert!(shallow[1..].iter().all(|r| r.depth() == 1));
+    let below: u64 = shallow[1..].iter().map(|r| *r.annotation).sum();
+    assert!(below > 0 && below <= 10_000);
+
+    // to full depth, every node is exported once
+    let all = export_annotations(&restored, usize::MAX).unwrap();
+    let nodes = store.node_records(&snapshot).count();
+    assert_eq!(all.len(), nodes);
+    assert!(all.windows(2).all(|w| w[0].path < w[1].path));
+
+    assert!(export_annotations(&Map::new(), 1).unwrap().is_empty());
+}
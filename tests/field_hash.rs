@@ -0,0 +1,87 @@
+use std::hash::Hasher;
+
+use kelvin::proof;
+use kelvin::{frame, Algebraic, ByteHash, ByteHashState, FieldHash, Store, KV};
+use kelvin_hamt::DefaultHAMTMap;
+
+// The Mersenne prime 2^61 - 1
+const P: u64 = (1 << 61) - 1;
+
+fn mul(a: u64, b: u64) -> u64 {
+    ((a as u128 * b as u128) % P as u128) as u64
+}
+
+// A toy sponge over a 61 bit field, standing in for Poseidon
+#[derive(Clone, Debug)]
+struct Toy;
+
+impl FieldHash for Toy {
+    type Element = u64;
+
+    const PACKED_BYTES: usize = 7;
+
+    fn pack(bytes: &[u8]) -> u64 {
+        let mut le = [0u8; 8];
+        le[..bytes.len()].copy_from_slice(bytes);
+        u64::from_le_bytes(le)
+    }
+
+    fn hash(elements: &[u64]) -> u64 {
+        elements.iter().fold(elements.len() as u64, |state, e| {
+            let x = (state + e) % P;
+            mul(mul(mul(x, x), mul(x, x)), x)
+        })
+    }
+
+    fn encode(element: &u64) -> [u8; 32] {
+        let mut digest = [0u8; 32];
+        digest[..8].copy_from_slice(&element.to_le_bytes());
+        digest
+    }
+}
+
+type Map = DefaultHAMTMap<u64, u64, Algebraic<Toy>>;
+
+fn digest(bytes: &[u8]) -> [u8; 32] {
+    let mut state = Algebraic::<Toy>::state();
+    // written in pieces not aligned to the elements
+    for piece in bytes.chunks(3) {
+        state.write(piece);
+    }
+    state.fin()
+}
+
+#[test]
+fn framing() {
+    assert_eq!(frame::<Toy>(b""), vec![1]);
+    assert_eq!(frame::<Toy>(b"\x02"), vec![0x0102]);
+    assert_eq!(frame::<Toy>(&[0xff; 7]).len(), 2);
+    // trailing zeros are not lost
+    assert_ne!(frame::<Toy>(b"a"), frame::<Toy>(b"a\0"));
+
+    for len in 0..30 {
+        let bytes: Vec<u8> = (0..len).map(|i| i as u8 * 37).collect();
+        assert_eq!(
+            digest(&bytes),
+            Toy::encode(&Toy::hash(&frame::<Toy>(&bytes)))
+        );
+    }
+}
+
+#[test]
+fn trees_over_field_hashes() {
+    let store = Store::<Algebraic<Toy>>::ephemeral();
+    let mut map = Map::new();
+    for i in 0..500 {
+        map.insert(i, i * 3).unwrap();
+    }
+    let snapshot = store.persist(&mut map).unwrap();
+    let root = *snapshot.hash();
+
+    let restored = store.restore(&snapshot).unwrap();
+    for i in (0..500).step_by(11) {
+        assert_eq!(*restored.get(&i).unwrap().unwrap(), i * 3);
+        let proof = restored.prove(&i).unwrap().unwrap();
+        assert!(proof::verify(&root, &proof, &KV::new(i, i * 3)).unwrap());
+    }
+}
@@ -0,0 +1,125 @@
+use std::hash::Hasher;
+use std::io::{self, Write};
+
+use kelvin::{
+    Backend, Blake2b, ByteHash, ByteHashState, Content, Domain, Error,
+    MemBackend, Sink, Source, Store, FORMAT_VERSION,
+};
+use kelvin_hamt::DefaultHAMTMap;
+
+// Reads as the format version of the node it is restored from
+#[derive(Clone)]
+struct Version(u8, Option<Domain>);
+
+impl<H: ByteHash> Content<H> for Version {
+    fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
+        sink.write_all(&[0xff])
+    }
+
+    fn restore(source: &mut Source<H>) -> io::Result<Self> {
+        Ok(Version(source.version(), source.domain()))
+    }
+}
+
+// A store holding `bytes` as a node, as written by some earlier version,
+// and reading legacy nodes
+fn stored(bytes: &[u8]) -> (Store<Blake2b>, <Blake2b as ByteHash>::Digest) {
+    let mut state = Blake2b::state();
+    state.write(bytes);
+    let digest = state.fin();
+    let mut backend = MemBackend::new();
+    backend.put(digest, bytes.to_vec()).unwrap();
+    let store = Store::from_backend(backend);
+    store.set_legacy_reads(true);
+    (store, digest)
+}
+
+#[test]
+fn versioned_nodes() {
+    let store = Store::<Blake2b>::ephemeral();
+    let snapshot = store.persist(&mut Version(0, None)).unwrap();
+    let version = snapshot.restore().unwrap();
+    assert_eq!(version.0, FORMAT_VERSION);
+    assert_eq!(version.1, Some(Domain::Leaf));
+
+    let snapshot = store.persist(&mut 42u64).unwrap();
+    assert_eq!(snapshot.restore().unwrap(), 42);
+}
+
+#[test]
+fn unversioned_nodes() {
+    // written before format versions, without a header
+    let (store, digest) = stored(&42u64.to_be_bytes());
+    assert_eq!(store.snapshot::<u64>(&digest).restore().unwrap(), 42);
+
+    let (store, digest) = stored(&[0xff]);
+    assert_eq!(store.snapshot::<Version>(&digest).restore().unwrap().0, 0);
+
+    // written before domain separation
+    let (store, digest) = stored(&[0xce, 1, 0xff]);
+    let version = store.snapshot::<Version>(&digest).restore().unwrap();
+    assert_eq!(version.0, 1);
+    assert_eq!(version.1, None);
+}
+
+#[test]
+fn unversioned_nodes_leading_with_the_magic_byte() {
+    // headerless nodes that happen to start like a format header
+    for value in &[0xce01_0000_0000_002au64, 0xce02_0000_0000_002a] {
+        let (store, digest) = stored(&value.to_be_bytes());
+        assert_eq!(store.snapshot::<u64>(&digest).restore().unwrap(), *value);
+    }
+}
+
+#[test]
+fn legacy_nodes_are_only_read_on_purpose() {
+    for bytes in &[&42u64.to_be_bytes()[..], &[0xce, 1, 0xff]] {
+        let (store, digest) = stored(bytes);
+        store.set_legacy_reads(false);
+        let err = store.snapshot::<Version>(&digest).restore().unwrap_err();
+        match Error::from(err) {
+            Error::InvalidEncoding(_) => (),
+            e => panic!("{:?}", e),
+        }
+    }
+}
+
+#[test]
+fn domains_are_checked() {
+    let store = Store::<Blake2b>::ephemeral();
+    let mut map = DefaultHAMTMap::<u64, u64, Blake2b>::new();
+    map.insert(1, 2).unwrap();
+    let snapshot = store.persist(&mut map).unwrap();
+    let err = store.snapshot::<Version>(snapshot.hash()).restore();
+    match Error::from(err.err().unwrap()) {
+        Error::InvalidEncoding(_) => (),
+        e => panic!("{:?}", e),
+    }
+
+    // nor when reading legacy nodes, which are never read after a node of
+    // another domain
+    store.set_legacy_reads(true);
+    let err = store.snapshot::<Version>(snapshot.hash()).restore();
+    assert!(err.is_err());
+
+    // the same node, persisted without domain separation, reads as any
+    let legacy = Store::<Blake2b>::ephemeral();
+    legacy.set_domain_separation(false);
+    let snapshot = legacy.persist(&mut map).unwrap();
+    let version = legacy.snapshot::<Version>(snapshot.hash());
+    assert_eq!(version.restore().unwrap().0, 0);
+    let restored = legacy.restore(&snapshot).unwrap();
+    assert_eq!(*restored.get(&1).unwrap().unwrap(), 2);
+}
+
+#[test]
+fn newer_versions_are_rejected() {
+    let (store, digest) = stored(&[0xce, FORMAT_VERSION + 1, 0xff]);
+    let err = store.snapshot::<Version>(&digest).restore().unwrap_err();
+    match Error::from(err) {
+        Error::UnsupportedVersion(version) => {
+            assert_eq!(version, FORMAT_VERSION + 1)
+        }
+        e => panic!("{:?}", e),
+    }
+}
@@ -0,0 +1,45 @@
+use std::thread;
+
+use kelvin::{Blake2b, Freeze, Frozen, Store};
+use kelvin_hamt::DefaultHAMTMap;
+
+type Map = DefaultHAMTMap<u64, u64, Blake2b>;
+
+fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+#[test]
+fn shared_between_threads() {
+    let mut map = Map::new();
+    for i in 0..1000 {
+        map.insert(i, i).unwrap();
+    }
+    let store = Store::<Blake2b>::ephemeral();
+    let snapshot = store.persist(&mut map).unwrap();
+    let frozen: Frozen<Map, Blake2b> =
+        store.restore(&snapshot).unwrap().freeze();
+    assert_send_sync(&frozen);
+
+    let handles: Vec<_> = (0..4)
+        .map(|t| {
+            let frozen = frozen.clone();
+            thread::spawn(move || {
+                for i in (t..1000).step_by(4) {
+                    assert_eq!(*frozen.get(&i).unwrap().unwrap(), i);
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    // changes are made to a copy, leaving the frozen state untouched
+    let mut thawed = frozen.thaw();
+    thawed.insert(0, 42).unwrap();
+    assert_eq!(*thawed.get(&0).unwrap().unwrap(), 42);
+    assert_eq!(*frozen.get(&0).unwrap().unwrap(), 0);
+
+    let mut map = frozen.into_inner();
+    map.remove(&0).unwrap();
+    assert!(map.get(&0).unwrap().is_none());
+}
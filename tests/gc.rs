@@ -0,0 +1,163 @@
+use std::io;
+
+use kelvin::tests::tempfile::tempdir;
+use kelvin::{Blake2b, CancelToken, Content, Control, Store};
+use kelvin_hamt::DefaultHAMTMap;
+
+type Map = DefaultHAMTMap<u64, u64, Blake2b>;
+
+fn map(n: u64) -> Map {
+    let mut map = Map::new();
+    for i in 0..n {
+        map.insert(i, i).unwrap();
+    }
+    map
+}
+
+fn collect(store: &Store<Blake2b>) {
+    let mut old = map(1000);
+    let old = store.persist(&mut old).unwrap();
+    let mut new = old.restore().unwrap();
+    for i in 0..1000 {
+        new.insert(i, i + 1).unwrap();
+    }
+    let new = store.persist(&mut new).unwrap();
+    store.flush().unwrap();
+    let before = store.size();
+
+    let reclaimed = store
+        .gc(&mut Control::none(), |live| live.mark(&new))
+        .unwrap();
+    assert!(reclaimed > 0);
+    assert!(store.size() < before);
+
+    store.verify(&new).unwrap();
+    assert_eq!(*new.restore().unwrap().get(&7).unwrap().unwrap(), 8);
+    assert!(old.restore().is_err());
+
+    // only nodes of the new map are left
+    assert!(
+        store
+            .gc(&mut Control::none(), |live| live.mark(&new))
+            .unwrap()
+            < reclaimed
+    );
+    store.verify(&new).unwrap();
+}
+
+#[test]
+fn gc_ephemeral() {
+    collect(&Store::ephemeral());
+}
+
+#[test]
+fn gc_disk() {
+    let dir = tempdir().unwrap();
+    let store = Store::<Blake2b>::new(dir.path()).unwrap();
+    collect(&store);
+
+    let mut map = map(10);
+    let snapshot = store.persist(&mut map).unwrap();
+    store
+        .gc(&mut Control::none(), |live| live.mark(&snapshot))
+        .unwrap();
+    drop(store);
+
+    // the compacted store reopens intact
+    let store = Store::<Blake2b>::new(dir.path()).unwrap();
+    let snapshot = store.snapshot::<Map>(snapshot.hash());
+    store.verify(&snapshot).unwrap();
+}
+
+#[test]
+fn gc_retains_pinned() {
+    let store = Store::<Blake2b>::ephemeral();
+    let a = store.persist(&mut map(10)).unwrap();
+    let b = store.persist(&mut map(20)).unwrap();
+    let _pinned = b.pin();
+
+    let err = store
+        .gc(&mut Control::none(), |live| live.mark(&a))
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    store.verify(&a).unwrap();
+
+    store
+        .gc(&mut Control::none(), |live| {
+            live.mark(&a)?;
+            live.mark(&b)
+        })
+        .unwrap();
+    store.verify(&a).unwrap();
+    store.verify(&b).unwrap();
+}
+
+#[test]
+fn gc_archival() {
+    let dir = tempdir().unwrap();
+    let store = Store::<Blake2b>::archival(dir.path()).unwrap();
+    let snapshot = store.persist(&mut map(10)).unwrap();
+    let err = store
+        .gc(&mut Control::none(), |live| live.mark(&snapshot))
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+}
+
+// The state of a program, keeping its maps inline
+#[derive(Clone, Content)]
+struct State {
+    accounts: Map,
+    nonces: Option<Map>,
+}
+
+#[test]
+fn gc_composite_roots() {
+    let store = Store::<Blake2b>::ephemeral();
+    let mut state = State {
+        accounts: map(1000),
+        nonces: Some(map(500)),
+    };
+    let snapshot = store.persist(&mut state).unwrap();
+    store.persist(&mut map(100)).unwrap();
+
+    assert!(
+        store
+            .gc(&mut Control::none(), |live| live.mark(&snapshot))
+            .unwrap()
+            > 0
+    );
+    let state = snapshot.restore().unwrap();
+    for i in 0..1000 {
+        assert_eq!(*state.accounts.get(&i).unwrap().unwrap(), i);
+    }
+    let nonces = state.nonces.unwrap();
+    for i in 0..500 {
+        assert_eq!(*nonces.get(&i).unwrap().unwrap(), i);
+    }
+}
+
+#[test]
+fn gc_cancelled() {
+    let dir = tempdir().unwrap();
+    let store = Store::<Blake2b>::new(dir.path()).unwrap();
+    let old = store.persist(&mut map(1000)).unwrap();
+    let new = store.persist(&mut map(10)).unwrap();
+
+    let token = CancelToken::new();
+    let mut marked = 0;
+    let mut control =
+        Control::cancellable(token.clone()).with_progress(|done, _| {
+            marked = done;
+            if done == 1 {
+                token.cancel()
+            }
+        });
+    let err = store.gc(&mut control, |live| live.mark(&new)).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+    drop(control);
+    assert_eq!(marked, 1);
+
+    // nothing was deleted
+    store.verify(&old).unwrap();
+    store.verify(&new).unwrap();
+}
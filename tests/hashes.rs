@@ -0,0 +1,54 @@
+#![cfg(any(feature = "blake3", feature = "sha256"))]
+
+use std::hash::Hasher;
+
+use kelvin::{ByteHash, ByteHashState, Store};
+use kelvin_hamt::DefaultHAMTMap;
+
+fn hex<H: ByteHash>(bytes: &[u8]) -> String {
+    let mut state = H::state();
+    state.write(bytes);
+    state
+        .fin()
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn round_trip<H: ByteHash>() {
+    let mut map = DefaultHAMTMap::<u64, u64, H>::new();
+    for i in 0..1000 {
+        map.insert(i, i + 1).unwrap();
+    }
+    let store = Store::<H>::ephemeral();
+    let snapshot = store.persist(&mut map).unwrap();
+    let restored = store.restore(&snapshot).unwrap();
+    for i in (0..1000).step_by(7) {
+        assert_eq!(*restored.get(&i).unwrap().unwrap(), i + 1);
+    }
+}
+
+#[cfg(feature = "blake3")]
+#[test]
+fn blake3() {
+    use kelvin::Blake3;
+
+    assert_eq!(
+        hex::<Blake3>(b""),
+        "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262"
+    );
+    round_trip::<Blake3>();
+}
+
+#[cfg(feature = "sha256")]
+#[test]
+fn sha256() {
+    use kelvin::Sha256;
+
+    assert_eq!(
+        hex::<Sha256>(b"abc"),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    round_trip::<Sha256>();
+}
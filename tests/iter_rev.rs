@@ -0,0 +1,73 @@
+use kelvin::{Blake2b, LeafIterable, Store, ValIterable};
+use kelvin_hamt::DefaultHAMTMap;
+
+type Map = DefaultHAMTMap<u64, u64, Blake2b>;
+
+fn map(n: u64) -> Map {
+    let mut map = Map::new();
+    for i in 0..n {
+        map.insert(i, i).unwrap();
+    }
+    map
+}
+
+#[test]
+fn reverse_order() {
+    let mut map = map(1000);
+    let store = Store::<Blake2b>::ephemeral();
+    let snapshot = store.persist(&mut map).unwrap();
+    let restored = store.restore(&snapshot).unwrap();
+
+    for map in &[map, restored] {
+        let forward: Vec<u64> = map.iter().map(|l| l.unwrap().key).collect();
+        let mut backward: Vec<u64> =
+            map.iter().rev().map(|l| l.unwrap().key).collect();
+        backward.reverse();
+        assert_eq!(forward.len(), 1000);
+        assert_eq!(forward, backward);
+
+        // front and back meet without yielding any leaf twice
+        let mut iter = map.iter();
+        let mut front = vec![];
+        let mut back = vec![];
+        for _ in 0..300 {
+            front.push(iter.next().unwrap().unwrap().key);
+            back.push(iter.next_back().unwrap().unwrap().key);
+        }
+        front.extend(iter.map(|l| l.unwrap().key));
+        back.reverse();
+        front.extend(back);
+        assert_eq!(front, forward);
+    }
+}
+
+#[test]
+fn reverse_small() {
+    for n in 0..4 {
+        let map = map(n);
+        let mut iter = map.iter();
+        let mut count = 0;
+        while iter.next_back().is_some() {
+            count += 1;
+            if iter.next().is_some() {
+                count += 1;
+            }
+        }
+        assert_eq!(count, n);
+        assert!(iter.next().is_none());
+    }
+}
+
+#[test]
+fn reverse_mut() {
+    let mut map = map(100);
+    let mut visited = 0;
+    for leaf in map.iter_mut_rev() {
+        leaf.unwrap().val += 1;
+        visited += 1;
+    }
+    assert_eq!(visited, 100);
+    let mut values: Vec<u64> = map.values().map(|v| *v.unwrap()).collect();
+    values.sort();
+    assert_eq!(values, (1..101).collect::<Vec<_>>());
+}
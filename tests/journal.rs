@@ -0,0 +1,98 @@
+use std::fs::OpenOptions;
+use std::io::{self, Read, Write};
+
+use kelvin::tests::tempfile::tempdir;
+use kelvin::{Blake2b, Content, Journal, Operation, Sink, Source, Store};
+
+/// Adds to a number
+#[derive(Clone)]
+struct Add(u64);
+
+impl Content<Blake2b> for Add {
+    fn persist(&mut self, sink: &mut Sink<Blake2b>) -> io::Result<()> {
+        self.0.persist(sink)
+    }
+
+    fn restore(source: &mut Source<Blake2b>) -> io::Result<Self> {
+        Ok(Add(u64::restore(source)?))
+    }
+}
+
+impl Operation<Blake2b> for Add {
+    type Target = u64;
+
+    fn apply(&self, target: &mut u64) -> io::Result<()> {
+        *target += self.0;
+        Ok(())
+    }
+}
+
+#[test]
+fn point_in_time_recovery() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("journal");
+    let store = Store::<Blake2b>::ephemeral();
+
+    let mut state = 0u64;
+    let mut roots = vec![];
+    {
+        let mut journal = Journal::<Add, Blake2b>::open(&path).unwrap();
+        // commit a root every 10 operations
+        for i in 0..100 {
+            let mut op = Add(i);
+            op.apply(&mut state).unwrap();
+            assert_eq!(journal.append(&mut op).unwrap(), i);
+            if i % 10 == 9 {
+                let root = store.persist(&mut state).unwrap();
+                journal.mark(root.hash()).unwrap();
+                roots.push((i + 1, root));
+            }
+        }
+    }
+
+    let journal = Journal::<Add, Blake2b>::open(&path).unwrap();
+    assert_eq!(journal.len(), 100);
+
+    // roll the root at 30 forward to an intermediate point
+    let (at, ref root) = roots[2];
+    let mut target = store.restore(root).unwrap();
+    journal.roll_forward(root.hash(), &mut target, 57).unwrap();
+    assert_eq!(target, (0..57).sum::<u64>());
+
+    let mut target = store.restore(root).unwrap();
+    assert!(journal
+        .roll_forward(root.hash(), &mut target, at - 1)
+        .is_err());
+    assert!(journal.roll_forward(root.hash(), &mut target, 101).is_err());
+
+    let unknown = store.persist(&mut 12345u64).unwrap();
+    let err = journal.roll_forward(unknown.hash(), &mut target, 50);
+    assert_eq!(err.unwrap_err().kind(), io::ErrorKind::NotFound);
+}
+
+#[test]
+fn torn_entry_truncated() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("journal");
+    {
+        let mut journal = Journal::<Add, Blake2b>::open(&path).unwrap();
+        journal.append(&mut Add(1)).unwrap();
+        journal.append(&mut Add(2)).unwrap();
+    }
+    // simulate a crash in the middle of writing an entry
+    let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+    file.write_all(&[0, 0, 0, 0, 8, 1, 2]).unwrap();
+    drop(file);
+
+    let mut journal = Journal::<Add, Blake2b>::open(&path).unwrap();
+    assert_eq!(journal.len(), 2);
+    journal.append(&mut Add(3)).unwrap();
+
+    let mut bytes = vec![];
+    std::fs::File::open(&path)
+        .unwrap()
+        .read_to_end(&mut bytes)
+        .unwrap();
+    // three entries of a tag, a length and a u64
+    assert_eq!(bytes.len(), 3 * 13);
+}
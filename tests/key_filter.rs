@@ -0,0 +1,82 @@
+use kelvin::tests::tempfile::tempdir;
+use kelvin::{Blake2b, KeyFilter, Namespaces, Store};
+use kelvin_hamt::DefaultHAMTMap;
+
+type Map = DefaultHAMTMap<u64, u64, Blake2b>;
+
+#[test]
+fn no_false_negatives() {
+    let mut map = Map::new();
+    for i in 0..1000 {
+        map.insert(i, i).unwrap();
+    }
+    let filter = KeyFilter::from_map(&map, 0.01).unwrap();
+    assert_eq!(filter.len(), 1000);
+    for i in 0..1000u64 {
+        assert!(filter.may_contain(&i));
+    }
+    let false_positives =
+        (1000..11_000u64).filter(|i| filter.may_contain(i)).count();
+    assert!(false_positives < 200);
+}
+
+#[test]
+fn update_from_diff() {
+    let store = Store::<Blake2b>::ephemeral();
+    let mut map = Map::new();
+    for i in 0..100 {
+        map.insert(i, i).unwrap();
+    }
+    let old = store.persist(&mut map).unwrap();
+    let mut filter = KeyFilter::from_map(&map, 0.01).unwrap();
+
+    for i in 100..150 {
+        map.insert(i, i).unwrap();
+    }
+    map.insert(5, 6).unwrap();
+    let new = store.persist(&mut map).unwrap();
+
+    filter.update(&old, &new).unwrap();
+    for i in 0..150u64 {
+        assert!(filter.may_contain(&i));
+    }
+}
+
+#[test]
+fn namespace_map_roots() {
+    let dir = tempdir().unwrap();
+    let namespaces = Namespaces::<Blake2b>::new(dir.path()).unwrap();
+    let mut ns = namespaces.create("a", 1 << 24).unwrap();
+
+    assert!(!ns.may_contain("accounts", &1u64).unwrap());
+
+    let mut map = Map::new();
+    for i in 0..100 {
+        map.insert(i, i).unwrap();
+    }
+    ns.set_map_root("accounts", &mut map).unwrap();
+    assert_eq!(ns.filter("accounts").unwrap().unwrap().len(), 100);
+
+    // grow past the capacity, forcing a rebuild
+    for i in 100..1000 {
+        map.insert(i, i).unwrap();
+        if i % 100 == 0 {
+            ns.set_map_root("accounts", &mut map).unwrap();
+        }
+    }
+    ns.set_map_root("accounts", &mut map).unwrap();
+
+    let filter = ns.filter("accounts").unwrap().unwrap();
+    assert!(!filter.is_saturated());
+    for i in 0..1000u64 {
+        assert!(ns.may_contain("accounts", &i).unwrap());
+    }
+
+    // a plain root drops the stale filter
+    ns.set_root("accounts", &mut Map::new()).unwrap();
+    assert!(ns.filter("accounts").unwrap().is_none());
+    assert!(ns.may_contain("accounts", &1u64).unwrap());
+
+    ns.remove_root("accounts").unwrap();
+    assert!(!ns.may_contain("accounts", &1u64).unwrap());
+}
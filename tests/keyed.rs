@@ -0,0 +1,114 @@
+use std::io;
+
+use kelvin::annotations::{Cardinality, Count};
+use kelvin::{
+    reach_children, Blake2b, ByteHash, Compound, Content, Handle,
+    KeyValIterable, Keyed, Method, Reach, SearchResult, Sink, Source, Store,
+    KV,
+};
+
+// A trie branching on two bits of the key at each level
+#[derive(Clone, Default)]
+struct Trie<H: ByteHash>([Handle<Self, H>; 4]);
+
+impl<H: ByteHash> Content<H> for Trie<H> {
+    fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
+        for handle in self.0.iter_mut() {
+            handle.persist(sink)?
+        }
+        Ok(())
+    }
+
+    fn restore(source: &mut Source<H>) -> io::Result<Self> {
+        let mut trie = Trie::default();
+        for handle in trie.0.iter_mut() {
+            *handle = Handle::restore(source)?
+        }
+        Ok(trie)
+    }
+
+    fn reach<R: Reach<H>>(&self, reach: &mut R) -> io::Result<()> {
+        reach_children(self, reach)
+    }
+}
+
+impl<H: ByteHash> Compound<H> for Trie<H> {
+    type Leaf = KV<u64, u64>;
+    type Annotation = Cardinality<u64>;
+
+    fn children(&self) -> &[Handle<Self, H>] {
+        &self.0
+    }
+
+    fn children_mut(&mut self) -> &mut [Handle<Self, H>] {
+        &mut self.0
+    }
+}
+
+struct TrieSearch {
+    key: u64,
+    depth: u32,
+}
+
+impl<'k> From<&'k u64> for TrieSearch {
+    fn from(key: &'k u64) -> Self {
+        TrieSearch {
+            key: *key,
+            depth: 0,
+        }
+    }
+}
+
+impl<H: ByteHash> Method<Trie<H>, H> for TrieSearch {
+    fn select(&mut self, trie: &Trie<H>, _: usize) -> SearchResult {
+        if self.depth == 32 {
+            return SearchResult::None;
+        }
+        let slot = ((self.key >> (self.depth * 2)) & 3) as usize;
+        self.depth += 1;
+        match trie.0[slot].leaf() {
+            Some(leaf) if leaf.key == self.key => SearchResult::Leaf(slot),
+            _ => SearchResult::Path(slot),
+        }
+    }
+}
+
+impl<H: ByteHash> Keyed<u64, u64, H> for Trie<H> {
+    type KeySearch = TrieSearch;
+}
+
+#[test]
+fn keyed_map() {
+    let mut trie = Trie::<Blake2b>::default();
+    for i in 0..1000 {
+        assert_eq!(trie.insert(i * 7, i).unwrap(), None);
+    }
+    assert_eq!(trie.insert(7, 100).unwrap(), Some(1));
+    *trie.get_mut(&14).unwrap().unwrap() = 200;
+    assert_eq!(Count::<u64, Blake2b>::count(&trie), 1000);
+
+    let store = Store::<Blake2b>::ephemeral();
+    let snapshot = store.persist(&mut trie).unwrap();
+    let mut trie = snapshot.restore().unwrap();
+
+    assert_eq!(*trie.get(&7).unwrap().unwrap(), 100);
+    assert_eq!(*trie.get(&14).unwrap().unwrap(), 200);
+    assert_eq!(*trie.get(&21).unwrap().unwrap(), 3);
+    assert!(trie.get(&8).unwrap().is_none());
+    assert_eq!(trie.pairs().count(), 1000);
+
+    for i in (0..1000).step_by(2) {
+        assert!(trie.remove(&(i * 7)).unwrap().is_some());
+    }
+    assert_eq!(trie.remove(&0).unwrap(), None);
+    assert_eq!(Count::<u64, Blake2b>::count(&trie), 500);
+    for i in 0..1000 {
+        assert_eq!(trie.get(&(i * 7)).unwrap().is_some(), i % 2 == 1);
+    }
+
+    // removing every key leaves an empty trie
+    for i in (1..1000).step_by(2) {
+        trie.remove(&(i * 7)).unwrap();
+    }
+    assert!(trie.children().iter().all(Handle::is_none));
+}
@@ -0,0 +1,37 @@
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use kelvin::{Blake2b, Compound, LeafIterable, MemBackend, Store};
+use kelvin_hamt::DefaultHAMTMap;
+
+use common::Counting;
+
+type Map = DefaultHAMTMap<u64, u64, Blake2b>;
+
+#[test]
+fn children_read_on_traversal() {
+    let reads = Arc::new(AtomicUsize::new(0));
+    let store = Store::from_backend(Counting(MemBackend::new(), reads.clone()));
+
+    let mut map = Map::new();
+    for i in 0..4096 {
+        map.insert(i, i).unwrap();
+    }
+    let snapshot = store.persist(&mut map).unwrap();
+
+    // only the root is read, its children are known by digest
+    let map = snapshot.restore().unwrap();
+    assert_eq!(reads.load(Ordering::SeqCst), 1);
+    assert!(map.children().iter().any(|child| child.digest().is_some()));
+
+    // a lookup reads the nodes on the path to the key
+    assert_eq!(*map.get(&42).unwrap().unwrap(), 42);
+    let path = reads.load(Ordering::SeqCst) - 1;
+    assert!(path > 0 && path < 8);
+
+    // iteration reads the remaining nodes as it goes
+    assert_eq!(map.iter().count(), 4096);
+    assert!(reads.load(Ordering::SeqCst) > 1 + path);
+}
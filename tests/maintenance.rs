@@ -0,0 +1,137 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use kelvin::{
+    integrity_scan, Backend, Blake2b, Limits, Maintenance, MemBackend,
+    NodeKind, PreloadPolicy, Priority, Store,
+};
+use kelvin_hamt::DefaultHAMTMap;
+
+type Map = DefaultHAMTMap<u64, u64, Blake2b>;
+
+#[test]
+fn tasks_by_priority() {
+    let maintenance = Maintenance::new(Limits::default());
+    let order = Arc::new(Mutex::new(vec![]));
+    for (name, priority) in &[
+        ("low", Priority::Low),
+        ("high", Priority::High),
+        ("normal", Priority::Normal),
+    ] {
+        let order = order.clone();
+        maintenance.schedule(name, *priority, Duration::from_secs(60), {
+            move |_| {
+                order.lock().unwrap().push(*name);
+                Ok(())
+            }
+        });
+    }
+    assert_eq!(maintenance.run_pending(), 3);
+    assert_eq!(*order.lock().unwrap(), vec!["high", "normal", "low"]);
+
+    // nothing is due again before the interval
+    assert_eq!(maintenance.run_pending(), 0);
+    assert!(maintenance.unschedule("low"));
+    assert!(!maintenance.unschedule("low"));
+}
+
+#[test]
+fn integrity_scan_rate_limited() {
+    let store = Store::<Blake2b>::ephemeral();
+    let mut map = Map::new();
+    for i in 0..1024 {
+        map.insert(i, i).unwrap();
+    }
+    let snapshot = store.persist(&mut map).unwrap();
+    let size = store
+        .node_records(&snapshot)
+        .map(|r| r.unwrap().bytes.len())
+        .sum::<usize>() as u64;
+
+    // the scan is limited to a tenth of the tree per 100ms
+    let maintenance = Maintenance::new(Limits {
+        io_bytes_per_sec: Some(size),
+        cpu_share: 1.0,
+    });
+    let roots = vec![snapshot.clone()];
+    maintenance.schedule(
+        "scan",
+        Priority::Normal,
+        Duration::from_secs(60),
+        integrity_scan(store.clone(), move || roots.clone()),
+    );
+    let start = Instant::now();
+    maintenance.run_pending();
+    assert!(start.elapsed() >= Duration::from_millis(900));
+
+    let report = &maintenance.reports()[0];
+    assert_eq!(report.runs, 1);
+    assert_eq!(report.failures, 0);
+    assert_eq!(report.last_io, size);
+}
+
+#[test]
+fn integrity_scan_finds_corruption() {
+    let store = Store::<Blake2b>::ephemeral();
+    let mut map = Map::new();
+    for i in 0..1024 {
+        map.insert(i, i).unwrap();
+    }
+    let snapshot = store.persist(&mut map).unwrap();
+
+    // a copy with one leaf value flipped, still decoding fine
+    let mut backend = MemBackend::new();
+    let mut corrupted = false;
+    for record in store.node_records(&snapshot) {
+        let mut record = record.unwrap();
+        if record.kind == NodeKind::Bottom && !corrupted {
+            *record.bytes.last_mut().unwrap() ^= 1;
+            corrupted = true;
+        }
+        backend.put(record.digest, record.bytes).unwrap();
+    }
+    let store = Store::from_backend(backend);
+    let roots = vec![store.snapshot::<Map>(snapshot.hash())];
+
+    let maintenance = Maintenance::new(Limits::default());
+    maintenance.schedule(
+        "scan",
+        Priority::Normal,
+        Duration::from_secs(60),
+        integrity_scan(store.clone(), move || roots.clone()),
+    );
+    maintenance.run_pending();
+    assert_eq!(maintenance.reports()[0].failures, 1);
+}
+
+#[test]
+fn background_cache_demotion() {
+    let store = Store::<Blake2b>::ephemeral();
+    let mut map = Map::new();
+    for i in 0..1024 {
+        map.insert(i, i).unwrap();
+    }
+    let snapshot = store.persist(&mut map).unwrap();
+    store.preload(&snapshot, PreloadPolicy::Depth(2)).unwrap();
+    assert!(store.preloaded() > 0);
+
+    let runs = Arc::new(AtomicUsize::new(0));
+    let mut maintenance = Maintenance::new(Limits::default());
+    maintenance.schedule("demote", Priority::Low, Duration::from_millis(10), {
+        let store = store.clone();
+        let runs = runs.clone();
+        move |_| {
+            store.clear_preloaded();
+            runs.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    });
+    maintenance.start();
+    while runs.load(Ordering::SeqCst) < 3 {
+        thread::sleep(Duration::from_millis(5));
+    }
+    drop(maintenance);
+    assert_eq!(store.preloaded(), 0);
+}
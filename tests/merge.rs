@@ -0,0 +1,60 @@
+use kelvin::{merge, Blake2b, Store};
+use kelvin_hamt::DefaultHAMTMap;
+
+type Map = DefaultHAMTMap<u64, u64, Blake2b>;
+
+#[test]
+fn three_way_merge() {
+    let store = Store::<Blake2b>::ephemeral();
+    let mut map = Map::new();
+    for i in 0..100 {
+        map.insert(i, i).unwrap();
+    }
+    let base = store.persist(&mut map).unwrap();
+
+    let mut ours = base.restore().unwrap();
+    ours.insert(1, 10).unwrap(); // only ours
+    ours.insert(2, 20).unwrap(); // same on both sides
+    ours.insert(3, 30).unwrap(); // conflict
+    ours.remove(&4).unwrap(); // removed here, modified there
+    ours.insert(100, 100).unwrap();
+    let ours = store.persist(&mut ours).unwrap();
+
+    let mut theirs = base.restore().unwrap();
+    theirs.insert(2, 20).unwrap();
+    theirs.insert(3, 31).unwrap();
+    theirs.insert(4, 41).unwrap();
+    theirs.remove(&5).unwrap(); // only theirs
+    theirs.insert(101, 101).unwrap();
+    let theirs = store.persist(&mut theirs).unwrap();
+
+    let mut conflicts = vec![];
+    let merged = merge(&base, &ours, &theirs, |conflict| {
+        conflicts.push(*conflict.key);
+        match (conflict.ours, conflict.theirs) {
+            (Some(a), Some(b)) => Some(a.max(b) + 1000),
+            // removals win
+            _ => None,
+        }
+    })
+    .unwrap();
+
+    conflicts.sort();
+    assert_eq!(conflicts, vec![3, 4]);
+    let get = |k| merged.get(&k).unwrap().map(|v| *v);
+    assert_eq!(get(1), Some(10));
+    assert_eq!(get(2), Some(20));
+    assert_eq!(get(3), Some(1031));
+    assert_eq!(get(4), None);
+    assert_eq!(get(5), None);
+    assert_eq!(get(6), Some(6));
+    assert_eq!(get(100), Some(100));
+    assert_eq!(get(101), Some(101));
+
+    // merging without changes on one side yields the other
+    let merged = merge(&base, &base, &theirs, |_| unreachable!()).unwrap();
+    assert_eq!(
+        store.persist(&mut merged.clone()).unwrap().hash(),
+        theirs.hash()
+    );
+}
@@ -0,0 +1,115 @@
+use std::convert::TryInto;
+use std::io;
+
+use kelvin::annotations::VoidAnnotation;
+use kelvin::{
+    reach_children, Blake2b, ByteHash, Compound, Content, Handle, HandleMut,
+    Reach, Sink, Source, Store,
+};
+
+// A binary tree keeping the smallest leaf below each node as its metadata
+#[derive(Clone, Default)]
+struct Tree<H: ByteHash>([Handle<Self, H>; 2]);
+
+impl<H: ByteHash> Content<H> for Tree<H> {
+    fn persist(&mut self, sink: &mut Sink<H>) -> io::Result<()> {
+        for handle in self.0.iter_mut() {
+            handle.persist(sink)?
+        }
+        Ok(())
+    }
+
+    fn restore(source: &mut Source<H>) -> io::Result<Self> {
+        let mut tree = Tree::default();
+        for handle in tree.0.iter_mut() {
+            *handle = Handle::restore(source)?
+        }
+        Ok(tree)
+    }
+
+    fn reach<R: Reach<H>>(&self, reach: &mut R) -> io::Result<()> {
+        reach_children(self, reach)
+    }
+}
+
+impl<H: ByteHash> Compound<H> for Tree<H> {
+    type Leaf = u64;
+    type Annotation = VoidAnnotation;
+
+    fn children(&self) -> &[Handle<Self, H>] {
+        &self.0
+    }
+
+    fn children_mut(&mut self) -> &mut [Handle<Self, H>] {
+        &mut self.0
+    }
+
+    fn meta(&self) -> Option<Vec<u8>> {
+        self.0
+            .iter()
+            .filter_map(|child| match child.leaf() {
+                Some(leaf) => Some(*leaf),
+                None => min(child.meta().as_deref()),
+            })
+            .min()
+            .map(|min| min.to_be_bytes().to_vec())
+    }
+}
+
+fn min(meta: Option<&[u8]>) -> Option<u64> {
+    meta.map(|meta| u64::from_be_bytes(meta.try_into().unwrap()))
+}
+
+fn node(children: [Handle<Tree<Blake2b>, Blake2b>; 2]) -> Tree<Blake2b> {
+    Tree(children)
+}
+
+#[test]
+fn meta_is_kept_in_handles() {
+    let store = Store::<Blake2b>::ephemeral();
+    let mut tree = node([
+        Handle::new_node(node([Handle::new_leaf(5), Handle::new_leaf(3)])),
+        Handle::new_node(node([Handle::new_leaf(9), Handle::new_empty()])),
+    ]);
+    assert_eq!(min(tree.meta().as_deref()), Some(3));
+
+    let snapshot = store.persist(&mut tree).unwrap();
+    let mut restored = snapshot.restore().unwrap();
+    // the children are not read, their metadata comes from the handles
+    for (child, expected) in restored.0.iter().zip(&[3, 9]) {
+        assert!(child.digest().is_some());
+        assert_eq!(min(child.meta().as_deref()), Some(*expected));
+    }
+    assert_eq!(restored.meta(), tree.meta());
+
+    // modified nodes return their new metadata, which is persisted again
+    if let HandleMut::Node(node) = &mut *restored.0[1].inner_mut().unwrap() {
+        node.0[1] = Handle::new_leaf(1);
+    }
+    assert_eq!(min(restored.0[1].meta().as_deref()), Some(1));
+    let snapshot = store.persist(&mut restored).unwrap();
+    let restored = snapshot.restore().unwrap();
+    assert_eq!(min(restored.meta().as_deref()), Some(1));
+}
+
+// Refers to a tree through an untagged handle
+struct Untagged(Handle<Tree<Blake2b>, Blake2b>);
+
+impl Content<Blake2b> for Untagged {
+    fn persist(&mut self, sink: &mut Sink<Blake2b>) -> io::Result<()> {
+        self.0.persist_untagged(sink)
+    }
+
+    fn restore(source: &mut Source<Blake2b>) -> io::Result<Self> {
+        Ok(Untagged(Handle::restore_node(source)?))
+    }
+}
+
+#[test]
+fn meta_needs_tagged_handles() {
+    let store = Store::<Blake2b>::ephemeral();
+    let leaves = node([Handle::new_leaf(1), Handle::new_leaf(2)]);
+    assert!(store
+        .persist(&mut Untagged(Handle::new_node(leaves)))
+        .is_err());
+}
@@ -0,0 +1,110 @@
+use std::io;
+
+use kelvin::{migrate, Blake2b, Control, Migration, Migrations, Store, KV};
+use kelvin_hamt::DefaultHAMTMap;
+
+type V1 = DefaultHAMTMap<u64, u64, Blake2b>;
+type V3 = DefaultHAMTMap<String, String, Blake2b>;
+
+#[test]
+fn chained_migrations() {
+    let store = Store::<Blake2b>::ephemeral();
+    let mut map = V1::new();
+    for i in 0..10_000 {
+        map.insert(i, i * 2).unwrap();
+    }
+    let root = store.persist(&mut map).unwrap();
+
+    // v1 to v2 drops the odd keys, v2 to v3 turns the keys into strings
+    let v2 = |kv: KV<u64, u64>| -> io::Result<Option<KV<u64, String>>> {
+        Ok(if kv.key % 2 == 0 {
+            Some(KV::new(kv.key, kv.val.to_string()))
+        } else {
+            None
+        })
+    };
+    let v3 = |kv: KV<u64, String>| -> io::Result<Option<KV<String, String>>> {
+        Ok(Some(KV::new(format!("key-{}", kv.key), kv.val)))
+    };
+
+    let mut control = Control::none();
+    let migrated: V3 = migrate(&root, v2.then(v3), &mut control)
+        .unwrap()
+        .restore()
+        .unwrap();
+    assert_eq!(control.done(), 10_000);
+    for i in 0..10_000u64 {
+        let val = migrated.get(&format!("key-{}", i)).unwrap();
+        if i % 2 == 0 {
+            assert_eq!(*val.unwrap(), (i * 2).to_string());
+        } else {
+            assert!(val.is_none());
+        }
+    }
+
+    // the old root is untouched
+    let old: V1 = root.restore().unwrap();
+    assert_eq!(*old.get(&1).unwrap().unwrap(), 2);
+}
+
+#[test]
+fn colliding_keys() {
+    let store = Store::<Blake2b>::ephemeral();
+    let mut map = V1::new();
+    for i in 0..10 {
+        map.insert(i, i).unwrap();
+    }
+    let root = store.persist(&mut map).unwrap();
+
+    let halve = |kv: KV<u64, u64>| -> io::Result<Option<KV<u64, u64>>> {
+        Ok(Some(KV::new(kv.key / 2, kv.val)))
+    };
+    let result: io::Result<kelvin::Snapshot<V1, _>> =
+        migrate(&root, halve, &mut Control::none());
+    assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn registered_migrations() {
+    let store = Store::<Blake2b>::ephemeral();
+    let mut map = V1::new();
+    for i in 0..100 {
+        map.insert(i, i * 2).unwrap();
+    }
+    let root = store.persist(&mut map).unwrap();
+
+    let mut migrations = Migrations::new();
+    migrations
+        .register::<V1, V1, _, _, _>(
+            "balances",
+            1,
+            |kv: KV<u64, u64>| -> io::Result<Option<KV<u64, u64>>> {
+                Ok(Some(KV::new(kv.key, kv.val + 1)))
+            },
+        )
+        .register::<V1, V3, _, _, _>(
+            "balances",
+            2,
+            |kv: KV<u64, u64>| -> io::Result<Option<KV<String, String>>> {
+                Ok(Some(KV::new(kv.key.to_string(), kv.val.to_string())))
+            },
+        );
+
+    let mut control = Control::none();
+    let digest = migrations
+        .migrate(&store, "balances", 1, 3, root.hash(), &mut control)
+        .unwrap();
+    assert_eq!(control.done(), 200);
+    let migrated: V3 = store.snapshot(&digest).restore().unwrap();
+    for i in 0..100u64 {
+        let val = migrated.get(&i.to_string()).unwrap().unwrap();
+        assert_eq!(*val, (i * 2 + 1).to_string());
+    }
+
+    // a missing step fails before migrating anything
+    let err = migrations
+        .migrate(&store, "balances", 1, 4, root.hash(), &mut control)
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(control.done(), 200);
+}
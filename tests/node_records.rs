@@ -0,0 +1,46 @@
+use std::collections::HashSet;
+use std::hash::Hasher;
+
+use kelvin::{Blake2b, ByteHash, ByteHashState, NodeKind, Store};
+use kelvin_hamt::DefaultHAMTMap;
+
+type Map = DefaultHAMTMap<u64, u64, Blake2b>;
+
+#[test]
+fn records_of_reachable_nodes() {
+    let store = Store::<Blake2b>::ephemeral();
+    let mut map = Map::new();
+    for i in 0..1024 {
+        map.insert(i, i).unwrap();
+    }
+    let snapshot = store.persist(&mut map).unwrap();
+
+    let records: Vec<_> = store
+        .node_records(&snapshot)
+        .collect::<Result<_, _>>()
+        .unwrap();
+
+    assert!(records[0].digest == *snapshot.hash());
+    assert_eq!(records[0].kind, NodeKind::Root);
+    assert!(records[1..].iter().all(|r| r.kind != NodeKind::Root));
+    assert!(records.iter().any(|r| r.kind == NodeKind::Bottom));
+
+    let mut digests = HashSet::new();
+    for record in &records {
+        // each node is visited once, and its bytes hash to its digest
+        assert!(digests.insert(record.digest));
+        let mut state = Blake2b::state();
+        state.write(&record.bytes);
+        assert!(state.fin() == record.digest);
+    }
+
+    // a single key changed only adds the nodes on its path
+    map.insert(0, 1).unwrap();
+    let changed = store.persist(&mut map).unwrap();
+    let new = store
+        .node_records(&changed)
+        .map(|r| r.unwrap().digest)
+        .filter(|digest| !digests.contains(digest))
+        .count();
+    assert!(new > 0 && new < 8);
+}
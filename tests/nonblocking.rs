@@ -0,0 +1,172 @@
+#![cfg(feature = "async")]
+
+use std::collections::HashMap;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use futures::executor::block_on;
+use futures::future::BoxFuture;
+use futures::stream::StreamExt;
+use parking_lot::Mutex;
+
+use kelvin::{
+    AsyncBackend, AsyncStore, Blake2b, Branch, ByteHash, PutResult, ValPath,
+};
+use kelvin_hamt::{DefaultHAMTMap, HAMTSearch};
+
+type Map = DefaultHAMTMap<u64, u64, Blake2b>;
+type Digest = <Blake2b as ByteHash>::Digest;
+
+// An in-memory backend, shared by its clones, counting the nodes read
+#[derive(Clone, Default)]
+struct Remote {
+    nodes: Arc<Mutex<HashMap<Digest, Vec<u8>>>>,
+    reads: Arc<AtomicUsize>,
+}
+
+impl AsyncBackend<Blake2b> for Remote {
+    fn get<'a>(
+        &'a self,
+        digest: &'a Digest,
+    ) -> BoxFuture<'a, io::Result<Vec<u8>>> {
+        Box::pin(async move {
+            self.reads.fetch_add(1, Ordering::SeqCst);
+            self.nodes.lock().get(digest).cloned().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::NotFound, "Data not found")
+            })
+        })
+    }
+
+    fn put<'a>(
+        &'a self,
+        digest: Digest,
+        bytes: Vec<u8>,
+    ) -> BoxFuture<'a, io::Result<PutResult>> {
+        Box::pin(async move {
+            Ok(match self.nodes.lock().insert(digest, bytes) {
+                Some(_) => PutResult::AlreadyThere,
+                None => PutResult::Ok,
+            })
+        })
+    }
+
+    fn flush(&self) -> BoxFuture<'_, io::Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+// Persists a map of `n` pairs to a remote, returning it and the root
+fn remote_map(n: u64) -> (Remote, Digest) {
+    let remote = Remote::default();
+    let writer = AsyncStore::new(remote.clone());
+    let mut map = Map::new();
+    for i in 0..n {
+        map.insert(i, i).unwrap();
+    }
+    let root = *writer.store().persist(&mut map).unwrap().hash();
+    assert_eq!(remote.nodes.lock().len(), 0);
+    block_on(writer.flush()).unwrap();
+    assert!(remote.nodes.lock().len() > 1);
+    (remote, root)
+}
+
+#[test]
+fn get_awaits_missing_nodes() {
+    let (remote, root) = remote_map(1000);
+    let store = AsyncStore::new(remote.clone());
+    block_on(async {
+        let map: Map = store.restore(&root).await.unwrap();
+        for i in 0..1000 {
+            let val = ValPath::new_async(&map, || HAMTSearch::from(&i), &store)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(*val, i);
+        }
+        let missing =
+            ValPath::new_async(&map, || HAMTSearch::from(&1000), &store)
+                .await
+                .unwrap();
+        assert!(missing.is_none());
+    });
+
+    // every node was fetched once
+    assert_eq!(remote.reads.load(Ordering::SeqCst), store.fetched());
+
+    // the sync path only reads what was already fetched
+    let map: Map = store.store().snapshot(&root).restore().unwrap();
+    assert_eq!(*map.get(&42).unwrap().unwrap(), 42);
+}
+
+#[test]
+fn branch_and_stream() {
+    let (remote, root) = remote_map(300);
+    let store = AsyncStore::new(remote);
+    block_on(async {
+        let map: Map = store.restore(&root).await.unwrap();
+        let branch = Branch::new_async(&map, || HAMTSearch::from(&7), &store)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(branch.val, 7);
+
+        let mut leaves: Vec<_> = store
+            .leaves(&map)
+            .map(|leaf| leaf.unwrap().val)
+            .collect()
+            .await;
+        leaves.sort();
+        assert_eq!(leaves, (0..300).collect::<Vec<_>>());
+    });
+}
+
+#[test]
+fn stream_values_and_kv() {
+    let (remote, root) = remote_map(256);
+    let store = AsyncStore::new(remote);
+    block_on(async {
+        let map: Map = store.restore(&root).await.unwrap();
+
+        let mut values: Vec<u64> = store
+            .stream_values(&map)
+            .map(|v| v.unwrap())
+            .collect()
+            .await;
+        values.sort();
+        assert_eq!(values, (0..256).collect::<Vec<_>>());
+
+        let pairs: Vec<(u64, u64)> =
+            store.stream_kv(&map).map(|kv| kv.unwrap()).collect().await;
+        assert_eq!(pairs.len(), 256);
+        for (k, v) in pairs {
+            assert_eq!(k, v);
+        }
+    });
+}
+
+#[test]
+fn unknown_root() {
+    let store = AsyncStore::new(Remote::default());
+    let err = block_on(store.restore::<Map>(&Digest::default())).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+}
+
+#[test]
+fn fetched_nodes_are_bounded() {
+    let (remote, root) = remote_map(1000);
+    let store = AsyncStore::with_capacity(remote.clone(), 8);
+    block_on(async {
+        let map: Map = store.restore(&root).await.unwrap();
+        for i in 0..1000 {
+            let val = ValPath::new_async(&map, || HAMTSearch::from(&i), &store)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(*val, i);
+        }
+    });
+    assert!(store.fetched() <= 8);
+    // evicted nodes were fetched again
+    assert!(remote.reads.load(Ordering::SeqCst) > 8);
+}
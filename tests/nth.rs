@@ -0,0 +1,33 @@
+use kelvin::annotations::Select;
+use kelvin::{Blake2b, LeafIterable, Store};
+use kelvin_hamt::CountingHAMTMap;
+
+type Map = CountingHAMTMap<u64, u64, Blake2b>;
+
+#[test]
+fn leaves_by_position() {
+    let mut map = Map::new();
+    for i in 0..1000 {
+        map.insert(i, i).unwrap();
+    }
+    let store = Store::<Blake2b>::ephemeral();
+    let map = store.persist(&mut map).unwrap().restore().unwrap();
+
+    let keys: Vec<u64> = map.iter().map(|leaf| leaf.unwrap().key).collect();
+    for (n, key) in keys.iter().enumerate() {
+        assert_eq!(map.nth(n as u64).unwrap().unwrap().key, *key);
+    }
+    assert!(map.nth(1000u64).unwrap().is_none());
+
+    // pages of 64 leaves
+    for page in 0..16u64 {
+        let leaves: Vec<u64> = map
+            .iter_from(page * 64)
+            .take(64)
+            .map(|leaf| leaf.unwrap().key)
+            .collect();
+        let start = (page * 64) as usize;
+        let end = (start + 64).min(keys.len());
+        assert_eq!(leaves, &keys[start..end]);
+    }
+}
@@ -0,0 +1,48 @@
+mod common;
+
+use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use kelvin::tests::tempfile::tempdir;
+use kelvin::{Blake2b, DirObjectStore, ObjectBackend, Store};
+use kelvin_hamt::DefaultHAMTMap;
+
+use common::Counting;
+
+type Map = DefaultHAMTMap<u64, u64, Blake2b>;
+
+#[test]
+fn nodes_as_objects() {
+    let dir = tempdir().unwrap();
+    let objects = DirObjectStore::new(dir.path()).unwrap();
+    let store = Store::<Blake2b>::from_backend(
+        ObjectBackend::new(objects, 0).with_prefix("state-"),
+    );
+
+    let mut map = Map::new();
+    for i in 0..1000 {
+        map.insert(i, i).unwrap();
+    }
+    let snapshot = store.persist(&mut map).unwrap();
+    let nodes = store.node_records(&snapshot).count();
+    let files: Vec<_> = fs::read_dir(dir.path()).unwrap().collect();
+    assert_eq!(files.len(), nodes);
+    for file in files {
+        let name = file.unwrap().file_name().into_string().unwrap();
+        assert!(name.starts_with("state-"));
+    }
+
+    // read back through a cache large enough for the whole map
+    let fetched = Arc::new(AtomicUsize::new(0));
+    let objects = DirObjectStore::new(dir.path()).unwrap();
+    let backend =
+        ObjectBackend::new(Counting(objects, fetched.clone()), 1 << 20)
+            .with_prefix("state-");
+    let cached = Store::<Blake2b>::from_backend(backend);
+    let snapshot = cached.snapshot::<Map>(snapshot.hash());
+    cached.verify(&snapshot).unwrap();
+    assert_eq!(fetched.load(Ordering::SeqCst), nodes);
+    cached.verify(&snapshot).unwrap();
+    assert_eq!(fetched.load(Ordering::SeqCst), nodes);
+}
@@ -0,0 +1,32 @@
+use kelvin::{Blake2b, KeyValIterable, Store};
+use kelvin_hamt::DefaultHAMTMap;
+
+type Map = DefaultHAMTMap<u64, u64, Blake2b>;
+
+#[test]
+fn keys_and_pairs() {
+    let mut map = Map::new();
+    for i in 0..100 {
+        map.insert(i, i * 2).unwrap();
+    }
+    let store = Store::<Blake2b>::ephemeral();
+    let snapshot = store.persist(&mut map).unwrap();
+    let mut map = store.restore(&snapshot).unwrap();
+
+    let mut keys: Vec<u64> = map.keys().map(|k| *k.unwrap()).collect();
+    keys.sort();
+    assert_eq!(keys, (0..100).collect::<Vec<_>>());
+
+    for pair in map.pairs() {
+        let (k, v) = pair.unwrap();
+        assert_eq!(*v, k * 2);
+    }
+
+    for pair in map.pairs_mut() {
+        let (k, v) = pair.unwrap();
+        *v += k;
+    }
+    for i in 0..100 {
+        assert_eq!(*map.get(&i).unwrap().unwrap(), i * 3);
+    }
+}
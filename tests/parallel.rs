@@ -0,0 +1,73 @@
+#![cfg(feature = "parallel")]
+
+use std::io;
+
+use kelvin::{map_values, map_values_par, Blake2b, Control, Store};
+use kelvin_hamt::DefaultHAMTMap;
+
+type Map = DefaultHAMTMap<u64, u64, Blake2b>;
+
+fn map(n: u64) -> Map {
+    let mut map = Map::new();
+    for i in 0..n {
+        map.insert(i, i).unwrap();
+    }
+    map
+}
+
+#[test]
+fn same_root_as_sequential_persist() {
+    let mut map = Map::new();
+    for i in 0..100_000 {
+        map.insert(i, i * 2).unwrap();
+    }
+    let mut copy = map.clone();
+
+    let store = Store::<Blake2b>::ephemeral();
+    let sequential = store.persist(&mut map).unwrap();
+
+    let parallel_store = Store::<Blake2b>::ephemeral();
+    let parallel = parallel_store.persist_parallel(&mut copy).unwrap();
+    assert_eq!(parallel.hash(), sequential.hash());
+
+    parallel_store.verify(&parallel).unwrap();
+    let restored = parallel.restore().unwrap();
+    for i in (0..100_000).step_by(997) {
+        assert_eq!(*restored.get(&i).unwrap().unwrap(), i * 2);
+    }
+
+    // already persisted subtrees are left as is
+    copy.insert(100_000, 0).unwrap();
+    map.insert(100_000, 0).unwrap();
+    assert_eq!(
+        parallel_store.persist_parallel(&mut copy).unwrap().hash(),
+        store.persist(&mut map).unwrap().hash()
+    );
+}
+
+#[test]
+fn parallel_matches_sequential() {
+    let store = Store::<Blake2b>::ephemeral();
+    let old = map(3000);
+    let mut seq: Map =
+        map_values(&old, |k, v| Ok(k * v), &mut Control::none()).unwrap();
+    let mut par: Map =
+        map_values_par(&old, |k, v| Ok(k * v), &mut Control::none()).unwrap();
+    assert!(
+        store.persist(&mut seq).unwrap().hash()
+            == store.persist(&mut par).unwrap().hash()
+    );
+
+    let failed: io::Result<Map> = map_values_par(
+        &old,
+        |k, v| {
+            if *k == 2000 {
+                Err(io::Error::new(io::ErrorKind::Other, "bad value"))
+            } else {
+                Ok(*v)
+            }
+        },
+        &mut Control::none(),
+    );
+    assert!(failed.is_err());
+}
@@ -0,0 +1,79 @@
+use kelvin::{
+    Blake2b, ByteHash, Clock, EvictionPolicy, Lfu, Lru, Store, TinyLfu,
+};
+use kelvin_hamt::DefaultHAMTMap;
+
+type Map = DefaultHAMTMap<u64, u64, Blake2b>;
+
+#[test]
+fn partitions_have_separate_budgets() {
+    let store = Store::<Blake2b>::ephemeral();
+    let mut accounts = Map::new();
+    let mut history = Map::new();
+    for i in 0..4096 {
+        accounts.insert(i, i).unwrap();
+        history.insert(i, i + 1).unwrap();
+    }
+    let accounts = store.persist(&mut accounts).unwrap();
+    let history = store.persist(&mut history).unwrap();
+
+    // unpartitioned handles cache nothing
+    assert_eq!(store.partition_name(), None);
+    assert_eq!(store.cached(), 0);
+
+    let hot = store.partition("accounts", 1 << 24);
+    let cold = store.partition("history", 4096);
+    assert_eq!(hot.partition_name(), Some("accounts"));
+
+    let map: Map = hot.restore(&accounts).unwrap();
+    for i in 0..4096 {
+        assert_eq!(*map.get(&i).unwrap().unwrap(), i);
+    }
+    let hot_size = hot.cached();
+    assert!(hot_size > 0);
+
+    // a scan of the history does not evict the accounts
+    let map: Map = cold.restore(&history).unwrap();
+    for i in 0..4096 {
+        assert_eq!(*map.get(&i).unwrap().unwrap(), i + 1);
+    }
+    assert!(cold.cached() <= 4096);
+    assert_eq!(hot.cached(), hot_size);
+
+    // handles to the same partition share its cache
+    assert_eq!(store.partition("accounts", 1 << 24).cached(), hot_size);
+    assert_eq!(store.partition("accounts", 0).cached(), 0);
+}
+
+fn cache_with<P>(name: &str, policy: P)
+where
+    P: EvictionPolicy<<Blake2b as ByteHash>::Digest> + 'static,
+{
+    let store = Store::<Blake2b>::ephemeral();
+    let mut map = Map::new();
+    for i in 0..4096 {
+        map.insert(i, i).unwrap();
+    }
+    let root = store.persist(&mut map).unwrap();
+
+    let partition = store.partition(name, 16 * 1024);
+    partition.set_eviction_policy(policy).unwrap();
+    let map: Map = partition.restore(&root).unwrap();
+    for i in 0..4096 {
+        assert_eq!(*map.get(&i).unwrap().unwrap(), i);
+    }
+    assert!(partition.cached() > 0);
+    assert!(partition.cached() <= 16 * 1024);
+}
+
+#[test]
+fn eviction_policies() {
+    cache_with("lru", Lru::new());
+    cache_with("lfu", Lfu::new());
+    cache_with("clock", Clock::new());
+    cache_with("tiny-lfu", TinyLfu::new());
+
+    // unpartitioned handles have no cache to evict from
+    let store = Store::<Blake2b>::ephemeral();
+    assert!(store.set_eviction_policy(Lru::new()).is_err());
+}
@@ -0,0 +1,25 @@
+use kelvin::{Blake2b, LeafIterable, Store};
+use kelvin_hamt::DefaultHAMTMap;
+
+type Map = DefaultHAMTMap<u64, u64, Blake2b>;
+
+#[test]
+fn prefetched_iteration() {
+    let store = Store::<Blake2b>::ephemeral();
+    store.set_prefetch(16);
+
+    let mut map = Map::new();
+    for i in 0..4096 {
+        map.insert(i, i).unwrap();
+    }
+    let snapshot = store.persist(&mut map).unwrap();
+    let map = store.restore(&snapshot).unwrap();
+
+    let mut keys: Vec<u64> = map.iter().map(|leaf| leaf.unwrap().key).collect();
+    keys.sort();
+    assert_eq!(keys, (0..4096).collect::<Vec<_>>());
+
+    for i in 0..4096 {
+        assert_eq!(*map.get(&i).unwrap().unwrap(), i);
+    }
+}
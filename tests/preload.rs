@@ -0,0 +1,47 @@
+use kelvin::{Blake2b, Method, PreloadPolicy, Store};
+use kelvin_hamt::{DefaultHAMTMap, HAMTSearch};
+
+type Map = DefaultHAMTMap<u64, u64, Blake2b>;
+
+#[test]
+fn preload_levels_and_paths() {
+    let store = Store::<Blake2b>::ephemeral();
+    let mut map = Map::new();
+    for i in 0..4096 {
+        map.insert(i, i).unwrap();
+    }
+    let snapshot = store.persist(&mut map).unwrap();
+
+    assert_eq!(
+        store.preload(&snapshot, PreloadPolicy::Depth(0)).unwrap(),
+        0
+    );
+    assert_eq!(
+        store.preload(&snapshot, PreloadPolicy::Depth(1)).unwrap(),
+        1
+    );
+    // the root and its 16 children, the root was already loaded
+    assert_eq!(
+        store.preload(&snapshot, PreloadPolicy::Depth(2)).unwrap(),
+        16
+    );
+    assert_eq!(store.preloaded(), 17);
+
+    let keys = [1u64, 2, 3];
+    let hot = keys
+        .iter()
+        .map(|k| {
+            Box::new(HAMTSearch::<_, u64, _, Blake2b>::from(k))
+                as Box<dyn Method<Map, Blake2b>>
+        })
+        .collect();
+    assert!(store.preload(&snapshot, PreloadPolicy::Hot(hot)).unwrap() > 0);
+
+    let restored = store.restore(&snapshot).unwrap();
+    for i in 0..4096 {
+        assert_eq!(*restored.get(&i).unwrap().unwrap(), i);
+    }
+
+    store.clear_preloaded();
+    assert_eq!(store.preloaded(), 0);
+}
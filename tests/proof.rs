@@ -0,0 +1,80 @@
+use kelvin::proof::{self, Proof};
+use kelvin::{Blake2b, Store, KV};
+use kelvin_hamt::{DefaultHAMTMap, HAMTSearch};
+
+type Map = DefaultHAMTMap<u64, u64, Blake2b>;
+
+#[test]
+fn inclusion_proofs() {
+    let store = Store::<Blake2b>::ephemeral();
+    let mut map = Map::new();
+    for i in 0..1024 {
+        map.insert(i, i).unwrap();
+    }
+    let snapshot = store.persist(&mut map).unwrap();
+    let root = *snapshot.hash();
+    let restored = store.restore(&snapshot).unwrap();
+
+    for i in (0..1024).step_by(17) {
+        // in memory and restored trees prove against the same root
+        let proof = map.prove(&i).unwrap().unwrap();
+        assert_eq!(proof.root(), root);
+        let proof = restored.prove(&i).unwrap().unwrap();
+        assert_eq!(proof.root(), root);
+
+        assert!(proof::verify(&root, &proof, &KV::new(i, i)).unwrap());
+        assert!(!proof::verify(&root, &proof, &KV::new(i, i + 1)).unwrap());
+        assert!(
+            !proof::verify(&[0; 32].into(), &proof, &KV::new(i, i)).unwrap()
+        );
+    }
+    assert!(map.prove(&1024).unwrap().is_none());
+}
+
+#[test]
+fn proofs_are_content() {
+    let store = Store::<Blake2b>::ephemeral();
+    let mut map = Map::new();
+    for i in 0..128 {
+        map.insert(i, i).unwrap();
+    }
+    let root = *store.persist(&mut map).unwrap().hash();
+
+    let mut proof = map.prove(&7).unwrap().unwrap();
+    let snapshot = store.persist(&mut proof).unwrap();
+
+    // the verifier only needs the proof, not the map
+    let proof: Proof<Map, Blake2b> = store.restore(&snapshot).unwrap();
+    assert!(proof::verify(&root, &proof, &KV::new(7, 7)).unwrap());
+}
+
+#[test]
+fn proofs_of_old_roots() {
+    // written as before format versions, and proven as stored
+    let store = Store::<Blake2b>::ephemeral();
+    store.set_domain_separation(false);
+    let mut map = Map::new();
+    for i in 0..1024 {
+        map.insert(i, i).unwrap();
+    }
+    let snapshot = store.persist(&mut map).unwrap();
+    let root = *snapshot.hash();
+    let restored = store.restore(&snapshot).unwrap();
+
+    for i in (0..1024).step_by(17) {
+        let proof = restored.prove(&i).unwrap().unwrap();
+        assert_eq!(proof.root(), root);
+        assert!(proof::verify_legacy(&root, &proof, &KV::new(i, i)).unwrap());
+
+        let mut search = HAMTSearch::from(&i);
+        let proof = proof::prove_stored(&snapshot, &mut search)
+            .unwrap()
+            .unwrap();
+        assert_eq!(proof.root(), root);
+        assert!(proof::verify_legacy(&root, &proof, &KV::new(i, i)).unwrap());
+    }
+
+    // nodes without a domain only verify on purpose
+    let proof = restored.prove(&0).unwrap().unwrap();
+    assert!(proof::verify(&root, &proof, &KV::new(0, 0)).is_err());
+}
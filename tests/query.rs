@@ -0,0 +1,75 @@
+use kelvin::annotations::{
+    Cardinality, Compose, FirstWhere, Min, PrefixWhere, Sum,
+};
+use kelvin::{Blake2b, Branch, Compound, LeafIterable, Store};
+use kelvin_hamt::HAMT;
+
+type Ann = Compose<(Cardinality<u64>, Sum<u64>, Min<u64>)>;
+type Map = HAMT<u64, u64, Ann, Blake2b>;
+
+fn map() -> Map {
+    let mut map = Map::new();
+    for i in 0..1000 {
+        map.insert(i, (i * 7) % 1000 + 1).unwrap();
+    }
+    let store = Store::<Blake2b>::ephemeral();
+    store.persist(&mut map).unwrap().restore().unwrap()
+}
+
+#[test]
+fn composed_annotations() {
+    let map = map();
+    let (count, sum, min) = map.annotation().unwrap().into_inner();
+    assert_eq!(*count, 1000);
+    assert_eq!(*sum, (1..=1000).sum::<u64>());
+    assert_eq!(*min, 1);
+}
+
+#[test]
+fn first_where() {
+    let map = map();
+    let vals: Vec<u64> = map.iter().map(|leaf| leaf.unwrap().val).collect();
+
+    for bound in &[1, 10, 500, 999] {
+        let expected = vals.iter().position(|v| v <= bound);
+        let mut method = FirstWhere::new(|ann: &Ann| *ann.2 <= *bound);
+        let found = Branch::new(&map, &mut method).unwrap();
+        assert_eq!(found.map(|b| b.val), expected.map(|i| vals[i]));
+    }
+
+    let mut method = FirstWhere::new(|ann: &Ann| *ann.2 == 0);
+    assert!(Branch::new(&map, &mut method).unwrap().is_none());
+}
+
+#[test]
+fn prefix_where() {
+    let map = map();
+    let vals: Vec<u64> = map.iter().map(|leaf| leaf.unwrap().val).collect();
+
+    for limit in &[0, 1000, 250_000, 500_000] {
+        let mut total = 0;
+        let expected = vals.iter().position(|v| {
+            total += v;
+            total > *limit
+        });
+
+        let mut method = PrefixWhere::new(|ann: &Ann| *ann.1 > *limit);
+        let found = Branch::new(&map, &mut method).unwrap().unwrap();
+        assert_eq!(found.val, vals[expected.unwrap()]);
+        // the leaves passed over add up to the index of the one found
+        let before = method.prefix().map(|ann| *(**ann).0).unwrap_or(0);
+        assert_eq!(before, expected.unwrap() as u64);
+    }
+
+    let mut method = PrefixWhere::new(|ann: &Ann| *ann.1 > 500_500);
+    assert!(Branch::new(&map, &mut method).unwrap().is_none());
+}
+
+#[test]
+fn sums_saturate() {
+    let mut map = HAMT::<u64, u64, Sum<u64>, Blake2b>::new();
+    for i in 0..3 {
+        map.insert(i, u64::max_value() / 2).unwrap();
+    }
+    assert_eq!(*map.annotation().unwrap(), u64::max_value());
+}
@@ -0,0 +1,94 @@
+use std::io;
+use std::thread;
+
+use kelvin::tests::tempfile::tempdir;
+use kelvin::{Blake2b, DirRoots, Namespaces, RootStore};
+
+#[test]
+fn consistent_across_roots() {
+    let dir = tempdir().unwrap();
+    let namespaces = Namespaces::<Blake2b>::new(dir.path()).unwrap();
+    let mut writer = namespaces.create("bank", 1 << 30).unwrap();
+    let reader = namespaces.open("bank").unwrap();
+
+    writer
+        .commit(|ns| {
+            ns.set_root("checking", &mut 100u64)?;
+            ns.set_root("savings", &mut 0u64)
+        })
+        .unwrap();
+
+    let transfers = thread::spawn(move || {
+        for i in 1..=100u64 {
+            let (mut checking, mut savings) = (100 - i, i);
+            writer
+                .commit(|ns| {
+                    ns.set_root("checking", &mut checking)?;
+                    ns.set_root("savings", &mut savings)
+                })
+                .unwrap();
+        }
+    });
+
+    for _ in 0..100 {
+        let view = reader.read_view().unwrap();
+        let checking: u64 = view.restore("checking").unwrap().unwrap();
+        let savings: u64 = view.restore("savings").unwrap().unwrap();
+        assert_eq!(checking + savings, 100);
+    }
+    transfers.join().unwrap();
+
+    let view = reader.read_view().unwrap();
+    assert_eq!(view.restore::<u64>("savings").unwrap(), Some(100));
+    assert_eq!(view.roots().len(), 2);
+    assert!(view.restore::<u64>("missing").unwrap().is_none());
+}
+
+#[test]
+fn failed_commit_reverts() {
+    let dir = tempdir().unwrap();
+    let namespaces = Namespaces::<Blake2b>::new(dir.path()).unwrap();
+    let mut ns = namespaces.create("ns", 1 << 30).unwrap();
+    ns.set_root("a", &mut 1u64).unwrap();
+
+    let err = ns
+        .commit(|ns| {
+            ns.set_root("a", &mut 2u64)?;
+            ns.set_root("b", &mut 2u64)?;
+            Err::<(), _>(io::Error::new(io::ErrorKind::Other, "abort"))
+        })
+        .err()
+        .unwrap();
+    assert_eq!(err.to_string(), "abort");
+
+    let view = ns.read_view().unwrap();
+    assert_eq!(view.restore::<u64>("a").unwrap(), Some(1));
+    assert!(view.digest("b").is_none());
+}
+
+#[test]
+fn commits_are_journaled() {
+    let dir = tempdir().unwrap();
+    let namespaces = Namespaces::<Blake2b>::new(dir.path()).unwrap();
+    let mut ns = namespaces.create("ns", 1 << 30).unwrap();
+    ns.set_root("a", &mut 1u64).unwrap();
+
+    ns.commit(|ns| {
+        ns.set_root("a", &mut 2u64)?;
+        ns.set_root("b", &mut 2u64)?;
+        // the commit sees its own roots before they are set
+        assert_eq!(ns.restore::<u64>("a")?, Some(2));
+        assert_eq!(ns.read_view()?.restore::<u64>("a")?, Some(1));
+        Ok(())
+    })
+    .unwrap();
+
+    // the roots are kept in a registry of their own, with their history
+    let roots = DirRoots::<Blake2b>::open(
+        dir.path().join("namespaces").join("ns").join("roots"),
+    )
+    .unwrap();
+    assert_eq!(roots.names().unwrap(), vec!["a", "b"]);
+    assert_eq!(roots.history("a").unwrap().len(), 2);
+    assert_eq!(ns.roots().unwrap().len(), 2);
+}
@@ -0,0 +1,38 @@
+use std::collections::hash_map::DefaultHasher;
+
+use bytehash::Wrapped;
+use kelvin::{rehash_roots, Blake2b, Control, Store};
+use kelvin_hamt::DefaultHAMTMap;
+
+type Old = DefaultHAMTMap<u64, u64, Blake2b>;
+type New = DefaultHAMTMap<u64, u64, Wrapped<DefaultHasher>>;
+
+#[test]
+fn rehash_between_stores() {
+    let old_store = Store::<Blake2b>::ephemeral();
+    let new_store = Store::<Wrapped<DefaultHasher>>::ephemeral();
+
+    let mut roots = vec![];
+    let mut map = Old::new();
+    for n in &[10u64, 100, 1000] {
+        for i in 0..*n {
+            map.insert(i, i * 2).unwrap();
+        }
+        roots.push(old_store.persist(&mut map).unwrap());
+    }
+
+    let mapping: Vec<(_, kelvin::Snapshot<New, _>)> =
+        rehash_roots(&roots, &new_store, Control::none()).unwrap();
+    assert_eq!(mapping.len(), 3);
+
+    for ((old, new), (root, n)) in
+        mapping.iter().zip(roots.iter().zip(&[10u64, 100, 1000]))
+    {
+        assert!(old == root.hash());
+        let map = new_store.restore(new).unwrap();
+        for i in 0..*n {
+            assert_eq!(*map.get(&i).unwrap().unwrap(), i * 2);
+        }
+        assert!(map.get(n).unwrap().is_none());
+    }
+}
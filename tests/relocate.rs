@@ -0,0 +1,120 @@
+use std::fs;
+use std::io;
+
+use kelvin::tests::tempfile::tempdir;
+use kelvin::{Blake2b, CachedBackend, DiskBackend, RootStore, Store};
+use kelvin_hamt::DefaultHAMTMap;
+
+type Map = DefaultHAMTMap<u64, u64, Blake2b>;
+
+fn map(n: u64) -> Map {
+    let mut map = Map::new();
+    for i in 0..n {
+        map.insert(i, i).unwrap();
+    }
+    map
+}
+
+#[test]
+fn relocate_while_open() {
+    let dir = tempdir().unwrap();
+    let from = dir.path().join("from");
+    let to = dir.path().join("to");
+
+    let store = Store::<Blake2b>::new(&from).unwrap();
+    let before = store.persist(&mut map(1000)).unwrap();
+
+    let relocation = store.relocate(&to).unwrap();
+    // written and read while the copy may still be running
+    let during = store.persist(&mut map(2000)).unwrap();
+    let restored = store.restore(&during).unwrap();
+    assert_eq!(*restored.get(&1999).unwrap().unwrap(), 1999);
+
+    relocation.wait().unwrap();
+    assert!(!from.exists());
+    assert_eq!(store.path(), Some(to.clone()));
+
+    let after = store.persist(&mut map(3000)).unwrap();
+    for (snapshot, n) in &[(&before, 1000), (&during, 2000), (&after, 3000)] {
+        let restored = store.restore(snapshot).unwrap();
+        assert_eq!(*restored.get(&(n - 1)).unwrap().unwrap(), n - 1);
+    }
+
+    // everything is found in the new directory when reopened
+    store.flush().unwrap();
+    let reopened = Store::<Blake2b>::new(&to).unwrap();
+    for snapshot in &[&before, &during, &after] {
+        reopened.verify(snapshot).unwrap();
+    }
+}
+
+#[test]
+fn roots_set_while_relocating() {
+    let dir = tempdir().unwrap();
+    let from = dir.path().join("from");
+    let to = dir.path().join("to");
+
+    let store = Store::<Blake2b>::new(&from).unwrap();
+    let roots = store.roots().unwrap();
+    let before = store.persist(&mut map(1000)).unwrap();
+    roots.set("before", before.hash()).unwrap();
+
+    let relocation = store.relocate(&to).unwrap();
+    // set while the copy may still be running, through a registry taken
+    // before and one taken during the relocation
+    let during = store.persist(&mut map(2000)).unwrap();
+    roots.set("during", during.hash()).unwrap();
+    store.roots().unwrap().set("also", during.hash()).unwrap();
+
+    relocation.wait().unwrap();
+    assert!(!from.exists());
+
+    // the registries followed the store
+    let after = store.persist(&mut map(3000)).unwrap();
+    roots.set("after", after.hash()).unwrap();
+    assert!(roots.get("during").unwrap() == Some(*during.hash()));
+
+    store.flush().unwrap();
+    let reopened = Store::<Blake2b>::new(&to).unwrap();
+    let roots = reopened.roots().unwrap();
+    assert_eq!(
+        roots.names().unwrap(),
+        ["after", "also", "before", "during"]
+    );
+    for (name, snapshot) in &[
+        ("before", &before),
+        ("during", &during),
+        ("also", &during),
+        ("after", &after),
+    ] {
+        assert!(roots.get(name).unwrap() == Some(*snapshot.hash()));
+        reopened.verify(snapshot).unwrap();
+    }
+}
+
+#[test]
+fn relocate_refused() {
+    let dir = tempdir().unwrap();
+    let to = dir.path().join("to");
+    fs::create_dir_all(&to).unwrap();
+    fs::write(to.join("file"), b"taken").unwrap();
+
+    let store = Store::<Blake2b>::new(dir.path().join("from")).unwrap();
+    let err = store.relocate(&to).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+
+    let err = Store::<Blake2b>::ephemeral()
+        .relocate(dir.path().join("elsewhere"))
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    // the wrapper would be lost in the copy
+    let wrapped = dir.path().join("wrapped");
+    let backend = DiskBackend::<Blake2b>::new(&wrapped).unwrap();
+    let store = Store::from_backend(CachedBackend::new(backend, 1 << 20));
+    let snapshot = store.persist(&mut map(100)).unwrap();
+    let err = store.relocate(dir.path().join("elsewhere")).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert!(wrapped.exists());
+    store.verify(&snapshot).unwrap();
+}
@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use kelvin::{Blake2b, HttpFetch, MemBackend, RemoteBackend, Snapshot, Store};
+use kelvin_hamt::DefaultHAMTMap;
+
+type Map = DefaultHAMTMap<u64, u64, Blake2b>;
+
+// Serves the nodes in `nodes` by hex digest, keeping connections open, in
+// chunks if `chunked`. Returns the server address and the number of
+// connections accepted.
+fn serve(
+    nodes: HashMap<String, Vec<u8>>,
+    chunked: bool,
+) -> (String, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let nodes = Arc::new(nodes);
+    let connections = Arc::new(AtomicUsize::new(0));
+    let accepted = connections.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            accepted.fetch_add(1, Ordering::SeqCst);
+            let nodes = nodes.clone();
+            let stream = stream.unwrap();
+            thread::spawn(move || respond(stream, &nodes, chunked));
+        }
+    });
+    (addr, connections)
+}
+
+// Answers the requests sent on `stream`, until it is closed
+fn respond(
+    mut stream: TcpStream,
+    nodes: &HashMap<String, Vec<u8>>,
+    chunked: bool,
+) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).unwrap_or(0) == 0 {
+            return;
+        }
+        let path = line.split_whitespace().nth(1).unwrap().to_string();
+        // skip the headers
+        while line.trim_end() != "" {
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+        }
+        let hex = path.trim_start_matches("/nodes/");
+        match nodes.get(hex) {
+            Some(bytes) if chunked => {
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n"
+                )
+                .unwrap();
+                for chunk in bytes.chunks(7) {
+                    write!(stream, "{:x}\r\n", chunk.len()).unwrap();
+                    stream.write_all(chunk).unwrap();
+                    write!(stream, "\r\n").unwrap();
+                }
+                write!(stream, "0\r\n\r\n").unwrap();
+            }
+            Some(bytes) => {
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n",
+                    bytes.len()
+                )
+                .unwrap();
+                stream.write_all(bytes).unwrap();
+            }
+            None => write!(
+                stream,
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n"
+            )
+            .unwrap(),
+        }
+    }
+}
+
+// Persists a map, returning the store and the root, with the nodes of the
+// map by hex digest
+fn nodes() -> (
+    Store<Blake2b>,
+    Snapshot<Map, Blake2b>,
+    HashMap<String, Vec<u8>>,
+) {
+    let full = Store::<Blake2b>::ephemeral();
+    let mut map = Map::new();
+    for i in 0..1000 {
+        map.insert(i, i).unwrap();
+    }
+    let snapshot = full.persist(&mut map).unwrap();
+    let mut nodes = HashMap::new();
+    for record in full.node_records(&snapshot) {
+        let record = record.unwrap();
+        let hex: String =
+            record.digest.iter().map(|b| format!("{:02x}", b)).collect();
+        nodes.insert(hex, record.bytes);
+    }
+    (full, snapshot, nodes)
+}
+
+#[test]
+fn lazy_sync() {
+    let (full, snapshot, nodes) = nodes();
+    let count = nodes.len();
+    let (addr, connections) = serve(nodes, false);
+
+    let backend = RemoteBackend::new(
+        MemBackend::<Blake2b>::new(),
+        HttpFetch::new(&addr, "/nodes"),
+    );
+    let light = Store::from_backend(backend);
+    let map: Map = light.snapshot(snapshot.hash()).restore().unwrap();
+
+    // only the path to the key is fetched
+    assert_eq!(*map.get(&42).unwrap().unwrap(), 42);
+    light.flush().unwrap();
+    let fetched = light.size();
+    assert!(fetched > 0 && fetched < full.size());
+
+    // a full traversal fetches everything
+    light
+        .verify(&light.snapshot::<Map>(snapshot.hash()))
+        .unwrap();
+    light.flush().unwrap();
+    assert_eq!(light.size(), full.size());
+
+    // nodes unknown to the remote are missing
+    assert!(light.snapshot::<Map>(&[0; 32]).restore().is_err());
+
+    // connections are kept open between fetches
+    assert!(connections.load(Ordering::SeqCst) < count);
+}
+
+#[test]
+fn chunked_responses() {
+    let (full, snapshot, nodes) = nodes();
+    let (addr, _) = serve(nodes, true);
+
+    let backend = RemoteBackend::new(
+        MemBackend::<Blake2b>::new(),
+        HttpFetch::new(&addr, "/nodes"),
+    );
+    let light = Store::from_backend(backend);
+    light
+        .verify(&light.snapshot::<Map>(snapshot.hash()))
+        .unwrap();
+    light.flush().unwrap();
+    assert_eq!(light.size(), full.size());
+}
+
+#[test]
+fn oversized_responses_rejected() {
+    let (_, snapshot, nodes) = nodes();
+    let longest = nodes.values().map(Vec::len).max().unwrap();
+    for chunked in [false, true].iter() {
+        let (addr, _) = serve(nodes.clone(), *chunked);
+        let backend = RemoteBackend::new(
+            MemBackend::<Blake2b>::new(),
+            HttpFetch::new(&addr, "/nodes").with_max_len(longest - 1),
+        );
+        let light = Store::from_backend(backend);
+        let err = light
+            .verify(&light.snapshot::<Map>(snapshot.hash()))
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,110 @@
+use std::thread;
+
+use kelvin::tests::tempfile::tempdir;
+use kelvin::{Blake2b, MemRoots, RootStore, Store};
+use kelvin_hamt::DefaultHAMTMap;
+
+type Map = DefaultHAMTMap<u64, u64, Blake2b>;
+
+fn registry<R: RootStore<Blake2b>>(roots: &R) {
+    assert_eq!(roots.get("state").unwrap(), None);
+    roots.set("state", &[1; 32]).unwrap();
+    roots.set("mempool", &[9; 32]).unwrap();
+
+    assert!(roots
+        .compare_and_set("state", None, &[2; 32])
+        .unwrap()
+        .is_err());
+    match roots
+        .compare_and_set("state", Some(&[3; 32]), &[2; 32])
+        .unwrap()
+    {
+        Err(conflict) => assert_eq!(conflict.current(), Some(&[1; 32])),
+        Ok(_) => panic!("stale root was overwritten"),
+    }
+    roots
+        .compare_and_set("state", Some(&[1; 32]), &[2; 32])
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(roots.get("state").unwrap(), Some([2; 32]));
+    assert_eq!(roots.history("state").unwrap(), vec![[1; 32], [2; 32]]);
+    assert_eq!(roots.names().unwrap(), vec!["mempool", "state"]);
+
+    roots.remove("mempool").unwrap();
+    assert_eq!(roots.get("mempool").unwrap(), None);
+    assert_eq!(roots.names().unwrap(), vec!["state"]);
+
+    assert!(roots.set("../state", &[0; 32]).is_err());
+}
+
+#[test]
+fn mem_roots() {
+    registry(&MemRoots::<Blake2b>::new());
+}
+
+#[test]
+fn dir_roots() {
+    let dir = tempdir().unwrap();
+    let store = Store::<Blake2b>::new(dir.path()).unwrap();
+    registry(&store.roots().unwrap());
+    assert!(Store::<Blake2b>::ephemeral().roots().is_err());
+}
+
+#[test]
+fn store_roots() {
+    let dir = tempdir().unwrap();
+    let snapshot = {
+        let store = Store::<Blake2b>::new(dir.path()).unwrap();
+        let mut map = Map::new();
+        map.insert(1, 2).unwrap();
+        let snapshot = store.persist(&mut map).unwrap();
+        store.flush().unwrap();
+        store
+            .roots()
+            .unwrap()
+            .set("state", snapshot.hash())
+            .unwrap();
+        *snapshot.hash()
+    };
+
+    let store = Store::<Blake2b>::new(dir.path()).unwrap();
+    let current = store.roots().unwrap().get("state").unwrap().unwrap();
+    assert_eq!(current, snapshot);
+    let map: Map = store.snapshot(&current).restore().unwrap();
+    assert_eq!(*map.get(&1).unwrap().unwrap(), 2);
+}
+
+#[test]
+fn concurrent_updates() {
+    let dir = tempdir().unwrap();
+    let store = Store::<Blake2b>::new(dir.path()).unwrap();
+    let threads: Vec<_> = (0..4u8)
+        .map(|t| {
+            let store = store.clone();
+            thread::spawn(move || {
+                let roots = store.roots().unwrap();
+                for i in 0..10u8 {
+                    // retry until the update lands on the current root
+                    loop {
+                        let current = roots.get("counter").unwrap();
+                        let mut next = [t; 32];
+                        next[1] = i;
+                        if roots
+                            .compare_and_set("counter", current.as_ref(), &next)
+                            .unwrap()
+                            .is_ok()
+                        {
+                            break;
+                        }
+                    }
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    let roots = store.roots().unwrap();
+    assert_eq!(roots.history("counter").unwrap().len(), 40);
+}
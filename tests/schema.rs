@@ -0,0 +1,91 @@
+use kelvin::tests::tempfile::tempdir;
+use kelvin::{Blake2b, Error, Mismatch, Namespaces, Registry};
+use kelvin_hamt::DefaultHAMTMap;
+
+type Accounts = DefaultHAMTMap<u64, u64, Blake2b>;
+type Names = DefaultHAMTMap<String, u64, Blake2b>;
+
+#[test]
+fn validate_on_open() {
+    let dir = tempdir().unwrap();
+    let namespaces = Namespaces::<Blake2b>::new(dir.path()).unwrap();
+    let mut ns = namespaces.create("app", 1 << 24).unwrap();
+
+    let mut accounts = Accounts::new();
+    accounts.insert(1, 1).unwrap();
+    let mut names = Names::new();
+    names.insert("one".into(), 1).unwrap();
+
+    let mut registry = Registry::new();
+    registry
+        .register::<Accounts, Blake2b>("accounts", "accounts", 1)
+        .register::<Names, Blake2b>("names", "names", 1)
+        .register::<Names, Blake2b>("absent", "names", 1);
+
+    // roots set without a registry have no schema recorded
+    ns.set_root("accounts", &mut accounts).unwrap();
+    ns.set_root("names", &mut accounts).unwrap();
+    assert!(ns.schema("accounts").unwrap().is_none());
+
+    let err = namespaces.open_validated("app", &registry).err().unwrap();
+    match Error::from(err) {
+        Error::Schema(mismatches) => assert_eq!(
+            mismatches,
+            vec![
+                Mismatch::Unrecorded {
+                    root: "accounts".into()
+                },
+                Mismatch::Unrecorded {
+                    root: "names".into()
+                },
+            ]
+        ),
+        e => panic!("{:?}", e),
+    }
+
+    // rewriting the roots under a registry records its schemas
+    let mut registry_v0 = Registry::new();
+    registry_v0
+        .register::<Accounts, Blake2b>("accounts", "accounts", 0)
+        .register::<Names, Blake2b>("names", "names", 0);
+    let mut ns = namespaces.open("app").unwrap();
+    ns.set_registry(&registry_v0);
+    ns.set_root("accounts", &mut accounts).unwrap();
+    ns.set_root("names", &mut names).unwrap();
+    let schema = ns.schema("accounts").unwrap().unwrap();
+    assert_eq!(schema.name, "accounts");
+    assert_eq!(schema.version, 0);
+    let mut ns = namespaces.open_validated("app", &registry_v0).unwrap();
+
+    // writing the wrong type through a validated namespace fails
+    assert!(ns.set_root("accounts", &mut names).is_err());
+    assert!(ns.validate(&registry_v0).unwrap().is_empty());
+
+    let mismatches = ns.validate(&registry).unwrap();
+    assert_eq!(
+        mismatches,
+        vec![
+            Mismatch::Version {
+                root: "accounts".into(),
+                expected: 1,
+                found: 0
+            },
+            Mismatch::Version {
+                root: "names".into(),
+                expected: 1,
+                found: 0
+            },
+        ]
+    );
+
+    let mut renamed = Registry::new();
+    renamed.register::<Names, Blake2b>("names", "people", 0);
+    assert_eq!(
+        ns.validate(&renamed).unwrap(),
+        vec![Mismatch::Type {
+            root: "names".into(),
+            expected: "people".into(),
+            found: "names".into()
+        }]
+    );
+}
@@ -0,0 +1,30 @@
+use kelvin::tests::tempfile::tempdir;
+use kelvin::{Blake2b, Sharded};
+use kelvin_hamt::DefaultHAMTMap;
+
+type Map = DefaultHAMTMap<u64, u64, Blake2b>;
+
+#[test]
+fn sharded_map() {
+    let dir = tempdir().unwrap();
+
+    let combined = {
+        let mut sharded = Sharded::<Map, Blake2b>::new(dir.path(), 8).unwrap();
+        for i in 0..1024 {
+            sharded.get_mut(&i).insert(i, i + 1).unwrap();
+        }
+        sharded.commit().unwrap()
+    };
+
+    let sharded = Sharded::<Map, Blake2b>::new(dir.path(), 8).unwrap();
+    assert!(sharded.combined_root().unwrap() == combined);
+
+    for i in 0..1024 {
+        assert_eq!(*sharded.get(&i).get(&i).unwrap().unwrap(), i + 1);
+    }
+
+    let mut keys: Vec<u64> =
+        sharded.iter().map(|leaf| leaf.unwrap().key).collect();
+    keys.sort();
+    assert_eq!(keys, (0..1024).collect::<Vec<_>>());
+}
@@ -0,0 +1,58 @@
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use kelvin::{Blake2b, Frozen, LeafIterable, MemBackend, Store};
+use kelvin_hamt::DefaultHAMTMap;
+
+use common::Counting;
+
+type Map = DefaultHAMTMap<u64, u64, Blake2b>;
+
+fn sum(map: &Map) -> u64 {
+    map.iter().map(|leaf| leaf.unwrap().val).sum()
+}
+
+#[test]
+fn frozen_snapshots_are_send_and_sync() {
+    fn shareable<T: Send + Sync>() {}
+    shareable::<Frozen<Map, Blake2b>>();
+}
+
+#[test]
+fn concurrent_readers_restore_nodes_once() {
+    let reads = Arc::new(AtomicUsize::new(0));
+    let store = Store::from_backend(Counting(MemBackend::new(), reads.clone()));
+
+    let mut map = Map::new();
+    for i in 0..1000 {
+        map.insert(i, i).unwrap();
+    }
+    let snapshot = store.persist(&mut map).unwrap();
+    let expected: u64 = (0..1000).sum();
+
+    let shared = store.restore_shared(&snapshot).unwrap();
+    let readers: Vec<_> = (0..4)
+        .map(|_| {
+            let shared = shared.clone();
+            thread::spawn(move || sum(&shared))
+        })
+        .collect();
+
+    // a writer builds the next version meanwhile
+    let mut next = shared.thaw();
+    next.insert(1000, 1000).unwrap();
+    store.persist(&mut next).unwrap();
+
+    for reader in readers {
+        assert_eq!(reader.join().unwrap(), expected);
+    }
+
+    // once restored, the nodes are never read again
+    let read = reads.load(Ordering::SeqCst);
+    assert_eq!(sum(&shared), expected);
+    assert_eq!(reads.load(Ordering::SeqCst), read);
+    assert_eq!(sum(&next), expected + 1000);
+}
@@ -0,0 +1,128 @@
+//! Randomized exploration of the schedules of readers and a writer sharing a
+//! store
+//!
+//! The threads, the lock publishing roots and the yield points come from
+//! shuttle, which runs the threads of each test under its own scheduler and
+//! tries many interleavings of them. The locks of the store itself are not
+//! instrumented, so interleavings are explored between the steps of the
+//! readers and the writer, such as the leaves of an iteration, rather than
+//! within a single store operation.
+use shuttle::sync::{Arc, RwLock};
+use shuttle::thread;
+
+use kelvin::{Blake2b, LeafIterable, Snapshot, Store};
+use kelvin_hamt::DefaultHAMTMap;
+
+// Schedules tried by each test
+const ITERATIONS: usize = 256;
+const COMMITS: u64 = 4;
+const LEAVES: u64 = 64;
+
+type Map = DefaultHAMTMap<u64, u64, Blake2b>;
+/// The map together with the expected (count, sum) of its values
+type State = (Map, (u64, u64));
+
+type Published = Arc<RwLock<Option<Snapshot<State, Blake2b>>>>;
+
+fn check(map: &Map, (count, sum): (u64, u64)) {
+    let (mut seen_count, mut seen_sum) = (0, 0);
+    for leaf in map.iter() {
+        seen_count += 1;
+        seen_sum += leaf.unwrap().val;
+        // let the other threads run between the leaves
+        thread::yield_now();
+    }
+    assert_eq!(seen_count, count, "incomplete root observed");
+    assert_eq!(seen_sum, sum, "inconsistent root observed");
+}
+
+fn commit(state: &mut State, commit: u64) {
+    let (ref mut map, (ref mut count, ref mut sum)) = *state;
+    for i in 0..LEAVES {
+        let key = commit * LEAVES + i;
+        map.insert(key, key).unwrap();
+        *count += 1;
+        *sum += key;
+    }
+    // overwrite an entry of the previous commit
+    if let Some(old) = map.insert(commit * LEAVES / 2, 0).unwrap() {
+        *sum -= old;
+    } else {
+        *count += 1;
+    }
+}
+
+#[test]
+fn readers_observe_committed_roots() {
+    shuttle::check_random(
+        || {
+            let store = Store::<Blake2b>::ephemeral();
+            let published: Published = Arc::new(RwLock::new(None));
+
+            let readers: Vec<_> = (0..2)
+                .map(|_| {
+                    let store = store.clone();
+                    let published = published.clone();
+                    thread::spawn(move || {
+                        for _ in 0..COMMITS {
+                            let snapshot = published.read().unwrap().clone();
+                            if let Some(snapshot) = snapshot {
+                                let (map, totals) =
+                                    store.restore(&snapshot).unwrap();
+                                check(&map, totals);
+                            }
+                            thread::yield_now();
+                        }
+                    })
+                })
+                .collect();
+
+            let mut state = State::default();
+            for i in 0..COMMITS {
+                commit(&mut state, i);
+                let snapshot = store.persist(&mut state).unwrap();
+                *published.write().unwrap() = Some(snapshot);
+                thread::yield_now();
+            }
+
+            for reader in readers {
+                reader.join().unwrap();
+            }
+        },
+        ITERATIONS,
+    );
+}
+
+#[test]
+fn shared_handles_outlive_any_thread() {
+    shuttle::check_random(
+        || {
+            let store = Store::<Blake2b>::ephemeral();
+            let mut state = State::default();
+            commit(&mut state, 0);
+            let (mut map, totals) = state;
+            let snapshot = store.persist(&mut map).unwrap();
+
+            let shared = store.restore_shared(&snapshot).unwrap();
+            let readers: Vec<_> = (0..2)
+                .map(|_| {
+                    let shared = shared.clone();
+                    thread::spawn(move || check(&shared, totals))
+                })
+                .collect();
+
+            // the writer builds the next version from the shared nodes, and
+            // lets go of them before or after the readers, by schedule
+            let mut next = shared.thaw();
+            drop(shared);
+            next.insert(LEAVES, LEAVES).unwrap();
+            store.persist(&mut next).unwrap();
+            check(&next, (totals.0 + 1, totals.1 + LEAVES));
+
+            for reader in readers {
+                reader.join().unwrap();
+            }
+        },
+        ITERATIONS,
+    );
+}
@@ -0,0 +1,43 @@
+use kelvin::tests::tempfile::tempdir;
+use kelvin::{Blake2b, Spill, Store};
+use kelvin_hamt::DefaultHAMTMap;
+
+type Map = DefaultHAMTMap<u64, u64, Blake2b>;
+
+#[test]
+fn bulk_import() {
+    let dir = tempdir().unwrap();
+    let store = Store::<Blake2b>::new(dir.path()).unwrap();
+
+    let mut map = Map::new();
+    for i in 0..1000 {
+        map.insert(i, i).unwrap();
+    }
+    let before = store.persist(&mut map).unwrap();
+    store.flush().unwrap();
+    let size = store.size();
+
+    // extend the committed map, spilling every 1000 inserts
+    let mut map = store.restore(&before).unwrap();
+    let spill = Spill::new(&store).unwrap();
+    for i in 1000..10_000 {
+        map.insert(i, i).unwrap();
+        if i % 1000 == 0 {
+            spill.spill(&mut map).unwrap();
+        }
+    }
+    assert!(spill.staged() > 0);
+    // nothing reaches the store until committed
+    assert_eq!(store.size(), size);
+
+    let after = spill.commit(&mut map).unwrap();
+    assert_eq!(*map.get(&9999).unwrap().unwrap(), 9999);
+
+    let reopened = Store::<Blake2b>::new(dir.path()).unwrap();
+    reopened.verify(&before).unwrap();
+    reopened.verify(&after).unwrap();
+    let map: Map = reopened.restore(&after).unwrap();
+    for i in 0..10_000 {
+        assert_eq!(*map.get(&i).unwrap().unwrap(), i);
+    }
+}
@@ -0,0 +1,125 @@
+mod common;
+
+use std::fs::{self, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use kelvin::tests::tempfile::tempdir;
+use kelvin::{
+    Backend, Blake2b, DiskBackend, MemBackend, Snapshot, Store, TieredBackend,
+};
+use kelvin_hamt::DefaultHAMTMap;
+
+use common::Counting;
+
+type Map = DefaultHAMTMap<u64, u64, Blake2b>;
+
+fn copy_dir(from: &Path, to: &Path) {
+    fs::create_dir_all(to).unwrap();
+    for entry in fs::read_dir(from).unwrap() {
+        let entry = entry.unwrap();
+        let target = to.join(entry.file_name());
+        if entry.file_type().unwrap().is_dir() {
+            copy_dir(&entry.path(), &target);
+        } else {
+            fs::copy(entry.path(), target).unwrap();
+        }
+    }
+}
+
+fn replica(path: &Path) -> Snapshot<Map, Blake2b> {
+    let store = Store::<Blake2b>::new(path).unwrap();
+    let mut map = Map::new();
+    for i in 0..1000 {
+        map.insert(i, i).unwrap();
+    }
+    let snapshot = store.persist(&mut map).unwrap();
+    store.flush().unwrap();
+    snapshot
+}
+
+fn disk(path: &Path) -> Box<dyn Backend<Blake2b>> {
+    Box::new(DiskBackend::new(path).unwrap())
+}
+
+#[test]
+fn missing_nodes_are_repaired() {
+    let dir = tempdir().unwrap();
+    let snapshot = replica(dir.path());
+
+    let primary: Box<dyn Backend<Blake2b>> = Box::new(MemBackend::new());
+    let store = Store::tiered(vec![primary, disk(dir.path())]).unwrap();
+    let map: Map = store.restore(&snapshot).unwrap();
+    assert_eq!(*map.get(&999).unwrap().unwrap(), 999);
+    assert!(store.repairs() > 0);
+
+    // every node is repaired once, and then read from the primary tier
+    store.verify(&snapshot).unwrap();
+    let repairs = store.repairs();
+    store.verify(&snapshot).unwrap();
+    assert_eq!(store.repairs(), repairs);
+}
+
+#[test]
+fn corrupt_nodes_are_repaired() {
+    let dir = tempdir().unwrap();
+    let primary = dir.path().join("primary");
+    let replica_dir = dir.path().join("replica");
+    let snapshot = replica(&replica_dir);
+    copy_dir(&replica_dir, &primary);
+
+    // corrupt the first node written, deep in the tree
+    let mut data = OpenOptions::new()
+        .write(true)
+        .open(primary.join("data"))
+        .unwrap();
+    data.seek(SeekFrom::Start(20)).unwrap();
+    data.write_all(&[0xff]).unwrap();
+    drop(data);
+
+    {
+        let store =
+            Store::tiered(vec![disk(&primary), disk(&replica_dir)]).unwrap();
+        store.verify(&snapshot).unwrap();
+        assert_eq!(store.repairs(), 1);
+    }
+
+    // the primary tier is intact on its own again
+    let store = Store::<Blake2b>::new(&primary).unwrap();
+    store.verify(&snapshot).unwrap();
+
+    assert!(Store::<Blake2b>::tiered(vec![]).is_err());
+}
+
+#[test]
+fn layered_backends() {
+    let dir = tempdir().unwrap();
+    let snapshot = replica(dir.path());
+
+    let reads = Arc::new(AtomicUsize::new(0));
+    let lower = Counting(DiskBackend::new(dir.path()).unwrap(), reads.clone());
+    let store =
+        Store::from_backend(TieredBackend::new(MemBackend::new(), lower));
+    let snapshot = store.snapshot::<Map>(snapshot.hash());
+    store.verify(&snapshot).unwrap();
+    let nodes = reads.load(Ordering::SeqCst);
+    assert!(nodes > 0);
+
+    // the nodes read were promoted to the upper layer
+    store.flush().unwrap();
+    store.verify(&snapshot).unwrap();
+    assert_eq!(reads.load(Ordering::SeqCst), nodes);
+
+    // and writes go through to the lower one
+    let mut map = Map::new();
+    map.insert(1, 2).unwrap();
+    let written = store.persist(&mut map).unwrap();
+    store.flush().unwrap();
+    drop(store);
+    Store::<Blake2b>::new(dir.path())
+        .unwrap()
+        .verify(&written)
+        .unwrap();
+}
@@ -0,0 +1,79 @@
+use std::io::Cursor;
+
+use kelvin::tests::tempfile::tempdir;
+use kelvin::{Blake2b, Recorder, Store, Trace, TraceOp};
+use kelvin_hamt::{CountingHAMTMap, DefaultHAMTMap};
+
+fn workload(store: &Store<Blake2b>) -> Trace {
+    let mut recorder = Recorder::new();
+    let mut map = DefaultHAMTMap::<String, u64, Blake2b>::new();
+    for i in 0..500u64 {
+        recorder.insert(&mut map, format!("user-{}", i), i).unwrap();
+        if i % 100 == 99 {
+            let snapshot = recorder.persist(store, &mut map).unwrap();
+            map = store.restore(&snapshot).unwrap();
+        }
+    }
+    for i in 0..100u64 {
+        let key = format!("user-{}", i * 3);
+        let found =
+            recorder.record(TraceOp::Get, &key, || map.get(&key).unwrap());
+        assert!(found.is_some());
+    }
+    for i in 0..50u64 {
+        recorder.remove(&mut map, &format!("user-{}", i)).unwrap();
+    }
+    recorder.into_trace()
+}
+
+#[test]
+fn record_and_replay() {
+    let trace = workload(&Store::ephemeral());
+    assert_eq!(trace.len(), 655);
+
+    // equal keys hash equally, without revealing the key itself
+    let events = trace.events();
+    assert_eq!(events[0].key, events[505].key);
+    assert_eq!(events[0].op, TraceOp::Insert);
+    assert_eq!(events[100].op, TraceOp::Persist);
+
+    let mut bytes = vec![];
+    trace.write_to(&mut bytes).unwrap();
+    let trace = Trace::read_from(&mut Cursor::new(bytes)).unwrap();
+
+    let dir = tempdir().unwrap();
+    let disk = Store::<Blake2b>::new(dir.path()).unwrap();
+    let candidates = [
+        Store::<Blake2b>::ephemeral(),
+        disk.partition("small-cache", 4 * 1024),
+        disk.partition("large-cache", 4 * 1024 * 1024),
+    ];
+    for store in &candidates {
+        let report = trace
+            .replay::<DefaultHAMTMap<u64, u64, Blake2b>, _>(store)
+            .unwrap();
+        assert_eq!(report.count(TraceOp::Insert), 500);
+        assert_eq!(report.count(TraceOp::Get), 100);
+        assert_eq!(report.count(TraceOp::Remove), 50);
+        assert_eq!(report.count(TraceOp::Persist), 5);
+        assert!(report.elapsed() >= report.elapsed_for(TraceOp::Get));
+    }
+
+    let report = trace
+        .replay::<CountingHAMTMap<u64, u64, Blake2b>, _>(&Store::ephemeral())
+        .unwrap();
+    assert!(report.store_size() > 0);
+}
+
+#[test]
+fn invalid_trace() {
+    let mut bytes = vec![];
+    Trace::default().write_to(&mut bytes).unwrap();
+    assert!(Trace::read_from(&mut Cursor::new(&bytes))
+        .unwrap()
+        .is_empty());
+
+    bytes[7] = 1;
+    bytes.push(9);
+    assert!(Trace::read_from(&mut Cursor::new(bytes)).is_err());
+}
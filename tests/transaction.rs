@@ -0,0 +1,99 @@
+use std::fs;
+
+use kelvin::tests::tempfile::tempdir;
+use kelvin::{Blake2b, MemRoots, RootStore, RootUpdate, Store, Transaction};
+use kelvin_hamt::DefaultHAMTMap;
+
+type Map = DefaultHAMTMap<u64, u64, Blake2b>;
+
+fn map(n: u64) -> Map {
+    let mut map = Map::new();
+    for i in 0..n {
+        map.insert(i, i).unwrap();
+    }
+    map
+}
+
+#[test]
+fn commit_all_roots() {
+    let dir = tempdir().unwrap();
+    let store = Store::<Blake2b>::new(dir.path()).unwrap();
+
+    let mut tx = store.transaction().unwrap();
+    let state = tx.stage("state", &mut map(10)).unwrap();
+    let mempool = tx.stage("mempool", &mut 42u64).unwrap();
+    assert_eq!(tx.staged().collect::<Vec<_>>(), vec!["state", "mempool"]);
+    let roots = store.roots().unwrap();
+    assert_eq!(roots.get("state").unwrap(), None);
+    tx.commit().unwrap().unwrap();
+
+    drop(store);
+    let store = Store::<Blake2b>::new(dir.path()).unwrap();
+    let roots = store.roots().unwrap();
+    assert_eq!(roots.get("state").unwrap().as_ref(), Some(state.hash()));
+    assert_eq!(roots.get("mempool").unwrap().as_ref(), Some(mempool.hash()));
+    let n: u64 = store.snapshot(mempool.hash()).restore().unwrap();
+    assert_eq!(n, 42);
+}
+
+#[test]
+fn rollback_on_drop() {
+    let roots = MemRoots::<Blake2b>::new();
+    let store = Store::<Blake2b>::ephemeral();
+    {
+        let mut tx = Transaction::new(&store, &roots);
+        tx.stage("state", &mut map(10)).unwrap();
+    }
+    assert!(roots.names().unwrap().is_empty());
+}
+
+#[test]
+fn conflicting_commit() {
+    let roots = MemRoots::<Blake2b>::new();
+    let store = Store::<Blake2b>::ephemeral();
+
+    let mut tx = Transaction::new(&store, &roots);
+    tx.stage("a", &mut map(1)).unwrap();
+    tx.stage("b", &mut map(2)).unwrap();
+
+    // another writer sets `b` meanwhile
+    roots.set("b", &[7; 32]).unwrap();
+    let conflict = tx.commit().unwrap().unwrap_err();
+    assert_eq!(conflict.root(), Some("b"));
+    assert_eq!(conflict.current(), Some(&[7; 32]));
+    // neither root was set
+    assert_eq!(roots.get("a").unwrap(), None);
+}
+
+#[test]
+fn interrupted_commit() {
+    let dir = tempdir().unwrap();
+    let store = Store::<Blake2b>::new(dir.path()).unwrap();
+    let roots = store.roots().unwrap();
+    roots.set("a", &[1; 32]).unwrap();
+
+    // a commit recorded, but only partly applied before a crash
+    let mut record = vec![];
+    for (name, digest) in &[("a", [2; 32]), ("b", [3; 32])] {
+        record.push(name.len() as u8);
+        record.extend_from_slice(name.as_bytes());
+        record.extend_from_slice(digest);
+    }
+    fs::write(dir.path().join("roots").join(".commit"), record).unwrap();
+
+    // readers already see the whole commit
+    assert_eq!(roots.get("a").unwrap(), Some([2; 32]));
+    assert_eq!(roots.get("b").unwrap(), Some([3; 32]));
+    assert_eq!(roots.names().unwrap(), vec!["a", "b"]);
+
+    // and the next writer completes it
+    let update = RootUpdate {
+        name: "c".into(),
+        expected: None,
+        digest: [4; 32],
+    };
+    roots.commit(&[update]).unwrap().unwrap();
+    assert!(!dir.path().join("roots").join(".commit").exists());
+    assert_eq!(roots.history("a").unwrap(), vec![[1; 32], [2; 32]]);
+    assert_eq!(roots.history("b").unwrap(), vec![[3; 32]]);
+}
@@ -0,0 +1,47 @@
+use kelvin::{move_entry, Blake2b, Compound, Store};
+use kelvin_hamt::CountingHAMTMap;
+
+type Map = CountingHAMTMap<u64, u64, Blake2b>;
+
+fn count(map: &Map) -> u64 {
+    map.annotation().map(|ann| *ann).unwrap_or(0)
+}
+
+#[test]
+fn move_between_maps() {
+    let mut escrow = Map::new();
+    let mut settled = Map::new();
+    for i in 0..100 {
+        escrow.insert(i, i * 10).unwrap();
+    }
+    settled.insert(1000, 0).unwrap();
+
+    let store = Store::<Blake2b>::ephemeral();
+    let snapshot = store.persist(&mut escrow).unwrap();
+    let mut escrow = store.restore(&snapshot).unwrap();
+
+    assert!(move_entry(&mut escrow, &mut settled, &7).unwrap());
+    assert!(escrow.get(&7).unwrap().is_none());
+    assert_eq!(*settled.get(&7).unwrap().unwrap(), 70);
+    assert_eq!(count(&escrow), 99);
+    assert_eq!(count(&settled), 2);
+
+    // absent keys move nothing
+    assert!(!move_entry(&mut escrow, &mut settled, &7).unwrap());
+    assert_eq!(count(&settled), 2);
+}
+
+#[test]
+fn failed_move_leaves_both_untouched() {
+    let mut src = Map::new();
+    let mut dst = Map::new();
+    src.insert(1, 10).unwrap();
+    dst.insert(1, 20).unwrap();
+
+    let err = move_entry(&mut src, &mut dst, &1).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+    assert_eq!(*src.get(&1).unwrap().unwrap(), 10);
+    assert_eq!(*dst.get(&1).unwrap().unwrap(), 20);
+    assert_eq!(count(&src), 1);
+    assert_eq!(count(&dst), 1);
+}
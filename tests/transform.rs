@@ -0,0 +1,38 @@
+use std::io;
+
+use kelvin::{map_keys, map_values, Blake2b, Control};
+use kelvin_hamt::DefaultHAMTMap;
+
+type Map = DefaultHAMTMap<u64, u64, Blake2b>;
+
+fn map(n: u64) -> Map {
+    let mut map = Map::new();
+    for i in 0..n {
+        map.insert(i, i).unwrap();
+    }
+    map
+}
+
+#[test]
+fn transform_values() {
+    let old = map(3000);
+    let new: DefaultHAMTMap<u64, String, Blake2b> =
+        map_values(&old, |_, v| Ok(v.to_string()), &mut Control::none())
+            .unwrap();
+    for i in 0..3000 {
+        assert_eq!(*new.get(&i).unwrap().unwrap(), i.to_string());
+    }
+}
+
+#[test]
+fn transform_keys() {
+    let old = map(100);
+    let new: Map =
+        map_keys(&old, |k| Ok(k + 1000), &mut Control::none()).unwrap();
+    assert!(new.get(&0).unwrap().is_none());
+    assert_eq!(*new.get(&1099).unwrap().unwrap(), 99);
+
+    let collide: io::Result<Map> =
+        map_keys(&old, |k| Ok(k / 2), &mut Control::none());
+    assert_eq!(collide.err().unwrap().kind(), io::ErrorKind::InvalidData);
+}
@@ -0,0 +1,37 @@
+use std::fs::OpenOptions;
+use std::io::{Seek, SeekFrom, Write};
+
+use kelvin::tests::tempfile::tempdir;
+use kelvin::{Blake2b, Store};
+use kelvin_hamt::DefaultHAMTMap;
+
+type Map = DefaultHAMTMap<u64, u64, Blake2b>;
+
+#[test]
+fn verify_retained_roots() {
+    let dir = tempdir().unwrap();
+    let store = Store::<Blake2b>::new(dir.path()).unwrap();
+
+    let mut map = Map::new();
+    for i in 0..1024 {
+        map.insert(i, i).unwrap();
+    }
+    let snapshot = store.persist(&mut map).unwrap();
+    store.verify(&snapshot).unwrap();
+    store.flush().unwrap();
+    drop(store);
+
+    // corrupt the first node written, deep in the tree
+    let mut data = OpenOptions::new()
+        .write(true)
+        .open(dir.path().join("data"))
+        .unwrap();
+    data.seek(SeekFrom::Start(20)).unwrap();
+    data.write_all(&[0xff]).unwrap();
+    drop(data);
+
+    let store = Store::<Blake2b>::new(dir.path()).unwrap();
+    // the root node itself is intact
+    assert!(store.restore(&snapshot).is_ok());
+    assert!(store.verify(&snapshot).is_err());
+}
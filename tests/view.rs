@@ -0,0 +1,75 @@
+use std::io;
+
+use kelvin::{Blake2b, Content, Sink, Source, Store, View, Viewed};
+use kelvin_hamt::DefaultHAMTMap;
+
+type Map = DefaultHAMTMap<u64, u64, Blake2b>;
+
+/// Number of entries per bucket of 100 in value
+#[derive(Clone, Default)]
+struct Buckets(DefaultHAMTMap<u64, u64, Blake2b>);
+
+impl Buckets {
+    fn count(&self, bucket: u64) -> u64 {
+        self.0.get(&bucket).unwrap().map(|c| *c).unwrap_or(0)
+    }
+
+    fn add(&mut self, bucket: u64, n: i64) -> io::Result<()> {
+        let count = (self.count(bucket) as i64 + n) as u64;
+        if count == 0 {
+            self.0.remove(&bucket)?;
+        } else {
+            self.0.insert(bucket, count)?;
+        }
+        Ok(())
+    }
+}
+
+impl Content<Blake2b> for Buckets {
+    fn persist(&mut self, sink: &mut Sink<Blake2b>) -> io::Result<()> {
+        self.0.persist(sink)
+    }
+
+    fn restore(source: &mut Source<Blake2b>) -> io::Result<Self> {
+        Ok(Buckets(Content::restore(source)?))
+    }
+}
+
+impl View<u64, u64, Blake2b> for Buckets {
+    fn update(
+        &mut self,
+        _: &u64,
+        old: Option<&u64>,
+        new: Option<&u64>,
+    ) -> io::Result<()> {
+        if let Some(old) = old {
+            self.add(old / 100, -1)?;
+        }
+        if let Some(new) = new {
+            self.add(new / 100, 1)?;
+        }
+        Ok(())
+    }
+}
+
+kelvin::tests::quickcheck::quickcheck! {
+    fn view_consistent(ops: Vec<(bool, u8, u16)>) -> bool {
+        let mut viewed = Viewed::<Map, Buckets>::default();
+        for (insert, key, val) in ops {
+            if insert {
+                viewed.insert(key as u64, val as u64).unwrap();
+            } else {
+                viewed.remove(&(key as u64)).unwrap();
+            }
+        }
+
+        let store = Store::<Blake2b>::ephemeral();
+        let snapshot = store.persist(&mut viewed).unwrap();
+        let restored = store.restore(&snapshot).unwrap();
+
+        // the maintained view matches one rebuilt from the map
+        let (base, view) = restored.into_parts();
+        let rebuilt = Viewed::<Map, Buckets>::new(base).unwrap();
+        (0..=655).all(|b| view.count(b) == rebuilt.view().count(b))
+    }
+}
@@ -0,0 +1,60 @@
+use std::sync::Arc;
+
+use kelvin::{Blake2b, Compound, Store};
+use kelvin_hamt::DefaultHAMTMap;
+
+type Map = DefaultHAMTMap<u64, u64, Blake2b>;
+
+fn map() -> Map {
+    let mut map = Map::new();
+    for i in 0..1024 {
+        map.insert(i, i).unwrap();
+    }
+    map
+}
+
+fn first_node(map: &Map) -> usize {
+    map.children()
+        .iter()
+        .position(|c| c.downgrade().is_some())
+        .expect("no node")
+}
+
+#[test]
+fn weak_shared_node() {
+    let mut map = map();
+    // in-memory nodes can not be referenced weakly
+    assert!(map.children().iter().all(|c| c.downgrade().is_none()));
+
+    for child in map.children_mut() {
+        child.make_shared();
+    }
+    let i = first_node(&map);
+    let weak = map.children()[i].downgrade().unwrap();
+
+    let strong = weak.upgrade().unwrap().unwrap();
+    assert!(Arc::ptr_eq(&strong, &weak.upgrade().unwrap().unwrap()));
+    drop(strong);
+
+    // the weak handle does not keep the node alive
+    drop(map);
+    assert!(weak.upgrade().unwrap().is_none());
+}
+
+#[test]
+fn weak_persisted_node() {
+    let store = Store::<Blake2b>::ephemeral();
+    let snapshot = store.persist(&mut map()).unwrap();
+    let map = store.restore(&snapshot).unwrap();
+
+    let i = first_node(&map);
+    let weak = map.children()[i].downgrade().unwrap();
+    drop(map);
+
+    // restored from the store, and shared while alive
+    let a = weak.upgrade().unwrap().unwrap();
+    let b = weak.upgrade().unwrap().unwrap();
+    assert!(Arc::ptr_eq(&a, &b));
+    drop((a, b));
+    assert!(weak.upgrade().unwrap().is_some());
+}